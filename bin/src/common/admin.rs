@@ -0,0 +1,341 @@
+//! Non-interactive maintenance commands, e.g. `indradb stats`, so that
+//! operators can script maintenance.
+
+use errors;
+use indradb;
+use indradb::{
+    Datastore as IndraDbDatastore, MemoryDatastore, RangeVertexQuery, RocksdbDatastore, SpecificVertexQuery,
+    Transaction as IndraDbTransaction, Vertex, VertexQueryExt,
+};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::OpenOptions;
+use std::io;
+use std::io::{BufReader, BufWriter, Write};
+use uuid::Uuid;
+
+// How many vertices are read at a time while scanning.
+const PAGE_SIZE: u32 = 1000;
+
+/// The names of the maintenance commands.
+pub const COMMANDS: [&str; 10] = [
+    "backup",
+    "restore",
+    "compact",
+    "clone",
+    "verify",
+    "check",
+    "stats",
+    "export",
+    "import",
+    "export-edge-index",
+];
+
+const USAGE: &str = "Maintenance commands, which run against `DATABASE_URL`:
+  indradb backup <file>    Writes every vertex, edge and property to a new file
+  indradb restore <file>   Reads a backup into an empty datastore
+  indradb compact          Compacts a RocksDB datastore
+  indradb clone <path>     Copies a RocksDB datastore into a new, compacted one
+  indradb verify           Checks that every entry in a RocksDB datastore decodes
+  indradb check            Checks for edges whose vertices don't exist
+  indradb stats            Prints counts of vertices, edges and properties
+  indradb export           Writes every vertex, edge and property to stdout
+  indradb import           Reads vertices, edges and properties from stdin
+  indradb export-edge-index <edges.npy> <ids.npy>
+                           Writes the edges as a NumPy edge index, along with
+                           the vertex ids at each index";
+
+/// Runs a maintenance command.
+///
+/// # Arguments
+/// * `connection_string` - The datastore to run the command against.
+/// * `command` - The name of the command, one of `COMMANDS`.
+/// * `args` - The command's arguments.
+pub fn start(connection_string: &str, command: &str, args: &[String]) -> Result<(), errors::Error> {
+    if connection_string.starts_with("rocksdb://") {
+        let path = &connection_string[10..connection_string.len()];
+        let datastore = RocksdbDatastore::new(path, None, false).map_err(map_err)?;
+
+        match (command, args) {
+            ("compact", &[]) => {
+                datastore.compact().map_err(map_err)?;
+                println!("ok");
+                Ok(())
+            }
+            ("clone", &[ref path]) => {
+                datastore.clone_to(path).map_err(map_err)?;
+                println!("ok");
+                Ok(())
+            }
+            ("verify", &[]) => {
+                let corrupt_entries = datastore.verify().map_err(map_err)?;
+
+                for entry in &corrupt_entries {
+                    println!("corrupt entry in {} at key {:?}: {}", entry.cf, entry.key, entry.reason);
+                }
+
+                if !corrupt_entries.is_empty() {
+                    return Err(format!("Found {} corrupt entries", corrupt_entries.len()).into());
+                }
+
+                println!("ok");
+                Ok(())
+            }
+            _ => run(&datastore, command, args, io::stdout()),
+        }
+    } else if connection_string == "memory://" {
+        run(&MemoryDatastore::default(), command, args, io::stdout())
+    } else {
+        Err(format!("Cannot parse connection string `{}`", connection_string).into())
+    }
+}
+
+/// Runs a maintenance command that works with any datastore.
+///
+/// # Arguments
+/// * `datastore` - The datastore to run the command against.
+/// * `command` - The name of the command.
+/// * `args` - The command's arguments.
+/// * `output` - Where to write the command's output.
+pub fn run<D, T, W>(datastore: &D, command: &str, args: &[String], mut output: W) -> Result<(), errors::Error>
+where
+    D: IndraDbDatastore<Trans = T>,
+    T: IndraDbTransaction,
+    W: Write,
+{
+    let trans = datastore.transaction().map_err(map_err)?;
+
+    match (command, args) {
+        ("backup", &[ref path]) => {
+            // Refuse to overwrite an existing backup.
+            let file = OpenOptions::new().write(true).create_new(true).open(path)?;
+            let count = indradb::export(&trans, BufWriter::new(file)).map_err(map_err)?;
+            writeln!(output, "backed up {} items", count)?;
+        }
+        ("restore", &[ref path]) => {
+            if trans.get_vertex_count().map_err(map_err)? > 0 {
+                return Err("Backups can only be restored into an empty datastore".into());
+            }
+
+            let file = OpenOptions::new().read(true).open(path)?;
+            let count = indradb::import(datastore, BufReader::new(file)).map_err(map_err)?;
+            writeln!(output, "restored {} items", count)?;
+        }
+        ("compact", &[]) => return Err("Only RocksDB datastores can be compacted".into()),
+        ("clone", &[_]) => return Err("Only RocksDB datastores can be cloned".into()),
+        ("verify", &[]) => return Err("Only RocksDB datastores can be verified".into()),
+        ("check", &[]) => {
+            let dangling = check(&trans)?;
+
+            for key in &dangling {
+                writeln!(
+                    output,
+                    "dangling edge: {} {} {}",
+                    key.outbound_id, key.t.0, key.inbound_id
+                )?;
+            }
+
+            if !dangling.is_empty() {
+                return Err(format!("Found {} dangling edges", dangling.len()).into());
+            }
+
+            writeln!(output, "ok")?;
+        }
+        ("stats", &[]) => {
+            let stats = stats(&trans)?;
+            writeln!(output, "vertices: {}", stats.vertex_count)?;
+            writeln!(output, "edges: {}", stats.edge_count)?;
+            writeln!(output, "vertex properties: {}", stats.vertex_property_count)?;
+            writeln!(output, "edge properties: {}", stats.edge_property_count)?;
+
+            for (t, count) in &stats.vertex_type_counts {
+                writeln!(output, "vertices of type {}: {}", t, count)?;
+            }
+
+            for (t, count) in &stats.edge_type_counts {
+                writeln!(output, "edges of type {}: {}", t, count)?;
+            }
+        }
+        ("export", &[]) => {
+            // Output is written as it's read, so the count goes to stderr.
+            let count = indradb::export(&trans, output).map_err(map_err)?;
+            eprintln!("exported {} items", count);
+        }
+        ("import", &[]) => {
+            let stdin = io::stdin();
+            let count = indradb::import(datastore, stdin.lock()).map_err(map_err)?;
+            writeln!(output, "imported {} items", count)?;
+        }
+        ("export-edge-index", &[ref edge_index_path, ref id_mapping_path]) => {
+            let edge_index = OpenOptions::new().write(true).create_new(true).open(edge_index_path)?;
+            let id_mapping = OpenOptions::new().write(true).create_new(true).open(id_mapping_path)?;
+            let (vertex_count, edge_count) =
+                indradb::export_edge_index(&trans, BufWriter::new(edge_index), BufWriter::new(id_mapping))
+                    .map_err(map_err)?;
+            writeln!(output, "exported {} vertices and {} edges", vertex_count, edge_count)?;
+        }
+        _ => return Err(format!("Unknown command or wrong arguments\n\n{}", USAGE).into()),
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+struct Stats {
+    vertex_count: u64,
+    edge_count: u64,
+    vertex_property_count: u64,
+    edge_property_count: u64,
+    vertex_type_counts: BTreeMap<String, u64>,
+    edge_type_counts: BTreeMap<String, u64>,
+}
+
+fn stats<T: IndraDbTransaction>(trans: &T) -> Result<Stats, errors::Error> {
+    let mut stats = Stats::default();
+
+    for_each_page(trans, |trans, vertices| {
+        let q = SpecificVertexQuery::new(vertices.iter().map(|vertex| vertex.id).collect());
+
+        for vertex_properties in trans.get_all_vertex_properties(q.clone()).map_err(map_err)? {
+            stats.vertex_count += 1;
+            stats.vertex_property_count += vertex_properties.props.len() as u64;
+            *stats
+                .vertex_type_counts
+                .entry(vertex_properties.vertex.t.0)
+                .or_insert(0) += 1;
+        }
+
+        for edge_properties in trans
+            .get_all_edge_properties(q.outbound(u32::max_value()))
+            .map_err(map_err)?
+        {
+            stats.edge_count += 1;
+            stats.edge_property_count += edge_properties.props.len() as u64;
+            *stats.edge_type_counts.entry(edge_properties.edge.key.t.0).or_insert(0) += 1;
+        }
+
+        Ok(())
+    })?;
+
+    Ok(stats)
+}
+
+// Finds edges with a vertex that doesn't exist. Every edge is reached from
+// both its vertices, so edges whose outbound vertex is missing are found
+// through the inbound vertex.
+fn check<T: IndraDbTransaction>(trans: &T) -> Result<Vec<indradb::EdgeKey>, errors::Error> {
+    let mut dangling = Vec::new();
+
+    for_each_page(trans, |trans, vertices| {
+        let q = SpecificVertexQuery::new(vertices.iter().map(|vertex| vertex.id).collect());
+        let outbound = trans.get_edges(q.clone().outbound(u32::max_value())).map_err(map_err)?;
+        let inbound = trans.get_edges(q.inbound(u32::max_value())).map_err(map_err)?;
+
+        let other_ids: HashSet<Uuid> = outbound
+            .iter()
+            .map(|edge| edge.key.inbound_id)
+            .chain(inbound.iter().map(|edge| edge.key.outbound_id))
+            .collect();
+        let existing_ids: HashSet<Uuid> = trans
+            .get_vertices(SpecificVertexQuery::new(other_ids.into_iter().collect()))
+            .map_err(map_err)?
+            .into_iter()
+            .map(|vertex| vertex.id)
+            .collect();
+
+        dangling.extend(
+            outbound
+                .into_iter()
+                .filter(|edge| !existing_ids.contains(&edge.key.inbound_id))
+                .map(|edge| edge.key),
+        );
+        dangling.extend(
+            inbound
+                .into_iter()
+                .filter(|edge| !existing_ids.contains(&edge.key.outbound_id))
+                .map(|edge| edge.key),
+        );
+
+        Ok(())
+    })?;
+
+    Ok(dangling)
+}
+
+fn for_each_page<T, F>(trans: &T, mut f: F) -> Result<(), errors::Error>
+where
+    T: IndraDbTransaction,
+    F: FnMut(&T, Vec<Vertex>) -> Result<(), errors::Error>,
+{
+    let mut q = RangeVertexQuery::new(PAGE_SIZE);
+
+    loop {
+        let vertices = trans.get_vertices(q.clone()).map_err(map_err)?;
+        let is_last_page = vertices.len() < PAGE_SIZE as usize;
+
+        let next_id = match vertices.last() {
+            Some(vertex) => indradb::util::next_uuid(vertex.id).ok(),
+            None => None,
+        };
+
+        f(trans, vertices)?;
+
+        match next_id {
+            Some(next_id) if !is_last_page => q = q.start_id(next_id),
+            _ => return Ok(()),
+        }
+    }
+}
+
+fn map_err(err: indradb::Error) -> errors::Error {
+    format!("{}", err).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run;
+    use indradb::{Datastore, EdgeKey, MemoryDatastore, Transaction, Type, Vertex};
+    use uuid::Uuid;
+
+    fn run_command(datastore: &MemoryDatastore, command: &str) -> (bool, String) {
+        let mut output = Vec::new();
+        let result = run(datastore, command, &[], &mut output);
+        (result.is_ok(), String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn should_get_stats_and_check() {
+        let datastore = MemoryDatastore::default();
+        let trans = datastore.transaction().unwrap();
+        let t = Type::new("foo").unwrap();
+        let outbound_v = Vertex::new(t.clone());
+        let inbound_v = Vertex::new(t.clone());
+        trans.create_vertex(&outbound_v).unwrap();
+        trans.create_vertex(&inbound_v).unwrap();
+        trans
+            .create_edge(&EdgeKey::new(outbound_v.id, t.clone(), inbound_v.id))
+            .unwrap();
+
+        let (is_ok, output) = run_command(&datastore, "stats");
+        assert!(is_ok);
+        assert!(output.contains("vertices: 2\n"));
+        assert!(output.contains("edges: 1\n"));
+        assert!(output.contains("vertices of type foo: 2\n"));
+
+        assert_eq!(run_command(&datastore, "check"), (true, "ok\n".to_string()));
+
+        // The memory datastore doesn't check that an edge's vertices exist
+        datastore
+            .bulk_insert(
+                vec![::indradb::BulkInsertItem::Edge(EdgeKey::new(
+                    outbound_v.id,
+                    t,
+                    Uuid::default(),
+                ))]
+                .into_iter(),
+            )
+            .unwrap();
+        let (is_ok, output) = run_command(&datastore, "check");
+        assert!(!is_ok);
+        assert!(output.starts_with("dangling edge:"));
+    }
+}
@@ -0,0 +1,116 @@
+//! Runs synthetic workloads, started with `indradb bench`, to compare the
+//! throughput of datastores and their tuning options.
+
+use errors;
+use indradb::workload::{DegreeDistribution, Workload, WorkloadReport};
+use indradb::{Datastore as IndraDbDatastore, MemoryDatastore, RocksdbDatastore};
+use std::io::Write;
+
+/// Runs a workload against a new datastore and prints the measurements.
+///
+/// # Arguments
+/// * `connection_string` - The datastore to run against: `memory://` or
+///   `rocksdb://<path>`. RocksDB datastores should point at an empty
+///   directory.
+/// * `workload` - The workload to run.
+/// * `output` - Where to write the measurements.
+pub fn start<W: Write>(connection_string: &str, workload: &Workload, output: W) -> Result<(), errors::Error> {
+    let report = if connection_string.starts_with("rocksdb://") {
+        let path = &connection_string[10..connection_string.len()];
+        let datastore = RocksdbDatastore::new(path, None, false).map_err(map_err)?;
+        run(&datastore, workload)?
+    } else if connection_string == "memory://" {
+        run(&MemoryDatastore::default(), workload)?
+    } else {
+        return Err(format!("Cannot parse connection string `{}`", connection_string).into());
+    };
+
+    print_report(&report, output)
+}
+
+fn run<D: IndraDbDatastore>(datastore: &D, workload: &Workload) -> Result<WorkloadReport, errors::Error> {
+    workload.run(datastore).map_err(map_err)
+}
+
+fn print_report<W: Write>(report: &WorkloadReport, mut output: W) -> Result<(), errors::Error> {
+    for measurement in &report.measurements {
+        let elapsed = measurement.elapsed.as_secs() as f64 + f64::from(measurement.elapsed.subsec_nanos()) / 1e9;
+
+        writeln!(
+            output,
+            "{}: {} in {:.3}s ({:.0}/s)",
+            measurement.name,
+            measurement.count,
+            elapsed,
+            measurement.per_second()
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Parses a degree distribution, in one of the forms `constant:<degree>`,
+/// `uniform:<min>:<max>` or `powerlaw:<exponent>:<min>:<max>`.
+///
+/// # Arguments
+/// * `s` - The string to parse.
+pub fn parse_degree(s: &str) -> Result<DegreeDistribution, errors::Error> {
+    let parts: Vec<&str> = s.split(':').collect();
+
+    let distribution = match parts.as_slice() {
+        ["constant", degree] => degree.parse().map(DegreeDistribution::Constant).ok(),
+        ["uniform", min, max] => match (min.parse(), max.parse()) {
+            (Ok(min), Ok(max)) if min <= max => Some(DegreeDistribution::Uniform { min, max }),
+            _ => None,
+        },
+        ["powerlaw", exponent, min, max] => match (exponent.parse::<f64>(), min.parse(), max.parse()) {
+            (Ok(exponent), Ok(min), Ok(max)) if exponent > 1.0 && min <= max => {
+                Some(DegreeDistribution::PowerLaw { exponent, min, max })
+            }
+            _ => None,
+        },
+        _ => None,
+    };
+
+    distribution.ok_or_else(|| format!("Could not parse degree distribution `{}`", s).into())
+}
+
+fn map_err(err: ::indradb::Error) -> errors::Error {
+    format!("{}", err).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_degree, start};
+    use indradb::workload::{DegreeDistribution, Workload};
+
+    #[test]
+    fn should_parse_degrees() {
+        assert_eq!(parse_degree("constant:3").unwrap(), DegreeDistribution::Constant(3));
+        assert_eq!(
+            parse_degree("uniform:1:10").unwrap(),
+            DegreeDistribution::Uniform { min: 1, max: 10 }
+        );
+        assert_eq!(
+            parse_degree("powerlaw:2.5:1:1000").unwrap(),
+            DegreeDistribution::PowerLaw {
+                exponent: 2.5,
+                min: 1,
+                max: 1000
+            }
+        );
+        assert!(parse_degree("uniform:10:1").is_err());
+        assert!(parse_degree("powerlaw:1:1:10").is_err());
+        assert!(parse_degree("normal:3").is_err());
+    }
+
+    #[test]
+    fn should_print_measurements() {
+        let workload = Workload::new(10, DegreeDistribution::Constant(2)).read_count(5);
+        let mut output = Vec::new();
+        start("memory://", &workload, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("insert edges: 20 in "));
+        assert!(output.contains("scan: 10 in "));
+    }
+}
@@ -1,14 +1,17 @@
 use autogen;
+use capnp::capability::Promise;
 use capnp::Error as CapnpError;
 use capnp_rpc::rpc_twoparty_capnp::Side;
-use capnp_rpc::{twoparty, RpcSystem};
+use capnp_rpc::{twoparty, RpcSystem, Server};
 use converters;
-use futures::Future;
+use futures::future::poll_fn;
+use futures::sync::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::{Async, Future, Stream};
 use indradb;
 use serde_json::value::Value as JsonValue;
 use std::cell::RefCell;
 use std::fmt::Debug;
-use std::net::ToSocketAddrs;
+use std::net::{SocketAddr, ToSocketAddrs};
 use std::rc::Rc;
 use std::thread::sleep;
 use std::time::Duration;
@@ -28,9 +31,17 @@ pub struct ClientDatastore {
 
 impl ClientDatastore {
     pub fn new(port: u16) -> Self {
+        let addr = format!("127.0.0.1:{}", port).to_socket_addrs().unwrap().next().unwrap();
+        Self::connect(addr)
+    }
+
+    /// Connects to a server at an address, which doesn't have to be local.
+    ///
+    /// # Arguments
+    /// * `addr` - The address of the server.
+    pub fn connect(addr: SocketAddr) -> Self {
         let mut core = Core::new().unwrap();
         let handle = core.handle();
-        let addr = format!("127.0.0.1:{}", port).to_socket_addrs().unwrap().next().unwrap();
 
         for _ in 0..5 {
             if let Ok(stream) = core.run(TcpStream::connect(&addr, &handle)) {
@@ -62,6 +73,42 @@ impl ClientDatastore {
 
         panic!("Could not connect to the server after a few seconds");
     }
+    /// Runs a batch of operations in its own transaction on the server.
+    /// If the request is retried with the same idempotency key, e.g. after
+    /// a network failure, the server replays the recorded response rather
+    /// than running the batch again.
+    ///
+    /// # Arguments
+    /// * `idempotency_key` - A key unique to this batch, or an empty string
+    ///   to not record the response.
+    /// * `operations` - The operations to run.
+    pub fn execute_batch(
+        &self,
+        idempotency_key: &str,
+        operations: Vec<indradb::Operation>,
+    ) -> Result<Vec<indradb::OperationOutput>, indradb::Error> {
+        let mut req = self.client.execute_batch_request();
+        req.get().set_idempotency_key(idempotency_key);
+
+        {
+            let mut builder = req.get().init_operations(operations.len() as u32);
+
+            for (i, operation) in operations.iter().enumerate() {
+                converters::from_operation(operation, builder.reborrow().get(i as u32));
+            }
+        }
+
+        let f = req.send().promise.and_then(move |res| {
+            let list = res.get()?.get_result()?;
+            let list: Result<Vec<indradb::OperationOutput>, CapnpError> = list
+                .into_iter()
+                .map(|reader| converters::to_operation_output(&reader))
+                .collect();
+            list
+        });
+
+        map_indradb_error(self.core.borrow_mut().run(f))
+    }
 }
 
 impl indradb::Datastore for ClientDatastore {
@@ -90,6 +137,17 @@ impl indradb::Datastore for ClientDatastore {
         let trans = self.client.transaction_request().send().pipeline.get_transaction();
         Ok(ClientTransaction::new(self.core.clone(), trans))
     }
+
+    fn get_write_pressure(&self) -> Result<indradb::WritePressure, indradb::Error> {
+        let req = self.client.get_write_pressure_request();
+
+        let f = req
+            .send()
+            .promise
+            .and_then(move |res| Ok(converters::to_write_pressure(&res.get()?.get_result()?)));
+
+        map_indradb_error(self.core.borrow_mut().run(f))
+    }
 }
 
 pub struct ClientTransaction {
@@ -114,6 +172,55 @@ impl ClientTransaction {
         let future = f(&mut self.trans.borrow_mut());
         map_indradb_error(self.core.borrow_mut().run(future))
     }
+
+    // Runs a bulk delete, calling `progress` with the updates sent to its
+    // listener. The server sends every update before the response, so
+    // updates are drained before checking for the response to keep them in
+    // order.
+    fn run_bulk_delete<P, F>(
+        &self,
+        mut promise: P,
+        mut receiver: UnboundedReceiver<indradb::BulkDeleteProgress>,
+        mut progress: F,
+    ) -> Result<u64, indradb::Error>
+    where
+        P: Future<Item = u64, Error = CapnpError>,
+        F: FnMut(indradb::BulkDeleteProgress),
+    {
+        let future = poll_fn(move || {
+            while let Ok(Async::Ready(Some(update))) = receiver.poll() {
+                progress(update);
+            }
+
+            promise.poll()
+        });
+
+        map_indradb_error(self.core.borrow_mut().run(future))
+    }
+}
+
+// Receives the progress of a bulk delete from the server.
+struct BulkDeleteListener(UnboundedSender<indradb::BulkDeleteProgress>);
+
+impl BulkDeleteListener {
+    fn new_client(sender: UnboundedSender<indradb::BulkDeleteProgress>) -> autogen::bulk_delete_listener::Client {
+        autogen::bulk_delete_listener::ToClient::new(BulkDeleteListener(sender)).into_client::<Server>()
+    }
+}
+
+impl autogen::bulk_delete_listener::Server for BulkDeleteListener {
+    fn on_progress(
+        &mut self,
+        req: autogen::bulk_delete_listener::OnProgressParams,
+        _: autogen::bulk_delete_listener::OnProgressResults,
+    ) -> Promise<(), CapnpError> {
+        let params = pry!(req.get());
+        let progress = indradb::BulkDeleteProgress::new(params.get_deleted_count(), params.get_total_count());
+
+        // The receiver is only dropped once the delete has finished
+        let _ = self.0.unbounded_send(progress);
+        Promise::ok(())
+    }
 }
 
 impl indradb::Transaction for ClientTransaction {
@@ -158,6 +265,25 @@ impl indradb::Transaction for ClientTransaction {
         })
     }
 
+    fn get_vertices_page<Q: Into<indradb::VertexQuery>>(
+        &self,
+        q: Q,
+        max_results: u32,
+    ) -> Result<indradb::VertexPage, indradb::Error> {
+        self.execute(move |trans| {
+            let mut req = trans.get_vertices_page_request();
+            converters::from_vertex_query(&q.into(), req.get().init_q());
+            req.get().set_max_results(max_results);
+
+            let f = req
+                .send()
+                .promise
+                .and_then(move |res| converters::to_vertex_page(&res.get()?.get_result()?));
+
+            Box::new(f)
+        })
+    }
+
     fn delete_vertices<Q: Into<indradb::VertexQuery>>(&self, q: Q) -> Result<(), indradb::Error> {
         self.execute(move |trans| {
             let mut req = trans.delete_vertices_request();
@@ -172,6 +298,20 @@ impl indradb::Transaction for ClientTransaction {
         })
     }
 
+    fn delete_vertices_in_batches<Q, F>(&self, q: Q, batch_size: u32, progress: F) -> Result<u64, indradb::Error>
+    where
+        Q: Into<indradb::VertexQuery>,
+        F: FnMut(indradb::BulkDeleteProgress),
+    {
+        let (sender, receiver) = unbounded();
+        let mut req = self.trans.borrow_mut().delete_vertices_in_batches_request();
+        converters::from_vertex_query(&q.into(), req.get().init_q());
+        req.get().set_batch_size(batch_size);
+        req.get().set_listener(BulkDeleteListener::new_client(sender));
+        let promise = req.send().promise.and_then(move |res| Ok(res.get()?.get_result()));
+        self.run_bulk_delete(promise, receiver, progress)
+    }
+
     fn get_vertex_count(&self) -> Result<u64, indradb::Error> {
         self.execute(move |trans| {
             let req = trans.get_vertex_count_request();
@@ -209,6 +349,25 @@ impl indradb::Transaction for ClientTransaction {
         })
     }
 
+    fn get_edges_page<Q: Into<indradb::EdgeQuery>>(
+        &self,
+        q: Q,
+        max_results: u32,
+    ) -> Result<indradb::EdgePage, indradb::Error> {
+        self.execute(move |trans| {
+            let mut req = trans.get_edges_page_request();
+            converters::from_edge_query(&q.into(), req.get().init_q());
+            req.get().set_max_results(max_results);
+
+            let f = req
+                .send()
+                .promise
+                .and_then(move |res| converters::to_edge_page(&res.get()?.get_result()?));
+
+            Box::new(f)
+        })
+    }
+
     fn delete_edges<Q: Into<indradb::EdgeQuery>>(&self, q: Q) -> Result<(), indradb::Error> {
         self.execute(move |trans| {
             let mut req = trans.delete_edges_request();
@@ -223,6 +382,20 @@ impl indradb::Transaction for ClientTransaction {
         })
     }
 
+    fn delete_edges_in_batches<Q, F>(&self, q: Q, batch_size: u32, progress: F) -> Result<u64, indradb::Error>
+    where
+        Q: Into<indradb::EdgeQuery>,
+        F: FnMut(indradb::BulkDeleteProgress),
+    {
+        let (sender, receiver) = unbounded();
+        let mut req = self.trans.borrow_mut().delete_edges_in_batches_request();
+        converters::from_edge_query(&q.into(), req.get().init_q());
+        req.get().set_batch_size(batch_size);
+        req.get().set_listener(BulkDeleteListener::new_client(sender));
+        let promise = req.send().promise.and_then(move |res| Ok(res.get()?.get_result()));
+        self.run_bulk_delete(promise, receiver, progress)
+    }
+
     fn get_edge_count(
         &self,
         id: Uuid,
@@ -341,4 +514,74 @@ impl indradb::Transaction for ClientTransaction {
             Box::new(f)
         })
     }
+
+    fn get_all_vertex_properties<Q: Into<indradb::VertexQuery>>(
+        &self,
+        q: Q,
+    ) -> Result<Vec<indradb::VertexProperties>, indradb::Error> {
+        self.execute(move |trans| {
+            let mut req = trans.get_all_vertex_properties_request();
+            converters::from_vertex_query(&q.into(), req.get().init_q());
+
+            let f = req.send().promise.and_then(move |res| {
+                let list = res.get()?.get_result()?;
+                let list: Result<Vec<indradb::VertexProperties>, CapnpError> = list
+                    .into_iter()
+                    .map(|reader| converters::to_vertex_properties(&reader))
+                    .collect();
+                list
+            });
+
+            Box::new(f)
+        })
+    }
+
+    fn get_all_edge_properties<Q: Into<indradb::EdgeQuery>>(
+        &self,
+        q: Q,
+    ) -> Result<Vec<indradb::EdgeProperties>, indradb::Error> {
+        self.execute(move |trans| {
+            let mut req = trans.get_all_edge_properties_request();
+            converters::from_edge_query(&q.into(), req.get().init_q());
+
+            let f = req.send().promise.and_then(move |res| {
+                let list = res.get()?.get_result()?;
+                let list: Result<Vec<indradb::EdgeProperties>, CapnpError> = list
+                    .into_iter()
+                    .map(|reader| converters::to_edge_properties(&reader))
+                    .collect();
+                list
+            });
+
+            Box::new(f)
+        })
+    }
+
+    fn execute_batch(
+        &self,
+        operations: Vec<indradb::Operation>,
+    ) -> Result<Vec<indradb::OperationOutput>, indradb::Error> {
+        self.execute(move |trans| {
+            let mut req = trans.execute_batch_request();
+
+            {
+                let mut builder = req.get().init_operations(operations.len() as u32);
+
+                for (i, operation) in operations.iter().enumerate() {
+                    converters::from_operation(operation, builder.reborrow().get(i as u32));
+                }
+            }
+
+            let f = req.send().promise.and_then(move |res| {
+                let list = res.get()?.get_result()?;
+                let list: Result<Vec<indradb::OperationOutput>, CapnpError> = list
+                    .into_iter()
+                    .map(|reader| converters::to_operation_output(&reader))
+                    .collect();
+                list
+            });
+
+            Box::new(f)
+        })
+    }
 }
@@ -68,6 +68,67 @@ pub fn to_vertex_property<'a>(
     Ok(indradb::VertexProperty::new(id, value))
 }
 
+pub fn from_named_property<'a>(property: &indradb::NamedProperty, mut builder: autogen::property::Builder<'a>) {
+    builder.set_name(&property.name);
+    builder.set_value(&property.value.to_string());
+}
+
+pub fn to_named_property<'a>(reader: &autogen::property::Reader<'a>) -> Result<indradb::NamedProperty, CapnpError> {
+    let name = reader.get_name()?.to_string();
+    let value = map_capnp_err(serde_json::from_str(reader.get_value()?))?;
+    Ok(indradb::NamedProperty::new(name, value))
+}
+
+pub fn from_vertex_properties<'a>(
+    properties: &indradb::VertexProperties,
+    mut builder: autogen::vertex_properties::Builder<'a>,
+) {
+    from_vertex(&properties.vertex, builder.reborrow().init_vertex());
+    let mut props = builder.init_props(properties.props.len() as u32);
+
+    for (i, property) in properties.props.iter().enumerate() {
+        from_named_property(property, props.reborrow().get(i as u32));
+    }
+}
+
+pub fn to_vertex_properties<'a>(
+    reader: &autogen::vertex_properties::Reader<'a>,
+) -> Result<indradb::VertexProperties, CapnpError> {
+    let vertex = to_vertex(&reader.get_vertex()?)?;
+    let props: Result<Vec<indradb::NamedProperty>, CapnpError> = reader
+        .get_props()?
+        .into_iter()
+        .map(|reader| to_named_property(&reader))
+        .collect();
+    Ok(indradb::VertexProperties::new(vertex, props?))
+}
+
+pub fn from_edge_properties<'a>(
+    properties: &indradb::EdgeProperties,
+    mut builder: autogen::edge_properties::Builder<'a>,
+) -> Result<(), CapnpError> {
+    from_edge(&properties.edge, builder.reborrow().init_edge())?;
+    let mut props = builder.init_props(properties.props.len() as u32);
+
+    for (i, property) in properties.props.iter().enumerate() {
+        from_named_property(property, props.reborrow().get(i as u32));
+    }
+
+    Ok(())
+}
+
+pub fn to_edge_properties<'a>(
+    reader: &autogen::edge_properties::Reader<'a>,
+) -> Result<indradb::EdgeProperties, CapnpError> {
+    let edge = to_edge(&reader.get_edge()?)?;
+    let props: Result<Vec<indradb::NamedProperty>, CapnpError> = reader
+        .get_props()?
+        .into_iter()
+        .map(|reader| to_named_property(&reader))
+        .collect();
+    Ok(indradb::EdgeProperties::new(edge, props?))
+}
+
 pub fn from_edge_property<'a>(property: &indradb::EdgeProperty, mut builder: autogen::edge_property::Builder<'a>) {
     builder.set_value(&property.value.to_string());
     from_edge_key(&property.key, builder.init_key());
@@ -318,10 +379,200 @@ pub fn to_bulk_insert_items<'a>(
     Ok(items?.into_iter())
 }
 
+pub fn from_operation<'a>(operation: &indradb::Operation, builder: autogen::operation::Builder<'a>) {
+    match operation {
+        indradb::Operation::CreateVertex(vertex) => from_vertex(vertex, builder.init_create_vertex()),
+        indradb::Operation::CreateVertexFromType(t) => builder.set_create_vertex_from_type(&t.0),
+        indradb::Operation::GetVertices(q) => from_vertex_query(q, builder.init_get_vertices()),
+        indradb::Operation::DeleteVertices(q) => from_vertex_query(q, builder.init_delete_vertices()),
+        indradb::Operation::CreateEdge(key) => from_edge_key(key, builder.init_create_edge()),
+        indradb::Operation::GetEdges(q) => from_edge_query(q, builder.init_get_edges()),
+        indradb::Operation::DeleteEdges(q) => from_edge_query(q, builder.init_delete_edges()),
+        indradb::Operation::GetVertexProperties(q) => {
+            from_vertex_property_query(q, builder.init_get_vertex_properties())
+        }
+        indradb::Operation::SetVertexProperties(q, value) => {
+            let mut builder = builder.init_set_vertex_properties();
+            builder.set_value(&value.to_string());
+            from_vertex_property_query(q, builder.init_q());
+        }
+        indradb::Operation::DeleteVertexProperties(q) => {
+            from_vertex_property_query(q, builder.init_delete_vertex_properties())
+        }
+        indradb::Operation::GetEdgeProperties(q) => from_edge_property_query(q, builder.init_get_edge_properties()),
+        indradb::Operation::SetEdgeProperties(q, value) => {
+            let mut builder = builder.init_set_edge_properties();
+            builder.set_value(&value.to_string());
+            from_edge_property_query(q, builder.init_q());
+        }
+        indradb::Operation::DeleteEdgeProperties(q) => {
+            from_edge_property_query(q, builder.init_delete_edge_properties())
+        }
+    }
+}
+
+pub fn to_operation<'a>(reader: &autogen::operation::Reader<'a>) -> Result<indradb::Operation, CapnpError> {
+    match reader.which()? {
+        autogen::operation::CreateVertex(vertex) => Ok(indradb::Operation::CreateVertex(to_vertex(&vertex?)?)),
+        autogen::operation::CreateVertexFromType(t) => {
+            let t = map_capnp_err(indradb::Type::new(t?))?;
+            Ok(indradb::Operation::CreateVertexFromType(t))
+        }
+        autogen::operation::GetVertices(q) => Ok(indradb::Operation::GetVertices(to_vertex_query(&q?)?)),
+        autogen::operation::DeleteVertices(q) => Ok(indradb::Operation::DeleteVertices(to_vertex_query(&q?)?)),
+        autogen::operation::CreateEdge(key) => Ok(indradb::Operation::CreateEdge(to_edge_key(&key?)?)),
+        autogen::operation::GetEdges(q) => Ok(indradb::Operation::GetEdges(to_edge_query(&q?)?)),
+        autogen::operation::DeleteEdges(q) => Ok(indradb::Operation::DeleteEdges(to_edge_query(&q?)?)),
+        autogen::operation::GetVertexProperties(q) => {
+            Ok(indradb::Operation::GetVertexProperties(to_vertex_property_query(&q?)?))
+        }
+        autogen::operation::SetVertexProperties(params) => {
+            let q = to_vertex_property_query(&params.get_q()?)?;
+            let value = map_capnp_err(serde_json::from_str(params.get_value()?))?;
+            Ok(indradb::Operation::SetVertexProperties(q, value))
+        }
+        autogen::operation::DeleteVertexProperties(q) => Ok(indradb::Operation::DeleteVertexProperties(
+            to_vertex_property_query(&q?)?,
+        )),
+        autogen::operation::GetEdgeProperties(q) => {
+            Ok(indradb::Operation::GetEdgeProperties(to_edge_property_query(&q?)?))
+        }
+        autogen::operation::SetEdgeProperties(params) => {
+            let q = to_edge_property_query(&params.get_q()?)?;
+            let value = map_capnp_err(serde_json::from_str(params.get_value()?))?;
+            Ok(indradb::Operation::SetEdgeProperties(q, value))
+        }
+        autogen::operation::DeleteEdgeProperties(q) => {
+            Ok(indradb::Operation::DeleteEdgeProperties(to_edge_property_query(&q?)?))
+        }
+    }
+}
+
+pub fn from_operation_output<'a>(
+    output: &indradb::OperationOutput,
+    mut builder: autogen::operation_output::Builder<'a>,
+) -> Result<(), CapnpError> {
+    match output {
+        indradb::OperationOutput::Created(created) => builder.set_created(*created),
+        indradb::OperationOutput::Id(id) => builder.set_id(id.as_bytes()),
+        indradb::OperationOutput::Vertices(vertices) => {
+            let mut builder = builder.init_vertices(vertices.len() as u32);
+
+            for (i, vertex) in vertices.iter().enumerate() {
+                from_vertex(vertex, builder.reborrow().get(i as u32));
+            }
+        }
+        indradb::OperationOutput::Edges(edges) => {
+            let mut builder = builder.init_edges(edges.len() as u32);
+
+            for (i, edge) in edges.iter().enumerate() {
+                from_edge(edge, builder.reborrow().get(i as u32))?;
+            }
+        }
+        indradb::OperationOutput::VertexProperties(properties) => {
+            let mut builder = builder.init_vertex_properties(properties.len() as u32);
+
+            for (i, property) in properties.iter().enumerate() {
+                from_vertex_property(property, builder.reborrow().get(i as u32));
+            }
+        }
+        indradb::OperationOutput::EdgeProperties(properties) => {
+            let mut builder = builder.init_edge_properties(properties.len() as u32);
+
+            for (i, property) in properties.iter().enumerate() {
+                from_edge_property(property, builder.reborrow().get(i as u32));
+            }
+        }
+        indradb::OperationOutput::Done => builder.set_done(()),
+    }
+
+    Ok(())
+}
+
+pub fn to_operation_output<'a>(
+    reader: &autogen::operation_output::Reader<'a>,
+) -> Result<indradb::OperationOutput, CapnpError> {
+    match reader.which()? {
+        autogen::operation_output::Created(created) => Ok(indradb::OperationOutput::Created(created)),
+        autogen::operation_output::Id(id) => {
+            let id = map_capnp_err(Uuid::from_slice(id?))?;
+            Ok(indradb::OperationOutput::Id(id))
+        }
+        autogen::operation_output::Vertices(vertices) => {
+            let vertices: Result<Vec<indradb::Vertex>, CapnpError> =
+                vertices?.into_iter().map(|reader| to_vertex(&reader)).collect();
+            Ok(indradb::OperationOutput::Vertices(vertices?))
+        }
+        autogen::operation_output::Edges(edges) => {
+            let edges: Result<Vec<indradb::Edge>, CapnpError> =
+                edges?.into_iter().map(|reader| to_edge(&reader)).collect();
+            Ok(indradb::OperationOutput::Edges(edges?))
+        }
+        autogen::operation_output::VertexProperties(properties) => {
+            let properties: Result<Vec<indradb::VertexProperty>, CapnpError> = properties?
+                .into_iter()
+                .map(|reader| to_vertex_property(&reader))
+                .collect();
+            Ok(indradb::OperationOutput::VertexProperties(properties?))
+        }
+        autogen::operation_output::EdgeProperties(properties) => {
+            let properties: Result<Vec<indradb::EdgeProperty>, CapnpError> = properties?
+                .into_iter()
+                .map(|reader| to_edge_property(&reader))
+                .collect();
+            Ok(indradb::OperationOutput::EdgeProperties(properties?))
+        }
+        autogen::operation_output::Done(()) => Ok(indradb::OperationOutput::Done),
+    }
+}
+
+// Encodes the outputs of a batch, e.g. to record the response to a request
+// made with an idempotency key.
+pub fn encode_operation_outputs(outputs: &[indradb::OperationOutput]) -> Result<Vec<u8>, CapnpError> {
+    let mut message = capnp::message::Builder::new_default();
+
+    {
+        let response = message.init_root::<autogen::batch_response::Builder>();
+        let mut builder = response.init_outputs(outputs.len() as u32);
+
+        for (i, output) in outputs.iter().enumerate() {
+            from_operation_output(output, builder.reborrow().get(i as u32))?;
+        }
+    }
+
+    let mut bytes = Vec::new();
+    map_capnp_err(capnp::serialize::write_message(&mut bytes, &message))?;
+    Ok(bytes)
+}
+
+// Decodes the outputs of a batch encoded by `encode_operation_outputs`.
+pub fn decode_operation_outputs(mut bytes: &[u8]) -> Result<Vec<indradb::OperationOutput>, CapnpError> {
+    let message = capnp::serialize::read_message(&mut bytes, capnp::message::ReaderOptions::new())?;
+    let response = message.get_root::<autogen::batch_response::Reader>()?;
+    response
+        .get_outputs()?
+        .into_iter()
+        .map(|reader| to_operation_output(&reader))
+        .collect()
+}
+
+pub fn from_live_query_change<'a>(
+    change: &indradb::LiveQueryChange,
+    mut builder: autogen::live_query_change::Builder<'a>,
+) {
+    match *change {
+        indradb::LiveQueryChange::VertexAdded(ref vertex) => from_vertex(vertex, builder.init_vertex_added()),
+        indradb::LiveQueryChange::VertexRemoved(ref id) => builder.set_vertex_removed(id.as_bytes()),
+        indradb::LiveQueryChange::EdgeAdded(ref key) => from_edge_key(key, builder.init_edge_added()),
+        indradb::LiveQueryChange::EdgeRemoved(ref key) => from_edge_key(key, builder.init_edge_removed()),
+    }
+}
+
 pub fn from_edge_direction(direction: indradb::EdgeDirection) -> autogen::EdgeDirection {
     match direction {
         indradb::EdgeDirection::Outbound => autogen::EdgeDirection::Outbound,
         indradb::EdgeDirection::Inbound => autogen::EdgeDirection::Inbound,
+        indradb::EdgeDirection::Both => autogen::EdgeDirection::Both,
     }
 }
 
@@ -329,6 +580,96 @@ pub fn to_edge_direction(direction: autogen::EdgeDirection) -> indradb::EdgeDire
     match direction {
         autogen::EdgeDirection::Outbound => indradb::EdgeDirection::Outbound,
         autogen::EdgeDirection::Inbound => indradb::EdgeDirection::Inbound,
+        autogen::EdgeDirection::Both => indradb::EdgeDirection::Both,
+    }
+}
+
+pub fn from_vertex_page<'a>(page: &indradb::VertexPage, mut builder: autogen::vertex_page::Builder<'a>) {
+    builder.set_truncated(page.truncated);
+
+    if let Some(ref cursor) = page.cursor {
+        from_vertex_query(cursor, builder.reborrow().init_cursor());
+    }
+
+    let mut vertices = builder.init_vertices(page.vertices.len() as u32);
+
+    for (i, vertex) in page.vertices.iter().enumerate() {
+        from_vertex(vertex, vertices.reborrow().get(i as u32));
+    }
+}
+
+pub fn to_vertex_page<'a>(reader: &autogen::vertex_page::Reader<'a>) -> Result<indradb::VertexPage, CapnpError> {
+    let vertices: Result<Vec<indradb::Vertex>, CapnpError> = reader
+        .get_vertices()?
+        .into_iter()
+        .map(|reader| to_vertex(&reader))
+        .collect();
+
+    let cursor = if reader.has_cursor() {
+        Some(to_vertex_query(&reader.get_cursor()?)?)
+    } else {
+        None
+    };
+
+    Ok(indradb::VertexPage {
+        vertices: vertices?,
+        truncated: reader.get_truncated(),
+        cursor,
+    })
+}
+
+pub fn from_edge_page<'a>(
+    page: &indradb::EdgePage,
+    mut builder: autogen::edge_page::Builder<'a>,
+) -> Result<(), CapnpError> {
+    builder.set_truncated(page.truncated);
+
+    if let Some(ref cursor) = page.cursor {
+        from_edge_query(cursor, builder.reborrow().init_cursor());
+    }
+
+    let mut edges = builder.init_edges(page.edges.len() as u32);
+
+    for (i, edge) in page.edges.iter().enumerate() {
+        from_edge(edge, edges.reborrow().get(i as u32))?;
+    }
+
+    Ok(())
+}
+
+pub fn to_edge_page<'a>(reader: &autogen::edge_page::Reader<'a>) -> Result<indradb::EdgePage, CapnpError> {
+    let edges: Result<Vec<indradb::Edge>, CapnpError> =
+        reader.get_edges()?.into_iter().map(|reader| to_edge(&reader)).collect();
+
+    let cursor = if reader.has_cursor() {
+        Some(to_edge_query(&reader.get_cursor()?)?)
+    } else {
+        None
+    };
+
+    Ok(indradb::EdgePage {
+        edges: edges?,
+        truncated: reader.get_truncated(),
+        cursor,
+    })
+}
+
+pub fn from_write_pressure<'a>(pressure: &indradb::WritePressure, mut builder: autogen::write_pressure::Builder<'a>) {
+    builder.set_stopped(pressure.stopped);
+    builder.set_delayed_write_rate(pressure.delayed_write_rate.unwrap_or(0));
+    builder.set_pending_compaction_bytes(pressure.pending_compaction_bytes);
+}
+
+pub fn to_write_pressure<'a>(reader: &autogen::write_pressure::Reader<'a>) -> indradb::WritePressure {
+    let delayed_write_rate = match reader.get_delayed_write_rate() {
+        0 => None,
+        rate => Some(rate),
+    };
+
+    indradb::WritePressure {
+        stopped: reader.get_stopped(),
+        delayed_write_rate,
+        pending_compaction_bytes: reader.get_pending_compaction_bytes(),
     }
 }
 
@@ -7,12 +7,15 @@ extern crate core;
 extern crate error_chain;
 extern crate futures;
 extern crate futures_cpupool;
+extern crate libloading;
+extern crate rhai;
 #[cfg(test)]
 #[macro_use]
 extern crate lazy_static;
 extern crate serde_json;
 extern crate tokio_core;
 extern crate tokio_io;
+extern crate tungstenite;
 extern crate uuid;
 
 #[cfg(test)]
@@ -21,12 +24,20 @@ extern crate indradb;
 #[cfg(not(test))]
 extern crate indradb;
 
+pub mod admin;
 pub mod autogen;
+#[cfg(feature = "bench")]
+pub mod bench;
 #[macro_use]
 pub mod converters;
 pub mod client_datastore;
 pub mod errors;
+pub mod plugins;
+pub mod script;
 pub mod server;
+pub mod shell;
+pub mod visualize;
+pub mod websocket;
 
 #[cfg(test)]
 mod tests;
@@ -0,0 +1,109 @@
+//! Loads server-side plugins from shared libraries. See the library's
+//! `export_plugins!` for how to build one.
+
+use errors;
+use indradb::{
+    Datastore as IndraDbDatastore, PluginDeclaration, PluginRegistrar, Transaction as IndraDbTransaction,
+    PLUGIN_API_VERSION,
+};
+use libloading::{Library, Symbol};
+use serde_json::Value as JsonValue;
+use std::fs;
+use std::path::Path;
+
+/// Plugins loaded from shared libraries.
+pub struct Plugins {
+    // Declared before `libraries` so that the plugins are dropped before the
+    // code backing them is unloaded.
+    registrar: PluginRegistrar,
+    libraries: Vec<Library>,
+}
+
+impl Plugins {
+    /// Creates an empty set of plugins.
+    pub fn empty() -> Self {
+        Self {
+            registrar: PluginRegistrar::new(),
+            libraries: Vec::new(),
+        }
+    }
+
+    /// Loads the plugins from every shared library in a directory.
+    ///
+    /// # Arguments
+    /// * `dir` - The directory to load the libraries from.
+    ///
+    /// # Errors
+    /// Returns an error if a library couldn't be loaded, doesn't export any
+    /// plugins, or was built against a different version of the plugin API.
+    pub fn load<P: AsRef<Path>>(dir: P) -> Result<Self, errors::Error> {
+        let mut plugins = Self::empty();
+
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+
+            let is_library = match path.extension().and_then(|extension| extension.to_str()) {
+                Some("so") | Some("dylib") | Some("dll") => true,
+                _ => false,
+            };
+
+            if is_library {
+                plugins.load_library(&path)?;
+            }
+        }
+
+        Ok(plugins)
+    }
+
+    fn load_library(&mut self, path: &Path) -> Result<(), errors::Error> {
+        let library = Library::new(path)?;
+
+        {
+            // This is only sound if the library was built with the same
+            // compiler and plugin API version, which is checked as far as
+            // possible.
+            let declaration: Symbol<*const PluginDeclaration> = unsafe { library.get(b"INDRADB_PLUGINS\0")? };
+            let declaration = unsafe { &**declaration };
+
+            if declaration.api_version != PLUGIN_API_VERSION {
+                return Err(format!(
+                    "Plugin library {:?} was built against version {} of the plugin API, but the server uses {}",
+                    path, declaration.api_version, PLUGIN_API_VERSION
+                )
+                .into());
+            }
+
+            (declaration.register)(&mut self.registrar);
+        }
+
+        self.libraries.push(library);
+        Ok(())
+    }
+
+    /// Calls a plugin in its own transaction.
+    ///
+    /// # Arguments
+    /// * `datastore` - The datastore to run the plugin against.
+    /// * `name` - The name of the plugin.
+    /// * `arg` - The argument to pass to the plugin.
+    ///
+    /// # Errors
+    /// Returns an error if there's no plugin with the given name, or if the
+    /// plugin fails.
+    pub fn call<D, T>(&self, datastore: &D, name: &str, arg: JsonValue) -> Result<JsonValue, errors::Error>
+    where
+        D: IndraDbDatastore<Trans = T>,
+        T: IndraDbTransaction,
+    {
+        let plugin = self
+            .registrar
+            .get(name)
+            .ok_or_else(|| -> errors::Error { format!("No plugin named `{}`", name).into() })?;
+        let trans = datastore
+            .transaction()
+            .map_err(|err| -> errors::Error { format!("{}", err).into() })?;
+        plugin
+            .call(&trans, arg)
+            .map_err(|err| -> errors::Error { format!("{}", err).into() })
+    }
+}
@@ -0,0 +1,306 @@
+//! Server-side scripts, which run against a transaction with no network
+//! round trips between steps.
+//!
+//! Scripts are written in [Rhai](https://rhai.rs). They're uploaded under a
+//! name, and run with a JSON argument available as `arg`; the value of the
+//! script's last expression is returned as JSON. Each run gets its own
+//! transaction, and is limited in how many operations it may perform, so a
+//! runaway script can't stall the server.
+
+use indradb::{
+    Datastore as IndraDbDatastore, EdgeDirection, EdgeKey, EdgeQueryExt, SpecificEdgeQuery, SpecificVertexQuery,
+    Transaction as IndraDbTransaction, Type, VertexQueryExt,
+};
+use rhai;
+use rhai::{Dynamic, Engine, EvalAltResult, Map, Scope};
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use uuid::Uuid;
+
+/// The default for the maximum number of operations a script run may
+/// perform.
+pub const DEFAULT_MAX_OPERATIONS: u64 = 1_000_000;
+
+// How deeply scripts may nest function calls.
+const MAX_CALL_LEVELS: usize = 32;
+
+// How large strings, arrays and maps built by scripts may get.
+const MAX_COLLECTION_SIZE: usize = 1_000_000;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+fn map_script_err<T, E: Display>(result: Result<T, E>) -> ScriptResult<T> {
+    result.map_err(|err| format!("{}", err).into())
+}
+
+/// Named scripts uploaded to the server. Clones share the same scripts.
+#[derive(Clone, Debug)]
+pub struct Scripts {
+    sources: Arc<RwLock<HashMap<String, String>>>,
+    max_operations: u64,
+}
+
+impl Scripts {
+    /// Creates a new, empty set of scripts.
+    ///
+    /// # Arguments
+    /// * `max_operations` - The maximum number of operations a script run
+    ///   may perform.
+    pub fn new(max_operations: u64) -> Self {
+        Self {
+            sources: Arc::new(RwLock::new(HashMap::new())),
+            max_operations,
+        }
+    }
+
+    /// Uploads a script, replacing any existing script with the same name.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the script.
+    /// * `source` - The script's source.
+    ///
+    /// # Errors
+    /// Returns an error if the script doesn't compile.
+    pub fn put(&self, name: String, source: String) -> Result<(), String> {
+        // Functions are resolved when the script runs, so the transaction
+        // doesn't need to be registered to compile it.
+        self.engine().compile(&source).map_err(|err| format!("{}", err))?;
+        self.sources.write().unwrap().insert(name, source);
+        Ok(())
+    }
+
+    /// Runs a script in its own transaction.
+    ///
+    /// # Arguments
+    /// * `datastore` - The datastore to run the script against.
+    /// * `name` - The name of the script.
+    /// * `arg` - The argument to pass to the script, available as `arg`.
+    ///
+    /// # Errors
+    /// Returns an error if there's no script with the given name, or if the
+    /// script fails or exceeds its limits.
+    pub fn run<D, T>(&self, datastore: &D, name: &str, arg: JsonValue) -> Result<JsonValue, String>
+    where
+        D: IndraDbDatastore<Trans = T>,
+        T: IndraDbTransaction + 'static,
+    {
+        let source = match self.sources.read().unwrap().get(name) {
+            Some(source) => source.clone(),
+            None => return Err(format!("No script named `{}`", name)),
+        };
+
+        let trans = datastore.transaction().map_err(|err| format!("{}", err))?;
+        let mut engine = self.engine();
+        register_transaction(&mut engine, &Arc::new(trans));
+        let mut scope = Scope::new();
+        scope.push_constant("arg", rhai::serde::to_dynamic(arg).map_err(|err| format!("{}", err))?);
+        let result: Dynamic = engine
+            .eval_with_scope(&mut scope, &source)
+            .map_err(|err| format!("{}", err))?;
+        rhai::serde::from_dynamic(&result).map_err(|err| format!("{}", err))
+    }
+
+    fn engine(&self) -> Engine {
+        let mut engine = Engine::new();
+        engine.set_max_operations(self.max_operations);
+        engine.set_max_call_levels(MAX_CALL_LEVELS);
+        engine.set_max_string_size(MAX_COLLECTION_SIZE);
+        engine.set_max_array_size(MAX_COLLECTION_SIZE);
+        engine.set_max_map_size(MAX_COLLECTION_SIZE);
+        engine
+    }
+}
+
+fn to_uuid(id: &str) -> ScriptResult<Uuid> {
+    map_script_err(Uuid::from_str(id))
+}
+
+fn to_type(t: &str) -> ScriptResult<Type> {
+    map_script_err(Type::new(t))
+}
+
+fn to_edge_key(outbound_id: &str, t: &str, inbound_id: &str) -> ScriptResult<EdgeKey> {
+    Ok(EdgeKey::new(to_uuid(outbound_id)?, to_type(t)?, to_uuid(inbound_id)?))
+}
+
+fn from_edge_key(key: &EdgeKey) -> Dynamic {
+    let mut map = Map::new();
+    map.insert("outbound_id".into(), key.outbound_id.to_hyphenated().to_string().into());
+    map.insert("t".into(), key.t.0.clone().into());
+    map.insert("inbound_id".into(), key.inbound_id.to_hyphenated().to_string().into());
+    map.into()
+}
+
+// Exposes the transaction to scripts. Ids are passed around as strings, and
+// JSON values as the equivalent script values.
+fn register_transaction<T: IndraDbTransaction + 'static>(engine: &mut Engine, trans: &Arc<T>) {
+    let t = trans.clone();
+    engine.register_fn("create_vertex", move |vertex_t: &str| -> ScriptResult<String> {
+        let id = map_script_err(t.create_vertex_from_type(to_type(vertex_t)?))?;
+        Ok(id.to_hyphenated().to_string())
+    });
+
+    let t = trans.clone();
+    engine.register_fn("get_vertex", move |id: &str| -> ScriptResult<Dynamic> {
+        let vertices = map_script_err(t.get_vertices(SpecificVertexQuery::single(to_uuid(id)?)))?;
+
+        Ok(match vertices.into_iter().next() {
+            Some(vertex) => {
+                let mut map = Map::new();
+                map.insert("id".into(), vertex.id.to_hyphenated().to_string().into());
+                map.insert("t".into(), vertex.t.0.into());
+                map.into()
+            }
+            None => Dynamic::UNIT,
+        })
+    });
+
+    let t = trans.clone();
+    engine.register_fn("delete_vertex", move |id: &str| -> ScriptResult<()> {
+        map_script_err(t.delete_vertices(SpecificVertexQuery::single(to_uuid(id)?)))
+    });
+
+    let t = trans.clone();
+    engine.register_fn(
+        "create_edge",
+        move |outbound_id: &str, edge_t: &str, inbound_id: &str| -> ScriptResult<bool> {
+            map_script_err(t.create_edge(&to_edge_key(outbound_id, edge_t, inbound_id)?))
+        },
+    );
+
+    let t = trans.clone();
+    engine.register_fn(
+        "delete_edge",
+        move |outbound_id: &str, edge_t: &str, inbound_id: &str| -> ScriptResult<()> {
+            let q = SpecificEdgeQuery::single(to_edge_key(outbound_id, edge_t, inbound_id)?);
+            map_script_err(t.delete_edges(q))
+        },
+    );
+
+    for &(name, direction) in &[
+        ("outbound", EdgeDirection::Outbound),
+        ("inbound", EdgeDirection::Inbound),
+    ] {
+        let t = trans.clone();
+        engine.register_fn(
+            name,
+            move |id: &str, edge_t: &str, limit: i64| -> ScriptResult<Dynamic> {
+                if limit < 0 || limit > i64::from(u32::max_value()) {
+                    return Err("Limit must be a u32".into());
+                }
+
+                let limit = limit as u32;
+                let q = SpecificVertexQuery::single(to_uuid(id)?);
+
+                let edges = match direction {
+                    EdgeDirection::Outbound => t.get_edges(q.outbound(limit).t(to_type(edge_t)?)),
+                    _ => t.get_edges(q.inbound(limit).t(to_type(edge_t)?)),
+                };
+
+                let keys: Vec<Dynamic> = map_script_err(edges)?
+                    .iter()
+                    .map(|edge| from_edge_key(&edge.key))
+                    .collect();
+                Ok(keys.into())
+            },
+        );
+    }
+
+    let t = trans.clone();
+    engine.register_fn(
+        "get_edge_count",
+        move |id: &str, direction: &str| -> ScriptResult<i64> {
+            let direction = map_script_err(EdgeDirection::from_str(direction))?;
+            Ok(map_script_err(t.get_edge_count(to_uuid(id)?, None, direction))? as i64)
+        },
+    );
+
+    let t = trans.clone();
+    engine.register_fn(
+        "get_vertex_property",
+        move |id: &str, name: &str| -> ScriptResult<Dynamic> {
+            let q = SpecificVertexQuery::single(to_uuid(id)?).property(name);
+
+            match map_script_err(t.get_vertex_properties(q))?.into_iter().next() {
+                Some(property) => rhai::serde::to_dynamic(property.value),
+                None => Ok(Dynamic::UNIT),
+            }
+        },
+    );
+
+    let t = trans.clone();
+    engine.register_fn(
+        "set_vertex_property",
+        move |id: &str, name: &str, value: Dynamic| -> ScriptResult<()> {
+            let value: JsonValue = rhai::serde::from_dynamic(&value)?;
+            let q = SpecificVertexQuery::single(to_uuid(id)?).property(name);
+            map_script_err(t.set_vertex_properties(q, &value))
+        },
+    );
+
+    let t = trans.clone();
+    engine.register_fn(
+        "delete_vertex_property",
+        move |id: &str, name: &str| -> ScriptResult<()> {
+            let q = SpecificVertexQuery::single(to_uuid(id)?).property(name);
+            map_script_err(t.delete_vertex_properties(q))
+        },
+    );
+
+    let t = trans.clone();
+    engine.register_fn(
+        "get_edge_property",
+        move |outbound_id: &str, edge_t: &str, inbound_id: &str, name: &str| -> ScriptResult<Dynamic> {
+            let q = SpecificEdgeQuery::single(to_edge_key(outbound_id, edge_t, inbound_id)?).property(name);
+
+            match map_script_err(t.get_edge_properties(q))?.into_iter().next() {
+                Some(property) => rhai::serde::to_dynamic(property.value),
+                None => Ok(Dynamic::UNIT),
+            }
+        },
+    );
+
+    let t = trans.clone();
+    engine.register_fn(
+        "set_edge_property",
+        move |outbound_id: &str, edge_t: &str, inbound_id: &str, name: &str, value: Dynamic| -> ScriptResult<()> {
+            let value: JsonValue = rhai::serde::from_dynamic(&value)?;
+            let q = SpecificEdgeQuery::single(to_edge_key(outbound_id, edge_t, inbound_id)?).property(name);
+            map_script_err(t.set_edge_properties(q, &value))
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Scripts;
+    use indradb::{Datastore, MemoryDatastore, Transaction};
+    use serde_json::Value as JsonValue;
+
+    #[test]
+    fn should_run_scripts() {
+        let datastore = MemoryDatastore::default();
+        let scripts = Scripts::new(1000);
+        let source = "let a = create_vertex(arg); let b = create_vertex(arg); create_edge(a, arg, b); get_edge_count(a, \"outbound\")";
+        scripts.put("link".to_string(), source.to_string()).unwrap();
+
+        let result = scripts
+            .run(&datastore, "link", JsonValue::String("foo".to_string()))
+            .unwrap();
+        assert_eq!(result, JsonValue::from(1));
+        assert_eq!(datastore.transaction().unwrap().get_vertex_count().unwrap(), 2);
+    }
+
+    #[test]
+    fn should_limit_scripts() {
+        let datastore = MemoryDatastore::default();
+        let scripts = Scripts::new(1000);
+        scripts.put("loop".to_string(), "loop {}".to_string()).unwrap();
+        assert!(scripts.run(&datastore, "loop", JsonValue::Null).is_err());
+        assert!(scripts.run(&datastore, "missing", JsonValue::Null).is_err());
+        assert!(scripts.put("invalid".to_string(), "let".to_string()).is_err());
+    }
+}
@@ -4,39 +4,118 @@ use capnp::Error as CapnpError;
 use capnp_rpc::rpc_twoparty_capnp::Side;
 use capnp_rpc::twoparty::VatNetwork;
 use capnp_rpc::{RpcSystem, Server};
+use chrono::{Duration as ChronoDuration, Utc};
 use converters;
 use errors;
+use futures::sync::mpsc::{unbounded, UnboundedReceiver};
 use futures::{Future, Stream};
 use futures_cpupool::CpuPool;
 use indradb;
 use indradb::{
-    Datastore as IndraDbDatastore, Edge, EdgeProperty, MemoryDatastore, RocksdbDatastore,
-    Transaction as IndraDbTransaction, Type, Vertex, VertexProperty,
+    AdmissionOptions, BulkDeleteProgress, Datastore as IndraDbDatastore, Edge, EdgeProperties, EdgeProperty, HeapSize,
+    LiveQuery, LiveQueryChange, MemoryBudget, MemoryDatastore, MemoryReservation, OperationOutput, ResourceOptions,
+    RocksdbDatastore, Transaction as IndraDbTransaction, Type, Vertex, VertexProperties, VertexProperty,
 };
+use plugins::Plugins;
+use script::{Scripts, DEFAULT_MAX_OPERATIONS};
 use serde_json;
+use std::collections::HashSet;
 use std::env;
 use std::net::SocketAddr;
 use std::net::ToSocketAddrs;
-use std::sync::Arc;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 use tokio_core::net::TcpListener;
-use tokio_core::reactor::Core;
+use tokio_core::reactor::{Core, Handle};
 use tokio_io::AsyncRead;
 use uuid::Uuid;
+use visualize;
+use websocket;
+
+// How long live query threads wait for the datastore to change at a time.
+const LIVE_QUERY_POLL_INTERVAL_MS: u64 = 1000;
+
+// How long the responses to requests with idempotency keys are kept by
+// default, in seconds.
+const DEFAULT_IDEMPOTENCY_KEY_TTL_SECS: i64 = 86_400;
 
 struct Service<D: IndraDbDatastore<Trans = T> + Send + Sync + 'static, T: IndraDbTransaction + Send + Sync + 'static> {
     datastore: Arc<D>,
     pool: CpuPool,
+    handle: Handle,
+    scripts: Scripts,
+    plugins: Arc<Plugins>,
+    idempotency_key_ttl: ChronoDuration,
+    // Idempotency keys of batches that are still running.
+    running_idempotency_keys: Arc<Mutex<HashSet<String>>>,
+    max_result_size: Option<u32>,
+    memory_budget: Option<MemoryBudget>,
 }
 
 impl<D: IndraDbDatastore<Trans = T> + Send + Sync + 'static, T: IndraDbTransaction + Send + Sync + 'static>
     Service<D, T>
 {
-    fn new(datastore: D, worker_count: usize) -> Self {
+    fn new(
+        datastore: Arc<D>,
+        worker_count: usize,
+        handle: Handle,
+        scripts: Scripts,
+        plugins: Plugins,
+        idempotency_key_ttl: ChronoDuration,
+        max_result_size: Option<u32>,
+        memory_budget: Option<MemoryBudget>,
+    ) -> Self {
         Self {
-            datastore: Arc::new(datastore),
+            datastore,
             pool: CpuPool::new(worker_count),
+            handle,
+            scripts,
+            plugins: Arc::new(plugins),
+            idempotency_key_ttl,
+            running_idempotency_keys: Arc::new(Mutex::new(HashSet::new())),
+            max_result_size,
+            memory_budget,
         }
     }
+
+    // Runs a live query on its own thread, since it blocks waiting for
+    // changes, and forwards its changes to the listener from the event loop.
+    fn subscribe(&self, mut live_query: LiveQuery<T>, listener: autogen::live_query_listener::Client) {
+        let (sender, receiver) = unbounded::<Vec<LiveQueryChange>>();
+        let interval = Duration::from_millis(LIVE_QUERY_POLL_INTERVAL_MS);
+
+        thread::spawn(move || loop {
+            let changes = match live_query.changes(interval) {
+                Ok(ref changes) if changes.is_empty() => continue,
+                Ok(changes) => changes,
+                Err(_) => return,
+            };
+
+            // Sending fails once the listener is gone, so the thread exits
+            // on the first change after that.
+            if sender.unbounded_send(changes).is_err() {
+                return;
+            }
+        });
+
+        let f = receiver.for_each(move |changes| {
+            let mut req = listener.on_changes_request();
+
+            {
+                let mut res = req.get().init_changes(changes.len() as u32);
+
+                for (i, change) in changes.iter().enumerate() {
+                    converters::from_live_query_change(change, res.reborrow().get(i as u32));
+                }
+            }
+
+            req.send().promise.map(|_| ()).map_err(|_| ())
+        });
+
+        self.handle.spawn(f);
+    }
 }
 
 impl<D: IndraDbDatastore<Trans = T> + Send + Sync + 'static, T: IndraDbTransaction + Send + Sync + 'static>
@@ -63,6 +142,14 @@ impl<D: IndraDbDatastore<Trans = T> + Send + Sync + 'static, T: IndraDbTransacti
         let f = self
             .pool
             .spawn_fn(move || -> Result<(), CapnpError> {
+                // Fail fast so that ingestion pipelines can back off, rather
+                // than having their requests hang until writes resume
+                if converters::map_capnp_err(datastore.get_write_pressure())?.stopped {
+                    return Err(CapnpError::failed(
+                        "Writes are stopped until compaction catches up; retry later".to_string(),
+                    ));
+                }
+
                 converters::map_capnp_err(datastore.bulk_insert(items))?;
                 Ok(())
             })
@@ -80,25 +167,303 @@ impl<D: IndraDbDatastore<Trans = T> + Send + Sync + 'static, T: IndraDbTransacti
         mut res: autogen::service::TransactionResults,
     ) -> Promise<(), CapnpError> {
         let trans = pry!(converters::map_capnp_err(self.datastore.transaction()));
-        let trans_server = Transaction::new(self.pool.clone(), trans);
+        let trans_server = Transaction::new(
+            self.pool.clone(),
+            trans,
+            self.max_result_size,
+            self.memory_budget.clone(),
+        );
         let trans_client = autogen::transaction::ToClient::new(trans_server).into_client::<Server>();
         res.get().set_transaction(trans_client);
         Promise::ok(())
     }
+
+    fn subscribe_vertices(
+        &mut self,
+        req: autogen::service::SubscribeVerticesParams,
+        mut res: autogen::service::SubscribeVerticesResults,
+    ) -> Promise<(), CapnpError> {
+        let params = pry!(req.get());
+        let cnp_q = pry!(params.get_q());
+        let q = pry!(converters::to_vertex_query(&cnp_q));
+        let listener = pry!(params.get_listener());
+        let live_query = pry!(converters::map_capnp_err(LiveQuery::vertices(&*self.datastore, q)));
+        self.subscribe(live_query, listener);
+        res.get().set_result(());
+        Promise::ok(())
+    }
+
+    fn subscribe_edges(
+        &mut self,
+        req: autogen::service::SubscribeEdgesParams,
+        mut res: autogen::service::SubscribeEdgesResults,
+    ) -> Promise<(), CapnpError> {
+        let params = pry!(req.get());
+        let cnp_q = pry!(params.get_q());
+        let q = pry!(converters::to_edge_query(&cnp_q));
+        let listener = pry!(params.get_listener());
+        let live_query = pry!(converters::map_capnp_err(LiveQuery::edges(&*self.datastore, q)));
+        self.subscribe(live_query, listener);
+        res.get().set_result(());
+        Promise::ok(())
+    }
+
+    fn put_script(
+        &mut self,
+        req: autogen::service::PutScriptParams,
+        mut res: autogen::service::PutScriptResults,
+    ) -> Promise<(), CapnpError> {
+        let scripts = self.scripts.clone();
+        let params = pry!(req.get());
+        let name = pry!(params.get_name()).to_string();
+        let source = pry!(params.get_source()).to_string();
+
+        let f = self
+            .pool
+            .spawn_fn(move || -> Result<(), CapnpError> { converters::map_capnp_err(scripts.put(name, source)) })
+            .and_then(move |_| -> Result<(), CapnpError> {
+                res.get().set_result(());
+                Ok(())
+            });
+
+        Promise::from_future(f)
+    }
+
+    fn run_script(
+        &mut self,
+        req: autogen::service::RunScriptParams,
+        mut res: autogen::service::RunScriptResults,
+    ) -> Promise<(), CapnpError> {
+        let datastore = self.datastore.clone();
+        let scripts = self.scripts.clone();
+        let params = pry!(req.get());
+        let name = pry!(params.get_name()).to_string();
+        let arg = pry!(converters::map_capnp_err(serde_json::from_str(pry!(params.get_arg()))));
+
+        let f = self
+            .pool
+            .spawn_fn(move || -> Result<serde_json::Value, CapnpError> {
+                converters::map_capnp_err(scripts.run(&*datastore, &name, arg))
+            })
+            .and_then(move |value| -> Result<(), CapnpError> {
+                res.get().set_result(&value.to_string());
+                Ok(())
+            });
+
+        Promise::from_future(f)
+    }
+
+    fn call_plugin(
+        &mut self,
+        req: autogen::service::CallPluginParams,
+        mut res: autogen::service::CallPluginResults,
+    ) -> Promise<(), CapnpError> {
+        let datastore = self.datastore.clone();
+        let plugins = self.plugins.clone();
+        let params = pry!(req.get());
+        let name = pry!(params.get_name()).to_string();
+        let arg = pry!(converters::map_capnp_err(serde_json::from_str(pry!(params.get_arg()))));
+
+        let f = self
+            .pool
+            .spawn_fn(move || -> Result<serde_json::Value, CapnpError> {
+                converters::map_capnp_err(plugins.call(&*datastore, &name, arg))
+            })
+            .and_then(move |value| -> Result<(), CapnpError> {
+                res.get().set_result(&value.to_string());
+                Ok(())
+            });
+
+        Promise::from_future(f)
+    }
+
+    fn execute_batch(
+        &mut self,
+        req: autogen::service::ExecuteBatchParams,
+        mut res: autogen::service::ExecuteBatchResults,
+    ) -> Promise<(), CapnpError> {
+        let datastore = self.datastore.clone();
+        let idempotency_key_ttl = self.idempotency_key_ttl;
+        let running_idempotency_keys = self.running_idempotency_keys.clone();
+        let params = pry!(req.get());
+        let key = pry!(params.get_idempotency_key()).to_string();
+        let cnp_operations = pry!(params.get_operations());
+        let operations: Result<Vec<indradb::Operation>, CapnpError> = cnp_operations
+            .into_iter()
+            .map(|reader| converters::to_operation(&reader))
+            .collect();
+        let operations = pry!(operations);
+
+        let f = self
+            .pool
+            .spawn_fn(move || -> Result<Vec<OperationOutput>, CapnpError> {
+                if key.is_empty() {
+                    let trans = converters::map_capnp_err(datastore.transaction())?;
+                    return converters::map_capnp_err(trans.execute_batch(operations));
+                }
+
+                // Retries that arrive while the first request is still
+                // running would otherwise miss its record and run again
+                if !running_idempotency_keys.lock().unwrap().insert(key.clone()) {
+                    return Err(CapnpError::failed(format!(
+                        "A request with the idempotency key `{}` is already running",
+                        key
+                    )));
+                }
+
+                let result = execute_batch_once(&*datastore, &key, operations, idempotency_key_ttl);
+                running_idempotency_keys.lock().unwrap().remove(&key);
+                result
+            })
+            .and_then(move |outputs| -> Result<(), CapnpError> {
+                let mut res = res.get().init_result(outputs.len() as u32);
+
+                for (i, output) in outputs.iter().enumerate() {
+                    converters::from_operation_output(output, res.reborrow().get(i as u32))?;
+                }
+
+                Ok(())
+            });
+
+        Promise::from_future(f)
+    }
+
+    fn get_write_pressure(
+        &mut self,
+        _: autogen::service::GetWritePressureParams,
+        mut res: autogen::service::GetWritePressureResults,
+    ) -> Promise<(), CapnpError> {
+        let datastore = self.datastore.clone();
+
+        let f = self
+            .pool
+            .spawn_fn(move || -> Result<indradb::WritePressure, CapnpError> {
+                converters::map_capnp_err(datastore.get_write_pressure())
+            })
+            .and_then(move |pressure| -> Result<(), CapnpError> {
+                converters::from_write_pressure(&pressure, res.get().init_result());
+                Ok(())
+            });
+
+        Promise::from_future(f)
+    }
+}
+
+// Runs a batch in its own transaction and records its response under an
+// idempotency key, or replays the response if one was already recorded.
+fn execute_batch_once<D, T>(
+    datastore: &D,
+    key: &str,
+    operations: Vec<indradb::Operation>,
+    ttl: ChronoDuration,
+) -> Result<Vec<OperationOutput>, CapnpError>
+where
+    D: IndraDbDatastore<Trans = T>,
+    T: IndraDbTransaction,
+{
+    if let Some(response) = converters::map_capnp_err(datastore.get_idempotency_record(key))? {
+        return converters::decode_operation_outputs(&response);
+    }
+
+    let outputs = {
+        // The transaction is committed when it's dropped, before the
+        // response is recorded
+        let trans = converters::map_capnp_err(datastore.transaction())?;
+        converters::map_capnp_err(trans.execute_batch(operations))?
+    };
+
+    let response = converters::encode_operation_outputs(&outputs)?;
+    converters::map_capnp_err(datastore.set_idempotency_record(key, &response, Utc::now() + ttl))?;
+    Ok(outputs)
 }
 
 struct Transaction<T: IndraDbTransaction + Send + Sync + 'static> {
     pool: CpuPool,
     trans: Arc<T>,
+    max_result_size: Option<u32>,
+    memory_budget: Option<MemoryBudget>,
 }
 
 impl<T: IndraDbTransaction + Send + Sync + 'static> Transaction<T> {
-    fn new(pool: CpuPool, trans: T) -> Self {
+    fn new(pool: CpuPool, trans: T, max_result_size: Option<u32>, memory_budget: Option<MemoryBudget>) -> Self {
         Self {
             pool,
             trans: Arc::new(trans),
+            max_result_size,
+            memory_budget,
         }
     }
+
+    // The most results a paged query can return, given the maximum the
+    // client asked for.
+    fn page_size(&self, max_results: u32) -> u32 {
+        match self.max_result_size {
+            Some(max_result_size) => max_results.min(max_result_size),
+            None => max_results,
+        }
+    }
+}
+
+// Query results, along with the memory reserved for them.
+type Reserved<T> = (T, Option<MemoryReservation>);
+
+// Admits a query against the memory budget, if there is one, reserving an
+// estimate for the most results it can return up front. This blocks while
+// the query is queued, so it has to be called from the worker pool.
+fn admit<I>(memory_budget: &Option<MemoryBudget>, max_results: u32) -> Result<Option<MemoryReservation>, CapnpError> {
+    match memory_budget {
+        Some(memory_budget) => Ok(Some(converters::map_capnp_err(
+            memory_budget.admit_for::<I>(max_results),
+        )?)),
+        None => Ok(None),
+    }
+}
+
+// Grows a query's reservation to cover its materialized results, which is
+// held until its response has been built. This fails the query if the
+// results don't fit in the budget.
+fn reserve<I: HeapSize>(reservation: &mut Option<MemoryReservation>, items: &[I]) -> Result<(), CapnpError> {
+    match reservation {
+        Some(reservation) => converters::map_capnp_err(reservation.grow_for(items)),
+        None => Ok(()),
+    }
+}
+
+// The most results a vertex query can return.
+fn vertex_query_limit(q: &indradb::VertexQuery) -> u32 {
+    match q {
+        indradb::VertexQuery::Range(q) => q.limit,
+        indradb::VertexQuery::Specific(q) => q.ids.len() as u32,
+        indradb::VertexQuery::Pipe(q) => q.limit,
+    }
+}
+
+// The most results an edge query can return.
+fn edge_query_limit(q: &indradb::EdgeQuery) -> u32 {
+    match q {
+        indradb::EdgeQuery::Specific(q) => q.keys.len() as u32,
+        indradb::EdgeQuery::Pipe(q) => q.limit,
+    }
+}
+
+// The most results a query can return, given its limit and the server's
+// maximum result size.
+fn result_limit(limit: u32, max_result_size: Option<u32>) -> u32 {
+    match max_result_size {
+        Some(max_result_size) => limit.min(max_result_size),
+        None => limit,
+    }
+}
+
+// Fails queries whose results were cut short by the maximum result size,
+// rather than returning some of them.
+fn check_result_size(truncated: bool, max_result_size: u32) -> Result<(), CapnpError> {
+    if truncated {
+        let err: indradb::Error = indradb::ErrorKind::QueryTooLarge(u64::from(max_result_size)).into();
+        Err(CapnpError::failed(err.to_string()))
+    } else {
+        Ok(())
+    }
 }
 
 impl<T: IndraDbTransaction + Send + Sync + 'static> autogen::transaction::Server for Transaction<T> {
@@ -150,13 +515,28 @@ impl<T: IndraDbTransaction + Send + Sync + 'static> autogen::transaction::Server
         mut res: autogen::transaction::GetVerticesResults,
     ) -> Promise<(), CapnpError> {
         let trans = self.trans.clone();
+        let memory_budget = self.memory_budget.clone();
+        let max_result_size = self.max_result_size;
         let cnp_q = pry!(pry!(req.get()).get_q());
         let q = pry!(converters::to_vertex_query(&cnp_q));
 
         let f = self
             .pool
-            .spawn_fn(move || -> Result<Vec<Vertex>, CapnpError> { converters::map_capnp_err(trans.get_vertices(q)) })
-            .and_then(move |vertices| -> Result<(), CapnpError> {
+            .spawn_fn(move || -> Result<Reserved<Vec<Vertex>>, CapnpError> {
+                let mut reservation =
+                    admit::<Vertex>(&memory_budget, result_limit(vertex_query_limit(&q), max_result_size))?;
+                let vertices = match max_result_size {
+                    Some(max_result_size) => {
+                        let page = converters::map_capnp_err(trans.get_vertices_page(q, max_result_size))?;
+                        check_result_size(page.truncated, max_result_size)?;
+                        page.vertices
+                    }
+                    None => converters::map_capnp_err(trans.get_vertices(q))?,
+                };
+                reserve(&mut reservation, &vertices)?;
+                Ok((vertices, reservation))
+            })
+            .and_then(move |(vertices, _reservation)| -> Result<(), CapnpError> {
                 let mut res = res.get().init_result(vertices.len() as u32);
 
                 for (i, vertex) in vertices.into_iter().enumerate() {
@@ -236,13 +616,28 @@ impl<T: IndraDbTransaction + Send + Sync + 'static> autogen::transaction::Server
         mut res: autogen::transaction::GetEdgesResults,
     ) -> Promise<(), CapnpError> {
         let trans = self.trans.clone();
+        let memory_budget = self.memory_budget.clone();
+        let max_result_size = self.max_result_size;
         let cnp_q = pry!(pry!(req.get()).get_q());
         let q = pry!(converters::to_edge_query(&cnp_q));
 
         let f = self
             .pool
-            .spawn_fn(move || -> Result<Vec<Edge>, CapnpError> { converters::map_capnp_err(trans.get_edges(q)) })
-            .and_then(move |edges| -> Result<(), CapnpError> {
+            .spawn_fn(move || -> Result<Reserved<Vec<Edge>>, CapnpError> {
+                let mut reservation =
+                    admit::<Edge>(&memory_budget, result_limit(edge_query_limit(&q), max_result_size))?;
+                let edges = match max_result_size {
+                    Some(max_result_size) => {
+                        let page = converters::map_capnp_err(trans.get_edges_page(q, max_result_size))?;
+                        check_result_size(page.truncated, max_result_size)?;
+                        page.edges
+                    }
+                    None => converters::map_capnp_err(trans.get_edges(q))?,
+                };
+                reserve(&mut reservation, &edges)?;
+                Ok((edges, reservation))
+            })
+            .and_then(move |(edges, _reservation)| -> Result<(), CapnpError> {
                 let mut res = res.get().init_result(edges.len() as u32);
 
                 for (i, edge) in edges.into_iter().enumerate() {
@@ -311,16 +706,20 @@ impl<T: IndraDbTransaction + Send + Sync + 'static> autogen::transaction::Server
         mut res: autogen::transaction::GetVertexPropertiesResults,
     ) -> Promise<(), CapnpError> {
         let trans = self.trans.clone();
+        let memory_budget = self.memory_budget.clone();
         let params = pry!(req.get());
         let cnp_q = pry!(params.get_q());
         let q = pry!(converters::to_vertex_property_query(&cnp_q));
 
         let f = self
             .pool
-            .spawn_fn(move || -> Result<Vec<VertexProperty>, CapnpError> {
-                converters::map_capnp_err(trans.get_vertex_properties(q))
+            .spawn_fn(move || -> Result<Reserved<Vec<VertexProperty>>, CapnpError> {
+                let mut reservation = admit::<VertexProperty>(&memory_budget, vertex_query_limit(&q.inner))?;
+                let properties = converters::map_capnp_err(trans.get_vertex_properties(q))?;
+                reserve(&mut reservation, &properties)?;
+                Ok((properties, reservation))
             })
-            .and_then(move |properties| -> Result<(), CapnpError> {
+            .and_then(move |(properties, _reservation)| -> Result<(), CapnpError> {
                 let mut res = res.get().init_result(properties.len() as u32);
 
                 for (i, property) in properties.into_iter().enumerate() {
@@ -387,16 +786,20 @@ impl<T: IndraDbTransaction + Send + Sync + 'static> autogen::transaction::Server
         mut res: autogen::transaction::GetEdgePropertiesResults,
     ) -> Promise<(), CapnpError> {
         let trans = self.trans.clone();
+        let memory_budget = self.memory_budget.clone();
         let params = pry!(req.get());
         let cnp_q = pry!(params.get_q());
         let q = pry!(converters::to_edge_property_query(&cnp_q));
 
         let f = self
             .pool
-            .spawn_fn(move || -> Result<Vec<EdgeProperty>, CapnpError> {
-                converters::map_capnp_err(trans.get_edge_properties(q))
+            .spawn_fn(move || -> Result<Reserved<Vec<EdgeProperty>>, CapnpError> {
+                let mut reservation = admit::<EdgeProperty>(&memory_budget, edge_query_limit(&q.inner))?;
+                let properties = converters::map_capnp_err(trans.get_edge_properties(q))?;
+                reserve(&mut reservation, &properties)?;
+                Ok((properties, reservation))
             })
-            .and_then(move |properties| -> Result<(), CapnpError> {
+            .and_then(move |(properties, _reservation)| -> Result<(), CapnpError> {
                 let mut res = res.get().init_result(properties.len() as u32);
 
                 for (i, property) in properties.into_iter().enumerate() {
@@ -454,18 +857,274 @@ impl<T: IndraDbTransaction + Send + Sync + 'static> autogen::transaction::Server
 
         Promise::from_future(f)
     }
+
+    fn get_all_vertex_properties(
+        &mut self,
+        req: autogen::transaction::GetAllVertexPropertiesParams,
+        mut res: autogen::transaction::GetAllVertexPropertiesResults,
+    ) -> Promise<(), CapnpError> {
+        let trans = self.trans.clone();
+        let memory_budget = self.memory_budget.clone();
+        let cnp_q = pry!(pry!(req.get()).get_q());
+        let q = pry!(converters::to_vertex_query(&cnp_q));
+
+        let f = self
+            .pool
+            .spawn_fn(move || -> Result<Reserved<Vec<VertexProperties>>, CapnpError> {
+                let mut reservation = admit::<VertexProperties>(&memory_budget, vertex_query_limit(&q))?;
+                let properties = converters::map_capnp_err(trans.get_all_vertex_properties(q))?;
+                reserve(&mut reservation, &properties)?;
+                Ok((properties, reservation))
+            })
+            .and_then(move |(properties, _reservation)| -> Result<(), CapnpError> {
+                let mut res = res.get().init_result(properties.len() as u32);
+
+                for (i, properties) in properties.into_iter().enumerate() {
+                    converters::from_vertex_properties(&properties, res.reborrow().get(i as u32));
+                }
+
+                Ok(())
+            });
+
+        Promise::from_future(f)
+    }
+
+    fn get_all_edge_properties(
+        &mut self,
+        req: autogen::transaction::GetAllEdgePropertiesParams,
+        mut res: autogen::transaction::GetAllEdgePropertiesResults,
+    ) -> Promise<(), CapnpError> {
+        let trans = self.trans.clone();
+        let memory_budget = self.memory_budget.clone();
+        let cnp_q = pry!(pry!(req.get()).get_q());
+        let q = pry!(converters::to_edge_query(&cnp_q));
+
+        let f = self
+            .pool
+            .spawn_fn(move || -> Result<Reserved<Vec<EdgeProperties>>, CapnpError> {
+                let mut reservation = admit::<EdgeProperties>(&memory_budget, edge_query_limit(&q))?;
+                let properties = converters::map_capnp_err(trans.get_all_edge_properties(q))?;
+                reserve(&mut reservation, &properties)?;
+                Ok((properties, reservation))
+            })
+            .and_then(move |(properties, _reservation)| -> Result<(), CapnpError> {
+                let mut res = res.get().init_result(properties.len() as u32);
+
+                for (i, properties) in properties.into_iter().enumerate() {
+                    converters::from_edge_properties(&properties, res.reborrow().get(i as u32))?;
+                }
+
+                Ok(())
+            });
+
+        Promise::from_future(f)
+    }
+
+    fn execute_batch(
+        &mut self,
+        req: autogen::transaction::ExecuteBatchParams,
+        mut res: autogen::transaction::ExecuteBatchResults,
+    ) -> Promise<(), CapnpError> {
+        let trans = self.trans.clone();
+        let cnp_operations = pry!(pry!(req.get()).get_operations());
+        let operations: Result<Vec<indradb::Operation>, CapnpError> = cnp_operations
+            .into_iter()
+            .map(|reader| converters::to_operation(&reader))
+            .collect();
+        let operations = pry!(operations);
+
+        let f = self
+            .pool
+            .spawn_fn(move || -> Result<Vec<OperationOutput>, CapnpError> {
+                converters::map_capnp_err(trans.execute_batch(operations))
+            })
+            .and_then(move |outputs| -> Result<(), CapnpError> {
+                let mut res = res.get().init_result(outputs.len() as u32);
+
+                for (i, output) in outputs.iter().enumerate() {
+                    converters::from_operation_output(output, res.reborrow().get(i as u32))?;
+                }
+
+                Ok(())
+            });
+
+        Promise::from_future(f)
+    }
+
+    fn delete_vertices_in_batches(
+        &mut self,
+        req: autogen::transaction::DeleteVerticesInBatchesParams,
+        mut res: autogen::transaction::DeleteVerticesInBatchesResults,
+    ) -> Promise<(), CapnpError> {
+        let trans = self.trans.clone();
+        let params = pry!(req.get());
+        let cnp_q = pry!(params.get_q());
+        let q = pry!(converters::to_vertex_query(&cnp_q));
+        let batch_size = params.get_batch_size();
+        let listener = pry!(params.get_listener());
+        let (sender, receiver) = unbounded::<BulkDeleteProgress>();
+
+        let deleted = self.pool.spawn_fn(move || -> Result<u64, CapnpError> {
+            converters::map_capnp_err(trans.delete_vertices_in_batches(q, batch_size, |progress| {
+                let _ = sender.unbounded_send(progress);
+            }))
+        });
+
+        let f = forward_bulk_delete_progress(receiver, listener).join(deleted).and_then(
+            move |(_, count)| -> Result<(), CapnpError> {
+                res.get().set_result(count);
+                Ok(())
+            },
+        );
+
+        Promise::from_future(f)
+    }
+
+    fn delete_edges_in_batches(
+        &mut self,
+        req: autogen::transaction::DeleteEdgesInBatchesParams,
+        mut res: autogen::transaction::DeleteEdgesInBatchesResults,
+    ) -> Promise<(), CapnpError> {
+        let trans = self.trans.clone();
+        let params = pry!(req.get());
+        let cnp_q = pry!(params.get_q());
+        let q = pry!(converters::to_edge_query(&cnp_q));
+        let batch_size = params.get_batch_size();
+        let listener = pry!(params.get_listener());
+        let (sender, receiver) = unbounded::<BulkDeleteProgress>();
+
+        let deleted = self.pool.spawn_fn(move || -> Result<u64, CapnpError> {
+            converters::map_capnp_err(trans.delete_edges_in_batches(q, batch_size, |progress| {
+                let _ = sender.unbounded_send(progress);
+            }))
+        });
+
+        let f = forward_bulk_delete_progress(receiver, listener).join(deleted).and_then(
+            move |(_, count)| -> Result<(), CapnpError> {
+                res.get().set_result(count);
+                Ok(())
+            },
+        );
+
+        Promise::from_future(f)
+    }
+
+    fn get_vertices_page(
+        &mut self,
+        req: autogen::transaction::GetVerticesPageParams,
+        mut res: autogen::transaction::GetVerticesPageResults,
+    ) -> Promise<(), CapnpError> {
+        let trans = self.trans.clone();
+        let memory_budget = self.memory_budget.clone();
+        let params = pry!(req.get());
+        let q = pry!(converters::to_vertex_query(&pry!(params.get_q())));
+        let max_results = self.page_size(params.get_max_results());
+
+        let f = self
+            .pool
+            .spawn_fn(move || -> Result<Reserved<indradb::VertexPage>, CapnpError> {
+                let mut reservation = admit::<Vertex>(&memory_budget, vertex_query_limit(&q).min(max_results))?;
+                let page = converters::map_capnp_err(trans.get_vertices_page(q, max_results))?;
+                reserve(&mut reservation, &page.vertices)?;
+                Ok((page, reservation))
+            })
+            .and_then(move |(page, _reservation)| -> Result<(), CapnpError> {
+                converters::from_vertex_page(&page, res.get().init_result());
+                Ok(())
+            });
+
+        Promise::from_future(f)
+    }
+
+    fn get_edges_page(
+        &mut self,
+        req: autogen::transaction::GetEdgesPageParams,
+        mut res: autogen::transaction::GetEdgesPageResults,
+    ) -> Promise<(), CapnpError> {
+        let trans = self.trans.clone();
+        let memory_budget = self.memory_budget.clone();
+        let params = pry!(req.get());
+        let q = pry!(converters::to_edge_query(&pry!(params.get_q())));
+        let max_results = self.page_size(params.get_max_results());
+
+        let f = self
+            .pool
+            .spawn_fn(move || -> Result<Reserved<indradb::EdgePage>, CapnpError> {
+                let mut reservation = admit::<Edge>(&memory_budget, edge_query_limit(&q).min(max_results))?;
+                let page = converters::map_capnp_err(trans.get_edges_page(q, max_results))?;
+                reserve(&mut reservation, &page.edges)?;
+                Ok((page, reservation))
+            })
+            .and_then(move |(page, _reservation)| -> Result<(), CapnpError> {
+                converters::from_edge_page(&page, res.get().init_result())?;
+                Ok(())
+            });
+
+        Promise::from_future(f)
+    }
+}
+
+// Calls a bulk delete's listener with each progress update, waiting for each
+// call to finish before making the next, so that the updates arrive in order
+// and before the delete's response. The channel closes once the delete
+// finishes. Failed calls are ignored, since the delete carries on anyway.
+fn forward_bulk_delete_progress(
+    receiver: UnboundedReceiver<BulkDeleteProgress>,
+    listener: autogen::bulk_delete_listener::Client,
+) -> Box<Future<Item = (), Error = CapnpError>> {
+    let f = receiver
+        .map_err(|_| CapnpError::failed("Bulk delete progress channel failed".to_string()))
+        .for_each(move |progress| {
+            let mut req = listener.on_progress_request();
+            req.get().set_deleted_count(progress.deleted_count);
+            req.get().set_total_count(progress.total_count);
+            req.send().promise.then(|_| -> Result<(), CapnpError> { Ok(()) })
+        });
+
+    Box::new(f)
 }
 
-fn run<D, T>(addr: SocketAddr, datastore: D, worker_count: usize) -> Result<(), errors::Error>
+fn run<D, T>(
+    addr: SocketAddr,
+    websocket_addr: Option<SocketAddr>,
+    visualization_addr: Option<SocketAddr>,
+    scripts: Scripts,
+    plugins: Plugins,
+    idempotency_key_ttl: ChronoDuration,
+    max_result_size: Option<u32>,
+    memory_budget: Option<MemoryBudget>,
+    datastore: D,
+    worker_count: usize,
+) -> Result<(), errors::Error>
 where
     D: IndraDbDatastore<Trans = T> + Send + Sync + 'static,
     T: IndraDbTransaction + Send + Sync + 'static,
 {
+    let datastore = Arc::new(datastore);
+
+    if let Some(websocket_addr) = websocket_addr {
+        websocket::start(websocket_addr, datastore.clone())?;
+    }
+
+    if let Some(visualization_addr) = visualization_addr {
+        visualize::start(visualization_addr, datastore.clone())?;
+    }
+
     let mut core = Core::new().unwrap();
     let handle = core.handle();
     let socket = TcpListener::bind(&addr, &handle)?;
 
-    let service = autogen::service::ToClient::new(Service::new(datastore, worker_count)).into_client::<Server>();
+    let service = autogen::service::ToClient::new(Service::new(
+        datastore,
+        worker_count,
+        handle.clone(),
+        scripts,
+        plugins,
+        idempotency_key_ttl,
+        max_result_size,
+        memory_budget,
+    ))
+    .into_client::<Server>();
 
     let done = socket.incoming().for_each(move |(socket, _)| {
         socket.set_nodelay(true)?;
@@ -480,12 +1139,91 @@ where
     Ok(())
 }
 
+// Parses an optional numeric RocksDB setting, which is 0 (i.e. the default)
+// if it isn't set.
+fn parse_rocksdb_env_var<T: FromStr + Default>(name: &str) -> T {
+    match env::var(name) {
+        Ok(value) => value
+            .parse::<T>()
+            .unwrap_or_else(|_| panic!("Could not parse environment variable `{}`: must be a number", name)),
+        Err(_) => T::default(),
+    }
+}
+
 pub fn start(binding: &str, connection_string: &str, worker_count: usize) -> Result<(), errors::Error> {
     let addr = binding
         .to_socket_addrs()?
         .next()
         .ok_or_else(|| -> errors::Error { "Could not parse binding".into() })?;
 
+    let websocket_addr = match env::var("WEBSOCKET_PORT") {
+        Ok(value) => {
+            let port = value
+                .parse::<u16>()
+                .expect("Could not parse environment variable `WEBSOCKET_PORT`");
+            Some(SocketAddr::new(addr.ip(), port))
+        }
+        Err(_) => None,
+    };
+
+    let visualization_addr = match env::var("VISUALIZATION_PORT") {
+        Ok(value) => {
+            let port = value
+                .parse::<u16>()
+                .expect("Could not parse environment variable `VISUALIZATION_PORT`");
+            Some(SocketAddr::new(addr.ip(), port))
+        }
+        Err(_) => None,
+    };
+
+    let script_max_operations = match env::var("SCRIPT_MAX_OPERATIONS") {
+        Ok(value) => value
+            .parse::<u64>()
+            .expect("Could not parse environment variable `SCRIPT_MAX_OPERATIONS`"),
+        Err(_) => DEFAULT_MAX_OPERATIONS,
+    };
+
+    let scripts = Scripts::new(script_max_operations);
+
+    let plugins = match env::var("PLUGIN_PATH") {
+        Ok(value) => Plugins::load(value)?,
+        Err(_) => Plugins::empty(),
+    };
+
+    let idempotency_key_ttl = match env::var("IDEMPOTENCY_KEY_TTL_SECS") {
+        Ok(value) => ChronoDuration::seconds(
+            value
+                .parse::<i64>()
+                .expect("Could not parse environment variable `IDEMPOTENCY_KEY_TTL_SECS`"),
+        ),
+        Err(_) => ChronoDuration::seconds(DEFAULT_IDEMPOTENCY_KEY_TTL_SECS),
+    };
+
+    let max_result_size = match env::var("MAX_RESULT_SIZE") {
+        Ok(value) => match value.parse::<u32>() {
+            Ok(size) if size > 0 => Some(size),
+            _ => panic!("Could not parse environment variable `MAX_RESULT_SIZE`: must be a positive number"),
+        },
+        Err(_) => None,
+    };
+
+    let memory_budget = match env::var("MEMORY_BUDGET_BYTES") {
+        Ok(value) => {
+            let budget_bytes = value
+                .parse::<usize>()
+                .expect("Could not parse environment variable `MEMORY_BUDGET_BYTES`");
+            let queue_timeout_ms = match env::var("MEMORY_BUDGET_QUEUE_TIMEOUT_MS") {
+                Ok(value) => value
+                    .parse::<u64>()
+                    .expect("Could not parse environment variable `MEMORY_BUDGET_QUEUE_TIMEOUT_MS`"),
+                Err(_) => 0,
+            };
+            let options = AdmissionOptions::new(budget_bytes).queue_timeout(Duration::from_millis(queue_timeout_ms));
+            Some(MemoryBudget::new(options))
+        }
+        Err(_) => None,
+    };
+
     if connection_string.starts_with("rocksdb://") {
         let path = &connection_string[10..connection_string.len()];
 
@@ -497,13 +1235,41 @@ pub fn start(binding: &str, connection_string: &str, worker_count: usize) -> Res
 
         let bulk_load_optimized = env::var("ROCKSDB_BULK_LOAD_OPTIMIZED").unwrap_or_else(|_| "".to_string()) == "true";
 
-        let datastore = RocksdbDatastore::new(path, Some(max_open_files), bulk_load_optimized)
-            .expect("Expected to be able to create the RocksDB datastore");
-
-        run(addr, datastore, worker_count)
+        let resources = ResourceOptions::new()
+            .rate_limit_bytes_per_sec(parse_rocksdb_env_var("ROCKSDB_RATE_LIMIT_BYTES_PER_SEC"))
+            .compaction_threads(parse_rocksdb_env_var("ROCKSDB_COMPACTION_THREADS"))
+            .flush_threads(parse_rocksdb_env_var("ROCKSDB_FLUSH_THREADS"));
+
+        let datastore =
+            RocksdbDatastore::new_with_resources(path, Some(max_open_files), bulk_load_optimized, resources)
+                .expect("Expected to be able to create the RocksDB datastore");
+
+        run(
+            addr,
+            websocket_addr,
+            visualization_addr,
+            scripts,
+            plugins,
+            idempotency_key_ttl,
+            max_result_size,
+            memory_budget,
+            datastore,
+            worker_count,
+        )
     } else if connection_string == "memory://" {
         let datastore = MemoryDatastore::default();
-        run(addr, datastore, worker_count)
+        run(
+            addr,
+            websocket_addr,
+            visualization_addr,
+            scripts,
+            plugins,
+            idempotency_key_ttl,
+            max_result_size,
+            memory_budget,
+            datastore,
+            worker_count,
+        )
     } else {
         panic!("Cannot parse environment variable `DATABASE_URL`");
     }
@@ -0,0 +1,345 @@
+//! An interactive shell for working with a datastore, started with
+//! `indradb shell`.
+
+use client_datastore::ClientDatastore;
+use errors;
+use indradb::{
+    Datastore as IndraDbDatastore, EdgeDirection, EdgeKey, EdgeQueryExt, MemoryDatastore, RangeVertexQuery,
+    RocksdbDatastore, SpecificEdgeQuery, SpecificVertexQuery, Transaction as IndraDbTransaction, Type, VertexQueryExt,
+};
+use serde_json;
+use serde_json::Value as JsonValue;
+use std::io;
+use std::io::{BufRead, Write};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::str::FromStr;
+use uuid::Uuid;
+
+// The limit used for listing commands that don't specify one.
+const DEFAULT_LIMIT: u32 = 100;
+
+const HELP: &str = "Commands:
+  create-vertex <type>
+  get-vertex <id>
+  list-vertices [limit] [type]
+  delete-vertex <id>
+  count-vertices
+  create-edge <outbound id> <type> <inbound id>
+  get-edges <id> [outbound|inbound|both] [type] [limit]
+  delete-edge <outbound id> <type> <inbound id>
+  count-edges <id> [outbound|inbound|both] [type]
+  get-vertex-property <id> <name>
+  set-vertex-property <id> <name> <json>
+  delete-vertex-property <id> <name>
+  get-vertex-properties <id>
+  get-edge-property <outbound id> <type> <inbound id> <name>
+  set-edge-property <outbound id> <type> <inbound id> <name> <json>
+  delete-edge-property <outbound id> <type> <inbound id> <name>
+  get-edge-properties <outbound id> <type> <inbound id>
+  help
+  exit";
+
+/// Starts a shell on stdin and stdout.
+///
+/// # Arguments
+/// * `connection_string` - What to connect to: `memory://`,
+///   `rocksdb://<path>`, or `server://<host>:<port>` for a server. For a
+///   server running locally, `server://<port>` is a shorthand for
+///   `server://127.0.0.1:<port>`.
+pub fn start(connection_string: &str) -> Result<(), errors::Error> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+
+    if connection_string.starts_with("rocksdb://") {
+        let path = &connection_string[10..connection_string.len()];
+        let datastore =
+            RocksdbDatastore::new(path, None, false).map_err(|err| -> errors::Error { format!("{}", err).into() })?;
+        run(&datastore, stdin.lock(), stdout.lock())
+    } else if connection_string.starts_with("server://") {
+        let addr = to_server_addr(&connection_string[9..connection_string.len()])?;
+        run(&ClientDatastore::connect(addr), stdin.lock(), stdout.lock())
+    } else if connection_string == "memory://" {
+        run(&MemoryDatastore::default(), stdin.lock(), stdout.lock())
+    } else {
+        Err(format!("Cannot parse connection string `{}`", connection_string).into())
+    }
+}
+
+// Parses the address of a server from `<host>:<port>`, or just `<port>` for
+// a server running locally.
+fn to_server_addr(s: &str) -> Result<SocketAddr, errors::Error> {
+    let (host, port) = match s.rfind(':') {
+        // IPv6 hosts are bracketed, to separate them from the port
+        Some(i) => (s[..i].trim_start_matches('[').trim_end_matches(']'), &s[i + 1..]),
+        None => ("127.0.0.1", s),
+    };
+
+    let port = port
+        .parse::<u16>()
+        .map_err(|_| -> errors::Error { "Could not parse the server port".into() })?;
+
+    (host, port)
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .ok_or_else(|| format!("Could not resolve the server host `{}`", host).into())
+}
+
+/// Runs a shell until the input ends or `exit` is entered.
+///
+/// # Arguments
+/// * `datastore` - The datastore to work with.
+/// * `input` - Where to read commands from.
+/// * `output` - Where to write prompts and results to.
+pub fn run<D, T, R, W>(datastore: &D, input: R, mut output: W) -> Result<(), errors::Error>
+where
+    D: IndraDbDatastore<Trans = T>,
+    T: IndraDbTransaction,
+    R: BufRead,
+    W: Write,
+{
+    let trans = datastore
+        .transaction()
+        .map_err(|err| -> errors::Error { format!("{}", err).into() })?;
+    let mut lines = input.lines();
+
+    loop {
+        write!(output, "indradb> ")?;
+        output.flush()?;
+
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => break,
+        };
+
+        let args: Vec<&str> = line.split_whitespace().collect();
+
+        match args.first() {
+            None => continue,
+            Some(&"exit") => break,
+            Some(&"help") => writeln!(output, "{}", HELP)?,
+            Some(_) => match execute(&trans, &args) {
+                Ok(result) => writeln!(output, "{}", result)?,
+                Err(err) => writeln!(output, "error: {}", err)?,
+            },
+        }
+    }
+
+    Ok(())
+}
+
+fn execute<T: IndraDbTransaction>(trans: &T, args: &[&str]) -> Result<String, errors::Error> {
+    let result = match (args[0], &args[1..]) {
+        ("create-vertex", &[t]) => trans.create_vertex_from_type(to_type(t)?).map(|id| id.to_string()),
+        ("get-vertex", &[id]) => trans
+            .get_vertices(SpecificVertexQuery::single(to_uuid(id)?))
+            .map(|vertices| format_vertices(&vertices)),
+        ("list-vertices", rest) if rest.len() <= 2 => {
+            let limit = match rest.first() {
+                Some(limit) => to_limit(limit)?,
+                None => DEFAULT_LIMIT,
+            };
+
+            let q = match rest.get(1) {
+                Some(t) => RangeVertexQuery::new(limit).t(to_type(t)?),
+                None => RangeVertexQuery::new(limit),
+            };
+
+            trans.get_vertices(q).map(|vertices| format_vertices(&vertices))
+        }
+        ("delete-vertex", &[id]) => trans
+            .delete_vertices(SpecificVertexQuery::single(to_uuid(id)?))
+            .map(|_| "ok".to_string()),
+        ("count-vertices", &[]) => trans.get_vertex_count().map(|count| count.to_string()),
+        ("create-edge", &[outbound_id, t, inbound_id]) => trans
+            .create_edge(&to_edge_key(outbound_id, t, inbound_id)?)
+            .map(|created| created.to_string()),
+        ("get-edges", rest) if !rest.is_empty() && rest.len() <= 4 => {
+            let q = SpecificVertexQuery::single(to_uuid(rest[0])?);
+
+            let limit = match rest.get(3) {
+                Some(limit) => to_limit(limit)?,
+                None => DEFAULT_LIMIT,
+            };
+
+            let q = match to_direction(rest.get(1))? {
+                EdgeDirection::Outbound => q.outbound(limit),
+                EdgeDirection::Inbound => q.inbound(limit),
+                EdgeDirection::Both => q.both(limit),
+            };
+
+            let q = match rest.get(2) {
+                Some(t) => q.t(to_type(t)?),
+                None => q,
+            };
+
+            trans.get_edges(q).map(|edges| {
+                edges
+                    .iter()
+                    .map(|edge| format!("{}\t{}", format_edge_key(&edge.key), edge.created_datetime.to_rfc3339()))
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            })
+        }
+        ("delete-edge", &[outbound_id, t, inbound_id]) => trans
+            .delete_edges(SpecificEdgeQuery::single(to_edge_key(outbound_id, t, inbound_id)?))
+            .map(|_| "ok".to_string()),
+        ("count-edges", rest) if !rest.is_empty() && rest.len() <= 3 => {
+            let t = match rest.get(2) {
+                Some(t) => Some(to_type(t)?),
+                None => None,
+            };
+
+            trans
+                .get_edge_count(to_uuid(rest[0])?, t.as_ref(), to_direction(rest.get(1))?)
+                .map(|count| count.to_string())
+        }
+        ("get-vertex-property", &[id, name]) => trans
+            .get_vertex_properties(SpecificVertexQuery::single(to_uuid(id)?).property(name))
+            .map(|properties| match properties.into_iter().next() {
+                Some(property) => format_json(&property.value),
+                None => "null".to_string(),
+            }),
+        ("set-vertex-property", &[id, name, ..]) => {
+            let value = to_json(&args[3..])?;
+            trans
+                .set_vertex_properties(SpecificVertexQuery::single(to_uuid(id)?).property(name), &value)
+                .map(|_| "ok".to_string())
+        }
+        ("delete-vertex-property", &[id, name]) => trans
+            .delete_vertex_properties(SpecificVertexQuery::single(to_uuid(id)?).property(name))
+            .map(|_| "ok".to_string()),
+        ("get-vertex-properties", &[id]) => trans
+            .get_all_vertex_properties(SpecificVertexQuery::single(to_uuid(id)?))
+            .map(|properties| match properties.into_iter().next() {
+                Some(properties) => properties
+                    .props
+                    .iter()
+                    .map(|property| format!("{}\t{}", property.name, property.value))
+                    .collect::<Vec<String>>()
+                    .join("\n"),
+                None => "".to_string(),
+            }),
+        ("get-edge-property", &[outbound_id, t, inbound_id, name]) => trans
+            .get_edge_properties(SpecificEdgeQuery::single(to_edge_key(outbound_id, t, inbound_id)?).property(name))
+            .map(|properties| match properties.into_iter().next() {
+                Some(property) => format_json(&property.value),
+                None => "null".to_string(),
+            }),
+        ("set-edge-property", &[outbound_id, t, inbound_id, name, ..]) => {
+            let value = to_json(&args[5..])?;
+            let q = SpecificEdgeQuery::single(to_edge_key(outbound_id, t, inbound_id)?).property(name);
+            trans.set_edge_properties(q, &value).map(|_| "ok".to_string())
+        }
+        ("delete-edge-property", &[outbound_id, t, inbound_id, name]) => trans
+            .delete_edge_properties(SpecificEdgeQuery::single(to_edge_key(outbound_id, t, inbound_id)?).property(name))
+            .map(|_| "ok".to_string()),
+        ("get-edge-properties", &[outbound_id, t, inbound_id]) => trans
+            .get_all_edge_properties(SpecificEdgeQuery::single(to_edge_key(outbound_id, t, inbound_id)?))
+            .map(|properties| match properties.into_iter().next() {
+                Some(properties) => properties
+                    .props
+                    .iter()
+                    .map(|property| format!("{}\t{}", property.name, property.value))
+                    .collect::<Vec<String>>()
+                    .join("\n"),
+                None => "".to_string(),
+            }),
+        _ => return Err(format!("Unknown command or wrong arguments: `{}`; try `help`", args.join(" ")).into()),
+    };
+
+    result.map_err(|err| format!("{}", err).into())
+}
+
+fn to_uuid(id: &str) -> Result<Uuid, errors::Error> {
+    Uuid::from_str(id).map_err(|_| format!("Invalid id `{}`", id).into())
+}
+
+fn to_type(t: &str) -> Result<Type, errors::Error> {
+    Type::new(t).map_err(|_| format!("Invalid type `{}`", t).into())
+}
+
+fn to_limit(limit: &str) -> Result<u32, errors::Error> {
+    limit.parse().map_err(|_| format!("Invalid limit `{}`", limit).into())
+}
+
+fn to_direction(direction: Option<&&str>) -> Result<EdgeDirection, errors::Error> {
+    match direction {
+        Some(direction) => {
+            EdgeDirection::from_str(direction).map_err(|_| format!("Invalid direction `{}`", direction).into())
+        }
+        None => Ok(EdgeDirection::Outbound),
+    }
+}
+
+fn to_edge_key(outbound_id: &str, t: &str, inbound_id: &str) -> Result<EdgeKey, errors::Error> {
+    Ok(EdgeKey::new(to_uuid(outbound_id)?, to_type(t)?, to_uuid(inbound_id)?))
+}
+
+// JSON values may contain spaces, which split them into several arguments.
+fn to_json(args: &[&str]) -> Result<JsonValue, errors::Error> {
+    serde_json::from_str(&args.join(" ")).map_err(|err| format!("Invalid JSON: {}", err).into())
+}
+
+fn format_vertices(vertices: &[::indradb::Vertex]) -> String {
+    vertices
+        .iter()
+        .map(|vertex| format!("{}\t{}", vertex.id, vertex.t.0))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn format_edge_key(key: &EdgeKey) -> String {
+    format!("{}\t{}\t{}", key.outbound_id, key.t.0, key.inbound_id)
+}
+
+fn format_json(value: &JsonValue) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run, to_server_addr};
+    use indradb::MemoryDatastore;
+
+    fn run_commands(datastore: &MemoryDatastore, commands: &str) -> Vec<String> {
+        let mut output = Vec::new();
+        run(datastore, commands.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output)
+            .unwrap()
+            .split("indradb> ")
+            .skip(1)
+            .map(|result| result.trim_end().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn should_run_commands() {
+        let datastore = MemoryDatastore::default();
+        let results = run_commands(&datastore, "create-vertex foo\ncreate-vertex foo\n");
+        let (outbound_id, inbound_id) = (&results[0], &results[1]);
+
+        let commands = format!(
+            "create-edge {0} bar {1}\ncount-edges {0}\nset-vertex-property {0} baz {{\"a\": 1}}\nget-vertex-property {0} baz\ncount-vertices\nfrobnicate\nexit\ncount-vertices\n",
+            outbound_id, inbound_id
+        );
+
+        let results = run_commands(&datastore, &commands);
+        assert_eq!(results[0], "true");
+        assert_eq!(results[1], "1");
+        assert_eq!(results[2], "ok");
+        assert_eq!(results[3], "{\n  \"a\": 1\n}");
+        assert_eq!(results[4], "2");
+        assert!(results[5].starts_with("error: Unknown command"));
+        assert_eq!(results.len(), 7);
+    }
+
+    #[test]
+    fn should_parse_server_addrs() {
+        assert_eq!(to_server_addr("8000").unwrap().to_string(), "127.0.0.1:8000");
+        assert_eq!(to_server_addr("10.0.0.2:8000").unwrap().to_string(), "10.0.0.2:8000");
+        assert_eq!(to_server_addr("[::1]:8000").unwrap().to_string(), "[::1]:8000");
+        assert!(to_server_addr("10.0.0.2:foo").is_err());
+        assert!(to_server_addr("").is_err());
+    }
+}
@@ -1,6 +1,6 @@
 use client_datastore::ClientDatastore;
 use indradb::util::generate_temporary_path;
-use indradb::{Datastore, Transaction};
+use indradb::{Datastore, Operation, OperationOutput, Transaction, Type};
 use server;
 use std::panic::catch_unwind;
 use std::sync::atomic::AtomicUsize;
@@ -36,6 +36,29 @@ fn should_create_rocksdb_datastore() {
     assert_eq!(count, 0);
 }
 
+#[test]
+fn should_execute_batches_idempotently() {
+    let port = (*CURRENT_PORT).fetch_add(1, Ordering::SeqCst);
+    spawn(move || server::start(&format!("127.0.0.1:{}", port), "memory://", 1));
+    let datastore = ClientDatastore::new(port as u16);
+
+    let create = || vec![Operation::CreateVertexFromType(Type::new("foo").unwrap())];
+    let id = match datastore.execute_batch("create-foo", create()).unwrap()[0] {
+        OperationOutput::Id(id) => id,
+        ref output => panic!("Unexpected output: {:?}", output),
+    };
+
+    // Retrying replays the response instead of creating another vertex
+    match datastore.execute_batch("create-foo", create()).unwrap()[0] {
+        OperationOutput::Id(replayed_id) => assert_eq!(replayed_id, id),
+        ref output => panic!("Unexpected output: {:?}", output),
+    }
+
+    datastore.execute_batch("", create()).unwrap();
+    let trans = datastore.transaction().unwrap();
+    assert_eq!(trans.get_vertex_count().unwrap(), 2);
+}
+
 #[test]
 fn should_panic_on_bad_connection_string() {
     let result = catch_unwind(|| server::start("127.0.0.1:9999", "foo://", 1));
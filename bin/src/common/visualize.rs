@@ -0,0 +1,386 @@
+//! An HTTP endpoint that serves an interactive, force-directed view of part
+//! of the graph, for debugging without wiring up external visualization. It
+//! is an admin tool: there's no authentication, and it's meant for small
+//! subgraphs.
+//!
+//! `GET /` serves the viewer, a single static page. It fetches the subgraph
+//! to draw from `GET /graph`, which returns it as JSON, e.g.
+//! `{"vertices": [{"id": "...", "t": "user"}], "edges": [{"outbound_id": "...", "t": "follows", "inbound_id": "..."}]}`.
+//! `/graph` takes these query parameters:
+//!
+//! * `id`: If set, returns the ego network of this vertex: the vertices
+//!   within `depth` hops of it, along with the edges between them.
+//! * `depth`: How many hops to take from `id`. Defaults to 1.
+//! * `t`: If `id` isn't set, returns the result of a range query over
+//!   vertices of this type, along with the edges between them. If neither
+//!   is set, the range query is over all vertices.
+//! * `limit`: The maximum number of vertices to return. Defaults to 100.
+
+use errors;
+use indradb::{
+    Datastore as IndraDbDatastore, EdgeKey, RangeVertexQuery, SpecificVertexQuery, Transaction as IndraDbTransaction,
+    Type, Vertex, VertexQueryExt,
+};
+use serde_json::{Map, Value as JsonValue};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::str;
+use std::sync::Arc;
+use std::thread;
+use uuid::Uuid;
+
+// The page that draws the graph.
+const VIEWER_HTML: &str = include_str!("visualize.html");
+
+// The vertex limit used for requests that don't specify one.
+const DEFAULT_LIMIT: u32 = 100;
+
+// The most vertices a single request may return, since the viewer lays out
+// every vertex in the browser.
+const MAX_LIMIT: u32 = 10_000;
+
+// The depth used for ego network requests that don't specify one.
+const DEFAULT_DEPTH: u32 = 1;
+
+// The most hops an ego network request may take.
+const MAX_DEPTH: u32 = 5;
+
+/// Starts serving the visualization endpoint on a background thread.
+///
+/// # Arguments
+/// * `addr` - The address to listen on.
+/// * `datastore` - The datastore to query.
+pub fn start<D, T>(addr: SocketAddr, datastore: Arc<D>) -> Result<(), errors::Error>
+where
+    D: IndraDbDatastore<Trans = T> + Send + Sync + 'static,
+    T: IndraDbTransaction + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind(&addr)?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            if let Ok(stream) = stream {
+                let datastore = datastore.clone();
+                // Errors only affect their own connection, which is closed
+                // when the thread exits.
+                thread::spawn(move || serve(stream, &*datastore));
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn serve<D, T>(mut stream: TcpStream, datastore: &D) -> Result<(), errors::Error>
+where
+    D: IndraDbDatastore<Trans = T>,
+    T: IndraDbTransaction,
+{
+    let mut request_line = String::new();
+    let mut reader = BufReader::new(stream.try_clone()?);
+    reader.read_line(&mut request_line)?;
+
+    // Skip the headers; nothing in them is needed.
+    loop {
+        let mut header = String::new();
+
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let target = parts.next().unwrap_or("");
+
+    let (path, query) = match target.find('?') {
+        Some(i) => (&target[..i], &target[i + 1..]),
+        None => (target, ""),
+    };
+
+    if method != "GET" {
+        return respond(
+            &mut stream,
+            "405 Method Not Allowed",
+            "text/plain",
+            "method not allowed",
+        );
+    }
+
+    match path {
+        "/" => respond(&mut stream, "200 OK", "text/html; charset=utf-8", VIEWER_HTML),
+        "/graph" => match graph(datastore, &parse_query(query)) {
+            Ok(value) => respond(&mut stream, "200 OK", "application/json", &value.to_string()),
+            Err(err) => {
+                let mut message = Map::new();
+                message.insert("error".to_string(), JsonValue::String(format!("{}", err)));
+                let body = JsonValue::Object(message).to_string();
+                respond(&mut stream, "400 Bad Request", "application/json", &body)
+            }
+        },
+        _ => respond(&mut stream, "404 Not Found", "text/plain", "not found"),
+    }
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &str) -> Result<(), errors::Error> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Gets the subgraph described by the `/graph` query parameters, as JSON.
+fn graph<D, T>(datastore: &D, params: &HashMap<String, String>) -> Result<JsonValue, errors::Error>
+where
+    D: IndraDbDatastore<Trans = T>,
+    T: IndraDbTransaction,
+{
+    let limit = match params.get("limit") {
+        Some(limit) => match limit.parse::<u32>() {
+            Ok(limit) if limit <= MAX_LIMIT => limit,
+            _ => return Err(format!("`limit` must be a number no greater than {}", MAX_LIMIT).into()),
+        },
+        None => DEFAULT_LIMIT,
+    };
+
+    let trans = datastore.transaction().map_err(map_err)?;
+
+    let ids = match params.get("id") {
+        Some(id) => {
+            let id = Uuid::parse_str(id).map_err(|_| -> errors::Error { "`id` must be a uuid".into() })?;
+
+            let depth = match params.get("depth") {
+                Some(depth) => match depth.parse::<u32>() {
+                    Ok(depth) if depth <= MAX_DEPTH => depth,
+                    _ => return Err(format!("`depth` must be a number no greater than {}", MAX_DEPTH).into()),
+                },
+                None => DEFAULT_DEPTH,
+            };
+
+            ego_network(&trans, id, depth, limit)?
+        }
+        None => {
+            let q = match params.get("t") {
+                Some(t) => {
+                    let t = Type::new(t.as_str()).map_err(|err| -> errors::Error { format!("{}", err).into() })?;
+                    RangeVertexQuery::new(limit).t(t)
+                }
+                None => RangeVertexQuery::new(limit),
+            };
+
+            trans
+                .get_vertices(q)
+                .map_err(map_err)?
+                .into_iter()
+                .map(|vertex| vertex.id)
+                .collect()
+        }
+    };
+
+    if ids.is_empty() {
+        return Ok(to_graph(&[], &[]));
+    }
+
+    let ids_vec: Vec<Uuid> = ids.iter().cloned().collect();
+    let vertices = trans
+        .get_vertices(SpecificVertexQuery::new(ids_vec.clone()))
+        .map_err(map_err)?;
+    let mut edges: Vec<EdgeKey> = trans
+        .get_edges(SpecificVertexQuery::new(ids_vec).outbound(u32::MAX))
+        .map_err(map_err)?
+        .into_iter()
+        .map(|edge| edge.key)
+        .filter(|key| ids.contains(&key.inbound_id))
+        .collect();
+    edges.sort();
+
+    Ok(to_graph(&vertices, &edges))
+}
+
+/// Gets the ids of the vertices within `depth` hops of a vertex, in either
+/// direction, stopping once there are `limit` of them.
+fn ego_network<T: IndraDbTransaction>(
+    trans: &T,
+    id: Uuid,
+    depth: u32,
+    limit: u32,
+) -> Result<HashSet<Uuid>, errors::Error> {
+    let mut ids = HashSet::new();
+
+    if limit == 0
+        || trans
+            .get_vertices(SpecificVertexQuery::single(id))
+            .map_err(map_err)?
+            .is_empty()
+    {
+        return Ok(ids);
+    }
+
+    ids.insert(id);
+    let mut frontier = vec![id];
+
+    for _ in 0..depth {
+        if frontier.is_empty() {
+            break;
+        }
+
+        let mut next_frontier = Vec::new();
+        let outbound = SpecificVertexQuery::new(frontier.clone()).outbound(limit);
+        let inbound = SpecificVertexQuery::new(frontier).inbound(limit);
+
+        for edge in trans.get_edges(outbound).map_err(map_err)? {
+            next_frontier.push(edge.key.inbound_id);
+        }
+
+        for edge in trans.get_edges(inbound).map_err(map_err)? {
+            next_frontier.push(edge.key.outbound_id);
+        }
+
+        frontier = Vec::new();
+
+        for id in next_frontier {
+            if ids.len() >= limit as usize {
+                return Ok(ids);
+            }
+
+            if ids.insert(id) {
+                frontier.push(id);
+            }
+        }
+    }
+
+    Ok(ids)
+}
+
+fn to_graph(vertices: &[Vertex], edges: &[EdgeKey]) -> JsonValue {
+    let vertices = vertices
+        .iter()
+        .map(|vertex| {
+            let mut value = Map::new();
+            value.insert(
+                "id".to_string(),
+                JsonValue::String(vertex.id.to_hyphenated().to_string()),
+            );
+            value.insert("t".to_string(), JsonValue::String(vertex.t.0.clone()));
+            JsonValue::Object(value)
+        })
+        .collect();
+
+    let edges = edges
+        .iter()
+        .map(|key| {
+            let mut value = Map::new();
+            value.insert(
+                "outbound_id".to_string(),
+                JsonValue::String(key.outbound_id.to_hyphenated().to_string()),
+            );
+            value.insert("t".to_string(), JsonValue::String(key.t.0.clone()));
+            value.insert(
+                "inbound_id".to_string(),
+                JsonValue::String(key.inbound_id.to_hyphenated().to_string()),
+            );
+            JsonValue::Object(value)
+        })
+        .collect();
+
+    let mut graph = Map::new();
+    graph.insert("vertices".to_string(), JsonValue::Array(vertices));
+    graph.insert("edges".to_string(), JsonValue::Array(edges));
+    JsonValue::Object(graph)
+}
+
+/// Parses a URL query string, decoding percent-escapes and `+`.
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.find('=') {
+            Some(i) => (decode(&pair[..i]), decode(&pair[i + 1..])),
+            None => (decode(pair), String::new()),
+        })
+        .collect()
+}
+
+fn decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() && bytes[i + 1].is_ascii_hexdigit() && bytes[i + 2].is_ascii_hexdigit() => {
+                // Both bytes are ASCII hex digits, so this can't fail
+                let hex = str::from_utf8(&bytes[i + 1..i + 3]).unwrap();
+                decoded.push(u8::from_str_radix(hex, 16).unwrap());
+                i += 2;
+            }
+            byte => decoded.push(byte),
+        }
+
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn map_err(err: indradb::Error) -> errors::Error {
+    format!("{}", err).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{graph, parse_query};
+    use indradb::{Datastore, EdgeKey, MemoryDatastore, Transaction, Type};
+    use serde_json::Value as JsonValue;
+
+    #[test]
+    fn should_parse_query() {
+        let params = parse_query("t=a+b&id=%41%2f&empty&depth=");
+        assert_eq!(params["t"], "a b");
+        assert_eq!(params["id"], "A/");
+        assert_eq!(params["empty"], "");
+        assert_eq!(params["depth"], "");
+        assert_eq!(parse_query("x=%4").get("x").unwrap(), "%4");
+        assert!(parse_query("").is_empty());
+    }
+
+    #[test]
+    fn should_get_graph() {
+        let datastore = MemoryDatastore::default();
+        let trans = datastore.transaction().unwrap();
+        let t = Type::new("foo").unwrap();
+        let a = trans.create_vertex_from_type(t.clone()).unwrap();
+        let b = trans.create_vertex_from_type(t.clone()).unwrap();
+        let c = trans.create_vertex_from_type(t.clone()).unwrap();
+        trans.create_edge(&EdgeKey::new(a, t.clone(), b)).unwrap();
+        trans.create_edge(&EdgeKey::new(c, t.clone(), b)).unwrap();
+
+        let count = |value: &JsonValue, name: &str| value[name].as_array().unwrap().len();
+
+        let value = graph(&datastore, &parse_query(&format!("id={}", a))).unwrap();
+        assert_eq!(count(&value, "vertices"), 2);
+        assert_eq!(count(&value, "edges"), 1);
+
+        let value = graph(&datastore, &parse_query(&format!("id={}&depth=2", a))).unwrap();
+        assert_eq!(count(&value, "vertices"), 3);
+        assert_eq!(count(&value, "edges"), 2);
+
+        let value = graph(&datastore, &parse_query("t=foo")).unwrap();
+        assert_eq!(count(&value, "vertices"), 3);
+        assert_eq!(count(&value, "edges"), 2);
+
+        let value = graph(&datastore, &parse_query("t=bar")).unwrap();
+        assert_eq!(count(&value, "vertices"), 0);
+
+        assert!(graph(&datastore, &parse_query("id=foo")).is_err());
+        assert!(graph(&datastore, &parse_query("limit=-1")).is_err());
+    }
+}
@@ -0,0 +1,186 @@
+//! A WebSocket endpoint that streams live query changes as JSON, for clients
+//! like browser dashboards that can't speak capnp.
+//!
+//! Each connection sends a single text message describing the query to
+//! subscribe to, e.g. `{"type": "vertices", "t": "user", "limit": 100}` or
+//! `{"type": "edges", "t": "follows", "limit": 100}`. `t` is optional; for
+//! edge subscriptions it filters by edge type, and the edges are the
+//! outbound edges of the first `limit` vertices. The server then sends a
+//! text message per change, e.g. `{"vertex_added": {"id": "...", "t": "user"}}`,
+//! until the connection is closed.
+
+use errors;
+use indradb::{
+    Datastore as IndraDbDatastore, EdgeKey, LiveQuery, LiveQueryChange, RangeVertexQuery,
+    Transaction as IndraDbTransaction, Type, VertexQueryExt,
+};
+use serde_json;
+use serde_json::{Map, Value as JsonValue};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tungstenite::server::accept;
+use tungstenite::{Message, WebSocket};
+
+// How long connection threads wait for the datastore to change at a time.
+const POLL_INTERVAL_MS: u64 = 1000;
+
+// The limit used for subscriptions that don't specify one.
+const DEFAULT_LIMIT: u32 = 1000;
+
+/// Starts serving the WebSocket endpoint on a background thread.
+///
+/// # Arguments
+/// * `addr` - The address to listen on.
+/// * `datastore` - The datastore to query.
+pub fn start<D, T>(addr: SocketAddr, datastore: Arc<D>) -> Result<(), errors::Error>
+where
+    D: IndraDbDatastore<Trans = T> + Send + Sync + 'static,
+    T: IndraDbTransaction + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind(&addr)?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            if let Ok(stream) = stream {
+                let datastore = datastore.clone();
+                // Errors only affect their own connection, which is closed
+                // when the thread exits.
+                thread::spawn(move || serve(stream, &*datastore));
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn serve<D, T>(stream: TcpStream, datastore: &D) -> Result<(), errors::Error>
+where
+    D: IndraDbDatastore<Trans = T>,
+    T: IndraDbTransaction,
+{
+    let mut socket = accept(stream).map_err(|err| -> errors::Error { format!("{}", err).into() })?;
+
+    let mut live_query = loop {
+        match socket.read_message() {
+            Ok(Message::Text(text)) => match subscription(datastore, &text) {
+                Ok(live_query) => break live_query,
+                Err(err) => return close(socket, &format!("{}", err)),
+            },
+            Ok(Message::Close(_)) => return Ok(()),
+            // Pings are answered by the socket itself.
+            Ok(_) => continue,
+            Err(err) => return Err(format!("{}", err).into()),
+        }
+    };
+
+    let interval = Duration::from_millis(POLL_INTERVAL_MS);
+
+    loop {
+        let changes = match live_query.changes(interval) {
+            Ok(changes) => changes,
+            Err(err) => return close(socket, &format!("{}", err)),
+        };
+
+        for change in changes {
+            let message = Message::Text(from_live_query_change(&change).to_string());
+            socket
+                .write_message(message)
+                .map_err(|err| -> errors::Error { format!("{}", err).into() })?;
+        }
+    }
+}
+
+fn subscription<D, T>(datastore: &D, text: &str) -> Result<LiveQuery<T>, errors::Error>
+where
+    D: IndraDbDatastore<Trans = T>,
+    T: IndraDbTransaction,
+{
+    let value: JsonValue = serde_json::from_str(text).map_err(|err| -> errors::Error { format!("{}", err).into() })?;
+
+    let t = match value.get("t") {
+        Some(&JsonValue::String(ref t)) => {
+            Some(Type::new(t.as_str()).map_err(|err| -> errors::Error { format!("{}", err).into() })?)
+        }
+        None | Some(&JsonValue::Null) => None,
+        Some(_) => return Err("`t` must be a string".into()),
+    };
+
+    let limit = match value.get("limit") {
+        Some(&JsonValue::Number(ref limit)) => match limit.as_u64() {
+            Some(limit) if limit <= u64::from(u32::max_value()) => limit as u32,
+            _ => return Err("`limit` must be a u32".into()),
+        },
+        None | Some(&JsonValue::Null) => DEFAULT_LIMIT,
+        Some(_) => return Err("`limit` must be a u32".into()),
+    };
+
+    let live_query = match value.get("type").and_then(|t| t.as_str()) {
+        Some("vertices") => {
+            let q = match t {
+                Some(t) => RangeVertexQuery::new(limit).t(t),
+                None => RangeVertexQuery::new(limit),
+            };
+
+            LiveQuery::vertices(datastore, q.into())
+        }
+        Some("edges") => {
+            let q = RangeVertexQuery::new(limit).outbound(limit);
+
+            let q = match t {
+                Some(t) => q.t(t),
+                None => q,
+            };
+
+            LiveQuery::edges(datastore, q.into())
+        }
+        _ => return Err("`type` must be either `vertices` or `edges`".into()),
+    };
+
+    live_query.map_err(|err| -> errors::Error { format!("{}", err).into() })
+}
+
+fn close(mut socket: WebSocket<TcpStream>, reason: &str) -> Result<(), errors::Error> {
+    let mut message = Map::new();
+    message.insert("error".to_string(), JsonValue::String(reason.to_string()));
+    socket
+        .write_message(Message::Text(JsonValue::Object(message).to_string()))
+        .and_then(|_| socket.close(None))
+        .map_err(|err| -> errors::Error { format!("{}", err).into() })
+}
+
+fn from_live_query_change(change: &LiveQueryChange) -> JsonValue {
+    let (name, value) = match *change {
+        LiveQueryChange::VertexAdded(ref vertex) => {
+            let mut value = Map::new();
+            value.insert(
+                "id".to_string(),
+                JsonValue::String(vertex.id.to_hyphenated().to_string()),
+            );
+            value.insert("t".to_string(), JsonValue::String(vertex.t.0.clone()));
+            ("vertex_added", JsonValue::Object(value))
+        }
+        LiveQueryChange::VertexRemoved(ref id) => ("vertex_removed", JsonValue::String(id.to_hyphenated().to_string())),
+        LiveQueryChange::EdgeAdded(ref key) => ("edge_added", from_edge_key(key)),
+        LiveQueryChange::EdgeRemoved(ref key) => ("edge_removed", from_edge_key(key)),
+    };
+
+    let mut message = Map::new();
+    message.insert(name.to_string(), value);
+    JsonValue::Object(message)
+}
+
+fn from_edge_key(key: &EdgeKey) -> JsonValue {
+    let mut value = Map::new();
+    value.insert(
+        "outbound_id".to_string(),
+        JsonValue::String(key.outbound_id.to_hyphenated().to_string()),
+    );
+    value.insert("t".to_string(), JsonValue::String(key.t.0.clone()));
+    value.insert(
+        "inbound_id".to_string(),
+        JsonValue::String(key.inbound_id.to_hyphenated().to_string()),
+    );
+    JsonValue::Object(value)
+}
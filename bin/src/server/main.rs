@@ -10,10 +10,45 @@ extern crate serde_json;
 extern crate uuid;
 
 use std::env;
+use std::process;
 
 const DEFAULT_PORT: u16 = 27615;
 
 fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    if args.get(1).map(|arg| arg.as_str()) == Some("shell") {
+        let connection_string = match args.get(2) {
+            Some(value) => value.clone(),
+            None => env::var("DATABASE_URL").unwrap_or_else(|_| "memory://".to_string()),
+        };
+
+        common::shell::start(&connection_string).expect("Expected to be able to run the shell");
+        return;
+    }
+
+    #[cfg(feature = "bench")]
+    {
+        if args.get(1).map(|arg| arg.as_str()) == Some("bench") {
+            bench();
+            return;
+        }
+    }
+
+    if let Some(command) = args
+        .get(1)
+        .filter(|arg| common::admin::COMMANDS.contains(&arg.as_str()))
+    {
+        let connection_string = env::var("DATABASE_URL").unwrap_or_else(|_| "memory://".to_string());
+
+        if let Err(err) = common::admin::start(&connection_string, command, &args[2..]) {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+
+        return;
+    }
+
     let port = match env::var("PORT") {
         Ok(value) => value
             .parse::<u16>()
@@ -34,3 +69,43 @@ fn main() {
 
     common::server::start(&binding, &connection_string, worker_count).expect("Expected to be able to start the server");
 }
+
+#[cfg(feature = "bench")]
+fn bench() {
+    let connection_string = env::var("DATABASE_URL").unwrap_or_else(|_| "memory://".to_string());
+
+    let vertex_count = match env::var("BENCH_VERTEX_COUNT") {
+        Ok(value) => value
+            .parse::<u32>()
+            .expect("Could not parse environment variable `BENCH_VERTEX_COUNT`"),
+        Err(_) => 100_000,
+    };
+
+    let degree = match env::var("BENCH_DEGREE") {
+        Ok(value) => common::bench::parse_degree(&value).expect("Could not parse environment variable `BENCH_DEGREE`"),
+        Err(_) => indradb::workload::DegreeDistribution::Constant(10),
+    };
+
+    let mut workload = indradb::workload::Workload::new(vertex_count, degree);
+
+    if let Ok(value) = env::var("BENCH_READ_COUNT") {
+        workload = workload.read_count(
+            value
+                .parse::<u32>()
+                .expect("Could not parse environment variable `BENCH_READ_COUNT`"),
+        );
+    }
+
+    if let Ok(value) = env::var("BENCH_SEED") {
+        workload = workload.seed(
+            value
+                .parse::<u64>()
+                .expect("Could not parse environment variable `BENCH_SEED`"),
+        );
+    }
+
+    if let Err(err) = common::bench::start(&connection_string, &workload, std::io::stdout()) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
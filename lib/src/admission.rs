@@ -0,0 +1,395 @@
+//! Admission control based on the approximate memory used by in-flight
+//! requests. Requests reserve an estimate of the memory their results will
+//! take up before they're run, and are only admitted while that fits in a
+//! budget. As results are materialized, the reservation grows to cover their
+//! actual size, and requests that outgrow the budget fail. This way many
+//! concurrent heavy scans queue up or fail rather than running the process
+//! out of memory.
+
+use errors::{ErrorKind, Result};
+use models;
+use serde_json::Value as JsonValue;
+use std::mem;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Options for admitting requests against a memory budget.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AdmissionOptions {
+    /// Roughly how many bytes in-flight requests can have reserved. Requests
+    /// whose estimates don't fit are held back, and requests whose results
+    /// outgrow it fail.
+    pub budget_bytes: usize,
+
+    /// How long a request waits for other requests to release memory before
+    /// it's rejected. Defaults to zero, i.e. requests over the budget are
+    /// rejected right away.
+    pub queue_timeout: Duration,
+}
+
+impl AdmissionOptions {
+    /// Creates new admission options.
+    ///
+    /// # Arguments
+    /// * `budget_bytes` - Roughly how many bytes in-flight requests can
+    ///   have reserved.
+    pub fn new(budget_bytes: usize) -> Self {
+        AdmissionOptions {
+            budget_bytes,
+            queue_timeout: Duration::from_secs(0),
+        }
+    }
+
+    /// Sets how long a request waits for memory before it's rejected.
+    ///
+    /// # Arguments
+    /// * `queue_timeout` - How long to wait.
+    pub fn queue_timeout(self, queue_timeout: Duration) -> Self {
+        AdmissionOptions { queue_timeout, ..self }
+    }
+}
+
+#[derive(Debug)]
+struct Budget {
+    options: AdmissionOptions,
+    reserved_bytes: Mutex<usize>,
+    released: Condvar,
+}
+
+/// Tracks the memory reserved by in-flight requests, and admits new ones
+/// while it's under a budget. Clones share the same budget.
+#[derive(Clone, Debug)]
+pub struct MemoryBudget {
+    budget: Arc<Budget>,
+}
+
+impl MemoryBudget {
+    /// Creates a new memory budget.
+    ///
+    /// # Arguments
+    /// * `options` - The admission options.
+    pub fn new(options: AdmissionOptions) -> Self {
+        MemoryBudget {
+            budget: Arc::new(Budget {
+                options,
+                reserved_bytes: Mutex::new(0),
+                released: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Admits a request, reserving an estimate of the memory it needs up
+    /// front. If the estimate doesn't fit in what's left of the budget, this
+    /// waits up to the queue timeout for other requests to release memory.
+    /// The memory is released when the returned reservation is dropped.
+    ///
+    /// # Arguments
+    /// * `estimated_bytes` - Roughly how many bytes the request needs.
+    ///
+    /// # Errors
+    /// Returns an `Overloaded` error if the estimate is over the whole
+    /// budget, or still doesn't fit after the queue timeout.
+    pub fn admit(&self, estimated_bytes: usize) -> Result<MemoryReservation> {
+        let budget_bytes = self.budget.options.budget_bytes;
+
+        if estimated_bytes > budget_bytes {
+            return Err(ErrorKind::Overloaded(format!(
+                "The request needs an estimated {} bytes, over the budget of {}",
+                estimated_bytes, budget_bytes
+            ))
+            .into());
+        }
+
+        let deadline = Instant::now() + self.budget.options.queue_timeout;
+        let mut reserved_bytes = self.budget.reserved_bytes.lock().unwrap();
+
+        while *reserved_bytes + estimated_bytes > budget_bytes {
+            let now = Instant::now();
+
+            if now >= deadline {
+                return Err(ErrorKind::Overloaded(format!(
+                    "{} bytes reserved by in-flight requests, leaving too little of the budget of {} for the {} \
+                     bytes the request needs",
+                    *reserved_bytes, budget_bytes, estimated_bytes
+                ))
+                .into());
+            }
+
+            reserved_bytes = self
+                .budget
+                .released
+                .wait_timeout(reserved_bytes, deadline - now)
+                .unwrap()
+                .0;
+        }
+
+        *reserved_bytes += estimated_bytes;
+
+        Ok(MemoryReservation {
+            budget: self.budget.clone(),
+            bytes: estimated_bytes,
+        })
+    }
+
+    /// Admits a request that materializes up to `max_items` items of a type,
+    /// with an estimate based on the size of the type. What the items own
+    /// on the heap isn't known until they're materialized, so it's left for
+    /// `MemoryReservation::grow_for` to account for.
+    ///
+    /// # Arguments
+    /// * `max_items` - The most items the request can materialize.
+    ///
+    /// # Errors
+    /// Returns an `Overloaded` error like `admit`.
+    pub fn admit_for<T>(&self, max_items: u32) -> Result<MemoryReservation> {
+        self.admit((max_items as usize).saturating_mul(mem::size_of::<T>()))
+    }
+
+    /// Gets how many bytes in-flight requests have reserved.
+    pub fn reserved_bytes(&self) -> usize {
+        *self.budget.reserved_bytes.lock().unwrap()
+    }
+}
+
+/// Memory reserved by an admitted request, which is released when this is
+/// dropped.
+#[derive(Debug)]
+pub struct MemoryReservation {
+    budget: Arc<Budget>,
+    bytes: usize,
+}
+
+impl MemoryReservation {
+    /// Reserves more memory for the request. This never blocks, since the
+    /// memory has usually been used by the time it's accounted for.
+    ///
+    /// # Arguments
+    /// * `bytes` - The number of bytes to reserve.
+    ///
+    /// # Errors
+    /// Returns an `Overloaded` error, without reserving anything, if this
+    /// would take the reserved memory over the budget. The request should
+    /// then fail, so that what it's already materialized is freed.
+    pub fn grow(&mut self, bytes: usize) -> Result<()> {
+        let budget_bytes = self.budget.options.budget_bytes;
+        let mut reserved_bytes = self.budget.reserved_bytes.lock().unwrap();
+
+        if *reserved_bytes + bytes > budget_bytes {
+            return Err(ErrorKind::Overloaded(format!(
+                "The request outgrew its reservation of {} bytes by {}, which is over the budget of {}",
+                self.bytes, bytes, budget_bytes
+            ))
+            .into());
+        }
+
+        *reserved_bytes += bytes;
+        self.bytes += bytes;
+        Ok(())
+    }
+
+    /// Grows the reservation to cover materialized items, including what
+    /// they own on the heap, if the request was admitted with too low an
+    /// estimate. This can be called as more items are materialized.
+    ///
+    /// # Arguments
+    /// * `items` - All of the items the request has materialized so far.
+    ///
+    /// # Errors
+    /// Returns an `Overloaded` error like `grow`.
+    pub fn grow_for<T: HeapSize>(&mut self, items: &[T]) -> Result<()> {
+        let bytes = mem::size_of_val(items) + items.iter().map(HeapSize::heap_size).sum::<usize>();
+
+        if bytes > self.bytes {
+            let extra_bytes = bytes - self.bytes;
+            self.grow(extra_bytes)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Gets how many bytes are reserved for the request.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+impl Drop for MemoryReservation {
+    fn drop(&mut self) {
+        *self.budget.reserved_bytes.lock().unwrap() -= self.bytes;
+        self.budget.released.notify_all();
+    }
+}
+
+/// Estimates the memory a value owns on the heap, beyond the size of its
+/// type, so that materialized results can be accounted for.
+pub trait HeapSize {
+    /// Gets roughly how many bytes the value owns on the heap.
+    fn heap_size(&self) -> usize;
+}
+
+impl HeapSize for String {
+    fn heap_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Vec<T> {
+    fn heap_size(&self) -> usize {
+        self.capacity() * mem::size_of::<T>() + self.iter().map(HeapSize::heap_size).sum::<usize>()
+    }
+}
+
+// Maps are counted as a key and value per entry, ignoring the overhead of
+// their nodes.
+impl HeapSize for JsonValue {
+    fn heap_size(&self) -> usize {
+        match self {
+            JsonValue::String(s) => s.heap_size(),
+            JsonValue::Array(values) => values.heap_size(),
+            JsonValue::Object(map) => map
+                .iter()
+                .map(|(key, value)| {
+                    mem::size_of::<String>() + key.heap_size() + mem::size_of_val(value) + value.heap_size()
+                })
+                .sum(),
+            _ => 0,
+        }
+    }
+}
+
+impl HeapSize for models::Type {
+    fn heap_size(&self) -> usize {
+        self.0.heap_size()
+    }
+}
+
+impl HeapSize for models::Vertex {
+    fn heap_size(&self) -> usize {
+        self.t.heap_size()
+    }
+}
+
+impl HeapSize for models::EdgeKey {
+    fn heap_size(&self) -> usize {
+        self.t.heap_size()
+    }
+}
+
+impl HeapSize for models::Edge {
+    fn heap_size(&self) -> usize {
+        self.key.heap_size()
+    }
+}
+
+impl HeapSize for models::VertexProperty {
+    fn heap_size(&self) -> usize {
+        self.value.heap_size()
+    }
+}
+
+impl HeapSize for models::EdgeProperty {
+    fn heap_size(&self) -> usize {
+        self.key.heap_size() + self.value.heap_size()
+    }
+}
+
+impl HeapSize for models::NamedProperty {
+    fn heap_size(&self) -> usize {
+        self.name.heap_size() + self.value.heap_size()
+    }
+}
+
+impl HeapSize for models::VertexProperties {
+    fn heap_size(&self) -> usize {
+        self.vertex.heap_size() + self.props.heap_size()
+    }
+}
+
+impl HeapSize for models::EdgeProperties {
+    fn heap_size(&self) -> usize {
+        self.edge.heap_size() + self.props.heap_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AdmissionOptions, HeapSize, MemoryBudget};
+    use errors::{ErrorKind, Result};
+    use models::{Type, Vertex};
+    use std::mem;
+    use std::thread;
+    use std::time::Duration;
+
+    fn assert_overloaded<T>(result: Result<T>) {
+        match result {
+            Err(err) => match err.kind() {
+                ErrorKind::Overloaded(_) => (),
+                kind => panic!("Unexpected error kind: {:?}", kind),
+            },
+            Ok(_) => panic!("Expected the request to be rejected"),
+        }
+    }
+
+    #[test]
+    fn should_reject_requests_over_the_budget() {
+        let budget = MemoryBudget::new(AdmissionOptions::new(100));
+        let first = budget.admit_for::<u64>(10).unwrap();
+        assert_eq!(first.bytes(), 80);
+        assert_eq!(budget.reserved_bytes(), 80);
+
+        // The estimate doesn't fit in what's left of the budget
+        assert_overloaded(budget.admit(40));
+        // The estimate doesn't fit in the budget at all
+        assert_overloaded(budget.admit(101));
+
+        let second = budget.admit(20).unwrap();
+        assert_eq!(budget.reserved_bytes(), 100);
+
+        drop(first);
+        drop(second);
+        assert_eq!(budget.reserved_bytes(), 0);
+        assert!(budget.admit(40).is_ok());
+    }
+
+    #[test]
+    fn should_grow_reservations_for_materialized_items() {
+        let budget = MemoryBudget::new(AdmissionOptions::new(1000));
+        let mut reservation = budget.admit_for::<Vertex>(1).unwrap();
+        let t = Type::new("foo").unwrap();
+        let vertices = vec![Vertex::new(t.clone()), Vertex::new(t.clone())];
+
+        // The estimate only covered one vertex, without its type's name
+        reservation.grow_for(&vertices).unwrap();
+        assert_eq!(
+            reservation.bytes(),
+            2 * mem::size_of::<Vertex>() + vertices.iter().map(HeapSize::heap_size).sum::<usize>()
+        );
+        assert!(vertices[0].heap_size() >= 3);
+        assert_eq!(budget.reserved_bytes(), reservation.bytes());
+
+        // Already covered, so nothing more is reserved
+        let bytes = reservation.bytes();
+        reservation.grow_for(&vertices[..1]).unwrap();
+        assert_eq!(reservation.bytes(), bytes);
+
+        // Outgrowing the budget fails, without reserving anything
+        assert_overloaded(reservation.grow(1000));
+        assert_eq!(reservation.bytes(), bytes);
+        assert_eq!(budget.reserved_bytes(), bytes);
+    }
+
+    #[test]
+    fn should_queue_requests_until_memory_is_released() {
+        let budget = MemoryBudget::new(AdmissionOptions::new(100).queue_timeout(Duration::from_secs(10)));
+        let reservation = budget.admit(100).unwrap();
+
+        let releaser = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            drop(reservation);
+        });
+
+        assert!(budget.admit(50).is_ok());
+        releaser.join().unwrap();
+        assert_eq!(budget.reserved_bytes(), 0);
+    }
+}
@@ -0,0 +1,636 @@
+use super::super::{Datastore, EdgePropertyQuery, EdgeQuery, Transaction, VertexPropertyQuery, VertexQuery};
+use chrono::offset::Utc;
+use chrono::DateTime;
+use errors::Result;
+use models;
+use models::VertexQueryExt;
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// Controls how much a `CachedDatastore` caches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AdjacencyCacheOptions {
+    /// The maximum number of edges to cache, across all of the adjacency
+    /// lists. It's split evenly between the partitions, and a list that
+    /// doesn't fit in its partition's share isn't cached.
+    pub capacity: usize,
+
+    /// The number of partitions that vertices are hashed into. More
+    /// partitions mean writes invalidate less of the cache, and less lock
+    /// contention, but a smaller share of the capacity for each.
+    pub partitions: usize,
+
+    /// The fewest edges an adjacency list must have to be cached. Short
+    /// lists are cheap to read from the underlying datastore, so caching
+    /// them would only evict the lists of hub vertices.
+    pub min_degree: usize,
+}
+
+impl Default for AdjacencyCacheOptions {
+    fn default() -> Self {
+        AdjacencyCacheOptions {
+            capacity: 1_000_000,
+            partitions: 64,
+            min_degree: 0,
+        }
+    }
+}
+
+impl AdjacencyCacheOptions {
+    /// Creates new adjacency cache options, which cache up to a million
+    /// edges in 64 partitions, regardless of degree.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum number of edges to cache.
+    ///
+    /// # Arguments
+    /// * `capacity` - The number of edges.
+    pub fn capacity(self, capacity: usize) -> Self {
+        AdjacencyCacheOptions { capacity, ..self }
+    }
+
+    /// Sets the number of partitions that vertices are hashed into.
+    ///
+    /// # Arguments
+    /// * `partitions` - The number of partitions.
+    pub fn partitions(self, partitions: usize) -> Self {
+        AdjacencyCacheOptions { partitions, ..self }
+    }
+
+    /// Sets the fewest edges an adjacency list must have to be cached.
+    ///
+    /// # Arguments
+    /// * `min_degree` - The number of edges.
+    pub fn min_degree(self, min_degree: usize) -> Self {
+        AdjacencyCacheOptions { min_degree, ..self }
+    }
+}
+
+/// How effective an adjacency cache has been.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AdjacencyCacheStats {
+    /// The number of reads that were answered from the cache.
+    pub hits: u64,
+
+    /// The number of reads that went to the underlying datastore.
+    pub misses: u64,
+
+    /// The number of adjacency lists currently cached.
+    pub entries: u64,
+
+    /// The number of edges currently cached.
+    pub edges: u64,
+}
+
+// The vertex, edge type filter and direction of an adjacency list.
+type AdjacencyKey = (Uuid, Option<models::Type>, models::EdgeDirection);
+
+#[derive(Debug)]
+struct Entry {
+    edges: Arc<Vec<models::Edge>>,
+    last_used: u64,
+}
+
+// A group of vertices whose cached adjacency lists are invalidated
+// together. The version is bumped by every write that touches one of the
+// vertices.
+#[derive(Debug, Default)]
+struct Partition {
+    version: u64,
+    entries: HashMap<AdjacencyKey, Entry>,
+    edge_count: usize,
+}
+
+impl Partition {
+    fn invalidate(&mut self) {
+        self.version += 1;
+        self.entries.clear();
+        self.edge_count = 0;
+    }
+
+    // Evicts the least recently used lists until the partition is within
+    // its capacity.
+    fn evict(&mut self, capacity: usize) {
+        while self.edge_count > capacity {
+            let key = match self.entries.iter().min_by_key(|(_, entry)| entry.last_used) {
+                Some((key, _)) => key.clone(),
+                None => return,
+            };
+
+            if let Some(entry) = self.entries.remove(&key) {
+                self.edge_count -= entry.edges.len();
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct AdjacencyCache {
+    options: AdjacencyCacheOptions,
+    partitions: Vec<Mutex<Partition>>,
+    tick: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl AdjacencyCache {
+    fn new(options: AdjacencyCacheOptions) -> Self {
+        Self {
+            options,
+            partitions: (0..options.partitions)
+                .map(|_| Mutex::new(Partition::default()))
+                .collect(),
+            tick: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    // Vertices are assigned to partitions by a hash of their id. Every byte
+    // is mixed in, since the version 1 UUIDs that `Vertex::new` generates
+    // only differ in a few of them.
+    fn partition(&self, id: Uuid) -> &Mutex<Partition> {
+        let hash = id
+            .as_bytes()
+            .iter()
+            .fold(0usize, |hash, byte| hash.wrapping_mul(31).wrapping_add(*byte as usize));
+        &self.partitions[hash % self.partitions.len()]
+    }
+
+    // Gets a cached adjacency list, or otherwise the version of its
+    // partition, which has to be read before the list is read from the
+    // underlying datastore. That way, a list read before a concurrent write
+    // is committed is stored with the version from before the write bumped
+    // it, and so isn't cached.
+    fn get(&self, key: &AdjacencyKey) -> ::std::result::Result<Arc<Vec<models::Edge>>, u64> {
+        let mut partition = self.partition(key.0).lock().unwrap();
+
+        if let Some(entry) = partition.entries.get_mut(key) {
+            entry.last_used = self.tick.fetch_add(1, Ordering::SeqCst);
+            self.hits.fetch_add(1, Ordering::SeqCst);
+            return Ok(Arc::clone(&entry.edges));
+        }
+
+        self.misses.fetch_add(1, Ordering::SeqCst);
+        Err(partition.version)
+    }
+
+    fn insert(&self, key: AdjacencyKey, version: u64, edges: Arc<Vec<models::Edge>>) {
+        let capacity = self.options.capacity / self.partitions.len();
+
+        if edges.len() < self.options.min_degree || edges.len() > capacity {
+            return;
+        }
+
+        let mut partition = self.partition(key.0).lock().unwrap();
+
+        if partition.version != version {
+            return;
+        }
+
+        partition.edge_count += edges.len();
+        let entry = Entry {
+            edges,
+            last_used: self.tick.fetch_add(1, Ordering::SeqCst),
+        };
+
+        if let Some(previous) = partition.entries.insert(key, entry) {
+            partition.edge_count -= previous.edges.len();
+        }
+
+        partition.evict(capacity);
+    }
+
+    fn invalidate(&self, id: Uuid) {
+        self.partition(id).lock().unwrap().invalidate();
+    }
+
+    fn invalidate_edge(&self, key: &models::EdgeKey) {
+        self.invalidate(key.outbound_id);
+        self.invalidate(key.inbound_id);
+    }
+
+    fn invalidate_all(&self) {
+        for partition in &self.partitions {
+            partition.lock().unwrap().invalidate();
+        }
+    }
+
+    fn stats(&self) -> AdjacencyCacheStats {
+        let mut stats = AdjacencyCacheStats {
+            hits: self.hits.load(Ordering::SeqCst),
+            misses: self.misses.load(Ordering::SeqCst),
+            ..AdjacencyCacheStats::default()
+        };
+
+        for partition in &self.partitions {
+            let partition = partition.lock().unwrap();
+            stats.entries += partition.entries.len() as u64;
+            stats.edges += partition.edge_count as u64;
+        }
+
+        stats
+    }
+}
+
+/// A datastore that caches the adjacency lists of vertices read from
+/// another datastore.
+#[derive(Debug)]
+pub struct CachedDatastore<D: Datastore> {
+    base: D,
+    cache: Arc<AdjacencyCache>,
+}
+
+impl<D: Datastore> CachedDatastore<D> {
+    /// Creates a new cached datastore, with an empty cache.
+    ///
+    /// # Arguments
+    /// * `base` - The underlying datastore.
+    /// * `options` - How much to cache.
+    ///
+    /// # Panics
+    /// Panics if `options.partitions` is zero.
+    pub fn new(base: D, options: AdjacencyCacheOptions) -> Self {
+        assert!(options.partitions > 0, "Expected at least one partition");
+        Self {
+            base,
+            cache: Arc::new(AdjacencyCache::new(options)),
+        }
+    }
+
+    /// Gets the underlying datastore. Writes made to it directly aren't
+    /// reflected in the cache.
+    pub fn base(&self) -> &D {
+        &self.base
+    }
+
+    /// Gets how effective the cache has been.
+    pub fn get_cache_stats(&self) -> AdjacencyCacheStats {
+        self.cache.stats()
+    }
+
+    /// Discards everything in the cache, e.g. after writing to the
+    /// underlying datastore directly.
+    pub fn clear_cache(&self) {
+        self.cache.invalidate_all();
+    }
+}
+
+impl<D: Datastore> Datastore for CachedDatastore<D> {
+    type Trans = CachedTransaction<D>;
+
+    fn bulk_insert<I>(&self, items: I) -> Result<()>
+    where
+        I: Iterator<Item = models::BulkInsertItem>,
+    {
+        let mut edge_keys = Vec::new();
+
+        let result = self.base.bulk_insert(items.inspect(|item| {
+            if let models::BulkInsertItem::Edge(ref key) = item {
+                edge_keys.push(key.clone());
+            }
+        }));
+
+        // Some of the edges may have been inserted even if it failed
+        for key in &edge_keys {
+            self.cache.invalidate_edge(key);
+        }
+
+        result
+    }
+
+    fn transaction(&self) -> Result<Self::Trans> {
+        Ok(CachedTransaction {
+            base: self.base.transaction()?,
+            cache: Arc::clone(&self.cache),
+        })
+    }
+
+    fn subscribe(&self, filter: models::EventFilter) -> Result<Receiver<models::Event>> {
+        self.base.subscribe(filter)
+    }
+
+    fn get_idempotency_record(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.base.get_idempotency_record(key)
+    }
+
+    fn set_idempotency_record(&self, key: &str, response: &[u8], expires_at: DateTime<Utc>) -> Result<()> {
+        self.base.set_idempotency_record(key, response, expires_at)
+    }
+
+    fn get_write_pressure(&self) -> Result<models::WritePressure> {
+        self.base.get_write_pressure()
+    }
+
+    // Catching up brings in writes the cache hasn't seen, so everything
+    // cached is discarded
+    fn catch_up(&self) -> Result<()> {
+        self.base.catch_up()?;
+        self.cache.invalidate_all();
+        Ok(())
+    }
+}
+
+/// A transaction for cached datastores.
+#[derive(Debug)]
+pub struct CachedTransaction<D: Datastore> {
+    base: D::Trans,
+    cache: Arc<AdjacencyCache>,
+}
+
+impl<D: Datastore> CachedTransaction<D> {
+    // Gets a vertex's whole adjacency list, in the order the underlying
+    // datastore returns it.
+    fn get_adjacency(
+        &self,
+        id: Uuid,
+        t: Option<&models::Type>,
+        direction: models::EdgeDirection,
+    ) -> Result<Arc<Vec<models::Edge>>> {
+        let key = (id, t.cloned(), direction);
+
+        let version = match self.cache.get(&key) {
+            Ok(edges) => return Ok(edges),
+            Err(version) => version,
+        };
+
+        let q = models::SpecificVertexQuery::single(id);
+
+        let mut q = match direction {
+            models::EdgeDirection::Outbound => q.outbound(u32::MAX),
+            models::EdgeDirection::Inbound => q.inbound(u32::MAX),
+            // Edges in both directions aren't cached
+            models::EdgeDirection::Both => unreachable!(),
+        };
+
+        if let Some(t) = t {
+            q = q.t(t.clone());
+        }
+
+        let edges = Arc::new(self.base.get_edges(q)?);
+        self.cache.insert(key, version, Arc::clone(&edges));
+        Ok(edges)
+    }
+
+    // Runs a write that touches the edges of unknown vertices, and then
+    // invalidates the whole cache.
+    fn invalidating_all<T, F: FnOnce(&D::Trans) -> Result<T>>(&self, f: F) -> Result<T> {
+        let result = f(&self.base);
+        self.cache.invalidate_all();
+        result
+    }
+
+    // Runs a write to a single edge, and then invalidates the partitions of
+    // its vertices.
+    fn invalidating_edge<T, F: FnOnce(&D::Trans) -> Result<T>>(&self, key: &models::EdgeKey, f: F) -> Result<T> {
+        let result = f(&self.base);
+        self.cache.invalidate_edge(key);
+        result
+    }
+}
+
+impl<D: Datastore> Transaction for CachedTransaction<D> {
+    fn create_vertex(&self, vertex: &models::Vertex) -> Result<bool> {
+        self.base.create_vertex(vertex)
+    }
+
+    fn create_vertex_with_id(&self, id: Uuid, t: models::Type) -> Result<bool> {
+        self.base.create_vertex_with_id(id, t)
+    }
+
+    fn upsert_vertex(&self, vertex: &models::Vertex) -> Result<Option<models::Type>> {
+        self.base.upsert_vertex(vertex)
+    }
+
+    fn get_vertices<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<Vec<models::Vertex>> {
+        self.base.get_vertices(q)
+    }
+
+    fn filter_existing_vertices(&self, ids: Vec<Uuid>) -> Result<Vec<bool>> {
+        self.base.filter_existing_vertices(ids)
+    }
+
+    fn delete_vertices<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<()> {
+        self.invalidating_all(|base| base.delete_vertices(q))
+    }
+
+    fn get_vertex_count(&self) -> Result<u64> {
+        self.base.get_vertex_count()
+    }
+
+    fn create_edge(&self, key: &models::EdgeKey) -> Result<bool> {
+        self.invalidating_edge(key, |base| base.create_edge(key))
+    }
+
+    fn create_edge_with_datetime(&self, key: &models::EdgeKey, update_datetime: DateTime<Utc>) -> Result<bool> {
+        self.invalidating_edge(key, |base| base.create_edge_with_datetime(key, update_datetime))
+    }
+
+    fn touch_edge(&self, key: &models::EdgeKey) -> Result<bool> {
+        self.invalidating_edge(key, |base| base.touch_edge(key))
+    }
+
+    // Only edges piped from a single vertex in one direction are read
+    // through the cache; anything else goes to the underlying datastore.
+    // The cached list is filtered the same way the underlying datastore
+    // would filter it, so the order of the results is the same.
+    fn get_edges<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<Vec<models::Edge>> {
+        let pipe = match q.into() {
+            EdgeQuery::Pipe(pipe) => pipe,
+            q => return self.base.get_edges(q),
+        };
+
+        let id = match *pipe.inner {
+            VertexQuery::Specific(ref specific) if specific.ids.len() == 1 => specific.ids[0],
+            _ => return self.base.get_edges(pipe),
+        };
+
+        if pipe.direction == models::EdgeDirection::Both {
+            return self.base.get_edges(pipe);
+        }
+
+        let edges = self.get_adjacency(id, pipe.t.as_ref(), pipe.direction)?;
+
+        let results = edges
+            .iter()
+            .filter(|edge| match pipe.high {
+                Some(high) => edge.created_datetime <= high,
+                None => true,
+            })
+            .filter(|edge| match pipe.low {
+                Some(low) => edge.created_datetime >= low,
+                None => true,
+            })
+            .take(pipe.limit as usize)
+            .cloned()
+            .collect();
+
+        Ok(results)
+    }
+
+    fn edge_exists(&self, key: &models::EdgeKey) -> Result<bool> {
+        self.base.edge_exists(key)
+    }
+
+    fn filter_existing_edges(&self, keys: Vec<models::EdgeKey>) -> Result<Vec<bool>> {
+        self.base.filter_existing_edges(keys)
+    }
+
+    fn delete_edges<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<()> {
+        self.invalidating_all(|base| base.delete_edges(q))
+    }
+
+    fn delete_edges_unmodified_since<Q: Into<models::EdgeQuery>>(&self, q: Q, since: DateTime<Utc>) -> Result<u64> {
+        self.invalidating_all(|base| base.delete_edges_unmodified_since(q, since))
+    }
+
+    fn get_edge_count(&self, id: Uuid, t: Option<&models::Type>, direction: models::EdgeDirection) -> Result<u64> {
+        if direction == models::EdgeDirection::Both {
+            self.base.get_edge_count(id, t, direction)
+        } else {
+            Ok(self.get_adjacency(id, t, direction)?.len() as u64)
+        }
+    }
+
+    fn get_common_neighbors(
+        &self,
+        first_id: Uuid,
+        second_id: Uuid,
+        t: &models::Type,
+        direction: models::EdgeDirection,
+        limit: u32,
+    ) -> Result<Vec<Uuid>> {
+        self.base.get_common_neighbors(first_id, second_id, t, direction, limit)
+    }
+
+    fn get_vertex_properties(&self, q: VertexPropertyQuery) -> Result<Vec<models::VertexProperty>> {
+        self.base.get_vertex_properties(q)
+    }
+
+    fn set_vertex_properties(&self, q: VertexPropertyQuery, value: &JsonValue) -> Result<()> {
+        self.base.set_vertex_properties(q, value)
+    }
+
+    fn delete_vertex_properties(&self, q: VertexPropertyQuery) -> Result<()> {
+        self.base.delete_vertex_properties(q)
+    }
+
+    fn get_vertex_properties_raw(&self, q: VertexPropertyQuery) -> Result<Vec<models::RawVertexProperty>> {
+        self.base.get_vertex_properties_raw(q)
+    }
+
+    fn get_all_vertex_properties<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<Vec<models::VertexProperties>> {
+        self.base.get_all_vertex_properties(q)
+    }
+
+    fn get_edge_properties(&self, q: EdgePropertyQuery) -> Result<Vec<models::EdgeProperty>> {
+        self.base.get_edge_properties(q)
+    }
+
+    fn set_edge_properties(&self, q: EdgePropertyQuery, value: &JsonValue) -> Result<()> {
+        self.base.set_edge_properties(q, value)
+    }
+
+    fn delete_edge_properties(&self, q: EdgePropertyQuery) -> Result<()> {
+        self.base.delete_edge_properties(q)
+    }
+
+    fn get_edge_properties_raw(&self, q: EdgePropertyQuery) -> Result<Vec<models::RawEdgeProperty>> {
+        self.base.get_edge_properties_raw(q)
+    }
+
+    fn get_all_edge_properties<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<Vec<models::EdgeProperties>> {
+        self.base.get_all_edge_properties(q)
+    }
+
+    fn get_keys_scanned(&self) -> Option<u64> {
+        self.base.get_keys_scanned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AdjacencyCacheOptions, CachedDatastore};
+    use memory::MemoryDatastore;
+    use models::{EdgeDirection, EdgeKey, SpecificEdgeQuery, SpecificVertexQuery, Type, Vertex, VertexQueryExt};
+    use traits::{Datastore, Transaction};
+
+    #[test]
+    fn should_cache_and_invalidate_adjacency_lists() {
+        let datastore = CachedDatastore::new(MemoryDatastore::default(), AdjacencyCacheOptions::new().min_degree(2));
+        let trans = datastore.transaction().unwrap();
+        let t = Type::new("foo").unwrap();
+        let hub = Vertex::new(t.clone());
+        trans.create_vertex(&hub).unwrap();
+        let mut keys = Vec::new();
+
+        for _ in 0..3 {
+            let v = Vertex::new(t.clone());
+            trans.create_vertex(&v).unwrap();
+            let key = EdgeKey::new(hub.id, t.clone(), v.id);
+            trans.create_edge(&key).unwrap();
+            keys.push(key);
+        }
+
+        let q = SpecificVertexQuery::single(hub.id).outbound(2);
+        assert_eq!(trans.get_edges(q.clone()).unwrap().len(), 2);
+        assert_eq!(trans.get_edges(q.clone()).unwrap().len(), 2);
+        assert_eq!(trans.get_edge_count(hub.id, None, EdgeDirection::Outbound).unwrap(), 3);
+
+        let stats = datastore.get_cache_stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries, stats.edges), (2, 1, 1, 3));
+
+        // Short lists aren't cached
+        let leaf_q = SpecificVertexQuery::single(keys[0].inbound_id).inbound(10);
+        assert_eq!(trans.get_edges(leaf_q).unwrap().len(), 1);
+        assert_eq!(datastore.get_cache_stats().entries, 1);
+
+        trans.delete_edges(SpecificEdgeQuery::single(keys[0].clone())).unwrap();
+        assert_eq!(datastore.get_cache_stats().entries, 0);
+        let edges = trans
+            .get_edges(SpecificVertexQuery::single(hub.id).outbound(10))
+            .unwrap();
+        assert_eq!(edges.len(), 2);
+        assert!(edges.iter().all(|edge| edge.key != keys[0]));
+    }
+
+    #[test]
+    fn should_evict_least_recently_used_lists() {
+        let options = AdjacencyCacheOptions::new().capacity(4).partitions(1);
+        let datastore = CachedDatastore::new(MemoryDatastore::default(), options);
+        let trans = datastore.transaction().unwrap();
+        let t = Type::new("foo").unwrap();
+        let vertices: Vec<Vertex> = (0..3).map(|_| Vertex::new(t.clone())).collect();
+
+        for v in &vertices {
+            trans.create_vertex(v).unwrap();
+        }
+
+        for (i, outbound_v) in vertices.iter().enumerate() {
+            for inbound_v in vertices.iter().skip(i) {
+                trans
+                    .create_edge(&EdgeKey::new(outbound_v.id, t.clone(), inbound_v.id))
+                    .unwrap();
+            }
+        }
+
+        // The lists have 3, 2 and 1 edges, so caching the last two evicts
+        // the first
+        for v in &vertices {
+            trans.get_edges(SpecificVertexQuery::single(v.id).outbound(10)).unwrap();
+        }
+
+        let stats = datastore.get_cache_stats();
+        assert_eq!((stats.entries, stats.edges), (2, 3));
+        trans
+            .get_edges(SpecificVertexQuery::single(vertices[2].id).outbound(10))
+            .unwrap();
+        assert_eq!(datastore.get_cache_stats().hits, 1);
+    }
+}
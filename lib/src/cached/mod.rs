@@ -0,0 +1,25 @@
+//! A datastore frontend that caches the adjacency lists of vertices on top
+//! of another datastore, for workloads that repeatedly traverse the same
+//! hub vertices. Vertices are hashed into partitions, each of which has its
+//! own lock and version; writes through the frontend bump the versions of
+//! the partitions they touch, which invalidates everything cached for them.
+//! This has these drawbacks:
+//!
+//! * Writes made to the underlying datastore directly, rather than through
+//!   the frontend, aren't seen until the cached lists are evicted.
+//! * A write invalidates every cached list in the partitions it touches,
+//!   not just the lists it changes. Deleting vertices invalidates every
+//!   partition, since their edges can be in any of them.
+//! * A cache miss reads the vertex's whole adjacency list, even if the query
+//!   only asks for a few edges.
+
+mod datastore;
+
+pub use self::datastore::{AdjacencyCacheOptions, AdjacencyCacheStats, CachedDatastore, CachedTransaction};
+
+#[cfg(feature = "test-suite")]
+full_test_impl!({
+    use memory::MemoryDatastore;
+
+    CachedDatastore::new(MemoryDatastore::default(), AdjacencyCacheOptions::default())
+});
@@ -0,0 +1,83 @@
+//! Sources of the current datetime, which datastores use to timestamp edges
+//! and history. Injecting a clock lets tests and import jobs control those
+//! timestamps deterministically.
+
+use chrono::offset::Utc;
+use chrono::{DateTime, Duration};
+use std::fmt::Debug;
+use std::sync::Mutex;
+
+/// Provides the current datetime.
+pub trait Clock: Debug + Send + Sync {
+    /// Gets the current datetime.
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// A clock that reads the system time. This is what datastores use unless
+/// they're given another clock.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that only changes when it's told to, for tests and for backdating
+/// imported data.
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+impl ManualClock {
+    /// Creates a new manual clock.
+    ///
+    /// # Arguments
+    /// * `now` - The datetime the clock starts at.
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self { now: Mutex::new(now) }
+    }
+
+    /// Sets the clock's datetime. The clock is allowed to go backwards.
+    ///
+    /// # Arguments
+    /// * `now` - The new datetime.
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    /// Moves the clock forward.
+    ///
+    /// # Arguments
+    /// * `duration` - How far to move the clock.
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().unwrap();
+        let advanced = *now + duration;
+        *now = advanced;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Clock, ManualClock};
+    use chrono::{Duration, Utc};
+
+    #[test]
+    fn should_only_move_manual_clocks_when_told() {
+        let start = Utc::now();
+        let clock = ManualClock::new(start);
+        assert_eq!(clock.now(), start);
+        clock.advance(Duration::seconds(5));
+        assert_eq!(clock.now(), start + Duration::seconds(5));
+        clock.set(start - Duration::days(1));
+        assert_eq!(clock.now(), start - Duration::days(1));
+    }
+}
@@ -0,0 +1,287 @@
+//! Encodes vertices, edges and properties as Apache Arrow record batches,
+//! and exports them to Parquet files, so that analytics tools can consume
+//! large results as columns rather than as one JSON object per item.
+//!
+//! Ids are encoded as hyphenated strings, and property values as JSON
+//! strings, since they have no fixed type. Edge update datetimes are encoded
+//! as nanosecond timestamps in UTC.
+
+use arrow::array::{ArrayRef, StringArray, TimestampNanosecondArray};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow::record_batch::RecordBatch;
+use errors::{Result, ValidationError};
+use export::for_each_page;
+use models;
+use models::VertexQueryExt;
+use parquet::arrow::ArrowWriter;
+use std::io::Write;
+use std::sync::Arc;
+use traits::Transaction;
+use util::signed_nanos_since_epoch;
+use uuid::Uuid;
+
+fn from_uuids<'a, I: Iterator<Item = &'a Uuid>>(ids: I) -> ArrayRef {
+    Arc::new(StringArray::from_iter_values(
+        ids.map(|id| id.to_hyphenated().to_string()),
+    ))
+}
+
+fn from_strs<'a, I: Iterator<Item = &'a str>>(values: I) -> ArrayRef {
+    Arc::new(StringArray::from_iter_values(values))
+}
+
+fn string_field(name: &str) -> Field {
+    Field::new(name, DataType::Utf8, false)
+}
+
+/// The schema of record batches of vertices: `id` and `t`.
+pub fn vertex_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![string_field("id"), string_field("t")]))
+}
+
+/// The schema of record batches of edges: `outbound_id`, `t`, `inbound_id`
+/// and `created_datetime`.
+pub fn edge_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        string_field("outbound_id"),
+        string_field("t"),
+        string_field("inbound_id"),
+        Field::new(
+            "created_datetime",
+            DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into())),
+            false,
+        ),
+    ]))
+}
+
+/// The schema of record batches of vertex properties: `id`, `name` and
+/// `value`.
+pub fn vertex_property_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        string_field("id"),
+        string_field("name"),
+        string_field("value"),
+    ]))
+}
+
+/// The schema of record batches of edge properties: `outbound_id`, `t`,
+/// `inbound_id`, `name` and `value`.
+pub fn edge_property_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        string_field("outbound_id"),
+        string_field("t"),
+        string_field("inbound_id"),
+        string_field("name"),
+        string_field("value"),
+    ]))
+}
+
+/// Encodes vertices as a record batch with the `vertex_schema`.
+pub fn vertices_to_record_batch(vertices: &[models::Vertex]) -> Result<RecordBatch> {
+    let columns = vec![
+        from_uuids(vertices.iter().map(|vertex| &vertex.id)),
+        from_strs(vertices.iter().map(|vertex| vertex.t.0.as_str())),
+    ];
+
+    Ok(RecordBatch::try_new(vertex_schema(), columns)?)
+}
+
+/// Encodes edges as a record batch with the `edge_schema`.
+///
+/// # Errors
+/// Returns a `ValidationError` if an edge's update datetime can't be
+/// represented as nanoseconds since the unix epoch.
+pub fn edges_to_record_batch(edges: &[models::Edge]) -> Result<RecordBatch> {
+    let mut datetimes = Vec::with_capacity(edges.len());
+
+    for edge in edges {
+        let nanos = signed_nanos_since_epoch(&edge.created_datetime)
+            .ok_or_else(|| ValidationError::from("Datetime is out of range"))?;
+        datetimes.push(nanos);
+    }
+
+    let columns = vec![
+        from_uuids(edges.iter().map(|edge| &edge.key.outbound_id)),
+        from_strs(edges.iter().map(|edge| edge.key.t.0.as_str())),
+        from_uuids(edges.iter().map(|edge| &edge.key.inbound_id)),
+        Arc::new(TimestampNanosecondArray::from(datetimes).with_timezone("UTC")) as ArrayRef,
+    ];
+
+    Ok(RecordBatch::try_new(edge_schema(), columns)?)
+}
+
+/// Encodes vertex properties as a record batch with the
+/// `vertex_property_schema`.
+pub fn vertex_properties_to_record_batch(properties: &[models::NamedVertexProperty]) -> Result<RecordBatch> {
+    let columns = vec![
+        from_uuids(properties.iter().map(|property| &property.id)),
+        from_strs(properties.iter().map(|property| property.name.as_str())),
+        Arc::new(StringArray::from_iter_values(
+            properties.iter().map(|property| property.value.to_string()),
+        )) as ArrayRef,
+    ];
+
+    Ok(RecordBatch::try_new(vertex_property_schema(), columns)?)
+}
+
+/// Encodes edge properties as a record batch with the
+/// `edge_property_schema`.
+pub fn edge_properties_to_record_batch(properties: &[models::NamedEdgeProperty]) -> Result<RecordBatch> {
+    let columns = vec![
+        from_uuids(properties.iter().map(|property| &property.key.outbound_id)),
+        from_strs(properties.iter().map(|property| property.key.t.0.as_str())),
+        from_uuids(properties.iter().map(|property| &property.key.inbound_id)),
+        from_strs(properties.iter().map(|property| property.name.as_str())),
+        Arc::new(StringArray::from_iter_values(
+            properties.iter().map(|property| property.value.to_string()),
+        )) as ArrayRef,
+    ];
+
+    Ok(RecordBatch::try_new(edge_property_schema(), columns)?)
+}
+
+/// Exports every vertex to a Parquet file with the `vertex_schema`. Each
+/// page of vertices is written as a row group. Returns the number of
+/// vertices written.
+///
+/// # Arguments
+/// * `trans` - The transaction to read from.
+/// * `writer` - Where to write the file.
+pub fn export_vertices_to_parquet<T, W>(trans: &T, writer: W) -> Result<u64>
+where
+    T: Transaction,
+    W: Write + Send,
+{
+    let mut writer = ArrowWriter::try_new(writer, vertex_schema(), None)?;
+    let mut count = 0;
+
+    for_each_page(trans, |q| {
+        let vertices = trans.get_vertices(q)?;
+        count += vertices.len() as u64;
+        writer.write(&vertices_to_record_batch(&vertices)?)?;
+        Ok(())
+    })?;
+
+    writer.close()?;
+    Ok(count)
+}
+
+/// Exports every edge to a Parquet file with the `edge_schema`. The edges of
+/// each page of vertices are written as a row group. Returns the number of
+/// edges written.
+///
+/// # Arguments
+/// * `trans` - The transaction to read from.
+/// * `writer` - Where to write the file.
+pub fn export_edges_to_parquet<T, W>(trans: &T, writer: W) -> Result<u64>
+where
+    T: Transaction,
+    W: Write + Send,
+{
+    let mut writer = ArrowWriter::try_new(writer, edge_schema(), None)?;
+    let mut count = 0;
+
+    for_each_page(trans, |q| {
+        let edges = trans.get_edges(q.outbound(u32::MAX))?;
+        count += edges.len() as u64;
+        writer.write(&edges_to_record_batch(&edges)?)?;
+        Ok(())
+    })?;
+
+    writer.close()?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        edge_properties_to_record_batch, edges_to_record_batch, export_edges_to_parquet, export_vertices_to_parquet,
+        vertex_properties_to_record_batch, vertices_to_record_batch,
+    };
+    use arrow::array::{Array, StringArray, TimestampNanosecondArray};
+    use arrow::record_batch::RecordBatch;
+    use memory::MemoryDatastore;
+    use models::{Edge, EdgeKey, NamedEdgeProperty, NamedVertexProperty, Type, Vertex};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use serde_json::Value as JsonValue;
+    use std::fs::{remove_file, File};
+    use traits::{Datastore, Transaction};
+    use util::{generate_temporary_path, signed_nanos_since_epoch};
+
+    fn strings(batch: &RecordBatch, column: usize) -> Vec<String> {
+        let array = batch.column(column).as_any().downcast_ref::<StringArray>().unwrap();
+        (0..array.len()).map(|i| array.value(i).to_string()).collect()
+    }
+
+    #[test]
+    fn should_encode_record_batches() {
+        let t = Type::new("user").unwrap();
+        let vertices = vec![Vertex::new(t.clone()), Vertex::new(t.clone())];
+        let batch = vertices_to_record_batch(&vertices).unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(strings(&batch, 0)[1], vertices[1].id.to_hyphenated().to_string());
+        assert_eq!(strings(&batch, 1), vec!["user", "user"]);
+
+        let key = EdgeKey::new(vertices[0].id, t.clone(), vertices[1].id);
+        let edges = vec![Edge::new_with_current_datetime(key.clone())];
+        let batch = edges_to_record_batch(&edges).unwrap();
+        assert_eq!(strings(&batch, 1), vec!["user"]);
+        assert_eq!(strings(&batch, 2), vec![vertices[1].id.to_hyphenated().to_string()]);
+        let datetimes = batch
+            .column(3)
+            .as_any()
+            .downcast_ref::<TimestampNanosecondArray>()
+            .unwrap();
+        assert_eq!(
+            Some(datetimes.value(0)),
+            signed_nanos_since_epoch(&edges[0].created_datetime)
+        );
+
+        let property = NamedVertexProperty::new(vertices[0].id, "age".to_string(), JsonValue::from(42));
+        let batch = vertex_properties_to_record_batch(&[property]).unwrap();
+        assert_eq!(strings(&batch, 1), vec!["age"]);
+        assert_eq!(strings(&batch, 2), vec!["42"]);
+
+        let property = NamedEdgeProperty::new(key, "since".to_string(), JsonValue::from("2019"));
+        let batch = edge_properties_to_record_batch(&[property]).unwrap();
+        assert_eq!(batch.num_columns(), 5);
+        assert_eq!(strings(&batch, 4), vec!["\"2019\""]);
+    }
+
+    #[test]
+    fn should_export_to_parquet() {
+        let datastore = MemoryDatastore::default();
+        let trans = datastore.transaction().unwrap();
+        let t = Type::new("user").unwrap();
+        let outbound_id = trans.create_vertex_from_type(t.clone()).unwrap();
+        let inbound_id = trans.create_vertex_from_type(t.clone()).unwrap();
+        trans.create_edge(&EdgeKey::new(outbound_id, t, inbound_id)).unwrap();
+
+        let read = |path: &str| -> Vec<RecordBatch> {
+            let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap())
+                .unwrap()
+                .build()
+                .unwrap();
+            let batches = reader.map(|batch| batch.unwrap()).collect();
+            remove_file(path).unwrap();
+            batches
+        };
+
+        let path = generate_temporary_path();
+        assert_eq!(
+            export_vertices_to_parquet(&trans, File::create(&path).unwrap()).unwrap(),
+            2
+        );
+        let rows: usize = read(&path).iter().map(|batch| batch.num_rows()).sum();
+        assert_eq!(rows, 2);
+
+        let path = generate_temporary_path();
+        assert_eq!(
+            export_edges_to_parquet(&trans, File::create(&path).unwrap()).unwrap(),
+            1
+        );
+        let batches = read(&path);
+        assert_eq!(batches.len(), 1);
+        assert_eq!(strings(&batches[0], 0), vec![outbound_id.to_hyphenated().to_string()]);
+    }
+}
@@ -0,0 +1,255 @@
+//! Discovers the schema of a graph from the data in it, for getting oriented
+//! in an unfamiliar dataset: which vertex and edge types there are, which
+//! vertex types each edge type connects, and which properties they have.
+//!
+//! Since nothing enforces a schema, this is only what was observed. Large
+//! graphs can be described from a sample, which is quicker but may miss
+//! rare types and properties.
+
+use errors::Result;
+use models;
+use models::VertexQueryExt;
+use serde_json::Value as JsonValue;
+use std::cmp::min;
+use std::collections::{BTreeMap, HashMap};
+use traits::Transaction;
+use util::next_uuid;
+use uuid::Uuid;
+
+// How many vertices are read at a time.
+const DESCRIBE_PAGE_SIZE: u32 = 1000;
+
+/// The number of property values of each JSON type.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ValueTypeHistogram {
+    /// The number of `null` values.
+    pub nulls: u64,
+
+    /// The number of boolean values.
+    pub bools: u64,
+
+    /// The number of numeric values.
+    pub numbers: u64,
+
+    /// The number of string values.
+    pub strings: u64,
+
+    /// The number of array values.
+    pub arrays: u64,
+
+    /// The number of object values.
+    pub objects: u64,
+}
+
+impl ValueTypeHistogram {
+    fn add(&mut self, value: &JsonValue) {
+        match *value {
+            JsonValue::Null => self.nulls += 1,
+            JsonValue::Bool(_) => self.bools += 1,
+            JsonValue::Number(_) => self.numbers += 1,
+            JsonValue::String(_) => self.strings += 1,
+            JsonValue::Array(_) => self.arrays += 1,
+            JsonValue::Object(_) => self.objects += 1,
+        }
+    }
+}
+
+/// What was observed about the vertices of a type.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VertexTypeDescription {
+    /// The number of vertices of the type that were read.
+    pub count: u64,
+
+    /// The names of the properties the vertices have, along with the types
+    /// of their values.
+    pub properties: BTreeMap<String, ValueTypeHistogram>,
+}
+
+/// What was observed about the edges of a type.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EdgeTypeDescription {
+    /// The number of edges of the type that were read.
+    pub count: u64,
+
+    /// The number of edges between each pair of outbound and inbound vertex
+    /// types.
+    pub endpoint_types: BTreeMap<(models::Type, models::Type), u64>,
+
+    /// The names of the properties the edges have, along with the types of
+    /// their values.
+    pub properties: BTreeMap<String, ValueTypeHistogram>,
+}
+
+/// The observed schema of a graph. See `describe`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Schema {
+    /// The vertex types that were observed.
+    pub vertex_types: BTreeMap<models::Type, VertexTypeDescription>,
+
+    /// The edge types that were observed.
+    pub edge_types: BTreeMap<models::Type, EdgeTypeDescription>,
+
+    /// Whether every vertex was read. If not, the schema was observed from a
+    /// sample.
+    pub is_complete: bool,
+}
+
+/// Describes the schema of a graph, by reading vertices along with their
+/// properties, outbound edges and the edges' properties.
+///
+/// # Arguments
+/// * `trans` - The transaction to read from.
+/// * `max_vertices` - The maximum number of vertices to read. If there are
+///   more, the ones with the lowest ids are read. Use `u32::MAX` to read
+///   every vertex.
+pub fn describe<T: Transaction>(trans: &T, max_vertices: u32) -> Result<Schema> {
+    let mut schema = Schema::default();
+
+    // The types of vertices seen so far, so that the types of inbound
+    // vertices usually don't have to be looked up
+    let mut vertex_types: HashMap<Uuid, models::Type> = HashMap::new();
+    let mut start_id = Uuid::default();
+    let mut remaining = max_vertices;
+
+    loop {
+        if remaining == 0 {
+            schema.is_complete = trans
+                .get_vertices(models::RangeVertexQuery::new(1).start_id(start_id))?
+                .is_empty();
+            return Ok(schema);
+        }
+
+        let limit = min(remaining, DESCRIBE_PAGE_SIZE);
+        let q = models::RangeVertexQuery::new(limit).start_id(start_id);
+        let page = trans.get_all_vertex_properties(q.clone())?;
+
+        for vertex_properties in &page {
+            let vertex = &vertex_properties.vertex;
+            vertex_types.insert(vertex.id, vertex.t.clone());
+            let description = schema.vertex_types.entry(vertex.t.clone()).or_default();
+            description.count += 1;
+
+            for property in &vertex_properties.props {
+                description
+                    .properties
+                    .entry(property.name.clone())
+                    .or_default()
+                    .add(&property.value);
+            }
+        }
+
+        let edge_page = trans.get_all_edge_properties(q.outbound(u32::MAX))?;
+        let mut unknown_ids: Vec<Uuid> = edge_page
+            .iter()
+            .map(|edge_properties| edge_properties.edge.key.inbound_id)
+            .filter(|id| !vertex_types.contains_key(id))
+            .collect();
+        unknown_ids.sort();
+        unknown_ids.dedup();
+
+        if !unknown_ids.is_empty() {
+            for vertex in trans.get_vertices(models::SpecificVertexQuery::new(unknown_ids))? {
+                vertex_types.insert(vertex.id, vertex.t);
+            }
+        }
+
+        for edge_properties in &edge_page {
+            let key = &edge_properties.edge.key;
+            let description = schema.edge_types.entry(key.t.clone()).or_default();
+            description.count += 1;
+
+            if let (Some(outbound_t), Some(inbound_t)) =
+                (vertex_types.get(&key.outbound_id), vertex_types.get(&key.inbound_id))
+            {
+                *description
+                    .endpoint_types
+                    .entry((outbound_t.clone(), inbound_t.clone()))
+                    .or_insert(0) += 1;
+            }
+
+            for property in &edge_properties.props {
+                description
+                    .properties
+                    .entry(property.name.clone())
+                    .or_default()
+                    .add(&property.value);
+            }
+        }
+
+        remaining -= page.len() as u32;
+
+        if page.len() < limit as usize {
+            schema.is_complete = true;
+            return Ok(schema);
+        }
+
+        match next_uuid(page.last().unwrap().vertex.id) {
+            Ok(next_id) => start_id = next_id,
+            Err(_) => {
+                schema.is_complete = true;
+                return Ok(schema);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{describe, ValueTypeHistogram};
+    use memory::MemoryDatastore;
+    use models::{EdgeKey, EdgeQueryExt, SpecificEdgeQuery, SpecificVertexQuery, Type, VertexQueryExt};
+    use serde_json::Value as JsonValue;
+    use traits::{Datastore, Transaction};
+
+    #[test]
+    fn should_describe() {
+        let datastore = MemoryDatastore::default();
+        let trans = datastore.transaction().unwrap();
+        let person_t = Type::new("person").unwrap();
+        let city_t = Type::new("city").unwrap();
+        let lives_in_t = Type::new("lives_in").unwrap();
+        let city_id = trans.create_vertex_from_type(city_t.clone()).unwrap();
+
+        for i in 0..3 {
+            let person_id = trans.create_vertex_from_type(person_t.clone()).unwrap();
+            let value = if i == 0 { JsonValue::Null } else { JsonValue::from(i) };
+            trans
+                .set_vertex_properties(SpecificVertexQuery::single(person_id).property("age"), &value)
+                .unwrap();
+            let key = EdgeKey::new(person_id, lives_in_t.clone(), city_id);
+            trans.create_edge(&key).unwrap();
+            trans
+                .set_edge_properties(
+                    SpecificEdgeQuery::single(key).property("since"),
+                    &JsonValue::from("2019"),
+                )
+                .unwrap();
+        }
+
+        let schema = describe(&trans, u32::MAX).unwrap();
+        assert!(schema.is_complete);
+        assert_eq!(schema.vertex_types.len(), 2);
+        assert_eq!(schema.vertex_types[&city_t].count, 1);
+        let person = &schema.vertex_types[&person_t];
+        assert_eq!(person.count, 3);
+        assert_eq!(
+            person.properties["age"],
+            ValueTypeHistogram {
+                nulls: 1,
+                numbers: 2,
+                ..ValueTypeHistogram::default()
+            }
+        );
+
+        let lives_in = &schema.edge_types[&lives_in_t];
+        assert_eq!(lives_in.count, 3);
+        assert_eq!(lives_in.endpoint_types[&(person_t, city_t)], 3);
+        assert_eq!(lives_in.properties["since"].strings, 3);
+
+        let schema = describe(&trans, 2).unwrap();
+        assert!(!schema.is_complete);
+        let count: u64 = schema.vertex_types.values().map(|description| description.count).sum();
+        assert_eq!(count, 2);
+        assert!(describe(&trans, 4).unwrap().is_complete);
+    }
+}
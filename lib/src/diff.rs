@@ -0,0 +1,716 @@
+//! Compares two graphs, e.g. to check that a migration or a replica produced
+//! what was expected, and applies the differences to other graphs.
+//!
+//! Changesets can be saved as patches, which are JSON lines, one change per
+//! line:
+//!
+//! ```text
+//! {"remove_edge":{"outbound_id":"...","t":"follows","inbound_id":"..."}}
+//! {"add_vertex":{"id":"...","t":"user"}}
+//! {"set_vertex_property":{"id":"...","name":"age","old_value":41,"new_value":42}}
+//! ```
+//!
+//! `old_value` is left out for added properties.
+
+use errors::{ErrorKind, Result, ValidationError};
+use export::{from_edge_key, from_uuid, to_edge_key, to_string, to_type, to_uuid, to_value, write_item};
+use models;
+use models::{EdgeQueryExt, VertexQueryExt};
+use serde_json;
+use serde_json::{Map, Value as JsonValue};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io::{BufRead, Write};
+use traits::Transaction;
+use util::next_uuid;
+use uuid::Uuid;
+
+// How many vertices are read at a time from each side.
+const DIFF_PAGE_SIZE: u32 = 1000;
+
+/// A single difference between two graphs.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    /// A vertex only exists in the second graph.
+    AddVertex { id: Uuid, t: models::Type },
+
+    /// A vertex only exists in the first graph. Its properties and edges are
+    /// implicitly removed with it.
+    RemoveVertex { id: Uuid, t: models::Type },
+
+    /// An edge only exists in the second graph.
+    AddEdge(models::EdgeKey),
+
+    /// An edge only exists in the first graph. Its properties are implicitly
+    /// removed with it.
+    RemoveEdge(models::EdgeKey),
+
+    /// A vertex property was added or modified. `old_value` is `None` for
+    /// added properties.
+    SetVertexProperty {
+        id: Uuid,
+        name: String,
+        old_value: Option<JsonValue>,
+        new_value: JsonValue,
+    },
+
+    /// A vertex property only exists in the first graph.
+    RemoveVertexProperty {
+        id: Uuid,
+        name: String,
+        old_value: JsonValue,
+    },
+
+    /// An edge property was added or modified. `old_value` is `None` for
+    /// added properties.
+    SetEdgeProperty {
+        key: models::EdgeKey,
+        name: String,
+        old_value: Option<JsonValue>,
+        new_value: JsonValue,
+    },
+
+    /// An edge property only exists in the first graph.
+    RemoveEdgeProperty {
+        key: models::EdgeKey,
+        name: String,
+        old_value: JsonValue,
+    },
+}
+
+/// The differences between two graphs.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Changeset {
+    /// The changes, ordered so that they can be made one after another:
+    /// edge removals, then vertex removals, vertex additions, edge additions
+    /// and finally property changes.
+    pub changes: Vec<Change>,
+}
+
+impl Changeset {
+    /// Returns whether the graphs were the same.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Writes the changeset as a patch.
+    ///
+    /// # Arguments
+    /// * `writer` - Where to write the patch.
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
+        for change in &self.changes {
+            let (name, value) = from_change(change);
+            write_item(&mut writer, name, value)?;
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads a patch written by `write`.
+    ///
+    /// # Arguments
+    /// * `reader` - Where to read the patch from.
+    ///
+    /// # Errors
+    /// Returns a `ValidationError` if a change is malformed.
+    pub fn read<R: BufRead>(reader: R) -> Result<Self> {
+        let mut changes = Vec::new();
+
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let change = to_change(&line).map_err(|err| ValidationError::from(format!("line {}: {}", i + 1, err)))?;
+            changes.push(change);
+        }
+
+        Ok(Self { changes })
+    }
+}
+
+/// Finds the changes that turn one graph into another.
+///
+/// Both graphs are read in pages, ordered by vertex id, so only a page of
+/// each is held in memory at a time. Pass transactions from two datastores
+/// to compare them, or two transactions from the same datastore to compare
+/// snapshots.
+///
+/// A vertex whose type differs is reported as removed and re-added, along
+/// with all of its edges and properties. Edges are found through their
+/// outbound vertex, so edges whose outbound vertex doesn't exist aren't
+/// compared.
+///
+/// # Arguments
+/// * `from` - The first graph.
+/// * `to` - The second graph.
+pub fn diff<A: Transaction, B: Transaction>(from: &A, to: &B) -> Result<Changeset> {
+    let mut from_cursor = Cursor::new(from);
+    let mut to_cursor = Cursor::new(to);
+    let mut differ = Differ::default();
+
+    loop {
+        let from_id = from_cursor.peek_id()?;
+        let to_id = to_cursor.peek_id()?;
+
+        match (from_id, to_id) {
+            (None, None) => break,
+            (Some(from_id), Some(to_id)) if from_id == to_id => {
+                let from_vertex = from_cursor.next()?.unwrap();
+                let to_vertex = to_cursor.next()?.unwrap();
+                differ.compare(from_vertex, to_vertex);
+            }
+            (Some(from_id), Some(to_id)) if from_id < to_id => differ.remove(from_cursor.next()?.unwrap()),
+            (Some(_), None) => differ.remove(from_cursor.next()?.unwrap()),
+            _ => differ.add(to_cursor.next()?.unwrap()),
+        }
+    }
+
+    // Re-typed vertices are removed along with their inbound edges, so those
+    // need to be re-added too.
+    for ids in differ.retyped_ids.clone().chunks(DIFF_PAGE_SIZE as usize) {
+        let q = models::SpecificVertexQuery::new(ids.to_vec()).inbound(u32::MAX);
+
+        for edge_properties in to.get_all_edge_properties(q)? {
+            differ.add_edge(edge_properties.edge.key, to_map(edge_properties.props));
+        }
+    }
+
+    Ok(differ.into_changeset())
+}
+
+/// Makes the changes in a changeset, in order. Old property values aren't
+/// checked, so a changeset can be applied to a graph that has drifted from
+/// the one it was made from. Use a transaction that commits atomically to
+/// apply all of the changes or none of them.
+///
+/// # Arguments
+/// * `trans` - The transaction to make the changes in.
+/// * `changeset` - The changes to make.
+///
+/// # Errors
+/// Returns a `Conflict` error if an added vertex already exists, or a
+/// `NotFound` error if an added edge's vertices don't exist. The changes
+/// before it will have been made.
+pub fn apply<T: Transaction>(trans: &T, changeset: &Changeset) -> Result<()> {
+    for change in &changeset.changes {
+        match *change {
+            Change::AddVertex { id, ref t } => {
+                if !trans.create_vertex(&models::Vertex::with_id(id, t.clone()))? {
+                    return Err(ErrorKind::Conflict(format!("vertex {}", id)).into());
+                }
+            }
+            Change::RemoveVertex { id, .. } => trans.delete_vertices(models::SpecificVertexQuery::single(id))?,
+            Change::AddEdge(ref key) => {
+                if !trans.create_edge(key)? {
+                    return Err(ErrorKind::NotFound(format!(
+                        "vertices of edge {} {} {}",
+                        key.outbound_id, key.t.0, key.inbound_id
+                    ))
+                    .into());
+                }
+            }
+            Change::RemoveEdge(ref key) => trans.delete_edges(models::SpecificEdgeQuery::single(key.clone()))?,
+            Change::SetVertexProperty {
+                id,
+                ref name,
+                ref new_value,
+                ..
+            } => trans.set_vertex_properties(
+                models::SpecificVertexQuery::single(id).property(name.as_str()),
+                new_value,
+            )?,
+            Change::RemoveVertexProperty { id, ref name, .. } => {
+                trans.delete_vertex_properties(models::SpecificVertexQuery::single(id).property(name.as_str()))?
+            }
+            Change::SetEdgeProperty {
+                ref key,
+                ref name,
+                ref new_value,
+                ..
+            } => trans.set_edge_properties(
+                models::SpecificEdgeQuery::single(key.clone()).property(name.as_str()),
+                new_value,
+            )?,
+            Change::RemoveEdgeProperty { ref key, ref name, .. } => {
+                trans.delete_edge_properties(models::SpecificEdgeQuery::single(key.clone()).property(name.as_str()))?
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// A vertex, with its properties and outbound edges.
+struct VertexState {
+    id: Uuid,
+    t: models::Type,
+    props: BTreeMap<String, JsonValue>,
+    edges: BTreeMap<models::EdgeKey, BTreeMap<String, JsonValue>>,
+}
+
+// Reads the vertices of a graph in order, a page at a time.
+struct Cursor<'a, T: Transaction + 'a> {
+    trans: &'a T,
+    page: VecDeque<VertexState>,
+    next_id: Option<Uuid>,
+}
+
+impl<'a, T: Transaction + 'a> Cursor<'a, T> {
+    fn new(trans: &'a T) -> Self {
+        Self {
+            trans,
+            page: VecDeque::new(),
+            next_id: Some(Uuid::default()),
+        }
+    }
+
+    fn peek_id(&mut self) -> Result<Option<Uuid>> {
+        if self.page.is_empty() {
+            self.read_page()?;
+        }
+
+        Ok(self.page.front().map(|vertex| vertex.id))
+    }
+
+    fn next(&mut self) -> Result<Option<VertexState>> {
+        if self.page.is_empty() {
+            self.read_page()?;
+        }
+
+        Ok(self.page.pop_front())
+    }
+
+    fn read_page(&mut self) -> Result<()> {
+        let start_id = match self.next_id.take() {
+            Some(start_id) => start_id,
+            None => return Ok(()),
+        };
+
+        let q = models::RangeVertexQuery::new(DIFF_PAGE_SIZE).start_id(start_id);
+        let vertex_properties = self.trans.get_all_vertex_properties(q.clone())?;
+        let mut edges: BTreeMap<Uuid, BTreeMap<models::EdgeKey, BTreeMap<String, JsonValue>>> = BTreeMap::new();
+
+        for edge_properties in self.trans.get_all_edge_properties(q.outbound(u32::MAX))? {
+            let key = edge_properties.edge.key;
+            edges
+                .entry(key.outbound_id)
+                .or_default()
+                .insert(key, to_map(edge_properties.props));
+        }
+
+        if vertex_properties.len() == DIFF_PAGE_SIZE as usize {
+            self.next_id = next_uuid(vertex_properties.last().unwrap().vertex.id).ok();
+        }
+
+        for vertex_properties in vertex_properties {
+            let id = vertex_properties.vertex.id;
+
+            self.page.push_back(VertexState {
+                id,
+                t: vertex_properties.vertex.t,
+                props: to_map(vertex_properties.props),
+                edges: edges.remove(&id).unwrap_or_default(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+// Collects changes into buckets, so they can be put in a workable order.
+#[derive(Default)]
+struct Differ {
+    removed_edges: Vec<Change>,
+    removed_vertices: Vec<Change>,
+    added_vertices: Vec<Change>,
+    added_edges: Vec<Change>,
+    added_edge_keys: BTreeSet<models::EdgeKey>,
+    property_changes: Vec<Change>,
+    retyped_ids: Vec<Uuid>,
+}
+
+impl Differ {
+    fn remove(&mut self, vertex: VertexState) {
+        self.removed_vertices.push(Change::RemoveVertex {
+            id: vertex.id,
+            t: vertex.t,
+        });
+    }
+
+    fn add(&mut self, vertex: VertexState) {
+        let id = vertex.id;
+        self.added_vertices.push(Change::AddVertex { id, t: vertex.t });
+
+        for (name, value) in vertex.props {
+            self.property_changes.push(Change::SetVertexProperty {
+                id,
+                name,
+                old_value: None,
+                new_value: value,
+            });
+        }
+
+        for (key, props) in vertex.edges {
+            self.add_edge(key, props);
+        }
+    }
+
+    fn add_edge(&mut self, key: models::EdgeKey, props: BTreeMap<String, JsonValue>) {
+        if !self.added_edge_keys.insert(key.clone()) {
+            return;
+        }
+
+        for (name, value) in props {
+            self.property_changes.push(Change::SetEdgeProperty {
+                key: key.clone(),
+                name,
+                old_value: None,
+                new_value: value,
+            });
+        }
+
+        self.added_edges.push(Change::AddEdge(key));
+    }
+
+    fn compare(&mut self, from: VertexState, to: VertexState) {
+        if from.t != to.t {
+            self.retyped_ids.push(from.id);
+            self.remove(from);
+            self.add(to);
+            return;
+        }
+
+        let id = from.id;
+
+        for (name, old_value, new_value) in diff_properties(from.props, to.props) {
+            self.property_changes.push(match new_value {
+                Some(new_value) => Change::SetVertexProperty {
+                    id,
+                    name,
+                    old_value,
+                    new_value,
+                },
+                None => Change::RemoveVertexProperty {
+                    id,
+                    name,
+                    old_value: old_value.unwrap(),
+                },
+            });
+        }
+
+        let mut to_edges = to.edges;
+
+        for (key, from_props) in from.edges {
+            let to_props = match to_edges.remove(&key) {
+                Some(to_props) => to_props,
+                None => {
+                    self.removed_edges.push(Change::RemoveEdge(key));
+                    continue;
+                }
+            };
+
+            for (name, old_value, new_value) in diff_properties(from_props, to_props) {
+                let key = key.clone();
+
+                self.property_changes.push(match new_value {
+                    Some(new_value) => Change::SetEdgeProperty {
+                        key,
+                        name,
+                        old_value,
+                        new_value,
+                    },
+                    None => Change::RemoveEdgeProperty {
+                        key,
+                        name,
+                        old_value: old_value.unwrap(),
+                    },
+                });
+            }
+        }
+
+        for (key, props) in to_edges {
+            self.add_edge(key, props);
+        }
+    }
+
+    fn into_changeset(self) -> Changeset {
+        let mut changes = self.removed_edges;
+        changes.extend(self.removed_vertices);
+        changes.extend(self.added_vertices);
+        changes.extend(self.added_edges);
+        changes.extend(self.property_changes);
+        Changeset { changes }
+    }
+}
+
+// Returns the name, old value and new value of each property that differs.
+fn diff_properties(
+    from: BTreeMap<String, JsonValue>,
+    mut to: BTreeMap<String, JsonValue>,
+) -> Vec<(String, Option<JsonValue>, Option<JsonValue>)> {
+    let mut changes = Vec::new();
+
+    for (name, old_value) in from {
+        match to.remove(&name) {
+            Some(ref new_value) if *new_value == old_value => (),
+            new_value => changes.push((name, Some(old_value), new_value)),
+        }
+    }
+
+    for (name, new_value) in to {
+        changes.push((name, None, Some(new_value)));
+    }
+
+    changes
+}
+
+fn from_change(change: &Change) -> (&'static str, JsonValue) {
+    let mut value = Map::new();
+
+    let name = match *change {
+        Change::AddVertex { id, ref t } | Change::RemoveVertex { id, ref t } => {
+            value.insert("id".to_string(), from_uuid(id));
+            value.insert("t".to_string(), JsonValue::String(t.0.clone()));
+
+            match *change {
+                Change::AddVertex { .. } => "add_vertex",
+                _ => "remove_vertex",
+            }
+        }
+        Change::AddEdge(ref key) => return ("add_edge", from_edge_key(key)),
+        Change::RemoveEdge(ref key) => return ("remove_edge", from_edge_key(key)),
+        Change::SetVertexProperty {
+            id,
+            ref name,
+            ref old_value,
+            ref new_value,
+        } => {
+            value.insert("id".to_string(), from_uuid(id));
+            value.insert("name".to_string(), JsonValue::String(name.clone()));
+
+            if let Some(ref old_value) = *old_value {
+                value.insert("old_value".to_string(), old_value.clone());
+            }
+
+            value.insert("new_value".to_string(), new_value.clone());
+            "set_vertex_property"
+        }
+        Change::RemoveVertexProperty {
+            id,
+            ref name,
+            ref old_value,
+        } => {
+            value.insert("id".to_string(), from_uuid(id));
+            value.insert("name".to_string(), JsonValue::String(name.clone()));
+            value.insert("old_value".to_string(), old_value.clone());
+            "remove_vertex_property"
+        }
+        Change::SetEdgeProperty {
+            ref key,
+            ref name,
+            ref old_value,
+            ref new_value,
+        } => {
+            value = from_edge_key_map(key);
+            value.insert("name".to_string(), JsonValue::String(name.clone()));
+
+            if let Some(ref old_value) = *old_value {
+                value.insert("old_value".to_string(), old_value.clone());
+            }
+
+            value.insert("new_value".to_string(), new_value.clone());
+            "set_edge_property"
+        }
+        Change::RemoveEdgeProperty {
+            ref key,
+            ref name,
+            ref old_value,
+        } => {
+            value = from_edge_key_map(key);
+            value.insert("name".to_string(), JsonValue::String(name.clone()));
+            value.insert("old_value".to_string(), old_value.clone());
+            "remove_edge_property"
+        }
+    };
+
+    (name, JsonValue::Object(value))
+}
+
+fn from_edge_key_map(key: &models::EdgeKey) -> Map<String, JsonValue> {
+    match from_edge_key(key) {
+        JsonValue::Object(value) => value,
+        _ => unreachable!(),
+    }
+}
+
+fn to_change(line: &str) -> Result<Change> {
+    let item: JsonValue = serde_json::from_str(line)?;
+
+    let (name, value) = match item {
+        JsonValue::Object(ref item) if item.len() == 1 => item.iter().next().unwrap(),
+        _ => return Err(ValidationError::from("expected an object with a single field").into()),
+    };
+
+    match name.as_str() {
+        "add_vertex" => Ok(Change::AddVertex {
+            id: to_uuid(value, "id")?,
+            t: to_type(value, "t")?,
+        }),
+        "remove_vertex" => Ok(Change::RemoveVertex {
+            id: to_uuid(value, "id")?,
+            t: to_type(value, "t")?,
+        }),
+        "add_edge" => Ok(Change::AddEdge(to_edge_key(value)?)),
+        "remove_edge" => Ok(Change::RemoveEdge(to_edge_key(value)?)),
+        "set_vertex_property" => Ok(Change::SetVertexProperty {
+            id: to_uuid(value, "id")?,
+            name: to_string(value, "name")?,
+            old_value: value.get("old_value").cloned(),
+            new_value: to_value(value, "new_value")?,
+        }),
+        "remove_vertex_property" => Ok(Change::RemoveVertexProperty {
+            id: to_uuid(value, "id")?,
+            name: to_string(value, "name")?,
+            old_value: to_value(value, "old_value")?,
+        }),
+        "set_edge_property" => Ok(Change::SetEdgeProperty {
+            key: to_edge_key(value)?,
+            name: to_string(value, "name")?,
+            old_value: value.get("old_value").cloned(),
+            new_value: to_value(value, "new_value")?,
+        }),
+        "remove_edge_property" => Ok(Change::RemoveEdgeProperty {
+            key: to_edge_key(value)?,
+            name: to_string(value, "name")?,
+            old_value: to_value(value, "old_value")?,
+        }),
+        _ => Err(ValidationError::from(format!("unknown change `{}`", name)).into()),
+    }
+}
+
+fn to_map(props: Vec<models::NamedProperty>) -> BTreeMap<String, JsonValue> {
+    props.into_iter().map(|prop| (prop.name, prop.value)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply, diff, Change, Changeset};
+    use memory::MemoryDatastore;
+    use models::{EdgeKey, EdgeQueryExt, SpecificEdgeQuery, SpecificVertexQuery, Type, Vertex, VertexQueryExt};
+    use serde_json::Value as JsonValue;
+    use traits::{Datastore, Transaction};
+
+    #[test]
+    fn should_diff_graphs() {
+        let t = Type::new("foo").unwrap();
+        let kept_v = Vertex::new(t.clone());
+        let removed_v = Vertex::new(t.clone());
+        let added_v = Vertex::new(t.clone());
+        let kept_key = EdgeKey::new(kept_v.id, t.clone(), removed_v.id);
+        let added_key = EdgeKey::new(kept_v.id, t.clone(), added_v.id);
+
+        let from = MemoryDatastore::default();
+        let from_trans = from.transaction().unwrap();
+        from_trans.create_vertex(&kept_v).unwrap();
+        from_trans.create_vertex(&removed_v).unwrap();
+        from_trans.create_edge(&kept_key).unwrap();
+        from_trans
+            .set_vertex_properties(
+                SpecificVertexQuery::single(kept_v.id).property("a"),
+                &JsonValue::from(1),
+            )
+            .unwrap();
+        from_trans
+            .set_vertex_properties(
+                SpecificVertexQuery::single(kept_v.id).property("b"),
+                &JsonValue::from(2),
+            )
+            .unwrap();
+
+        let to = MemoryDatastore::default();
+        let to_trans = to.transaction().unwrap();
+        to_trans.create_vertex(&kept_v).unwrap();
+        to_trans.create_vertex(&added_v).unwrap();
+        to_trans.create_edge(&added_key).unwrap();
+        to_trans
+            .set_vertex_properties(
+                SpecificVertexQuery::single(kept_v.id).property("a"),
+                &JsonValue::from(3),
+            )
+            .unwrap();
+        to_trans
+            .set_edge_properties(
+                SpecificEdgeQuery::single(added_key.clone()).property("c"),
+                &JsonValue::from(4),
+            )
+            .unwrap();
+
+        let changeset = diff(&from_trans, &to_trans).unwrap();
+        assert_eq!(
+            changeset.changes,
+            vec![
+                Change::RemoveEdge(kept_key),
+                Change::RemoveVertex {
+                    id: removed_v.id,
+                    t: t.clone()
+                },
+                Change::AddVertex { id: added_v.id, t },
+                Change::AddEdge(added_key.clone()),
+                Change::SetVertexProperty {
+                    id: kept_v.id,
+                    name: "a".to_string(),
+                    old_value: Some(JsonValue::from(1)),
+                    new_value: JsonValue::from(3),
+                },
+                Change::RemoveVertexProperty {
+                    id: kept_v.id,
+                    name: "b".to_string(),
+                    old_value: JsonValue::from(2),
+                },
+                Change::SetEdgeProperty {
+                    key: added_key,
+                    name: "c".to_string(),
+                    old_value: None,
+                    new_value: JsonValue::from(4),
+                },
+            ]
+        );
+
+        assert!(diff(&to_trans, &to_trans).unwrap().is_empty());
+
+        let mut patch = Vec::new();
+        changeset.write(&mut patch).unwrap();
+        let changeset = Changeset::read(&patch[..]).unwrap();
+        apply(&from_trans, &changeset).unwrap();
+        assert!(diff(&from_trans, &to_trans).unwrap().is_empty());
+        assert!(apply(&from_trans, &changeset).is_err());
+    }
+
+    #[test]
+    fn should_diff_retyped_vertices() {
+        let outbound_v = Vertex::new(Type::new("foo").unwrap());
+        let inbound_v = Vertex::new(Type::new("foo").unwrap());
+        let key = EdgeKey::new(outbound_v.id, Type::new("bar").unwrap(), inbound_v.id);
+
+        let from = MemoryDatastore::default();
+        let from_trans = from.transaction().unwrap();
+        from_trans.create_vertex(&outbound_v).unwrap();
+        from_trans.create_vertex(&inbound_v).unwrap();
+        from_trans.create_edge(&key).unwrap();
+
+        let to = MemoryDatastore::default();
+        let to_trans = to.transaction().unwrap();
+        to_trans.create_vertex(&outbound_v).unwrap();
+        to_trans
+            .create_vertex(&Vertex::with_id(inbound_v.id, Type::new("baz").unwrap()))
+            .unwrap();
+        to_trans.create_edge(&key).unwrap();
+
+        let changes = diff(&from_trans, &to_trans).unwrap().changes;
+        assert_eq!(changes.len(), 3);
+        assert_eq!(changes[2], Change::AddEdge(key));
+    }
+}
@@ -1,15 +1,91 @@
+#[cfg(feature = "arrow-encoding")]
+use arrow::error::ArrowError;
+#[cfg(feature = "arrow-encoding")]
+use parquet::errors::ParquetError;
 #[cfg(feature = "rocksdb-datastore")]
 use rocksdb::Error as RocksDbError;
+#[cfg(feature = "postgres-datastore")]
+use pg::Error as PostgresError;
+#[cfg(feature = "sqlite-datastore")]
+use rusqlite::Error as SqliteError;
+use models::Type;
 use serde_json::Error as JsonError;
+use std::io::Error as IoError;
+use uuid::Uuid;
 
 error_chain!{
     types {
         Error, ErrorKind, ResultExt, Result;
     }
 
+    links {
+        Validation(ValidationError, ValidationErrorKind);
+    }
+
     foreign_links {
         Json(JsonError);
+        Io(IoError);
         RocksDb(RocksDbError) #[cfg(feature = "rocksdb-datastore")];
+        Sqlite(SqliteError) #[cfg(feature = "sqlite-datastore")];
+        Postgres(PostgresError) #[cfg(feature = "postgres-datastore")];
+        Arrow(ArrowError) #[cfg(feature = "arrow-encoding")];
+        Parquet(ParquetError) #[cfg(feature = "arrow-encoding")];
+    }
+
+    errors {
+        /// An item that an operation requires doesn't exist.
+        NotFound(what: String) {
+            description("not found")
+            display("not found: {}", what)
+        }
+
+        /// An operation would overwrite an item that already exists.
+        Conflict(what: String) {
+            description("conflict")
+            display("conflict: {}", what)
+        }
+
+        /// A vertex couldn't be created with a given id, because a vertex
+        /// of a different type already has it.
+        AlreadyExists(id: Uuid, existing_t: Type) {
+            description("vertex already exists")
+            display("vertex {} already exists with type {}", id, existing_t.0)
+        }
+
+        /// The storage engine failed to read or write a key. The engine's
+        /// error is kept as the cause.
+        Storage(cf: String, key: Vec<u8>) {
+            description("storage error")
+            display("storage error in column family '{}' at key {:?}", cf, key)
+        }
+
+        /// A stored entry couldn't be decoded, e.g. because it was truncated
+        /// or written by an incompatible version.
+        Corrupt(what: String) {
+            description("corrupt entry")
+            display("corrupt entry: {}", what)
+        }
+
+        /// A datastore couldn't be opened because its files are locked by
+        /// another process, or another instance in this process.
+        Locked(path: String) {
+            description("datastore is locked")
+            display("datastore at '{}' is locked by another process or instance", path)
+        }
+
+        /// There aren't enough resources to run an operation right now, e.g.
+        /// because other requests have used up a memory budget. It can be
+        /// retried later.
+        Overloaded(why: String) {
+            description("overloaded")
+            display("overloaded: {}", why)
+        }
+
+        /// A query would return or touch more items than allowed.
+        QueryTooLarge(limit: u64) {
+            description("query too large")
+            display("query too large: more than {} items", limit)
+        }
     }
 }
 
@@ -0,0 +1,139 @@
+use models::{Event, EventFilter};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+type Subscribers = Vec<(EventFilter, Sender<Event>)>;
+
+/// Delivers events about changes to a datastore to subscribers in the same
+/// process. Clones share the same subscribers.
+#[derive(Clone, Debug, Default)]
+pub struct EventBus {
+    subscribers: Arc<Mutex<Subscribers>>,
+}
+
+impl EventBus {
+    /// Subscribes to events matching a filter. Subscribers are removed once
+    /// their receiver is dropped.
+    ///
+    /// # Arguments
+    /// * `filter` - The events to receive.
+    pub fn subscribe(&self, filter: EventFilter) -> Receiver<Event> {
+        let (sender, receiver) = channel();
+        self.subscribers.lock().unwrap().push((filter, sender));
+        receiver
+    }
+
+    /// Starts collecting the events for a write, to be published once it's
+    /// been applied. If there are no subscribers, nothing is collected.
+    pub fn pending(&self) -> PendingEvents {
+        let events = if self.subscribers.lock().unwrap().is_empty() {
+            None
+        } else {
+            Some(Vec::new())
+        };
+
+        PendingEvents {
+            bus: self.clone(),
+            events,
+        }
+    }
+
+    fn publish(&self, events: Vec<Event>) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+
+        for event in events {
+            subscribers.retain(|(filter, sender)| !filter.matches(&event) || sender.send(event.clone()).is_ok());
+        }
+    }
+}
+
+/// Events collected for a write that hasn't been applied yet.
+pub struct PendingEvents {
+    bus: EventBus,
+    events: Option<Vec<Event>>,
+}
+
+impl PendingEvents {
+    /// Adds an event. The event is only built if there are subscribers.
+    ///
+    /// # Arguments
+    /// * `f` - Builds the event.
+    pub fn push<F: FnOnce() -> Event>(&mut self, f: F) {
+        if let Some(ref mut events) = self.events {
+            events.push(f());
+        }
+    }
+
+    /// Publishes the collected events. This should only be called once the
+    /// write has been applied.
+    pub fn publish(self) {
+        if let Some(events) = self.events {
+            self.bus.publish(events);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use memory::MemoryDatastore;
+    use models::{EdgeKey, Event, EventFilter, EventKind, SpecificVertexQuery, Type, Vertex, VertexQueryExt};
+    use serde_json::Value as JsonValue;
+    use traits::{Datastore, Transaction};
+
+    #[test]
+    fn should_publish_events_to_subscribers() {
+        let datastore = MemoryDatastore::default();
+        let all_events = datastore.subscribe(EventFilter::new()).unwrap();
+        let edge_events = datastore.subscribe(EventFilter::new().kind(EventKind::Edge)).unwrap();
+        let trans = datastore.transaction().unwrap();
+
+        let t = Type::new("foo").unwrap();
+        let outbound_v = Vertex::new(t.clone());
+        let inbound_v = Vertex::new(t.clone());
+        trans.create_vertex(&outbound_v).unwrap();
+        trans.create_vertex(&inbound_v).unwrap();
+        let key = EdgeKey::new(outbound_v.id, t, inbound_v.id);
+        trans.create_edge(&key).unwrap();
+        let q = SpecificVertexQuery::single(outbound_v.id);
+        trans
+            .set_vertex_properties(q.clone().property("bar"), &JsonValue::Bool(true))
+            .unwrap();
+        trans.delete_vertices(q).unwrap();
+
+        let events: Vec<Event> = all_events.try_iter().collect();
+        assert_eq!(events.len(), 5);
+
+        match events[3] {
+            Event::VertexPropertySet(id, ref name, ref value) => {
+                assert_eq!(id, outbound_v.id);
+                assert_eq!(name, "bar");
+                assert_eq!(value, &JsonValue::Bool(true));
+            }
+            ref event => panic!("Unexpected event: {:?}", event),
+        }
+
+        match events[4] {
+            Event::VertexDeleted(ref vertex) => assert_eq!(vertex.id, outbound_v.id),
+            ref event => panic!("Unexpected event: {:?}", event),
+        }
+
+        let events: Vec<Event> = edge_events.try_iter().collect();
+        assert_eq!(events.len(), 1);
+
+        match events[0] {
+            Event::EdgeCreated(ref created_key) => assert_eq!(created_key, &key),
+            ref event => panic!("Unexpected event: {:?}", event),
+        }
+    }
+
+    #[test]
+    fn should_drop_disconnected_subscribers() {
+        let datastore = MemoryDatastore::default();
+        drop(datastore.subscribe(EventFilter::new()).unwrap());
+        let trans = datastore.transaction().unwrap();
+        trans.create_vertex(&Vertex::new(Type::new("foo").unwrap())).unwrap();
+        let events = datastore.subscribe(EventFilter::new()).unwrap();
+        trans.create_vertex(&Vertex::new(Type::new("foo").unwrap())).unwrap();
+        assert_eq!(events.try_iter().count(), 1);
+    }
+}
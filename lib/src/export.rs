@@ -0,0 +1,293 @@
+//! Exports datastores to, and imports them from, a portable format that
+//! works across datastore implementations.
+//!
+//! The format is JSON lines, one item per line:
+//!
+//! ```text
+//! {"vertex":{"id":"...","t":"user"}}
+//! {"vertex_property":{"id":"...","name":"age","value":42}}
+//! {"edge":{"outbound_id":"...","t":"follows","inbound_id":"..."}}
+//! {"edge_property":{"outbound_id":"...","t":"follows","inbound_id":"...","name":"since","value":2019}}
+//! ```
+//!
+//! Every vertex is written before any edge. Edge creation datetimes aren't
+//! kept, so imported edges are created at the time of the import.
+
+use errors::{Result, ValidationError};
+use models;
+use models::VertexQueryExt;
+use serde_json;
+use serde_json::{Map, Value as JsonValue};
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+use traits::{Datastore, Transaction};
+use util::next_uuid;
+use uuid::Uuid;
+
+// How many vertices are read at a time while exporting.
+const EXPORT_PAGE_SIZE: u32 = 1000;
+
+// How many items are inserted at a time while importing.
+const IMPORT_BATCH_SIZE: usize = 10_000;
+
+/// Exports every vertex, edge and property. Returns the number of items
+/// written.
+///
+/// # Arguments
+/// * `trans` - The transaction to read from.
+/// * `writer` - Where to write the items.
+pub fn export<T: Transaction, W: Write>(trans: &T, mut writer: W) -> Result<u64> {
+    let mut count = 0;
+
+    for_each_page(trans, |q| {
+        for vertex_properties in trans.get_all_vertex_properties(q)? {
+            let id = vertex_properties.vertex.id;
+            count += write_item(&mut writer, "vertex", from_vertex(&vertex_properties.vertex))?;
+
+            for property in vertex_properties.props {
+                let mut value = Map::new();
+                value.insert("id".to_string(), from_uuid(id));
+                value.insert("name".to_string(), JsonValue::String(property.name));
+                value.insert("value".to_string(), property.value);
+                count += write_item(&mut writer, "vertex_property", JsonValue::Object(value))?;
+            }
+        }
+
+        Ok(())
+    })?;
+
+    for_each_page(trans, |q| {
+        for edge_properties in trans.get_all_edge_properties(q.outbound(u32::MAX))? {
+            let key = &edge_properties.edge.key;
+            count += write_item(&mut writer, "edge", from_edge_key(key))?;
+
+            for property in edge_properties.props {
+                let mut value = match from_edge_key(key) {
+                    JsonValue::Object(value) => value,
+                    _ => unreachable!(),
+                };
+
+                value.insert("name".to_string(), JsonValue::String(property.name));
+                value.insert("value".to_string(), property.value);
+                count += write_item(&mut writer, "edge_property", JsonValue::Object(value))?;
+            }
+        }
+
+        Ok(())
+    })?;
+
+    writer.flush()?;
+    Ok(count)
+}
+
+/// Imports items written by `export`. Returns the number of items read.
+///
+/// # Arguments
+/// * `datastore` - The datastore to insert into.
+/// * `reader` - Where to read the items from.
+///
+/// # Errors
+/// Returns a `ValidationError` if an item is malformed, in which case the
+/// items before it may have been imported.
+pub fn import<D: Datastore, R: BufRead>(datastore: &D, reader: R) -> Result<u64> {
+    let mut count = 0;
+    let mut items = Vec::with_capacity(IMPORT_BATCH_SIZE);
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let item = to_item(&line).map_err(|err| ValidationError::from(format!("line {}: {}", i + 1, err)))?;
+        items.push(item);
+        count += 1;
+
+        if items.len() >= IMPORT_BATCH_SIZE {
+            datastore.bulk_insert(items.drain(..))?;
+        }
+    }
+
+    datastore.bulk_insert(items.into_iter())?;
+    Ok(count)
+}
+
+pub(crate) fn for_each_page<T, F>(trans: &T, mut f: F) -> Result<()>
+where
+    T: Transaction,
+    F: FnMut(models::RangeVertexQuery) -> Result<()>,
+{
+    let mut start_id = Uuid::default();
+
+    loop {
+        let q = models::RangeVertexQuery::new(EXPORT_PAGE_SIZE).start_id(start_id);
+        let vertices = trans.get_vertices(q.clone())?;
+
+        if vertices.is_empty() {
+            return Ok(());
+        }
+
+        f(q)?;
+
+        // The page is re-read by `f`, which is fine since the range starts
+        // at a fixed id.
+        match next_uuid(vertices.last().unwrap().id) {
+            Ok(next_id) => start_id = next_id,
+            Err(_) => return Ok(()),
+        }
+
+        if vertices.len() < EXPORT_PAGE_SIZE as usize {
+            return Ok(());
+        }
+    }
+}
+
+pub(crate) fn write_item<W: Write>(writer: &mut W, name: &str, value: JsonValue) -> Result<u64> {
+    let mut item = Map::new();
+    item.insert(name.to_string(), value);
+    writeln!(writer, "{}", JsonValue::Object(item))?;
+    Ok(1)
+}
+
+pub(crate) fn from_uuid(id: Uuid) -> JsonValue {
+    JsonValue::String(id.to_hyphenated().to_string())
+}
+
+fn from_vertex(vertex: &models::Vertex) -> JsonValue {
+    let mut value = Map::new();
+    value.insert("id".to_string(), from_uuid(vertex.id));
+    value.insert("t".to_string(), JsonValue::String(vertex.t.0.clone()));
+    JsonValue::Object(value)
+}
+
+pub(crate) fn from_edge_key(key: &models::EdgeKey) -> JsonValue {
+    let mut value = Map::new();
+    value.insert("outbound_id".to_string(), from_uuid(key.outbound_id));
+    value.insert("t".to_string(), JsonValue::String(key.t.0.clone()));
+    value.insert("inbound_id".to_string(), from_uuid(key.inbound_id));
+    JsonValue::Object(value)
+}
+
+fn to_item(line: &str) -> Result<models::BulkInsertItem> {
+    let item: JsonValue = serde_json::from_str(line)?;
+
+    let (name, value) = match item {
+        JsonValue::Object(ref item) if item.len() == 1 => item.iter().next().unwrap(),
+        _ => return Err(ValidationError::from("expected an object with a single field").into()),
+    };
+
+    match name.as_str() {
+        "vertex" => Ok(models::BulkInsertItem::Vertex(models::Vertex::with_id(
+            to_uuid(value, "id")?,
+            to_type(value, "t")?,
+        ))),
+        "vertex_property" => Ok(models::BulkInsertItem::VertexProperty(
+            to_uuid(value, "id")?,
+            to_string(value, "name")?,
+            to_value(value, "value")?,
+        )),
+        "edge" => Ok(models::BulkInsertItem::Edge(to_edge_key(value)?)),
+        "edge_property" => Ok(models::BulkInsertItem::EdgeProperty(
+            to_edge_key(value)?,
+            to_string(value, "name")?,
+            to_value(value, "value")?,
+        )),
+        _ => Err(ValidationError::from(format!("unknown item `{}`", name)).into()),
+    }
+}
+
+pub(crate) fn to_string(value: &JsonValue, field: &str) -> Result<String> {
+    match value.get(field) {
+        Some(JsonValue::String(s)) => Ok(s.clone()),
+        _ => Err(ValidationError::from(format!("expected `{}` to be a string", field)).into()),
+    }
+}
+
+pub(crate) fn to_uuid(value: &JsonValue, field: &str) -> Result<Uuid> {
+    Uuid::from_str(&to_string(value, field)?)
+        .map_err(|_| ValidationError::from(format!("expected `{}` to be a uuid", field)).into())
+}
+
+pub(crate) fn to_type(value: &JsonValue, field: &str) -> Result<models::Type> {
+    Ok(models::Type::new(to_string(value, field)?)?)
+}
+
+pub(crate) fn to_value(value: &JsonValue, field: &str) -> Result<JsonValue> {
+    match value.get(field) {
+        Some(value) => Ok(value.clone()),
+        None => Err(ValidationError::from(format!("expected a `{}`", field)).into()),
+    }
+}
+
+pub(crate) fn to_edge_key(value: &JsonValue) -> Result<models::EdgeKey> {
+    Ok(models::EdgeKey::new(
+        to_uuid(value, "outbound_id")?,
+        to_type(value, "t")?,
+        to_uuid(value, "inbound_id")?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{export, import};
+    use memory::MemoryDatastore;
+    use models::{EdgeKey, EdgeQueryExt, SpecificEdgeQuery, SpecificVertexQuery, Type, Vertex, VertexQueryExt};
+    use serde_json::Value as JsonValue;
+    use traits::{Datastore, Transaction};
+
+    #[test]
+    fn should_export_and_import() {
+        let datastore = MemoryDatastore::default();
+        let trans = datastore.transaction().unwrap();
+        let t = Type::new("foo").unwrap();
+        let outbound_v = Vertex::new(t.clone());
+        let inbound_v = Vertex::new(t.clone());
+        trans.create_vertex(&outbound_v).unwrap();
+        trans.create_vertex(&inbound_v).unwrap();
+        let key = EdgeKey::new(outbound_v.id, t, inbound_v.id);
+        trans.create_edge(&key).unwrap();
+        trans
+            .set_vertex_properties(
+                SpecificVertexQuery::single(outbound_v.id).property("a"),
+                &JsonValue::from(1),
+            )
+            .unwrap();
+        trans
+            .set_edge_properties(
+                SpecificEdgeQuery::single(key.clone()).property("b"),
+                &JsonValue::from(2),
+            )
+            .unwrap();
+
+        let mut buf = Vec::new();
+        assert_eq!(export(&trans, &mut buf).unwrap(), 5);
+
+        let imported = MemoryDatastore::default();
+        assert_eq!(import(&imported, &buf[..]).unwrap(), 5);
+        let imported_trans = imported.transaction().unwrap();
+        assert_eq!(imported_trans.get_vertex_count().unwrap(), 2);
+        assert_eq!(
+            imported_trans
+                .get_edges(SpecificEdgeQuery::single(key.clone()))
+                .unwrap()
+                .len(),
+            1
+        );
+        let properties = imported_trans
+            .get_vertex_properties(SpecificVertexQuery::single(outbound_v.id).property("a"))
+            .unwrap();
+        assert_eq!(properties[0].value, JsonValue::from(1));
+        let properties = imported_trans
+            .get_edge_properties(SpecificEdgeQuery::single(key).property("b"))
+            .unwrap();
+        assert_eq!(properties[0].value, JsonValue::from(2));
+    }
+
+    #[test]
+    fn should_not_import_malformed_items() {
+        let datastore = MemoryDatastore::default();
+        let result = import(&datastore, &b"{\"vertex\":{\"id\":\"nope\",\"t\":\"foo\"}}\n"[..]);
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,235 @@
+//! HyperLogLog sketches, for estimating how many distinct items there are
+//! without keeping track of every one of them. A sketch takes a fixed
+//! amount of memory, set by its precision, and sketches of the same
+//! precision can be merged, so counts can be gathered in pieces (e.g. per
+//! time window) and combined later.
+
+use errors::{ValidationError, ValidationResult};
+
+/// The lowest supported precision.
+pub const MIN_HLL_PRECISION: u8 = 4;
+
+/// The highest supported precision.
+pub const MAX_HLL_PRECISION: u8 = 16;
+
+// The precision of sketches made with `HyperLogLog::default`, which has a
+// standard error of about 1.6% and takes 4kb.
+const DEFAULT_HLL_PRECISION: u8 = 12;
+
+/// A HyperLogLog sketch of a set of items. Estimates have a standard error
+/// of about `1.04 / sqrt(2 ^ precision)`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HyperLogLog {
+    // The highest rank seen for each register. There are
+    // `2 ^ precision` of them.
+    registers: Vec<u8>,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        HyperLogLog::new(DEFAULT_HLL_PRECISION).unwrap()
+    }
+}
+
+impl HyperLogLog {
+    /// Creates a new, empty sketch.
+    ///
+    /// # Arguments
+    /// * `precision` - The number of bits of each item's hash used to pick
+    ///   its register. Higher precisions are more accurate, but take more
+    ///   memory: the sketch takes `2 ^ precision` bytes.
+    ///
+    /// # Errors
+    /// Returns a `ValidationError` if the precision isn't between
+    /// `MIN_HLL_PRECISION` and `MAX_HLL_PRECISION`.
+    pub fn new(precision: u8) -> ValidationResult<Self> {
+        if !(MIN_HLL_PRECISION..=MAX_HLL_PRECISION).contains(&precision) {
+            return Err(format!(
+                "HyperLogLog precision must be between {} and {}",
+                MIN_HLL_PRECISION, MAX_HLL_PRECISION
+            )
+            .into());
+        }
+
+        Ok(HyperLogLog {
+            registers: vec![0; 1 << precision],
+        })
+    }
+
+    /// Reads a sketch from the bytes returned by `as_bytes`.
+    ///
+    /// # Errors
+    /// Returns a `ValidationError` if the bytes aren't a sketch.
+    pub fn from_bytes(bytes: &[u8]) -> ValidationResult<Self> {
+        let is_valid_len = (MIN_HLL_PRECISION..=MAX_HLL_PRECISION).any(|precision| bytes.len() == 1 << precision);
+
+        if !is_valid_len {
+            return Err(ValidationError::from("Invalid HyperLogLog sketch length"));
+        }
+
+        Ok(HyperLogLog {
+            registers: bytes.to_vec(),
+        })
+    }
+
+    /// Gets the sketch as bytes, which can be stored and read back with
+    /// `from_bytes`.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.registers
+    }
+
+    /// Gets the precision of the sketch.
+    pub fn precision(&self) -> u8 {
+        self.registers.len().trailing_zeros() as u8
+    }
+
+    /// Adds an item to the sketch. Items are compared by their bytes.
+    ///
+    /// # Arguments
+    /// * `item` - The item.
+    pub fn insert(&mut self, item: &[u8]) {
+        let (index, rank) = register_update(self.precision(), item);
+        self.update(index, rank);
+    }
+
+    /// Merges another sketch into this one, so that this one estimates the
+    /// number of distinct items added to either of them.
+    ///
+    /// # Arguments
+    /// * `other` - The other sketch.
+    ///
+    /// # Errors
+    /// Returns a `ValidationError` if the sketches have different
+    /// precisions.
+    pub fn merge(&mut self, other: &HyperLogLog) -> ValidationResult<()> {
+        if self.registers.len() != other.registers.len() {
+            return Err("Can't merge HyperLogLog sketches with different precisions".into());
+        }
+
+        for (register, &other_register) in self.registers.iter_mut().zip(&other.registers) {
+            if other_register > *register {
+                *register = other_register;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Estimates the number of distinct items added to the sketch.
+    pub fn estimate(&self) -> u64 {
+        let m = self.registers.len() as f64;
+
+        let alpha = match self.registers.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+
+        let sum: f64 = self
+            .registers
+            .iter()
+            .map(|&register| 1.0 / (1u64 << register) as f64)
+            .sum();
+        let estimate = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|&&register| register == 0).count();
+
+        // Small cardinalities are estimated more accurately by counting the
+        // registers that are still empty
+        if estimate <= 2.5 * m && zeros > 0 {
+            (m * (m / zeros as f64).ln()).round() as u64
+        } else {
+            estimate.round() as u64
+        }
+    }
+
+    // Raises a register to a rank, if it's lower.
+    pub(crate) fn update(&mut self, index: usize, rank: u8) {
+        if let Some(register) = self.registers.get_mut(index) {
+            if rank > *register {
+                *register = rank;
+            }
+        }
+    }
+}
+
+// Gets the register an item falls in for a precision, and the rank it
+// raises that register to. This is split out from `insert` so that updates
+// can be stored as merge operands without building a whole sketch.
+pub(crate) fn register_update(precision: u8, item: &[u8]) -> (usize, u8) {
+    let hash = hash(item);
+    let index = (hash >> (64 - u32::from(precision))) as usize;
+    let rest = hash << precision;
+    let rank = (rest.leading_zeros() + 1).min(64 - u32::from(precision) + 1);
+    (index, rank as u8)
+}
+
+// Hashes an item with 64-bit FNV-1a, followed by MurmurHash3's finalizer to
+// spread the bits. The hash has to be stable across processes and versions,
+// since sketches are persisted.
+fn hash(item: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+
+    for &byte in item {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^ (hash >> 33)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HyperLogLog;
+
+    fn assert_close(estimate: u64, expected: u64) {
+        let error = (estimate as f64 - expected as f64).abs() / expected as f64;
+        assert!(error < 0.05, "estimated {} for {}", estimate, expected);
+    }
+
+    #[test]
+    fn should_estimate_distinct_items() {
+        let mut sketch = HyperLogLog::default();
+        assert_eq!(sketch.estimate(), 0);
+
+        for i in 0..10u32 {
+            // Duplicates don't count
+            sketch.insert(&i.to_be_bytes());
+            sketch.insert(&i.to_be_bytes());
+        }
+
+        assert_eq!(sketch.estimate(), 10);
+
+        for i in 0..100_000u32 {
+            sketch.insert(&i.to_be_bytes());
+        }
+
+        assert_close(sketch.estimate(), 100_000);
+        assert_eq!(HyperLogLog::from_bytes(sketch.as_bytes()).unwrap(), sketch);
+        assert!(HyperLogLog::from_bytes(&[0; 100]).is_err());
+        assert!(HyperLogLog::new(3).is_err());
+        assert!(HyperLogLog::new(17).is_err());
+    }
+
+    #[test]
+    fn should_merge_sketches() {
+        let mut first = HyperLogLog::new(10).unwrap();
+        let mut second = HyperLogLog::new(10).unwrap();
+
+        for i in 0..3000u32 {
+            first.insert(&i.to_be_bytes());
+        }
+
+        for i in 2000..5000u32 {
+            second.insert(&i.to_be_bytes());
+        }
+
+        first.merge(&second).unwrap();
+        assert_close(first.estimate(), 5000);
+        assert!(first.merge(&HyperLogLog::new(11).unwrap()).is_err());
+    }
+}
@@ -24,10 +24,24 @@ extern crate regex;
 extern crate serde_json;
 extern crate uuid;
 
+#[cfg(feature = "arrow-encoding")]
+extern crate arrow;
+#[cfg(feature = "arrow-encoding")]
+extern crate parquet;
 #[cfg(feature = "rocksdb-datastore")]
 extern crate rocksdb;
 #[cfg(feature = "rocksdb-datastore")]
 extern crate byteorder;
+#[cfg(feature = "rocksdb-datastore")]
+extern crate roaring;
+#[cfg(feature = "sqlite-datastore")]
+#[macro_use]
+extern crate rusqlite;
+#[cfg(feature = "postgres-datastore")]
+extern crate postgres as pg;
+
+#[cfg(test)]
+extern crate quickcheck;
 
 #[cfg(feature = "test-suite")]
 #[macro_use]
@@ -37,18 +51,93 @@ pub mod tests;
 #[macro_use]
 pub mod benches;
 
+mod admission;
+mod cached;
+mod clock;
+mod describe;
+mod diff;
 mod errors;
+mod events;
+mod export;
+mod hll;
+mod live;
 mod memory;
+#[cfg(feature = "mock")]
+pub mod mock;
 mod models;
+mod overlay;
+mod pattern;
+mod plugins;
+mod reconcile;
+mod replicated;
+mod sharded;
+mod statistics;
+mod tensors;
 mod traits;
+mod traversal;
 pub mod util;
+#[cfg(feature = "workload")]
+pub mod workload;
 
+pub use admission::{AdmissionOptions, HeapSize, MemoryBudget, MemoryReservation};
+pub use cached::{AdjacencyCacheOptions, AdjacencyCacheStats, CachedDatastore, CachedTransaction};
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use describe::{describe, EdgeTypeDescription, Schema, ValueTypeHistogram, VertexTypeDescription};
+pub use diff::{apply, diff, Change, Changeset};
 pub use errors::*;
-pub use memory::{MemoryDatastore, MemoryTransaction};
+pub use export::{export, import};
+pub use hll::{HyperLogLog, MAX_HLL_PRECISION, MIN_HLL_PRECISION};
+pub use live::{LiveQuery, LiveQueryChange};
+pub use memory::{MemoryDatastore, MemoryTransaction, RetentionPolicy};
+#[cfg(feature = "rocksdb-datastore")]
+pub use memory::SpillOptions;
 pub use models::*;
+pub use overlay::{OverlayDatastore, OverlayTransaction};
+pub use pattern::{match_pattern, match_pattern_with_statistics, Pattern};
+pub use plugins::{DynTransaction, Plugin, PluginDeclaration, PluginRegistrar, PLUGIN_API_VERSION};
+pub use reconcile::{MergeOptions, MergePolicy, Reconciler};
+pub use replicated::{ReadConsistency, ReplicatedDatastore};
+pub use sharded::{ShardedDatastore, ShardedTransaction};
+pub use statistics::{
+    sample_statistics, EdgeTypeStatistics, GraphStatistics, PropertySketch, StatisticsOptions, StatisticsService,
+    VertexTypeStatistics,
+};
+pub use tensors::export_edge_index;
 pub use traits::*;
+pub use traversal::{
+    decayed_scores, decayed_weight, score_links, simple_paths, time_respecting_reachable, top_neighbors,
+    transitive_closure, two_coloring, LinkScore, RankBy, TwoColoring,
+};
+
+#[cfg(feature = "arrow-encoding")]
+mod columnar;
+#[cfg(feature = "arrow-encoding")]
+pub use columnar::{
+    edge_properties_to_record_batch, edge_property_schema, edge_schema, edges_to_record_batch,
+    export_edges_to_parquet, export_vertices_to_parquet, vertex_properties_to_record_batch, vertex_property_schema,
+    vertex_schema, vertices_to_record_batch,
+};
 
 #[cfg(feature = "rocksdb-datastore")]
 mod rdb;
+#[cfg(all(feature = "rocksdb-datastore", feature = "testing"))]
+pub use rdb::bytes;
+#[cfg(feature = "rocksdb-datastore")]
+pub use rdb::{
+    AutoTuneOptions, BackgroundTaskMetrics, BatchTransaction, CoalesceOptions, CommitOptions, CorruptEntry,
+    DistributedDatastore, DistributedTransaction, IndexBackfillProgress, ObjectStore, OrderedKvClient,
+    OrderedKvTransaction, ResourceOptions, RocksdbDatastore, RocksdbTransaction, Savepoint, ScanOptions, SnapshotTag,
+    TieredDatastore, TieredOptions, TieredStore, TieredTransaction, TuningChange, TypeStats, WriteCoalescer,
+};
 #[cfg(feature = "rocksdb-datastore")]
-pub use rdb::{RocksdbDatastore, RocksdbTransaction};
+pub use roaring::RoaringBitmap;
+
+#[cfg(feature = "sqlite-datastore")]
+mod sqlite;
+#[cfg(feature = "sqlite-datastore")]
+pub use sqlite::{SqliteDatastore, SqliteTransaction};
+
+#[cfg(feature = "postgres-datastore")]
+mod postgres;
+#[cfg(feature = "postgres-datastore")]
+pub use postgres::{PostgresDatastore, PostgresTransaction};
@@ -0,0 +1,205 @@
+use errors::Result;
+use models::{EdgeKey, EdgeQuery, Event, EventFilter, EventKind, Type, Vertex, VertexQuery};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::Duration;
+use traits::{Datastore, Transaction};
+use uuid::Uuid;
+
+/// A change to the results of a live query.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LiveQueryChange {
+    /// A vertex was added to the results.
+    VertexAdded(Vertex),
+
+    /// A vertex was removed from the results.
+    VertexRemoved(Uuid),
+
+    /// An edge was added to the results.
+    EdgeAdded(EdgeKey),
+
+    /// An edge was removed from the results.
+    EdgeRemoved(EdgeKey),
+}
+
+#[derive(Debug)]
+enum LiveQueryResults {
+    Vertices(VertexQuery, BTreeMap<Uuid, Type>),
+    Edges(EdgeQuery, BTreeSet<EdgeKey>),
+}
+
+/// A query whose results are kept up to date as the datastore changes.
+///
+/// The query is re-run whenever a vertex or edge is created or deleted, and
+/// the difference from the previous results is reported as changes. Property
+/// changes aren't tracked.
+pub struct LiveQuery<T: Transaction> {
+    trans: T,
+    events: Receiver<Event>,
+    results: LiveQueryResults,
+    is_started: bool,
+}
+
+impl<T: Transaction> LiveQuery<T> {
+    /// Creates a live query for vertices.
+    ///
+    /// # Arguments
+    /// * `datastore` - The datastore to query.
+    /// * `q` - The vertex query to run.
+    ///
+    /// # Errors
+    /// Returns an error if the datastore doesn't support subscriptions.
+    pub fn vertices<D: Datastore<Trans = T>>(datastore: &D, q: VertexQuery) -> Result<Self> {
+        Self::new(datastore, LiveQueryResults::Vertices(q, BTreeMap::new()))
+    }
+
+    /// Creates a live query for edges.
+    ///
+    /// # Arguments
+    /// * `datastore` - The datastore to query.
+    /// * `q` - The edge query to run.
+    ///
+    /// # Errors
+    /// Returns an error if the datastore doesn't support subscriptions.
+    pub fn edges<D: Datastore<Trans = T>>(datastore: &D, q: EdgeQuery) -> Result<Self> {
+        Self::new(datastore, LiveQueryResults::Edges(q, BTreeSet::new()))
+    }
+
+    fn new<D: Datastore<Trans = T>>(datastore: &D, results: LiveQueryResults) -> Result<Self> {
+        // Subscribe before querying, so that no changes are missed between
+        // the two.
+        let events = datastore.subscribe(EventFilter::new().kind(EventKind::Vertex).kind(EventKind::Edge))?;
+
+        Ok(Self {
+            trans: datastore.transaction()?,
+            events,
+            results,
+            is_started: false,
+        })
+    }
+
+    /// Gets the changes to the query's results. The first call returns the
+    /// current results as additions without waiting. Later calls wait up to
+    /// `timeout` for the datastore to change, and return an empty list if it
+    /// doesn't, or if the change doesn't affect the results.
+    ///
+    /// # Arguments
+    /// * `timeout` - How long to wait for the datastore to change.
+    ///
+    /// # Errors
+    /// Returns an error if the query fails, or if the datastore has stopped
+    /// publishing events.
+    pub fn changes(&mut self, timeout: Duration) -> Result<Vec<LiveQueryChange>> {
+        if self.is_started {
+            match self.events.recv_timeout(timeout) {
+                Ok(_) => {}
+                Err(RecvTimeoutError::Timeout) => return Ok(vec![]),
+                Err(RecvTimeoutError::Disconnected) => return Err("The datastore stopped publishing events".into()),
+            }
+
+            // Several writes may have happened since; one query covers them
+            // all.
+            while self.events.try_recv().is_ok() {}
+        } else {
+            self.is_started = true;
+        }
+
+        match self.results {
+            LiveQueryResults::Vertices(ref q, ref mut results) => {
+                let vertices: BTreeMap<Uuid, Type> = self
+                    .trans
+                    .get_vertices(q.clone())?
+                    .into_iter()
+                    .map(|vertex| (vertex.id, vertex.t))
+                    .collect();
+
+                let mut changes: Vec<LiveQueryChange> = results
+                    .keys()
+                    .filter(|id| !vertices.contains_key(id))
+                    .map(|id| LiveQueryChange::VertexRemoved(*id))
+                    .collect();
+
+                changes.extend(
+                    vertices
+                        .iter()
+                        .filter(|(id, _)| !results.contains_key(id))
+                        .map(|(id, t)| LiveQueryChange::VertexAdded(Vertex::with_id(*id, t.clone()))),
+                );
+
+                *results = vertices;
+                Ok(changes)
+            }
+            LiveQueryResults::Edges(ref q, ref mut results) => {
+                let keys: BTreeSet<EdgeKey> = self
+                    .trans
+                    .get_edges(q.clone())?
+                    .into_iter()
+                    .map(|edge| edge.key)
+                    .collect();
+
+                let mut changes: Vec<LiveQueryChange> = results
+                    .difference(&keys)
+                    .map(|key| LiveQueryChange::EdgeRemoved(key.clone()))
+                    .collect();
+
+                changes.extend(
+                    keys.difference(results)
+                        .map(|key| LiveQueryChange::EdgeAdded(key.clone())),
+                );
+
+                *results = keys;
+                Ok(changes)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LiveQuery, LiveQueryChange};
+    use memory::MemoryDatastore;
+    use models::{EdgeKey, RangeVertexQuery, SpecificVertexQuery, Type, Vertex, VertexQueryExt};
+    use std::time::Duration;
+    use traits::{Datastore, Transaction};
+
+    #[test]
+    fn should_report_live_query_changes() {
+        let datastore = MemoryDatastore::default();
+        let trans = datastore.transaction().unwrap();
+        let t = Type::new("foo").unwrap();
+        let first_v = Vertex::new(t.clone());
+        trans.create_vertex(&first_v).unwrap();
+
+        let q = RangeVertexQuery::new(10).t(t.clone());
+        let mut vertices = LiveQuery::vertices(&datastore, q.clone().into()).unwrap();
+        let mut edges = LiveQuery::edges(&datastore, q.outbound(10).into()).unwrap();
+        let timeout = Duration::from_millis(10);
+
+        assert_eq!(
+            vertices.changes(timeout).unwrap(),
+            vec![LiveQueryChange::VertexAdded(first_v.clone())]
+        );
+        assert_eq!(edges.changes(timeout).unwrap(), vec![]);
+        assert_eq!(vertices.changes(timeout).unwrap(), vec![]);
+
+        let second_v = Vertex::new(t.clone());
+        trans.create_vertex(&second_v).unwrap();
+        let key = EdgeKey::new(first_v.id, t, second_v.id);
+        trans.create_edge(&key).unwrap();
+        assert_eq!(
+            vertices.changes(timeout).unwrap(),
+            vec![LiveQueryChange::VertexAdded(second_v)]
+        );
+        assert_eq!(
+            edges.changes(timeout).unwrap(),
+            vec![LiveQueryChange::EdgeAdded(key.clone())]
+        );
+
+        trans.delete_vertices(SpecificVertexQuery::single(first_v.id)).unwrap();
+        assert_eq!(
+            vertices.changes(timeout).unwrap(),
+            vec![LiveQueryChange::VertexRemoved(first_v.id)]
+        );
+        assert_eq!(edges.changes(timeout).unwrap(), vec![LiveQueryChange::EdgeRemoved(key)]);
+    }
+}
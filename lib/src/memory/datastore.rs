@@ -1,50 +1,278 @@
 use super::super::{Datastore, EdgePropertyQuery, EdgeQuery, Transaction, VertexPropertyQuery, VertexQuery};
+use super::history;
+use super::history::{History, RetentionPolicy};
+#[cfg(feature = "rocksdb-datastore")]
+use super::spill::{estimate_size, SpillOptions, SpillStore};
 use chrono::offset::Utc;
 use chrono::DateTime;
+use clock::{Clock, SystemClock};
 use errors::Result;
+use events::EventBus;
 use models;
 use serde_json::Value as JsonValue;
-use std::collections::{BTreeMap, HashSet};
-use std::sync::{Arc, RwLock};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex, RwLock, RwLockWriteGuard};
+use traits::missing_edge_vertices;
 use uuid::Uuid;
 
+// The number of shards the data is partitioned into. Each shard has its own
+// lock, so writes to different shards don't contend with each other.
+const SHARD_COUNT: usize = 16;
+
+// Vertices are assigned to shards by a hash of their id. Every byte is mixed
+// in, since the version 1 UUIDs that `Vertex::new` generates only differ in
+// a few of them. Vertex properties live on the shard of their vertex, and
+// edges and edge properties on the shard of their outbound vertex.
+fn shard_index(id: Uuid) -> usize {
+    let hash = id.as_bytes().iter().fold(0, |hash, byte| hash ^ byte);
+    hash as usize % SHARD_COUNT
+}
+
+// A property value, which is either held in memory or has been spilled to
+// disk. Resident values are tagged with the tick they were written at, so
+// that the least recently written ones can be spilled first.
+#[derive(Clone, Debug)]
+#[cfg_attr(not(feature = "rocksdb-datastore"), allow(dead_code))]
+enum PropertyValue {
+    Resident(JsonValue, u64),
+    #[cfg(feature = "rocksdb-datastore")]
+    Spilled(u64),
+}
+
+#[derive(Clone, Debug, Default)]
+struct Shard {
+    edge_properties: BTreeMap<(models::EdgeKey, String), PropertyValue>,
+    edges: BTreeMap<models::EdgeKey, DateTime<Utc>>,
+    vertex_properties: BTreeMap<(Uuid, String), PropertyValue>,
+    vertices: BTreeMap<Uuid, models::Type>,
+}
+
+// Recorded responses by idempotency key, along with when they expire.
+type IdempotencyRecords = HashMap<String, (DateTime<Utc>, Vec<u8>)>;
+
 // All of the data is actually stored in this struct, which is stored
-// internally to the datastore itself. This way, we can wrap an rwlock around
-// the entire datastore, rather than on a per-data structure basis, as the
-// latter approach would risk deadlocking without extreme care.
+// internally to the datastore itself. Reads lock one shard at a time. Writes
+// lock every shard they touch up front, always in ascending order, so that
+// writers can't deadlock each other; the history is only ever locked after
+// the shards. Shards may be shared with forks, and are copied the first
+// time they're written to.
 #[derive(Debug)]
 struct InternalMemoryDatastore {
-    edge_properties: BTreeMap<(models::EdgeKey, String), JsonValue>,
-    edges: BTreeMap<models::EdgeKey, DateTime<Utc>>,
-    vertex_properties: BTreeMap<(Uuid, String), JsonValue>,
-    vertices: BTreeMap<Uuid, models::Type>,
+    shards: Vec<RwLock<Arc<Shard>>>,
+    history: Option<Mutex<History>>,
+    events: EventBus,
+    clock: RwLock<Arc<dyn Clock>>,
+    idempotency_records: Mutex<IdempotencyRecords>,
+    write_tick: AtomicU64,
+    resident_property_bytes: AtomicUsize,
+    #[cfg(feature = "rocksdb-datastore")]
+    spill: Option<Arc<SpillStore>>,
 }
 
 impl InternalMemoryDatastore {
+    fn new(history: Option<History>) -> Self {
+        Self {
+            shards: (0..SHARD_COUNT)
+                .map(|_| RwLock::new(Arc::new(Shard::default())))
+                .collect(),
+            history: history.map(Mutex::new),
+            events: EventBus::default(),
+            clock: RwLock::new(Arc::new(SystemClock)),
+            idempotency_records: Mutex::new(HashMap::new()),
+            write_tick: AtomicU64::new(0),
+            resident_property_bytes: AtomicUsize::new(0),
+            #[cfg(feature = "rocksdb-datastore")]
+            spill: None,
+        }
+    }
+
+    fn now(&self) -> DateTime<Utc> {
+        self.clock.read().unwrap().now()
+    }
+
+    // Write locks the shards that own the given vertex ids.
+    fn lock_shards<I: IntoIterator<Item = Uuid>>(&self, ids: I) -> ShardsMut<'_> {
+        let indexes: BTreeSet<usize> = ids.into_iter().map(shard_index).collect();
+        let mut guards: Vec<Option<RwLockWriteGuard<Arc<Shard>>>> = (0..SHARD_COUNT).map(|_| None).collect();
+
+        for index in indexes {
+            guards[index] = Some(self.shards[index].write().unwrap());
+        }
+
+        ShardsMut {
+            datastore: self,
+            guards,
+        }
+    }
+
+    fn lock_all_shards(&self) -> ShardsMut<'_> {
+        ShardsMut {
+            datastore: self,
+            guards: self.shards.iter().map(|shard| Some(shard.write().unwrap())).collect(),
+        }
+    }
+
+    // Wraps a property value that's being written. Its size is only
+    // accounted for if values may be spilled.
+    fn new_property_value(&self, value: JsonValue) -> PropertyValue {
+        #[cfg(feature = "rocksdb-datastore")]
+        {
+            if self.spill.is_some() {
+                self.resident_property_bytes
+                    .fetch_add(estimate_size(&value), Ordering::SeqCst);
+            }
+        }
+
+        PropertyValue::Resident(value, self.write_tick.fetch_add(1, Ordering::SeqCst))
+    }
+
+    // Accounts for a property value that's been overwritten or deleted.
+    #[cfg(feature = "rocksdb-datastore")]
+    fn discard_property_value(&self, value: &PropertyValue) {
+        if let (Some(_), PropertyValue::Resident(value, _)) = (&self.spill, value) {
+            self.resident_property_bytes
+                .fetch_sub(estimate_size(value), Ordering::SeqCst);
+        }
+    }
+
+    #[cfg(not(feature = "rocksdb-datastore"))]
+    fn discard_property_value(&self, _value: &PropertyValue) {}
+
+    fn resolve_property_value(&self, value: &PropertyValue) -> Result<JsonValue> {
+        match value {
+            PropertyValue::Resident(value, _) => Ok(value.clone()),
+            #[cfg(feature = "rocksdb-datastore")]
+            PropertyValue::Spilled(id) => self
+                .spill
+                .as_ref()
+                .expect("Expected a spill store for a spilled property value")
+                .get(*id),
+        }
+    }
+
+    // Spills the least recently written property values to disk if the
+    // resident values are over the memory budget. This locks every shard, so
+    // it must be called after the writer's own locks have been released.
+    #[cfg(feature = "rocksdb-datastore")]
+    fn spill_if_over_budget(&self) -> Result<()> {
+        let spill = match self.spill {
+            Some(ref spill) => spill,
+            None => return Ok(()),
+        };
+
+        if self.resident_property_bytes.load(Ordering::SeqCst) <= spill.options().budget_bytes {
+            return Ok(());
+        }
+
+        let mut shards = self.lock_all_shards();
+
+        // Another writer may have spilled while we waited for the locks
+        let resident_bytes = self.resident_property_bytes.load(Ordering::SeqCst);
+        if resident_bytes <= spill.options().budget_bytes {
+            return Ok(());
+        }
+
+        let mut resident_values: Vec<(u64, usize)> = Vec::new();
+
+        for shard in shards.guards.iter().flatten() {
+            let values = shard.vertex_properties.values().chain(shard.edge_properties.values());
+
+            for value in values {
+                if let PropertyValue::Resident(value, tick) = value {
+                    resident_values.push((*tick, estimate_size(value)));
+                }
+            }
+        }
+
+        // Finds the newest tick to spill at or before, so that what's left
+        // fits under the low water mark
+        resident_values.sort_unstable();
+        let low_water_bytes = spill.options().low_water_bytes();
+        let mut remaining_bytes = resident_bytes;
+        let mut threshold = None;
+
+        for (tick, size) in resident_values {
+            if remaining_bytes <= low_water_bytes {
+                break;
+            }
+
+            remaining_bytes = remaining_bytes.saturating_sub(size);
+            threshold = Some(tick);
+        }
+
+        let threshold = match threshold {
+            Some(threshold) => threshold,
+            None => return Ok(()),
+        };
+
+        let mut freed_bytes = 0;
+
+        for guard in shards.guards.iter_mut().flatten() {
+            let is_cold = |value: &PropertyValue| match value {
+                PropertyValue::Resident(_, tick) => *tick <= threshold,
+                PropertyValue::Spilled(_) => false,
+            };
+
+            // Avoids copying shards shared with a fork that have nothing to
+            // spill
+            if !guard.vertex_properties.values().any(is_cold) && !guard.edge_properties.values().any(is_cold) {
+                continue;
+            }
+
+            let shard = Arc::make_mut(guard);
+            freed_bytes += spill_properties(spill, &mut shard.vertex_properties, threshold)?;
+            freed_bytes += spill_properties(spill, &mut shard.edge_properties, threshold)?;
+        }
+
+        self.resident_property_bytes.fetch_sub(freed_bytes, Ordering::SeqCst);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "rocksdb-datastore"))]
+    fn spill_if_over_budget(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn get_vertex_type(&self, id: Uuid) -> Option<models::Type> {
+        self.shards[shard_index(id)].read().unwrap().vertices.get(&id).cloned()
+    }
+
     fn get_vertex_values_by_query(&self, q: VertexQuery) -> Result<Vec<(Uuid, models::Type)>> {
         match q {
             VertexQuery::Range(range) => {
-                let mut iter: Box<dyn Iterator<Item = (&Uuid, &models::Type)>> = if let Some(start_id) = range.start_id
-                {
-                    Box::new(self.vertices.range(start_id..))
-                } else {
-                    Box::new(self.vertices.iter())
-                };
+                let mut results = Vec::new();
 
-                if let Some(ref t) = range.t {
-                    iter = Box::new(iter.filter(move |(_, v)| v == &t));
+                // Each shard is already ordered, so the first `limit`
+                // vertices overall are among the first `limit` of each shard
+                for shard in &self.shards {
+                    let shard = shard.read().unwrap();
+
+                    let mut iter: Box<dyn Iterator<Item = (&Uuid, &models::Type)>> =
+                        if let Some(start_id) = range.start_id {
+                            Box::new(shard.vertices.range(start_id..))
+                        } else {
+                            Box::new(shard.vertices.iter())
+                        };
+
+                    if let Some(ref t) = range.t {
+                        iter = Box::new(iter.filter(move |(_, v)| v == &t));
+                    }
+
+                    results.extend(iter.take(range.limit as usize).map(|(k, v)| (*k, v.clone())));
                 }
 
-                Ok(iter.take(range.limit as usize).map(|(k, v)| (*k, v.clone())).collect())
+                results.sort_by_key(|(id, _)| *id);
+                results.truncate(range.limit as usize);
+                Ok(results)
             }
             VertexQuery::Specific(specific) => {
                 let mut results = Vec::new();
 
                 for id in specific.ids {
-                    let value = self.vertices.get(&id);
-
-                    if let Some(value) = value {
-                        results.push((id, value.clone()));
+                    if let Some(value) = self.get_vertex_type(id) {
+                        results.push((id, value));
                     }
                 }
 
@@ -56,18 +284,26 @@ impl InternalMemoryDatastore {
                 let iter: Box<dyn Iterator<Item = Uuid>> = match pipe.direction {
                     models::EdgeDirection::Outbound => Box::new(edge_values.map(|(key, _)| key.outbound_id)),
                     models::EdgeDirection::Inbound => Box::new(edge_values.map(|(key, _)| key.inbound_id)),
+                    models::EdgeDirection::Both => {
+                        let mut seen_ids = HashSet::new();
+                        Box::new(
+                            edge_values
+                                .flat_map(|(key, _)| vec![key.outbound_id, key.inbound_id])
+                                .filter(move |id| seen_ids.insert(*id)),
+                        )
+                    }
                 };
 
-                let mut iter: Box<dyn Iterator<Item = (Uuid, &models::Type)>> = Box::new(
-                    iter.map(|id| (id, self.vertices.get(&id)))
+                let mut iter: Box<dyn Iterator<Item = (Uuid, models::Type)>> = Box::new(
+                    iter.map(|id| (id, self.get_vertex_type(id)))
                         .filter_map(|(k, v)| Some((k, v?))),
                 );
 
                 if let Some(ref t) = pipe.t {
-                    iter = Box::new(iter.filter(move |(_, v)| v == &t));
+                    iter = Box::new(iter.filter(move |(_, v)| v == t));
                 }
 
-                Ok(iter.take(pipe.limit as usize).map(|(k, v)| (k, v.clone())).collect())
+                Ok(iter.take(pipe.limit as usize).collect())
             }
         }
     }
@@ -78,15 +314,38 @@ impl InternalMemoryDatastore {
                 let mut results = Vec::new();
 
                 for key in specific.keys {
-                    let value = self.edges.get(&key);
+                    let shard = self.shards[shard_index(key.outbound_id)].read().unwrap();
 
-                    if let Some(update_datetime) = value {
+                    if let Some(update_datetime) = shard.edges.get(&key) {
                         results.push((key.clone(), *update_datetime));
                     }
                 }
 
                 Ok(results)
             }
+            EdgeQuery::Pipe(ref pipe) if pipe.direction == models::EdgeDirection::Both => {
+                let mut results = Vec::new();
+                let mut seen_keys = HashSet::new();
+
+                for &direction in &[models::EdgeDirection::Outbound, models::EdgeDirection::Inbound] {
+                    let directed_pipe = models::PipeEdgeQuery {
+                        direction,
+                        ..pipe.clone()
+                    };
+
+                    for (key, update_datetime) in self.get_edge_values_by_query(directed_pipe.into())? {
+                        if results.len() == pipe.limit as usize {
+                            return Ok(results);
+                        }
+
+                        if seen_keys.insert(key.clone()) {
+                            results.push((key, update_datetime));
+                        }
+                    }
+                }
+
+                Ok(results)
+            }
             EdgeQuery::Pipe(pipe) => {
                 let vertex_values = self.get_vertex_values_by_query(*pipe.inner)?;
                 let mut results = Vec::new();
@@ -106,7 +365,9 @@ impl InternalMemoryDatastore {
                                 }
                             };
 
-                            for (key, update_datetime) in self.edges.range(lower_bound..) {
+                            let shard = self.shards[shard_index(id)].read().unwrap();
+
+                            for (key, update_datetime) in shard.edges.range(lower_bound..) {
                                 if key.outbound_id != id {
                                     break;
                                 }
@@ -143,36 +404,49 @@ impl InternalMemoryDatastore {
                             candidate_ids.insert(id);
                         }
 
-                        for (key, update_datetime) in &self.edges {
-                            if !candidate_ids.contains(&key.inbound_id) {
-                                continue;
-                            }
+                        for shard in &self.shards {
+                            let shard = shard.read().unwrap();
+                            let mut shard_result_count = 0;
 
-                            if let Some(t) = &pipe.t {
-                                if &key.t != t {
+                            for (key, update_datetime) in &shard.edges {
+                                if !candidate_ids.contains(&key.inbound_id) {
                                     continue;
                                 }
-                            }
 
-                            if let Some(high) = &pipe.high {
-                                if update_datetime > high {
-                                    continue;
+                                if let Some(t) = &pipe.t {
+                                    if &key.t != t {
+                                        continue;
+                                    }
                                 }
-                            }
 
-                            if let Some(low) = &pipe.low {
-                                if update_datetime < low {
-                                    continue;
+                                if let Some(high) = &pipe.high {
+                                    if update_datetime > high {
+                                        continue;
+                                    }
+                                }
+
+                                if let Some(low) = &pipe.low {
+                                    if update_datetime < low {
+                                        continue;
+                                    }
                                 }
-                            }
 
-                            results.push((key.clone(), *update_datetime));
+                                results.push((key.clone(), *update_datetime));
+                                shard_result_count += 1;
 
-                            if results.len() == pipe.limit as usize {
-                                return Ok(results);
+                                if shard_result_count == pipe.limit as usize {
+                                    break;
+                                }
                             }
                         }
+
+                        // Returns the edges in key order, as if they were
+                        // all stored together
+                        results.sort_by(|(first_key, _), (second_key, _)| first_key.cmp(second_key));
+                        results.truncate(pipe.limit as usize);
                     }
+                    // Handled by the previous match arm
+                    models::EdgeDirection::Both => unreachable!(),
                 }
 
                 Ok(results)
@@ -180,13 +454,216 @@ impl InternalMemoryDatastore {
         }
     }
 
+    // Builds a datastore from the items it should contain.
+    fn from_items(
+        vertices: BTreeMap<Uuid, models::Type>,
+        edges: BTreeMap<models::EdgeKey, DateTime<Utc>>,
+        vertex_properties: BTreeMap<(Uuid, String), JsonValue>,
+        edge_properties: BTreeMap<(models::EdgeKey, String), JsonValue>,
+    ) -> Self {
+        let datastore = Self::new(None);
+
+        {
+            let mut shards = datastore.lock_all_shards();
+
+            for (id, t) in vertices {
+                shards.shard_mut(id).vertices.insert(id, t);
+            }
+
+            for (key, update_datetime) in edges {
+                shards.shard_mut(key.outbound_id).edges.insert(key, update_datetime);
+            }
+
+            for ((id, name), value) in vertex_properties {
+                let value = datastore.new_property_value(value);
+                shards.shard_mut(id).vertex_properties.insert((id, name), value);
+            }
+
+            for ((key, name), value) in edge_properties {
+                let value = datastore.new_property_value(value);
+                shards
+                    .shard_mut(key.outbound_id)
+                    .edge_properties
+                    .insert((key, name), value);
+            }
+        }
+
+        datastore
+    }
+}
+
+// Moves the resident values in a property map that were written at or
+// before a tick into the spill store, returning how many bytes were freed.
+#[cfg(feature = "rocksdb-datastore")]
+fn spill_properties<K: Clone + Ord>(
+    spill: &SpillStore,
+    properties: &mut BTreeMap<K, PropertyValue>,
+    threshold: u64,
+) -> Result<usize> {
+    let (keys, values): (Vec<K>, Vec<&JsonValue>) = properties
+        .iter()
+        .filter_map(|(key, value)| match value {
+            PropertyValue::Resident(value, tick) if *tick <= threshold => Some((key.clone(), value)),
+            _ => None,
+        })
+        .unzip();
+
+    let freed_bytes = values.iter().map(|value| estimate_size(value)).sum();
+    let ids = spill.put_all(&values)?;
+
+    for (key, id) in keys.into_iter().zip(ids) {
+        properties.insert(key, PropertyValue::Spilled(id));
+    }
+
+    Ok(freed_bytes)
+}
+
+// Write locks on some or all of the shards of a datastore.
+struct ShardsMut<'a> {
+    datastore: &'a InternalMemoryDatastore,
+    guards: Vec<Option<RwLockWriteGuard<'a, Arc<Shard>>>>,
+}
+
+impl<'a> ShardsMut<'a> {
+    // Gets the shard that owns a vertex id. This panics if the shard isn't
+    // locked, since that's a bug in the caller.
+    fn shard(&self, id: Uuid) -> &Shard {
+        let guard = self.guards[shard_index(id)]
+            .as_ref()
+            .expect("Expected the shard to be locked");
+        guard
+    }
+
+    // Like `shard`, but for writes. The shard is copied first if it's shared
+    // with a fork.
+    fn shard_mut(&mut self, id: Uuid) -> &mut Shard {
+        let guard = self.guards[shard_index(id)]
+            .as_mut()
+            .expect("Expected the shard to be locked");
+        Arc::make_mut(guard)
+    }
+
+    fn record<F: FnOnce(&mut History, DateTime<Utc>)>(&self, f: F) {
+        if let Some(ref history) = self.datastore.history {
+            f(&mut history.lock().unwrap(), self.datastore.now());
+        }
+    }
+
+    fn vertex_exists(&self, id: Uuid) -> bool {
+        self.shard(id).vertices.contains_key(&id)
+    }
+
+    fn edge_exists(&self, key: &models::EdgeKey) -> bool {
+        self.shard(key.outbound_id).edges.contains_key(key)
+    }
+
+    fn set_vertex(&mut self, id: Uuid, t: models::Type) {
+        self.record(|history, now| {
+            history::record(&mut history.vertices, &history.retention, id, Some(t.clone()), now)
+        });
+
+        self.shard_mut(id).vertices.insert(id, t);
+    }
+
+    fn set_edge(&mut self, key: models::EdgeKey, update_datetime: DateTime<Utc>) {
+        self.record(|history, now| {
+            history::record(
+                &mut history.edges,
+                &history.retention,
+                key.clone(),
+                Some(update_datetime),
+                now,
+            )
+        });
+
+        self.shard_mut(key.outbound_id).edges.insert(key, update_datetime);
+    }
+
+    fn set_vertex_property(&mut self, id: Uuid, name: String, value: JsonValue) {
+        self.record(|history, now| {
+            history::record(
+                &mut history.vertex_properties,
+                &history.retention,
+                (id, name.clone()),
+                Some(value.clone()),
+                now,
+            )
+        });
+
+        let value = self.datastore.new_property_value(value);
+
+        if let Some(old_value) = self.shard_mut(id).vertex_properties.insert((id, name), value) {
+            self.datastore.discard_property_value(&old_value);
+        }
+    }
+
+    fn delete_vertex_property(&mut self, id: Uuid, name: String) {
+        if let Some(old_value) = self.shard_mut(id).vertex_properties.remove(&(id, name.clone())) {
+            self.datastore.discard_property_value(&old_value);
+            self.record(|history, now| {
+                history::record(
+                    &mut history.vertex_properties,
+                    &history.retention,
+                    (id, name),
+                    None,
+                    now,
+                )
+            });
+        }
+    }
+
+    fn set_edge_property(&mut self, key: models::EdgeKey, name: String, value: JsonValue) {
+        self.record(|history, now| {
+            history::record(
+                &mut history.edge_properties,
+                &history.retention,
+                (key.clone(), name.clone()),
+                Some(value.clone()),
+                now,
+            )
+        });
+
+        let value = self.datastore.new_property_value(value);
+
+        if let Some(old_value) = self
+            .shard_mut(key.outbound_id)
+            .edge_properties
+            .insert((key, name), value)
+        {
+            self.datastore.discard_property_value(&old_value);
+        }
+    }
+
+    fn delete_edge_property(&mut self, key: models::EdgeKey, name: String) {
+        if let Some(old_value) = self
+            .shard_mut(key.outbound_id)
+            .edge_properties
+            .remove(&(key.clone(), name.clone()))
+        {
+            self.datastore.discard_property_value(&old_value);
+            self.record(|history, now| {
+                history::record(&mut history.edge_properties, &history.retention, (key, name), None, now)
+            });
+        }
+    }
+
+    // This requires every shard to be locked, since inbound edges can be on
+    // any of them.
     fn delete_vertices(&mut self, vertices: Vec<Uuid>) {
         for vertex_id in vertices {
-            self.vertices.remove(&vertex_id);
+            if self.shard_mut(vertex_id).vertices.remove(&vertex_id).is_some() {
+                self.record(|history, now| {
+                    history::record(&mut history.vertices, &history.retention, vertex_id, None, now)
+                });
+            }
 
             let mut deletable_vertex_properties: Vec<(Uuid, String)> = Vec::new();
 
-            for (property_key, _) in self.vertex_properties.range((vertex_id, "".to_string())..) {
+            for (property_key, _) in self
+                .shard(vertex_id)
+                .vertex_properties
+                .range((vertex_id, "".to_string())..)
+            {
                 let &(ref property_vertex_id, _) = property_key;
 
                 if &vertex_id != property_vertex_id {
@@ -196,15 +673,17 @@ impl InternalMemoryDatastore {
                 deletable_vertex_properties.push(property_key.clone());
             }
 
-            for property_key in deletable_vertex_properties {
-                self.vertex_properties.remove(&property_key);
+            for (id, name) in deletable_vertex_properties {
+                self.delete_vertex_property(id, name);
             }
 
             let mut deletable_edges: Vec<models::EdgeKey> = Vec::new();
 
-            for edge_key in self.edges.keys() {
-                if edge_key.outbound_id == vertex_id || edge_key.inbound_id == vertex_id {
-                    deletable_edges.push(edge_key.clone());
+            for shard in self.guards.iter().flatten() {
+                for edge_key in shard.edges.keys() {
+                    if edge_key.outbound_id == vertex_id || edge_key.inbound_id == vertex_id {
+                        deletable_edges.push(edge_key.clone());
+                    }
                 }
             }
 
@@ -214,11 +693,19 @@ impl InternalMemoryDatastore {
 
     fn delete_edges(&mut self, edges: Vec<models::EdgeKey>) {
         for edge_key in edges {
-            self.edges.remove(&edge_key);
+            if self.shard_mut(edge_key.outbound_id).edges.remove(&edge_key).is_some() {
+                self.record(|history, now| {
+                    history::record(&mut history.edges, &history.retention, edge_key.clone(), None, now)
+                });
+            }
 
             let mut deletable_edge_properties: Vec<(models::EdgeKey, String)> = Vec::new();
 
-            for (property_key, _) in self.edge_properties.range((edge_key.clone(), "".to_string())..) {
+            for (property_key, _) in self
+                .shard(edge_key.outbound_id)
+                .edge_properties
+                .range((edge_key.clone(), "".to_string())..)
+            {
                 let &(ref property_edge_key, _) = property_key;
 
                 if &edge_key != property_edge_key {
@@ -228,8 +715,8 @@ impl InternalMemoryDatastore {
                 deletable_edge_properties.push(property_key.clone());
             }
 
-            for property_key in deletable_edge_properties {
-                self.edge_properties.remove(&property_key);
+            for (key, name) in deletable_edge_properties {
+                self.delete_edge_property(key, name);
             }
         }
     }
@@ -237,112 +724,474 @@ impl InternalMemoryDatastore {
 
 /// An in-memory-only datastore.
 #[derive(Debug)]
-pub struct MemoryDatastore(Arc<RwLock<InternalMemoryDatastore>>);
+pub struct MemoryDatastore(Arc<InternalMemoryDatastore>);
 
 impl MemoryDatastore {
     /// Creates a new in-memory datastore.
     pub fn default() -> MemoryDatastore {
         Self {
-            0: Arc::new(RwLock::new(InternalMemoryDatastore {
-                edge_properties: BTreeMap::new(),
-                edges: BTreeMap::new(),
-                vertex_properties: BTreeMap::new(),
-                vertices: BTreeMap::new(),
-            })),
+            0: Arc::new(InternalMemoryDatastore::new(None)),
         }
     }
+
+    /// Creates a new in-memory datastore that retains previous versions of
+    /// vertices, edges and properties, keyed by the datetime they were
+    /// committed. Use `as_of` to read the graph as it was at a point in
+    /// time. Nothing is ever discarded from the history, so memory usage
+    /// grows with every write; see `with_history_retention` to bound it.
+    pub fn with_history() -> MemoryDatastore {
+        Self::with_history_retention(RetentionPolicy::new())
+    }
+
+    /// Creates a new in-memory datastore that retains previous versions of
+    /// vertices, edges and properties, subject to a retention policy.
+    ///
+    /// # Arguments
+    /// * `retention` - How much history to retain.
+    pub fn with_history_retention(retention: RetentionPolicy) -> MemoryDatastore {
+        let history = History::new(retention);
+        MemoryDatastore(Arc::new(InternalMemoryDatastore::new(Some(history))))
+    }
+
+    /// Creates a new in-memory datastore that spills property values to a
+    /// temporary rocksdb database once they take up more memory than a
+    /// budget, so that graphs with more property data than fits in memory
+    /// can still be held. The least recently written values are spilled
+    /// first, and reading a spilled value reads it back from disk. Vertices
+    /// and edges are always kept in memory.
+    ///
+    /// # Arguments
+    /// * `options` - The memory budget, and where to spill to.
+    ///
+    /// # Errors
+    /// Returns an error if the spill database couldn't be created.
+    #[cfg(feature = "rocksdb-datastore")]
+    pub fn with_spill(options: SpillOptions) -> Result<MemoryDatastore> {
+        let mut datastore = InternalMemoryDatastore::new(None);
+        datastore.spill = Some(Arc::new(SpillStore::new(options)?));
+        Ok(MemoryDatastore(Arc::new(datastore)))
+    }
+
+    /// Sets the clock used to timestamp edges and history, in place of the
+    /// system time. This allows tests and import jobs to control timestamps
+    /// deterministically.
+    ///
+    /// # Arguments
+    /// * `clock` - The clock to use.
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> MemoryDatastore {
+        *self.0.clock.write().unwrap() = clock;
+        self
+    }
+
+    /// Discards the history of the given vertices, keeping only their
+    /// current versions. The history of the vertices' properties, edges and
+    /// edge properties is discarded as well. Vertices that have been deleted
+    /// are removed from the history entirely. This does nothing if history
+    /// is not enabled.
+    ///
+    /// # Arguments
+    /// * `ids` - The ids of the vertices.
+    pub fn vacuum_history(&self, ids: &[Uuid]) {
+        if let Some(ref history) = self.0.history {
+            let mut history = history.lock().unwrap();
+
+            for id in ids {
+                history.vacuum_vertex(*id);
+            }
+        }
+    }
+
+    /// Creates a copy of the datastore that can be changed independently of
+    /// it, e.g. to try out changes and then discard them. Forking is cheap,
+    /// since the copy shares the datastore's data until either of them
+    /// writes to it; then only the part being written to is copied. The fork
+    /// doesn't have any history, and has its own subscribers. If property
+    /// values are spilled, the fork shares the spill database.
+    pub fn fork(&self) -> MemoryDatastore {
+        // Holding every shard's lock at once makes the fork consistent
+        let shards: Vec<_> = self.0.shards.iter().map(|shard| shard.read().unwrap()).collect();
+        let mut fork = InternalMemoryDatastore::new(None);
+        fork.write_tick = AtomicU64::new(self.0.write_tick.load(Ordering::SeqCst));
+        fork.resident_property_bytes = AtomicUsize::new(self.0.resident_property_bytes.load(Ordering::SeqCst));
+
+        #[cfg(feature = "rocksdb-datastore")]
+        {
+            fork.spill = self.0.spill.clone();
+        }
+
+        for (shard, forked_shard) in shards.iter().zip(&fork.shards) {
+            *forked_shard.write().unwrap() = Arc::clone(shard);
+        }
+
+        *fork.clock.write().unwrap() = Arc::clone(&self.0.clock.read().unwrap());
+        MemoryDatastore(Arc::new(fork))
+    }
+
+    // Creates a new in-memory datastore holding the given items, e.g. to
+    // read a persistent datastore's history as of a point in time.
+    pub(crate) fn from_items(
+        vertices: BTreeMap<Uuid, models::Type>,
+        edges: BTreeMap<models::EdgeKey, DateTime<Utc>>,
+        vertex_properties: BTreeMap<(Uuid, String), JsonValue>,
+        edge_properties: BTreeMap<(models::EdgeKey, String), JsonValue>,
+    ) -> MemoryDatastore {
+        MemoryDatastore(Arc::new(InternalMemoryDatastore::from_items(
+            vertices,
+            edges,
+            vertex_properties,
+            edge_properties,
+        )))
+    }
+
+    /// Gets a copy of the datastore as it was at a given datetime. The copy
+    /// is detached from this datastore; changes to either are not reflected
+    /// in the other.
+    ///
+    /// # Arguments
+    /// * `datetime` - The datetime to read the graph as of.
+    ///
+    /// # Errors
+    /// Returns an error if the datastore was not created via
+    /// `with_history`.
+    pub fn as_of(&self, datetime: DateTime<Utc>) -> Result<MemoryDatastore> {
+        let history = match self.0.history {
+            Some(ref history) => history.lock().unwrap(),
+            None => return Err("History is not enabled for this datastore".into()),
+        };
+
+        let snapshot = InternalMemoryDatastore::from_items(
+            history::items_as_of(&history.vertices, datetime),
+            history::items_as_of(&history.edges, datetime),
+            history::items_as_of(&history.vertex_properties, datetime),
+            history::items_as_of(&history.edge_properties, datetime),
+        );
+        *snapshot.clock.write().unwrap() = Arc::clone(&self.0.clock.read().unwrap());
+
+        Ok(MemoryDatastore(Arc::new(snapshot)))
+    }
 }
 
 impl Datastore for MemoryDatastore {
     type Trans = MemoryTransaction;
 
+    // We override the default `bulk_insert` implementation so that all of
+    // the items are inserted under a single set of write locks. Like the
+    // rocksdb datastore, this does not verify that the vertices associated
+    // with an inserted edge or property exist.
+    fn bulk_insert<I>(&self, items: I) -> Result<()>
+    where
+        I: Iterator<Item = models::BulkInsertItem>,
+    {
+        let mut shards = self.0.lock_all_shards();
+        let mut events = self.0.events.pending();
+
+        for item in items {
+            match item {
+                models::BulkInsertItem::Vertex(vertex) => {
+                    shards.set_vertex(vertex.id, vertex.t.clone());
+                    events.push(|| models::Event::VertexCreated(vertex));
+                }
+                models::BulkInsertItem::Edge(key) => {
+                    let update_datetime = self.0.now();
+                    shards.set_edge(key.clone(), update_datetime);
+                    events.push(|| models::Event::EdgeCreated(key));
+                }
+                models::BulkInsertItem::VertexProperty(id, name, value) => {
+                    events.push(|| models::Event::VertexPropertySet(id, name.clone(), value.clone()));
+                    shards.set_vertex_property(id, name, value);
+                }
+                models::BulkInsertItem::EdgeProperty(key, name, value) => {
+                    events.push(|| models::Event::EdgePropertySet(key.clone(), name.clone(), value.clone()));
+                    shards.set_edge_property(key, name, value);
+                }
+            }
+        }
+
+        events.publish();
+        drop(shards);
+        self.0.spill_if_over_budget()
+    }
+
     fn transaction(&self) -> Result<Self::Trans> {
         Ok(MemoryTransaction {
             datastore: Arc::clone(&self.0),
         })
     }
+
+    // Copies made by `as_of` have their own subscribers.
+    fn subscribe(&self, filter: models::EventFilter) -> Result<Receiver<models::Event>> {
+        Ok(self.0.events.subscribe(filter))
+    }
+
+    // Like property expiry in the rocksdb datastore, records expire by the
+    // system time rather than the datastore's clock.
+    fn get_idempotency_record(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match self.0.idempotency_records.lock().unwrap().get(key) {
+            Some((expires_at, response)) if *expires_at > Utc::now() => Ok(Some(response.clone())),
+            _ => Ok(None),
+        }
+    }
+
+    fn set_idempotency_record(&self, key: &str, response: &[u8], expires_at: DateTime<Utc>) -> Result<()> {
+        let now = Utc::now();
+        let mut records = self.0.idempotency_records.lock().unwrap();
+        // Expired records are purged as new ones are set
+        records.retain(|_, (expires_at, _)| *expires_at > now);
+        records.insert(key.to_string(), (expires_at, response.to_vec()));
+        Ok(())
+    }
 }
 
 /// A transaction for manipulating in-memory-only datastores.
 #[derive(Debug)]
 pub struct MemoryTransaction {
-    datastore: Arc<RwLock<InternalMemoryDatastore>>,
+    datastore: Arc<InternalMemoryDatastore>,
 }
 
+// Writes that take a query run it before taking any write locks, and then
+// skip items that were deleted in the meantime.
 impl Transaction for MemoryTransaction {
     fn create_vertex(&self, vertex: &models::Vertex) -> Result<bool> {
-        let mut datastore = self.datastore.write().unwrap();
-        let mut inserted = false;
+        let mut shards = self.datastore.lock_shards(vec![vertex.id]);
 
-        datastore.vertices.entry(vertex.id).or_insert_with(|| {
-            inserted = true;
-            vertex.t.clone()
-        });
+        if shards.vertex_exists(vertex.id) {
+            return Ok(false);
+        }
 
-        Ok(inserted)
+        shards.set_vertex(vertex.id, vertex.t.clone());
+
+        let mut events = self.datastore.events.pending();
+        events.push(|| models::Event::VertexCreated(vertex.clone()));
+        events.publish();
+        Ok(true)
+    }
+
+    fn upsert_vertex(&self, vertex: &models::Vertex) -> Result<Option<models::Type>> {
+        let mut shards = self.datastore.lock_shards(vec![vertex.id]);
+        let existing_t = shards.shard(vertex.id).vertices.get(&vertex.id).cloned();
+
+        if existing_t.as_ref() != Some(&vertex.t) {
+            shards.set_vertex(vertex.id, vertex.t.clone());
+
+            let mut events = self.datastore.events.pending();
+            events.push(|| models::Event::VertexCreated(vertex.clone()));
+            events.publish();
+        }
+
+        Ok(existing_t)
     }
 
     fn get_vertices<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<Vec<models::Vertex>> {
-        let vertex_values = self.datastore.read().unwrap().get_vertex_values_by_query(q.into())?;
+        let vertex_values = self.datastore.get_vertex_values_by_query(q.into())?;
         let iter = vertex_values
             .into_iter()
             .map(|(uuid, t)| models::Vertex::with_id(uuid, t));
         Ok(iter.collect())
     }
 
+    fn filter_existing_vertices(&self, ids: Vec<Uuid>) -> Result<Vec<bool>> {
+        let mut results = vec![false; ids.len()];
+        let mut shard_indexes: Vec<Vec<usize>> = (0..SHARD_COUNT).map(|_| Vec::new()).collect();
+
+        for (i, id) in ids.iter().enumerate() {
+            shard_indexes[shard_index(*id)].push(i);
+        }
+
+        for (shard, indexes) in self.datastore.shards.iter().zip(shard_indexes) {
+            if indexes.is_empty() {
+                continue;
+            }
+
+            let shard = shard.read().unwrap();
+
+            for i in indexes {
+                results[i] = shard.vertices.contains_key(&ids[i]);
+            }
+        }
+
+        Ok(results)
+    }
+
     fn delete_vertices<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<()> {
-        let mut datastore = self.datastore.write().unwrap();
-        let deletable_vertices = datastore
-            .get_vertex_values_by_query(q.into())?
+        let vertex_values = self.datastore.get_vertex_values_by_query(q.into())?;
+        let mut shards = self.datastore.lock_all_shards();
+        let vertex_values: Vec<(Uuid, models::Type)> = vertex_values
             .into_iter()
-            .map(|(k, _)| k)
+            .filter(|(id, _)| shards.vertex_exists(*id))
             .collect();
-        datastore.delete_vertices(deletable_vertices);
+        shards.delete_vertices(vertex_values.iter().map(|(k, _)| *k).collect());
+
+        let mut events = self.datastore.events.pending();
+
+        for (id, t) in vertex_values {
+            events.push(|| models::Event::VertexDeleted(models::Vertex::with_id(id, t)));
+        }
+
+        events.publish();
         Ok(())
     }
 
     fn get_vertex_count(&self) -> Result<u64> {
-        let datastore = self.datastore.read().unwrap();
-        Ok(datastore.vertices.len() as u64)
+        let count: usize = self
+            .datastore
+            .shards
+            .iter()
+            .map(|shard| shard.read().unwrap().vertices.len())
+            .sum();
+        Ok(count as u64)
     }
 
     fn create_edge(&self, key: &models::EdgeKey) -> Result<bool> {
-        let mut datastore = self.datastore.write().unwrap();
+        self.create_edge_with_datetime(key, self.datastore.now())
+    }
+
+    fn create_edge_with_datetime(&self, key: &models::EdgeKey, update_datetime: DateTime<Utc>) -> Result<bool> {
+        let mut shards = self.datastore.lock_shards(vec![key.outbound_id, key.inbound_id]);
 
-        if !datastore.vertices.contains_key(&key.outbound_id) || !datastore.vertices.contains_key(&key.inbound_id) {
+        if !shards.vertex_exists(key.outbound_id) || !shards.vertex_exists(key.inbound_id) {
             return Ok(false);
         }
 
-        datastore.edges.insert(key.clone(), Utc::now());
+        shards.set_edge(key.clone(), update_datetime);
+
+        let mut events = self.datastore.events.pending();
+        events.push(|| models::Event::EdgeCreated(key.clone()));
+        events.publish();
         Ok(true)
     }
 
-    fn get_edges<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<Vec<models::Edge>> {
-        let edge_values = {
-            let datastore = self.datastore.read().unwrap();
-            datastore.get_edge_values_by_query(q.into())?
-        };
+    fn touch_edge(&self, key: &models::EdgeKey) -> Result<bool> {
+        let mut shards = self.datastore.lock_shards(vec![key.outbound_id, key.inbound_id]);
+
+        if !shards.vertex_exists(key.outbound_id) || !shards.vertex_exists(key.inbound_id) {
+            return Err(missing_edge_vertices(key));
+        }
+
+        let created = !shards.shard(key.outbound_id).edges.contains_key(key);
+        shards.set_edge(key.clone(), self.datastore.now());
+
+        let mut events = self.datastore.events.pending();
+        events.push(|| models::Event::EdgeCreated(key.clone()));
+        events.publish();
+        Ok(created)
+    }
 
+    fn create_mirrored_edge(&self, key: &models::EdgeKey, vertex_id: Uuid) -> Result<bool> {
+        self.create_mirrored_edge_with_datetime(key, vertex_id, self.datastore.now())
+    }
+
+    fn create_mirrored_edge_with_datetime(
+        &self,
+        key: &models::EdgeKey,
+        vertex_id: Uuid,
+        update_datetime: DateTime<Utc>,
+    ) -> Result<bool> {
+        let mut shards = self.datastore.lock_shards(vec![key.outbound_id, key.inbound_id]);
+
+        if !shards.vertex_exists(vertex_id) {
+            return Ok(false);
+        }
+
+        shards.set_edge(key.clone(), update_datetime);
+
+        let mut events = self.datastore.events.pending();
+        events.push(|| models::Event::EdgeCreated(key.clone()));
+        events.publish();
+        Ok(true)
+    }
+
+    fn get_edges<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<Vec<models::Edge>> {
+        let edge_values = self.datastore.get_edge_values_by_query(q.into())?;
         let iter = edge_values
             .into_iter()
             .map(|(key, update_datetime)| models::Edge::new(key, update_datetime));
         Ok(iter.collect())
     }
 
+    fn edge_exists(&self, key: &models::EdgeKey) -> Result<bool> {
+        let shard = self.datastore.shards[shard_index(key.outbound_id)].read().unwrap();
+        Ok(shard.edges.contains_key(key))
+    }
+
+    fn filter_existing_edges(&self, keys: Vec<models::EdgeKey>) -> Result<Vec<bool>> {
+        let mut results = vec![false; keys.len()];
+        let mut shard_indexes: Vec<Vec<usize>> = (0..SHARD_COUNT).map(|_| Vec::new()).collect();
+
+        for (i, key) in keys.iter().enumerate() {
+            shard_indexes[shard_index(key.outbound_id)].push(i);
+        }
+
+        // Each shard is only locked once
+        for (shard, indexes) in self.datastore.shards.iter().zip(shard_indexes) {
+            if indexes.is_empty() {
+                continue;
+            }
+
+            let shard = shard.read().unwrap();
+
+            for i in indexes {
+                results[i] = shard.edges.contains_key(&keys[i]);
+            }
+        }
+
+        Ok(results)
+    }
+
     fn delete_edges<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<()> {
-        let mut datastore = self.datastore.write().unwrap();
-        let deletable_edges: Vec<models::EdgeKey> = datastore
-            .get_edge_values_by_query(q.into())?
+        let edge_values = self.datastore.get_edge_values_by_query(q.into())?;
+        let mut shards = self
+            .datastore
+            .lock_shards(edge_values.iter().map(|(key, _)| key.outbound_id));
+        let deletable_edges: Vec<models::EdgeKey> = edge_values
             .into_iter()
             .map(|(k, _)| k)
+            .filter(|k| shards.edge_exists(k))
             .collect();
-        datastore.delete_edges(deletable_edges);
+        let mut events = self.datastore.events.pending();
+
+        for key in &deletable_edges {
+            events.push(|| models::Event::EdgeDeleted(key.clone()));
+        }
+
+        shards.delete_edges(deletable_edges);
+        events.publish();
         Ok(())
     }
 
-    fn get_edge_count(&self, id: Uuid, t: Option<&models::Type>, direction: models::EdgeDirection) -> Result<u64> {
-        let datastore = self.datastore.read().unwrap();
+    // Edges are stored regardless of whether their vertices are, so mirrored
+    // edges are deleted like any others
+    fn delete_mirrored_edges(&self, keys: Vec<models::EdgeKey>) -> Result<()> {
+        self.delete_edges(models::SpecificEdgeQuery::new(keys))
+    }
+
+    fn delete_edges_unmodified_since<Q: Into<models::EdgeQuery>>(&self, q: Q, since: DateTime<Utc>) -> Result<u64> {
+        let edge_values = self.datastore.get_edge_values_by_query(q.into())?;
+        let mut shards = self
+            .datastore
+            .lock_shards(edge_values.iter().map(|(key, _)| key.outbound_id));
+
+        // The update datetimes are re-read now that the shards are locked
+        let deletable_edges: Vec<models::EdgeKey> = edge_values
+            .into_iter()
+            .map(|(k, _)| k)
+            .filter(|k| match shards.shard(k.outbound_id).edges.get(k) {
+                Some(update_datetime) => *update_datetime <= since,
+                None => false,
+            })
+            .collect();
+        let count = deletable_edges.len() as u64;
+        let mut events = self.datastore.events.pending();
 
+        for key in &deletable_edges {
+            events.push(|| models::Event::EdgeDeleted(key.clone()));
+        }
+
+        shards.delete_edges(deletable_edges);
+        events.publish();
+        Ok(count)
+    }
+
+    fn get_edge_count(&self, id: Uuid, t: Option<&models::Type>, direction: models::EdgeDirection) -> Result<u64> {
         if direction == models::EdgeDirection::Outbound {
             let lower_bound = match t {
                 Some(t) => models::EdgeKey::new(id, t.clone(), Uuid::default()),
@@ -351,7 +1200,8 @@ impl Transaction for MemoryTransaction {
                     models::EdgeKey::new(id, empty_type, Uuid::default())
                 }
             };
-            let range = datastore.edges.range(lower_bound..);
+            let shard = self.datastore.shards[shard_index(id)].read().unwrap();
+            let range = shard.edges.range(lower_bound..);
 
             let range = range.take_while(|&(k, _)| {
                 if let Some(t) = t {
@@ -363,28 +1213,42 @@ impl Transaction for MemoryTransaction {
 
             Ok(range.count() as u64)
         } else {
-            let range = datastore.edges.iter().filter(|&(k, _)| {
-                if let Some(t) = t {
-                    k.inbound_id == id && &k.t == t
-                } else {
-                    k.inbound_id == id
-                }
-            });
+            let mut count = 0;
 
-            Ok(range.count() as u64)
+            for shard in &self.datastore.shards {
+                let shard = shard.read().unwrap();
+
+                let range = shard.edges.iter().filter(|&(k, _)| {
+                    let is_adjacent = match direction {
+                        models::EdgeDirection::Both => k.outbound_id == id || k.inbound_id == id,
+                        _ => k.inbound_id == id,
+                    };
+
+                    if let Some(t) = t {
+                        is_adjacent && &k.t == t
+                    } else {
+                        is_adjacent
+                    }
+                });
+
+                count += range.count() as u64;
+            }
+
+            Ok(count)
         }
     }
 
     fn get_vertex_properties(&self, q: VertexPropertyQuery) -> Result<Vec<models::VertexProperty>> {
         let mut result = Vec::new();
-        let datastore = self.datastore.read().unwrap();
-        let vertex_values = datastore.get_vertex_values_by_query(q.inner)?;
+        let vertex_values = self.datastore.get_vertex_values_by_query(q.inner)?;
 
         for (id, _) in vertex_values {
-            let property_value = datastore.vertex_properties.get(&(id, q.name.clone()));
+            let shard = self.datastore.shards[shard_index(id)].read().unwrap();
+            let property_value = shard.vertex_properties.get(&(id, q.name.clone()));
 
             if let Some(property_value) = property_value {
-                result.push(models::VertexProperty::new(id, property_value.clone()));
+                let property_value = self.datastore.resolve_property_value(property_value)?;
+                result.push(models::VertexProperty::new(id, property_value));
             }
         }
 
@@ -392,39 +1256,51 @@ impl Transaction for MemoryTransaction {
     }
 
     fn set_vertex_properties(&self, q: VertexPropertyQuery, value: &JsonValue) -> Result<()> {
-        let mut datastore = self.datastore.write().unwrap();
+        let vertex_values = self.datastore.get_vertex_values_by_query(q.inner)?;
+        let mut shards = self.datastore.lock_shards(vertex_values.iter().map(|(id, _)| *id));
 
-        let vertex_values = datastore.get_vertex_values_by_query(q.inner)?;
+        let mut events = self.datastore.events.pending();
+        let name = &q.name;
 
         for (id, _) in vertex_values {
-            datastore.vertex_properties.insert((id, q.name.clone()), value.clone());
+            if shards.vertex_exists(id) {
+                events.push(|| models::Event::VertexPropertySet(id, name.clone(), value.clone()));
+                shards.set_vertex_property(id, q.name.clone(), value.clone());
+            }
         }
 
-        Ok(())
+        events.publish();
+        drop(shards);
+        self.datastore.spill_if_over_budget()
     }
 
     fn delete_vertex_properties(&self, q: VertexPropertyQuery) -> Result<()> {
-        let mut datastore = self.datastore.write().unwrap();
+        let vertex_values = self.datastore.get_vertex_values_by_query(q.inner)?;
+        let mut shards = self.datastore.lock_shards(vertex_values.iter().map(|(id, _)| *id));
 
-        let vertex_values = datastore.get_vertex_values_by_query(q.inner)?;
+        let mut events = self.datastore.events.pending();
+        let name = &q.name;
 
         for (id, _) in vertex_values {
-            datastore.vertex_properties.remove(&(id, q.name.clone()));
+            events.push(|| models::Event::VertexPropertyDeleted(id, name.clone()));
+            shards.delete_vertex_property(id, q.name.clone());
         }
 
+        events.publish();
         Ok(())
     }
 
     fn get_edge_properties(&self, q: EdgePropertyQuery) -> Result<Vec<models::EdgeProperty>> {
         let mut result = Vec::new();
-        let datastore = self.datastore.read().unwrap();
-        let edge_values = datastore.get_edge_values_by_query(q.inner)?;
+        let edge_values = self.datastore.get_edge_values_by_query(q.inner)?;
 
         for (key, _) in edge_values {
-            let property_value = datastore.edge_properties.get(&(key.clone(), q.name.clone()));
+            let shard = self.datastore.shards[shard_index(key.outbound_id)].read().unwrap();
+            let property_value = shard.edge_properties.get(&(key.clone(), q.name.clone()));
 
             if let Some(property_value) = property_value {
-                result.push(models::EdgeProperty::new(key, property_value.clone()));
+                let property_value = self.datastore.resolve_property_value(property_value)?;
+                result.push(models::EdgeProperty::new(key, property_value));
             }
         }
 
@@ -432,26 +1308,314 @@ impl Transaction for MemoryTransaction {
     }
 
     fn set_edge_properties(&self, q: EdgePropertyQuery, value: &JsonValue) -> Result<()> {
-        let mut datastore = self.datastore.write().unwrap();
+        let edge_values = self.datastore.get_edge_values_by_query(q.inner)?;
+        let mut shards = self
+            .datastore
+            .lock_shards(edge_values.iter().map(|(key, _)| key.outbound_id));
 
-        let edge_values = datastore.get_edge_values_by_query(q.inner)?;
+        let mut events = self.datastore.events.pending();
+        let name = &q.name;
 
         for (key, _) in edge_values {
-            datastore.edge_properties.insert((key, q.name.clone()), value.clone());
+            if shards.edge_exists(&key) {
+                events.push(|| models::Event::EdgePropertySet(key.clone(), name.clone(), value.clone()));
+                shards.set_edge_property(key, q.name.clone(), value.clone());
+            }
         }
 
-        Ok(())
+        events.publish();
+        drop(shards);
+        self.datastore.spill_if_over_budget()
     }
 
     fn delete_edge_properties(&self, q: EdgePropertyQuery) -> Result<()> {
-        let mut datastore = self.datastore.write().unwrap();
+        let edge_values = self.datastore.get_edge_values_by_query(q.inner)?;
+        let mut shards = self
+            .datastore
+            .lock_shards(edge_values.iter().map(|(key, _)| key.outbound_id));
 
-        let edge_values = datastore.get_edge_values_by_query(q.inner)?;
+        let mut events = self.datastore.events.pending();
+        let name = &q.name;
 
         for (key, _) in edge_values {
-            datastore.edge_properties.remove(&(key, q.name.clone()));
+            events.push(|| models::Event::EdgePropertyDeleted(key.clone(), name.clone()));
+            shards.delete_edge_property(key, q.name.clone());
         }
 
+        events.publish();
         Ok(())
     }
+
+    fn get_all_vertex_properties<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<Vec<models::VertexProperties>> {
+        let vertex_values = self.datastore.get_vertex_values_by_query(q.into())?;
+        let mut result = Vec::new();
+
+        for (id, t) in vertex_values {
+            let shard = self.datastore.shards[shard_index(id)].read().unwrap();
+            let props = shard
+                .vertex_properties
+                .range((id, "".to_string())..)
+                .take_while(|((property_id, _), _)| *property_id == id)
+                .map(|((_, name), value)| {
+                    let value = self.datastore.resolve_property_value(value)?;
+                    Ok(models::NamedProperty::new(name.clone(), value))
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            result.push(models::VertexProperties::new(models::Vertex::with_id(id, t), props));
+        }
+
+        Ok(result)
+    }
+
+    fn get_all_edge_properties<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<Vec<models::EdgeProperties>> {
+        let edge_values = self.datastore.get_edge_values_by_query(q.into())?;
+        let mut result = Vec::new();
+
+        for (key, update_datetime) in edge_values {
+            let shard = self.datastore.shards[shard_index(key.outbound_id)].read().unwrap();
+            let props = shard
+                .edge_properties
+                .range((key.clone(), "".to_string())..)
+                .take_while(|((property_key, _), _)| *property_key == key)
+                .map(|((_, name), value)| {
+                    let value = self.datastore.resolve_property_value(value)?;
+                    Ok(models::NamedProperty::new(name.clone(), value))
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            result.push(models::EdgeProperties::new(
+                models::Edge::new(key, update_datetime),
+                props,
+            ));
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MemoryDatastore;
+    use chrono::{Duration, Utc};
+    use models::{EdgeKey, RangeVertexQuery, SpecificEdgeQuery, SpecificVertexQuery, Type, Vertex, VertexQueryExt};
+    use std::sync::Arc;
+    use std::thread;
+    use traits::{Datastore, Transaction};
+    use uuid::Uuid;
+
+    #[test]
+    fn should_create_edges_with_datetime() {
+        let datastore = MemoryDatastore::default();
+        let trans = datastore.transaction().unwrap();
+        let t = Type::new("foo").unwrap();
+        let outbound_v = Vertex::new(t.clone());
+        let inbound_v = Vertex::new(t.clone());
+        trans.create_vertex(&outbound_v).unwrap();
+        trans.create_vertex(&inbound_v).unwrap();
+
+        let now = Utc::now();
+        let key = EdgeKey::new(outbound_v.id, t.clone(), inbound_v.id);
+        trans.create_edge(&key).unwrap();
+        trans.create_edge_with_datetime(&key, now - Duration::days(1)).unwrap();
+
+        let edges = trans
+            .get_edges(SpecificVertexQuery::single(outbound_v.id).outbound(10).high(now))
+            .unwrap();
+        assert_eq!(edges.len(), 1);
+        assert_eq!(edges[0].created_datetime, now - Duration::days(1));
+
+        let missing_key = EdgeKey::new(outbound_v.id, t, Uuid::default());
+        assert!(!trans.create_edge_with_datetime(&missing_key, now).unwrap());
+        assert!(trans
+            .get_edges(SpecificEdgeQuery::single(missing_key))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn should_delete_edges_unmodified_since() {
+        let datastore = MemoryDatastore::default();
+        let trans = datastore.transaction().unwrap();
+        let t = Type::new("foo").unwrap();
+        let outbound_v = Vertex::new(t.clone());
+        trans.create_vertex(&outbound_v).unwrap();
+        let now = Utc::now();
+        let mut keys = Vec::new();
+
+        for days in 1..4 {
+            let inbound_v = Vertex::new(t.clone());
+            trans.create_vertex(&inbound_v).unwrap();
+            let key = EdgeKey::new(outbound_v.id, t.clone(), inbound_v.id);
+            trans
+                .create_edge_with_datetime(&key, now - Duration::days(days))
+                .unwrap();
+            keys.push(key);
+        }
+
+        let q = SpecificVertexQuery::single(outbound_v.id).outbound(10);
+        let count = trans
+            .delete_edges_unmodified_since(q.clone(), now - Duration::days(2))
+            .unwrap();
+        assert_eq!(count, 2);
+        let remaining: Vec<EdgeKey> = trans.get_edges(q).unwrap().into_iter().map(|e| e.key).collect();
+        assert_eq!(remaining, vec![keys[0].clone()]);
+    }
+
+    #[test]
+    fn should_upsert_vertices() {
+        let datastore = MemoryDatastore::default();
+        let trans = datastore.transaction().unwrap();
+        let v = Vertex::new(Type::new("foo").unwrap());
+        assert_eq!(trans.upsert_vertex(&v).unwrap(), None);
+        assert_eq!(trans.upsert_vertex(&v).unwrap(), Some(v.t.clone()));
+
+        let changed_v = Vertex::with_id(v.id, Type::new("bar").unwrap());
+        assert_eq!(trans.upsert_vertex(&changed_v).unwrap(), Some(v.t));
+        assert_eq!(
+            trans.get_vertices(SpecificVertexQuery::single(v.id)).unwrap(),
+            vec![changed_v]
+        );
+        assert_eq!(trans.get_vertex_count().unwrap(), 1);
+    }
+
+    #[test]
+    fn should_write_from_multiple_threads() {
+        let datastore = Arc::new(MemoryDatastore::default());
+        let t = Type::new("foo").unwrap();
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let datastore = Arc::clone(&datastore);
+                let t = t.clone();
+
+                thread::spawn(move || {
+                    let trans = datastore.transaction().unwrap();
+                    let mut previous_id = None;
+
+                    for _ in 0..100 {
+                        let v = Vertex::new(t.clone());
+                        assert!(trans.create_vertex(&v).unwrap());
+
+                        if let Some(previous_id) = previous_id {
+                            assert!(trans.create_edge(&EdgeKey::new(previous_id, t.clone(), v.id)).unwrap());
+                        }
+
+                        previous_id = Some(v.id);
+                    }
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let trans = datastore.transaction().unwrap();
+        assert_eq!(trans.get_vertex_count().unwrap(), 400);
+        let vertices = trans.get_vertices(RangeVertexQuery::new(u32::MAX)).unwrap();
+        assert_eq!(vertices.len(), 400);
+        assert!(vertices.windows(2).all(|pair| pair[0].id < pair[1].id));
+        let edges = trans
+            .get_edges(RangeVertexQuery::new(u32::MAX).outbound(u32::MAX))
+            .unwrap();
+        assert_eq!(edges.len(), 396);
+    }
+
+    #[test]
+    fn should_fork() {
+        let datastore = MemoryDatastore::default();
+        let trans = datastore.transaction().unwrap();
+        let t = Type::new("foo").unwrap();
+        let original_v = Vertex::new(t.clone());
+        trans.create_vertex(&original_v).unwrap();
+
+        let fork = datastore.fork();
+        let fork_trans = fork.transaction().unwrap();
+        let forked_v = Vertex::new(t.clone());
+        fork_trans.create_vertex(&forked_v).unwrap();
+        fork_trans
+            .delete_vertices(SpecificVertexQuery::single(original_v.id))
+            .unwrap();
+        trans.create_vertex(&Vertex::new(t)).unwrap();
+
+        assert_eq!(
+            fork_trans.get_vertices(RangeVertexQuery::new(u32::MAX)).unwrap(),
+            vec![forked_v.clone()]
+        );
+        assert_eq!(trans.get_vertex_count().unwrap(), 2);
+        assert!(trans
+            .get_vertices(SpecificVertexQuery::single(forked_v.id))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn should_record_idempotency_keys() {
+        let datastore = MemoryDatastore::default();
+        assert_eq!(datastore.get_idempotency_record("a").unwrap(), None);
+
+        let expires_at = Utc::now() + Duration::hours(1);
+        datastore.set_idempotency_record("a", b"first", expires_at).unwrap();
+        datastore.set_idempotency_record("b", b"second", expires_at).unwrap();
+        datastore.set_idempotency_record("a", b"third", expires_at).unwrap();
+        assert_eq!(datastore.get_idempotency_record("a").unwrap(), Some(b"third".to_vec()));
+        assert_eq!(datastore.get_idempotency_record("b").unwrap(), Some(b"second".to_vec()));
+
+        let expired_at = Utc::now() - Duration::seconds(1);
+        datastore.set_idempotency_record("b", b"fourth", expired_at).unwrap();
+        assert_eq!(datastore.get_idempotency_record("b").unwrap(), None);
+        assert!(datastore.fork().get_idempotency_record("a").unwrap().is_none());
+    }
+
+    #[cfg(feature = "rocksdb-datastore")]
+    #[test]
+    fn should_spill_properties_over_budget() {
+        use super::super::SpillOptions;
+        use serde_json::Value as JsonValue;
+        use std::sync::atomic::Ordering;
+
+        let datastore = MemoryDatastore::with_spill(SpillOptions::new(4096)).unwrap();
+        let trans = datastore.transaction().unwrap();
+        let t = Type::new("foo").unwrap();
+        let mut vertices = Vec::new();
+
+        for i in 0..100 {
+            let v = Vertex::new(t.clone());
+            trans.create_vertex(&v).unwrap();
+            let value = JsonValue::String(format!("{:0>100}", i));
+            trans
+                .set_vertex_properties(SpecificVertexQuery::single(v.id).property("bar"), &value)
+                .unwrap();
+            vertices.push((v, value));
+        }
+
+        assert!(datastore.0.resident_property_bytes.load(Ordering::SeqCst) <= 4096);
+
+        let fork = datastore.fork();
+        let fork_trans = fork.transaction().unwrap();
+
+        for (v, value) in &vertices {
+            let q = SpecificVertexQuery::single(v.id).property("bar");
+            assert_eq!(trans.get_vertex_properties(q.clone()).unwrap()[0].value, *value);
+            assert_eq!(fork_trans.get_vertex_properties(q).unwrap()[0].value, *value);
+        }
+
+        // Overwriting and deleting spilled values doesn't affect the fork
+        let (first_v, first_value) = &vertices[0];
+        let q = SpecificVertexQuery::single(first_v.id).property("bar");
+        trans.set_vertex_properties(q.clone(), &JsonValue::Bool(true)).unwrap();
+        assert_eq!(
+            trans.get_vertex_properties(q.clone()).unwrap()[0].value,
+            JsonValue::Bool(true)
+        );
+        trans.delete_vertex_properties(q.clone()).unwrap();
+        assert!(trans.get_vertex_properties(q.clone()).unwrap().is_empty());
+        assert_eq!(fork_trans.get_vertex_properties(q).unwrap()[0].value, *first_value);
+
+        let all_properties = trans
+            .get_all_vertex_properties(SpecificVertexQuery::single(vertices[1].0.id))
+            .unwrap();
+        assert_eq!(all_properties[0].props[0].value, vertices[1].1);
+    }
 }
@@ -0,0 +1,313 @@
+use chrono::offset::Utc;
+use chrono::{DateTime, Duration};
+use models;
+use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// The recorded versions of a single item, oldest first. Each version is the
+/// commit datetime paired with the item's value, or `None` if the item was
+/// deleted.
+pub type Versions<V> = Vec<(DateTime<Utc>, Option<V>)>;
+
+/// Limits how much history an in-memory datastore retains for each item.
+/// The policy is enforced for an item whenever a new version of it is
+/// recorded.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RetentionPolicy {
+    /// The maximum number of versions to keep per item.
+    pub max_versions: Option<usize>,
+
+    /// The maximum age of versions to keep. The newest version older than
+    /// this is kept as well, since it's still in effect at the cutoff.
+    pub max_age: Option<Duration>,
+}
+
+impl RetentionPolicy {
+    /// Creates a new retention policy that keeps all history.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum number of versions to keep per item.
+    ///
+    /// # Arguments
+    /// * `max_versions` - The maximum number of versions.
+    pub fn max_versions(self, max_versions: usize) -> Self {
+        Self {
+            max_versions: Some(max_versions),
+            max_age: self.max_age,
+        }
+    }
+
+    /// Sets the maximum age of versions to keep.
+    ///
+    /// # Arguments
+    /// * `max_age` - The maximum age.
+    pub fn max_age(self, max_age: Duration) -> Self {
+        Self {
+            max_versions: self.max_versions,
+            max_age: Some(max_age),
+        }
+    }
+
+    fn enforce<V>(&self, versions: &mut Versions<V>, now: DateTime<Utc>) {
+        if let Some(max_age) = self.max_age {
+            let cutoff = now - max_age;
+            let expired_count = versions.iter().take_while(|(datetime, _)| *datetime < cutoff).count();
+
+            if expired_count > 1 {
+                versions.drain(..expired_count - 1);
+            }
+
+            // A deletion in effect at the cutoff is indistinguishable from
+            // not having a version at all
+            if let Some((datetime, None)) = versions.first() {
+                if *datetime < cutoff {
+                    versions.remove(0);
+                }
+            }
+        }
+
+        if let Some(max_versions) = self.max_versions {
+            if versions.len() > max_versions {
+                let excess_count = versions.len() - max_versions;
+                versions.drain(..excess_count);
+            }
+        }
+    }
+}
+
+/// Previous versions of every item in an in-memory datastore.
+#[derive(Debug, Default)]
+pub struct History {
+    pub retention: RetentionPolicy,
+    pub vertices: BTreeMap<Uuid, Versions<models::Type>>,
+    pub edges: BTreeMap<models::EdgeKey, Versions<DateTime<Utc>>>,
+    pub vertex_properties: BTreeMap<(Uuid, String), Versions<JsonValue>>,
+    pub edge_properties: BTreeMap<(models::EdgeKey, String), Versions<JsonValue>>,
+}
+
+impl History {
+    /// Creates a new, empty history.
+    ///
+    /// # Arguments
+    /// * `retention` - How much history to retain.
+    pub fn new(retention: RetentionPolicy) -> Self {
+        Self {
+            retention,
+            ..Self::default()
+        }
+    }
+
+    /// Discards all but the current versions of a vertex, and of the
+    /// properties, edges and edge properties associated with it.
+    ///
+    /// # Arguments
+    /// * `id` - The id of the vertex.
+    pub fn vacuum_vertex(&mut self, id: Uuid) {
+        vacuum(&mut self.vertices, |vertex_id| *vertex_id == id);
+        vacuum(&mut self.vertex_properties, |(vertex_id, _)| *vertex_id == id);
+        vacuum(&mut self.edges, |key| key.outbound_id == id || key.inbound_id == id);
+        vacuum(&mut self.edge_properties, |(key, _)| {
+            key.outbound_id == id || key.inbound_id == id
+        });
+    }
+}
+
+/// Records a new version of an item, committed at `now`, and enforces the
+/// retention policy on the item's versions.
+pub fn record<K: Ord + Clone, V>(
+    history: &mut BTreeMap<K, Versions<V>>,
+    retention: &RetentionPolicy,
+    key: K,
+    value: Option<V>,
+    now: DateTime<Utc>,
+) {
+    let is_empty = {
+        let versions = history.entry(key.clone()).or_default();
+        versions.push((now, value));
+        retention.enforce(versions, now);
+        versions.is_empty()
+    };
+
+    if is_empty {
+        history.remove(&key);
+    }
+}
+
+// Discards all but the current version of matching items. Items that are
+// currently deleted are discarded entirely.
+fn vacuum<K: Ord, V, F>(history: &mut BTreeMap<K, Versions<V>>, f: F)
+where
+    F: Fn(&K) -> bool,
+{
+    for (key, versions) in history.iter_mut() {
+        if f(key) {
+            let excess_count = versions.len() - 1;
+            versions.drain(..excess_count);
+
+            if versions[0].1.is_none() {
+                versions.clear();
+            }
+        }
+    }
+
+    history.retain(|_, versions| !versions.is_empty());
+}
+
+/// Gets the value an item had as of a given datetime, if it existed then.
+pub fn value_as_of<V>(versions: &[(DateTime<Utc>, Option<V>)], datetime: DateTime<Utc>) -> Option<&V> {
+    versions
+        .iter()
+        .rev()
+        .find(|(committed_datetime, _)| *committed_datetime <= datetime)
+        .and_then(|(_, value)| value.as_ref())
+}
+
+/// Rebuilds the items that existed as of a given datetime.
+pub fn items_as_of<K: Ord + Clone, V: Clone>(
+    history: &BTreeMap<K, Versions<V>>,
+    datetime: DateTime<Utc>,
+) -> BTreeMap<K, V> {
+    history
+        .iter()
+        .filter_map(|(key, versions)| Some((key.clone(), value_as_of(versions, datetime)?.clone())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::MemoryDatastore;
+    use super::{items_as_of, record, value_as_of, RetentionPolicy, Versions};
+    use chrono::{Duration, Utc};
+    use clock::ManualClock;
+    use models::{EdgeKey, SpecificEdgeQuery, SpecificVertexQuery, Type, Vertex, VertexQueryExt};
+    use serde_json::Value as JsonValue;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use traits::{Datastore, Transaction};
+
+    #[test]
+    fn should_get_value_as_of_datetime() {
+        let now = Utc::now();
+        let versions: Versions<u8> = vec![
+            (now - Duration::seconds(3), Some(1)),
+            (now - Duration::seconds(2), None),
+            (now - Duration::seconds(1), Some(2)),
+        ];
+
+        assert_eq!(value_as_of(&versions, now - Duration::seconds(4)), None);
+        assert_eq!(value_as_of(&versions, now - Duration::seconds(3)), Some(&1));
+        assert_eq!(value_as_of(&versions, now - Duration::seconds(2)), None);
+        assert_eq!(value_as_of(&versions, now), Some(&2));
+    }
+
+    #[test]
+    fn should_get_items_as_of_datetime() {
+        let mut history = BTreeMap::new();
+        let retention = RetentionPolicy::new();
+        record(&mut history, &retention, "foo", Some(1), Utc::now());
+        record(&mut history, &retention, "bar", Some(2), Utc::now());
+        let checkpoint = Utc::now();
+        record(&mut history, &retention, "foo", None, Utc::now());
+
+        let items = items_as_of(&history, checkpoint);
+        assert_eq!(items.len(), 2);
+        assert_eq!(items_as_of(&history, Utc::now()).len(), 1);
+    }
+
+    #[test]
+    fn should_read_datastore_as_of_datetime() {
+        let datastore = MemoryDatastore::with_history();
+        let trans = datastore.transaction().unwrap();
+        let v = Vertex::new(Type::new("foo").unwrap());
+        let q = SpecificVertexQuery::single(v.id);
+        trans.create_vertex(&v).unwrap();
+        trans
+            .set_vertex_properties(q.clone().property("bar"), &JsonValue::Bool(true))
+            .unwrap();
+        let checkpoint = Utc::now();
+        trans
+            .set_vertex_properties(q.clone().property("bar"), &JsonValue::Bool(false))
+            .unwrap();
+        trans.delete_vertices(q.clone()).unwrap();
+
+        assert_eq!(trans.get_vertices(q.clone()).unwrap().len(), 0);
+
+        let past_trans = datastore.as_of(checkpoint).unwrap().transaction().unwrap();
+        assert_eq!(past_trans.get_vertices(q.clone()).unwrap().len(), 1);
+        let properties = past_trans.get_vertex_properties(q.property("bar")).unwrap();
+        assert_eq!(properties.len(), 1);
+        assert_eq!(properties[0].value, JsonValue::Bool(true));
+
+        assert!(MemoryDatastore::default().as_of(checkpoint).is_err());
+    }
+
+    #[test]
+    fn should_enforce_retention_policy() {
+        let mut history = BTreeMap::new();
+        let retention = RetentionPolicy::new().max_versions(2);
+        record(&mut history, &retention, "foo", Some(1), Utc::now());
+        record(&mut history, &retention, "foo", Some(2), Utc::now());
+        record(&mut history, &retention, "foo", Some(3), Utc::now());
+        assert_eq!(history["foo"].len(), 2);
+        assert_eq!(history["foo"][0].1, Some(2));
+
+        let mut history = BTreeMap::new();
+        let retention = RetentionPolicy::new().max_age(Duration::hours(1));
+        let now = Utc::now();
+        history.insert(
+            "foo",
+            vec![(now - Duration::hours(3), Some(1)), (now - Duration::hours(2), Some(2))],
+        );
+        history.insert("bar", vec![(now - Duration::hours(2), Some(1))]);
+        record(&mut history, &retention, "foo", Some(3), Utc::now());
+        record(&mut history, &retention, "bar", None, Utc::now());
+        assert_eq!(history["foo"].len(), 2);
+        assert_eq!(history["foo"][0].1, Some(2));
+        assert_eq!(history["bar"].len(), 2);
+    }
+
+    #[test]
+    fn should_vacuum_vertex_history() {
+        let datastore = MemoryDatastore::with_history();
+        let trans = datastore.transaction().unwrap();
+        let v = Vertex::new(Type::new("foo").unwrap());
+        let q = SpecificVertexQuery::single(v.id).property("bar");
+        trans.create_vertex(&v).unwrap();
+        trans.set_vertex_properties(q.clone(), &JsonValue::Bool(true)).unwrap();
+        let checkpoint = Utc::now();
+        trans.set_vertex_properties(q.clone(), &JsonValue::Bool(false)).unwrap();
+        datastore.vacuum_history(&[v.id]);
+
+        let past_trans = datastore.as_of(checkpoint).unwrap().transaction().unwrap();
+        assert_eq!(past_trans.get_vertex_properties(q.clone()).unwrap().len(), 0);
+        let properties = trans.get_vertex_properties(q).unwrap();
+        assert_eq!(properties[0].value, JsonValue::Bool(false));
+    }
+
+    #[test]
+    fn should_timestamp_with_clock() {
+        let start = Utc::now();
+        let clock = Arc::new(ManualClock::new(start));
+        let datastore = MemoryDatastore::with_history().with_clock(clock.clone());
+        let trans = datastore.transaction().unwrap();
+        let t = Type::new("foo").unwrap();
+        let outbound_v = Vertex::new(t.clone());
+        let inbound_v = Vertex::new(t.clone());
+        trans.create_vertex(&outbound_v).unwrap();
+        trans.create_vertex(&inbound_v).unwrap();
+
+        clock.advance(Duration::hours(1));
+        let key = EdgeKey::new(outbound_v.id, t, inbound_v.id);
+        trans.create_edge(&key).unwrap();
+
+        let edges = trans.get_edges(SpecificEdgeQuery::single(key.clone())).unwrap();
+        assert_eq!(edges[0].created_datetime, start + Duration::hours(1));
+
+        let past_trans = datastore.as_of(start).unwrap().transaction().unwrap();
+        assert_eq!(past_trans.get_vertex_count().unwrap(), 2);
+        assert_eq!(past_trans.get_edges(SpecificEdgeQuery::single(key)).unwrap().len(), 0);
+    }
+}
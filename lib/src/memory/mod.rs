@@ -3,13 +3,22 @@
 //!
 //! * Data is not persisted.
 //! * Transaction changes cannot be rolled back on error.
-//! * Locking is coarse-grained; only one thread can write to the datastore at
-//!   a time. Consequently, this may actually perform worse on highly
-//!   concurrent write-heavy workloads.
+//! * Data is partitioned into shards that are locked independently, so
+//!   reads that span several shards can observe a concurrent write that's
+//!   only been applied to some of them. Deleting vertices locks every shard.
+//!
+//! With the `rocksdb-datastore` feature, property values can be spilled to a
+//! temporary rocksdb database once they exceed a memory budget.
 
 mod datastore;
+mod history;
+#[cfg(feature = "rocksdb-datastore")]
+mod spill;
 
 pub use self::datastore::{MemoryDatastore, MemoryTransaction};
+pub use self::history::RetentionPolicy;
+#[cfg(feature = "rocksdb-datastore")]
+pub use self::spill::SpillOptions;
 
 #[cfg(feature = "bench-suite")]
 full_bench_impl!(MemoryDatastore::default());
@@ -0,0 +1,147 @@
+//! Spilling of cold property values to disk. When a memory datastore is
+//! created with a spill budget, property values beyond the budget are moved
+//! into a temporary rocksdb database, and read back from it on demand.
+
+use errors::Result;
+use rocksdb::{Options, WriteBatch, WriteOptions, DB};
+use serde_json::Value as JsonValue;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use util::generate_temporary_path;
+
+/// Options for spilling property values out of memory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpillOptions {
+    /// Roughly how many bytes of property values to keep in memory. Once
+    /// it's exceeded, the least recently written values are spilled until
+    /// the resident values fit in three quarters of the budget, so that
+    /// writes just over the budget don't spill on every commit.
+    pub budget_bytes: usize,
+
+    /// The directory to put the spill database in, or `None` for a new
+    /// directory under the temporary directory. It's deleted when the
+    /// datastore is dropped.
+    pub path: Option<String>,
+}
+
+impl SpillOptions {
+    /// Creates new spill options.
+    ///
+    /// # Arguments
+    /// * `budget_bytes` - Roughly how many bytes of property values to keep
+    ///   in memory.
+    pub fn new(budget_bytes: usize) -> Self {
+        SpillOptions {
+            budget_bytes,
+            path: None,
+        }
+    }
+
+    /// Sets the directory to put the spill database in.
+    ///
+    /// # Arguments
+    /// * `path` - The directory.
+    pub fn path(self, path: &str) -> Self {
+        SpillOptions {
+            path: Some(path.to_string()),
+            ..self
+        }
+    }
+
+    // The number of resident bytes to spill down to.
+    pub(crate) fn low_water_bytes(&self) -> usize {
+        self.budget_bytes / 4 * 3
+    }
+}
+
+// A temporary rocksdb database holding spilled property values. Each spilled
+// value gets a new id, and is never rewritten; values that are overwritten
+// or deleted after being spilled stay on disk until the store is dropped,
+// since forks of the datastore may still refer to them.
+pub(crate) struct SpillStore {
+    options: SpillOptions,
+    path: String,
+    db: Option<DB>,
+    next_id: AtomicU64,
+}
+
+impl SpillStore {
+    pub(crate) fn new(options: SpillOptions) -> Result<Self> {
+        let path = options.path.clone().unwrap_or_else(generate_temporary_path);
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        // Anything left over from a previous process is stale
+        DB::destroy(&opts, &path)?;
+        let db = DB::open(&opts, &path)?;
+
+        Ok(SpillStore {
+            options,
+            path,
+            db: Some(db),
+            next_id: AtomicU64::new(0),
+        })
+    }
+
+    pub(crate) fn options(&self) -> &SpillOptions {
+        &self.options
+    }
+
+    fn db(&self) -> &DB {
+        self.db.as_ref().expect("Expected the spill database to be open")
+    }
+
+    // Writes values to disk, returning their ids in the same order.
+    pub(crate) fn put_all(&self, values: &[&JsonValue]) -> Result<Vec<u64>> {
+        let mut batch = WriteBatch::default();
+        let mut ids = Vec::with_capacity(values.len());
+
+        for value in values {
+            let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+            batch.put(id.to_be_bytes(), serde_json::to_vec(value)?);
+            ids.push(id);
+        }
+
+        // The database doesn't outlive the process, so there's no point in
+        // logging writes for crash recovery
+        let mut opts = WriteOptions::default();
+        opts.disable_wal(true);
+        self.db().write_opt(batch, &opts)?;
+        Ok(ids)
+    }
+
+    pub(crate) fn get(&self, id: u64) -> Result<JsonValue> {
+        match self.db().get(id.to_be_bytes())? {
+            Some(value) => Ok(serde_json::from_slice(&value)?),
+            None => Err(format!("Spilled property value {} is missing", id).into()),
+        }
+    }
+}
+
+impl fmt::Debug for SpillStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SpillStore")
+            .field("options", &self.options)
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+impl Drop for SpillStore {
+    fn drop(&mut self) {
+        // The database has to be closed before it can be destroyed
+        self.db.take();
+        let _ = DB::destroy(&Options::default(), &self.path);
+    }
+}
+
+// Roughly how many bytes a property value takes up in memory.
+pub(crate) fn estimate_size(value: &JsonValue) -> usize {
+    let children = match value {
+        JsonValue::String(s) => s.len(),
+        JsonValue::Array(values) => values.iter().map(estimate_size).sum(),
+        JsonValue::Object(map) => map.iter().map(|(k, v)| k.len() + estimate_size(v)).sum(),
+        _ => 0,
+    };
+
+    children + std::mem::size_of::<JsonValue>()
+}
@@ -0,0 +1,300 @@
+//! A scripted transaction, so that applications embedding IndraDB can unit
+//! test their graph logic without a datastore. Calls are recorded, and each
+//! call returns the next scripted response.
+
+use chrono::offset::Utc;
+use chrono::DateTime;
+use errors::Result;
+use models;
+use serde_json::Value as JsonValue;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use traits::{Datastore, Transaction};
+use uuid::Uuid;
+
+/// A call made to a mock transaction.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Call {
+    CreateVertex(models::Vertex),
+    UpsertVertex(models::Vertex),
+    GetVertices(models::VertexQuery),
+    DeleteVertices(models::VertexQuery),
+    GetVertexCount,
+    CreateEdge(models::EdgeKey),
+    CreateEdgeWithDatetime(models::EdgeKey, DateTime<Utc>),
+    GetEdges(models::EdgeQuery),
+    DeleteEdges(models::EdgeQuery),
+    DeleteEdgesUnmodifiedSince(models::EdgeQuery, DateTime<Utc>),
+    GetEdgeCount(Uuid, Option<models::Type>, models::EdgeDirection),
+    GetVertexProperties(models::VertexPropertyQuery),
+    SetVertexProperties(models::VertexPropertyQuery, JsonValue),
+    DeleteVertexProperties(models::VertexPropertyQuery),
+    GetAllVertexProperties(models::VertexQuery),
+    GetEdgeProperties(models::EdgePropertyQuery),
+    SetEdgeProperties(models::EdgePropertyQuery, JsonValue),
+    DeleteEdgeProperties(models::EdgePropertyQuery),
+    GetAllEdgeProperties(models::EdgeQuery),
+}
+
+/// A scripted response to a call made to a mock transaction.
+#[derive(Clone, Debug)]
+pub enum Response {
+    /// The response to calls that return whether they succeeded, e.g.
+    /// `create_vertex`.
+    Bool(bool),
+
+    /// The response to `upsert_vertex`.
+    PreviousType(Option<models::Type>),
+
+    /// The response to `get_vertices`.
+    Vertices(Vec<models::Vertex>),
+
+    /// The response to `get_vertex_count`, `get_edge_count` and
+    /// `delete_edges_unmodified_since`.
+    Count(u64),
+
+    /// The response to `get_edges`.
+    Edges(Vec<models::Edge>),
+
+    /// The response to `get_vertex_properties`.
+    VertexProperties(Vec<models::VertexProperty>),
+
+    /// The response to `get_all_vertex_properties`.
+    AllVertexProperties(Vec<models::VertexProperties>),
+
+    /// The response to `get_edge_properties`.
+    EdgeProperties(Vec<models::EdgeProperty>),
+
+    /// The response to `get_all_edge_properties`.
+    AllEdgeProperties(Vec<models::EdgeProperties>),
+
+    /// The response to calls that don't return anything, e.g.
+    /// `delete_vertices`.
+    Unit,
+
+    /// Makes the call fail with the given message.
+    Error(String),
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    calls: Vec<Call>,
+    responses: VecDeque<Response>,
+}
+
+/// A transaction that records the calls made to it and returns scripted
+/// responses. Responses are returned in the order they were added,
+/// regardless of which method is called; once they run out, calls succeed
+/// with empty results, e.g. `false`, `0` or no vertices.
+///
+/// Clones share their calls and responses, so a clone can be handed to the
+/// code under test while the original is used to script and inspect it.
+#[derive(Clone, Debug, Default)]
+pub struct MockTransaction(Arc<Mutex<MockState>>);
+
+impl MockTransaction {
+    /// Creates a new mock transaction with no scripted responses.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a response, to be returned after the ones already added.
+    ///
+    /// # Arguments
+    /// * `response` - The response.
+    pub fn respond(&self, response: Response) -> &Self {
+        self.0.lock().unwrap().responses.push_back(response);
+        self
+    }
+
+    /// Gets the calls made so far, oldest first.
+    pub fn calls(&self) -> Vec<Call> {
+        self.0.lock().unwrap().calls.clone()
+    }
+
+    /// Gets the number of scripted responses that haven't been returned yet.
+    pub fn remaining_response_count(&self) -> usize {
+        self.0.lock().unwrap().responses.len()
+    }
+
+    fn call(&self, call: Call) -> Result<Option<Response>> {
+        let mut state = self.0.lock().unwrap();
+        state.calls.push(call);
+
+        match state.responses.pop_front() {
+            Some(Response::Error(message)) => Err(message.into()),
+            response => Ok(response),
+        }
+    }
+}
+
+// Converts a scripted response into the return value of a call, reporting
+// responses of the wrong kind as errors so that mis-scripted tests fail.
+macro_rules! expect_response {
+    ($response:expr, $variant:ident, $default:expr) => {
+        match $response {
+            None => Ok($default),
+            Some(Response::$variant(value)) => Ok(value),
+            Some(response) => Err(format!("Unexpected scripted response: {:?}", response).into()),
+        }
+    };
+}
+
+fn expect_unit(response: Option<Response>) -> Result<()> {
+    match response {
+        None | Some(Response::Unit) => Ok(()),
+        Some(response) => Err(format!("Unexpected scripted response: {:?}", response).into()),
+    }
+}
+
+impl Transaction for MockTransaction {
+    fn create_vertex(&self, vertex: &models::Vertex) -> Result<bool> {
+        expect_response!(self.call(Call::CreateVertex(vertex.clone()))?, Bool, false)
+    }
+
+    fn upsert_vertex(&self, vertex: &models::Vertex) -> Result<Option<models::Type>> {
+        expect_response!(self.call(Call::UpsertVertex(vertex.clone()))?, PreviousType, None)
+    }
+
+    fn get_vertices<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<Vec<models::Vertex>> {
+        expect_response!(self.call(Call::GetVertices(q.into()))?, Vertices, Vec::new())
+    }
+
+    fn delete_vertices<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<()> {
+        expect_unit(self.call(Call::DeleteVertices(q.into()))?)
+    }
+
+    fn get_vertex_count(&self) -> Result<u64> {
+        expect_response!(self.call(Call::GetVertexCount)?, Count, 0)
+    }
+
+    fn create_edge(&self, key: &models::EdgeKey) -> Result<bool> {
+        expect_response!(self.call(Call::CreateEdge(key.clone()))?, Bool, false)
+    }
+
+    fn create_edge_with_datetime(&self, key: &models::EdgeKey, update_datetime: DateTime<Utc>) -> Result<bool> {
+        let call = Call::CreateEdgeWithDatetime(key.clone(), update_datetime);
+        expect_response!(self.call(call)?, Bool, false)
+    }
+
+    fn get_edges<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<Vec<models::Edge>> {
+        expect_response!(self.call(Call::GetEdges(q.into()))?, Edges, Vec::new())
+    }
+
+    fn delete_edges<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<()> {
+        expect_unit(self.call(Call::DeleteEdges(q.into()))?)
+    }
+
+    fn delete_edges_unmodified_since<Q: Into<models::EdgeQuery>>(&self, q: Q, since: DateTime<Utc>) -> Result<u64> {
+        expect_response!(self.call(Call::DeleteEdgesUnmodifiedSince(q.into(), since))?, Count, 0)
+    }
+
+    fn get_edge_count(&self, id: Uuid, t: Option<&models::Type>, direction: models::EdgeDirection) -> Result<u64> {
+        let call = Call::GetEdgeCount(id, t.cloned(), direction);
+        expect_response!(self.call(call)?, Count, 0)
+    }
+
+    fn get_vertex_properties(&self, q: models::VertexPropertyQuery) -> Result<Vec<models::VertexProperty>> {
+        expect_response!(self.call(Call::GetVertexProperties(q))?, VertexProperties, Vec::new())
+    }
+
+    fn set_vertex_properties(&self, q: models::VertexPropertyQuery, value: &JsonValue) -> Result<()> {
+        expect_unit(self.call(Call::SetVertexProperties(q, value.clone()))?)
+    }
+
+    fn delete_vertex_properties(&self, q: models::VertexPropertyQuery) -> Result<()> {
+        expect_unit(self.call(Call::DeleteVertexProperties(q))?)
+    }
+
+    fn get_all_vertex_properties<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<Vec<models::VertexProperties>> {
+        let call = Call::GetAllVertexProperties(q.into());
+        expect_response!(self.call(call)?, AllVertexProperties, Vec::new())
+    }
+
+    fn get_edge_properties(&self, q: models::EdgePropertyQuery) -> Result<Vec<models::EdgeProperty>> {
+        expect_response!(self.call(Call::GetEdgeProperties(q))?, EdgeProperties, Vec::new())
+    }
+
+    fn set_edge_properties(&self, q: models::EdgePropertyQuery, value: &JsonValue) -> Result<()> {
+        expect_unit(self.call(Call::SetEdgeProperties(q, value.clone()))?)
+    }
+
+    fn delete_edge_properties(&self, q: models::EdgePropertyQuery) -> Result<()> {
+        expect_unit(self.call(Call::DeleteEdgeProperties(q))?)
+    }
+
+    fn get_all_edge_properties<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<Vec<models::EdgeProperties>> {
+        let call = Call::GetAllEdgeProperties(q.into());
+        expect_response!(self.call(call)?, AllEdgeProperties, Vec::new())
+    }
+}
+
+/// A datastore whose transactions are all clones of one mock transaction,
+/// for code that takes a datastore rather than a transaction. Bulk inserts
+/// go through the transaction.
+#[derive(Clone, Debug, Default)]
+pub struct MockDatastore(MockTransaction);
+
+impl MockDatastore {
+    /// Creates a new mock datastore.
+    ///
+    /// # Arguments
+    /// * `trans` - The mock transaction to hand out.
+    pub fn new(trans: MockTransaction) -> Self {
+        MockDatastore(trans)
+    }
+}
+
+impl Datastore for MockDatastore {
+    type Trans = MockTransaction;
+
+    fn transaction(&self) -> Result<Self::Trans> {
+        Ok(self.0.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Call, MockDatastore, MockTransaction, Response};
+    use models::{SpecificVertexQuery, Type, Vertex, VertexQueryExt};
+    use serde_json::Value as JsonValue;
+    use traits::{Datastore, Transaction};
+
+    #[test]
+    fn should_record_calls_and_return_responses() {
+        let mock = MockTransaction::new();
+        let v = Vertex::new(Type::new("foo").unwrap());
+        mock.respond(Response::Bool(true))
+            .respond(Response::Vertices(vec![v.clone()]))
+            .respond(Response::Error("oops".to_string()));
+
+        let trans = MockDatastore::new(mock.clone()).transaction().unwrap();
+        let q = SpecificVertexQuery::single(v.id);
+        assert!(trans.create_vertex(&v).unwrap());
+        assert_eq!(trans.get_vertices(q.clone()).unwrap(), vec![v.clone()]);
+        assert!(trans
+            .set_vertex_properties(q.clone().property("bar"), &JsonValue::Null)
+            .is_err());
+
+        // Responses have run out
+        assert_eq!(trans.get_vertex_count().unwrap(), 0);
+        assert_eq!(mock.remaining_response_count(), 0);
+
+        assert_eq!(
+            mock.calls(),
+            vec![
+                Call::CreateVertex(v),
+                Call::GetVertices(q.clone().into()),
+                Call::SetVertexProperties(q.property("bar"), JsonValue::Null),
+                Call::GetVertexCount,
+            ]
+        );
+    }
+
+    #[test]
+    fn should_fail_on_mismatched_responses() {
+        let mock = MockTransaction::new();
+        mock.respond(Response::Count(1));
+        assert!(mock.create_vertex(&Vertex::new(Type::new("foo").unwrap())).is_err());
+    }
+}
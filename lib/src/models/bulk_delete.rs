@@ -0,0 +1,22 @@
+/// The progress of a bulk delete, reported after each batch is deleted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BulkDeleteProgress {
+    /// The number of items deleted so far.
+    pub deleted_count: u64,
+    /// The number of items the query matched.
+    pub total_count: u64,
+}
+
+impl BulkDeleteProgress {
+    /// Creates a new bulk delete progress.
+    ///
+    /// # Arguments
+    /// * `deleted_count` - The number of items deleted so far.
+    /// * `total_count` - The number of items the query matched.
+    pub fn new(deleted_count: u64, total_count: u64) -> Self {
+        Self {
+            deleted_count,
+            total_count,
+        }
+    }
+}
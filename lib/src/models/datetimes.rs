@@ -0,0 +1,74 @@
+use chrono::offset::Utc;
+use chrono::{DateTime, SecondsFormat};
+use serde_json::Map;
+use serde_json::Value as JsonValue;
+
+/// The key that marks a JSON object as a datetime property value.
+pub const DATETIME_KEY: &str = "$datetime";
+
+/// A datetime property value.
+///
+/// JSON has no datetime type, so datetimes are stored as an object with a
+/// single `$datetime` key, whose value is an RFC 3339 string. Property
+/// indexes recognize this shape, and order datetimes chronologically rather
+/// than as objects.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct DateTimeValue(pub DateTime<Utc>);
+
+impl DateTimeValue {
+    /// Parses a datetime property value from JSON. Returns `None` if the
+    /// JSON isn't a datetime property value.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The JSON value.
+    pub fn from_json(value: &JsonValue) -> Option<Self> {
+        let map = value.as_object()?;
+
+        if map.len() != 1 {
+            return None;
+        }
+
+        let s = map.get(DATETIME_KEY)?.as_str()?;
+        let datetime = DateTime::parse_from_rfc3339(s).ok()?;
+        Some(DateTimeValue(datetime.with_timezone(&Utc)))
+    }
+}
+
+impl From<DateTime<Utc>> for DateTimeValue {
+    fn from(datetime: DateTime<Utc>) -> Self {
+        DateTimeValue(datetime)
+    }
+}
+
+impl From<DateTimeValue> for JsonValue {
+    fn from(value: DateTimeValue) -> Self {
+        let mut map = Map::new();
+        map.insert(
+            DATETIME_KEY.to_string(),
+            JsonValue::String(value.0.to_rfc3339_opts(SecondsFormat::Nanos, true)),
+        );
+        JsonValue::Object(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DateTimeValue;
+    use chrono::{DateTime, Utc};
+    use serde_json::Value as JsonValue;
+
+    #[test]
+    fn should_convert_datetime_values_to_and_from_json() {
+        let datetime: DateTime<Utc> = "2019-02-03T04:05:06.000000007Z".parse().unwrap();
+        let value = DateTimeValue(datetime);
+        let json = JsonValue::from(value);
+        assert_eq!(DateTimeValue::from_json(&json), Some(value));
+        assert_eq!(
+            DateTimeValue::from_json(&JsonValue::String("2019-02-03".to_string())),
+            None
+        );
+        let json = serde_json::from_str(r#"{"$datetime": "2019-02-03T04:05:06Z", "foo": 1}"#).unwrap();
+        assert_eq!(DateTimeValue::from_json(&json), None);
+    }
+}
@@ -0,0 +1,161 @@
+use super::edges::EdgeKey;
+use super::types::Type;
+use super::vertices::Vertex;
+use serde_json::Value as JsonValue;
+use uuid::Uuid;
+
+/// The kinds of entities that events are published for.
+#[derive(Eq, PartialEq, Clone, Debug, Hash, Copy)]
+pub enum EventKind {
+    Vertex,
+    Edge,
+    VertexProperty,
+    EdgeProperty,
+}
+
+/// A change to a datastore, published to subscribers in the same process.
+///
+/// Events are published for each item matched by a write, after the write
+/// has been applied.
+#[derive(Clone, Debug)]
+pub enum Event {
+    /// A vertex was created.
+    VertexCreated(Vertex),
+
+    /// A vertex was deleted. Its properties and edges are deleted along
+    /// with it, without events of their own.
+    VertexDeleted(Vertex),
+
+    /// An edge was created, or re-created, which bumps its update datetime.
+    EdgeCreated(EdgeKey),
+
+    /// An edge was deleted. Its properties are deleted along with it,
+    /// without events of their own.
+    EdgeDeleted(EdgeKey),
+
+    /// A vertex property was set.
+    VertexPropertySet(Uuid, String, JsonValue),
+
+    /// A vertex property was deleted.
+    VertexPropertyDeleted(Uuid, String),
+
+    /// An edge property was set.
+    EdgePropertySet(EdgeKey, String, JsonValue),
+
+    /// An edge property was deleted.
+    EdgePropertyDeleted(EdgeKey, String),
+}
+
+impl Event {
+    /// Gets the kind of entity the event is for.
+    pub fn kind(&self) -> EventKind {
+        match *self {
+            Event::VertexCreated(_) | Event::VertexDeleted(_) => EventKind::Vertex,
+            Event::EdgeCreated(_) | Event::EdgeDeleted(_) => EventKind::Edge,
+            Event::VertexPropertySet(..) | Event::VertexPropertyDeleted(..) => EventKind::VertexProperty,
+            Event::EdgePropertySet(..) | Event::EdgePropertyDeleted(..) => EventKind::EdgeProperty,
+        }
+    }
+
+    /// Gets the type of the vertex or edge the event is for. Vertex
+    /// property events don't carry the vertex's type, so this is `None` for
+    /// them.
+    pub fn t(&self) -> Option<&Type> {
+        match *self {
+            Event::VertexCreated(ref vertex) | Event::VertexDeleted(ref vertex) => Some(&vertex.t),
+            Event::EdgeCreated(ref key) | Event::EdgeDeleted(ref key) => Some(&key.t),
+            Event::EdgePropertySet(ref key, _, _) | Event::EdgePropertyDeleted(ref key, _) => Some(&key.t),
+            Event::VertexPropertySet(..) | Event::VertexPropertyDeleted(..) => None,
+        }
+    }
+}
+
+/// Selects which events a subscriber receives. An empty filter matches
+/// every event.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EventFilter {
+    /// The kinds of entities to receive events for. If empty, events for
+    /// all kinds are received.
+    pub kinds: Vec<EventKind>,
+
+    /// The types of vertices and edges to receive events for. If empty,
+    /// events for all types are received. Vertex property events don't
+    /// carry a type, so they aren't filtered by it.
+    pub types: Vec<Type>,
+}
+
+impl EventFilter {
+    /// Creates a new filter that matches every event.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a kind of entity to receive events for.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - The kind of entity.
+    pub fn kind(mut self, kind: EventKind) -> Self {
+        self.kinds.push(kind);
+        self
+    }
+
+    /// Adds a type of vertex or edge to receive events for.
+    ///
+    /// # Arguments
+    ///
+    /// * `t` - The type.
+    pub fn t(mut self, t: Type) -> Self {
+        self.types.push(t);
+        self
+    }
+
+    /// Checks whether an event matches the filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event to check.
+    pub fn matches(&self, event: &Event) -> bool {
+        if !self.kinds.is_empty() && !self.kinds.contains(&event.kind()) {
+            return false;
+        }
+
+        match event.t() {
+            Some(t) => self.types.is_empty() || self.types.contains(t),
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Event, EventFilter, EventKind};
+    use models::{EdgeKey, Type, Vertex};
+    use uuid::Uuid;
+
+    #[test]
+    fn should_match_events() {
+        let t = Type::new("foo").unwrap();
+        let vertex_event = Event::VertexCreated(Vertex::new(t.clone()));
+        let edge_event = Event::EdgeDeleted(EdgeKey::new(
+            Uuid::default(),
+            Type::new("bar").unwrap(),
+            Uuid::default(),
+        ));
+        let property_event = Event::VertexPropertyDeleted(Uuid::default(), "baz".to_string());
+
+        let filter = EventFilter::new();
+        assert!(filter.matches(&vertex_event));
+        assert!(filter.matches(&edge_event));
+
+        let filter = EventFilter::new().kind(EventKind::Vertex);
+        assert!(filter.matches(&vertex_event));
+        assert!(!filter.matches(&edge_event));
+        assert!(!filter.matches(&property_event));
+
+        let filter = EventFilter::new().t(t);
+        assert!(filter.matches(&vertex_event));
+        assert!(!filter.matches(&edge_event));
+        assert!(filter.matches(&property_event));
+    }
+}
@@ -0,0 +1,100 @@
+use super::vertices::Vertex;
+use errors::ValidationResult;
+use serde_json::Value as JsonValue;
+
+/// A property index, which indexes vertices by the values of one or more of
+/// their properties.
+///
+/// Indexes over multiple properties are composite: entries are ordered by
+/// the value of the first property, then the second, and so on. A vertex is
+/// only indexed if it has all of the indexed properties.
+///
+/// Multi-valued indexes have an entry for each element of array values,
+/// rather than one for the array as a whole, so e.g. vertices whose `tags`
+/// contain `"x"` can be looked up by `["x"]`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PropertyIndex {
+    /// The name of the index.
+    pub name: String,
+
+    /// The names of the indexed properties, in order.
+    pub properties: Vec<String>,
+
+    /// Whether array values are indexed by each of their elements.
+    pub multi_valued: bool,
+}
+
+impl PropertyIndex {
+    /// Creates a new property index.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the index, which must be between 1 and 255
+    ///   bytes long.
+    /// * `properties` - The names of the indexed properties, in order.
+    ///
+    /// # Errors
+    /// Returns a `ValidationError` if the name is empty or too long, or if
+    /// no properties are specified.
+    pub fn new<S: Into<String>>(name: S, properties: Vec<String>) -> ValidationResult<Self> {
+        let name = name.into();
+
+        if name.is_empty() {
+            Err("Index name is empty".into())
+        } else if name.len() > 255 {
+            Err("Index name is too long".into())
+        } else if properties.is_empty() {
+            Err("Index has no properties".into())
+        } else {
+            Ok(Self {
+                name,
+                properties,
+                multi_valued: false,
+            })
+        }
+    }
+
+    /// Sets whether array values are indexed by each of their elements.
+    ///
+    /// # Arguments
+    ///
+    /// * `multi_valued` - Whether the index is multi-valued.
+    pub fn multi_valued(self, multi_valued: bool) -> Self {
+        Self { multi_valued, ..self }
+    }
+}
+
+/// The order to scan an index in.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum IndexOrder {
+    /// From the lowest indexed values to the highest.
+    Ascending,
+
+    /// From the highest indexed values to the lowest.
+    Descending,
+}
+
+/// A vertex found by scanning an index, along with the values of its indexed
+/// properties.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexedVertex {
+    /// The vertex.
+    pub vertex: Vertex,
+
+    /// The values of the indexed properties, in the index's order.
+    pub values: Vec<JsonValue>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PropertyIndex;
+
+    #[test]
+    fn should_validate_property_index() {
+        let properties = vec!["country".to_string(), "city".to_string()];
+        assert!(PropertyIndex::new("location", properties.clone()).is_ok());
+        assert!(PropertyIndex::new("", properties.clone()).is_err());
+        assert!(PropertyIndex::new("a".repeat(256), properties).is_err());
+        assert!(PropertyIndex::new("location", vec![]).is_err());
+    }
+}
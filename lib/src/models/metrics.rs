@@ -0,0 +1,16 @@
+/// Metadata about how a query was executed, returned alongside its results
+/// by `Transaction::get_vertices_with_metrics` and
+/// `Transaction::get_edges_with_metrics`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QueryMetrics {
+    /// How many keys the datastore read to answer the query, across every
+    /// step of it. Datastores that don't count the keys they read report
+    /// the number of items they produced instead.
+    pub keys_scanned: u64,
+    /// How many items were returned.
+    pub items_returned: u64,
+    /// Whether there were more results than the query's limit allowed, so
+    /// some were left out. Only the limit of the last step of the query is
+    /// taken into account.
+    pub truncated: bool,
+}
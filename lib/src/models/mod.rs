@@ -1,13 +1,32 @@
+mod bulk_delete;
 mod bulk_insert;
+mod datetimes;
 mod edges;
+mod events;
+mod indexes;
+mod metrics;
+mod operations;
+mod pages;
 mod properties;
 mod queries;
 mod types;
 mod vertices;
+mod write_pressure;
 
+pub use self::bulk_delete::BulkDeleteProgress;
 pub use self::bulk_insert::BulkInsertItem;
+pub use self::datetimes::{DateTimeValue, DATETIME_KEY};
 pub use self::edges::{Edge, EdgeKey};
-pub use self::properties::{EdgeProperty, VertexProperty};
+pub use self::events::{Event, EventFilter, EventKind};
+pub use self::indexes::{IndexOrder, IndexedVertex, PropertyIndex};
+pub use self::metrics::QueryMetrics;
+pub use self::operations::{Operation, OperationOutput};
+pub use self::pages::{EdgePage, VertexPage};
+pub use self::properties::{
+    EdgeProperties, EdgeProperty, NamedEdgeProperty, NamedProperty, NamedVertexProperty, RawEdgeProperty, RawVertexProperty,
+    VertexProperties, VertexProperty,
+};
 pub use self::queries::*;
 pub use self::types::Type;
 pub use self::vertices::Vertex;
+pub use self::write_pressure::WritePressure;
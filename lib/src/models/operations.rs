@@ -0,0 +1,41 @@
+use super::edges::{Edge, EdgeKey};
+use super::properties::{EdgeProperty, VertexProperty};
+use super::queries::{EdgePropertyQuery, EdgeQuery, VertexPropertyQuery, VertexQuery};
+use super::types::Type;
+use super::vertices::Vertex;
+use serde_json::Value as JsonValue;
+use uuid::Uuid;
+
+/// A single operation in a batch. Each variant corresponds to the
+/// `Transaction` method of the same name.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operation {
+    CreateVertex(Vertex),
+    CreateVertexFromType(Type),
+    GetVertices(VertexQuery),
+    DeleteVertices(VertexQuery),
+    CreateEdge(EdgeKey),
+    GetEdges(EdgeQuery),
+    DeleteEdges(EdgeQuery),
+    GetVertexProperties(VertexPropertyQuery),
+    SetVertexProperties(VertexPropertyQuery, JsonValue),
+    DeleteVertexProperties(VertexPropertyQuery),
+    GetEdgeProperties(EdgePropertyQuery),
+    SetEdgeProperties(EdgePropertyQuery, JsonValue),
+    DeleteEdgeProperties(EdgePropertyQuery),
+}
+
+/// The output of a single operation in a batch.
+#[derive(Clone, Debug)]
+pub enum OperationOutput {
+    /// Whether a vertex or edge was created.
+    Created(bool),
+    /// The id of a vertex created from a type.
+    Id(Uuid),
+    Vertices(Vec<Vertex>),
+    Edges(Vec<Edge>),
+    VertexProperties(Vec<VertexProperty>),
+    EdgeProperties(Vec<EdgeProperty>),
+    /// The output of operations that don't return anything.
+    Done,
+}
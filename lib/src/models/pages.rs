@@ -0,0 +1,37 @@
+use super::edges::Edge;
+use super::queries::{EdgeQuery, VertexQuery};
+use super::vertices::Vertex;
+
+/// Vertices returned by `Transaction::get_vertices_page`, whose number was
+/// capped at a maximum result size.
+#[derive(Clone, Debug)]
+pub struct VertexPage {
+    /// The vertices.
+    pub vertices: Vec<Vertex>,
+
+    /// Whether the maximum result size cut the results short, so there may
+    /// be more of them.
+    pub truncated: bool,
+
+    /// A query that gets the rest of the results. This is only set if the
+    /// results were truncated and the query can be resumed; piped queries
+    /// can't be, so they have to be narrowed down instead.
+    pub cursor: Option<VertexQuery>,
+}
+
+/// Edges returned by `Transaction::get_edges_page`, whose number was capped
+/// at a maximum result size.
+#[derive(Clone, Debug)]
+pub struct EdgePage {
+    /// The edges.
+    pub edges: Vec<Edge>,
+
+    /// Whether the maximum result size cut the results short, so there may
+    /// be more of them.
+    pub truncated: bool,
+
+    /// A query that gets the rest of the results. This is only set if the
+    /// results were truncated and the query can be resumed; piped queries
+    /// can't be, so they have to be narrowed down instead.
+    pub cursor: Option<EdgeQuery>,
+}
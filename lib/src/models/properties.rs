@@ -1,4 +1,5 @@
-use super::edges::EdgeKey;
+use super::edges::{Edge, EdgeKey};
+use super::vertices::Vertex;
 use serde_json::Value as JsonValue;
 use uuid::Uuid;
 
@@ -45,3 +46,165 @@ impl EdgeProperty {
         Self { key, value }
     }
 }
+
+/// Represents a vertex property whose value hasn't been deserialized.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawVertexProperty {
+    /// The id of the vertex
+    pub id: Uuid,
+
+    /// The property value, as JSON-encoded bytes.
+    pub value: Vec<u8>,
+}
+
+impl RawVertexProperty {
+    /// Creates a new raw vertex property.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the vertex.
+    /// * `value` - The property value, as JSON-encoded bytes.
+    pub fn new(id: Uuid, value: Vec<u8>) -> Self {
+        Self { id, value }
+    }
+}
+
+/// Represents an edge property whose value hasn't been deserialized.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawEdgeProperty {
+    /// The key to the edge.
+    pub key: EdgeKey,
+
+    /// The property value, as JSON-encoded bytes.
+    pub value: Vec<u8>,
+}
+
+impl RawEdgeProperty {
+    /// Creates a new raw edge property.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to the edge.
+    /// * `value` - The property value, as JSON-encoded bytes.
+    pub fn new(key: EdgeKey, value: Vec<u8>) -> Self {
+        Self { key, value }
+    }
+}
+
+/// Represents a property and its name.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NamedProperty {
+    /// The property name.
+    pub name: String,
+
+    /// The property value.
+    pub value: JsonValue,
+}
+
+impl NamedProperty {
+    /// Creates a new named property.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The property name.
+    /// * `value` - The property value.
+    pub fn new(name: String, value: JsonValue) -> Self {
+        Self { name, value }
+    }
+}
+
+/// Represents a vertex property along with the id of its vertex and its name.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NamedVertexProperty {
+    /// The id of the vertex.
+    pub id: Uuid,
+
+    /// The property name.
+    pub name: String,
+
+    /// The property value.
+    pub value: JsonValue,
+}
+
+impl NamedVertexProperty {
+    /// Creates a new named vertex property.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the vertex.
+    /// * `name` - The property name.
+    /// * `value` - The property value.
+    pub fn new(id: Uuid, name: String, value: JsonValue) -> Self {
+        Self { id, name, value }
+    }
+}
+
+/// Represents an edge property along with the key of its edge and its name.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NamedEdgeProperty {
+    /// The key to the edge.
+    pub key: EdgeKey,
+
+    /// The property name.
+    pub name: String,
+
+    /// The property value.
+    pub value: JsonValue,
+}
+
+impl NamedEdgeProperty {
+    /// Creates a new named edge property.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to the edge.
+    /// * `name` - The property name.
+    /// * `value` - The property value.
+    pub fn new(key: EdgeKey, name: String, value: JsonValue) -> Self {
+        Self { key, name, value }
+    }
+}
+
+/// Represents a vertex along with some or all of its properties.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VertexProperties {
+    /// The vertex.
+    pub vertex: Vertex,
+
+    /// The vertex's properties.
+    pub props: Vec<NamedProperty>,
+}
+
+impl VertexProperties {
+    /// Creates new vertex properties.
+    ///
+    /// # Arguments
+    ///
+    /// * `vertex` - The vertex.
+    /// * `props` - The vertex's properties.
+    pub fn new(vertex: Vertex, props: Vec<NamedProperty>) -> Self {
+        Self { vertex, props }
+    }
+}
+
+/// Represents an edge along with some or all of its properties.
+#[derive(Clone, Debug)]
+pub struct EdgeProperties {
+    /// The edge.
+    pub edge: Edge,
+
+    /// The edge's properties.
+    pub props: Vec<NamedProperty>,
+}
+
+impl EdgeProperties {
+    /// Creates new edge properties.
+    ///
+    /// # Arguments
+    ///
+    /// * `edge` - The edge.
+    /// * `props` - The edge's properties.
+    pub fn new(edge: Edge, props: Vec<NamedProperty>) -> Self {
+        Self { edge, props }
+    }
+}
@@ -12,12 +12,14 @@ use uuid::Uuid;
 /// Edge and vertex queries can build off of one another via pipes - e.g. you
 /// can get the outbound edges of a set of vertices by piping from a vertex
 /// query to an edge query. `EdgeDirection`s are used to specify which
-/// end of things you want to pipe - either the outbound items or the inbound
-/// items.
+/// end of things you want to pipe - either the outbound items, the inbound
+/// items, or both. Piping with `Both` de-duplicates the results, so e.g. a
+/// self-loop edge is only returned once.
 #[derive(Eq, PartialEq, Clone, Debug, Hash, Copy)]
 pub enum EdgeDirection {
     Outbound,
     Inbound,
+    Both,
 }
 
 impl FromStr for EdgeDirection {
@@ -27,6 +29,7 @@ impl FromStr for EdgeDirection {
         match s {
             "outbound" => Ok(EdgeDirection::Outbound),
             "inbound" => Ok(EdgeDirection::Inbound),
+            "both" => Ok(EdgeDirection::Both),
             _ => Err("invalid value".into()),
         }
     }
@@ -37,6 +40,7 @@ impl From<EdgeDirection> for String {
         match d {
             EdgeDirection::Outbound => "outbound".to_string(),
             EdgeDirection::Inbound => "inbound".to_string(),
+            EdgeDirection::Both => "both".to_string(),
         }
     }
 }
@@ -89,6 +93,15 @@ pub trait VertexQueryExt: Into<VertexQuery> {
         PipeEdgeQuery::new(Box::new(self.into()), EdgeDirection::Inbound, limit)
     }
 
+    /// Gets both the outbound and inbound edges associated with the
+    /// vertices.
+    ///
+    /// # Arguments
+    /// * `limit` - Limits the number of returned results.
+    fn both(self, limit: u32) -> PipeEdgeQuery {
+        PipeEdgeQuery::new(Box::new(self.into()), EdgeDirection::Both, limit)
+    }
+
     /// Gets a property associated with the vertices.
     ///
     /// # Arguments
@@ -296,6 +309,14 @@ pub trait EdgeQueryExt: Into<EdgeQuery> {
         PipeVertexQuery::new(Box::new(self.into()), EdgeDirection::Inbound, limit)
     }
 
+    /// Gets the vertices associated with both ends of the edges.
+    ///
+    /// # Arguments
+    /// * `limit` - Limits the number of returned results.
+    fn both(self, limit: u32) -> PipeVertexQuery {
+        PipeVertexQuery::new(Box::new(self.into()), EdgeDirection::Both, limit)
+    }
+
     /// Gets a property associated with the edges.
     ///
     /// # Arguments
@@ -458,6 +479,7 @@ mod tests {
     fn should_convert_str_to_edge_direction() {
         assert_eq!(EdgeDirection::from_str("outbound").unwrap(), EdgeDirection::Outbound);
         assert_eq!(EdgeDirection::from_str("inbound").unwrap(), EdgeDirection::Inbound);
+        assert_eq!(EdgeDirection::from_str("both").unwrap(), EdgeDirection::Both);
         assert!(EdgeDirection::from_str("foo").is_err());
     }
 
@@ -467,5 +489,7 @@ mod tests {
         assert_eq!(s, "outbound".to_string());
         let s: String = EdgeDirection::Inbound.into();
         assert_eq!(s, "inbound".to_string());
+        let s: String = EdgeDirection::Both.into();
+        assert_eq!(s, "both".to_string());
     }
 }
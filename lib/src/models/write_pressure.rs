@@ -0,0 +1,22 @@
+/// How far a datastore's background work has fallen behind its writes. When
+/// compaction can't keep up, writes are slowed down and eventually stopped
+/// until it catches up, so writers such as ingestion pipelines can check
+/// this to back off before their writes start timing out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WritePressure {
+    /// Whether writes are stopped until the backlog clears.
+    pub stopped: bool,
+    /// The rate writes are being slowed down to, in bytes per second, or
+    /// `None` if they aren't being slowed down.
+    pub delayed_write_rate: Option<u64>,
+    /// An estimate of the number of bytes compaction has to rewrite to clear
+    /// the backlog.
+    pub pending_compaction_bytes: u64,
+}
+
+impl WritePressure {
+    /// Whether writes are being slowed down or stopped.
+    pub fn is_stalled(&self) -> bool {
+        self.stopped || self.delayed_write_rate.is_some()
+    }
+}
@@ -0,0 +1,771 @@
+use super::super::{Datastore, EdgePropertyQuery, EdgeQuery, Transaction, VertexPropertyQuery, VertexQuery};
+use chrono::offset::Utc;
+use chrono::DateTime;
+use clock::{Clock, SystemClock};
+use errors::Result;
+use models;
+use models::{EdgeQueryExt, VertexQueryExt};
+use serde_json::Value as JsonValue;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use uuid::Uuid;
+
+// Changes that haven't been committed to the underlying datastore yet.
+// `None` marks something that has been deleted.
+#[derive(Debug, Default)]
+struct Delta {
+    vertices: BTreeMap<Uuid, Option<models::Type>>,
+    edges: BTreeMap<models::EdgeKey, Option<DateTime<Utc>>>,
+    vertex_properties: BTreeMap<(Uuid, String), Option<JsonValue>>,
+    edge_properties: BTreeMap<(models::EdgeKey, String), Option<JsonValue>>,
+}
+
+// Applies staged property changes to a vertex's or edge's properties from
+// the underlying datastore. The result is ordered by property name.
+fn merge_properties<'a>(
+    props: Vec<models::NamedProperty>,
+    staged: impl Iterator<Item = (&'a String, &'a Option<JsonValue>)>,
+) -> Vec<models::NamedProperty> {
+    let mut merged: BTreeMap<String, JsonValue> = props.into_iter().map(|p| (p.name, p.value)).collect();
+
+    for (name, value) in staged {
+        if let Some(value) = value {
+            merged.insert(name.clone(), value.clone());
+        } else {
+            merged.remove(name);
+        }
+    }
+
+    merged
+        .into_iter()
+        .map(|(name, value)| models::NamedProperty::new(name, value))
+        .collect()
+}
+
+/// A datastore that stages changes in memory on top of another datastore,
+/// until they're committed or discarded.
+#[derive(Debug)]
+pub struct OverlayDatastore<D: Datastore> {
+    base: D,
+    delta: Arc<RwLock<Delta>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<D: Datastore> OverlayDatastore<D> {
+    /// Creates a new overlay datastore with no staged changes.
+    ///
+    /// # Arguments
+    /// * `base` - The underlying datastore.
+    pub fn new(base: D) -> Self {
+        Self {
+            base,
+            delta: Arc::new(RwLock::new(Delta::default())),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Sets the clock used to timestamp staged edges, in place of the system
+    /// time.
+    ///
+    /// # Arguments
+    /// * `clock` - The clock to use.
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
+
+    /// Gets the underlying datastore, which doesn't reflect any of the
+    /// staged changes.
+    pub fn base(&self) -> &D {
+        &self.base
+    }
+
+    /// Applies the staged changes to the underlying datastore, and then
+    /// clears them.
+    ///
+    /// # Errors
+    /// Returns an error if the underlying datastore fails to apply a change.
+    /// Changes applied before the failure are kept, and the staged changes
+    /// aren't cleared.
+    pub fn commit(&self) -> Result<()> {
+        let mut delta = self.delta.write().unwrap();
+        let trans = self.base.transaction()?;
+
+        // Deletions go first, so that a vertex that was deleted and then
+        // recreated only ends up with its new edges and properties
+        for ((key, name), value) in &delta.edge_properties {
+            if value.is_none() {
+                let q = models::SpecificEdgeQuery::single(key.clone()).property(name.clone());
+                trans.delete_edge_properties(q)?;
+            }
+        }
+
+        for (key, update_datetime) in &delta.edges {
+            if update_datetime.is_none() {
+                trans.delete_edges(models::SpecificEdgeQuery::single(key.clone()))?;
+            }
+        }
+
+        for ((id, name), value) in &delta.vertex_properties {
+            if value.is_none() {
+                let q = models::SpecificVertexQuery::single(*id).property(name.clone());
+                trans.delete_vertex_properties(q)?;
+            }
+        }
+
+        for (id, t) in &delta.vertices {
+            match t {
+                Some(t) => {
+                    // Replaces the old vertex if it was deleted and then
+                    // recreated, possibly with a different type
+                    trans.delete_vertices(models::SpecificVertexQuery::single(*id))?;
+                    trans.create_vertex(&models::Vertex::with_id(*id, t.clone()))?;
+                }
+                None => trans.delete_vertices(models::SpecificVertexQuery::single(*id))?,
+            }
+        }
+
+        for (key, update_datetime) in &delta.edges {
+            if let Some(update_datetime) = update_datetime {
+                trans.create_edge_with_datetime(key, *update_datetime)?;
+            }
+        }
+
+        for ((id, name), value) in &delta.vertex_properties {
+            if let Some(value) = value {
+                let q = models::SpecificVertexQuery::single(*id).property(name.clone());
+                trans.set_vertex_properties(q, value)?;
+            }
+        }
+
+        for ((key, name), value) in &delta.edge_properties {
+            if let Some(value) = value {
+                let q = models::SpecificEdgeQuery::single(key.clone()).property(name.clone());
+                trans.set_edge_properties(q, value)?;
+            }
+        }
+
+        *delta = Delta::default();
+        Ok(())
+    }
+
+    /// Throws away the staged changes.
+    pub fn discard(&self) {
+        *self.delta.write().unwrap() = Delta::default();
+    }
+}
+
+impl<D: Datastore> Datastore for OverlayDatastore<D> {
+    type Trans = OverlayTransaction<D>;
+
+    fn transaction(&self) -> Result<Self::Trans> {
+        Ok(OverlayTransaction {
+            trans: self.base.transaction()?,
+            delta: Arc::clone(&self.delta),
+            clock: Arc::clone(&self.clock),
+        })
+    }
+}
+
+/// A transaction for manipulating overlay datastores. Writes are staged
+/// rather than applied to the underlying datastore.
+#[derive(Debug)]
+pub struct OverlayTransaction<D: Datastore> {
+    trans: D::Trans,
+    delta: Arc<RwLock<Delta>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<D: Datastore> OverlayTransaction<D> {
+    fn get_vertices_by_ids(&self, ids: Vec<Uuid>) -> Result<Vec<models::Vertex>> {
+        let delta = self.delta.read().unwrap();
+        let base_ids: Vec<Uuid> = ids
+            .iter()
+            .filter(|id| !delta.vertices.contains_key(id))
+            .cloned()
+            .collect();
+        let mut found = HashMap::new();
+
+        if !base_ids.is_empty() {
+            for vertex in self.trans.get_vertices(models::SpecificVertexQuery::new(base_ids))? {
+                found.insert(vertex.id, vertex.t);
+            }
+        }
+
+        Ok(ids
+            .into_iter()
+            .filter_map(|id| {
+                let t = match delta.vertices.get(&id) {
+                    Some(t) => t.clone(),
+                    None => found.get(&id).cloned(),
+                };
+
+                t.map(|t| models::Vertex::with_id(id, t))
+            })
+            .collect())
+    }
+
+    fn get_edges_by_keys(&self, keys: Vec<models::EdgeKey>) -> Result<Vec<models::Edge>> {
+        let delta = self.delta.read().unwrap();
+        let base_keys: Vec<models::EdgeKey> = keys
+            .iter()
+            .filter(|key| !delta.edges.contains_key(key))
+            .cloned()
+            .collect();
+        let mut found = HashMap::new();
+
+        if !base_keys.is_empty() {
+            for edge in self.trans.get_edges(models::SpecificEdgeQuery::new(base_keys))? {
+                found.insert(edge.key, edge.created_datetime);
+            }
+        }
+
+        Ok(keys
+            .into_iter()
+            .filter_map(|key| {
+                let update_datetime = match delta.edges.get(&key) {
+                    Some(update_datetime) => *update_datetime,
+                    None => found.get(&key).cloned(),
+                };
+
+                update_datetime.map(|update_datetime| models::Edge::new(key, update_datetime))
+            })
+            .collect())
+    }
+
+    fn get_vertices_by_query(&self, q: VertexQuery) -> Result<Vec<models::Vertex>> {
+        match q {
+            VertexQuery::Range(range) => {
+                let delta = self.delta.read().unwrap();
+
+                // Fetches enough extra vertices to make up for any that the
+                // staged changes hide
+                let limit = range.limit.saturating_add(delta.vertices.len() as u32);
+                let mut base_range = models::RangeVertexQuery::new(limit);
+
+                if let Some(ref t) = range.t {
+                    base_range = base_range.t(t.clone());
+                }
+
+                if let Some(start_id) = range.start_id {
+                    base_range = base_range.start_id(start_id);
+                }
+
+                let mut results: Vec<models::Vertex> = self
+                    .trans
+                    .get_vertices(base_range)?
+                    .into_iter()
+                    .filter(|vertex| !delta.vertices.contains_key(&vertex.id))
+                    .collect();
+
+                let staged = match range.start_id {
+                    Some(start_id) => delta.vertices.range(start_id..),
+                    None => delta.vertices.range(..),
+                };
+
+                for (id, t) in staged {
+                    if let Some(t) = t {
+                        if range.t.is_none() || range.t.as_ref() == Some(t) {
+                            results.push(models::Vertex::with_id(*id, t.clone()));
+                        }
+                    }
+                }
+
+                results.sort_by_key(|vertex| vertex.id);
+                results.truncate(range.limit as usize);
+                Ok(results)
+            }
+            VertexQuery::Specific(specific) => self.get_vertices_by_ids(specific.ids),
+            VertexQuery::Pipe(pipe) => {
+                let direction = pipe.direction;
+                let mut seen_ids = HashSet::new();
+                let ids = self
+                    .get_edges_by_query(*pipe.inner)?
+                    .into_iter()
+                    .flat_map(|edge| match direction {
+                        models::EdgeDirection::Outbound => vec![edge.key.outbound_id],
+                        models::EdgeDirection::Inbound => vec![edge.key.inbound_id],
+                        models::EdgeDirection::Both => vec![edge.key.outbound_id, edge.key.inbound_id],
+                    })
+                    .filter(|id| direction != models::EdgeDirection::Both || seen_ids.insert(*id))
+                    .collect();
+
+                let iter = self.get_vertices_by_ids(ids)?.into_iter();
+
+                let results = match pipe.t {
+                    Some(ref t) => iter.filter(|v| &v.t == t).take(pipe.limit as usize).collect(),
+                    None => iter.take(pipe.limit as usize).collect(),
+                };
+
+                Ok(results)
+            }
+        }
+    }
+
+    fn get_edges_by_query(&self, q: EdgeQuery) -> Result<Vec<models::Edge>> {
+        match q {
+            EdgeQuery::Specific(specific) => self.get_edges_by_keys(specific.keys),
+            EdgeQuery::Pipe(ref pipe) if pipe.direction == models::EdgeDirection::Both => {
+                let mut results = Vec::new();
+                let mut seen_keys = HashSet::new();
+
+                for &direction in &[models::EdgeDirection::Outbound, models::EdgeDirection::Inbound] {
+                    let directed_pipe = models::PipeEdgeQuery {
+                        direction,
+                        ..pipe.clone()
+                    };
+
+                    for edge in self.get_edges_by_query(directed_pipe.into())? {
+                        if results.len() == pipe.limit as usize {
+                            return Ok(results);
+                        }
+
+                        if seen_keys.insert(edge.key.clone()) {
+                            results.push(edge);
+                        }
+                    }
+                }
+
+                Ok(results)
+            }
+            EdgeQuery::Pipe(pipe) => {
+                if pipe.limit == 0 {
+                    return Ok(Vec::new());
+                }
+
+                let ids: Vec<Uuid> = self
+                    .get_vertices_by_query(*pipe.inner)?
+                    .into_iter()
+                    .map(|vertex| vertex.id)
+                    .collect();
+                let delta = self.delta.read().unwrap();
+
+                let base_pipe = models::PipeEdgeQuery {
+                    inner: Box::new(models::SpecificVertexQuery::new(ids.clone()).into()),
+                    direction: pipe.direction,
+                    limit: pipe.limit.saturating_add(delta.edges.len() as u32),
+                    t: pipe.t.clone(),
+                    high: pipe.high,
+                    low: pipe.low,
+                };
+
+                let mut results: Vec<models::Edge> = self
+                    .trans
+                    .get_edges(base_pipe)?
+                    .into_iter()
+                    .filter(|edge| !delta.edges.contains_key(&edge.key))
+                    .collect();
+
+                // Maps each vertex to where it first appears in the inner
+                // query's results
+                let positions: HashMap<Uuid, usize> = ids.iter().enumerate().rev().map(|(i, id)| (*id, i)).collect();
+
+                for (key, update_datetime) in &delta.edges {
+                    let update_datetime = match update_datetime {
+                        Some(update_datetime) => *update_datetime,
+                        None => continue,
+                    };
+
+                    let id = match pipe.direction {
+                        models::EdgeDirection::Outbound => key.outbound_id,
+                        _ => key.inbound_id,
+                    };
+
+                    if !positions.contains_key(&id) {
+                        continue;
+                    }
+
+                    if let Some(ref t) = pipe.t {
+                        if &key.t != t {
+                            continue;
+                        }
+                    }
+
+                    if let Some(high) = pipe.high {
+                        if update_datetime > high {
+                            continue;
+                        }
+                    }
+
+                    if let Some(low) = pipe.low {
+                        if update_datetime < low {
+                            continue;
+                        }
+                    }
+
+                    results.push(models::Edge::new(key.clone(), update_datetime));
+                }
+
+                // Outbound edges are ordered by vertex and then by key, and
+                // inbound edges by key, as the in-memory datastore does
+                if pipe.direction == models::EdgeDirection::Outbound {
+                    results.sort_by(|first, second| {
+                        let first_position = positions[&first.key.outbound_id];
+                        let second_position = positions[&second.key.outbound_id];
+                        (first_position, &first.key).cmp(&(second_position, &second.key))
+                    });
+                } else {
+                    results.sort_by(|first, second| first.key.cmp(&second.key));
+                }
+
+                results.truncate(pipe.limit as usize);
+                Ok(results)
+            }
+        }
+    }
+}
+
+impl<D: Datastore> Transaction for OverlayTransaction<D> {
+    fn create_vertex(&self, vertex: &models::Vertex) -> Result<bool> {
+        if !self.get_vertices_by_ids(vec![vertex.id])?.is_empty() {
+            return Ok(false);
+        }
+
+        let mut delta = self.delta.write().unwrap();
+        delta.vertices.insert(vertex.id, Some(vertex.t.clone()));
+        Ok(true)
+    }
+
+    fn upsert_vertex(&self, vertex: &models::Vertex) -> Result<Option<models::Type>> {
+        // Holds the delta's lock throughout, so the vertex can't be changed
+        // in between reading its type and replacing it
+        let mut delta = self.delta.write().unwrap();
+
+        let existing_t = match delta.vertices.get(&vertex.id) {
+            Some(t) => t.clone(),
+            None => self
+                .trans
+                .get_vertices(models::SpecificVertexQuery::single(vertex.id))?
+                .pop()
+                .map(|existing| existing.t),
+        };
+
+        delta.vertices.insert(vertex.id, Some(vertex.t.clone()));
+        Ok(existing_t)
+    }
+
+    fn get_vertices<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<Vec<models::Vertex>> {
+        self.get_vertices_by_query(q.into())
+    }
+
+    fn delete_vertices<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<()> {
+        let ids: Vec<Uuid> = self
+            .get_vertices_by_query(q.into())?
+            .into_iter()
+            .map(|v| v.id)
+            .collect();
+        let vertex_q = models::SpecificVertexQuery::new(ids.clone());
+
+        // The vertices' edges and properties are deleted along with them
+        self.delete_edges(vertex_q.clone().both(u32::MAX))?;
+        let all_properties = self.get_all_vertex_properties(vertex_q)?;
+        let mut delta = self.delta.write().unwrap();
+
+        for properties in all_properties {
+            for property in properties.props {
+                delta
+                    .vertex_properties
+                    .insert((properties.vertex.id, property.name), None);
+            }
+        }
+
+        for id in ids {
+            delta.vertices.insert(id, None);
+        }
+
+        Ok(())
+    }
+
+    fn get_vertex_count(&self) -> Result<u64> {
+        let delta = self.delta.read().unwrap();
+        let ids: Vec<Uuid> = delta.vertices.keys().cloned().collect();
+        let base_ids: HashSet<Uuid> = self
+            .trans
+            .get_vertices(models::SpecificVertexQuery::new(ids))?
+            .into_iter()
+            .map(|vertex| vertex.id)
+            .collect();
+        let mut count = self.trans.get_vertex_count()?;
+
+        for (id, t) in &delta.vertices {
+            match (t.is_some(), base_ids.contains(id)) {
+                (true, false) => count += 1,
+                (false, true) => count -= 1,
+                _ => (),
+            }
+        }
+
+        Ok(count)
+    }
+
+    fn create_edge(&self, key: &models::EdgeKey) -> Result<bool> {
+        self.create_edge_with_datetime(key, self.clock.now())
+    }
+
+    fn create_edge_with_datetime(&self, key: &models::EdgeKey, update_datetime: DateTime<Utc>) -> Result<bool> {
+        if self.get_vertices_by_ids(vec![key.outbound_id, key.inbound_id])?.len() < 2 {
+            return Ok(false);
+        }
+
+        let mut delta = self.delta.write().unwrap();
+        delta.edges.insert(key.clone(), Some(update_datetime));
+        Ok(true)
+    }
+
+    fn get_edges<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<Vec<models::Edge>> {
+        self.get_edges_by_query(q.into())
+    }
+
+    fn delete_edges<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<()> {
+        let keys: Vec<models::EdgeKey> = self.get_edges_by_query(q.into())?.into_iter().map(|e| e.key).collect();
+
+        // The edges' properties are deleted along with them
+        let all_properties = self.get_all_edge_properties(models::SpecificEdgeQuery::new(keys.clone()))?;
+        let mut delta = self.delta.write().unwrap();
+
+        for properties in all_properties {
+            for property in properties.props {
+                delta
+                    .edge_properties
+                    .insert((properties.edge.key.clone(), property.name), None);
+            }
+        }
+
+        for key in keys {
+            delta.edges.insert(key, None);
+        }
+
+        Ok(())
+    }
+
+    fn get_edge_count(&self, id: Uuid, t: Option<&models::Type>, direction: models::EdgeDirection) -> Result<u64> {
+        self.get_edge_count_in_range(id, t, None, None, direction)
+    }
+
+    fn get_vertex_properties(&self, q: VertexPropertyQuery) -> Result<Vec<models::VertexProperty>> {
+        let ids: Vec<Uuid> = self.get_vertices_by_query(q.inner)?.into_iter().map(|v| v.id).collect();
+        let name = q.name;
+        let delta = self.delta.read().unwrap();
+        let base_ids: Vec<Uuid> = ids
+            .iter()
+            .filter(|id| !delta.vertex_properties.contains_key(&(**id, name.clone())))
+            .cloned()
+            .collect();
+        let mut found = HashMap::new();
+
+        if !base_ids.is_empty() {
+            let base_q = models::SpecificVertexQuery::new(base_ids).property(name.clone());
+
+            for property in self.trans.get_vertex_properties(base_q)? {
+                found.insert(property.id, property.value);
+            }
+        }
+
+        Ok(ids
+            .into_iter()
+            .filter_map(|id| {
+                let value = match delta.vertex_properties.get(&(id, name.clone())) {
+                    Some(value) => value.clone(),
+                    None => found.get(&id).cloned(),
+                };
+
+                value.map(|value| models::VertexProperty::new(id, value))
+            })
+            .collect())
+    }
+
+    fn set_vertex_properties(&self, q: VertexPropertyQuery, value: &JsonValue) -> Result<()> {
+        let ids: Vec<Uuid> = self.get_vertices_by_query(q.inner)?.into_iter().map(|v| v.id).collect();
+        let mut delta = self.delta.write().unwrap();
+
+        for id in ids {
+            delta
+                .vertex_properties
+                .insert((id, q.name.clone()), Some(value.clone()));
+        }
+
+        Ok(())
+    }
+
+    fn delete_vertex_properties(&self, q: VertexPropertyQuery) -> Result<()> {
+        let ids: Vec<Uuid> = self.get_vertices_by_query(q.inner)?.into_iter().map(|v| v.id).collect();
+        let mut delta = self.delta.write().unwrap();
+
+        for id in ids {
+            delta.vertex_properties.insert((id, q.name.clone()), None);
+        }
+
+        Ok(())
+    }
+
+    fn get_edge_properties(&self, q: EdgePropertyQuery) -> Result<Vec<models::EdgeProperty>> {
+        let keys: Vec<models::EdgeKey> = self.get_edges_by_query(q.inner)?.into_iter().map(|e| e.key).collect();
+        let name = q.name;
+        let delta = self.delta.read().unwrap();
+        let base_keys: Vec<models::EdgeKey> = keys
+            .iter()
+            .filter(|key| !delta.edge_properties.contains_key(&((*key).clone(), name.clone())))
+            .cloned()
+            .collect();
+        let mut found = HashMap::new();
+
+        if !base_keys.is_empty() {
+            let base_q = models::SpecificEdgeQuery::new(base_keys).property(name.clone());
+
+            for property in self.trans.get_edge_properties(base_q)? {
+                found.insert(property.key, property.value);
+            }
+        }
+
+        Ok(keys
+            .into_iter()
+            .filter_map(|key| {
+                let value = match delta.edge_properties.get(&(key.clone(), name.clone())) {
+                    Some(value) => value.clone(),
+                    None => found.get(&key).cloned(),
+                };
+
+                value.map(|value| models::EdgeProperty::new(key, value))
+            })
+            .collect())
+    }
+
+    fn set_edge_properties(&self, q: EdgePropertyQuery, value: &JsonValue) -> Result<()> {
+        let keys: Vec<models::EdgeKey> = self.get_edges_by_query(q.inner)?.into_iter().map(|e| e.key).collect();
+        let mut delta = self.delta.write().unwrap();
+
+        for key in keys {
+            delta.edge_properties.insert((key, q.name.clone()), Some(value.clone()));
+        }
+
+        Ok(())
+    }
+
+    fn delete_edge_properties(&self, q: EdgePropertyQuery) -> Result<()> {
+        let keys: Vec<models::EdgeKey> = self.get_edges_by_query(q.inner)?.into_iter().map(|e| e.key).collect();
+        let mut delta = self.delta.write().unwrap();
+
+        for key in keys {
+            delta.edge_properties.insert((key, q.name.clone()), None);
+        }
+
+        Ok(())
+    }
+
+    fn get_all_vertex_properties<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<Vec<models::VertexProperties>> {
+        let vertices = self.get_vertices_by_query(q.into())?;
+        let ids: Vec<Uuid> = vertices.iter().map(|vertex| vertex.id).collect();
+        let mut found = HashMap::new();
+
+        for properties in self
+            .trans
+            .get_all_vertex_properties(models::SpecificVertexQuery::new(ids))?
+        {
+            found.insert(properties.vertex.id, properties.props);
+        }
+
+        let delta = self.delta.read().unwrap();
+
+        Ok(vertices
+            .into_iter()
+            .map(|vertex| {
+                let staged = delta
+                    .vertex_properties
+                    .range((vertex.id, String::new())..)
+                    .take_while(|((id, _), _)| *id == vertex.id)
+                    .map(|((_, name), value)| (name, value));
+                let props = merge_properties(found.get(&vertex.id).cloned().unwrap_or_default(), staged);
+                models::VertexProperties::new(vertex, props)
+            })
+            .collect())
+    }
+
+    fn get_all_edge_properties<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<Vec<models::EdgeProperties>> {
+        let edges = self.get_edges_by_query(q.into())?;
+        let keys: Vec<models::EdgeKey> = edges.iter().map(|edge| edge.key.clone()).collect();
+        let mut found = HashMap::new();
+
+        for properties in self
+            .trans
+            .get_all_edge_properties(models::SpecificEdgeQuery::new(keys))?
+        {
+            found.insert(properties.edge.key, properties.props);
+        }
+
+        let delta = self.delta.read().unwrap();
+
+        Ok(edges
+            .into_iter()
+            .map(|edge| {
+                let staged = delta
+                    .edge_properties
+                    .range((edge.key.clone(), String::new())..)
+                    .take_while(|((key, _), _)| *key == edge.key)
+                    .map(|((_, name), value)| (name, value));
+                let props = merge_properties(found.get(&edge.key).cloned().unwrap_or_default(), staged);
+                models::EdgeProperties::new(edge, props)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OverlayDatastore;
+    use memory::MemoryDatastore;
+    use models::{EdgeDirection, EdgeKey, EdgeQueryExt, SpecificVertexQuery, Type, Vertex, VertexQueryExt};
+    use serde_json::Value as JsonValue;
+    use traits::{Datastore, Transaction};
+
+    #[test]
+    fn should_commit_staged_changes() {
+        let datastore = OverlayDatastore::new(MemoryDatastore::default());
+        let t = Type::new("foo").unwrap();
+        let old = Vertex::new(t.clone());
+        let base_trans = datastore.base().transaction().unwrap();
+        base_trans.create_vertex(&old).unwrap();
+
+        let trans = datastore.transaction().unwrap();
+        let outbound = Vertex::new(t.clone());
+        let inbound = Vertex::new(t.clone());
+        let key = EdgeKey::new(outbound.id, t.clone(), inbound.id);
+        trans.delete_vertices(SpecificVertexQuery::single(old.id)).unwrap();
+        trans.create_vertex(&outbound).unwrap();
+        trans.create_vertex(&inbound).unwrap();
+        assert!(trans.create_edge(&key).unwrap());
+        let q = SpecificVertexQuery::single(outbound.id).outbound(1).property("bar");
+        trans.set_edge_properties(q.clone(), &JsonValue::Bool(true)).unwrap();
+
+        // The underlying datastore is untouched until the commit
+        assert_eq!(trans.get_vertex_count().unwrap(), 2);
+        assert_eq!(trans.get_edge_properties(q.clone()).unwrap().len(), 1);
+        assert_eq!(base_trans.get_vertex_count().unwrap(), 1);
+
+        datastore.commit().unwrap();
+        assert_eq!(base_trans.get_vertex_count().unwrap(), 2);
+        assert!(base_trans
+            .get_vertices(SpecificVertexQuery::single(old.id))
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            base_trans
+                .get_edge_count(inbound.id, None, EdgeDirection::Inbound)
+                .unwrap(),
+            1
+        );
+        let properties = base_trans.get_edge_properties(q).unwrap();
+        assert_eq!(properties[0].value, JsonValue::Bool(true));
+    }
+
+    #[test]
+    fn should_discard_staged_changes() {
+        let datastore = OverlayDatastore::new(MemoryDatastore::default());
+        let trans = datastore.transaction().unwrap();
+        trans.create_vertex_from_type(Type::new("foo").unwrap()).unwrap();
+        assert_eq!(trans.get_vertex_count().unwrap(), 1);
+
+        datastore.discard();
+        assert_eq!(trans.get_vertex_count().unwrap(), 0);
+        datastore.commit().unwrap();
+        assert_eq!(datastore.base().transaction().unwrap().get_vertex_count().unwrap(), 0);
+    }
+}
@@ -0,0 +1,24 @@
+//! A datastore frontend that stages changes in memory on top of another
+//! datastore, e.g. to preview the effect of a bulk edit before applying it.
+//! Reads see the underlying datastore with the staged changes applied. The
+//! staged changes can then be committed to the underlying datastore, or
+//! discarded. This has these drawbacks:
+//!
+//! * Committing is not atomic. If it fails partway through, the staged
+//!   changes are kept, so that the commit can be retried.
+//! * Committing backdates edges, so the underlying datastore has to support
+//!   `create_edge_with_datetime`.
+//! * Reads have to fetch extra results from the underlying datastore to make
+//!   up for the ones the staged changes hide, so they get slower as more
+//!   changes are staged.
+
+mod datastore;
+
+pub use self::datastore::{OverlayDatastore, OverlayTransaction};
+
+#[cfg(feature = "test-suite")]
+full_test_impl!({
+    use memory::MemoryDatastore;
+
+    OverlayDatastore::new(MemoryDatastore::default())
+});
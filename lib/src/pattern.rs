@@ -0,0 +1,654 @@
+//! Matches small subgraph patterns, e.g. mutual follows or triangles.
+//!
+//! Patterns are written like `(a:user)-[:follows]->(b:user)-[:follows]->(a)`:
+//! vertices are named variables in parentheses, optionally with a type, and
+//! edges are arrows in either direction, optionally with a type. Several
+//! paths can be given, separated by commas, e.g.
+//! `(a)-[:follows]->(b), (a)<-[:blocks]-(b)`. A variable that appears more
+//! than once refers to the same vertex each time.
+//!
+//! Patterns are matched with index nested loop joins: the vertices of one
+//! variable are scanned, and every other variable is bound by following
+//! edges from a variable that's already bound. Each remaining edge of the
+//! pattern is then checked once both of its vertices are bound. Typed edges
+//! are much cheaper to check than untyped ones, since they can be looked up
+//! directly.
+//!
+//! Given `GraphStatistics`, `match_pattern_with_statistics` scans the
+//! variable with the fewest estimated vertices first, and follows the edges
+//! with the lowest estimated fan-out before the others.
+
+use errors::{Result, ValidationError, ValidationResult};
+use models;
+use models::{EdgeDirection, EdgeQueryExt, VertexQueryExt};
+use statistics::GraphStatistics;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use traits::Transaction;
+use util::next_uuid;
+use uuid::Uuid;
+
+// How many vertices of the first variable are read at a time.
+const PATTERN_PAGE_SIZE: u32 = 1000;
+
+#[derive(Clone, Debug, PartialEq)]
+struct PatternEdge {
+    outbound: usize,
+    t: Option<models::Type>,
+    inbound: usize,
+}
+
+/// A subgraph pattern to match. See the module docs for the syntax, which
+/// is parsed with `from_str`. Patterns can also be built up with `vertex`
+/// and `edge`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Pattern {
+    variables: Vec<(String, Option<models::Type>)>,
+    edges: Vec<PatternEdge>,
+}
+
+impl Pattern {
+    /// Adds a vertex variable, or sets the type of an existing one.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the variable.
+    /// * `t` - The type the vertex must have, if any.
+    pub fn vertex(mut self, name: &str, t: Option<models::Type>) -> Self {
+        let i = self.variable(name);
+
+        if t.is_some() {
+            self.variables[i].1 = t;
+        }
+
+        self
+    }
+
+    /// Adds an edge between two vertex variables. Variables that haven't
+    /// been added yet are added without a type.
+    ///
+    /// # Arguments
+    /// * `outbound` - The name of the variable for the outbound vertex.
+    /// * `t` - The type the edge must have, if any.
+    /// * `inbound` - The name of the variable for the inbound vertex.
+    pub fn edge(mut self, outbound: &str, t: Option<models::Type>, inbound: &str) -> Self {
+        let outbound = self.variable(outbound);
+        let inbound = self.variable(inbound);
+        self.edges.push(PatternEdge { outbound, t, inbound });
+        self
+    }
+
+    fn variable(&mut self, name: &str) -> usize {
+        match self.variables.iter().position(|variable| variable.0 == name) {
+            Some(i) => i,
+            None => {
+                self.variables.push((name.to_string(), None));
+                self.variables.len() - 1
+            }
+        }
+    }
+
+    /// Works out the order to bind variables in. Each step after the first
+    /// binds a variable by following an edge from a variable bound by an
+    /// earlier step.
+    fn plan(&self, statistics: Option<&GraphStatistics>) -> ValidationResult<Vec<Step>> {
+        if self.variables.is_empty() {
+            return Err("Patterns must have at least one vertex".into());
+        }
+
+        let first = match statistics {
+            // Start from the variable with the fewest vertices to scan
+            Some(statistics) => (0..self.variables.len())
+                .min_by_key(|&i| statistics.estimate_vertex_count(self.variables[i].1.as_ref()))
+                .unwrap(),
+            // Start from a typed variable if there is one, since scanning
+            // its vertices is cheaper
+            None => self
+                .variables
+                .iter()
+                .position(|variable| variable.1.is_some())
+                .unwrap_or(0),
+        };
+        let mut is_bound = vec![false; self.variables.len()];
+        let mut is_checked = vec![false; self.edges.len()];
+        let mut steps = Vec::with_capacity(self.variables.len());
+        let mut next = Some((first, None));
+
+        while let Some((variable, via)) = next {
+            is_bound[variable] = true;
+
+            if let Some(via) = via {
+                is_checked[via] = true;
+            }
+
+            let checks: Vec<usize> = (0..self.edges.len())
+                .filter(|&i| {
+                    let edge = &self.edges[i];
+                    !is_checked[i]
+                        && (edge.outbound == variable || edge.inbound == variable)
+                        && is_bound[edge.outbound]
+                        && is_bound[edge.inbound]
+                })
+                .collect();
+
+            for &i in &checks {
+                is_checked[i] = true;
+            }
+
+            steps.push(Step { variable, via, checks });
+
+            // The edges that lead out of the bound variables, along with the
+            // direction they're followed in
+            let mut frontier = self.edges.iter().enumerate().filter_map(|(i, edge)| {
+                if is_bound[edge.outbound] && !is_bound[edge.inbound] {
+                    Some((i, EdgeDirection::Outbound))
+                } else if is_bound[edge.inbound] && !is_bound[edge.outbound] {
+                    Some((i, EdgeDirection::Inbound))
+                } else {
+                    None
+                }
+            });
+
+            let via = match statistics {
+                // Follow the edge that leads to the fewest candidates
+                Some(statistics) => frontier.min_by(|&(i, i_direction), &(j, j_direction)| {
+                    let i_fan_out = self.estimate_fan_out(statistics, i, i_direction);
+                    let j_fan_out = self.estimate_fan_out(statistics, j, j_direction);
+                    i_fan_out.partial_cmp(&j_fan_out).unwrap_or(Ordering::Equal)
+                }),
+                // Follow the first edge
+                None => frontier.next(),
+            };
+
+            next = via.map(|(i, direction)| match direction {
+                EdgeDirection::Outbound => (self.edges[i].inbound, Some(i)),
+                _ => (self.edges[i].outbound, Some(i)),
+            });
+        }
+
+        if steps.len() < self.variables.len() {
+            return Err("Patterns must be connected".into());
+        }
+
+        Ok(steps)
+    }
+
+    // Estimates how many vertices following an edge from its bound end
+    // leads to.
+    fn estimate_fan_out(&self, statistics: &GraphStatistics, i: usize, direction: EdgeDirection) -> f64 {
+        let edge = &self.edges[i];
+
+        let from = match direction {
+            EdgeDirection::Outbound => edge.outbound,
+            _ => edge.inbound,
+        };
+
+        statistics.estimate_degree(self.variables[from].1.as_ref(), edge.t.as_ref(), direction)
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = ValidationError;
+
+    fn from_str(s: &str) -> ValidationResult<Self> {
+        let mut parser = Parser {
+            chars: s.chars().filter(|c| !c.is_whitespace()).collect(),
+            pos: 0,
+            pattern: Pattern::default(),
+        };
+
+        loop {
+            let mut outbound = parser.node()?;
+
+            while parser.peek() == Some('-') || parser.peek() == Some('<') {
+                let (t, direction) = parser.arrow()?;
+                let inbound = parser.node()?;
+
+                let edge = match direction {
+                    EdgeDirection::Inbound => PatternEdge {
+                        outbound: inbound,
+                        t,
+                        inbound: outbound,
+                    },
+                    _ => PatternEdge { outbound, t, inbound },
+                };
+
+                parser.pattern.edges.push(edge);
+                outbound = inbound;
+            }
+
+            match parser.next() {
+                Some(',') => continue,
+                None => return Ok(parser.pattern),
+                Some(c) => return Err(format!("Unexpected `{}` in pattern", c).into()),
+            }
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    pattern: Pattern,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn expect(&mut self, expected: &str) -> ValidationResult<()> {
+        for c in expected.chars() {
+            if self.next() != Some(c) {
+                return Err(format!("Expected `{}` in pattern", expected).into());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn take_while<F: Fn(char) -> bool>(&mut self, f: F) -> String {
+        let start = self.pos;
+
+        while let Some(c) = self.peek() {
+            if !f(c) {
+                break;
+            }
+
+            self.pos += 1;
+        }
+
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    // Parses an optional `:type`.
+    fn t(&mut self) -> ValidationResult<Option<models::Type>> {
+        if self.peek() != Some(':') {
+            return Ok(None);
+        }
+
+        self.pos += 1;
+        let t = self.take_while(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        Ok(Some(models::Type::new(t)?))
+    }
+
+    // Parses a vertex, e.g. `(a:user)`, returning its variable.
+    fn node(&mut self) -> ValidationResult<usize> {
+        self.expect("(")?;
+        let name = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_');
+
+        if name.is_empty() {
+            return Err("Vertices in patterns must be named".into());
+        }
+
+        let t = self.t()?;
+        self.expect(")")?;
+        let i = self.pattern.variable(&name);
+
+        if let Some(t) = t {
+            match self.pattern.variables[i].1 {
+                Some(ref existing) if *existing != t => {
+                    return Err(format!("Variable `{}` is given more than one type", name).into())
+                }
+                _ => self.pattern.variables[i].1 = Some(t),
+            }
+        }
+
+        Ok(i)
+    }
+
+    // Parses an edge, e.g. `-[:follows]->` or `<-[]-`, returning its type and
+    // direction.
+    fn arrow(&mut self) -> ValidationResult<(Option<models::Type>, EdgeDirection)> {
+        let is_inbound = self.peek() == Some('<');
+
+        if is_inbound {
+            self.pos += 1;
+        }
+
+        self.expect("-[")?;
+        let t = self.t()?;
+        self.expect("]-")?;
+
+        if is_inbound {
+            Ok((t, EdgeDirection::Inbound))
+        } else {
+            self.expect(">")?;
+            Ok((t, EdgeDirection::Outbound))
+        }
+    }
+}
+
+struct Step {
+    // The variable this step binds.
+    variable: usize,
+
+    // The edge followed to find candidates for the variable. This is `None`
+    // for the first step, whose candidates are scanned.
+    via: Option<usize>,
+
+    // The edges to check once the variable is bound.
+    checks: Vec<usize>,
+}
+
+/// Finds matches of a pattern.
+///
+/// Returns the bindings of each match, from variable names to vertex ids.
+/// Different variables may be bound to the same vertex, e.g.
+/// `(a)-[:follows]->(b)` matches a vertex that follows itself with `a` and
+/// `b` both bound to it.
+///
+/// # Arguments
+/// * `trans` - The transaction to read from.
+/// * `pattern` - The pattern to match.
+/// * `limit` - The maximum number of matches to return.
+///
+/// # Errors
+/// Returns a `ValidationError` if the pattern has no vertices, or isn't
+/// connected.
+pub fn match_pattern<T: Transaction>(trans: &T, pattern: &Pattern, limit: u32) -> Result<Vec<BTreeMap<String, Uuid>>> {
+    let steps = pattern.plan(None)?;
+    match_steps(trans, pattern, &steps, limit)
+}
+
+/// Finds matches of a pattern, like `match_pattern`, but uses statistics
+/// about the graph to choose the order variables are bound in. Stale
+/// statistics only make matching slower, not incorrect.
+///
+/// # Arguments
+/// * `trans` - The transaction to read from.
+/// * `pattern` - The pattern to match.
+/// * `statistics` - Statistics about the graph, e.g. from a
+///   `StatisticsService`.
+/// * `limit` - The maximum number of matches to return.
+///
+/// # Errors
+/// Returns a `ValidationError` if the pattern has no vertices, or isn't
+/// connected.
+pub fn match_pattern_with_statistics<T: Transaction>(
+    trans: &T,
+    pattern: &Pattern,
+    statistics: &GraphStatistics,
+    limit: u32,
+) -> Result<Vec<BTreeMap<String, Uuid>>> {
+    let steps = pattern.plan(Some(statistics))?;
+    match_steps(trans, pattern, &steps, limit)
+}
+
+fn match_steps<T: Transaction>(
+    trans: &T,
+    pattern: &Pattern,
+    steps: &[Step],
+    limit: u32,
+) -> Result<Vec<BTreeMap<String, Uuid>>> {
+    let mut matches = Vec::new();
+
+    if limit == 0 {
+        return Ok(matches);
+    }
+
+    let first = &pattern.variables[steps[0].variable];
+    let mut bindings = vec![Uuid::default(); pattern.variables.len()];
+    let mut start_id = Uuid::default();
+
+    loop {
+        let q = models::RangeVertexQuery::new(PATTERN_PAGE_SIZE).start_id(start_id);
+
+        let q = match first.1 {
+            Some(ref t) => q.t(t.clone()),
+            None => q,
+        };
+
+        let page = trans.get_vertices(q)?;
+
+        for vertex in &page {
+            bindings[steps[0].variable] = vertex.id;
+
+            if check(trans, pattern, &steps[0], &bindings)?
+                && extend(trans, pattern, steps, 1, &mut bindings, &mut matches, limit)?
+            {
+                return Ok(matches);
+            }
+        }
+
+        if page.len() < PATTERN_PAGE_SIZE as usize {
+            return Ok(matches);
+        }
+
+        match next_uuid(page.last().unwrap().id) {
+            Ok(next_id) => start_id = next_id,
+            Err(_) => return Ok(matches),
+        }
+    }
+}
+
+// Binds the variable of `steps[depth]` and those of the steps after it,
+// returning whether `limit` matches have been found.
+fn extend<T: Transaction>(
+    trans: &T,
+    pattern: &Pattern,
+    steps: &[Step],
+    depth: usize,
+    bindings: &mut [Uuid],
+    matches: &mut Vec<BTreeMap<String, Uuid>>,
+    limit: u32,
+) -> Result<bool> {
+    if depth == steps.len() {
+        let binding = pattern
+            .variables
+            .iter()
+            .zip(bindings.iter())
+            .map(|(variable, id)| (variable.0.clone(), *id))
+            .collect();
+        matches.push(binding);
+        return Ok(matches.len() >= limit as usize);
+    }
+
+    let step = &steps[depth];
+    let edge = &pattern.edges[step.via.unwrap()];
+
+    // Follow the edge from whichever end is already bound
+    let (from, direction) = if edge.inbound == step.variable {
+        (edge.outbound, EdgeDirection::Outbound)
+    } else {
+        (edge.inbound, EdgeDirection::Inbound)
+    };
+
+    let q = models::SpecificVertexQuery::single(bindings[from]);
+
+    let q = match direction {
+        EdgeDirection::Outbound => q.outbound(u32::MAX),
+        _ => q.inbound(u32::MAX),
+    };
+
+    let q = match edge.t {
+        Some(ref t) => q.t(t.clone()),
+        None => q,
+    };
+
+    let q = match direction {
+        EdgeDirection::Outbound => q.inbound(u32::MAX),
+        _ => q.outbound(u32::MAX),
+    };
+
+    let q = match pattern.variables[step.variable].1 {
+        Some(ref t) => q.t(t.clone()),
+        None => q,
+    };
+
+    // Untyped edges can lead to the same vertex more than once
+    let mut candidates: Vec<Uuid> = trans.get_vertices(q)?.into_iter().map(|vertex| vertex.id).collect();
+    candidates.sort();
+    candidates.dedup();
+
+    for id in candidates {
+        bindings[step.variable] = id;
+
+        if check(trans, pattern, step, bindings)? && extend(trans, pattern, steps, depth + 1, bindings, matches, limit)?
+        {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+// Checks that the edges a step has to check exist.
+fn check<T: Transaction>(trans: &T, pattern: &Pattern, step: &Step, bindings: &[Uuid]) -> Result<bool> {
+    for &i in &step.checks {
+        let edge = &pattern.edges[i];
+        let outbound_id = bindings[edge.outbound];
+        let inbound_id = bindings[edge.inbound];
+
+        let exists = match edge.t {
+            Some(ref t) => {
+                let key = models::EdgeKey::new(outbound_id, t.clone(), inbound_id);
+                !trans.get_edges(models::SpecificEdgeQuery::single(key))?.is_empty()
+            }
+            None => trans
+                .get_edges(models::SpecificVertexQuery::single(outbound_id).outbound(u32::MAX))?
+                .iter()
+                .any(|edge| edge.key.inbound_id == inbound_id),
+        };
+
+        if !exists {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{match_pattern, match_pattern_with_statistics, Pattern};
+    use memory::MemoryDatastore;
+    use models::{EdgeKey, Type};
+    use statistics::sample_statistics;
+    use std::collections::BTreeSet;
+    use std::str::FromStr;
+    use traits::{Datastore, Transaction};
+
+    #[test]
+    fn should_parse_patterns() {
+        let user_t = Type::new("user").unwrap();
+        let follows_t = Type::new("follows").unwrap();
+
+        assert_eq!(
+            Pattern::from_str("(a:user)-[:follows]->(b:user) -[:follows]-> (a)").unwrap(),
+            Pattern::default()
+                .vertex("a", Some(user_t.clone()))
+                .vertex("b", Some(user_t.clone()))
+                .edge("a", Some(follows_t.clone()), "b")
+                .edge("b", Some(follows_t.clone()), "a")
+        );
+
+        assert_eq!(
+            Pattern::from_str("(a)<-[]-(b), (b)-[:follows]->(c:user)").unwrap(),
+            Pattern::default()
+                .vertex("a", None)
+                .edge("b", None, "a")
+                .edge("b", Some(follows_t), "c")
+                .vertex("c", Some(user_t))
+        );
+
+        assert!(Pattern::from_str("(a:user)-[]->(a:team)").is_err());
+        assert!(Pattern::from_str("(a)-[]-(b)").is_err());
+        assert!(Pattern::from_str("(a)->(b)").is_err());
+        assert!(Pattern::from_str("(:user)").is_err());
+        assert!(Pattern::from_str("(a:)").is_err());
+        assert!(Pattern::from_str("(a))").is_err());
+    }
+
+    #[test]
+    fn should_match_patterns() {
+        let datastore = MemoryDatastore::default();
+        let trans = datastore.transaction().unwrap();
+        let user_t = Type::new("user").unwrap();
+        let team_t = Type::new("team").unwrap();
+        let follows_t = Type::new("follows").unwrap();
+        let member_of_t = Type::new("member_of").unwrap();
+        let a = trans.create_vertex_from_type(user_t.clone()).unwrap();
+        let b = trans.create_vertex_from_type(user_t.clone()).unwrap();
+        let c = trans.create_vertex_from_type(user_t.clone()).unwrap();
+        let team = trans.create_vertex_from_type(team_t).unwrap();
+
+        for &(outbound_id, inbound_id) in &[(a, b), (b, a), (b, c)] {
+            trans
+                .create_edge(&EdgeKey::new(outbound_id, follows_t.clone(), inbound_id))
+                .unwrap();
+        }
+
+        for &id in &[a, c] {
+            trans.create_edge(&EdgeKey::new(id, member_of_t.clone(), team)).unwrap();
+        }
+
+        let find = |pattern: &str| -> BTreeSet<Vec<_>> {
+            match_pattern(&trans, &Pattern::from_str(pattern).unwrap(), u32::MAX)
+                .unwrap()
+                .into_iter()
+                .map(|binding| binding.into_iter().collect())
+                .collect()
+        };
+
+        let mutual = find("(x:user)-[:follows]->(y:user)-[:follows]->(x)");
+        assert_eq!(mutual.len(), 2);
+        assert!(mutual.contains(&vec![("x".to_string(), a), ("y".to_string(), b)]));
+        assert!(mutual.contains(&vec![("x".to_string(), b), ("y".to_string(), a)]));
+
+        // Followers of someone on the same team
+        let matches = find("(x)-[:follows]->(y)-[:member_of]->(t:team), (x)-[]->(t)");
+        assert!(matches.is_empty());
+        let matches = find("(x)-[:follows]->(y)-[:member_of]->(t:team)<-[:member_of]-(z)");
+        assert_eq!(matches.len(), 4);
+        let matches = find("(x)-[]->(y), (x)<-[]-(y)");
+        assert_eq!(matches.len(), 2);
+        let matches = find("(x:team)<-[:member_of]-(y)");
+        assert_eq!(matches.len(), 2);
+
+        let pattern = Pattern::from_str("(x)-[:follows]->(y)").unwrap();
+        assert_eq!(match_pattern(&trans, &pattern, 2).unwrap().len(), 2);
+        assert!(match_pattern(&trans, &pattern, 0).unwrap().is_empty());
+        assert!(match_pattern(&trans, &Pattern::from_str("(x), (y)").unwrap(), 1).is_err());
+        assert!(match_pattern(&trans, &Pattern::default(), 1).is_err());
+    }
+
+    #[test]
+    fn should_match_patterns_with_statistics() {
+        let datastore = MemoryDatastore::default();
+        let trans = datastore.transaction().unwrap();
+        let user_t = Type::new("user").unwrap();
+        let team_t = Type::new("team").unwrap();
+        let member_of_t = Type::new("member_of").unwrap();
+        let team = trans.create_vertex_from_type(team_t).unwrap();
+
+        for _ in 0..5 {
+            let user = trans.create_vertex_from_type(user_t.clone()).unwrap();
+            trans
+                .create_edge(&EdgeKey::new(user, member_of_t.clone(), team))
+                .unwrap();
+        }
+
+        let statistics = sample_statistics(&trans, u32::MAX).unwrap();
+        let pattern = Pattern::from_str("(x:user)-[:member_of]->(t:team)").unwrap();
+
+        // There are fewer teams than users, so teams are scanned first
+        let steps = pattern.plan(Some(&statistics)).unwrap();
+        assert_eq!(pattern.variables[steps[0].variable].0, "t");
+        let steps = pattern.plan(None).unwrap();
+        assert_eq!(pattern.variables[steps[0].variable].0, "x");
+
+        let mut expected = match_pattern(&trans, &pattern, u32::MAX).unwrap();
+        let mut matches = match_pattern_with_statistics(&trans, &pattern, &statistics, u32::MAX).unwrap();
+        expected.sort();
+        matches.sort();
+        assert_eq!(matches.len(), 5);
+        assert_eq!(matches, expected);
+    }
+}
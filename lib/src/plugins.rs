@@ -0,0 +1,472 @@
+//! Support for server-side plugins, which are loaded from shared libraries
+//! and called with direct access to a transaction.
+//!
+//! A plugin library exports its plugins with `export_plugins!`:
+//!
+//! ```ignore
+//! #[macro_use]
+//! extern crate indradb;
+//!
+//! struct Degree;
+//!
+//! impl indradb::Plugin for Degree {
+//!     fn call(&self, trans: &dyn indradb::DynTransaction, arg: JsonValue) -> indradb::Result<JsonValue> {
+//!         ...
+//!     }
+//! }
+//!
+//! fn register(registrar: &mut indradb::PluginRegistrar) {
+//!     registrar.register("degree", Box::new(Degree));
+//! }
+//!
+//! export_plugins!(register);
+//! ```
+//!
+//! Rust has no stable ABI, so plugins must be built with the same compiler
+//! as the server that loads them, and against the same version of the
+//! plugin API.
+
+use chrono::offset::Utc;
+use chrono::DateTime;
+use errors::Result;
+use models;
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use traits::Transaction;
+use uuid::Uuid;
+
+/// The version of the plugin API. Plugins built against a different version
+/// are rejected when loaded. This is bumped whenever `DynTransaction`,
+/// `Plugin`, `PluginRegistrar` or `PluginDeclaration` change, rather than
+/// following the library's version, so that plugins don't need rebuilding
+/// for releases that leave the API alone.
+pub const PLUGIN_API_VERSION: u32 = 1;
+
+/// An object-safe version of `Transaction`, so that plugins can work with
+/// transactions from any datastore.
+pub trait DynTransaction {
+    /// Creates a new vertex. See `Transaction::create_vertex`.
+    fn create_vertex(&self, vertex: &models::Vertex) -> Result<bool>;
+
+    /// Creates a new vertex with just a type. See
+    /// `Transaction::create_vertex_from_type`.
+    fn create_vertex_from_type(&self, t: models::Type) -> Result<Uuid>;
+
+    /// Creates a new vertex with a given id. See
+    /// `Transaction::create_vertex_with_id`.
+    fn create_vertex_with_id(&self, id: Uuid, t: models::Type) -> Result<bool>;
+
+    /// Creates a vertex or sets its type. See `Transaction::upsert_vertex`.
+    fn upsert_vertex(&self, vertex: &models::Vertex) -> Result<Option<models::Type>>;
+
+    /// Gets a range of vertices. See `Transaction::get_vertices`.
+    fn get_vertices(&self, q: models::VertexQuery) -> Result<Vec<models::Vertex>>;
+
+    /// Gets a range of vertices along with metadata about how the query was
+    /// executed. See `Transaction::get_vertices_with_metrics`.
+    fn get_vertices_with_metrics(&self, q: models::VertexQuery) -> Result<(Vec<models::Vertex>, models::QueryMetrics)>;
+
+    /// Gets a range of vertices, capped at a maximum result size. See
+    /// `Transaction::get_vertices_page`.
+    fn get_vertices_page(&self, q: models::VertexQuery, max_results: u32) -> Result<models::VertexPage>;
+
+    /// Checks which of a set of vertices exist. See
+    /// `Transaction::filter_existing_vertices`.
+    fn filter_existing_vertices(&self, ids: Vec<Uuid>) -> Result<Vec<bool>>;
+
+    /// Deletes existing vertices. See `Transaction::delete_vertices`.
+    fn delete_vertices(&self, q: models::VertexQuery) -> Result<()>;
+
+    /// Deletes existing vertices in batches. See
+    /// `Transaction::delete_vertices_in_batches`.
+    fn delete_vertices_in_batches(
+        &self,
+        q: models::VertexQuery,
+        batch_size: u32,
+        progress: &mut dyn FnMut(models::BulkDeleteProgress),
+    ) -> Result<u64>;
+
+    /// Gets the number of vertices. See `Transaction::get_vertex_count`.
+    fn get_vertex_count(&self) -> Result<u64>;
+
+    /// Creates a new edge. See `Transaction::create_edge`.
+    fn create_edge(&self, key: &models::EdgeKey) -> Result<bool>;
+
+    /// Creates a new edge with a given update datetime. See
+    /// `Transaction::create_edge_with_datetime`.
+    fn create_edge_with_datetime(&self, key: &models::EdgeKey, update_datetime: DateTime<Utc>) -> Result<bool>;
+
+    /// Creates an edge or refreshes its update datetime. See
+    /// `Transaction::touch_edge`.
+    fn touch_edge(&self, key: &models::EdgeKey) -> Result<bool>;
+
+    /// Gets a range of edges. See `Transaction::get_edges`.
+    fn get_edges(&self, q: models::EdgeQuery) -> Result<Vec<models::Edge>>;
+
+    /// Gets a range of edges along with metadata about how the query was
+    /// executed. See `Transaction::get_edges_with_metrics`.
+    fn get_edges_with_metrics(&self, q: models::EdgeQuery) -> Result<(Vec<models::Edge>, models::QueryMetrics)>;
+
+    /// Gets a range of edges, capped at a maximum result size. See
+    /// `Transaction::get_edges_page`.
+    fn get_edges_page(&self, q: models::EdgeQuery, max_results: u32) -> Result<models::EdgePage>;
+
+    /// Checks whether an edge exists. See `Transaction::edge_exists`.
+    fn edge_exists(&self, key: &models::EdgeKey) -> Result<bool>;
+
+    /// Checks which of a set of edges exist. See
+    /// `Transaction::filter_existing_edges`.
+    fn filter_existing_edges(&self, keys: Vec<models::EdgeKey>) -> Result<Vec<bool>>;
+
+    /// Deletes a set of edges. See `Transaction::delete_edges`.
+    fn delete_edges(&self, q: models::EdgeQuery) -> Result<()>;
+
+    /// Deletes a set of edges that haven't been updated since a datetime.
+    /// See `Transaction::delete_edges_unmodified_since`.
+    fn delete_edges_unmodified_since(&self, q: models::EdgeQuery, since: DateTime<Utc>) -> Result<u64>;
+
+    /// Deletes a set of edges in batches. See
+    /// `Transaction::delete_edges_in_batches`.
+    fn delete_edges_in_batches(
+        &self,
+        q: models::EdgeQuery,
+        batch_size: u32,
+        progress: &mut dyn FnMut(models::BulkDeleteProgress),
+    ) -> Result<u64>;
+
+    /// Gets the number of edges associated with a vertex. See
+    /// `Transaction::get_edge_count`.
+    fn get_edge_count(&self, id: Uuid, t: Option<&models::Type>, direction: models::EdgeDirection) -> Result<u64>;
+
+    /// Gets the number of edges associated with a vertex in a datetime
+    /// range. See `Transaction::get_edge_count_in_range`.
+    fn get_edge_count_in_range(
+        &self,
+        id: Uuid,
+        t: Option<&models::Type>,
+        low: Option<DateTime<Utc>>,
+        high: Option<DateTime<Utc>>,
+        direction: models::EdgeDirection,
+    ) -> Result<u64>;
+
+    /// Gets the neighbors that two vertices have in common. See
+    /// `Transaction::get_common_neighbors`.
+    fn get_common_neighbors(
+        &self,
+        first_id: Uuid,
+        second_id: Uuid,
+        t: &models::Type,
+        direction: models::EdgeDirection,
+        limit: u32,
+    ) -> Result<Vec<Uuid>>;
+
+    /// Gets vertex properties. See `Transaction::get_vertex_properties`.
+    fn get_vertex_properties(&self, q: models::VertexPropertyQuery) -> Result<Vec<models::VertexProperty>>;
+
+    /// Gets all vertex properties. See
+    /// `Transaction::get_all_vertex_properties`.
+    fn get_all_vertex_properties(&self, q: models::VertexQuery) -> Result<Vec<models::VertexProperties>>;
+
+    /// Sets vertex properties. See `Transaction::set_vertex_properties`.
+    fn set_vertex_properties(&self, q: models::VertexPropertyQuery, value: &JsonValue) -> Result<()>;
+
+    /// Deletes vertex properties. See
+    /// `Transaction::delete_vertex_properties`.
+    fn delete_vertex_properties(&self, q: models::VertexPropertyQuery) -> Result<()>;
+
+    /// Gets edge properties. See `Transaction::get_edge_properties`.
+    fn get_edge_properties(&self, q: models::EdgePropertyQuery) -> Result<Vec<models::EdgeProperty>>;
+
+    /// Gets all edge properties. See `Transaction::get_all_edge_properties`.
+    fn get_all_edge_properties(&self, q: models::EdgeQuery) -> Result<Vec<models::EdgeProperties>>;
+
+    /// Sets edge properties. See `Transaction::set_edge_properties`.
+    fn set_edge_properties(&self, q: models::EdgePropertyQuery, value: &JsonValue) -> Result<()>;
+
+    /// Deletes edge properties. See `Transaction::delete_edge_properties`.
+    fn delete_edge_properties(&self, q: models::EdgePropertyQuery) -> Result<()>;
+
+    /// Gets the number of keys read so far. See
+    /// `Transaction::get_keys_scanned`.
+    fn get_keys_scanned(&self) -> Option<u64>;
+
+    /// Runs a batch of operations. See `Transaction::execute_batch`.
+    fn execute_batch(&self, operations: Vec<models::Operation>) -> Result<Vec<models::OperationOutput>>;
+}
+
+impl<T: Transaction> DynTransaction for T {
+    fn create_vertex(&self, vertex: &models::Vertex) -> Result<bool> {
+        Transaction::create_vertex(self, vertex)
+    }
+
+    fn create_vertex_from_type(&self, t: models::Type) -> Result<Uuid> {
+        Transaction::create_vertex_from_type(self, t)
+    }
+
+    fn create_vertex_with_id(&self, id: Uuid, t: models::Type) -> Result<bool> {
+        Transaction::create_vertex_with_id(self, id, t)
+    }
+
+    fn upsert_vertex(&self, vertex: &models::Vertex) -> Result<Option<models::Type>> {
+        Transaction::upsert_vertex(self, vertex)
+    }
+
+    fn get_vertices(&self, q: models::VertexQuery) -> Result<Vec<models::Vertex>> {
+        Transaction::get_vertices(self, q)
+    }
+
+    fn get_vertices_with_metrics(&self, q: models::VertexQuery) -> Result<(Vec<models::Vertex>, models::QueryMetrics)> {
+        Transaction::get_vertices_with_metrics(self, q)
+    }
+
+    fn get_vertices_page(&self, q: models::VertexQuery, max_results: u32) -> Result<models::VertexPage> {
+        Transaction::get_vertices_page(self, q, max_results)
+    }
+
+    fn filter_existing_vertices(&self, ids: Vec<Uuid>) -> Result<Vec<bool>> {
+        Transaction::filter_existing_vertices(self, ids)
+    }
+
+    fn delete_vertices(&self, q: models::VertexQuery) -> Result<()> {
+        Transaction::delete_vertices(self, q)
+    }
+
+    fn delete_vertices_in_batches(
+        &self,
+        q: models::VertexQuery,
+        batch_size: u32,
+        progress: &mut dyn FnMut(models::BulkDeleteProgress),
+    ) -> Result<u64> {
+        Transaction::delete_vertices_in_batches(self, q, batch_size, progress)
+    }
+
+    fn get_vertex_count(&self) -> Result<u64> {
+        Transaction::get_vertex_count(self)
+    }
+
+    fn create_edge(&self, key: &models::EdgeKey) -> Result<bool> {
+        Transaction::create_edge(self, key)
+    }
+
+    fn create_edge_with_datetime(&self, key: &models::EdgeKey, update_datetime: DateTime<Utc>) -> Result<bool> {
+        Transaction::create_edge_with_datetime(self, key, update_datetime)
+    }
+
+    fn touch_edge(&self, key: &models::EdgeKey) -> Result<bool> {
+        Transaction::touch_edge(self, key)
+    }
+
+    fn get_edges(&self, q: models::EdgeQuery) -> Result<Vec<models::Edge>> {
+        Transaction::get_edges(self, q)
+    }
+
+    fn get_edges_with_metrics(&self, q: models::EdgeQuery) -> Result<(Vec<models::Edge>, models::QueryMetrics)> {
+        Transaction::get_edges_with_metrics(self, q)
+    }
+
+    fn get_edges_page(&self, q: models::EdgeQuery, max_results: u32) -> Result<models::EdgePage> {
+        Transaction::get_edges_page(self, q, max_results)
+    }
+
+    fn edge_exists(&self, key: &models::EdgeKey) -> Result<bool> {
+        Transaction::edge_exists(self, key)
+    }
+
+    fn filter_existing_edges(&self, keys: Vec<models::EdgeKey>) -> Result<Vec<bool>> {
+        Transaction::filter_existing_edges(self, keys)
+    }
+
+    fn delete_edges(&self, q: models::EdgeQuery) -> Result<()> {
+        Transaction::delete_edges(self, q)
+    }
+
+    fn delete_edges_unmodified_since(&self, q: models::EdgeQuery, since: DateTime<Utc>) -> Result<u64> {
+        Transaction::delete_edges_unmodified_since(self, q, since)
+    }
+
+    fn delete_edges_in_batches(
+        &self,
+        q: models::EdgeQuery,
+        batch_size: u32,
+        progress: &mut dyn FnMut(models::BulkDeleteProgress),
+    ) -> Result<u64> {
+        Transaction::delete_edges_in_batches(self, q, batch_size, progress)
+    }
+
+    fn get_edge_count(&self, id: Uuid, t: Option<&models::Type>, direction: models::EdgeDirection) -> Result<u64> {
+        Transaction::get_edge_count(self, id, t, direction)
+    }
+
+    fn get_edge_count_in_range(
+        &self,
+        id: Uuid,
+        t: Option<&models::Type>,
+        low: Option<DateTime<Utc>>,
+        high: Option<DateTime<Utc>>,
+        direction: models::EdgeDirection,
+    ) -> Result<u64> {
+        Transaction::get_edge_count_in_range(self, id, t, low, high, direction)
+    }
+
+    fn get_common_neighbors(
+        &self,
+        first_id: Uuid,
+        second_id: Uuid,
+        t: &models::Type,
+        direction: models::EdgeDirection,
+        limit: u32,
+    ) -> Result<Vec<Uuid>> {
+        Transaction::get_common_neighbors(self, first_id, second_id, t, direction, limit)
+    }
+
+    fn get_vertex_properties(&self, q: models::VertexPropertyQuery) -> Result<Vec<models::VertexProperty>> {
+        Transaction::get_vertex_properties(self, q)
+    }
+
+    fn get_all_vertex_properties(&self, q: models::VertexQuery) -> Result<Vec<models::VertexProperties>> {
+        Transaction::get_all_vertex_properties(self, q)
+    }
+
+    fn set_vertex_properties(&self, q: models::VertexPropertyQuery, value: &JsonValue) -> Result<()> {
+        Transaction::set_vertex_properties(self, q, value)
+    }
+
+    fn delete_vertex_properties(&self, q: models::VertexPropertyQuery) -> Result<()> {
+        Transaction::delete_vertex_properties(self, q)
+    }
+
+    fn get_edge_properties(&self, q: models::EdgePropertyQuery) -> Result<Vec<models::EdgeProperty>> {
+        Transaction::get_edge_properties(self, q)
+    }
+
+    fn get_all_edge_properties(&self, q: models::EdgeQuery) -> Result<Vec<models::EdgeProperties>> {
+        Transaction::get_all_edge_properties(self, q)
+    }
+
+    fn set_edge_properties(&self, q: models::EdgePropertyQuery, value: &JsonValue) -> Result<()> {
+        Transaction::set_edge_properties(self, q, value)
+    }
+
+    fn delete_edge_properties(&self, q: models::EdgePropertyQuery) -> Result<()> {
+        Transaction::delete_edge_properties(self, q)
+    }
+
+    fn get_keys_scanned(&self) -> Option<u64> {
+        Transaction::get_keys_scanned(self)
+    }
+
+    fn execute_batch(&self, operations: Vec<models::Operation>) -> Result<Vec<models::OperationOutput>> {
+        Transaction::execute_batch(self, operations)
+    }
+}
+
+/// A server-side procedure.
+pub trait Plugin: Send + Sync {
+    /// Runs the procedure.
+    ///
+    /// # Arguments
+    /// * `trans` - The transaction to run the procedure in.
+    /// * `arg` - The argument passed by the caller.
+    fn call(&self, trans: &dyn DynTransaction, arg: JsonValue) -> Result<JsonValue>;
+}
+
+/// Collects the plugins exported by a plugin library.
+#[derive(Default)]
+pub struct PluginRegistrar {
+    plugins: HashMap<String, Box<dyn Plugin>>,
+}
+
+impl PluginRegistrar {
+    /// Creates a new, empty registrar.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a plugin, replacing any existing plugin with the same
+    /// name.
+    ///
+    /// # Arguments
+    /// * `name` - The name the plugin is called by.
+    /// * `plugin` - The plugin.
+    pub fn register<S: Into<String>>(&mut self, name: S, plugin: Box<dyn Plugin>) {
+        self.plugins.insert(name.into(), plugin);
+    }
+
+    /// Gets a registered plugin.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the plugin.
+    pub fn get(&self, name: &str) -> Option<&dyn Plugin> {
+        self.plugins.get(name).map(|plugin| &**plugin)
+    }
+
+    /// Gets the names of the registered plugins.
+    pub fn names(&self) -> Vec<&str> {
+        self.plugins.keys().map(|name| name.as_str()).collect()
+    }
+}
+
+/// What a plugin library exports, as `INDRADB_PLUGINS`. Use
+/// `export_plugins!` rather than building this directly.
+pub struct PluginDeclaration {
+    /// The `PLUGIN_API_VERSION` the library was built with.
+    pub api_version: u32,
+
+    /// Registers the library's plugins.
+    pub register: fn(&mut PluginRegistrar),
+}
+
+/// Exports a plugin library's plugins.
+///
+/// # Arguments
+/// * `register` - A `fn(&mut PluginRegistrar)` that registers the plugins.
+#[macro_export]
+macro_rules! export_plugins {
+    ($register:expr) => {
+        #[doc(hidden)]
+        #[no_mangle]
+        pub static INDRADB_PLUGINS: $crate::PluginDeclaration = $crate::PluginDeclaration {
+            api_version: $crate::PLUGIN_API_VERSION,
+            register: $register,
+        };
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DynTransaction, Plugin, PluginRegistrar};
+    use errors::Result;
+    use memory::MemoryDatastore;
+    use models::{SpecificVertexQuery, Type, VertexQueryExt};
+    use serde_json::Value as JsonValue;
+    use traits::Datastore;
+
+    struct Degree;
+
+    impl Plugin for Degree {
+        fn call(&self, trans: &dyn DynTransaction, arg: JsonValue) -> Result<JsonValue> {
+            let id = arg.as_str().unwrap().parse().unwrap();
+            let edges = trans.get_edges(SpecificVertexQuery::single(id).outbound(10).into())?;
+            Ok(JsonValue::from(edges.len()))
+        }
+    }
+
+    #[test]
+    fn should_call_registered_plugins() {
+        let mut registrar = PluginRegistrar::new();
+        registrar.register("degree", Box::new(Degree));
+        assert_eq!(registrar.names(), vec!["degree"]);
+        assert!(registrar.get("missing").is_none());
+
+        let datastore = MemoryDatastore::default();
+        let trans = datastore.transaction().unwrap();
+        let t = Type::new("foo").unwrap();
+        let id = DynTransaction::create_vertex_from_type(&trans, t).unwrap();
+        let result = registrar
+            .get("degree")
+            .unwrap()
+            .call(&trans, JsonValue::String(id.to_string()))
+            .unwrap();
+        assert_eq!(result, JsonValue::from(0));
+    }
+}
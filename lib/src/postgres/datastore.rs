@@ -0,0 +1,823 @@
+use super::super::{Datastore, EdgePropertyQuery, EdgeQuery, Transaction, VertexPropertyQuery, VertexQuery};
+use chrono::offset::Utc;
+use chrono::DateTime;
+use clock::{Clock, SystemClock};
+use errors::{ErrorKind, Result, ValidationError};
+use models;
+use pg::types::ToSql;
+use pg::{Client, GenericClient, NoTls, Row};
+use serde_json::Value as JsonValue;
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use traits::missing_edge_vertices;
+use util::{datetime_from_signed_nanos, signed_nanos_since_epoch};
+use uuid::Uuid;
+
+type Params = Vec<Box<dyn ToSql + Sync>>;
+
+// Types and property names use the "C" collation, so that they're ordered
+// bytewise like they are in the other datastores. Update datetimes are
+// stored as nanoseconds since the epoch, since `timestamptz` only has
+// microsecond precision. The edge range indexes cover the columns that
+// traversals read, so that they're answered from an index alone.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS vertices (
+        id BYTEA NOT NULL PRIMARY KEY,
+        t TEXT COLLATE \"C\" NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS edges (
+        outbound_id BYTEA NOT NULL,
+        t TEXT COLLATE \"C\" NOT NULL,
+        inbound_id BYTEA NOT NULL,
+        update_datetime BIGINT NOT NULL,
+        PRIMARY KEY (outbound_id, t, inbound_id)
+    );
+
+    CREATE INDEX IF NOT EXISTS edge_ranges
+        ON edges (outbound_id, t, update_datetime DESC, inbound_id);
+
+    CREATE INDEX IF NOT EXISTS reversed_edge_ranges
+        ON edges (inbound_id, t, update_datetime DESC, outbound_id);
+
+    CREATE TABLE IF NOT EXISTS vertex_properties (
+        vertex_id BYTEA NOT NULL,
+        name TEXT COLLATE \"C\" NOT NULL,
+        value JSONB NOT NULL,
+        PRIMARY KEY (vertex_id, name)
+    );
+
+    CREATE TABLE IF NOT EXISTS edge_properties (
+        outbound_id BYTEA NOT NULL,
+        t TEXT COLLATE \"C\" NOT NULL,
+        inbound_id BYTEA NOT NULL,
+        name TEXT COLLATE \"C\" NOT NULL,
+        value JSONB NOT NULL,
+        PRIMARY KEY (outbound_id, t, inbound_id, name)
+    );
+";
+
+fn uuid_to_sql(id: Uuid) -> Vec<u8> {
+    id.as_bytes().to_vec()
+}
+
+fn uuid_from_sql(bytes: &[u8]) -> Result<Uuid> {
+    Uuid::from_slice(bytes).map_err(|_| ErrorKind::Corrupt("invalid uuid".to_string()).into())
+}
+
+fn datetime_to_sql(datetime: DateTime<Utc>) -> Result<i64> {
+    signed_nanos_since_epoch(&datetime).ok_or_else(|| ValidationError::from("Datetime is out of range").into())
+}
+
+fn param_refs(params: &[Box<dyn ToSql + Sync>]) -> Vec<&(dyn ToSql + Sync)> {
+    params.iter().map(|param| param.as_ref()).collect()
+}
+
+fn read_vertex(row: &Row) -> Result<(Uuid, models::Type)> {
+    let id: Vec<u8> = row.try_get(0)?;
+    let t: String = row.try_get(1)?;
+    Ok((uuid_from_sql(&id)?, models::Type(t)))
+}
+
+// Reads an edge from a row with the columns `outbound_id`, `t`, `inbound_id`
+// and `update_datetime`.
+fn read_edge(row: &Row) -> Result<(models::EdgeKey, DateTime<Utc>)> {
+    let outbound_id: Vec<u8> = row.try_get(0)?;
+    let t: String = row.try_get(1)?;
+    let inbound_id: Vec<u8> = row.try_get(2)?;
+    let update_datetime: i64 = row.try_get(3)?;
+
+    let key = models::EdgeKey::new(
+        uuid_from_sql(&outbound_id)?,
+        models::Type(t),
+        uuid_from_sql(&inbound_id)?,
+    );
+
+    Ok((key, datetime_from_signed_nanos(update_datetime)))
+}
+
+fn get_vertex_values_by_query<C: GenericClient>(client: &mut C, q: VertexQuery) -> Result<Vec<(Uuid, models::Type)>> {
+    match q {
+        VertexQuery::Range(range) => {
+            let mut sql = "SELECT id, t FROM vertices WHERE TRUE".to_string();
+            let mut params: Params = Vec::new();
+
+            if let Some(start_id) = range.start_id {
+                params.push(Box::new(uuid_to_sql(start_id)));
+                sql.push_str(&format!(" AND id >= ${}", params.len()));
+            }
+
+            if let Some(t) = range.t {
+                params.push(Box::new(t.0));
+                sql.push_str(&format!(" AND t = ${}", params.len()));
+            }
+
+            params.push(Box::new(i64::from(range.limit)));
+            sql.push_str(&format!(" ORDER BY id LIMIT ${}", params.len()));
+
+            let rows = client.query(sql.as_str(), &param_refs(&params))?;
+            rows.iter().map(read_vertex).collect()
+        }
+        VertexQuery::Specific(specific) => {
+            let mut results = Vec::new();
+
+            for id in specific.ids {
+                if let Some(t) = get_vertex_type(client, id)? {
+                    results.push((id, t));
+                }
+            }
+
+            Ok(results)
+        }
+        VertexQuery::Pipe(pipe) => {
+            let edge_values = get_edge_values_by_query(client, *pipe.inner)?;
+
+            let ids: Vec<Uuid> = match pipe.direction {
+                models::EdgeDirection::Outbound => edge_values.into_iter().map(|(key, _)| key.outbound_id).collect(),
+                models::EdgeDirection::Inbound => edge_values.into_iter().map(|(key, _)| key.inbound_id).collect(),
+                models::EdgeDirection::Both => {
+                    let mut seen_ids = HashSet::new();
+                    edge_values
+                        .into_iter()
+                        .flat_map(|(key, _)| vec![key.outbound_id, key.inbound_id])
+                        .filter(|id| seen_ids.insert(*id))
+                        .collect()
+                }
+            };
+
+            let mut results = Vec::new();
+
+            for id in ids {
+                if results.len() == pipe.limit as usize {
+                    break;
+                }
+
+                if let Some(t) = get_vertex_type(client, id)? {
+                    if let Some(ref pipe_t) = pipe.t {
+                        if pipe_t != &t {
+                            continue;
+                        }
+                    }
+
+                    results.push((id, t));
+                }
+            }
+
+            Ok(results)
+        }
+    }
+}
+
+fn get_vertex_type<C: GenericClient>(client: &mut C, id: Uuid) -> Result<Option<models::Type>> {
+    let row = client.query_opt("SELECT t FROM vertices WHERE id = $1", &[&uuid_to_sql(id)])?;
+
+    match row {
+        Some(row) => Ok(Some(models::Type(row.try_get(0)?))),
+        None => Ok(None),
+    }
+}
+
+fn get_edge_values_by_query<C: GenericClient>(
+    client: &mut C,
+    q: EdgeQuery,
+) -> Result<Vec<(models::EdgeKey, DateTime<Utc>)>> {
+    match q {
+        EdgeQuery::Specific(specific) => {
+            let stmt = client.prepare(
+                "SELECT outbound_id, t, inbound_id, update_datetime FROM edges
+                 WHERE outbound_id = $1 AND t = $2 AND inbound_id = $3",
+            )?;
+            let mut results = Vec::new();
+
+            for key in specific.keys {
+                let row = client.query_opt(
+                    &stmt,
+                    &[&uuid_to_sql(key.outbound_id), &key.t.0, &uuid_to_sql(key.inbound_id)],
+                )?;
+
+                if let Some(row) = row {
+                    results.push(read_edge(&row)?);
+                }
+            }
+
+            Ok(results)
+        }
+        EdgeQuery::Pipe(ref pipe) if pipe.direction == models::EdgeDirection::Both => {
+            let mut results = Vec::new();
+            let mut seen_keys = HashSet::new();
+
+            for &direction in &[models::EdgeDirection::Outbound, models::EdgeDirection::Inbound] {
+                let directed_pipe = models::PipeEdgeQuery {
+                    direction,
+                    ..pipe.clone()
+                };
+
+                for (key, update_datetime) in get_edge_values_by_query(client, directed_pipe.into())? {
+                    if results.len() == pipe.limit as usize {
+                        return Ok(results);
+                    }
+
+                    if seen_keys.insert(key.clone()) {
+                        results.push((key, update_datetime));
+                    }
+                }
+            }
+
+            Ok(results)
+        }
+        EdgeQuery::Pipe(pipe) => {
+            let vertex_values = get_vertex_values_by_query(client, *pipe.inner)?;
+            let mut results = Vec::new();
+
+            if pipe.limit == 0 {
+                return Ok(results);
+            }
+
+            // Picks the index that matches the direction, so that the rows
+            // come back ordered by type and then newest first.
+            let (id_column, other_id_column) = match pipe.direction {
+                models::EdgeDirection::Outbound => ("outbound_id", "inbound_id"),
+                models::EdgeDirection::Inbound => ("inbound_id", "outbound_id"),
+                // Handled by the previous match arm
+                models::EdgeDirection::Both => unreachable!(),
+            };
+
+            let mut sql = format!(
+                "SELECT outbound_id, t, inbound_id, update_datetime FROM edges WHERE {} = $1",
+                id_column
+            );
+            let mut filter_params: Params = Vec::new();
+
+            if let Some(ref t) = pipe.t {
+                filter_params.push(Box::new(t.0.clone()));
+                sql.push_str(&format!(" AND t = ${}", filter_params.len() + 1));
+            }
+
+            if let Some(high) = pipe.high {
+                filter_params.push(Box::new(datetime_to_sql(high)?));
+                sql.push_str(&format!(" AND update_datetime <= ${}", filter_params.len() + 1));
+            }
+
+            if let Some(low) = pipe.low {
+                filter_params.push(Box::new(datetime_to_sql(low)?));
+                sql.push_str(&format!(" AND update_datetime >= ${}", filter_params.len() + 1));
+            }
+
+            sql.push_str(&format!(
+                " ORDER BY t, update_datetime DESC, {} LIMIT ${}",
+                other_id_column,
+                filter_params.len() + 2
+            ));
+
+            let stmt = client.prepare(&sql)?;
+
+            for (id, _) in vertex_values {
+                let id = uuid_to_sql(id);
+                let limit = (pipe.limit as usize - results.len()) as i64;
+                let mut params: Vec<&(dyn ToSql + Sync)> = vec![&id];
+                params.extend(param_refs(&filter_params));
+                params.push(&limit);
+
+                for row in client.query(&stmt, &params)? {
+                    results.push(read_edge(&row)?);
+                }
+
+                if results.len() == pipe.limit as usize {
+                    break;
+                }
+            }
+
+            Ok(results)
+        }
+    }
+}
+
+fn set_edge<C: GenericClient>(client: &mut C, key: &models::EdgeKey, update_datetime: DateTime<Utc>) -> Result<()> {
+    client.execute(
+        "INSERT INTO edges (outbound_id, t, inbound_id, update_datetime) VALUES ($1, $2, $3, $4)
+         ON CONFLICT (outbound_id, t, inbound_id) DO UPDATE SET update_datetime = EXCLUDED.update_datetime",
+        &[
+            &uuid_to_sql(key.outbound_id),
+            &key.t.0,
+            &uuid_to_sql(key.inbound_id),
+            &datetime_to_sql(update_datetime)?,
+        ],
+    )?;
+    Ok(())
+}
+
+fn set_vertex_property<C: GenericClient>(client: &mut C, id: Uuid, name: &str, value: &JsonValue) -> Result<()> {
+    client.execute(
+        "INSERT INTO vertex_properties (vertex_id, name, value) VALUES ($1, $2, $3)
+         ON CONFLICT (vertex_id, name) DO UPDATE SET value = EXCLUDED.value",
+        &[&uuid_to_sql(id), &name, value],
+    )?;
+    Ok(())
+}
+
+fn set_edge_property<C: GenericClient>(
+    client: &mut C,
+    key: &models::EdgeKey,
+    name: &str,
+    value: &JsonValue,
+) -> Result<()> {
+    client.execute(
+        "INSERT INTO edge_properties (outbound_id, t, inbound_id, name, value) VALUES ($1, $2, $3, $4, $5)
+         ON CONFLICT (outbound_id, t, inbound_id, name) DO UPDATE SET value = EXCLUDED.value",
+        &[
+            &uuid_to_sql(key.outbound_id),
+            &key.t.0,
+            &uuid_to_sql(key.inbound_id),
+            &name,
+            value,
+        ],
+    )?;
+    Ok(())
+}
+
+fn delete_edge<C: GenericClient>(client: &mut C, key: &models::EdgeKey) -> Result<()> {
+    let outbound_id = uuid_to_sql(key.outbound_id);
+    let inbound_id = uuid_to_sql(key.inbound_id);
+    let params: [&(dyn ToSql + Sync); 3] = [&outbound_id, &key.t.0, &inbound_id];
+    client.execute(
+        "DELETE FROM edge_properties WHERE outbound_id = $1 AND t = $2 AND inbound_id = $3",
+        &params,
+    )?;
+    client.execute(
+        "DELETE FROM edges WHERE outbound_id = $1 AND t = $2 AND inbound_id = $3",
+        &params,
+    )?;
+    Ok(())
+}
+
+fn delete_vertex<C: GenericClient>(client: &mut C, id: Uuid) -> Result<()> {
+    let id = uuid_to_sql(id);
+    client.execute("DELETE FROM vertex_properties WHERE vertex_id = $1", &[&id])?;
+    client.execute(
+        "DELETE FROM edge_properties WHERE outbound_id = $1 OR inbound_id = $1",
+        &[&id],
+    )?;
+    client.execute("DELETE FROM edges WHERE outbound_id = $1 OR inbound_id = $1", &[&id])?;
+    client.execute("DELETE FROM vertices WHERE id = $1", &[&id])?;
+    Ok(())
+}
+
+/// A datastore that is backed by PostgreSQL, over a normalized schema with
+/// tables for vertices, edges and their properties. Each transaction method
+/// runs in its own Postgres transaction, so it's applied entirely or not at
+/// all. Method calls are serialized over a single connection.
+///
+/// Like the other datastores, edges and properties don't have foreign keys
+/// to their vertices, so that bulk inserts don't have to be ordered.
+pub struct PostgresDatastore {
+    client: Arc<Mutex<Client>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl PostgresDatastore {
+    /// Creates a new Postgres datastore, creating its tables if they don't
+    /// exist.
+    ///
+    /// # Arguments
+    /// * `connection_string` - How to connect to Postgres, e.g.
+    ///   `postgres://user@localhost/indradb`. TLS isn't supported.
+    pub fn new(connection_string: &str) -> Result<PostgresDatastore> {
+        Self::from_client(Client::connect(connection_string, NoTls)?)
+    }
+
+    /// Creates a new Postgres datastore whose tables are in a given schema,
+    /// creating the schema and its tables if they don't exist. This allows
+    /// one database to hold multiple graphs.
+    ///
+    /// # Arguments
+    /// * `connection_string` - How to connect to Postgres.
+    /// * `schema` - The name of the schema.
+    pub fn new_with_schema(connection_string: &str, schema: &str) -> Result<PostgresDatastore> {
+        if schema.is_empty() || !schema.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(ValidationError::from("Schema names can only contain letters, digits and underscores").into());
+        }
+
+        let mut client = Client::connect(connection_string, NoTls)?;
+        client.batch_execute(&format!(
+            "CREATE SCHEMA IF NOT EXISTS \"{0}\"; SET search_path TO \"{0}\"",
+            schema
+        ))?;
+        Self::from_client(client)
+    }
+
+    fn from_client(mut client: Client) -> Result<PostgresDatastore> {
+        client.batch_execute(SCHEMA)?;
+
+        Ok(PostgresDatastore {
+            client: Arc::new(Mutex::new(client)),
+            clock: Arc::new(SystemClock),
+        })
+    }
+
+    /// Sets the clock used to timestamp edges, in place of the system time.
+    ///
+    /// # Arguments
+    /// * `clock` - The clock to use.
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> PostgresDatastore {
+        PostgresDatastore { clock, ..self }
+    }
+}
+
+// `postgres::Client` doesn't implement `Debug`.
+impl fmt::Debug for PostgresDatastore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PostgresDatastore").field("clock", &self.clock).finish()
+    }
+}
+
+impl Datastore for PostgresDatastore {
+    type Trans = PostgresTransaction;
+
+    // We override the default `bulk_insert` implementation so that all of
+    // the items are inserted in a single Postgres transaction. Like the
+    // rocksdb datastore, this does not verify that the vertices associated
+    // with an inserted edge or property exist.
+    fn bulk_insert<I>(&self, items: I) -> Result<()>
+    where
+        I: Iterator<Item = models::BulkInsertItem>,
+    {
+        let mut client = self.client.lock().unwrap();
+        let mut tx = client.transaction()?;
+
+        for item in items {
+            match item {
+                models::BulkInsertItem::Vertex(vertex) => {
+                    tx.execute(
+                        "INSERT INTO vertices (id, t) VALUES ($1, $2) ON CONFLICT (id) DO UPDATE SET t = EXCLUDED.t",
+                        &[&uuid_to_sql(vertex.id), &vertex.t.0],
+                    )?;
+                }
+                models::BulkInsertItem::Edge(key) => {
+                    set_edge(&mut tx, &key, self.clock.now())?;
+                }
+                models::BulkInsertItem::VertexProperty(id, name, value) => {
+                    set_vertex_property(&mut tx, id, &name, &value)?;
+                }
+                models::BulkInsertItem::EdgeProperty(key, name, value) => {
+                    set_edge_property(&mut tx, &key, &name, &value)?;
+                }
+            }
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn transaction(&self) -> Result<Self::Trans> {
+        Ok(PostgresTransaction {
+            client: Arc::clone(&self.client),
+            clock: Arc::clone(&self.clock),
+        })
+    }
+}
+
+/// A transaction that is backed by Postgres.
+pub struct PostgresTransaction {
+    client: Arc<Mutex<Client>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl PostgresTransaction {
+    // Runs a function in a new Postgres transaction, committing it if the
+    // function succeeds and rolling it back otherwise.
+    fn write<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut pg::Transaction) -> Result<T>,
+    {
+        let mut client = self.client.lock().unwrap();
+        let mut tx = client.transaction()?;
+        let result = f(&mut tx)?;
+        tx.commit()?;
+        Ok(result)
+    }
+
+    fn read<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Client) -> Result<T>,
+    {
+        f(&mut self.client.lock().unwrap())
+    }
+}
+
+impl fmt::Debug for PostgresTransaction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PostgresTransaction")
+            .field("clock", &self.clock)
+            .finish()
+    }
+}
+
+impl Transaction for PostgresTransaction {
+    fn create_vertex(&self, vertex: &models::Vertex) -> Result<bool> {
+        self.write(|client| {
+            let count = client.execute(
+                "INSERT INTO vertices (id, t) VALUES ($1, $2) ON CONFLICT (id) DO NOTHING",
+                &[&uuid_to_sql(vertex.id), &vertex.t.0],
+            )?;
+            Ok(count > 0)
+        })
+    }
+
+    fn upsert_vertex(&self, vertex: &models::Vertex) -> Result<Option<models::Type>> {
+        self.write(|client| {
+            // Locks the row, if there is one, so that the type returned is
+            // the one that gets replaced
+            let row = client.query_opt(
+                "SELECT t FROM vertices WHERE id = $1 FOR UPDATE",
+                &[&uuid_to_sql(vertex.id)],
+            )?;
+            let existing_t = match row {
+                Some(row) => Some(models::Type(row.try_get(0)?)),
+                None => None,
+            };
+
+            client.execute(
+                "INSERT INTO vertices (id, t) VALUES ($1, $2) ON CONFLICT (id) DO UPDATE SET t = EXCLUDED.t",
+                &[&uuid_to_sql(vertex.id), &vertex.t.0],
+            )?;
+            Ok(existing_t)
+        })
+    }
+
+    fn get_vertices<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<Vec<models::Vertex>> {
+        let vertex_values = self.read(|client| get_vertex_values_by_query(client, q.into()))?;
+        let iter = vertex_values.into_iter().map(|(id, t)| models::Vertex::with_id(id, t));
+        Ok(iter.collect())
+    }
+
+    fn delete_vertices<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<()> {
+        self.write(|client| {
+            for (id, _) in get_vertex_values_by_query(client, q.into())? {
+                delete_vertex(client, id)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn get_vertex_count(&self) -> Result<u64> {
+        self.read(|client| {
+            let count: i64 = client.query_one("SELECT COUNT(*) FROM vertices", &[])?.try_get(0)?;
+            Ok(count as u64)
+        })
+    }
+
+    fn create_edge(&self, key: &models::EdgeKey) -> Result<bool> {
+        self.create_edge_with_datetime(key, self.clock.now())
+    }
+
+    fn create_edge_with_datetime(&self, key: &models::EdgeKey, update_datetime: DateTime<Utc>) -> Result<bool> {
+        self.write(|client| {
+            if get_vertex_type(client, key.outbound_id)?.is_none() || get_vertex_type(client, key.inbound_id)?.is_none()
+            {
+                return Ok(false);
+            }
+
+            set_edge(client, key, update_datetime)?;
+            Ok(true)
+        })
+    }
+
+    fn touch_edge(&self, key: &models::EdgeKey) -> Result<bool> {
+        self.write(|client| {
+            if get_vertex_type(client, key.outbound_id)?.is_none() || get_vertex_type(client, key.inbound_id)?.is_none()
+            {
+                return Err(missing_edge_vertices(key));
+            }
+
+            // A row's `xmax` is only zero if the row was just inserted,
+            // rather than updated on conflict
+            let row = client.query_one(
+                "INSERT INTO edges (outbound_id, t, inbound_id, update_datetime) VALUES ($1, $2, $3, $4)
+                 ON CONFLICT (outbound_id, t, inbound_id) DO UPDATE SET update_datetime = EXCLUDED.update_datetime
+                 RETURNING (xmax = 0)",
+                &[
+                    &uuid_to_sql(key.outbound_id),
+                    &key.t.0,
+                    &uuid_to_sql(key.inbound_id),
+                    &datetime_to_sql(self.clock.now())?,
+                ],
+            )?;
+            Ok(row.try_get(0)?)
+        })
+    }
+
+    fn get_edges<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<Vec<models::Edge>> {
+        let edge_values = self.read(|client| get_edge_values_by_query(client, q.into()))?;
+        let iter = edge_values
+            .into_iter()
+            .map(|(key, update_datetime)| models::Edge::new(key, update_datetime));
+        Ok(iter.collect())
+    }
+
+    fn delete_edges<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<()> {
+        self.write(|client| {
+            for (key, _) in get_edge_values_by_query(client, q.into())? {
+                delete_edge(client, &key)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn delete_edges_unmodified_since<Q: Into<models::EdgeQuery>>(&self, q: Q, since: DateTime<Utc>) -> Result<u64> {
+        let since = datetime_to_sql(since)?;
+
+        self.write(|client| {
+            let mut count = 0;
+
+            for (key, _) in get_edge_values_by_query(client, q.into())? {
+                // The condition is checked again by the delete, which waits
+                // for any concurrent write to the edge to commit first
+                let deleted = client.execute(
+                    "DELETE FROM edges WHERE outbound_id = $1 AND t = $2 AND inbound_id = $3 AND update_datetime <= $4",
+                    &[
+                        &uuid_to_sql(key.outbound_id),
+                        &key.t.0,
+                        &uuid_to_sql(key.inbound_id),
+                        &since,
+                    ],
+                )?;
+
+                if deleted > 0 {
+                    delete_edge(client, &key)?;
+                    count += 1;
+                }
+            }
+
+            Ok(count)
+        })
+    }
+
+    fn get_edge_count(&self, id: Uuid, t: Option<&models::Type>, direction: models::EdgeDirection) -> Result<u64> {
+        let mut sql = match direction {
+            models::EdgeDirection::Outbound => "SELECT COUNT(*) FROM edges WHERE outbound_id = $1",
+            models::EdgeDirection::Inbound => "SELECT COUNT(*) FROM edges WHERE inbound_id = $1",
+            models::EdgeDirection::Both => "SELECT COUNT(*) FROM edges WHERE (outbound_id = $1 OR inbound_id = $1)",
+        }
+        .to_string();
+        let mut params: Params = vec![Box::new(uuid_to_sql(id))];
+
+        if let Some(t) = t {
+            sql.push_str(" AND t = $2");
+            params.push(Box::new(t.0.clone()));
+        }
+
+        self.read(|client| {
+            let count: i64 = client.query_one(sql.as_str(), &param_refs(&params))?.try_get(0)?;
+            Ok(count as u64)
+        })
+    }
+
+    fn get_vertex_properties(&self, q: VertexPropertyQuery) -> Result<Vec<models::VertexProperty>> {
+        self.read(|client| {
+            let stmt = client.prepare("SELECT value FROM vertex_properties WHERE vertex_id = $1 AND name = $2")?;
+            let mut results = Vec::new();
+
+            for (id, _) in get_vertex_values_by_query(client, q.inner)? {
+                if let Some(row) = client.query_opt(&stmt, &[&uuid_to_sql(id), &q.name])? {
+                    results.push(models::VertexProperty::new(id, row.try_get(0)?));
+                }
+            }
+
+            Ok(results)
+        })
+    }
+
+    fn set_vertex_properties(&self, q: VertexPropertyQuery, value: &JsonValue) -> Result<()> {
+        self.write(|client| {
+            for (id, _) in get_vertex_values_by_query(client, q.inner)? {
+                set_vertex_property(client, id, &q.name, value)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn delete_vertex_properties(&self, q: VertexPropertyQuery) -> Result<()> {
+        self.write(|client| {
+            let stmt = client.prepare("DELETE FROM vertex_properties WHERE vertex_id = $1 AND name = $2")?;
+
+            for (id, _) in get_vertex_values_by_query(client, q.inner)? {
+                client.execute(&stmt, &[&uuid_to_sql(id), &q.name])?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn get_edge_properties(&self, q: EdgePropertyQuery) -> Result<Vec<models::EdgeProperty>> {
+        self.read(|client| {
+            let stmt = client.prepare(
+                "SELECT value FROM edge_properties
+                 WHERE outbound_id = $1 AND t = $2 AND inbound_id = $3 AND name = $4",
+            )?;
+            let mut results = Vec::new();
+
+            for (key, _) in get_edge_values_by_query(client, q.inner)? {
+                let row = client.query_opt(
+                    &stmt,
+                    &[
+                        &uuid_to_sql(key.outbound_id),
+                        &key.t.0,
+                        &uuid_to_sql(key.inbound_id),
+                        &q.name,
+                    ],
+                )?;
+
+                if let Some(row) = row {
+                    results.push(models::EdgeProperty::new(key, row.try_get(0)?));
+                }
+            }
+
+            Ok(results)
+        })
+    }
+
+    fn set_edge_properties(&self, q: EdgePropertyQuery, value: &JsonValue) -> Result<()> {
+        self.write(|client| {
+            for (key, _) in get_edge_values_by_query(client, q.inner)? {
+                set_edge_property(client, &key, &q.name, value)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn delete_edge_properties(&self, q: EdgePropertyQuery) -> Result<()> {
+        self.write(|client| {
+            let stmt = client.prepare(
+                "DELETE FROM edge_properties WHERE outbound_id = $1 AND t = $2 AND inbound_id = $3 AND name = $4",
+            )?;
+
+            for (key, _) in get_edge_values_by_query(client, q.inner)? {
+                client.execute(
+                    &stmt,
+                    &[
+                        &uuid_to_sql(key.outbound_id),
+                        &key.t.0,
+                        &uuid_to_sql(key.inbound_id),
+                        &q.name,
+                    ],
+                )?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn get_all_vertex_properties<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<Vec<models::VertexProperties>> {
+        self.read(|client| {
+            let stmt =
+                client.prepare("SELECT name, value FROM vertex_properties WHERE vertex_id = $1 ORDER BY name")?;
+            let mut results = Vec::new();
+
+            for (id, t) in get_vertex_values_by_query(client, q.into())? {
+                let mut props = Vec::new();
+
+                for row in client.query(&stmt, &[&uuid_to_sql(id)])? {
+                    props.push(models::NamedProperty::new(row.try_get(0)?, row.try_get(1)?));
+                }
+
+                results.push(models::VertexProperties::new(models::Vertex::with_id(id, t), props));
+            }
+
+            Ok(results)
+        })
+    }
+
+    fn get_all_edge_properties<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<Vec<models::EdgeProperties>> {
+        self.read(|client| {
+            let stmt = client.prepare(
+                "SELECT name, value FROM edge_properties
+                 WHERE outbound_id = $1 AND t = $2 AND inbound_id = $3 ORDER BY name",
+            )?;
+            let mut results = Vec::new();
+
+            for (key, update_datetime) in get_edge_values_by_query(client, q.into())? {
+                let mut props = Vec::new();
+                let rows = client.query(
+                    &stmt,
+                    &[&uuid_to_sql(key.outbound_id), &key.t.0, &uuid_to_sql(key.inbound_id)],
+                )?;
+
+                for row in rows {
+                    props.push(models::NamedProperty::new(row.try_get(0)?, row.try_get(1)?));
+                }
+
+                results.push(models::EdgeProperties::new(
+                    models::Edge::new(key, update_datetime),
+                    props,
+                ));
+            }
+
+            Ok(results)
+        })
+    }
+}
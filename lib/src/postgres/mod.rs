@@ -0,0 +1,38 @@
+//! The PostgreSQL datastore implementation. The graph is stored in ordinary
+//! tables, so it can be backed up, replicated and inspected with the usual
+//! Postgres tooling. It has these drawbacks:
+//!
+//! * Each datastore uses a single connection, so method calls are
+//!   serialized. Use several datastores for more concurrency.
+//! * Property TTLs, indexes, subscriptions and the other extensions specific
+//!   to the rocksdb datastore aren't supported.
+
+mod datastore;
+
+pub use self::datastore::{PostgresDatastore, PostgresTransaction};
+
+#[cfg(feature = "bench-suite")]
+full_bench_impl!({
+    use std::env;
+    use util::generate_random_secret;
+
+    // Each datastore gets its own schema, so that tests don't see each
+    // other's data.
+    let url =
+        env::var("INDRADB_TEST_POSTGRES_URL").unwrap_or_else(|_| "postgres://postgres@localhost/postgres".to_string());
+    let schema = format!("indradb_test_{}", generate_random_secret(8).to_lowercase());
+    PostgresDatastore::new_with_schema(&url, &schema).unwrap()
+});
+
+#[cfg(feature = "test-suite")]
+full_test_impl!({
+    use std::env;
+    use util::generate_random_secret;
+
+    // Each datastore gets its own schema, so that tests don't see each
+    // other's data.
+    let url =
+        env::var("INDRADB_TEST_POSTGRES_URL").unwrap_or_else(|_| "postgres://postgres@localhost/postgres".to_string());
+    let schema = format!("indradb_test_{}", generate_random_secret(8).to_lowercase());
+    PostgresDatastore::new_with_schema(&url, &schema).unwrap()
+});
@@ -0,0 +1,402 @@
+use super::datastore::CommitOptions;
+use super::managers::*;
+use chrono::offset::Utc;
+use chrono::DateTime;
+use clock::Clock;
+use errors::{Result, ValidationError};
+use models;
+use rocksdb::{WriteBatch, DB};
+use serde_json::Value as JsonValue;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+pub(crate) enum Operation {
+    CreateVertex(models::Vertex),
+    DeleteVertex(Uuid),
+    CreateEdge(models::EdgeKey, Option<DateTime<Utc>>),
+    DeleteEdge(models::EdgeKey),
+    SetVertexProperty(Uuid, String, JsonValue),
+    DeleteVertexProperty(Uuid, String),
+    SetEdgeProperty(models::EdgeKey, String, JsonValue),
+    DeleteEdgeProperty(models::EdgeKey, String),
+}
+
+/// A point in a batch that it can be rolled back to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Savepoint(usize);
+
+/// Queues creates, sets and deletes so they can be applied to a rocksdb
+/// datastore atomically, in a single `WriteBatch`. Nothing is written until
+/// `commit` is called, and dropping the batch discards it.
+///
+/// Unlike the methods on `Transaction`, queued operations are not verified:
+/// e.g. an edge can be created even if its vertices don't exist, just as
+/// with `bulk_insert`. Reads that are needed to apply an operation see the
+/// datastore as it was before the batch, with one exception: edges queued
+/// earlier in the batch are tracked, so re-creating or deleting them, or
+/// deleting one of their vertices, keeps the edge indices consistent.
+/// Properties queued earlier in the batch are not tracked, so deleting a
+/// vertex or edge won't delete properties that were set on it in the same
+/// batch.
+///
+/// Savepoints allow code composing multiple sub-operations to roll back just
+/// its own portion of a batch on error.
+pub struct BatchTransaction {
+    db: Arc<DB>,
+    clock: Arc<dyn Clock>,
+    // Whether edges are written to the edge log.
+    edge_log: bool,
+    // The width of the windows edges are counted in by the inbound neighbor
+    // sketches, if they're counted at all.
+    neighbor_sketch_window: Option<Duration>,
+    // The settings for adjacency bitmaps, if they're kept at all.
+    adjacency_bitmaps: Option<Arc<AdjacencyBitmapSettings>>,
+    // The names of the properties stored in the cold column families.
+    cold_properties: Arc<HashSet<String>>,
+    // The settings for recording history, if it's recorded at all.
+    history: Option<Arc<HistorySettings>>,
+    operations: Vec<Operation>,
+    // The number of queued operations at each savepoint that hasn't been
+    // rolled back or released, oldest first.
+    savepoints: Vec<usize>,
+}
+
+impl BatchTransaction {
+    pub(crate) fn new(
+        db: Arc<DB>,
+        clock: Arc<dyn Clock>,
+        edge_log: bool,
+        neighbor_sketch_window: Option<Duration>,
+        adjacency_bitmaps: Option<Arc<AdjacencyBitmapSettings>>,
+        cold_properties: Arc<HashSet<String>>,
+        history: Option<Arc<HistorySettings>>,
+    ) -> Self {
+        BatchTransaction {
+            db,
+            clock,
+            edge_log,
+            neighbor_sketch_window,
+            adjacency_bitmaps,
+            cold_properties,
+            history,
+            operations: Vec::new(),
+            savepoints: Vec::new(),
+        }
+    }
+
+    /// Queues the creation of a vertex. If a vertex with the same ID already
+    /// exists, it is overwritten.
+    ///
+    /// # Arguments
+    /// * `vertex`: The vertex to create.
+    pub fn create_vertex(&mut self, vertex: &models::Vertex) {
+        self.operations.push(Operation::CreateVertex(vertex.clone()));
+    }
+
+    /// Queues the deletion of a vertex, along with its properties and edges.
+    ///
+    /// # Arguments
+    /// * `id`: The ID of the vertex to delete.
+    pub fn delete_vertex(&mut self, id: Uuid) {
+        self.operations.push(Operation::DeleteVertex(id));
+    }
+
+    /// Queues the creation of an edge. If the edge already exists, its update
+    /// datetime is bumped to the time of the commit.
+    ///
+    /// # Arguments
+    /// * `key`: The edge to create.
+    pub fn create_edge(&mut self, key: &models::EdgeKey) {
+        self.operations.push(Operation::CreateEdge(key.clone(), None));
+    }
+
+    /// Queues the creation of an edge with a given update datetime, rather
+    /// than the time of the commit. If the edge already exists, its update
+    /// datetime is replaced, even if the given one is older.
+    ///
+    /// # Arguments
+    /// * `key`: The edge to create.
+    /// * `update_datetime`: The edge's update datetime.
+    pub fn create_edge_with_datetime(&mut self, key: &models::EdgeKey, update_datetime: DateTime<Utc>) {
+        self.operations
+            .push(Operation::CreateEdge(key.clone(), Some(update_datetime)));
+    }
+
+    /// Queues the deletion of an edge, along with its properties. This does
+    /// nothing if the edge doesn't exist.
+    ///
+    /// # Arguments
+    /// * `key`: The edge to delete.
+    pub fn delete_edge(&mut self, key: &models::EdgeKey) {
+        self.operations.push(Operation::DeleteEdge(key.clone()));
+    }
+
+    /// Queues setting a vertex property.
+    ///
+    /// # Arguments
+    /// * `id`: The ID of the vertex.
+    /// * `name`: The property name.
+    /// * `value`: The property value.
+    pub fn set_vertex_property(&mut self, id: Uuid, name: &str, value: &JsonValue) {
+        self.operations
+            .push(Operation::SetVertexProperty(id, name.to_string(), value.clone()));
+    }
+
+    /// Queues deleting a vertex property.
+    ///
+    /// # Arguments
+    /// * `id`: The ID of the vertex.
+    /// * `name`: The property name.
+    pub fn delete_vertex_property(&mut self, id: Uuid, name: &str) {
+        self.operations
+            .push(Operation::DeleteVertexProperty(id, name.to_string()));
+    }
+
+    /// Queues setting an edge property.
+    ///
+    /// # Arguments
+    /// * `key`: The edge.
+    /// * `name`: The property name.
+    /// * `value`: The property value.
+    pub fn set_edge_property(&mut self, key: &models::EdgeKey, name: &str, value: &JsonValue) {
+        self.operations
+            .push(Operation::SetEdgeProperty(key.clone(), name.to_string(), value.clone()));
+    }
+
+    /// Queues deleting an edge property.
+    ///
+    /// # Arguments
+    /// * `key`: The edge.
+    /// * `name`: The property name.
+    pub fn delete_edge_property(&mut self, key: &models::EdgeKey, name: &str) {
+        self.operations
+            .push(Operation::DeleteEdgeProperty(key.clone(), name.to_string()));
+    }
+
+    /// Marks the current point in the batch, so that operations queued after
+    /// it can be rolled back. Savepoints can be nested.
+    pub fn savepoint(&mut self) -> Savepoint {
+        self.savepoints.push(self.operations.len());
+        Savepoint(self.savepoints.len() - 1)
+    }
+
+    /// Discards all operations queued since a savepoint. The savepoint, and
+    /// any savepoints made after it, are released.
+    ///
+    /// # Arguments
+    /// * `savepoint`: The savepoint to roll back to.
+    ///
+    /// # Errors
+    /// Returns an error if the savepoint has already been rolled back or
+    /// released.
+    pub fn rollback_to(&mut self, savepoint: Savepoint) -> Result<()> {
+        if savepoint.0 >= self.savepoints.len() {
+            return Err(ValidationError::from("Savepoint has already been rolled back or released").into());
+        }
+
+        let operations_len = self.savepoints[savepoint.0];
+        self.operations.truncate(operations_len);
+        self.savepoints.truncate(savepoint.0);
+        Ok(())
+    }
+
+    /// Releases a savepoint, keeping the operations queued since it. Any
+    /// savepoints made after it are released as well.
+    ///
+    /// # Arguments
+    /// * `savepoint`: The savepoint to release.
+    ///
+    /// # Errors
+    /// Returns an error if the savepoint has already been rolled back or
+    /// released.
+    pub fn release(&mut self, savepoint: Savepoint) -> Result<()> {
+        if savepoint.0 >= self.savepoints.len() {
+            return Err(ValidationError::from("Savepoint has already been rolled back or released").into());
+        }
+
+        self.savepoints.truncate(savepoint.0);
+        Ok(())
+    }
+
+    /// Atomically applies all of the queued operations.
+    pub fn commit(self) -> Result<()> {
+        self.commit_with_options(CommitOptions::default())
+    }
+
+    /// Atomically applies all of the queued operations, committing them with
+    /// the given options rather than the rocksdb defaults.
+    ///
+    /// # Arguments
+    /// * `options`: The options to commit with.
+    pub fn commit_with_options(self, options: CommitOptions) -> Result<()> {
+        let opts = options.to_write_options()?;
+        let mut writer = BatchWriter::new(
+            self.db.clone(),
+            self.clock.clone(),
+            self.edge_log,
+            self.neighbor_sketch_window,
+            self.adjacency_bitmaps.clone(),
+            self.cold_properties.clone(),
+            self.history.clone(),
+        );
+
+        for operation in self.operations {
+            writer.apply(operation)?;
+        }
+
+        self.db.write_opt(writer.batch, &opts)?;
+        Ok(())
+    }
+
+    pub(crate) fn into_parts(self) -> (Arc<DB>, Vec<Operation>) {
+        (self.db, self.operations)
+    }
+}
+
+pub(crate) struct BatchWriter {
+    db: Arc<DB>,
+    clock: Arc<dyn Clock>,
+    edge_log: bool,
+    neighbor_sketch_window: Option<Duration>,
+    adjacency_bitmaps: Option<Arc<AdjacencyBitmapSettings>>,
+    cold_properties: Arc<HashSet<String>>,
+    history: Option<Arc<HistorySettings>>,
+    pub(crate) batch: WriteBatch,
+    // The update datetimes of edges written to this batch, or `None` for
+    // edges deleted in this batch.
+    edges: HashMap<models::EdgeKey, Option<DateTime<Utc>>>,
+}
+
+impl BatchWriter {
+    pub(crate) fn new(
+        db: Arc<DB>,
+        clock: Arc<dyn Clock>,
+        edge_log: bool,
+        neighbor_sketch_window: Option<Duration>,
+        adjacency_bitmaps: Option<Arc<AdjacencyBitmapSettings>>,
+        cold_properties: Arc<HashSet<String>>,
+        history: Option<Arc<HistorySettings>>,
+    ) -> Self {
+        BatchWriter {
+            db,
+            clock,
+            edge_log,
+            neighbor_sketch_window,
+            adjacency_bitmaps,
+            cold_properties,
+            history,
+            batch: WriteBatch::default(),
+            edges: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn apply(&mut self, operation: Operation) -> Result<()> {
+        match operation {
+            Operation::CreateVertex(vertex) => {
+                let vertex_manager = VertexManager::new(self.db.clone())?.with_history(self.history.clone());
+                vertex_manager.create(&mut self.batch, &vertex)
+            }
+            Operation::DeleteVertex(id) => self.delete_vertex(id),
+            Operation::CreateEdge(key, update_datetime) => self.create_edge(key, update_datetime),
+            Operation::DeleteEdge(key) => self.delete_edge(key),
+            Operation::SetVertexProperty(id, name, value) => {
+                let manager = VertexPropertyManager::new(self.db.clone())?
+                    .with_cold_names(self.cold_properties.clone())
+                    .with_history(self.history.clone());
+                manager.set(&mut self.batch, id, &name, &value)
+            }
+            Operation::DeleteVertexProperty(id, name) => {
+                let manager = VertexPropertyManager::new(self.db.clone())?
+                    .with_cold_names(self.cold_properties.clone())
+                    .with_history(self.history.clone());
+                manager.delete(&mut self.batch, id, &name)
+            }
+            Operation::SetEdgeProperty(key, name, value) => {
+                let manager = EdgePropertyManager::new(self.db.clone())?
+                    .with_cold_names(self.cold_properties.clone())
+                    .with_history(self.history.clone());
+                manager.set(&mut self.batch, key.outbound_id, &key.t, key.inbound_id, &name, &value)
+            }
+            Operation::DeleteEdgeProperty(key, name) => {
+                let manager = EdgePropertyManager::new(self.db.clone())?
+                    .with_cold_names(self.cold_properties.clone())
+                    .with_history(self.history.clone());
+                manager.delete(&mut self.batch, key.outbound_id, &key.t, key.inbound_id, &name)
+            }
+        }
+    }
+
+    fn delete_vertex(&mut self, id: Uuid) -> Result<()> {
+        let keys: Vec<models::EdgeKey> = self
+            .edges
+            .keys()
+            .filter(|key| key.outbound_id == id || key.inbound_id == id)
+            .cloned()
+            .collect();
+
+        for key in keys {
+            self.delete_edge(key)?;
+        }
+
+        let vertex_manager = VertexManager::new(self.db.clone())?.with_history(self.history.clone());
+        vertex_manager.delete(&mut self.batch, id)
+    }
+
+    fn create_edge(&mut self, key: models::EdgeKey, update_datetime: Option<DateTime<Utc>>) -> Result<()> {
+        let edge_manager = EdgeManager::new(self.db.clone())?
+            .with_log(self.edge_log)
+            .with_neighbor_sketches(self.neighbor_sketch_window)
+            .with_adjacency_bitmaps(self.adjacency_bitmaps.clone())
+            .with_history(self.history.clone());
+
+        // `EdgeManager::set` only cleans up the edge range entries that are
+        // already in the datastore, so entries for an edge written earlier
+        // in this batch have to be cleaned up here.
+        if let Some(&Some(update_datetime)) = self.edges.get(&key) {
+            edge_manager.delete_ranges(
+                &mut self.batch,
+                key.outbound_id,
+                &key.t,
+                key.inbound_id,
+                update_datetime,
+            )?;
+        }
+
+        let update_datetime = update_datetime.unwrap_or_else(|| self.clock.now());
+        edge_manager.set(
+            &mut self.batch,
+            key.outbound_id,
+            &key.t,
+            key.inbound_id,
+            update_datetime,
+        )?;
+        self.edges.insert(key, Some(update_datetime));
+        Ok(())
+    }
+
+    fn delete_edge(&mut self, key: models::EdgeKey) -> Result<()> {
+        let edge_manager = EdgeManager::new(self.db.clone())?
+            .with_adjacency_bitmaps(self.adjacency_bitmaps.clone())
+            .with_history(self.history.clone());
+
+        let update_datetime = match self.edges.get(&key) {
+            Some(update_datetime) => *update_datetime,
+            None => edge_manager.get(key.outbound_id, &key.t, key.inbound_id)?,
+        };
+
+        if let Some(update_datetime) = update_datetime {
+            edge_manager.delete(
+                &mut self.batch,
+                key.outbound_id,
+                &key.t,
+                key.inbound_id,
+                update_datetime,
+            )?;
+        }
+
+        self.edges.insert(key, None);
+        Ok(())
+    }
+}
@@ -1,30 +1,57 @@
-use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+//! Encodes and decodes the keys and values stored in rocksdb. Keys are
+//! built from components, and sort bytewise in the order documented for
+//! each component.
+
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt, WriteBytesExt};
 use chrono::offset::Utc;
 use chrono::{DateTime, NaiveDateTime};
 use chrono::{Duration, Timelike};
+use errors::{ErrorKind, Result};
 use models;
+use roaring::RoaringBitmap;
+use serde_json::Value as JsonValue;
 use std::i32;
 use std::i64;
 use std::io::Read;
 use std::io::Write;
-use std::io::{Cursor, Error as IoError};
-use std::str;
+use std::io::{Cursor, Result as IoResult};
 use std::u8;
 use util::nanos_since_epoch;
 use uuid::Uuid;
 
 lazy_static! {
+    /// The latest datetime that can be encoded.
     pub static ref MAX_DATETIME: DateTime<Utc> =
         DateTime::from_utc(NaiveDateTime::from_timestamp(i64::from(i32::MAX), 0), Utc)
             .with_nanosecond(1_999_999_999u32)
             .unwrap();
 }
 
+/// A part of a key or value.
 pub enum Component<'a> {
+    /// A uuid, as its 16 bytes.
     Uuid(Uuid),
+
+    /// A string without a length prefix, so it can only be the last
+    /// component.
     UnsizedString(&'a str),
+
+    /// A string of at most 255 bytes, prefixed with its length.
+    SizedString(&'a str),
+
+    /// A string of any length, prefixed with its length as four big-endian
+    /// bytes.
+    LongSizedString(&'a str),
+
+    /// A type, encoded like a `SizedString`.
     Type(&'a models::Type),
+
+    /// A datetime, encoded as the nanoseconds until `MAX_DATETIME`, so
+    /// later datetimes sort first.
     DateTime(DateTime<Utc>),
+
+    /// Raw bytes, which aren't delimited.
+    Bytes(&'a [u8]),
 }
 
 impl<'a> Component<'a> {
@@ -32,12 +59,15 @@ impl<'a> Component<'a> {
         match *self {
             Component::Uuid(_) => 16,
             Component::UnsizedString(s) => s.len(),
+            Component::SizedString(s) => s.len() + 1,
+            Component::LongSizedString(s) => s.len() + 4,
             Component::Type(t) => t.0.len() + 1,
             Component::DateTime(_) => 8,
+            Component::Bytes(b) => b.len(),
         }
     }
 
-    fn write(&self, cursor: &mut Cursor<Vec<u8>>) -> Result<(), IoError> {
+    fn write(&self, cursor: &mut Cursor<Vec<u8>>) -> IoResult<()> {
         match *self {
             Component::Uuid(uuid) => {
                 cursor.write_all(uuid.as_bytes())?;
@@ -45,6 +75,16 @@ impl<'a> Component<'a> {
             Component::UnsizedString(s) => {
                 cursor.write_all(s.as_bytes())?;
             }
+            Component::SizedString(s) => {
+                debug_assert!(s.len() <= u8::MAX as usize);
+                cursor.write_all(&[s.len() as u8])?;
+                cursor.write_all(s.as_bytes())?;
+            }
+            Component::LongSizedString(s) => {
+                debug_assert!(s.len() <= u32::MAX as usize);
+                cursor.write_u32::<BigEndian>(s.len() as u32)?;
+                cursor.write_all(s.as_bytes())?;
+            }
             Component::Type(t) => {
                 cursor.write_all(&[t.0.len() as u8])?;
                 cursor.write_all(t.0.as_bytes())?;
@@ -53,12 +93,16 @@ impl<'a> Component<'a> {
                 let time_to_end = nanos_since_epoch(&MAX_DATETIME) - nanos_since_epoch(&datetime);
                 cursor.write_u64::<BigEndian>(time_to_end)?;
             }
+            Component::Bytes(b) => {
+                cursor.write_all(b)?;
+            }
         };
 
         Ok(())
     }
 }
 
+/// Builds a key or value from its components.
 pub fn build(components: &[Component]) -> Vec<u8> {
     let len = components.iter().fold(0, |len, component| len + component.len());
     let mut cursor: Cursor<Vec<u8>> = Cursor::new(Vec::with_capacity(len));
@@ -72,36 +116,397 @@ pub fn build(components: &[Component]) -> Vec<u8> {
     cursor.into_inner()
 }
 
-pub fn read_uuid<T: AsRef<[u8]>>(cursor: &mut Cursor<T>) -> Uuid {
+// Type tags for encoded index values, which order values of different JSON
+// types relative to each other.
+const NULL_TAG: u8 = 1;
+const BOOL_TAG: u8 = 2;
+const NUMBER_TAG: u8 = 3;
+const STRING_TAG: u8 = 4;
+const ARRAY_TAG: u8 = 5;
+const OBJECT_TAG: u8 = 6;
+const DATETIME_TAG: u8 = 7;
+
+/// Encodes an `f64` so that the encodings of any two numbers sort the same
+/// way, bytewise, as the numbers themselves.
+pub fn encode_sortable_f64(value: f64) -> [u8; 8] {
+    // Normalize negative zero, so it's equal to positive zero
+    let value = if value == 0.0 { 0.0 } else { value };
+    let bits = value.to_bits();
+
+    // Flipping the sign bit orders positive numbers after negative ones,
+    // and flipping every bit of negative numbers orders them by descending
+    // magnitude
+    let sortable = if bits >> 63 == 0 { bits ^ (1 << 63) } else { !bits };
+
+    let mut bytes = [0; 8];
+    BigEndian::write_u64(&mut bytes, sortable);
+    bytes
+}
+
+/// Encodes an `i64` so that the encodings of any two numbers sort the same
+/// way, bytewise, as the numbers themselves.
+pub fn encode_sortable_i64(value: i64) -> [u8; 8] {
+    // Flipping the sign bit orders negative numbers before positive ones
+    let mut bytes = [0; 8];
+    BigEndian::write_u64(&mut bytes, (value as u64) ^ (1 << 63));
+    bytes
+}
+
+/// Encodes a property value for use in an index key. Encoded values are
+/// self-delimiting, so keys built from multiple values sort by the first
+/// value, then the second, and so on.
+///
+/// Values sort by type first: null, then booleans, numbers, strings, arrays,
+/// objects and datetimes. Within a type, numbers sort numerically, strings
+/// sort bytewise and datetimes sort chronologically, so ranges over any of
+/// them can be scanned. All numbers are encoded as `f64`s, so integers
+/// beyond 2^53 can tie with their neighbors. Arrays and objects are only
+/// encoded so that equality lookups work.
+pub fn encode_index_value(value: &JsonValue) -> Vec<u8> {
+    if let Some(models::DateTimeValue(datetime)) = models::DateTimeValue::from_json(value) {
+        let mut bytes = Vec::with_capacity(13);
+        bytes.push(DATETIME_TAG);
+        bytes.extend_from_slice(&encode_sortable_i64(datetime.timestamp()));
+        let mut nanos = [0; 4];
+        BigEndian::write_u32(&mut nanos, datetime.timestamp_subsec_nanos());
+        bytes.extend_from_slice(&nanos);
+        return bytes;
+    }
+
+    match *value {
+        JsonValue::Null => vec![NULL_TAG],
+        JsonValue::Bool(value) => vec![BOOL_TAG, value as u8],
+        JsonValue::Number(ref value) => {
+            let mut bytes = vec![NUMBER_TAG];
+            // This is always `Some` unless serde_json's arbitrary precision
+            // feature is enabled
+            bytes.extend_from_slice(&encode_sortable_f64(value.as_f64().unwrap()));
+            bytes
+        }
+        JsonValue::String(ref value) => {
+            // NUL bytes are escaped, so that a double NUL can terminate the
+            // string while still sorting before any longer string
+            let mut bytes = Vec::with_capacity(value.len() + 3);
+            bytes.push(STRING_TAG);
+
+            for &b in value.as_bytes() {
+                bytes.push(b);
+
+                if b == 0 {
+                    bytes.push(0xFF);
+                }
+            }
+
+            bytes.extend_from_slice(&[0, 0]);
+            bytes
+        }
+        JsonValue::Array(_) | JsonValue::Object(_) => {
+            let tag = if value.is_array() { ARRAY_TAG } else { OBJECT_TAG };
+            let mut bytes = vec![tag];
+
+            // Serialized JSON never contains a NUL byte, since control
+            // characters in strings are escaped, so it can be used as a
+            // terminator
+            bytes.extend(value.to_string().into_bytes());
+            bytes.push(0);
+            bytes
+        }
+    }
+}
+
+/// Gets the smallest key that's greater than every key starting with
+/// `prefix`, or `None` if there isn't one (i.e. if the prefix is all `0xFF`
+/// bytes.)
+pub fn prefix_successor(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut successor = prefix.to_vec();
+
+    while let Some(last) = successor.pop() {
+        if last < 0xFF {
+            successor.push(last + 1);
+            return Some(successor);
+        }
+    }
+
+    None
+}
+
+/// Builds a counter value, which is a big-endian `i64`.
+pub fn build_counter(value: i64) -> Vec<u8> {
+    let mut buf = vec![0u8; 8];
+    BigEndian::write_i64(&mut buf, value);
+    buf
+}
+
+/// Builds a sketch update, which raises one register of a HyperLogLog
+/// sketch: the sketch's precision, then the register's index as a
+/// big-endian `u16`, then the rank to raise it to.
+pub fn build_sketch_update(precision: u8, index: usize, rank: u8) -> Vec<u8> {
+    let mut buf = vec![precision, 0, 0, rank];
+    BigEndian::write_u16(&mut buf[1..3], index as u16);
+    buf
+}
+
+/// An update to an adjacency bitmap, which is merged into the stored
+/// bitmap. Updates to a bitmap that doesn't exist are ignored, other than
+/// `Seed`, which creates it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BitmapUpdate {
+    /// Adds a neighbor id.
+    Insert(u32),
+    /// Removes a neighbor id.
+    Remove(u32),
+    /// Adds every neighbor id in a bitmap, creating the stored bitmap if it
+    /// doesn't exist.
+    Seed(RoaringBitmap),
+}
+
+const BITMAP_INSERT_TAG: u8 = 1;
+const BITMAP_REMOVE_TAG: u8 = 2;
+const BITMAP_SEED_TAG: u8 = 3;
+
+/// Builds a bitmap update: a tag byte, followed by the neighbor id as a
+/// big-endian `u32`, or by the serialized bitmap for a `Seed`.
+pub fn build_bitmap_update(update: &BitmapUpdate) -> Vec<u8> {
+    let (tag, neighbor_id) = match *update {
+        BitmapUpdate::Insert(neighbor_id) => (BITMAP_INSERT_TAG, neighbor_id),
+        BitmapUpdate::Remove(neighbor_id) => (BITMAP_REMOVE_TAG, neighbor_id),
+        BitmapUpdate::Seed(ref bitmap) => {
+            let mut buf = vec![BITMAP_SEED_TAG];
+            buf.extend(build_bitmap(bitmap));
+            return buf;
+        }
+    };
+
+    let mut buf = vec![tag, 0, 0, 0, 0];
+    BigEndian::write_u32(&mut buf[1..], neighbor_id);
+    buf
+}
+
+/// Builds a stored adjacency bitmap, in the portable roaring format.
+pub fn build_bitmap(bitmap: &RoaringBitmap) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(bitmap.serialized_size());
+
+    if let Err(err) = bitmap.serialize_into(&mut buf) {
+        panic!("Could not write bytes: {}", err);
+    }
+
+    buf
+}
+
+/// Builds a neighbor id, which is a big-endian `u32` so that ids sort in
+/// the order they're allocated.
+pub fn build_neighbor_id(neighbor_id: u32) -> Vec<u8> {
+    let mut buf = vec![0u8; 4];
+    BigEndian::write_u32(&mut buf, neighbor_id);
+    buf
+}
+
+/// Builds a stored property value from its JSON-encoded bytes and an
+/// optional expiry datetime. Values with an expiry are prefixed with a NUL
+/// byte, which JSON never starts with, followed by the expiry.
+pub fn build_property_value(value_json: Vec<u8>, expires_at: Option<DateTime<Utc>>) -> Vec<u8> {
+    match expires_at {
+        Some(expires_at) => {
+            let mut value = build(&[Component::Bytes(&[0]), Component::DateTime(expires_at)]);
+            value.extend(value_json);
+            value
+        }
+        None => value_json,
+    }
+}
+
+/// Reads the JSON-encoded bytes of a stored property value, or `None` if
+/// the value had expired as of `now`.
+pub fn read_property_value(value: &[u8], now: DateTime<Utc>) -> Result<Option<&[u8]>> {
+    // Values with an expiry start with a NUL byte, which JSON never does
+    if value.len() > 9 && value[0] == 0 {
+        let mut cursor = Cursor::new(&value[1..9]);
+
+        if read_datetime(&mut cursor)? <= now {
+            Ok(None)
+        } else {
+            Ok(Some(&value[9..]))
+        }
+    } else {
+        Ok(Some(value))
+    }
+}
+
+// Flags for the stored versions of items in the history.
+const HISTORY_EXPIRES_FLAG: u8 = 1;
+const HISTORY_VALUE_FLAG: u8 = 2;
+
+/// Builds a stored version of an item in the history: a flags byte,
+/// followed by the datetime the version falls out of the retention window,
+/// if it has one yet, and then the item's value, unless the version records
+/// the item's deletion.
+pub fn build_history_version(expires_at: Option<DateTime<Utc>>, value: Option<&[u8]>) -> Vec<u8> {
+    let mut buf = vec![0u8];
+
+    if let Some(expires_at) = expires_at {
+        buf[0] |= HISTORY_EXPIRES_FLAG;
+        buf.extend(build(&[Component::DateTime(expires_at)]));
+    }
+
+    if let Some(value) = value {
+        buf[0] |= HISTORY_VALUE_FLAG;
+        buf.extend_from_slice(value);
+    }
+
+    buf
+}
+
+/// A stored version of an item in the history: the datetime it falls out of
+/// the retention window, if it has one yet, and the item's value, or `None`
+/// if the version records the item's deletion.
+pub type HistoryVersion<'a> = (Option<DateTime<Utc>>, Option<&'a [u8]>);
+
+/// Reads a stored version built by `build_history_version`.
+pub fn read_history_version(value: &[u8]) -> Result<HistoryVersion<'_>> {
+    let flags = match value.first() {
+        Some(&flags) if flags & !(HISTORY_EXPIRES_FLAG | HISTORY_VALUE_FLAG) == 0 => flags,
+        _ => return Err(ErrorKind::Corrupt("invalid history version".to_string()).into()),
+    };
+
+    let mut rest = &value[1..];
+
+    let expires_at = if flags & HISTORY_EXPIRES_FLAG != 0 {
+        if rest.len() < 8 {
+            return Err(ErrorKind::Corrupt("truncated datetime".to_string()).into());
+        }
+
+        let expires_at = read_datetime(&mut Cursor::new(&rest[..8]))?;
+        rest = &rest[8..];
+        Some(expires_at)
+    } else {
+        None
+    };
+
+    if flags & HISTORY_VALUE_FLAG != 0 {
+        Ok((expires_at, Some(rest)))
+    } else if rest.is_empty() {
+        Ok((expires_at, None))
+    } else {
+        Err(ErrorKind::Corrupt("history deletion has a value".to_string()).into())
+    }
+}
+
+fn read_exact<T: AsRef<[u8]>>(cursor: &mut Cursor<T>, buf: &mut [u8], what: &str) -> Result<()> {
+    cursor
+        .read_exact(buf)
+        .map_err(|_| ErrorKind::Corrupt(format!("truncated {}", what)).into())
+}
+
+/// Reads a `Component::Uuid`.
+pub fn read_uuid<T: AsRef<[u8]>>(cursor: &mut Cursor<T>) -> Result<Uuid> {
     let mut buf: [u8; 16] = [0; 16];
-    cursor.read_exact(&mut buf).unwrap();
-    Uuid::from_slice(&buf).unwrap()
+    read_exact(cursor, &mut buf, "uuid")?;
+    Uuid::from_slice(&buf).map_err(|_| ErrorKind::Corrupt("invalid uuid".to_string()).into())
 }
 
-pub fn read_type<T: AsRef<[u8]>>(cursor: &mut Cursor<T>) -> models::Type {
-    let t_len = {
+/// Reads a `Component::SizedString`.
+pub fn read_sized_string<T: AsRef<[u8]>>(cursor: &mut Cursor<T>) -> Result<String> {
+    let len = {
         let mut buf: [u8; 1] = [0; 1];
-        cursor.read_exact(&mut buf).unwrap();
+        read_exact(cursor, &mut buf, "string")?;
         buf[0] as usize
     };
 
-    let mut buf = vec![0u8; t_len];
-    cursor.read_exact(&mut buf).unwrap();
+    let mut buf = vec![0u8; len];
+    read_exact(cursor, &mut buf, "string")?;
+    String::from_utf8(buf).map_err(|_| ErrorKind::Corrupt("string isn't UTF-8".to_string()).into())
+}
+
+/// Reads a `Component::LongSizedString`.
+pub fn read_long_sized_string<T: AsRef<[u8]>>(cursor: &mut Cursor<T>) -> Result<String> {
+    let len = cursor
+        .read_u32::<BigEndian>()
+        .map_err(|_| ErrorKind::Corrupt("truncated string".to_string()))? as usize;
+
+    // Checks the length before allocating, in case it's corrupt
+    let remaining = cursor
+        .get_ref()
+        .as_ref()
+        .len()
+        .saturating_sub(cursor.position() as usize);
 
-    unsafe {
-        let s = str::from_utf8_unchecked(&buf).to_string();
-        models::Type::new_unchecked(s)
+    if len > remaining {
+        return Err(ErrorKind::Corrupt("truncated string".to_string()).into());
     }
+
+    let mut buf = vec![0u8; len];
+    read_exact(cursor, &mut buf, "string")?;
+    String::from_utf8(buf).map_err(|_| ErrorKind::Corrupt("string isn't UTF-8".to_string()).into())
 }
 
-pub fn read_unsized_string<T: AsRef<[u8]>>(cursor: &mut Cursor<T>) -> String {
+/// Reads a `Component::Type`.
+pub fn read_type<T: AsRef<[u8]>>(cursor: &mut Cursor<T>) -> Result<models::Type> {
+    let s = read_sized_string(cursor)?;
+
+    // The type was validated when it was written, and checking it again on
+    // every read would be expensive
+    unsafe { Ok(models::Type::new_unchecked(s)) }
+}
+
+/// Reads a `Component::UnsizedString`, which is the rest of the bytes.
+pub fn read_unsized_string<T: AsRef<[u8]>>(cursor: &mut Cursor<T>) -> Result<String> {
     let mut buf = String::new();
-    cursor.read_to_string(&mut buf).unwrap();
-    buf
+    cursor
+        .read_to_string(&mut buf)
+        .map_err(|_| ErrorKind::Corrupt("string isn't UTF-8".to_string()))?;
+    Ok(buf)
 }
 
-pub fn read_datetime<T: AsRef<[u8]>>(cursor: &mut Cursor<T>) -> DateTime<Utc> {
-    let time_to_end = cursor.read_u64::<BigEndian>().unwrap();
-    assert!(time_to_end <= i64::MAX as u64);
-    *MAX_DATETIME - Duration::nanoseconds(time_to_end as i64)
+/// Reads a `Component::DateTime`.
+pub fn read_datetime<T: AsRef<[u8]>>(cursor: &mut Cursor<T>) -> Result<DateTime<Utc>> {
+    let time_to_end = cursor
+        .read_u64::<BigEndian>()
+        .map_err(|_| ErrorKind::Corrupt("truncated datetime".to_string()))?;
+
+    if time_to_end > i64::MAX as u64 {
+        return Err(ErrorKind::Corrupt("datetime out of range".to_string()).into());
+    }
+
+    Ok(*MAX_DATETIME - Duration::nanoseconds(time_to_end as i64))
+}
+
+/// Reads a counter value.
+pub fn read_counter<T: AsRef<[u8]>>(cursor: &mut Cursor<T>) -> Result<i64> {
+    cursor
+        .read_i64::<BigEndian>()
+        .map_err(|_| ErrorKind::Corrupt("truncated counter".to_string()).into())
+}
+
+/// Reads a sketch update built by `build_sketch_update`, returning its
+/// precision, register index and rank.
+pub fn read_sketch_update(value: &[u8]) -> Result<(u8, usize, u8)> {
+    if value.len() != 4 {
+        return Err(ErrorKind::Corrupt("invalid sketch update".to_string()).into());
+    }
+
+    Ok((value[0], BigEndian::read_u16(&value[1..3]) as usize, value[3]))
+}
+
+/// Reads a bitmap update built by `build_bitmap_update`.
+pub fn read_bitmap_update(value: &[u8]) -> Result<BitmapUpdate> {
+    match (value.first(), value.len()) {
+        (Some(&BITMAP_INSERT_TAG), 5) => Ok(BitmapUpdate::Insert(BigEndian::read_u32(&value[1..]))),
+        (Some(&BITMAP_REMOVE_TAG), 5) => Ok(BitmapUpdate::Remove(BigEndian::read_u32(&value[1..]))),
+        (Some(&BITMAP_SEED_TAG), _) => Ok(BitmapUpdate::Seed(read_bitmap(&value[1..])?)),
+        _ => Err(ErrorKind::Corrupt("invalid bitmap update".to_string()).into()),
+    }
+}
+
+/// Reads a stored adjacency bitmap built by `build_bitmap`.
+pub fn read_bitmap(value: &[u8]) -> Result<RoaringBitmap> {
+    RoaringBitmap::deserialize_from(value).map_err(|_| ErrorKind::Corrupt("invalid bitmap".to_string()).into())
+}
+
+/// Reads a neighbor id built by `build_neighbor_id`.
+pub fn read_neighbor_id(value: &[u8]) -> Result<u32> {
+    if value.len() != 4 {
+        return Err(ErrorKind::Corrupt("invalid neighbor id".to_string()).into());
+    }
+
+    Ok(BigEndian::read_u32(value))
 }
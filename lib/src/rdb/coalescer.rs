@@ -0,0 +1,246 @@
+use super::batch::{BatchTransaction, BatchWriter, Operation};
+use super::datastore::CommitOptions;
+use super::managers::{AdjacencyBitmapSettings, HistorySettings};
+use clock::Clock;
+use errors::{Result, ValidationError};
+use rocksdb::DB;
+use std::collections::HashSet;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Controls how a `WriteCoalescer` groups writes, trading latency for
+/// throughput.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CoalesceOptions {
+    /// The number of operations after which a group is flushed, without
+    /// waiting for more writes.
+    pub max_batch_operations: usize,
+
+    /// How long a group waits for more writes after its first one, before
+    /// it's flushed. This bounds the latency that coalescing adds to each
+    /// write.
+    pub max_delay: Duration,
+
+    /// The options that each group is committed with.
+    pub commit: CommitOptions,
+}
+
+impl Default for CoalesceOptions {
+    fn default() -> Self {
+        CoalesceOptions {
+            max_batch_operations: 1000,
+            max_delay: Duration::from_millis(5),
+            commit: CommitOptions::default(),
+        }
+    }
+}
+
+impl CoalesceOptions {
+    /// Creates new coalesce options, which flush groups after 1000
+    /// operations or 5 milliseconds, and commit them with the rocksdb
+    /// defaults.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the number of operations after which a group is flushed.
+    ///
+    /// # Arguments
+    /// * `max_batch_operations` - The number of operations.
+    pub fn max_batch_operations(self, max_batch_operations: usize) -> Self {
+        CoalesceOptions {
+            max_batch_operations,
+            ..self
+        }
+    }
+
+    /// Sets how long a group waits for more writes before it's flushed.
+    ///
+    /// # Arguments
+    /// * `max_delay` - How long to wait.
+    pub fn max_delay(self, max_delay: Duration) -> Self {
+        CoalesceOptions { max_delay, ..self }
+    }
+
+    /// Sets the options that each group is committed with.
+    ///
+    /// # Arguments
+    /// * `commit` - The commit options.
+    pub fn commit(self, commit: CommitOptions) -> Self {
+        CoalesceOptions { commit, ..self }
+    }
+}
+
+// A batch waiting to be flushed, along with where to send the result.
+struct Submission {
+    operations: Vec<Operation>,
+    // Errors are sent as strings, since every writer in a group gets the
+    // same one.
+    reply: Sender<::std::result::Result<(), String>>,
+}
+
+/// Groups the batches written by many concurrent callers into shared
+/// `WriteBatch`es, so that many small writes cost one commit rather than
+/// one each. A group is flushed once it has `max_batch_operations`
+/// operations, or once `max_delay` has passed since its first write, and
+/// each `write` blocks until its group has been committed.
+///
+/// The batches in a group are applied in the order they were written, as if
+/// they were one `BatchTransaction`; so the same caveats about reads and
+/// properties apply across the batches in a group. Groups commit
+/// atomically, so if one fails, every write in it fails with the same
+/// error.
+///
+/// Coalescers are cheap to clone, and clones share the same groups. The
+/// datastore stays open until every clone has been dropped.
+#[derive(Clone)]
+pub struct WriteCoalescer {
+    db: Arc<DB>,
+    submissions: Arc<Mutex<Sender<Submission>>>,
+}
+
+impl WriteCoalescer {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        db: Arc<DB>,
+        clock: Arc<dyn Clock>,
+        edge_log: bool,
+        neighbor_sketch_window: Option<Duration>,
+        adjacency_bitmaps: Option<Arc<AdjacencyBitmapSettings>>,
+        cold_properties: Arc<HashSet<String>>,
+        history: Option<Arc<HistorySettings>>,
+        options: CoalesceOptions,
+    ) -> Result<Self> {
+        if options.max_batch_operations == 0 {
+            return Err(ValidationError::from("Max batch operations must be greater than zero").into());
+        }
+
+        // Validate the options up-front rather than on the first flush
+        options.commit.to_write_options()?;
+
+        let (sender, receiver) = channel();
+        let thread_db = db.clone();
+        thread::spawn(move || {
+            run_coalescer(
+                &thread_db,
+                &clock,
+                edge_log,
+                neighbor_sketch_window,
+                &adjacency_bitmaps,
+                &cold_properties,
+                &history,
+                options,
+                &receiver,
+            )
+        });
+
+        Ok(WriteCoalescer {
+            db,
+            submissions: Arc::new(Mutex::new(sender)),
+        })
+    }
+
+    /// Writes a batch as part of the current group, blocking until the group
+    /// has been committed.
+    ///
+    /// # Arguments
+    /// * `batch` - The batch to write, which must have been created by the
+    ///   same datastore as this coalescer.
+    ///
+    /// # Errors
+    /// Returns a `ValidationError` if the batch is from another datastore,
+    /// or an error if the group it was written with failed.
+    pub fn write(&self, batch: BatchTransaction) -> Result<()> {
+        let (db, operations) = batch.into_parts();
+
+        if !Arc::ptr_eq(&db, &self.db) {
+            return Err(ValidationError::from("The batch is from another datastore").into());
+        }
+
+        if operations.is_empty() {
+            return Ok(());
+        }
+
+        let (reply, receiver) = channel();
+
+        // The flushing thread only exits once every sender has been dropped
+        let _ = self.submissions.lock().unwrap().send(Submission { operations, reply });
+
+        match receiver.recv() {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(message)) => Err(format!("The coalesced write failed: {}", message).into()),
+            Err(_) => Err("The write coalescer has stopped".into()),
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_coalescer(
+    db: &Arc<DB>,
+    clock: &Arc<dyn Clock>,
+    edge_log: bool,
+    neighbor_sketch_window: Option<Duration>,
+    adjacency_bitmaps: &Option<Arc<AdjacencyBitmapSettings>>,
+    cold_properties: &Arc<HashSet<String>>,
+    history: &Option<Arc<HistorySettings>>,
+    options: CoalesceOptions,
+    receiver: &Receiver<Submission>,
+) {
+    // Each group starts with the first write made after the last one was
+    // flushed
+    while let Ok(first) = receiver.recv() {
+        let deadline = Instant::now() + options.max_delay;
+        let mut operation_count = first.operations.len();
+        let mut group = vec![first];
+
+        while operation_count < options.max_batch_operations {
+            let now = Instant::now();
+
+            if now >= deadline {
+                break;
+            }
+
+            match receiver.recv_timeout(deadline - now) {
+                Ok(submission) => {
+                    operation_count += submission.operations.len();
+                    group.push(submission);
+                }
+                Err(_) => break,
+            }
+        }
+
+        let mut replies = Vec::with_capacity(group.len());
+        let mut writer = BatchWriter::new(
+            db.clone(),
+            clock.clone(),
+            edge_log,
+            neighbor_sketch_window,
+            adjacency_bitmaps.clone(),
+            cold_properties.clone(),
+            history.clone(),
+        );
+        let mut result = Ok(());
+
+        for submission in group {
+            replies.push(submission.reply);
+
+            for operation in submission.operations {
+                if result.is_ok() {
+                    result = writer.apply(operation);
+                }
+            }
+        }
+
+        let result = result
+            .and_then(|_| options.commit.to_write_options())
+            .and_then(|opts| Ok(db.write_opt(writer.batch, &opts)?))
+            .map_err(|err| err.to_string());
+
+        for reply in replies {
+            // The writer may have given up waiting, e.g. if its thread panicked
+            let _ = reply.send(result.clone());
+        }
+    }
+}
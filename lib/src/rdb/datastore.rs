@@ -1,29 +1,144 @@
 use super::super::{
     Datastore, EdgeDirection, EdgePropertyQuery, EdgeQuery, Transaction, VertexPropertyQuery, VertexQuery,
 };
+use super::batch::BatchTransaction;
+use super::bytes::{
+    build, build_counter, read_datetime, read_history_version, read_property_value, read_type, read_unsized_string,
+    read_uuid, Component,
+};
+use super::coalescer::{CoalesceOptions, WriteCoalescer};
+use super::engine::{cf_handle, merge_bitmaps, merge_counters, merge_sketches, KvReadOptions};
 use super::managers::*;
+use super::query::QueryRunner;
+use super::tuner::{AutoTuneOptions, Tuner, TuningChange};
+use super::verify::{check_entry, CorruptEntry};
+use super::workers::{BackgroundTaskMetrics, BackgroundWorkers};
 use chrono::offset::Utc;
-use errors::Result;
+use chrono::DateTime;
+use clock::{Clock, SystemClock};
+use errors::{Error, ErrorKind, Result, ValidationError};
+use events::EventBus;
+use hll::HyperLogLog;
+use memory::{MemoryDatastore, RetentionPolicy};
 use models;
-use rocksdb::{DBCompactionStyle, Options, WriteBatch, WriteOptions, DB};
+use roaring::RoaringBitmap;
+use rocksdb::checkpoint::Checkpoint;
+use rocksdb::{
+    BlockBasedOptions, ColumnFamilyDescriptor, CompactionDecision, DBCompactionStyle, DBCompressionType,
+    Error as RocksDbError, IteratorMode, Options, WriteBatch, WriteOptions, DB,
+};
 use serde_json::Value as JsonValue;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fs;
 use std::i32;
-use std::sync::Arc;
+use std::io::Cursor;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
 use std::u64;
 use std::usize;
-use util::next_uuid;
+use traits::missing_edge_vertices;
+use util::intersect_sorted;
 use uuid::Uuid;
 
-const CF_NAMES: [&str; 6] = [
+const CF_NAMES: [&str; 25] = [
     "vertices:v1",
     "edges:v1",
     "edge_ranges:v1",
     "reversed_edge_ranges:v1",
+    "edge_types:v1",
+    "edge_log:v1",
+    "vertex_properties:v1",
+    "edge_properties:v1",
+    "edge_property_names:v1",
+    "cold_vertex_properties:v1",
+    "cold_edge_properties:v1",
+    "index_definitions:v1",
+    "index_entries:v1",
+    "snapshot_tags:v1",
+    "vertex_type_counts:v1",
+    "edge_type_counts:v1",
+    "idempotency_keys:v1",
+    "inbound_neighbor_sketches:v1",
+    "adjacency_bitmaps:v1",
+    "reversed_adjacency_bitmaps:v1",
+    "adjacency_degrees:v1",
+    "reversed_adjacency_degrees:v1",
+    "neighbor_ids:v1",
+    "neighbor_id_vertices:v1",
+    "history:v1",
+];
+
+// The column families that adjacency bitmaps are stored in. See
+// `RocksdbDatastore::with_adjacency_bitmaps`.
+const ADJACENCY_BITMAP_CF_NAMES: [&str; 2] = ["adjacency_bitmaps:v1", "reversed_adjacency_bitmaps:v1"];
+
+// The column families whose values can expire.
+const EXPIRING_CF_NAMES: [&str; 5] = [
     "vertex_properties:v1",
     "edge_properties:v1",
+    "cold_vertex_properties:v1",
+    "cold_edge_properties:v1",
+    "idempotency_keys:v1",
 ];
 
-fn get_options(max_open_files: Option<i32>, bulk_load_optimized: bool) -> Options {
+// The column families that cold properties are stored in. See
+// `RocksdbDatastore::with_cold_properties`.
+const COLD_CF_NAMES: [&str; 2] = ["cold_vertex_properties:v1", "cold_edge_properties:v1"];
+
+// The column families with bloom filters, which point lookups check for
+// existence.
+const BLOOM_FILTER_CF_NAMES: [&str; 3] = ["vertices:v1", "edges:v1", "neighbor_ids:v1"];
+
+// The names of the built-in background tasks.
+const TOMBSTONE_TASK: &str = "tombstones";
+const EXPIRY_TASK: &str = "expired_values";
+const INDEX_BACKFILL_TASK: &str = "index_backfills";
+const AUTO_TUNE_TASK: &str = "auto_tuner";
+const CATCH_UP_TASK: &str = "catch_up";
+
+// How often the background tasks that work through queues check them. These
+// tasks are also woken whenever work is queued.
+const QUEUE_TASK_INTERVAL: Duration = Duration::from_secs(10);
+
+// How often expired values are swept.
+const EXPIRY_TASK_INTERVAL: Duration = Duration::from_secs(3600);
+
+// How often secondary datastores catch up with their primary.
+const CATCH_UP_TASK_INTERVAL: Duration = Duration::from_secs(1);
+
+// The maximum number of properties and edges the background deletion task
+// deletes per batch.
+const BACKGROUND_DELETION_CHUNK_SIZE: usize = 1000;
+
+// The number of vertices an index backfill indexes per batch.
+const INDEX_BACKFILL_CHUNK_SIZE: usize = 1000;
+
+// The number of index entries dropping an index deletes per batch.
+const INDEX_DROP_CHUNK_SIZE: usize = 1000;
+
+// The number of entries cloning copies per batch.
+const CLONE_CHUNK_SIZE: usize = 10_000;
+
+// The number of entries building the edge property name index writes per
+// batch.
+const NAME_INDEX_CHUNK_SIZE: usize = 10_000;
+
+// The number of entries building the edge type index writes per batch.
+const TYPE_INDEX_CHUNK_SIZE: usize = 10_000;
+
+// How often to retry opening a database that's locked by another process.
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+// The compaction and flush thread counts are set separately, rather than
+// being split from `max_background_jobs`, which rocksdb only does when
+// neither is set.
+#[allow(deprecated)]
+fn get_options(max_open_files: Option<i32>, bulk_load_optimized: bool, resources: ResourceOptions) -> Options {
     // Current tuning based off of the total ordered example, flash
     // storage example on
     // https://github.com/facebook/rocksdb/wiki/RocksDB-Tuning-Guide
@@ -46,6 +161,11 @@ fn get_options(max_open_files: Option<i32>, bulk_load_optimized: bool) -> Option
         opts.set_max_open_files(max_open_files);
     }
 
+    // Sums the type counts and adjacency degrees. Other column families
+    // that are written with merges set their own operator, so it's safe to
+    // set this one for all of them.
+    opts.set_merge_operator_associative("counters", merge_counters);
+
     if bulk_load_optimized {
         // Via https://github.com/facebook/rocksdb/wiki/RocksDB-FAQ
         opts.set_allow_concurrent_memtable_write(false);
@@ -57,297 +177,2699 @@ fn get_options(max_open_files: Option<i32>, bulk_load_optimized: bool) -> Option
         opts.set_level_zero_stop_writes_trigger(1024 * 6);
     }
 
+    if resources.rate_limit_bytes_per_sec > 0 {
+        // Refill every 100ms, with rocksdb's default fairness
+        opts.set_ratelimiter(resources.rate_limit_bytes_per_sec, 100_000, 10);
+    }
+
+    if resources.compaction_threads > 0 {
+        opts.set_max_background_compactions(resources.compaction_threads);
+    }
+
+    if resources.flush_threads > 0 {
+        opts.set_max_background_flushes(resources.flush_threads);
+    }
+
     opts
 }
 
-fn remove_nones_from_iterator<I, T>(iter: I) -> impl Iterator<Item = Result<T>>
-where
-    I: Iterator<Item = Result<Option<T>>>,
-{
-    iter.filter_map(|item| match item {
-        Err(err) => Some(Err(err)),
-        Ok(Some(value)) => Some(Ok(value)),
-        _ => None,
-    })
+// Gets the options for a column family. Cold properties are large and
+// rarely read, so they're compressed harder, in larger blocks, and kept out
+// of the block cache so they don't evict the hot working set. Vertices and
+// edges get bloom filters, so that checking for ones that don't exist, e.g.
+// to dedupe ingestion, usually doesn't read from disk, as do neighbor ids,
+// which are looked up for every edge that's deleted. Only the column
+// families whose values can expire are filtered on compaction, along with
+// the history, whose versions age out of the retention window. Inbound
+// neighbor sketches and adjacency bitmaps are written with merges of their
+// own kinds.
+fn get_cf_options(
+    cf_name: &str,
+    max_open_files: Option<i32>,
+    bulk_load_optimized: bool,
+    resources: ResourceOptions,
+) -> Options {
+    let mut opts = get_options(max_open_files, bulk_load_optimized, resources);
+
+    if COLD_CF_NAMES.contains(&cf_name) {
+        let mut block_opts = BlockBasedOptions::default();
+        block_opts.set_block_size(65_536); // 64kb
+        block_opts.disable_cache();
+        opts.set_block_based_table_factory(&block_opts);
+        opts.set_compression_type(DBCompressionType::Zstd);
+    } else if BLOOM_FILTER_CF_NAMES.contains(&cf_name) {
+        let mut block_opts = BlockBasedOptions::default();
+        block_opts.set_bloom_filter(10.0, false);
+        opts.set_block_based_table_factory(&block_opts);
+    }
+
+    if EXPIRING_CF_NAMES.contains(&cf_name) {
+        // Purge expired property values and idempotency records. Reads check
+        // for expiry as well, since there's no telling when compaction will
+        // get to them. Other column families have values that could be
+        // mistaken for ones with an expiry, such as sketches, so they don't
+        // get the filter.
+        opts.set_compaction_filter("expired_properties", |_, _, value: &[u8]| {
+            // Values that can't be read are kept, so that they can be inspected
+            match read_property_value(value, Utc::now()) {
+                Ok(None) => CompactionDecision::Remove,
+                _ => CompactionDecision::Keep,
+            }
+        });
+    }
+
+    if cf_name == "history:v1" {
+        // Purge versions that have aged out of the retention window. Reads
+        // ignore them as well, since compaction may not have gotten to them.
+        opts.set_compaction_filter("expired_history", |_, _, value: &[u8]| {
+            if history_version_expired(value, Utc::now()) {
+                CompactionDecision::Remove
+            } else {
+                CompactionDecision::Keep
+            }
+        });
+    }
+
+    if cf_name == "inbound_neighbor_sketches:v1" {
+        opts.set_merge_operator_associative("sketches", merge_sketches);
+    }
+
+    if ADJACENCY_BITMAP_CF_NAMES.contains(&cf_name) {
+        opts.set_merge_operator_associative("bitmaps", merge_bitmaps);
+    }
+
+    opts
 }
 
-/// A datastore that is backed by rocksdb.
-#[derive(Debug)]
-pub struct RocksdbDatastore {
-    db: Arc<DB>,
+// Whether a version in the history had aged out of the retention window as
+// of `now`. Versions that can't be read are kept, so that they can be
+// inspected.
+fn history_version_expired(value: &[u8], now: DateTime<Utc>) -> bool {
+    match read_history_version(value) {
+        Ok((Some(expires_at), _)) => expires_at <= now,
+        _ => false,
+    }
 }
 
-impl RocksdbDatastore {
-    /// Creates a new rocksdb datastore.
+fn cf_descriptors(
+    cf_names: &[&str],
+    max_open_files: Option<i32>,
+    bulk_load_optimized: bool,
+    resources: ResourceOptions,
+) -> Vec<ColumnFamilyDescriptor> {
+    cf_names
+        .iter()
+        .map(|cf_name| {
+            ColumnFamilyDescriptor::new(
+                *cf_name,
+                get_cf_options(cf_name, max_open_files, bulk_load_optimized, resources),
+            )
+        })
+        .collect()
+}
+
+/// Controls how durably writes are committed, trading durability for
+/// throughput.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CommitOptions {
+    /// Whether to sync the write-ahead log to disk before a commit returns.
+    /// Synced writes survive machine crashes, not just process crashes, but
+    /// are much slower.
+    pub sync: bool,
+
+    /// Whether to skip the write-ahead log entirely. This is useful for bulk
+    /// loads, but writes that haven't been flushed are lost on a crash.
+    pub disable_wal: bool,
+}
+
+impl CommitOptions {
+    /// Creates new commit options using the rocksdb defaults: writes go
+    /// through the write-ahead log, but aren't synced.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether to sync the write-ahead log before a commit returns.
     ///
     /// # Arguments
-    /// * `path` - The file path to the rocksdb database.
-    /// * `max_open_files` - The maximum number of open files to have. If
-    ///   `None`, the default will be used.
-    /// * `bulk_load_optimized` - Whether to configure the database to
-    ///   optimize for bulk loading, based off of suggestions from the RocksDB
-    ///   FAQ.
-    pub fn new(path: &str, max_open_files: Option<i32>, bulk_load_optimized: bool) -> Result<RocksdbDatastore> {
-        let opts = get_options(max_open_files, bulk_load_optimized);
+    /// * `sync` - Whether to sync.
+    pub fn sync(self, sync: bool) -> Self {
+        CommitOptions { sync, ..self }
+    }
+
+    /// Sets whether to skip the write-ahead log.
+    ///
+    /// # Arguments
+    /// * `disable_wal` - Whether to skip the write-ahead log.
+    pub fn disable_wal(self, disable_wal: bool) -> Self {
+        CommitOptions { disable_wal, ..self }
+    }
+
+    pub(crate) fn to_write_options(self) -> Result<WriteOptions> {
+        if self.sync && self.disable_wal {
+            return Err(ValidationError::from("Writes cannot be synced if the write-ahead log is disabled").into());
+        }
+
+        let mut opts = WriteOptions::default();
+        opts.set_sync(self.sync);
+        opts.disable_wal(self.disable_wal);
+        Ok(opts)
+    }
+}
+
+/// Controls how queries read from rocksdb. Large one-off scans, such as
+/// exports, can skip the block cache so they don't evict the working set of
+/// other queries, and read ahead to cut down on disk seeks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScanOptions {
+    /// Whether blocks read by queries are added to the block cache.
+    pub fill_cache: bool,
+
+    /// How many bytes to read ahead of iterators, or 0 for the rocksdb
+    /// default.
+    pub readahead_size: usize,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            fill_cache: true,
+            readahead_size: 0,
+        }
+    }
+}
+
+impl ScanOptions {
+    /// Creates new scan options using the rocksdb defaults: blocks are
+    /// cached, and there's no explicit readahead.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether blocks read by queries are added to the block cache.
+    ///
+    /// # Arguments
+    /// * `fill_cache` - Whether to fill the cache.
+    pub fn fill_cache(self, fill_cache: bool) -> Self {
+        ScanOptions { fill_cache, ..self }
+    }
+
+    /// Sets how many bytes to read ahead of iterators.
+    ///
+    /// # Arguments
+    /// * `readahead_size` - The number of bytes, or 0 for the default.
+    pub fn readahead_size(self, readahead_size: usize) -> Self {
+        ScanOptions { readahead_size, ..self }
+    }
+
+    pub(crate) fn to_read_options(self) -> KvReadOptions {
+        KvReadOptions {
+            upper_bound: None,
+            skip_cache: !self.fill_cache,
+            readahead_size: if self.readahead_size == 0 {
+                None
+            } else {
+                Some(self.readahead_size)
+            },
+        }
+    }
+}
+
+/// Limits how much of the machine rocksdb's background work uses, so that
+/// bulk imports and compactions don't starve foreground reads on shared
+/// hardware. Fields left at 0 use the defaults.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResourceOptions {
+    /// The maximum rate, in bytes per second, that flushes and compactions
+    /// write to disk at, or 0 for no limit. Flushes take priority over
+    /// compactions when the limit is hit, so writes keep flowing while
+    /// compactions are slowed down.
+    pub rate_limit_bytes_per_sec: i64,
+
+    /// The maximum number of compactions that run at once.
+    pub compaction_threads: i32,
+
+    /// The maximum number of memtable flushes that run at once.
+    pub flush_threads: i32,
+}
+
+impl ResourceOptions {
+    /// Creates new resource options, which leave background work unlimited
+    /// and use the default thread counts.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum rate that flushes and compactions write at.
+    ///
+    /// # Arguments
+    /// * `rate_limit_bytes_per_sec` - The rate, or 0 for no limit.
+    pub fn rate_limit_bytes_per_sec(self, rate_limit_bytes_per_sec: i64) -> Self {
+        ResourceOptions {
+            rate_limit_bytes_per_sec,
+            ..self
+        }
+    }
+
+    /// Sets the maximum number of compactions that run at once.
+    ///
+    /// # Arguments
+    /// * `compaction_threads` - The number of compactions, or 0 for the
+    ///   default.
+    pub fn compaction_threads(self, compaction_threads: i32) -> Self {
+        ResourceOptions {
+            compaction_threads,
+            ..self
+        }
+    }
+
+    /// Sets the maximum number of memtable flushes that run at once.
+    ///
+    /// # Arguments
+    /// * `flush_threads` - The number of flushes, or 0 for the default.
+    pub fn flush_threads(self, flush_threads: i32) -> Self {
+        ResourceOptions { flush_threads, ..self }
+    }
+
+    fn validate(self) -> Result<()> {
+        if self.rate_limit_bytes_per_sec < 0 || self.compaction_threads < 0 || self.flush_threads < 0 {
+            return Err(ValidationError::from("Resource options cannot be negative").into());
+        }
+
+        Ok(())
+    }
+}
+
+// A vertex queued for background deletion, along with the settings for
+// recording its deletion in the history, if it's recorded at all.
+type Deletion = (Uuid, Option<Arc<HistorySettings>>);
+
+// Work queued for the built-in background tasks, along with the tasks
+// themselves.
+#[derive(Debug, Default)]
+struct Background {
+    workers: BackgroundWorkers,
+    deletions: Arc<Mutex<VecDeque<Deletion>>>,
+    backfills: Arc<Mutex<VecDeque<models::PropertyIndex>>>,
+    // The auto-tuner, once it's been enabled.
+    tuner: Arc<Mutex<Option<Tuner>>>,
+    tuning_changes: Arc<Mutex<VecDeque<TuningChange>>>,
+}
+
+fn spawn_background_tasks(
+    db: &Arc<DB>,
+    tombstones: &Arc<RwLock<HashSet<Uuid>>>,
+    index_backfills: &IndexBackfills,
+) -> Background {
+    let background = Background::default();
+
+    let deletions = background.deletions.clone();
+    let tombstones = tombstones.clone();
+    background.workers.register(
+        db,
+        TOMBSTONE_TASK,
+        QUEUE_TASK_INTERVAL,
+        Box::new(move |db| purge_tombstones(db, &deletions, &tombstones)),
+    );
+
+    background
+        .workers
+        .register(db, EXPIRY_TASK, EXPIRY_TASK_INTERVAL, Box::new(sweep_expired_values));
+
+    let backfills = background.backfills.clone();
+    let index_backfills = index_backfills.clone();
+    background.workers.register(
+        db,
+        INDEX_BACKFILL_TASK,
+        QUEUE_TASK_INTERVAL,
+        Box::new(move |db| run_index_backfills(db, &backfills, &index_backfills)),
+    );
+
+    background
+}
+
+// Purges the vertices queued for background deletion, returning how many
+// were purged.
+fn purge_tombstones(
+    db: &Arc<DB>,
+    deletions: &Mutex<VecDeque<Deletion>>,
+    tombstones: &RwLock<HashSet<Uuid>>,
+) -> Result<u64> {
+    let mut count = 0;
+    let mut last_error = None;
+
+    loop {
+        // Vertices are popped one at a time, so that queueing more
+        // deletions isn't blocked in the meantime
+        let (id, history) = match deletions.lock().unwrap().pop_front() {
+            Some(deletion) => deletion,
+            None => break,
+        };
+
+        let vertex_manager = VertexManager::new(db.clone())?.with_history(history);
+
+        // On failure the tombstone is removed regardless. The vertex is
+        // deleted last, so it reappears and the deletion can be retried.
+        let result = vertex_manager.delete_in_chunks(id, BACKGROUND_DELETION_CHUNK_SIZE, |batch| {
+            db.write(batch)?;
+            Ok(())
+        });
+
+        tombstones.write().unwrap().remove(&id);
 
-        let db = match DB::open_cf(&opts, path, &CF_NAMES) {
-            Ok(db) => db,
-            Err(_) => {
-                let mut db = DB::open(&opts, path)?;
+        match result {
+            Ok(()) => count += 1,
+            Err(err) => last_error = Some(err),
+        }
+    }
+
+    match last_error {
+        Some(err) => Err(err),
+        None => Ok(count),
+    }
+}
 
-                for cf_name in &CF_NAMES {
-                    db.create_cf(cf_name, &opts)?;
+// Purges expired values and history versions that have aged out, returning
+// how many were found. Reads already ignore them, so this just reclaims the
+// space sooner than compaction would otherwise get to them. The purging
+// itself is left to the compaction filters, by compacting the range of keys
+// that had expired values, so that values replaced in the meantime aren't
+// lost.
+fn sweep_expired_values(db: &Arc<DB>) -> Result<u64> {
+    let now = Utc::now();
+    let mut count = 0;
+
+    for cf_name in EXPIRING_CF_NAMES.iter().chain(&["history:v1"]) {
+        let cf = cf_handle(db, cf_name)?;
+        let mut range: Option<(Box<[u8]>, Box<[u8]>)> = None;
+
+        for (key, value) in db.iterator_cf(cf, IteratorMode::Start) {
+            let expired = if *cf_name == "history:v1" {
+                history_version_expired(&value, now)
+            } else {
+                match read_property_value(&value, now) {
+                    Ok(None) => true,
+                    _ => false,
                 }
+            };
 
-                db
+            if expired {
+                count += 1;
+
+                range = match range {
+                    Some((first, _)) => Some((first, key)),
+                    None => Some((key.clone(), key)),
+                };
             }
+        }
+
+        if let Some((first, last)) = range {
+            db.compact_range_cf(cf, Some(&first), Some(&last));
+        }
+    }
+
+    Ok(count)
+}
+
+// Runs the queued index backfills, returning the number of vertices
+// indexed. Failures are recorded in each backfill's progress, as well as
+// being returned.
+fn run_index_backfills(
+    db: &Arc<DB>,
+    queue: &Mutex<VecDeque<models::PropertyIndex>>,
+    backfills: &IndexBackfills,
+) -> Result<u64> {
+    let mut count = 0;
+    let mut last_error = None;
+
+    loop {
+        let index = match queue.lock().unwrap().pop_front() {
+            Some(index) => index,
+            None => break,
         };
 
-        Ok(RocksdbDatastore { db: Arc::new(db) })
+        let result = backfill_index(db, &index, backfills);
+
+        if let Some(progress) = backfills.write().unwrap().get_mut(&index.name) {
+            progress.done = true;
+            progress.error = result.as_ref().err().map(|err| err.to_string());
+            count += progress.indexed_vertex_count;
+        }
+
+        if let Err(err) = result {
+            last_error = Some(err);
+        }
     }
 
-    /// Runs a repair operation on the rocksdb database.
-    ///
-    /// # Arguments
-    /// * `path` - The file path to the rocksdb database.
-    /// * `max_open_files` - The maximum number of open files to have. If
-    ///   `None`, the default will be used.
-    pub fn repair(path: &str, max_open_files: Option<i32>) -> Result<()> {
-        let opts = get_options(max_open_files, false);
-        DB::repair(opts, path)?;
+    match last_error {
+        Some(err) => Err(err),
+        None => Ok(count),
+    }
+}
+
+// Opens an existing database without writing to it, either read-only, or as
+// a secondary instance of the database if there's a secondary path. Column
+// families can't be created without writing, so only the ones that already
+// exist are opened.
+fn open_db_for_reads(path: &str, secondary_path: Option<&str>, max_open_files: Option<i32>) -> Result<DB> {
+    let opts = get_options(max_open_files, false, ResourceOptions::default());
+    let existing_cf_names = DB::list_cf(&opts, path)?;
+    let cf_names: Vec<&str> = CF_NAMES
+        .iter()
+        .cloned()
+        .filter(|cf_name| existing_cf_names.iter().any(|name| name == cf_name))
+        .collect();
+    let descriptors = cf_descriptors(&cf_names, max_open_files, false, ResourceOptions::default());
+
+    let db = match secondary_path {
+        Some(secondary_path) => DB::open_cf_descriptors_as_secondary(&opts, path, secondary_path, descriptors)?,
+        None => DB::open_cf_descriptors_read_only(&opts, path, descriptors, false)?,
+    };
+
+    Ok(db)
+}
+
+// Catches a secondary database up with the writes its primary has made
+// since it last caught up.
+fn catch_up_with_primary(db: &Arc<DB>) -> Result<u64> {
+    db.try_catch_up_with_primary()?;
+    Ok(0)
+}
+
+/// The progress of an index backfill, which indexes the vertices that
+/// already existed when the index was created.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IndexBackfillProgress {
+    /// The number of vertices that have been indexed so far.
+    pub indexed_vertex_count: u64,
+
+    /// The number of vertices that existed when the backfill started, or
+    /// `None` if they're still being counted.
+    pub total_vertex_count: Option<u64>,
+
+    /// Whether the backfill has stopped, either because it finished or
+    /// because it failed.
+    pub done: bool,
+
+    /// The error the backfill failed with, if any.
+    pub error: Option<String>,
+}
+
+/// The number of vertices and edges of each type. See
+/// `RocksdbTransaction::get_type_stats`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TypeStats {
+    /// The number of vertices of each type.
+    pub vertex_counts: BTreeMap<models::Type, u64>,
+
+    /// The number of edges of each type.
+    pub edge_counts: BTreeMap<models::Type, u64>,
+}
+
+/// A named snapshot of the datastore. See `RocksdbDatastore::create_tag`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SnapshotTag {
+    /// The name of the tag.
+    pub name: String,
+
+    /// When the snapshot was taken.
+    pub created_datetime: DateTime<Utc>,
+}
+
+type IndexBackfills = Arc<RwLock<HashMap<String, IndexBackfillProgress>>>;
+
+fn backfill_index(db: &Arc<DB>, index: &models::PropertyIndex, backfills: &IndexBackfills) -> Result<()> {
+    let vertex_manager = VertexManager::new(db.clone())?;
+    let index_manager = IndexManager::new(db.clone())?;
+
+    let mut total_vertex_count = 0;
+
+    for item in vertex_manager.iterate_for_range(Uuid::default())? {
+        item?;
+        total_vertex_count += 1;
+    }
+
+    if let Some(progress) = backfills.write().unwrap().get_mut(&index.name) {
+        progress.total_vertex_count = Some(total_vertex_count);
+    }
+
+    let mut batch = WriteBatch::default();
+    let mut batch_size = 0;
+
+    for item in vertex_manager.iterate_for_range(Uuid::default())? {
+        let (id, _) = item?;
+        index_manager.index_vertex(&mut batch, id, index)?;
+        batch_size += 1;
+
+        if batch_size == INDEX_BACKFILL_CHUNK_SIZE {
+            // Stop if the index was dropped in the meantime
+            if index_manager.get(&index.name)?.as_ref() != Some(index) {
+                return Ok(());
+            }
+
+            db.write(mem::replace(&mut batch, WriteBatch::default()))?;
+
+            if let Some(progress) = backfills.write().unwrap().get_mut(&index.name) {
+                progress.indexed_vertex_count += batch_size as u64;
+            }
+
+            batch_size = 0;
+        }
+    }
+
+    if index_manager.get(&index.name)?.as_ref() == Some(index) {
+        db.write(batch)?;
+
+        if let Some(progress) = backfills.write().unwrap().get_mut(&index.name) {
+            progress.indexed_vertex_count += batch_size as u64;
+        }
+    }
+
+    Ok(())
+}
+
+// Opens the database, creating any column families that are missing.
+fn open_db(
+    path: &str,
+    max_open_files: Option<i32>,
+    bulk_load_optimized: bool,
+    resources: ResourceOptions,
+) -> Result<DB> {
+    let opts = get_options(max_open_files, bulk_load_optimized, resources);
+    let descriptors = |cf_names: &[&str]| -> Vec<ColumnFamilyDescriptor> {
+        cf_descriptors(cf_names, max_open_files, bulk_load_optimized, resources)
+    };
+
+    match DB::open_cf_descriptors(&opts, path, descriptors(&CF_NAMES)) {
+        Ok(db) => Ok(db),
+        Err(ref err) if is_lock_error(err) => Err(ErrorKind::Locked(path.to_string()).into()),
+        Err(_) => {
+            // Either the database doesn't exist yet, or it was created
+            // before some of the column families were added
+            let existing_cf_names = DB::list_cf(&opts, path).unwrap_or_default();
+            let existing_cf_names: Vec<&str> = existing_cf_names.iter().map(|name| name.as_str()).collect();
+            let mut db = DB::open_cf_descriptors(&opts, path, descriptors(&existing_cf_names))?;
+
+            for cf_name in &CF_NAMES {
+                if !existing_cf_names.contains(cf_name) {
+                    db.create_cf(
+                        cf_name,
+                        &get_cf_options(cf_name, max_open_files, bulk_load_optimized, resources),
+                    )?;
+                }
+            }
+
+            // Edge properties were stored before they were indexed by name
+            if existing_cf_names.contains(&"edge_properties:v1")
+                && !existing_cf_names.contains(&"edge_property_names:v1")
+            {
+                index_edge_property_names(&db)?;
+            }
+
+            // Edges were stored before they were indexed by type
+            if existing_cf_names.contains(&"edges:v1") && !existing_cf_names.contains(&"edge_types:v1") {
+                index_edge_types(&db)?;
+            }
+
+            // Vertices and edges were stored before they were counted
+            if existing_cf_names.contains(&"vertices:v1") && !existing_cf_names.contains(&"vertex_type_counts:v1") {
+                count_types(&db)?;
+            }
+
+            Ok(db)
+        }
+    }
+}
+
+// Builds the index of edge properties by name from scratch.
+fn index_edge_property_names(db: &DB) -> Result<()> {
+    let source_cf = cf_handle(db, "edge_properties:v1")?;
+    let target_cf = cf_handle(db, "edge_property_names:v1")?;
+    let mut batch = WriteBatch::default();
+    let mut batch_len = 0;
+
+    for (key, _) in db.iterator_cf(source_cf, IteratorMode::Start) {
+        let mut cursor = Cursor::new(key);
+        let outbound_id = read_uuid(&mut cursor)?;
+        let t = read_type(&mut cursor)?;
+        let inbound_id = read_uuid(&mut cursor)?;
+        let name = read_unsized_string(&mut cursor)?;
+        batch.put_cf(
+            target_cf,
+            edge_property_name_key(&name, outbound_id, &t, inbound_id),
+            [],
+        );
+        batch_len += 1;
+
+        if batch_len >= NAME_INDEX_CHUNK_SIZE {
+            db.write(mem::replace(&mut batch, WriteBatch::default()))?;
+            batch_len = 0;
+        }
+    }
+
+    db.write(batch)?;
+    Ok(())
+}
+
+// Builds the index of edges by type from scratch.
+fn index_edge_types(db: &DB) -> Result<()> {
+    let source_cf = cf_handle(db, "edges:v1")?;
+    let target_cf = cf_handle(db, "edge_types:v1")?;
+    let mut batch = WriteBatch::default();
+    let mut batch_len = 0;
+
+    for (key, value) in db.iterator_cf(source_cf, IteratorMode::Start) {
+        let mut cursor = Cursor::new(key);
+        let outbound_id = read_uuid(&mut cursor)?;
+        let t = read_type(&mut cursor)?;
+        let inbound_id = read_uuid(&mut cursor)?;
+        let update_datetime = read_datetime(&mut Cursor::new(value))?;
+        batch.put_cf(
+            target_cf,
+            edge_type_key(&t, update_datetime, outbound_id, inbound_id),
+            [],
+        );
+        batch_len += 1;
+
+        if batch_len >= TYPE_INDEX_CHUNK_SIZE {
+            db.write(mem::replace(&mut batch, WriteBatch::default()))?;
+            batch_len = 0;
+        }
+    }
+
+    db.write(batch)?;
+    Ok(())
+}
+
+// Counts vertices and edges by type from scratch, replacing the existing
+// counts.
+fn count_types(db: &DB) -> Result<()> {
+    let mut vertex_counts: HashMap<models::Type, i64> = HashMap::new();
+    let mut edge_counts: HashMap<models::Type, i64> = HashMap::new();
+
+    for (_, value) in db.iterator_cf(cf_handle(db, "vertices:v1")?, IteratorMode::Start) {
+        *vertex_counts.entry(read_type(&mut Cursor::new(value))?).or_insert(0) += 1;
+    }
+
+    for (key, _) in db.iterator_cf(cf_handle(db, "edges:v1")?, IteratorMode::Start) {
+        let mut cursor = Cursor::new(key);
+        read_uuid(&mut cursor)?;
+        *edge_counts.entry(read_type(&mut cursor)?).or_insert(0) += 1;
+    }
+
+    let mut batch = WriteBatch::default();
+
+    for (cf_name, counts) in &[
+        ("vertex_type_counts:v1", vertex_counts),
+        ("edge_type_counts:v1", edge_counts),
+    ] {
+        let cf = cf_handle(db, cf_name)?;
+
+        for (key, _) in db.iterator_cf(cf, IteratorMode::Start) {
+            batch.delete_cf(cf, &key);
+        }
+
+        for (t, count) in counts {
+            batch.put_cf(cf, build(&[Component::Type(t)]), build_counter(*count));
+        }
+    }
+
+    db.write(batch)?;
+    Ok(())
+}
+
+// Rocksdb doesn't distinguish lock failures from other IO errors, so they're
+// recognized by the lock file's name in the error message.
+fn is_lock_error(err: &RocksDbError) -> bool {
+    err.to_string().contains("LOCK")
+}
+
+// Tag names are used as directory names, so they're limited to characters
+// that are safe in paths.
+fn validate_tag_name(name: &str) -> Result<()> {
+    let is_valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+
+    if is_valid {
         Ok(())
+    } else {
+        Err(ValidationError::from(format!("Invalid tag name `{}`", name)).into())
     }
 }
 
-impl Datastore for RocksdbDatastore {
-    type Trans = RocksdbTransaction;
+// Gets the path of a tag's checkpoint. Checkpoints are kept in a directory
+// next to the database, rather than inside it.
+fn tag_path(db: &DB, name: &str) -> PathBuf {
+    let mut path = db.path().as_os_str().to_owned();
+    path.push(".tags");
+    PathBuf::from(path).join(name)
+}
+
+// Decides whether cloning should copy an entry, skipping expired property
+// values and anything that belongs to a vertex pending deletion.
+fn should_clone(
+    cf_name: &str,
+    key: &[u8],
+    value: &[u8],
+    tombstones: &HashSet<Uuid>,
+    now: DateTime<Utc>,
+) -> Result<bool> {
+    // Tags refer to checkpoints of this database, which aren't copied, and
+    // types are counted again once the copy is made
+    if cf_name == "snapshot_tags:v1" || cf_name == "vertex_type_counts:v1" || cf_name == "edge_type_counts:v1" {
+        return Ok(false);
+    }
+
+    // Bitmaps can include the vertices pending deletion, whose edges aren't
+    // copied, so they're only copied if there aren't any. Otherwise, they're
+    // seeded again as edges are written to the copy.
+    if ADJACENCY_BITMAP_CF_NAMES.contains(&cf_name) && !tombstones.is_empty() {
+        return Ok(false);
+    }
+
+    let mut cursor = Cursor::new(key);
+
+    let ids = match cf_name {
+        "vertices:v1"
+        | "vertex_properties:v1"
+        | "cold_vertex_properties:v1"
+        | "inbound_neighbor_sketches:v1"
+        | "adjacency_bitmaps:v1"
+        | "reversed_adjacency_bitmaps:v1"
+        | "adjacency_degrees:v1"
+        | "reversed_adjacency_degrees:v1" => vec![read_uuid(&mut cursor)?],
+        "edges:v1" | "edge_properties:v1" | "cold_edge_properties:v1" => {
+            let first_id = read_uuid(&mut cursor)?;
+            read_type(&mut cursor)?;
+            vec![first_id, read_uuid(&mut cursor)?]
+        }
+        "edge_ranges:v1" | "reversed_edge_ranges:v1" => {
+            let first_id = read_uuid(&mut cursor)?;
+            read_type(&mut cursor)?;
+            read_datetime(&mut cursor)?;
+            vec![first_id, read_uuid(&mut cursor)?]
+        }
+        "edge_types:v1" => {
+            read_type(&mut cursor)?;
+            read_datetime(&mut cursor)?;
+            let outbound_id = read_uuid(&mut cursor)?;
+            vec![outbound_id, read_uuid(&mut cursor)?]
+        }
+        "edge_log:v1" => {
+            read_datetime(&mut cursor)?;
+            let outbound_id = read_uuid(&mut cursor)?;
+            read_type(&mut cursor)?;
+            vec![outbound_id, read_uuid(&mut cursor)?]
+        }
+        _ => Vec::new(),
+    };
+
+    if ids.iter().any(|id| tombstones.contains(id)) {
+        return Ok(false);
+    }
+
+    match cf_name {
+        "vertex_properties:v1"
+        | "edge_properties:v1"
+        | "cold_vertex_properties:v1"
+        | "cold_edge_properties:v1"
+        | "idempotency_keys:v1" => Ok(read_property_value(value, now)?.is_some()),
+        "history:v1" => Ok(!history_version_expired(value, now)),
+        _ => Ok(true),
+    }
+}
+
+fn read_write_pressure(db: &DB) -> Result<models::WritePressure> {
+    let stopped = db.property_int_value("rocksdb.is-write-stopped")?.unwrap_or(0) != 0;
+
+    // The rate is zero when writes aren't being delayed
+    let delayed_write_rate = match db.property_int_value("rocksdb.actual-delayed-write-rate")? {
+        Some(0) | None => None,
+        rate => rate,
+    };
+
+    let mut pending_compaction_bytes = 0;
+
+    for cf_name in &CF_NAMES {
+        let cf = cf_handle(db, cf_name)?;
+        pending_compaction_bytes += db
+            .property_int_value_cf(cf, "rocksdb.estimate-pending-compaction-bytes")?
+            .unwrap_or(0);
+    }
+
+    Ok(models::WritePressure {
+        stopped,
+        delayed_write_rate,
+        pending_compaction_bytes,
+    })
+}
+
+fn is_locked(err: &Error) -> bool {
+    match *err.kind() {
+        ErrorKind::Locked(_) => true,
+        _ => false,
+    }
+}
+
+/// A datastore that is backed by rocksdb.
+#[derive(Debug)]
+pub struct RocksdbDatastore {
+    db: Arc<DB>,
+    tombstones: Arc<RwLock<HashSet<Uuid>>>,
+    background: Arc<Background>,
+    index_backfills: IndexBackfills,
+    events: EventBus,
+    clock: Arc<dyn Clock>,
+    // The datastores that tagged transactions read from, which are opened
+    // the first time a tag is used.
+    tagged_datastores: Mutex<HashMap<String, Arc<RocksdbDatastore>>>,
+    // Whether edges are written to the edge log.
+    edge_log: bool,
+    // The width of the windows edges are counted in by the inbound neighbor
+    // sketches, if they're counted at all.
+    neighbor_sketch_window: Option<Duration>,
+    // The settings for adjacency bitmaps, if they're kept at all.
+    adjacency_bitmaps: Option<Arc<AdjacencyBitmapSettings>>,
+    // The names of the properties stored in the cold column families.
+    cold_properties: Arc<HashSet<String>>,
+    // How much history to retain, if it's recorded at all.
+    history: Option<RetentionPolicy>,
+    read_only: bool,
+    // Whether this is a secondary instance that follows a primary.
+    secondary: bool,
+}
+
+impl RocksdbDatastore {
+    /// Creates a new rocksdb datastore.
+    ///
+    /// # Arguments
+    /// * `path` - The file path to the rocksdb database.
+    /// * `max_open_files` - The maximum number of open files to have. If
+    ///   `None`, the default will be used.
+    /// * `bulk_load_optimized` - Whether to configure the database to
+    ///   optimize for bulk loading, based off of suggestions from the RocksDB
+    ///   FAQ.
+    ///
+    /// # Errors
+    /// Returns a `Locked` error if the database is already open, either by
+    /// another process or by another datastore in this one.
+    pub fn new(path: &str, max_open_files: Option<i32>, bulk_load_optimized: bool) -> Result<RocksdbDatastore> {
+        let db = open_db(path, max_open_files, bulk_load_optimized, ResourceOptions::default())?;
+        Ok(RocksdbDatastore::from_db(db, true))
+    }
+
+    /// Creates a new rocksdb datastore, waiting for the database to be
+    /// closed if it's already open elsewhere. This allows tooling to take
+    /// turns with another process that opens the same database.
+    ///
+    /// # Arguments
+    /// * `path` - The file path to the rocksdb database.
+    /// * `max_open_files` - The maximum number of open files to have. If
+    ///   `None`, the default will be used.
+    /// * `bulk_load_optimized` - Whether to configure the database to
+    ///   optimize for bulk loading, based off of suggestions from the RocksDB
+    ///   FAQ.
+    /// * `timeout` - How long to wait for the database to be closed.
+    ///
+    /// # Errors
+    /// Returns a `Locked` error if the database is still open elsewhere
+    /// once the timeout has passed.
+    pub fn new_with_lock_timeout(
+        path: &str,
+        max_open_files: Option<i32>,
+        bulk_load_optimized: bool,
+        timeout: Duration,
+    ) -> Result<RocksdbDatastore> {
+        let started_at = Instant::now();
+
+        loop {
+            match open_db(path, max_open_files, bulk_load_optimized, ResourceOptions::default()) {
+                Ok(db) => return Ok(RocksdbDatastore::from_db(db, true)),
+                Err(ref err) if is_locked(err) && started_at.elapsed() < timeout => {
+                    thread::sleep(LOCK_RETRY_INTERVAL);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Creates a new rocksdb datastore, limiting the resources that its
+    /// background work uses.
+    ///
+    /// # Arguments
+    /// * `path` - The file path to the rocksdb database.
+    /// * `max_open_files` - The maximum number of open files to have. If
+    ///   `None`, the default will be used.
+    /// * `bulk_load_optimized` - Whether to configure the database to
+    ///   optimize for bulk loading, based off of suggestions from the RocksDB
+    ///   FAQ.
+    /// * `resources` - The limits on background work.
+    ///
+    /// # Errors
+    /// Returns a `ValidationError` if the resource options are negative, or
+    /// a `Locked` error if the database is already open.
+    pub fn new_with_resources(
+        path: &str,
+        max_open_files: Option<i32>,
+        bulk_load_optimized: bool,
+        resources: ResourceOptions,
+    ) -> Result<RocksdbDatastore> {
+        resources.validate()?;
+        let db = open_db(path, max_open_files, bulk_load_optimized, resources)?;
+        Ok(RocksdbDatastore::from_db(db, true))
+    }
+
+    fn from_db(db: DB, background_tasks: bool) -> RocksdbDatastore {
+        let db = Arc::new(db);
+        let tombstones = Arc::new(RwLock::new(HashSet::new()));
+        let index_backfills = Arc::new(RwLock::new(HashMap::new()));
+
+        let background = if background_tasks {
+            spawn_background_tasks(&db, &tombstones, &index_backfills)
+        } else {
+            Background::default()
+        };
+
+        RocksdbDatastore {
+            db,
+            tombstones,
+            background: Arc::new(background),
+            index_backfills,
+            events: EventBus::default(),
+            clock: Arc::new(SystemClock),
+            tagged_datastores: Mutex::new(HashMap::new()),
+            edge_log: false,
+            neighbor_sketch_window: None,
+            adjacency_bitmaps: None,
+            cold_properties: Arc::new(HashSet::new()),
+            history: None,
+            read_only: false,
+            secondary: false,
+        }
+    }
+
+    /// Opens an existing rocksdb datastore read-only. Its transactions read
+    /// the database as it was when it was opened, and return a validation
+    /// error for writes. Unlike with `new`, the database can be open in
+    /// another process at the same time, and no background tasks are run.
+    ///
+    /// # Arguments
+    /// * `path` - The file path to the rocksdb database.
+    /// * `max_open_files` - The maximum number of open files to have. If
+    ///   `None`, the default will be used.
+    pub fn new_read_only(path: &str, max_open_files: Option<i32>) -> Result<RocksdbDatastore> {
+        let db = open_db_for_reads(path, None, max_open_files)?;
+        Ok(RocksdbDatastore {
+            read_only: true,
+            ..RocksdbDatastore::from_db(db, false)
+        })
+    }
+
+    /// Opens a rocksdb datastore as a secondary instance of a database that
+    /// another process has open, e.g. so that analytics can read a live
+    /// database. Like with `new_read_only`, its transactions return a
+    /// validation error for writes. It follows the primary's writes by
+    /// tailing its write-ahead log, which the `catch_up` background task does
+    /// every second; see `catch_up_with_primary` to catch up on demand.
+    ///
+    /// # Arguments
+    /// * `primary_path` - The file path to the rocksdb database.
+    /// * `secondary_path` - The file path to keep the secondary instance's
+    ///   own logs at. It can't be shared with other secondary instances.
+    pub fn new_secondary(primary_path: &str, secondary_path: &str) -> Result<RocksdbDatastore> {
+        // Secondary instances have to keep every file open, so that the
+        // primary's compactions can't delete files out from under them
+        let db = open_db_for_reads(primary_path, Some(secondary_path), Some(-1))?;
+        let datastore = RocksdbDatastore {
+            read_only: true,
+            secondary: true,
+            ..RocksdbDatastore::from_db(db, false)
+        };
+        datastore.background.workers.register(
+            &datastore.db,
+            CATCH_UP_TASK,
+            CATCH_UP_TASK_INTERVAL,
+            Box::new(catch_up_with_primary),
+        );
+        Ok(datastore)
+    }
+
+    /// Catches a secondary datastore up with the writes its primary has
+    /// made, so that transactions created afterwards see them.
+    ///
+    /// # Errors
+    /// Returns an error if the datastore wasn't opened with
+    /// `new_secondary`.
+    pub fn catch_up_with_primary(&self) -> Result<()> {
+        catch_up_with_primary(&self.db)?;
+        Ok(())
+    }
+
+    /// Sets the clock used to timestamp edges, in place of the system time.
+    /// This allows tests and import jobs to control timestamps
+    /// deterministically. Property expiry is still checked against the
+    /// system time.
+    ///
+    /// # Arguments
+    /// * `clock` - The clock to use.
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> RocksdbDatastore {
+        RocksdbDatastore { clock, ..self }
+    }
+
+    /// Sets whether edges are written to the edge log, a global index of
+    /// edges ordered by update datetime that `get_newest_edges` reads from.
+    /// This is disabled by default, since it costs an extra write per edge.
+    /// Only edges created or updated while the log is enabled are in it;
+    /// enabling it doesn't backfill edges that already exist.
+    ///
+    /// # Arguments
+    /// * `edge_log` - Whether to write edges to the edge log.
+    pub fn with_edge_log(self, edge_log: bool) -> RocksdbDatastore {
+        RocksdbDatastore { edge_log, ..self }
+    }
+
+    /// Sets whether edges are counted by inbound neighbor sketches, which
+    /// estimate how many distinct vertices have edges going into a vertex
+    /// within a range of update datetimes. See
+    /// `RocksdbTransaction::get_inbound_neighbor_sketch`. This is disabled
+    /// by default, since it costs an extra write per edge. Only edges
+    /// created or updated while it's enabled are counted.
+    ///
+    /// # Arguments
+    /// * `window` - The width of the windows edges are counted in, which is
+    ///   the granularity that ranges of update datetimes are rounded out
+    ///   to, or `None` to disable the sketches. Each vertex takes 1kb for
+    ///   every window and edge type it has inbound edges in.
+    pub fn with_neighbor_sketches(self, window: Option<Duration>) -> RocksdbDatastore {
+        RocksdbDatastore {
+            neighbor_sketch_window: window,
+            ..self
+        }
+    }
+
+    /// Sets whether roaring bitmaps are kept of the neighbors of high-degree
+    /// vertices, by edge type and direction, so that the neighbors of two
+    /// vertices can be intersected without scanning their edges. See
+    /// `RocksdbTransaction::get_adjacency_bitmap`. This is disabled by
+    /// default, since it costs a few extra reads per edge written.
+    ///
+    /// Edges are counted while this is enabled, and once a vertex has
+    /// enough edges of a type, a bitmap is seeded from all of them. From
+    /// then on, it's kept up to date until the vertex is deleted, whether
+    /// or not this is still enabled.
+    ///
+    /// # Arguments
+    /// * `min_degree` - How many edges of a type a vertex needs in a
+    ///   direction before a bitmap is kept of their neighbors, or `None` to
+    ///   disable the bitmaps.
+    pub fn with_adjacency_bitmaps(self, min_degree: Option<u64>) -> RocksdbDatastore {
+        RocksdbDatastore {
+            adjacency_bitmaps: min_degree.map(|min_degree| Arc::new(AdjacencyBitmapSettings::new(min_degree))),
+            ..self
+        }
+    }
+
+    /// Sets the names of the vertex and edge properties that are stored in
+    /// separate, cold column families. Cold column families are compressed
+    /// harder and bypass the block cache, which suits large, rarely-read
+    /// values such as blobs: they take less space, and reading them doesn't
+    /// evict the small, hot properties that most queries read. Properties
+    /// are stored according to the names set when they're written, but are
+    /// found wherever they're stored, so the names can be changed between
+    /// opens; a property moves the next time it's written.
+    ///
+    /// # Arguments
+    /// * `names` - The names of the cold properties.
+    pub fn with_cold_properties(self, names: HashSet<String>) -> RocksdbDatastore {
+        RocksdbDatastore {
+            cold_properties: Arc::new(names),
+            ..self
+        }
+    }
+
+    /// Sets whether previous versions of vertices, edges and properties are
+    /// recorded, keyed by the datetime they were committed, so that the graph
+    /// can be read as it was at a point in time with `as_of`. This is
+    /// disabled by default, since it costs a read and a couple of writes per
+    /// item written. Only changes made while it's enabled are recorded, by
+    /// transactions, batches and write coalescers alike; bulk inserts and
+    /// vertices deleted in the background are recorded as well, the latter
+    /// once they're purged.
+    ///
+    /// Versions older than the retention policy's maximum age are dropped
+    /// by a compaction filter, and by the `expired_values` background task;
+    /// reads ignore them in the meantime. Versions beyond the maximum count
+    /// are deleted whenever a new version of an item is recorded.
+    ///
+    /// # Arguments
+    /// * `retention` - How much history to retain, or `None` to stop
+    ///   recording it. History that's already been recorded is kept.
+    pub fn with_history(self, retention: Option<RetentionPolicy>) -> RocksdbDatastore {
+        RocksdbDatastore {
+            history: retention,
+            ..self
+        }
+    }
+
+    /// Gets a copy of the datastore as it was at a given datetime, rebuilt
+    /// from the history into an in-memory datastore. The copy is detached
+    /// from this datastore; changes to either are not reflected in the
+    /// other.
+    ///
+    /// # Arguments
+    /// * `datetime` - The datetime to read the graph as of.
+    ///
+    /// # Errors
+    /// Returns an error if history isn't enabled with `with_history`.
+    pub fn as_of(&self, datetime: DateTime<Utc>) -> Result<MemoryDatastore> {
+        if self.history.is_none() {
+            return Err("History is not enabled for this datastore".into());
+        }
+
+        let items = HistoryManager::new(self.db.clone())?.items_as_of(datetime, self.clock.now())?;
+        Ok(MemoryDatastore::from_items(
+            items.vertices,
+            items.edges,
+            items.vertex_properties,
+            items.edge_properties,
+        )
+        .with_clock(self.clock.clone()))
+    }
+
+    /// Discards the history of the given vertices, keeping only their
+    /// current versions. The history of the vertices' properties, edges and
+    /// edge properties is discarded as well. Vertices that have been deleted
+    /// are removed from the history entirely.
+    ///
+    /// # Arguments
+    /// * `ids` - The ids of the vertices.
+    pub fn vacuum_history(&self, ids: &[Uuid]) -> Result<()> {
+        let ids: HashSet<Uuid> = ids.iter().cloned().collect();
+        let mut batch = WriteBatch::default();
+        HistoryManager::new(self.db.clone())?.vacuum(&mut batch, &ids)?;
+        self.db.write(batch)?;
+        Ok(())
+    }
+
+    // Gets the settings for recording history, if it's recorded at all.
+    fn history_settings(&self) -> Option<Arc<HistorySettings>> {
+        self.history.as_ref().map(|retention| {
+            Arc::new(HistorySettings {
+                retention: retention.clone(),
+                clock: self.clock.clone(),
+            })
+        })
+    }
+
+    /// Runs a repair operation on the rocksdb database.
+    ///
+    /// # Arguments
+    /// * `path` - The file path to the rocksdb database.
+    /// * `max_open_files` - The maximum number of open files to have. If
+    ///   `None`, the default will be used.
+    pub fn repair(path: &str, max_open_files: Option<i32>) -> Result<()> {
+        let opts = get_options(max_open_files, false, ResourceOptions::default());
+        DB::repair(&opts, path)?;
+        Ok(())
+    }
+
+    /// Compacts every column family, so that deleted and overwritten
+    /// entries are dropped from disk. This blocks until the compaction is
+    /// done, which can take a while for large datastores.
+    pub fn compact(&self) -> Result<()> {
+        for cf_name in &CF_NAMES {
+            self.db
+                .compact_range_cf(cf_handle(&self.db, cf_name)?, None::<&[u8]>, None::<&[u8]>);
+        }
+
+        Ok(())
+    }
+
+    /// Copies a consistent snapshot of the datastore into a new rocksdb
+    /// database, which can then be opened in place of this one. Only live
+    /// entries are copied, so deleted and overwritten entries, expired
+    /// property values and vertices pending deletion are left behind, and
+    /// the copy is compacted once written. This shrinks long-lived databases
+    /// bloated by tombstones. Writes continue while the copy is made, but
+    /// writes made after it starts aren't copied. Tags aren't copied either.
+    ///
+    /// # Errors
+    /// Returns a `Corrupt` error if an entry can't be decoded; see `verify`.
+    ///
+    /// # Arguments
+    /// * `path` - The file path to create the new database at. It must not
+    ///   exist, or be an empty directory.
+    pub fn clone_to(&self, path: &str) -> Result<()> {
+        if Path::new(path).exists() && fs::read_dir(path)?.next().is_some() {
+            return Err(ValidationError::from(format!("Cannot clone into `{}`, since it isn't empty", path)).into());
+        }
+
+        let target = open_db(path, None, true, ResourceOptions::default())?;
+        let snapshot = self.db.snapshot();
+        let tombstones = self.tombstones.read().unwrap().clone();
+        let now = Utc::now();
+
+        for cf_name in &CF_NAMES {
+            let source_cf = cf_handle(&self.db, cf_name)?;
+            let target_cf = cf_handle(&target, cf_name)?;
+            let mut batch = WriteBatch::default();
+            let mut batch_len = 0;
+
+            for (key, value) in snapshot.iterator_cf(source_cf, IteratorMode::Start) {
+                if !should_clone(cf_name, &key, &value, &tombstones, now)? {
+                    continue;
+                }
+
+                batch.put_cf(target_cf, &key, &value);
+                batch_len += 1;
+
+                if batch_len >= CLONE_CHUNK_SIZE {
+                    target.write(mem::replace(&mut batch, WriteBatch::default()))?;
+                    batch_len = 0;
+                }
+            }
+
+            target.write(batch)?;
+            target.compact_range_cf(target_cf, None::<&[u8]>, None::<&[u8]>);
+        }
+
+        count_types(&target)
+    }
+
+    /// Counts vertices and edges by type from scratch, replacing the counts
+    /// returned by `RocksdbTransaction::get_type_stats`. The counts are
+    /// kept up to date on every write, so this is only needed if they've
+    /// drifted; see `get_type_stats`. Writes made while this runs may be
+    /// miscounted.
+    pub fn recount_type_stats(&self) -> Result<()> {
+        count_types(&self.db)
+    }
+
+    /// Walks every column family and checks that each entry decodes
+    /// cleanly, i.e. that its uuids, types, datetimes, property names and
+    /// JSON values are well-formed. Corrupt entries are returned rather than
+    /// causing a panic, so they can be inspected and repaired.
+    pub fn verify(&self) -> Result<Vec<CorruptEntry>> {
+        let snapshot = self.db.snapshot();
+        let mut corrupt_entries = Vec::new();
+
+        for cf_name in &CF_NAMES {
+            let cf = cf_handle(&self.db, cf_name)?;
+
+            for (key, value) in snapshot.iterator_cf(cf, IteratorMode::Start) {
+                if let Err(err) = check_entry(cf_name, &key, &value) {
+                    corrupt_entries.push(CorruptEntry {
+                        cf: cf_name.to_string(),
+                        key: key.to_vec(),
+                        reason: err.to_string(),
+                    });
+                }
+            }
+        }
+
+        Ok(corrupt_entries)
+    }
+
+    /// Creates a new batch, which queues operations and applies them
+    /// atomically in a single `WriteBatch` when committed.
+    pub fn batch(&self) -> BatchTransaction {
+        BatchTransaction::new(
+            self.db.clone(),
+            self.clock.clone(),
+            self.edge_log,
+            self.neighbor_sketch_window,
+            self.adjacency_bitmaps.clone(),
+            self.cold_properties.clone(),
+            self.history_settings(),
+        )
+    }
+
+    /// Creates a write coalescer, which groups the batches written through
+    /// it by concurrent callers into shared `WriteBatch`es. This trades a
+    /// bounded delay on each write for much higher throughput when there
+    /// are many small writes. See `WriteCoalescer`.
+    ///
+    /// # Arguments
+    /// * `options` - How to group writes.
+    ///
+    /// # Errors
+    /// Returns a `ValidationError` if the options are invalid.
+    pub fn write_coalescer(&self, options: CoalesceOptions) -> Result<WriteCoalescer> {
+        WriteCoalescer::new(
+            self.db.clone(),
+            self.clock.clone(),
+            self.edge_log,
+            self.neighbor_sketch_window,
+            self.adjacency_bitmaps.clone(),
+            self.cold_properties.clone(),
+            self.history_settings(),
+            options,
+        )
+    }
+
+    /// Creates a new transaction whose writes are committed with the given
+    /// options, rather than the rocksdb defaults.
+    ///
+    /// # Arguments
+    /// * `options` - The options to commit writes with.
+    pub fn transaction_with_options(&self, options: CommitOptions) -> Result<RocksdbTransaction> {
+        let mut trans = RocksdbTransaction::new(
+            self.db.clone(),
+            self.tombstones.clone(),
+            self.background.clone(),
+            self.index_backfills.clone(),
+            self.events.clone(),
+            self.clock.clone(),
+            options,
+        )?;
+        trans.edge_log = self.edge_log;
+        trans.neighbor_sketch_window = self.neighbor_sketch_window;
+        trans.adjacency_bitmaps = self.adjacency_bitmaps.clone();
+        trans.cold_properties = self.cold_properties.clone();
+        trans.history = self.history_settings();
+        trans.read_only = self.read_only;
+        Ok(trans)
+    }
+
+    /// Gets the number of vertices that are in the process of being
+    /// deleted, either in the background or in chunks.
+    pub fn pending_deletion_count(&self) -> usize {
+        self.tombstones.read().unwrap().len()
+    }
+
+    /// Gets metrics for each background task, by name. The datastore runs
+    /// these built-in tasks:
+    ///
+    /// * `tombstones` - Purges vertices deleted with
+    ///   `delete_vertices_in_background`.
+    /// * `expired_values` - Purges expired property values, idempotency
+    ///   records and history versions that have aged out of the retention
+    ///   window, rather than waiting for compaction to get to them. Runs
+    ///   hourly.
+    /// * `index_backfills` - Indexes the vertices that already existed when
+    ///   an index was created.
+    ///
+    /// Datastores opened with `new_read_only` don't run any of these, and
+    /// ones opened with `new_secondary` only run `catch_up`, which catches
+    /// up with the primary's writes every second.
+    ///
+    /// The tasks that work through queues are woken whenever work is queued
+    /// for them, and otherwise check their queues every ten seconds.
+    pub fn get_background_task_metrics(&self) -> BTreeMap<String, BackgroundTaskMetrics> {
+        self.background.workers.metrics()
+    }
+
+    /// Pauses a background task, so that it skips its runs until it's
+    /// resumed. A run that's already in progress isn't interrupted. Work
+    /// queued for a paused task waits until it's resumed, so e.g. vertices
+    /// deleted in the background stay hidden but aren't purged.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the task.
+    ///
+    /// # Errors
+    /// Returns a `NotFound` error if there's no task with the name.
+    pub fn pause_background_task(&self, name: &str) -> Result<()> {
+        self.background.workers.set_paused(name, true)
+    }
+
+    /// Resumes a paused background task, and runs it.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the task.
+    ///
+    /// # Errors
+    /// Returns a `NotFound` error if there's no task with the name.
+    pub fn resume_background_task(&self, name: &str) -> Result<()> {
+        self.background.workers.set_paused(name, false)?;
+        self.background.workers.run(name)
+    }
+
+    /// Runs a background task now, rather than waiting for its next run.
+    /// This returns without waiting for the run to finish. Paused tasks
+    /// aren't run.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the task.
+    ///
+    /// # Errors
+    /// Returns a `NotFound` error if there's no task with the name.
+    pub fn run_background_task(&self, name: &str) -> Result<()> {
+        self.background.workers.run(name)
+    }
+
+    /// Enables the auto-tuner, which runs as the `auto_tuner` background
+    /// task. It watches for write stalls, and adjusts the size of memtables
+    /// and the number of level 0 files that writes are slowed down at to
+    /// suit the workload, keeping them within the given bounds. Changes
+    /// apply to every column family, last until the datastore is closed,
+    /// and are reported through `get_tuning_changes`. It starts from the
+    /// standard settings, so it isn't meant for bulk load optimized
+    /// datastores. If the tuner is already enabled, its bounds are replaced.
+    ///
+    /// # Arguments
+    /// * `options` - The bounds to tune within, and how often to run.
+    ///
+    /// # Errors
+    /// Returns a `ValidationError` if the bounds are invalid.
+    pub fn enable_auto_tuning(&self, options: AutoTuneOptions) -> Result<()> {
+        options.validate()?;
+
+        {
+            let mut tuner = self.background.tuner.lock().unwrap();
+
+            if let Some(ref mut tuner) = *tuner {
+                tuner.set_options(options);
+                return self.background.workers.set_interval(AUTO_TUNE_TASK, options.interval);
+            }
+
+            *tuner = Some(Tuner::new(options));
+        }
+
+        let tuner = self.background.tuner.clone();
+        let changes = self.background.tuning_changes.clone();
+        self.background.workers.register(
+            &self.db,
+            AUTO_TUNE_TASK,
+            options.interval,
+            Box::new(move |db| match *tuner.lock().unwrap() {
+                Some(ref mut tuner) => tuner.run(db, &CF_NAMES, &read_write_pressure(db)?, &changes),
+                None => Ok(0),
+            }),
+        );
+        Ok(())
+    }
+
+    /// Gets the most recent changes that the auto-tuner made, oldest first.
+    /// Only the last 100 changes are kept.
+    pub fn get_tuning_changes(&self) -> Vec<TuningChange> {
+        self.background.tuning_changes.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Sets how long a background task waits between runs.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the task.
+    /// * `interval` - How long to wait between runs.
+    ///
+    /// # Errors
+    /// Returns a `NotFound` error if there's no task with the name.
+    pub fn set_background_task_interval(&self, name: &str, interval: Duration) -> Result<()> {
+        self.background.workers.set_interval(name, interval)
+    }
+
+    /// Tags a consistent snapshot of the datastore with a name, so that it
+    /// can be read later through `tagged_transaction`. The snapshot is a
+    /// rocksdb checkpoint, stored in a `.tags` directory next to the
+    /// database. Checkpoints hard-link the database's files where they can,
+    /// so they're cheap to take, but those files then take up disk space
+    /// until the tag is deleted, even once compaction has replaced them.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the tag. It can only contain ASCII letters,
+    ///   digits, `-`, `_` and `.`, and can't start with `.`.
+    ///
+    /// # Errors
+    /// Returns a `Conflict` error if the tag already exists.
+    pub fn create_tag(&self, name: &str) -> Result<()> {
+        validate_tag_name(name)?;
+        let tag_manager = TagManager::new(self.db.clone())?;
+
+        if tag_manager.get(name)?.is_some() {
+            return Err(ErrorKind::Conflict(format!("tag {}", name)).into());
+        }
+
+        // A checkpoint is left behind if recording its tag failed, so it's
+        // replaced
+        let path = tag_path(&self.db, name);
+
+        if path.exists() {
+            fs::remove_dir_all(&path)?;
+        } else if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        Checkpoint::new(&self.db)?.create_checkpoint(&path)?;
+
+        let mut batch = WriteBatch::default();
+        tag_manager.create(&mut batch, name, self.clock.now())?;
+        self.db.write(batch)?;
+        Ok(())
+    }
+
+    /// Gets the tags, ordered by name.
+    pub fn list_tags(&self) -> Result<Vec<SnapshotTag>> {
+        let tag_manager = TagManager::new(self.db.clone())?;
+
+        let tags = tag_manager
+            .iterate()?
+            .map(|item| {
+                let (name, created_datetime) = item?;
+                Ok(SnapshotTag { name, created_datetime })
+            })
+            .collect();
+        tags
+    }
+
+    /// Deletes a tag, along with its snapshot. Transactions pinned to the
+    /// tag should be dropped beforehand.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the tag.
+    ///
+    /// # Errors
+    /// Returns a `NotFound` error if the tag doesn't exist.
+    pub fn delete_tag(&self, name: &str) -> Result<()> {
+        let tag_manager = TagManager::new(self.db.clone())?;
+
+        if tag_manager.get(name)?.is_none() {
+            return Err(ErrorKind::NotFound(format!("tag {}", name)).into());
+        }
+
+        // The tag is deleted before its checkpoint, so that a failure
+        // leaves an unused checkpoint behind rather than a broken tag
+        let mut batch = WriteBatch::default();
+        tag_manager.delete(&mut batch, name)?;
+        self.db.write(batch)?;
+
+        self.tagged_datastores.lock().unwrap().remove(name);
+        fs::remove_dir_all(tag_path(&self.db, name))?;
+        Ok(())
+    }
+
+    /// Creates a read-only transaction that reads from a tag's snapshot,
+    /// rather than the current state of the datastore. Writes made through
+    /// it return a validation error.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the tag.
+    ///
+    /// # Errors
+    /// Returns a `NotFound` error if the tag doesn't exist.
+    pub fn tagged_transaction(&self, name: &str) -> Result<RocksdbTransaction> {
+        let mut tagged_datastores = self.tagged_datastores.lock().unwrap();
+
+        let datastore = match tagged_datastores.get(name) {
+            Some(datastore) => datastore.clone(),
+            None => {
+                if TagManager::new(self.db.clone())?.get(name)?.is_none() {
+                    return Err(ErrorKind::NotFound(format!("tag {}", name)).into());
+                }
+
+                let db = open_db(
+                    &tag_path(&self.db, name).to_string_lossy(),
+                    None,
+                    false,
+                    ResourceOptions::default(),
+                )?;
+                // Tags are read-only, so there's nothing for background tasks to do
+                let datastore = Arc::new(RocksdbDatastore {
+                    read_only: true,
+                    ..RocksdbDatastore::from_db(db, false)
+                });
+                tagged_datastores.insert(name.to_string(), datastore.clone());
+                datastore
+            }
+        };
+
+        datastore.transaction()
+    }
+}
+
+impl Datastore for RocksdbDatastore {
+    type Trans = RocksdbTransaction;
+
+    // We override the default `bulk_insert` implementation because further
+    // optimization can be done by using `WriteBatch`s.
+    fn bulk_insert<I>(&self, items: I) -> Result<()>
+    where
+        I: Iterator<Item = models::BulkInsertItem>,
+    {
+        let history = self.history_settings();
+        let vertex_manager = VertexManager::new(self.db.clone())?.with_history(history.clone());
+        let edge_manager = EdgeManager::new(self.db.clone())?
+            .with_log(self.edge_log)
+            .with_neighbor_sketches(self.neighbor_sketch_window)
+            .with_adjacency_bitmaps(self.adjacency_bitmaps.clone())
+            .with_history(history.clone());
+        let vertex_property_manager = VertexPropertyManager::new(self.db.clone())?
+            .with_cold_names(self.cold_properties.clone())
+            .with_history(history.clone());
+        let edge_property_manager = EdgePropertyManager::new(self.db.clone())?
+            .with_cold_names(self.cold_properties.clone())
+            .with_history(history);
+        let mut batch = WriteBatch::default();
+        let mut events = self.events.pending();
+
+        for item in items {
+            match item {
+                models::BulkInsertItem::Vertex(ref vertex) => {
+                    vertex_manager.create(&mut batch, vertex)?;
+                }
+                models::BulkInsertItem::Edge(ref key) => {
+                    edge_manager.set(&mut batch, key.outbound_id, &key.t, key.inbound_id, self.clock.now())?;
+                }
+                models::BulkInsertItem::VertexProperty(id, ref name, ref value) => {
+                    vertex_property_manager.set(&mut batch, id, name, value)?;
+                }
+                models::BulkInsertItem::EdgeProperty(ref key, ref name, ref value) => {
+                    edge_property_manager.set(&mut batch, key.outbound_id, &key.t, key.inbound_id, name, value)?;
+                }
+            }
+
+            events.push(|| match item {
+                models::BulkInsertItem::Vertex(vertex) => models::Event::VertexCreated(vertex),
+                models::BulkInsertItem::Edge(key) => models::Event::EdgeCreated(key),
+                models::BulkInsertItem::VertexProperty(id, name, value) => {
+                    models::Event::VertexPropertySet(id, name, value)
+                }
+                models::BulkInsertItem::EdgeProperty(key, name, value) => {
+                    models::Event::EdgePropertySet(key, name, value)
+                }
+            });
+        }
+
+        // NOTE: syncing and WAL are disabled for bulk inserts to maximimze
+        // performance
+        let opts = CommitOptions::new().disable_wal(true).to_write_options()?;
+        self.db.write_opt(batch, &opts)?;
+        events.publish();
+        Ok(())
+    }
+
+    fn transaction(&self) -> Result<Self::Trans> {
+        self.transaction_with_options(CommitOptions::default())
+    }
+
+    // Only changes made through this datastore's transactions are
+    // published; changes made by batches or by other processes aren't.
+    fn subscribe(&self, filter: models::EventFilter) -> Result<Receiver<models::Event>> {
+        Ok(self.events.subscribe(filter))
+    }
+
+    fn get_idempotency_record(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        IdempotencyManager::new(self.db.clone())?.get(key, Utc::now())
+    }
+
+    fn set_idempotency_record(&self, key: &str, response: &[u8], expires_at: DateTime<Utc>) -> Result<()> {
+        // Empty values would be mistaken for ones without an expiry
+        if response.is_empty() {
+            return Err(ValidationError::from("Idempotency records cannot be empty").into());
+        }
+
+        let mut batch = WriteBatch::default();
+        IdempotencyManager::new(self.db.clone())?.set(&mut batch, key, response, expires_at)?;
+        self.db.write(batch)?;
+        Ok(())
+    }
+
+    fn get_write_pressure(&self) -> Result<models::WritePressure> {
+        read_write_pressure(&self.db)
+    }
+
+    // Only secondary instances follow another database; the others are
+    // either the primary or a snapshot of one.
+    fn catch_up(&self) -> Result<()> {
+        if self.secondary {
+            self.catch_up_with_primary()
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// A transaction that is backed by rocksdb.
+#[derive(Debug)]
+pub struct RocksdbTransaction {
+    db: Arc<DB>,
+    // Vertices that are in the process of being deleted in chunks, which
+    // reads should treat as already deleted.
+    tombstones: Arc<RwLock<HashSet<Uuid>>>,
+    background: Arc<Background>,
+    index_backfills: IndexBackfills,
+    events: EventBus,
+    clock: Arc<dyn Clock>,
+    options: CommitOptions,
+    scan_options: ScanOptions,
+    // Whether writes are rejected, for transactions pinned to a tag or
+    // created by a read-only datastore.
+    read_only: bool,
+    // Whether edges are written to the edge log.
+    edge_log: bool,
+    // The width of the windows edges are counted in by the inbound neighbor
+    // sketches, if they're counted at all.
+    neighbor_sketch_window: Option<Duration>,
+    // The settings for adjacency bitmaps, if they're kept at all.
+    adjacency_bitmaps: Option<Arc<AdjacencyBitmapSettings>>,
+    // The names of the properties stored in the cold column families.
+    cold_properties: Arc<HashSet<String>>,
+    // The settings for recording history, if it's recorded at all.
+    history: Option<Arc<HistorySettings>>,
+    // The number of keys read by this transaction's queries.
+    keys_scanned: Arc<AtomicU64>,
+}
+
+impl RocksdbTransaction {
+    fn new(
+        db: Arc<DB>,
+        tombstones: Arc<RwLock<HashSet<Uuid>>>,
+        background: Arc<Background>,
+        index_backfills: IndexBackfills,
+        events: EventBus,
+        clock: Arc<dyn Clock>,
+        options: CommitOptions,
+    ) -> Result<Self> {
+        // Validate the options up-front rather than on the first write
+        options.to_write_options()?;
+        Ok(RocksdbTransaction {
+            db,
+            tombstones,
+            background,
+            index_backfills,
+            events,
+            clock,
+            options,
+            scan_options: ScanOptions::default(),
+            read_only: false,
+            edge_log: false,
+            neighbor_sketch_window: None,
+            adjacency_bitmaps: None,
+            cold_properties: Arc::new(HashSet::new()),
+            history: None,
+            keys_scanned: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    /// Sets how this transaction's queries read from rocksdb.
+    ///
+    /// # Arguments
+    /// * `scan_options` - The scan options.
+    pub fn with_scan_options(self, scan_options: ScanOptions) -> Self {
+        RocksdbTransaction { scan_options, ..self }
+    }
+
+    /// Deletes vertices that match a query, along with their properties and
+    /// edges, committing the deletion in multiple bounded batches rather than
+    /// one. This keeps memory usage bounded when deleting vertices with a
+    /// very large number of edges. While a vertex is being deleted, reads
+    /// from this datastore treat it and its edges as already deleted. The
+    /// vertex itself is deleted in the last batch, so if the process crashes
+    /// partway through, the vertex still exists and the deletion can be
+    /// retried.
+    ///
+    /// # Arguments
+    /// * `q` - The query to run.
+    /// * `chunk_size` - The maximum number of properties and edges to delete
+    ///   per batch.
+    pub fn delete_vertices_in_chunks<Q: Into<models::VertexQuery>>(&self, q: Q, chunk_size: usize) -> Result<()> {
+        if chunk_size == 0 {
+            return Err(ValidationError::from("Chunk size must be greater than zero").into());
+        }
+
+        let vertices: Vec<VertexItem> = self
+            .vertex_query_to_iterator(q.into())?
+            .collect::<Result<Vec<VertexItem>>>()?;
+        let vertex_manager = VertexManager::new(self.db.clone())?.with_history(self.history.clone());
+
+        for (id, t) in vertices {
+            self.tombstones.write().unwrap().insert(id);
+            let result = vertex_manager.delete_in_chunks(id, chunk_size, |batch| self.write(batch));
+            self.tombstones.write().unwrap().remove(&id);
+            result?;
+
+            let mut events = self.events.pending();
+            events.push(|| models::Event::VertexDeleted(models::Vertex::with_id(id, t)));
+            events.publish();
+        }
+
+        Ok(())
+    }
+
+    /// Deletes vertices that match a query, along with their properties and
+    /// edges, in the background. The vertices are treated as deleted by
+    /// reads from this datastore as soon as this returns, while the
+    /// `tombstones` background task incrementally purges them in bounded
+    /// batches. The pending deletions only exist in memory, so if the process
+    /// exits before they finish, the vertices reappear.
+    ///
+    /// # Arguments
+    /// * `q` - The query to run.
+    pub fn delete_vertices_in_background<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<()> {
+        self.check_writable()?;
+
+        let vertices: Vec<VertexItem> = self
+            .vertex_query_to_iterator(q.into())?
+            .collect::<Result<Vec<VertexItem>>>()?;
+        self.check_writable()?;
+
+        // Events are published as soon as the vertices are treated as
+        // deleted, rather than once they're purged
+        for (id, t) in vertices {
+            self.tombstones.write().unwrap().insert(id);
+            self.background
+                .deletions
+                .lock()
+                .unwrap()
+                .push_back((id, self.history.clone()));
+
+            let mut events = self.events.pending();
+            events.push(|| models::Event::VertexDeleted(models::Vertex::with_id(id, t)));
+            events.publish();
+        }
+
+        self.background.workers.run(TOMBSTONE_TASK)
+    }
+
+    /// Sets property values that expire at a given datetime. Once expired,
+    /// the values are treated as deleted by reads, and are eventually purged
+    /// by compaction. Setting the property again replaces the expiry.
+    ///
+    /// Expired values aren't removed from property indexes until the
+    /// property is set again or deleted, but they're excluded from scans.
+    ///
+    /// # Arguments
+    /// * `q` - The query to run.
+    /// * `value` - The property value.
+    /// * `expires_at` - When the property value expires.
+    pub fn set_vertex_properties_with_expiry(
+        &self,
+        q: VertexPropertyQuery,
+        value: &JsonValue,
+        expires_at: DateTime<Utc>,
+    ) -> Result<()> {
+        let manager = self.vertex_property_manager()?;
+        let mut batch = WriteBatch::default();
+        let mut events = self.events.pending();
+        let name = &q.name;
+
+        for item in self.vertex_query_to_iterator(q.inner)? {
+            let (id, _) = item?;
+            manager.set_with_expiry(&mut batch, id, &q.name, value, Some(expires_at))?;
+            events.push(|| models::Event::VertexPropertySet(id, name.clone(), value.clone()));
+        }
+
+        self.write(batch)?;
+        events.publish();
+        Ok(())
+    }
+
+    /// Sets edge property values that expire at a given datetime. See
+    /// `set_vertex_properties_with_expiry` for how expiry works.
+    ///
+    /// # Arguments
+    /// * `q` - The query to run.
+    /// * `value` - The property value.
+    /// * `expires_at` - When the property value expires.
+    pub fn set_edge_properties_with_expiry(
+        &self,
+        q: EdgePropertyQuery,
+        value: &JsonValue,
+        expires_at: DateTime<Utc>,
+    ) -> Result<()> {
+        let manager = self.edge_property_manager()?;
+        let mut batch = WriteBatch::default();
+        let mut events = self.events.pending();
+        let name = &q.name;
+
+        for item in self.edge_query_to_iterator(q.inner)? {
+            let (outbound_id, t, _, inbound_id) = item?;
+            manager.set_with_expiry(
+                &mut batch,
+                outbound_id,
+                &t,
+                inbound_id,
+                &q.name,
+                value,
+                Some(expires_at),
+            )?;
+            events.push(|| {
+                let key = models::EdgeKey::new(outbound_id, t, inbound_id);
+                models::Event::EdgePropertySet(key, name.clone(), value.clone())
+            });
+        }
+
+        self.write(batch)?;
+        events.publish();
+        Ok(())
+    }
+
+    /// Gets a page of a vertex's edges of a given type, newest first. Edges
+    /// with the same update datetime are ordered by the ID of the vertex on
+    /// their other end, so a cursor made of both uniquely identifies a
+    /// position, and paging never re-reads or skips edges that share a
+    /// datetime.
+    ///
+    /// # Arguments
+    /// * `id` - The ID of the vertex.
+    /// * `t` - The type of edges to get.
+    /// * `direction` - Whether to get outbound or inbound edges. Pages can't
+    ///   be fetched in both directions, since the cursor only orders edges
+    ///   within one edge range.
+    /// * `after` - The cursor to resume after: the update datetime of the
+    ///   last edge in the previous page, and the ID of the vertex on its
+    ///   other end. If `None`, the first page is returned.
+    /// * `limit` - The maximum number of edges to return.
+    pub fn get_edge_page(
+        &self,
+        id: Uuid,
+        t: &models::Type,
+        direction: EdgeDirection,
+        after: Option<(DateTime<Utc>, Uuid)>,
+        limit: u32,
+    ) -> Result<Vec<models::Edge>> {
+        let tombstones = self.tombstones();
+
+        if tombstones.contains(&id) {
+            return Ok(Vec::new());
+        }
+
+        let edge_range_manager = match direction {
+            EdgeDirection::Outbound => EdgeRangeManager::new(self.db.clone())?.with_read_options(self.read_options()),
+            EdgeDirection::Inbound => {
+                EdgeRangeManager::new_reversed(self.db.clone())?.with_read_options(self.read_options())
+            }
+            EdgeDirection::Both => {
+                return Err(ValidationError::from("Edge pages can't be fetched in both directions").into())
+            }
+        };
+
+        let mut edges = Vec::new();
+
+        for item in edge_range_manager.iterate_for_range_after(id, t, after)? {
+            if edges.len() == limit as usize {
+                break;
+            }
+
+            let (first_id, t, update_datetime, second_id) = item?;
+
+            if tombstones.contains(&second_id) {
+                continue;
+            }
+
+            let key = match direction {
+                EdgeDirection::Inbound => models::EdgeKey::new(second_id, t, first_id),
+                _ => models::EdgeKey::new(first_id, t, second_id),
+            };
+
+            edges.push(models::Edge::new(key, update_datetime));
+        }
+
+        Ok(edges)
+    }
+
+    /// Gets the edges that have a property with the given name, along with
+    /// the property's value, ordered by edge key. Edge properties are
+    /// indexed by name, so this doesn't enumerate every edge.
+    ///
+    /// # Arguments
+    /// * `name` - The property name.
+    /// * `after` - The cursor to resume after: the key of the last edge in
+    ///   the previous page. If `None`, the first page is returned.
+    /// * `limit` - The maximum number of edge properties to return.
+    pub fn get_edge_properties_by_name(
+        &self,
+        name: &str,
+        after: Option<&models::EdgeKey>,
+        limit: u32,
+    ) -> Result<Vec<models::EdgeProperty>> {
+        let tombstones = self.tombstones();
+        let edge_property_manager = self.edge_property_manager()?;
+        let mut properties = Vec::new();
+
+        for item in edge_property_manager.iterate_for_name(name, after)? {
+            if properties.len() == limit as usize {
+                break;
+            }
+
+            let ((outbound_id, t, inbound_id, _), value) = item?;
+
+            if tombstones.contains(&outbound_id) || tombstones.contains(&inbound_id) {
+                continue;
+            }
+
+            let key = models::EdgeKey::new(outbound_id, t, inbound_id);
+            properties.push(models::EdgeProperty::new(key, value));
+        }
+
+        Ok(properties)
+    }
+
+    /// Gets the edges of a type, across all vertices, newest first. Edges
+    /// are indexed by type and update datetime, so this is a single range
+    /// scan rather than a scan of every vertex's edges.
+    ///
+    /// # Arguments
+    /// * `t` - The edge type.
+    /// * `low` - If set, only edges updated at or after this datetime are
+    ///   returned.
+    /// * `high` - If set, only edges updated at or before this datetime are
+    ///   returned.
+    /// * `limit` - The maximum number of edges to return.
+    pub fn get_edges_by_type(
+        &self,
+        t: &models::Type,
+        low: Option<DateTime<Utc>>,
+        high: Option<DateTime<Utc>>,
+        limit: u32,
+    ) -> Result<Vec<models::Edge>> {
+        let tombstones = self.tombstones();
+        let edge_manager = EdgeManager::new(self.db.clone())?;
+        let mut edges = Vec::new();
+
+        for item in edge_manager.iterate_for_type(t, high)? {
+            if edges.len() == limit as usize {
+                break;
+            }
+
+            let (outbound_id, t, update_datetime, inbound_id) = item?;
+
+            if let Some(low) = low {
+                if update_datetime < low {
+                    break;
+                }
+            }
+
+            if tombstones.contains(&outbound_id) || tombstones.contains(&inbound_id) {
+                continue;
+            }
+
+            let key = models::EdgeKey::new(outbound_id, t, inbound_id);
+            edges.push(models::Edge::new(key, update_datetime));
+        }
+
+        Ok(edges)
+    }
+
+    /// Gets the number of vertices and edges of each type, without scanning
+    /// them. The counts are kept up to date on every write. They can drift
+    /// if a batch or bulk insert writes the same vertex or edge more than
+    /// once, or deletes a vertex or edge it created, since those writes
+    /// don't see each other; `RocksdbDatastore::recount_type_stats` fixes
+    /// them. Vertices that are pending deletion are still counted.
+    pub fn get_type_stats(&self) -> Result<TypeStats> {
+        // Types that have been counted down to zero are left out
+        let read_counts = |manager: TypeCountManager<DB>| -> Result<BTreeMap<models::Type, u64>> {
+            let mut counts = BTreeMap::new();
+
+            for item in manager.iterate()? {
+                let (t, count) = item?;
+
+                if count > 0 {
+                    counts.insert(t, count as u64);
+                }
+            }
+
+            Ok(counts)
+        };
+
+        Ok(TypeStats {
+            vertex_counts: read_counts(TypeCountManager::new_for_vertices(self.db.clone())?)?,
+            edge_counts: read_counts(TypeCountManager::new_for_edges(self.db.clone())?)?,
+        })
+    }
+
+    /// Gets the most recently created or updated edges in the whole graph,
+    /// newest first, from the edge log. The log has to be enabled with
+    /// `RocksdbDatastore::with_edge_log`; edges written while it's disabled
+    /// aren't returned.
+    ///
+    /// # Arguments
+    /// * `high` - If set, only edges updated at or before this datetime are
+    ///   returned. This can be used to page through the log.
+    /// * `limit` - The maximum number of edges to return.
+    pub fn get_newest_edges(&self, high: Option<DateTime<Utc>>, limit: u32) -> Result<Vec<models::Edge>> {
+        let tombstones = self.tombstones();
+        let edge_manager = EdgeManager::new(self.db.clone())?;
+        let mut edges = Vec::new();
+
+        for item in edge_manager.iterate_log(high)? {
+            if edges.len() == limit as usize {
+                break;
+            }
+
+            let (outbound_id, t, update_datetime, inbound_id) = item?;
+
+            if tombstones.contains(&outbound_id) || tombstones.contains(&inbound_id) {
+                continue;
+            }
+
+            let key = models::EdgeKey::new(outbound_id, t, inbound_id);
+            edges.push(models::Edge::new(key, update_datetime));
+        }
+
+        Ok(edges)
+    }
+
+    /// Gets a sketch of the distinct vertices with edges going into a
+    /// vertex, whose `estimate` is roughly how many there are. Sketches of
+    /// different vertices can be merged to estimate how many distinct
+    /// vertices have edges going into any of them. The sketches have to be
+    /// enabled with `RocksdbDatastore::with_neighbor_sketches`; edges
+    /// written while they're disabled aren't counted. Sketches can't forget
+    /// items, so edges that have since been deleted are still counted.
+    ///
+    /// # Arguments
+    /// * `id` - The id of the vertex.
+    /// * `t` - If set, only edges of this type are counted.
+    /// * `low` - If set, only edges updated at or after this datetime are
+    ///   counted, rounded down to the start of its window.
+    /// * `high` - If set, only edges updated at or before this datetime are
+    ///   counted, rounded up to the end of its window.
+    pub fn get_inbound_neighbor_sketch(
+        &self,
+        id: Uuid,
+        t: Option<&models::Type>,
+        low: Option<DateTime<Utc>>,
+        high: Option<DateTime<Utc>>,
+    ) -> Result<HyperLogLog> {
+        let mut sketch = HyperLogLog::new(NEIGHBOR_SKETCH_PRECISION)?;
+
+        if self.tombstones().contains(&id) {
+            return Ok(sketch);
+        }
+
+        let round_down = |datetime: DateTime<Utc>| match self.neighbor_sketch_window {
+            Some(window) => neighbor_sketch_window_start(datetime, window),
+            None => datetime,
+        };
+
+        let low = low.map(round_down);
+        let high = high.map(round_down);
+
+        for item in NeighborSketchManager::new(self.db.clone())?.iterate_for_owner(id, t)? {
+            let ((_, window_start), window_sketch) = item?;
+
+            if low.map_or(false, |low| window_start < low) || high.map_or(false, |high| window_start > high) {
+                continue;
+            }
+
+            sketch.merge(&window_sketch)?;
+        }
+
+        Ok(sketch)
+    }
+
+    /// Gets a bitmap of the neighbors of a high-degree vertex by an edge
+    /// type, as the integer ids that `resolve_neighbor_ids` maps back to
+    /// vertex ids. Bitmaps share the same ids, so the neighbors that two
+    /// vertices have in common, e.g. their mutual followers, are the
+    /// intersection of their bitmaps. The bitmaps have to be enabled with
+    /// `RocksdbDatastore::with_adjacency_bitmaps`.
+    ///
+    /// Returns `None` if the vertex doesn't have a bitmap, e.g. because it
+    /// doesn't have enough edges, so its edges have to be scanned instead.
+    ///
+    /// # Arguments
+    /// * `id` - The id of the vertex.
+    /// * `t` - The type of the edges.
+    /// * `direction` - Whether to get the inbound vertices of the vertex's
+    ///   outbound edges, the outbound vertices of its inbound edges, or
+    ///   both. Both is only returned if the vertex has both bitmaps.
+    pub fn get_adjacency_bitmap(
+        &self,
+        id: Uuid,
+        t: &models::Type,
+        direction: EdgeDirection,
+    ) -> Result<Option<RoaringBitmap>> {
+        if self.tombstones().contains(&id) {
+            return Ok(None);
+        }
+
+        let adjacency_bitmap_manager = AdjacencyBitmapManager::new(self.db.clone())?;
+        let reversed_adjacency_bitmap_manager = AdjacencyBitmapManager::new_reversed(self.db.clone())?;
+
+        match direction {
+            EdgeDirection::Outbound => adjacency_bitmap_manager.get(id, t),
+            EdgeDirection::Inbound => reversed_adjacency_bitmap_manager.get(id, t),
+            EdgeDirection::Both => {
+                match (
+                    adjacency_bitmap_manager.get(id, t)?,
+                    reversed_adjacency_bitmap_manager.get(id, t)?,
+                ) {
+                    (Some(outbound), Some(inbound)) => Ok(Some(outbound | inbound)),
+                    _ => Ok(None),
+                }
+            }
+        }
+    }
+
+    /// Maps the neighbor ids in an adjacency bitmap back to vertex ids, in
+    /// the order of their neighbor ids. Vertices that are pending deletion
+    /// are skipped.
+    ///
+    /// # Arguments
+    /// * `bitmap` - The bitmap, e.g. as returned by `get_adjacency_bitmap`.
+    ///
+    /// # Errors
+    /// Returns a `Corrupt` error if the bitmap has an id that was never
+    /// allocated.
+    pub fn resolve_neighbor_ids(&self, bitmap: &RoaringBitmap) -> Result<Vec<Uuid>> {
+        let tombstones = self.tombstones();
+        let neighbor_id_manager = NeighborIdManager::new(self.db.clone())?;
+        let mut ids = Vec::with_capacity(bitmap.len() as usize);
+
+        for neighbor_id in bitmap {
+            match neighbor_id_manager.get_vertex_id(neighbor_id)? {
+                Some(id) => {
+                    if !tombstones.contains(&id) {
+                        ids.push(id);
+                    }
+                }
+                None => return Err(ErrorKind::Corrupt(format!("unknown neighbor id {}", neighbor_id)).into()),
+            }
+        }
+
+        Ok(ids)
+    }
+
+    /// Scans every vertex property in the datastore, ordered by vertex id
+    /// and then name. This reads the property column family directly rather
+    /// than going vertex-by-vertex, so it's suited to building external
+    /// indexes and verification jobs.
+    ///
+    /// # Arguments
+    /// * `after` - The cursor to resume after: the vertex id and name of the
+    ///   last property in the previous page. If `None`, the first page is
+    ///   returned.
+    /// * `limit` - The maximum number of vertex properties to return.
+    pub fn scan_vertex_properties(
+        &self,
+        after: Option<(Uuid, &str)>,
+        limit: u32,
+    ) -> Result<Vec<models::NamedVertexProperty>> {
+        let tombstones = self.tombstones();
+        let vertex_property_manager = self.vertex_property_manager()?;
+        let mut properties = Vec::new();
+
+        for item in vertex_property_manager.iterate_all(after)? {
+            if properties.len() == limit as usize {
+                break;
+            }
+
+            let ((id, name), value) = item?;
+
+            if tombstones.contains(&id) {
+                continue;
+            }
+
+            properties.push(models::NamedVertexProperty::new(id, name, value));
+        }
+
+        Ok(properties)
+    }
+
+    /// Scans every edge property in the datastore, ordered by edge key and
+    /// then name. This reads the property column family directly rather
+    /// than going edge-by-edge, so it's suited to building external indexes
+    /// and verification jobs.
+    ///
+    /// # Arguments
+    /// * `after` - The cursor to resume after: the edge key and name of the
+    ///   last property in the previous page. If `None`, the first page is
+    ///   returned.
+    /// * `limit` - The maximum number of edge properties to return.
+    pub fn scan_edge_properties(
+        &self,
+        after: Option<(&models::EdgeKey, &str)>,
+        limit: u32,
+    ) -> Result<Vec<models::NamedEdgeProperty>> {
+        let tombstones = self.tombstones();
+        let edge_property_manager = self.edge_property_manager()?;
+        let mut properties = Vec::new();
+
+        for item in edge_property_manager.iterate_all(after)? {
+            if properties.len() == limit as usize {
+                break;
+            }
+
+            let ((outbound_id, t, inbound_id, name), value) = item?;
+
+            if tombstones.contains(&outbound_id) || tombstones.contains(&inbound_id) {
+                continue;
+            }
+
+            let key = models::EdgeKey::new(outbound_id, t, inbound_id);
+            properties.push(models::NamedEdgeProperty::new(key, name, value));
+        }
+
+        Ok(properties)
+    }
+
+    /// Reads a vertex property's raw JSON value in place, passing it to `f`
+    /// rather than copying it out of rocksdb, which saves an allocation per
+    /// read on hot paths. Returns `None` if the vertex doesn't have the
+    /// property.
+    ///
+    /// # Arguments
+    /// * `id` - The id of the vertex.
+    /// * `name` - The property name.
+    /// * `f` - Decodes the value, which is only borrowed for the call.
+    pub fn with_raw_vertex_property<T, F>(&self, id: Uuid, name: &str, f: F) -> Result<Option<T>>
+    where
+        F: FnOnce(&[u8]) -> Result<T>,
+    {
+        if self.tombstones.read().unwrap().contains(&id) {
+            return Ok(None);
+        }
+
+        self.vertex_property_manager()?.get_with(id, name, f)
+    }
+
+    /// Reads an edge property's raw JSON value in place, passing it to `f`
+    /// rather than copying it out of rocksdb. Returns `None` if the edge
+    /// doesn't have the property.
+    ///
+    /// # Arguments
+    /// * `key` - The edge.
+    /// * `name` - The property name.
+    /// * `f` - Decodes the value, which is only borrowed for the call.
+    pub fn with_raw_edge_property<T, F>(&self, key: &models::EdgeKey, name: &str, f: F) -> Result<Option<T>>
+    where
+        F: FnOnce(&[u8]) -> Result<T>,
+    {
+        {
+            let tombstones = self.tombstones.read().unwrap();
+
+            if tombstones.contains(&key.outbound_id) || tombstones.contains(&key.inbound_id) {
+                return Ok(None);
+            }
+        }
+
+        self.edge_property_manager()?
+            .get_with(key.outbound_id, &key.t, key.inbound_id, name, f)
+    }
+
+    /// Creates a property index. Writes made after this returns are indexed
+    /// immediately, while vertices that already exist are indexed by the
+    /// `index_backfills` background task; until it's done, scans of the
+    /// index may be missing some of them. Use `get_index_backfill_progress`
+    /// to check on the backfill. The backfill only exists in memory, so if
+    /// the process exits before it's done, the index has to be dropped and
+    /// re-created.
+    ///
+    /// # Arguments
+    /// * `index` - The index to create.
+    ///
+    /// # Errors
+    /// Returns an error if an index with the same name already exists.
+    pub fn create_index(&self, index: &models::PropertyIndex) -> Result<()> {
+        let manager = IndexManager::new(self.db.clone())?;
+
+        if manager.get(&index.name)?.is_some() {
+            return Err(ErrorKind::Conflict(format!("index {}", index.name)).into());
+        }
+
+        let mut batch = WriteBatch::default();
+        manager.create(&mut batch, index)?;
+        self.write(batch)?;
+
+        self.index_backfills
+            .write()
+            .unwrap()
+            .insert(index.name.clone(), IndexBackfillProgress::default());
+        self.background.backfills.lock().unwrap().push_back(index.clone());
+        self.background.workers.run(INDEX_BACKFILL_TASK)
+    }
+
+    /// Drops a property index, along with all of its entries.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the index.
+    ///
+    /// # Errors
+    /// Returns an error if the index doesn't exist.
+    pub fn drop_index(&self, name: &str) -> Result<()> {
+        let manager = IndexManager::new(self.db.clone())?;
+
+        if manager.get(name)?.is_none() {
+            return Err(ErrorKind::NotFound(format!("index {}", name)).into());
+        }
 
-    // We override the default `bulk_insert` implementation because further
-    // optimization can be done by using `WriteBatch`s.
-    fn bulk_insert<I>(&self, items: I) -> Result<()>
-    where
-        I: Iterator<Item = models::BulkInsertItem>,
-    {
-        let vertex_manager = VertexManager::new(self.db.clone());
-        let edge_manager = EdgeManager::new(self.db.clone());
-        let vertex_property_manager = VertexPropertyManager::new(self.db.clone());
-        let edge_property_manager = EdgePropertyManager::new(self.db.clone());
+        // The definition is deleted first, so that writes stop maintaining
+        // the index and any backfill stops.
         let mut batch = WriteBatch::default();
+        manager.delete(&mut batch, name)?;
+        self.write(batch)?;
+        self.index_backfills.write().unwrap().remove(name);
 
-        for item in items {
-            match item {
-                models::BulkInsertItem::Vertex(ref vertex) => {
-                    vertex_manager.create(&mut batch, vertex)?;
-                }
-                models::BulkInsertItem::Edge(ref key) => {
-                    edge_manager.set(&mut batch, key.outbound_id, &key.t, key.inbound_id, Utc::now())?;
-                }
-                models::BulkInsertItem::VertexProperty(id, ref name, ref value) => {
-                    vertex_property_manager.set(&mut batch, id, name, value)?;
-                }
-                models::BulkInsertItem::EdgeProperty(ref key, ref name, ref value) => {
-                    edge_property_manager.set(&mut batch, key.outbound_id, &key.t, key.inbound_id, name, value)?;
-                }
+        let mut batch = WriteBatch::default();
+        let mut batch_size = 0;
+
+        for item in manager.iterate_entries(name)? {
+            let (key, _) = item?;
+            manager.delete_entry(&mut batch, &key)?;
+            batch_size += 1;
+
+            if batch_size == INDEX_DROP_CHUNK_SIZE {
+                self.write(mem::replace(&mut batch, WriteBatch::default()))?;
+                batch_size = 0;
             }
         }
 
-        // NOTE: syncing and WAL are disabled for bulk inserts to maximimze
-        // performance
-        let mut opts = WriteOptions::default();
-        opts.set_sync(false);
-        opts.disable_wal(true);
-
-        self.db.write_opt(batch, &opts)?;
-        Ok(())
+        self.write(batch)
     }
 
-    fn transaction(&self) -> Result<Self::Trans> {
-        RocksdbTransaction::new(self.db.clone())
+    /// Lists all of the property indexes, ordered by name.
+    pub fn list_indexes(&self) -> Result<Vec<models::PropertyIndex>> {
+        let manager = IndexManager::new(self.db.clone())?;
+        let indexes = manager.iterate()?.collect();
+        indexes
     }
-}
 
-/// A transaction that is backed by rocksdb.
-#[derive(Debug)]
-pub struct RocksdbTransaction {
-    db: Arc<DB>,
-}
+    /// Gets the progress of an index's backfill. Returns `None` if the index
+    /// wasn't created by this process, or has since been dropped.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the index.
+    pub fn get_index_backfill_progress(&self, name: &str) -> Option<IndexBackfillProgress> {
+        self.index_backfills.read().unwrap().get(name).cloned()
+    }
 
-impl RocksdbTransaction {
-    fn new(db: Arc<DB>) -> Result<Self> {
-        Ok(RocksdbTransaction { db })
+    /// Gets vertices by a range of their indexed property values, ordered
+    /// by those values. The bounds are tuples of property values, which are
+    /// compared against the index's properties in order. A bound with fewer
+    /// values than the index has properties is compared against just the
+    /// leading properties; e.g. with an index over `(country, city)`, the
+    /// bounds `["US"]` and `["US"]` get every vertex in the US, and empty
+    /// bounds get every indexed vertex.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the index.
+    /// * `low` - The inclusive lower bound.
+    /// * `high` - The inclusive upper bound.
+    /// * `limit` - The maximum number of vertices to return.
+    ///
+    /// # Errors
+    /// Returns an error if the index doesn't exist, or if either bound has
+    /// more values than the index has properties.
+    pub fn get_vertices_by_index(
+        &self,
+        name: &str,
+        low: &[JsonValue],
+        high: &[JsonValue],
+        limit: u32,
+    ) -> Result<Vec<models::Vertex>> {
+        let results = self.scan_index(name, low, high, models::IndexOrder::Ascending, limit)?;
+        Ok(results.into_iter().map(|result| result.vertex).collect())
     }
 
-    fn vertex_query_to_iterator(&self, q: VertexQuery) -> Result<Box<dyn Iterator<Item = Result<VertexItem>>>> {
-        match q {
-            VertexQuery::Range(q) => {
-                let vertex_manager = VertexManager::new(self.db.clone());
-
-                let next_uuid = match q.start_id {
-                    Some(start_id) => {
-                        match next_uuid(start_id) {
-                            Ok(next_uuid) => next_uuid,
-                            // If we get an error back, it's because
-                            // `start_id` is the maximum possible value. We
-                            // know that no vertices exist whose ID is greater
-                            // than the maximum possible value, so just return
-                            // an empty list.
-                            Err(_) => return Ok(Box::new(vec![].into_iter())),
-                        }
-                    }
-                    None => Uuid::default(),
-                };
+    /// Scans a range of an index in either order, returning vertices along
+    /// with the values of their indexed properties. Since results come back
+    /// in index order, this can answer e.g. "the top 10 vertices by score"
+    /// without sorting. See `get_vertices_by_index` for how the bounds work.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the index.
+    /// * `low` - The inclusive lower bound.
+    /// * `high` - The inclusive upper bound.
+    /// * `order` - The order to scan in.
+    /// * `limit` - The maximum number of vertices to return.
+    ///
+    /// # Errors
+    /// Returns an error if the index doesn't exist, or if either bound has
+    /// more values than the index has properties.
+    pub fn scan_index(
+        &self,
+        name: &str,
+        low: &[JsonValue],
+        high: &[JsonValue],
+        order: models::IndexOrder,
+        limit: u32,
+    ) -> Result<Vec<models::IndexedVertex>> {
+        let index_manager = IndexManager::new(self.db.clone())?;
+
+        let index = match index_manager.get(name)? {
+            Some(index) => index,
+            None => return Err(ErrorKind::NotFound(format!("index {}", name)).into()),
+        };
 
-                let mut iter: Box<dyn Iterator<Item = Result<VertexItem>>> =
-                    Box::new(vertex_manager.iterate_for_range(next_uuid)?);
+        if low.len() > index.properties.len() || high.len() > index.properties.len() {
+            return Err(ValidationError::from("Index bounds have more values than the index has properties").into());
+        }
 
-                if let Some(ref t) = q.t {
-                    iter = Box::new(iter.filter(move |item| match item {
-                        Ok((_, v)) => v == t,
-                        Err(_) => true,
-                    }));
-                }
+        let vertex_manager = VertexManager::new(self.db.clone())?;
+        let tombstones = self.tombstones();
+        let mut results = Vec::new();
+        // Multi-valued indexes can have multiple entries per vertex in range
+        let mut seen_ids = HashSet::new();
 
-                let results: Vec<Result<VertexItem>> = iter.take(q.limit as usize).collect();
-                Ok(Box::new(results.into_iter()))
+        for item in index_manager.iterate_for_range(name, low, high, order)? {
+            if results.len() == limit as usize {
+                break;
             }
-            VertexQuery::Specific(q) => {
-                let vertex_manager = VertexManager::new(self.db.clone());
 
-                let iter = q.ids.into_iter().map(move |id| match vertex_manager.get(id)? {
-                    Some(value) => Ok(Some((id, value))),
-                    None => Ok(None),
-                });
+            let (key, id) = item?;
 
-                Ok(Box::new(remove_nones_from_iterator(iter)))
+            if tombstones.contains(&id) || (index.multi_valued && seen_ids.contains(&id)) {
+                continue;
             }
-            VertexQuery::Pipe(q) => {
-                let vertex_manager = VertexManager::new(self.db.clone());
-                let edge_iterator = self.edge_query_to_iterator(*q.inner)?;
-                let direction = q.direction;
 
-                let iter = edge_iterator.map(move |item| {
-                    let (outbound_id, _, _, inbound_id) = item?;
+            let values = match index_manager.get_current_values(&index, &key, id)? {
+                Some(values) => values,
+                None => continue,
+            };
 
-                    let id = match direction {
-                        EdgeDirection::Outbound => outbound_id,
-                        EdgeDirection::Inbound => inbound_id,
-                    };
+            seen_ids.insert(id);
 
-                    match vertex_manager.get(id)? {
-                        Some(value) => Ok(Some((id, value))),
-                        None => Ok(None),
-                    }
+            if let Some(t) = vertex_manager.get(id)? {
+                results.push(models::IndexedVertex {
+                    vertex: models::Vertex::with_id(id, t),
+                    values,
                 });
+            }
+        }
 
-                let mut iter: Box<dyn Iterator<Item = Result<VertexItem>>> = Box::new(remove_nones_from_iterator(iter));
+        Ok(results)
+    }
 
-                if let Some(ref t) = q.t {
-                    iter = Box::new(iter.filter(move |item| match item {
-                        Ok((_, v)) => v == t,
-                        Err(_) => true,
-                    }));
-                }
+    fn tombstones(&self) -> HashSet<Uuid> {
+        self.tombstones.read().unwrap().clone()
+    }
 
-                let results: Vec<Result<VertexItem>> = iter.take(q.limit as usize).collect();
-                Ok(Box::new(results.into_iter()))
-            }
+    fn check_writable(&self) -> Result<()> {
+        if self.read_only {
+            Err(ValidationError::from("Cannot write through a read-only transaction").into())
+        } else {
+            Ok(())
         }
     }
 
-    fn edge_query_to_iterator(&self, q: EdgeQuery) -> Result<Box<dyn Iterator<Item = Result<EdgeRangeItem>>>> {
-        match q {
-            EdgeQuery::Specific(q) => {
-                let edge_manager = EdgeManager::new(self.db.clone());
-
-                let edges = q.keys.into_iter().map(move |key| {
-                    match edge_manager.get(key.outbound_id, &key.t, key.inbound_id)? {
-                        Some(update_datetime) => {
-                            Ok(Some((key.outbound_id, key.t.clone(), update_datetime, key.inbound_id)))
-                        }
-                        None => Ok(None),
-                    }
-                });
+    fn write(&self, batch: WriteBatch) -> Result<()> {
+        self.check_writable()?;
+        self.db.write_opt(batch, &self.options.to_write_options()?)?;
+        Ok(())
+    }
 
-                let iterator = remove_nones_from_iterator(edges);
-                Ok(Box::new(iterator))
-            }
-            EdgeQuery::Pipe(q) => {
-                let vertex_iterator = self.vertex_query_to_iterator(*q.inner)?;
+    fn read_options(&self) -> KvReadOptions {
+        self.scan_options.to_read_options()
+    }
 
-                let edge_range_manager = match q.direction {
-                    EdgeDirection::Outbound => EdgeRangeManager::new(self.db.clone()),
-                    EdgeDirection::Inbound => EdgeRangeManager::new_reversed(self.db.clone()),
-                };
+    fn vertex_property_manager(&self) -> Result<VertexPropertyManager<DB>> {
+        Ok(VertexPropertyManager::new(self.db.clone())?
+            .with_cold_names(self.cold_properties.clone())
+            .with_history(self.history.clone()))
+    }
 
-                // Ideally we'd use iterators all the way down, but things
-                // start breaking apart due to conditional expressions not
-                // returning the same type signature, issues with `Result`s
-                // and some of the iterators, etc. So at this point, we'll
-                // just resort to building a vector.
-                let mut edges: Vec<Result<EdgeRangeItem>> = Vec::new();
-
-                for item in vertex_iterator {
-                    let (id, _) = item?;
-                    let edge_iterator = edge_range_manager.iterate_for_range(id, q.t.as_ref(), q.high)?;
-
-                    for item in edge_iterator {
-                        match item {
-                            Ok((
-                                edge_range_first_id,
-                                edge_range_t,
-                                edge_range_update_datetime,
-                                edge_range_second_id,
-                            )) => {
-                                if let Some(low) = q.low {
-                                    if edge_range_update_datetime < low {
-                                        break;
-                                    }
-                                }
-
-                                edges.push(match q.direction {
-                                    EdgeDirection::Outbound => Ok((
-                                        edge_range_first_id,
-                                        edge_range_t,
-                                        edge_range_update_datetime,
-                                        edge_range_second_id,
-                                    )),
-                                    EdgeDirection::Inbound => Ok((
-                                        edge_range_second_id,
-                                        edge_range_t,
-                                        edge_range_update_datetime,
-                                        edge_range_first_id,
-                                    )),
-                                })
-                            }
-                            Err(_) => edges.push(item),
-                        }
-
-                        if edges.len() == q.limit as usize {
-                            break;
-                        }
-                    }
-                }
+    fn edge_property_manager(&self) -> Result<EdgePropertyManager<DB>> {
+        Ok(EdgePropertyManager::new(self.db.clone())?
+            .with_cold_names(self.cold_properties.clone())
+            .with_history(self.history.clone()))
+    }
+
+    // Gets the ids of a vertex's neighbors across edges of a type, in
+    // ascending order. Outbound neighbors are streamed in order from the
+    // edges column family, but inbound ones are only indexed by update
+    // datetime, so they're collected and sorted.
+    fn sorted_neighbor_ids<'a>(
+        &self,
+        edge_manager: &'a EdgeManager<DB>,
+        id: Uuid,
+        t: &models::Type,
+        direction: EdgeDirection,
+    ) -> Result<Box<dyn Iterator<Item = Result<Uuid>> + 'a>> {
+        if direction == EdgeDirection::Outbound {
+            return Ok(Box::new(edge_manager.iterate_inbound_ids(id, t)?));
+        }
+
+        let mut neighbor_ids = Vec::new();
 
-                Ok(Box::new(edges.into_iter()))
+        if direction == EdgeDirection::Both {
+            for item in edge_manager.iterate_inbound_ids(id, t)? {
+                neighbor_ids.push(item?);
             }
         }
+
+        let reversed_edge_range_manager =
+            EdgeRangeManager::new_reversed(self.db.clone())?.with_read_options(self.read_options());
+
+        for item in reversed_edge_range_manager.iterate_for_range(id, Some(t), None)? {
+            let (_, _, _, outbound_id) = item?;
+            neighbor_ids.push(outbound_id);
+        }
+
+        neighbor_ids.sort();
+        neighbor_ids.dedup();
+        Ok(Box::new(neighbor_ids.into_iter().map(Ok)))
+    }
+
+    fn query_runner(&self) -> QueryRunner<DB> {
+        QueryRunner {
+            db: self.db.clone(),
+            tombstones: self.tombstones(),
+            keys_scanned: self.keys_scanned.clone(),
+            read_options: self.read_options(),
+        }
+    }
+
+    fn vertex_query_to_iterator(&self, q: VertexQuery) -> Result<Box<dyn Iterator<Item = Result<VertexItem>>>> {
+        self.query_runner().vertex_query_to_iterator(q)
+    }
+
+    fn edge_query_to_iterator(&self, q: EdgeQuery) -> Result<Box<dyn Iterator<Item = Result<EdgeRangeItem>>>> {
+        self.query_runner().edge_query_to_iterator(q)
     }
 }
 
 impl Transaction for RocksdbTransaction {
     fn create_vertex(&self, vertex: &models::Vertex) -> Result<bool> {
-        let vertex_manager = VertexManager::new(self.db.clone());
+        let vertex_manager = VertexManager::new(self.db.clone())?.with_history(self.history.clone());
 
         if vertex_manager.exists(vertex.id)? {
             Ok(false)
         } else {
             let mut batch = WriteBatch::default();
             vertex_manager.create(&mut batch, vertex)?;
-            self.db.write(batch)?;
+            self.write(batch)?;
+
+            let mut events = self.events.pending();
+            events.push(|| models::Event::VertexCreated(vertex.clone()));
+            events.publish();
             Ok(true)
         }
     }
 
+    fn upsert_vertex(&self, vertex: &models::Vertex) -> Result<Option<models::Type>> {
+        let vertex_manager = VertexManager::new(self.db.clone())?.with_history(self.history.clone());
+        let existing_t = vertex_manager.get(vertex.id)?;
+
+        if existing_t.as_ref() != Some(&vertex.t) {
+            // Creating over an existing vertex moves it between the type
+            // counts, so both cases are a single write batch
+            let mut batch = WriteBatch::default();
+            vertex_manager.create(&mut batch, vertex)?;
+            self.write(batch)?;
+
+            let mut events = self.events.pending();
+            events.push(|| models::Event::VertexCreated(vertex.clone()));
+            events.publish();
+        }
+
+        Ok(existing_t)
+    }
+
     fn get_vertices<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<Vec<models::Vertex>> {
         let iterator = self.vertex_query_to_iterator(q.into())?;
 
@@ -360,40 +2882,129 @@ impl Transaction for RocksdbTransaction {
         mapped.collect()
     }
 
+    fn filter_existing_vertices(&self, ids: Vec<Uuid>) -> Result<Vec<bool>> {
+        let exists = VertexManager::new(self.db.clone())?.exists_many(&ids)?;
+        let tombstones = self.tombstones.read().unwrap();
+
+        let results = ids
+            .iter()
+            .zip(exists)
+            .map(|(id, exists)| exists && !tombstones.contains(id))
+            .collect();
+
+        Ok(results)
+    }
+
     fn delete_vertices<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<()> {
         let iterator = self.vertex_query_to_iterator(q.into())?;
-        let vertex_manager = VertexManager::new(self.db.clone());
+        let vertex_manager = VertexManager::new(self.db.clone())?.with_history(self.history.clone());
         let mut batch = WriteBatch::default();
+        let mut events = self.events.pending();
 
         for item in iterator {
-            let (id, _) = item?;
+            let (id, t) = item?;
             vertex_manager.delete(&mut batch, id)?;
+            events.push(|| models::Event::VertexDeleted(models::Vertex::with_id(id, t)));
         }
 
-        self.db.write(batch)?;
+        self.write(batch)?;
+        events.publish();
         Ok(())
     }
 
     fn get_vertex_count(&self) -> Result<u64> {
-        let vertex_manager = VertexManager::new(self.db.clone());
+        let vertex_manager = VertexManager::new(self.db.clone())?.with_read_options(self.read_options());
+        let tombstones = self.tombstones();
         let iterator = vertex_manager.iterate_for_range(Uuid::default())?;
-        Ok(iterator.count() as u64)
+
+        let count = iterator
+            .filter(|item| match item {
+                Ok((id, _)) => !tombstones.contains(id),
+                Err(_) => true,
+            })
+            .count();
+
+        Ok(count as u64)
     }
 
     fn create_edge(&self, key: &models::EdgeKey) -> Result<bool> {
-        let vertex_manager = VertexManager::new(self.db.clone());
+        self.create_edge_with_datetime(key, self.clock.now())
+    }
+
+    fn create_edge_with_datetime(&self, key: &models::EdgeKey, update_datetime: DateTime<Utc>) -> Result<bool> {
+        let vertex_manager = VertexManager::new(self.db.clone())?;
 
         if !vertex_manager.exists(key.outbound_id)? || !vertex_manager.exists(key.inbound_id)? {
             Ok(false)
         } else {
-            let edge_manager = EdgeManager::new(self.db.clone());
+            let edge_manager = EdgeManager::new(self.db.clone())?
+                .with_log(self.edge_log)
+                .with_neighbor_sketches(self.neighbor_sketch_window)
+                .with_adjacency_bitmaps(self.adjacency_bitmaps.clone())
+                .with_history(self.history.clone());
             let mut batch = WriteBatch::default();
-            edge_manager.set(&mut batch, key.outbound_id, &key.t, key.inbound_id, Utc::now())?;
-            self.db.write(batch)?;
+            edge_manager.set(&mut batch, key.outbound_id, &key.t, key.inbound_id, update_datetime)?;
+            self.write(batch)?;
+
+            let mut events = self.events.pending();
+            events.push(|| models::Event::EdgeCreated(key.clone()));
+            events.publish();
             Ok(true)
         }
     }
 
+    fn touch_edge(&self, key: &models::EdgeKey) -> Result<bool> {
+        let vertex_manager = VertexManager::new(self.db.clone())?;
+
+        if !vertex_manager.exists(key.outbound_id)? || !vertex_manager.exists(key.inbound_id)? {
+            return Err(missing_edge_vertices(key));
+        }
+
+        let edge_manager = EdgeManager::new(self.db.clone())?
+            .with_log(self.edge_log)
+            .with_neighbor_sketches(self.neighbor_sketch_window)
+            .with_adjacency_bitmaps(self.adjacency_bitmaps.clone())
+            .with_history(self.history.clone());
+        let created = !edge_manager.exists(key.outbound_id, &key.t, key.inbound_id)?;
+        let mut batch = WriteBatch::default();
+        edge_manager.set(&mut batch, key.outbound_id, &key.t, key.inbound_id, self.clock.now())?;
+        self.write(batch)?;
+
+        let mut events = self.events.pending();
+        events.push(|| models::Event::EdgeCreated(key.clone()));
+        events.publish();
+        Ok(created)
+    }
+
+    fn create_mirrored_edge(&self, key: &models::EdgeKey, vertex_id: Uuid) -> Result<bool> {
+        self.create_mirrored_edge_with_datetime(key, vertex_id, self.clock.now())
+    }
+
+    fn create_mirrored_edge_with_datetime(
+        &self,
+        key: &models::EdgeKey,
+        vertex_id: Uuid,
+        update_datetime: DateTime<Utc>,
+    ) -> Result<bool> {
+        if !VertexManager::new(self.db.clone())?.exists(vertex_id)? {
+            return Ok(false);
+        }
+
+        let edge_manager = EdgeManager::new(self.db.clone())?
+            .with_log(self.edge_log)
+            .with_neighbor_sketches(self.neighbor_sketch_window)
+            .with_adjacency_bitmaps(self.adjacency_bitmaps.clone())
+            .with_history(self.history.clone());
+        let mut batch = WriteBatch::default();
+        edge_manager.set(&mut batch, key.outbound_id, &key.t, key.inbound_id, update_datetime)?;
+        self.write(batch)?;
+
+        let mut events = self.events.pending();
+        events.push(|| models::Event::EdgeCreated(key.clone()));
+        events.publish();
+        Ok(true)
+    }
+
     fn get_edges<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<Vec<models::Edge>> {
         let iterator = self.edge_query_to_iterator(q.into())?;
 
@@ -407,37 +3018,213 @@ impl Transaction for RocksdbTransaction {
         mapped.collect()
     }
 
+    fn edge_exists(&self, key: &models::EdgeKey) -> Result<bool> {
+        {
+            let tombstones = self.tombstones.read().unwrap();
+
+            if tombstones.contains(&key.outbound_id) || tombstones.contains(&key.inbound_id) {
+                return Ok(false);
+            }
+        }
+
+        EdgeManager::new(self.db.clone())?.exists(key.outbound_id, &key.t, key.inbound_id)
+    }
+
+    fn filter_existing_edges(&self, keys: Vec<models::EdgeKey>) -> Result<Vec<bool>> {
+        let exists = EdgeManager::new(self.db.clone())?.exists_many(&keys)?;
+        let tombstones = self.tombstones.read().unwrap();
+
+        let results = keys
+            .iter()
+            .zip(exists)
+            .map(|(key, exists)| {
+                exists && !tombstones.contains(&key.outbound_id) && !tombstones.contains(&key.inbound_id)
+            })
+            .collect();
+
+        Ok(results)
+    }
+
     fn delete_edges<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<()> {
-        let edge_manager = EdgeManager::new(self.db.clone());
-        let vertex_manager = VertexManager::new(self.db.clone());
+        let edge_manager = EdgeManager::new(self.db.clone())?
+            .with_adjacency_bitmaps(self.adjacency_bitmaps.clone())
+            .with_history(self.history.clone());
+        let vertex_manager = VertexManager::new(self.db.clone())?;
         let iterator = self.edge_query_to_iterator(q.into())?;
         let mut batch = WriteBatch::default();
+        let mut events = self.events.pending();
 
         for item in iterator {
             let (outbound_id, t, update_datetime, inbound_id) = item?;
 
             if vertex_manager.get(outbound_id)?.is_some() {
                 edge_manager.delete(&mut batch, outbound_id, &t, inbound_id, update_datetime)?;
-            };
+                events.push(|| models::Event::EdgeDeleted(models::EdgeKey::new(outbound_id, t, inbound_id)));
+            }
         }
 
-        self.db.write(batch)?;
+        self.write(batch)?;
+        events.publish();
+        Ok(())
+    }
+
+    // Writes aren't serialized, so each edge's update datetime is re-read
+    // right before it's deleted. This narrows, but doesn't close, the window
+    // for a concurrent write to refresh an edge that's then deleted.
+    fn delete_edges_unmodified_since<Q: Into<models::EdgeQuery>>(&self, q: Q, since: DateTime<Utc>) -> Result<u64> {
+        let edge_manager = EdgeManager::new(self.db.clone())?
+            .with_adjacency_bitmaps(self.adjacency_bitmaps.clone())
+            .with_history(self.history.clone());
+        let iterator = self.edge_query_to_iterator(q.into())?;
+        let mut batch = WriteBatch::default();
+        let mut events = self.events.pending();
+        let mut count = 0;
+
+        for item in iterator {
+            let (outbound_id, t, _, inbound_id) = item?;
+
+            match edge_manager.get(outbound_id, &t, inbound_id)? {
+                Some(update_datetime) if update_datetime <= since => {
+                    edge_manager.delete(&mut batch, outbound_id, &t, inbound_id, update_datetime)?;
+                    events.push(|| models::Event::EdgeDeleted(models::EdgeKey::new(outbound_id, t, inbound_id)));
+                    count += 1;
+                }
+                _ => (),
+            }
+        }
+
+        self.write(batch)?;
+        events.publish();
+        Ok(count)
+    }
+
+    fn delete_mirrored_edges(&self, keys: Vec<models::EdgeKey>) -> Result<()> {
+        let edge_manager = EdgeManager::new(self.db.clone())?
+            .with_adjacency_bitmaps(self.adjacency_bitmaps.clone())
+            .with_history(self.history.clone());
+        let mut batch = WriteBatch::default();
+        let mut events = self.events.pending();
+
+        for key in keys {
+            if let Some(update_datetime) = edge_manager.get(key.outbound_id, &key.t, key.inbound_id)? {
+                edge_manager.delete(&mut batch, key.outbound_id, &key.t, key.inbound_id, update_datetime)?;
+                events.push(|| models::Event::EdgeDeleted(key));
+            }
+        }
+
+        self.write(batch)?;
+        events.publish();
         Ok(())
     }
 
     fn get_edge_count(&self, id: Uuid, t: Option<&models::Type>, direction: models::EdgeDirection) -> Result<u64> {
         let edge_range_manager = match direction {
-            EdgeDirection::Outbound => EdgeRangeManager::new(self.db.clone()),
-            EdgeDirection::Inbound => EdgeRangeManager::new_reversed(self.db.clone()),
+            EdgeDirection::Outbound => EdgeRangeManager::new(self.db.clone())?.with_read_options(self.read_options()),
+            EdgeDirection::Inbound => {
+                EdgeRangeManager::new_reversed(self.db.clone())?.with_read_options(self.read_options())
+            }
+            EdgeDirection::Both => return self.get_edge_count_in_range(id, t, None, None, direction),
         };
 
-        let count = edge_range_manager.iterate_for_range(id, t, None)?.count();
+        let tombstones = self.tombstones();
+
+        if tombstones.contains(&id) {
+            return Ok(0);
+        }
+
+        let count = edge_range_manager
+            .iterate_for_range(id, t, None)?
+            .filter(|item| match item {
+                Ok((_, _, _, second_id)) => !tombstones.contains(second_id),
+                Err(_) => true,
+            })
+            .count();
 
         Ok(count as u64)
     }
 
+    // Overridden so that edges are counted by their keys, rather than
+    // fetched
+    fn get_edge_count_in_range(
+        &self,
+        id: Uuid,
+        t: Option<&models::Type>,
+        low: Option<DateTime<Utc>>,
+        high: Option<DateTime<Utc>>,
+        direction: models::EdgeDirection,
+    ) -> Result<u64> {
+        let mut tombstones = self.tombstones();
+
+        if tombstones.contains(&id) {
+            return Ok(0);
+        }
+
+        match direction {
+            EdgeDirection::Outbound => EdgeRangeManager::new(self.db.clone())?
+                .with_read_options(self.read_options())
+                .count_for_range(id, t, low, high, &tombstones),
+            EdgeDirection::Inbound => EdgeRangeManager::new_reversed(self.db.clone())?
+                .with_read_options(self.read_options())
+                .count_for_range(id, t, low, high, &tombstones),
+            EdgeDirection::Both => {
+                let outbound_count = EdgeRangeManager::new(self.db.clone())?
+                    .with_read_options(self.read_options())
+                    .count_for_range(id, t, low, high, &tombstones)?;
+
+                // Self-loops are in both edge ranges, so they're skipped in
+                // the reversed one
+                tombstones.insert(id);
+                let inbound_count = EdgeRangeManager::new_reversed(self.db.clone())?
+                    .with_read_options(self.read_options())
+                    .count_for_range(id, t, low, high, &tombstones)?;
+
+                Ok(outbound_count + inbound_count)
+            }
+        }
+    }
+
+    // Overridden to intersect adjacency bitmaps when both vertices have
+    // them, and otherwise to intersect the neighbors as they're scanned
+    fn get_common_neighbors(
+        &self,
+        first_id: Uuid,
+        second_id: Uuid,
+        t: &models::Type,
+        direction: models::EdgeDirection,
+        limit: u32,
+    ) -> Result<Vec<Uuid>> {
+        let tombstones = self.tombstones();
+
+        if tombstones.contains(&first_id) || tombstones.contains(&second_id) {
+            return Ok(Vec::new());
+        }
+
+        if let Some(first_bitmap) = self.get_adjacency_bitmap(first_id, t, direction)? {
+            if let Some(second_bitmap) = self.get_adjacency_bitmap(second_id, t, direction)? {
+                // Neighbor ids aren't allocated in the order of vertex ids,
+                // so the whole intersection is resolved before it's limited
+                let mut common_ids = self.resolve_neighbor_ids(&(first_bitmap & second_bitmap))?;
+                common_ids.sort();
+                common_ids.truncate(limit as usize);
+                return Ok(common_ids);
+            }
+        }
+
+        // Only ids in both are kept, so one side is enough to skip vertices
+        // that are pending deletion
+        let edge_manager = EdgeManager::new(self.db.clone())?;
+        let first_neighbor_ids = self
+            .sorted_neighbor_ids(&edge_manager, first_id, t, direction)?
+            .filter(move |item| match item {
+                Ok(id) => !tombstones.contains(id),
+                Err(_) => true,
+            });
+        let second_neighbor_ids = self.sorted_neighbor_ids(&edge_manager, second_id, t, direction)?;
+        intersect_sorted(first_neighbor_ids, second_neighbor_ids, limit)
+    }
+
     fn get_vertex_properties(&self, q: VertexPropertyQuery) -> Result<Vec<models::VertexProperty>> {
-        let manager = VertexPropertyManager::new(self.db.clone());
+        let manager = self.vertex_property_manager()?;
         let mut properties = Vec::new();
 
         for item in self.vertex_query_to_iterator(q.inner)? {
@@ -453,33 +3240,83 @@ impl Transaction for RocksdbTransaction {
     }
 
     fn set_vertex_properties(&self, q: VertexPropertyQuery, value: &JsonValue) -> Result<()> {
-        let manager = VertexPropertyManager::new(self.db.clone());
+        let manager = self.vertex_property_manager()?;
         let mut batch = WriteBatch::default();
+        let mut events = self.events.pending();
+        let name = &q.name;
 
         for item in self.vertex_query_to_iterator(q.inner)? {
             let (id, _) = item?;
             manager.set(&mut batch, id, &q.name, value)?;
+            events.push(|| models::Event::VertexPropertySet(id, name.clone(), value.clone()));
         }
 
-        self.db.write(batch)?;
+        self.write(batch)?;
+        events.publish();
         Ok(())
     }
 
     fn delete_vertex_properties(&self, q: VertexPropertyQuery) -> Result<()> {
-        let manager = VertexPropertyManager::new(self.db.clone());
+        let manager = self.vertex_property_manager()?;
         let mut batch = WriteBatch::default();
+        let mut events = self.events.pending();
+        let name = &q.name;
 
         for item in self.vertex_query_to_iterator(q.inner)? {
             let (id, _) = item?;
             manager.delete(&mut batch, id, &q.name)?;
+            events.push(|| models::Event::VertexPropertyDeleted(id, name.clone()));
         }
 
-        self.db.write(batch)?;
+        self.write(batch)?;
+        events.publish();
         Ok(())
     }
 
+    fn get_vertex_properties_raw(&self, q: VertexPropertyQuery) -> Result<Vec<models::RawVertexProperty>> {
+        let manager = self.vertex_property_manager()?;
+        let mut properties = Vec::new();
+
+        for item in self.vertex_query_to_iterator(q.inner)? {
+            let (id, _) = item?;
+            let value = manager.get_raw(id, &q.name)?;
+
+            if let Some(value) = value {
+                properties.push(models::RawVertexProperty::new(id, value));
+            }
+        }
+
+        Ok(properties)
+    }
+
+    // Overridden so that each vertex is read once, rather than once per
+    // property name.
+    fn get_named_vertex_properties<Q: Into<models::VertexQuery>>(
+        &self,
+        q: Q,
+        names: &[&str],
+    ) -> Result<Vec<models::VertexProperties>> {
+        let manager = self.vertex_property_manager()?;
+        let mut results = Vec::new();
+
+        for item in self.vertex_query_to_iterator(q.into())? {
+            let (id, t) = item?;
+            let mut props = Vec::new();
+
+            for name in names {
+                if let Some(value) = manager.get(id, name)? {
+                    props.push(models::NamedProperty::new(name.to_string(), value));
+                }
+            }
+
+            results.push(models::VertexProperties::new(models::Vertex::with_id(id, t), props));
+        }
+
+        Ok(results)
+    }
+
     fn get_edge_properties(&self, q: EdgePropertyQuery) -> Result<Vec<models::EdgeProperty>> {
-        let manager = EdgePropertyManager::new(self.db.clone());
+        let manager = self.edge_property_manager()?;
         let mut properties = Vec::new();
 
         for item in self.edge_query_to_iterator(q.inner)? {
@@ -496,28 +3333,155 @@ impl Transaction for RocksdbTransaction {
     }
 
     fn set_edge_properties(&self, q: EdgePropertyQuery, value: &JsonValue) -> Result<()> {
-        let manager = EdgePropertyManager::new(self.db.clone());
+        let manager = self.edge_property_manager()?;
         let mut batch = WriteBatch::default();
+        let mut events = self.events.pending();
+        let name = &q.name;
 
         for item in self.edge_query_to_iterator(q.inner)? {
             let (outbound_id, t, _, inbound_id) = item?;
             manager.set(&mut batch, outbound_id, &t, inbound_id, &q.name, value)?;
+            events.push(|| {
+                let key = models::EdgeKey::new(outbound_id, t, inbound_id);
+                models::Event::EdgePropertySet(key, name.clone(), value.clone())
+            });
         }
 
-        self.db.write(batch)?;
+        self.write(batch)?;
+        events.publish();
         Ok(())
     }
 
     fn delete_edge_properties(&self, q: EdgePropertyQuery) -> Result<()> {
-        let manager = EdgePropertyManager::new(self.db.clone());
+        let manager = self.edge_property_manager()?;
         let mut batch = WriteBatch::default();
+        let mut events = self.events.pending();
+        let name = &q.name;
 
         for item in self.edge_query_to_iterator(q.inner)? {
             let (outbound_id, t, _, inbound_id) = item?;
             manager.delete(&mut batch, outbound_id, &t, inbound_id, &q.name)?;
+            events.push(|| {
+                let key = models::EdgeKey::new(outbound_id, t, inbound_id);
+                models::Event::EdgePropertyDeleted(key, name.clone())
+            });
         }
 
-        self.db.write(batch)?;
+        self.write(batch)?;
+        events.publish();
         Ok(())
     }
+
+    // Overridden so that each edge is read once, rather than once per
+    // property name.
+    fn get_named_edge_properties<Q: Into<models::EdgeQuery>>(
+        &self,
+        q: Q,
+        names: &[&str],
+    ) -> Result<Vec<models::EdgeProperties>> {
+        let manager = self.edge_property_manager()?;
+        let mut results = Vec::new();
+
+        for item in self.edge_query_to_iterator(q.into())? {
+            let (outbound_id, t, update_datetime, inbound_id) = item?;
+            let mut props = Vec::new();
+
+            for name in names {
+                if let Some(value) = manager.get(outbound_id, &t, inbound_id, name)? {
+                    props.push(models::NamedProperty::new(name.to_string(), value));
+                }
+            }
+
+            let key = models::EdgeKey::new(outbound_id, t, inbound_id);
+            results.push(models::EdgeProperties::new(
+                models::Edge::new(key, update_datetime),
+                props,
+            ));
+        }
+
+        Ok(results)
+    }
+
+    fn get_edge_properties_raw(&self, q: EdgePropertyQuery) -> Result<Vec<models::RawEdgeProperty>> {
+        let manager = self.edge_property_manager()?;
+        let mut properties = Vec::new();
+
+        for item in self.edge_query_to_iterator(q.inner)? {
+            let (outbound_id, t, _, inbound_id) = item?;
+            let value = manager.get_raw(outbound_id, &t, inbound_id, &q.name)?;
+
+            if let Some(value) = value {
+                let key = models::EdgeKey::new(outbound_id, t, inbound_id);
+                properties.push(models::RawEdgeProperty::new(key, value));
+            }
+        }
+
+        Ok(properties)
+    }
+
+    fn get_all_vertex_properties<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<Vec<models::VertexProperties>> {
+        let manager = self.vertex_property_manager()?.with_read_options(self.read_options());
+        let mut results = Vec::new();
+
+        for item in self.vertex_query_to_iterator(q.into())? {
+            let (id, t) = item?;
+            let mut props = Vec::new();
+
+            for item in manager.iterate_for_owner(id)? {
+                let ((_, name), value) = item?;
+                props.push(models::NamedProperty::new(name, value));
+            }
+
+            results.push(models::VertexProperties::new(models::Vertex::with_id(id, t), props));
+        }
+
+        Ok(results)
+    }
+
+    fn get_vertex_properties_with_prefix<Q: Into<models::VertexQuery>>(
+        &self,
+        q: Q,
+        prefix: &str,
+    ) -> Result<Vec<models::NamedVertexProperty>> {
+        let manager = self.vertex_property_manager()?.with_read_options(self.read_options());
+        let mut results = Vec::new();
+
+        for item in self.vertex_query_to_iterator(q.into())? {
+            let (id, _) = item?;
+
+            for item in manager.iterate_for_owner_with_prefix(id, prefix)? {
+                let ((_, name), value) = item?;
+                results.push(models::NamedVertexProperty::new(id, name, value));
+            }
+        }
+
+        Ok(results)
+    }
+
+    fn get_all_edge_properties<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<Vec<models::EdgeProperties>> {
+        let manager = self.edge_property_manager()?.with_read_options(self.read_options());
+        let mut results = Vec::new();
+
+        for item in self.edge_query_to_iterator(q.into())? {
+            let (outbound_id, t, update_datetime, inbound_id) = item?;
+            let mut props = Vec::new();
+
+            for item in manager.iterate_for_owner(outbound_id, &t, inbound_id)? {
+                let ((_, _, _, name), value) = item?;
+                props.push(models::NamedProperty::new(name, value));
+            }
+
+            let key = models::EdgeKey::new(outbound_id, t, inbound_id);
+            results.push(models::EdgeProperties::new(
+                models::Edge::new(key, update_datetime),
+                props,
+            ));
+        }
+
+        Ok(results)
+    }
+
+    fn get_keys_scanned(&self) -> Option<u64> {
+        Some(self.keys_scanned.load(Ordering::Relaxed))
+    }
 }
@@ -0,0 +1,690 @@
+//! A datastore backed by a distributed ordered key-value store, e.g. TiKV
+//! or FoundationDB, for graphs that outgrow a single machine. It stores the
+//! same keys and values as the rocksdb datastore, through the same
+//! managers; the store's single keyspace is split into the rocksdb column
+//! families by prefixing each key with its family's name.
+
+use super::super::{
+    Datastore, EdgeDirection, EdgePropertyQuery, EdgeQuery, Transaction, VertexPropertyQuery, VertexQuery,
+};
+use super::bytes::{
+    build, build_bitmap_update, build_counter, build_sketch_update, prefix_successor, read_counter, BitmapUpdate,
+    Component,
+};
+use super::engine::{apply_bitmap_operands, apply_sketch_operands, KvEngine, KvItem, KvIteratorMode, KvReadOptions};
+use super::managers::*;
+use super::query::QueryRunner;
+use chrono::offset::Utc;
+use chrono::DateTime;
+use clock::{Clock, SystemClock};
+use errors::Result;
+use events::EventBus;
+use hll::register_update;
+use models;
+use serde_json::Value as JsonValue;
+use std::collections::{HashSet, VecDeque};
+use std::io::Cursor;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+use uuid::Uuid;
+
+// How many keys iterators read from the store at a time.
+const SCAN_PAGE_SIZE: usize = 256;
+
+/// A transaction against a distributed ordered key-value store. Reads see
+/// a snapshot of the store, along with the transaction's own writes, and
+/// the writes are applied atomically when it's committed.
+pub trait OrderedKvTransaction {
+    /// Gets a value.
+    ///
+    /// # Arguments
+    /// * `key` - The key.
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>>;
+
+    /// Gets the keys and values in a range, ordered by key.
+    ///
+    /// # Arguments
+    /// * `start` - The first key in the range, inclusive.
+    /// * `end` - The last key in the range, exclusive.
+    /// * `limit` - The most keys to get.
+    /// * `reverse` - Whether to get the keys at the end of the range in
+    ///   reverse order, rather than the ones at the start.
+    fn scan(&mut self, start: &[u8], end: &[u8], limit: usize, reverse: bool) -> Result<Vec<(Vec<u8>, Vec<u8>)>>;
+
+    /// Sets a value.
+    ///
+    /// # Arguments
+    /// * `key` - The key.
+    /// * `value` - The value.
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<()>;
+
+    /// Deletes a value.
+    ///
+    /// # Arguments
+    /// * `key` - The key.
+    fn delete(&mut self, key: &[u8]) -> Result<()>;
+
+    /// Applies the transaction's writes.
+    ///
+    /// # Errors
+    /// Returns an error if the writes couldn't be applied, e.g. because
+    /// they conflict with a concurrent transaction's. None of them are
+    /// applied then.
+    fn commit(self) -> Result<()>;
+
+    /// Discards the transaction's writes. Transactions that only read are
+    /// rolled back once they're done.
+    fn rollback(self) -> Result<()>;
+}
+
+/// A client for a distributed ordered key-value store. Implement this for
+/// the store's own client, e.g. TiKV's transactional client or a
+/// FoundationDB database, to store a graph in it.
+pub trait OrderedKvClient: Send + Sync + 'static {
+    /// The store's transactions.
+    type Transaction: OrderedKvTransaction;
+
+    /// Begins a transaction.
+    fn begin(&self) -> Result<Self::Transaction>;
+}
+
+// Reads a value in a transaction of its own.
+fn read<C, T, F>(client: &C, f: F) -> Result<T>
+where
+    C: OrderedKvClient,
+    F: FnOnce(&mut C::Transaction) -> Result<T>,
+{
+    let mut trans = client.begin()?;
+    let result = f(&mut trans);
+    trans.rollback()?;
+    result
+}
+
+// A write staged in a batch. Merges are applied by reading the current value
+// in the transaction that writes the merged one, since the store has no
+// merge operators; a concurrent merge makes one of the transactions fail to
+// commit, rather than being lost.
+enum Mutation {
+    Put(Vec<u8>, Vec<u8>),
+    Delete(Vec<u8>),
+    Increment(Vec<u8>, i64),
+    AddToSketch(Vec<u8>, Vec<u8>),
+    UpdateBitmap(Vec<u8>, Vec<u8>),
+}
+
+/// Writes staged for a distributed ordered key-value store.
+#[derive(Default)]
+pub struct DistributedBatch {
+    mutations: Vec<Mutation>,
+}
+
+/// Iterates over a range of keys a page at a time, each read in a
+/// transaction of its own. A page that fails to load ends the iteration,
+/// like a rocksdb iterator that hits an error.
+struct ScanIterator<'a, C: OrderedKvClient> {
+    client: &'a C,
+    // The length of the prefix that's stripped from the keys.
+    prefix_len: usize,
+    start: Vec<u8>,
+    end: Vec<u8>,
+    reverse: bool,
+    page: VecDeque<(Vec<u8>, Vec<u8>)>,
+    done: bool,
+}
+
+impl<'a, C: OrderedKvClient> Iterator for ScanIterator<'a, C> {
+    type Item = KvItem;
+
+    fn next(&mut self) -> Option<KvItem> {
+        if self.page.is_empty() && !self.done {
+            let (start, end, reverse) = (&self.start, &self.end, self.reverse);
+
+            match read(self.client, |trans| trans.scan(start, end, SCAN_PAGE_SIZE, reverse)) {
+                Ok(page) => {
+                    self.done = page.len() < SCAN_PAGE_SIZE;
+
+                    // The next page starts right after the last key read,
+                    // or ends right before it when going in reverse
+                    if let Some((last_key, _)) = page.last() {
+                        if self.reverse {
+                            self.end = last_key.clone();
+                        } else {
+                            self.start = last_key.clone();
+                            self.start.push(0);
+                        }
+                    }
+
+                    self.page = page.into();
+                }
+                Err(_) => self.done = true,
+            }
+        }
+
+        let (key, value) = self.page.pop_front()?;
+        Some((key[self.prefix_len..].into(), value.into_boxed_slice()))
+    }
+}
+
+/// The storage engine for a distributed ordered key-value store, which
+/// applies each batch of writes in a single store transaction.
+pub struct DistributedEngine<C: OrderedKvClient> {
+    client: C,
+}
+
+impl<C: OrderedKvClient> DistributedEngine<C> {
+    // Gets the prefix of the keys in a keyspace, which is its name, sized so
+    // that no keyspace's prefix is the start of another's.
+    fn prefix(&self, keyspace: &str) -> Vec<u8> {
+        build(&[Component::SizedString(keyspace)])
+    }
+
+    fn key(&self, keyspace: &str, key: &[u8]) -> Vec<u8> {
+        build(&[Component::SizedString(keyspace), Component::Bytes(key)])
+    }
+
+    fn scan<'a>(&'a self, keyspace: &str, mode: KvIteratorMode, upper_bound: Option<&[u8]>) -> ScanIterator<'a, C> {
+        let prefix = self.prefix(keyspace);
+        // The prefix ends with the keyspace's name, which can't be all 0xFF
+        // bytes, so there's always a successor
+        let mut end = prefix_successor(&prefix).unwrap_or_default();
+
+        if let Some(upper_bound) = upper_bound {
+            end = end.min(self.key(keyspace, upper_bound));
+        }
+
+        let (start, end, reverse) = match mode {
+            KvIteratorMode::Start => (prefix.clone(), end, false),
+            KvIteratorMode::End => (prefix.clone(), end, true),
+            KvIteratorMode::Forward(key) => (self.key(keyspace, key), end, false),
+            KvIteratorMode::Reverse(key) => {
+                let mut end = self.key(keyspace, key);
+                end.push(0);
+                (prefix.clone(), end, true)
+            }
+        };
+
+        ScanIterator {
+            client: &self.client,
+            prefix_len: prefix.len(),
+            start,
+            end,
+            reverse,
+            page: VecDeque::new(),
+            done: false,
+        }
+    }
+}
+
+impl<C: OrderedKvClient> KvEngine for DistributedEngine<C> {
+    type Batch = DistributedBatch;
+    type Value = Vec<u8>;
+
+    fn get(&self, keyspace: &str, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let key = self.key(keyspace, key);
+        read(&self.client, |trans| trans.get(&key))
+    }
+
+    // Reads the keys in one transaction, rather than one each.
+    fn exists_many(&self, keyspace: &str, keys: &[Vec<u8>]) -> Result<Vec<bool>> {
+        read(&self.client, |trans| {
+            keys.iter()
+                .map(|key| Ok(trans.get(&self.key(keyspace, key))?.is_some()))
+                .collect()
+        })
+    }
+
+    fn iterate<'a>(&'a self, keyspace: &str, mode: KvIteratorMode) -> Result<Box<dyn Iterator<Item = KvItem> + 'a>> {
+        Ok(Box::new(self.scan(keyspace, mode, None)))
+    }
+
+    // The upper bound ends the store's scans, so that they don't read past
+    // it. The other options only apply to rocksdb.
+    fn iterate_with_options<'a>(
+        &'a self,
+        keyspace: &str,
+        mode: KvIteratorMode,
+        options: &KvReadOptions,
+    ) -> Result<Box<dyn Iterator<Item = KvItem> + 'a>> {
+        let upper_bound = match mode {
+            KvIteratorMode::Start | KvIteratorMode::Forward(_) => options.upper_bound.as_ref(),
+            KvIteratorMode::End | KvIteratorMode::Reverse(_) => None,
+        };
+
+        Ok(Box::new(self.scan(keyspace, mode, upper_bound.map(|b| &b[..]))))
+    }
+
+    fn put(&self, batch: &mut DistributedBatch, keyspace: &str, key: &[u8], value: &[u8]) -> Result<()> {
+        batch
+            .mutations
+            .push(Mutation::Put(self.key(keyspace, key), value.to_vec()));
+        Ok(())
+    }
+
+    fn delete(&self, batch: &mut DistributedBatch, keyspace: &str, key: &[u8]) -> Result<()> {
+        batch.mutations.push(Mutation::Delete(self.key(keyspace, key)));
+        Ok(())
+    }
+
+    fn increment(&self, batch: &mut DistributedBatch, keyspace: &str, key: &[u8], delta: i64) -> Result<()> {
+        batch
+            .mutations
+            .push(Mutation::Increment(self.key(keyspace, key), delta));
+        Ok(())
+    }
+
+    fn add_to_sketch(
+        &self,
+        batch: &mut DistributedBatch,
+        keyspace: &str,
+        key: &[u8],
+        precision: u8,
+        item: &[u8],
+    ) -> Result<()> {
+        let (index, rank) = register_update(precision, item);
+        batch.mutations.push(Mutation::AddToSketch(
+            self.key(keyspace, key),
+            build_sketch_update(precision, index, rank),
+        ));
+        Ok(())
+    }
+
+    fn update_bitmap(
+        &self,
+        batch: &mut DistributedBatch,
+        keyspace: &str,
+        key: &[u8],
+        update: &BitmapUpdate,
+    ) -> Result<()> {
+        batch.mutations.push(Mutation::UpdateBitmap(
+            self.key(keyspace, key),
+            build_bitmap_update(update),
+        ));
+        Ok(())
+    }
+
+    fn write(&self, batch: DistributedBatch) -> Result<()> {
+        let mut trans = self.client.begin()?;
+
+        let result: Result<()> = batch.mutations.into_iter().try_for_each(|mutation| match mutation {
+            Mutation::Put(key, value) => trans.put(&key, &value),
+            Mutation::Delete(key) => trans.delete(&key),
+            Mutation::Increment(key, delta) => {
+                let count = match trans.get(&key)? {
+                    Some(value) => read_counter(&mut Cursor::new(value))?,
+                    None => 0,
+                };
+
+                trans.put(&key, &build_counter(count.wrapping_add(delta)))
+            }
+            Mutation::AddToSketch(key, update) => {
+                let existing = trans.get(&key)?;
+                let sketch = apply_sketch_operands(existing.as_ref().map(|v| &v[..]), vec![&update[..]]);
+                trans.put(&key, &sketch)
+            }
+            Mutation::UpdateBitmap(key, update) => {
+                let existing = trans.get(&key)?;
+                let bitmap = apply_bitmap_operands(existing.as_ref().map(|v| &v[..]), vec![&update[..]]);
+                trans.put(&key, &bitmap)
+            }
+        });
+
+        match result {
+            Ok(()) => trans.commit(),
+            Err(err) => {
+                trans.rollback()?;
+                Err(err)
+            }
+        }
+    }
+}
+
+/// A datastore that is backed by a distributed ordered key-value store.
+///
+/// Each write made through a transaction, e.g. deleting the vertices that
+/// match a query along with their edges, is applied atomically in a single
+/// store transaction. The reads it's based on are made beforehand, in
+/// transactions of their own, so concurrent writes to the same vertices can
+/// interleave. Change events are only published to subscribers in the same
+/// process.
+pub struct DistributedDatastore<C: OrderedKvClient> {
+    db: Arc<DistributedEngine<C>>,
+    events: EventBus,
+    clock: Arc<dyn Clock>,
+}
+
+impl<C: OrderedKvClient> DistributedDatastore<C> {
+    /// Creates a new distributed datastore.
+    ///
+    /// # Arguments
+    /// * `client` - The client for the store.
+    pub fn new(client: C) -> Self {
+        DistributedDatastore {
+            db: Arc::new(DistributedEngine { client }),
+            events: EventBus::default(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Sets the clock used to timestamp edges, in place of the system time.
+    ///
+    /// # Arguments
+    /// * `clock` - The clock to use.
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        DistributedDatastore { clock, ..self }
+    }
+}
+
+impl<C: OrderedKvClient> Datastore for DistributedDatastore<C> {
+    type Trans = DistributedTransaction<C>;
+
+    fn transaction(&self) -> Result<Self::Trans> {
+        Ok(DistributedTransaction {
+            db: self.db.clone(),
+            events: self.events.clone(),
+            clock: self.clock.clone(),
+            keys_scanned: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    fn subscribe(&self, filter: models::EventFilter) -> Result<Receiver<models::Event>> {
+        Ok(self.events.subscribe(filter))
+    }
+}
+
+/// A transaction that is backed by a distributed ordered key-value store.
+pub struct DistributedTransaction<C: OrderedKvClient> {
+    db: Arc<DistributedEngine<C>>,
+    events: EventBus,
+    clock: Arc<dyn Clock>,
+    keys_scanned: Arc<AtomicU64>,
+}
+
+impl<C: OrderedKvClient> DistributedTransaction<C> {
+    fn query_runner(&self) -> QueryRunner<DistributedEngine<C>> {
+        QueryRunner {
+            db: self.db.clone(),
+            tombstones: HashSet::new(),
+            keys_scanned: self.keys_scanned.clone(),
+            read_options: KvReadOptions::default(),
+        }
+    }
+
+    fn vertex_query_to_iterator(&self, q: VertexQuery) -> Result<Box<dyn Iterator<Item = Result<VertexItem>>>> {
+        self.query_runner().vertex_query_to_iterator(q)
+    }
+
+    fn edge_query_to_iterator(&self, q: EdgeQuery) -> Result<Box<dyn Iterator<Item = Result<EdgeRangeItem>>>> {
+        self.query_runner().edge_query_to_iterator(q)
+    }
+}
+
+impl<C: OrderedKvClient> Transaction for DistributedTransaction<C> {
+    fn create_vertex(&self, vertex: &models::Vertex) -> Result<bool> {
+        let vertex_manager = VertexManager::new(self.db.clone())?;
+
+        if vertex_manager.exists(vertex.id)? {
+            Ok(false)
+        } else {
+            let mut batch = DistributedBatch::default();
+            vertex_manager.create(&mut batch, vertex)?;
+            self.db.write(batch)?;
+
+            let mut events = self.events.pending();
+            events.push(|| models::Event::VertexCreated(vertex.clone()));
+            events.publish();
+            Ok(true)
+        }
+    }
+
+    fn get_vertices<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<Vec<models::Vertex>> {
+        self.vertex_query_to_iterator(q.into())?
+            .map(|item| {
+                let (id, t) = item?;
+                Ok(models::Vertex::with_id(id, t))
+            })
+            .collect()
+    }
+
+    fn delete_vertices<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<()> {
+        let iterator = self.vertex_query_to_iterator(q.into())?;
+        let vertex_manager = VertexManager::new(self.db.clone())?;
+        let mut batch = DistributedBatch::default();
+        let mut events = self.events.pending();
+
+        for item in iterator {
+            let (id, t) = item?;
+            vertex_manager.delete(&mut batch, id)?;
+            events.push(|| models::Event::VertexDeleted(models::Vertex::with_id(id, t)));
+        }
+
+        self.db.write(batch)?;
+        events.publish();
+        Ok(())
+    }
+
+    fn get_vertex_count(&self) -> Result<u64> {
+        let vertex_manager = VertexManager::new(self.db.clone())?;
+        let count = vertex_manager.iterate_for_range(Uuid::default())?.count();
+        Ok(count as u64)
+    }
+
+    fn create_edge(&self, key: &models::EdgeKey) -> Result<bool> {
+        self.create_edge_with_datetime(key, self.clock.now())
+    }
+
+    fn create_edge_with_datetime(&self, key: &models::EdgeKey, update_datetime: DateTime<Utc>) -> Result<bool> {
+        let vertex_manager = VertexManager::new(self.db.clone())?;
+
+        if !vertex_manager.exists(key.outbound_id)? || !vertex_manager.exists(key.inbound_id)? {
+            Ok(false)
+        } else {
+            let edge_manager = EdgeManager::new(self.db.clone())?;
+            let mut batch = DistributedBatch::default();
+            edge_manager.set(&mut batch, key.outbound_id, &key.t, key.inbound_id, update_datetime)?;
+            self.db.write(batch)?;
+
+            let mut events = self.events.pending();
+            events.push(|| models::Event::EdgeCreated(key.clone()));
+            events.publish();
+            Ok(true)
+        }
+    }
+
+    fn get_edges<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<Vec<models::Edge>> {
+        self.edge_query_to_iterator(q.into())?
+            .map(|item| {
+                let (outbound_id, t, update_datetime, inbound_id) = item?;
+                let key = models::EdgeKey::new(outbound_id, t, inbound_id);
+                Ok(models::Edge::new(key, update_datetime))
+            })
+            .collect()
+    }
+
+    fn edge_exists(&self, key: &models::EdgeKey) -> Result<bool> {
+        EdgeManager::new(self.db.clone())?.exists(key.outbound_id, &key.t, key.inbound_id)
+    }
+
+    fn delete_edges<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<()> {
+        let edge_manager = EdgeManager::new(self.db.clone())?;
+        let vertex_manager = VertexManager::new(self.db.clone())?;
+        let iterator = self.edge_query_to_iterator(q.into())?;
+        let mut batch = DistributedBatch::default();
+        let mut events = self.events.pending();
+
+        for item in iterator {
+            let (outbound_id, t, update_datetime, inbound_id) = item?;
+
+            if vertex_manager.get(outbound_id)?.is_some() {
+                edge_manager.delete(&mut batch, outbound_id, &t, inbound_id, update_datetime)?;
+                events.push(|| models::Event::EdgeDeleted(models::EdgeKey::new(outbound_id, t, inbound_id)));
+            }
+        }
+
+        self.db.write(batch)?;
+        events.publish();
+        Ok(())
+    }
+
+    fn get_edge_count(&self, id: Uuid, t: Option<&models::Type>, direction: models::EdgeDirection) -> Result<u64> {
+        let edge_range_manager = match direction {
+            EdgeDirection::Outbound => EdgeRangeManager::new(self.db.clone())?,
+            EdgeDirection::Inbound => EdgeRangeManager::new_reversed(self.db.clone())?,
+            EdgeDirection::Both => return self.get_edge_count_in_range(id, t, None, None, direction),
+        };
+
+        let count = edge_range_manager.iterate_for_range(id, t, None)?.count();
+        Ok(count as u64)
+    }
+
+    fn get_vertex_properties(&self, q: VertexPropertyQuery) -> Result<Vec<models::VertexProperty>> {
+        let manager = VertexPropertyManager::new(self.db.clone())?;
+        let mut properties = Vec::new();
+
+        for item in self.vertex_query_to_iterator(q.inner)? {
+            let (id, _) = item?;
+
+            if let Some(value) = manager.get(id, &q.name)? {
+                properties.push(models::VertexProperty::new(id, value));
+            }
+        }
+
+        Ok(properties)
+    }
+
+    fn set_vertex_properties(&self, q: VertexPropertyQuery, value: &JsonValue) -> Result<()> {
+        let manager = VertexPropertyManager::new(self.db.clone())?;
+        let mut batch = DistributedBatch::default();
+        let mut events = self.events.pending();
+        let name = &q.name;
+
+        for item in self.vertex_query_to_iterator(q.inner)? {
+            let (id, _) = item?;
+            manager.set(&mut batch, id, &q.name, value)?;
+            events.push(|| models::Event::VertexPropertySet(id, name.clone(), value.clone()));
+        }
+
+        self.db.write(batch)?;
+        events.publish();
+        Ok(())
+    }
+
+    fn delete_vertex_properties(&self, q: VertexPropertyQuery) -> Result<()> {
+        let manager = VertexPropertyManager::new(self.db.clone())?;
+        let mut batch = DistributedBatch::default();
+        let mut events = self.events.pending();
+        let name = &q.name;
+
+        for item in self.vertex_query_to_iterator(q.inner)? {
+            let (id, _) = item?;
+            manager.delete(&mut batch, id, &q.name)?;
+            events.push(|| models::Event::VertexPropertyDeleted(id, name.clone()));
+        }
+
+        self.db.write(batch)?;
+        events.publish();
+        Ok(())
+    }
+
+    fn get_all_vertex_properties<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<Vec<models::VertexProperties>> {
+        let manager = VertexPropertyManager::new(self.db.clone())?;
+        let mut results = Vec::new();
+
+        for item in self.vertex_query_to_iterator(q.into())? {
+            let (id, t) = item?;
+            let mut props = Vec::new();
+
+            for item in manager.iterate_for_owner(id)? {
+                let ((_, name), value) = item?;
+                props.push(models::NamedProperty::new(name, value));
+            }
+
+            results.push(models::VertexProperties::new(models::Vertex::with_id(id, t), props));
+        }
+
+        Ok(results)
+    }
+
+    fn get_edge_properties(&self, q: EdgePropertyQuery) -> Result<Vec<models::EdgeProperty>> {
+        let manager = EdgePropertyManager::new(self.db.clone())?;
+        let mut properties = Vec::new();
+
+        for item in self.edge_query_to_iterator(q.inner)? {
+            let (outbound_id, t, _, inbound_id) = item?;
+
+            if let Some(value) = manager.get(outbound_id, &t, inbound_id, &q.name)? {
+                let key = models::EdgeKey::new(outbound_id, t, inbound_id);
+                properties.push(models::EdgeProperty::new(key, value));
+            }
+        }
+
+        Ok(properties)
+    }
+
+    fn set_edge_properties(&self, q: EdgePropertyQuery, value: &JsonValue) -> Result<()> {
+        let manager = EdgePropertyManager::new(self.db.clone())?;
+        let mut batch = DistributedBatch::default();
+        let mut events = self.events.pending();
+        let name = &q.name;
+
+        for item in self.edge_query_to_iterator(q.inner)? {
+            let (outbound_id, t, _, inbound_id) = item?;
+            manager.set(&mut batch, outbound_id, &t, inbound_id, &q.name, value)?;
+            events.push(|| {
+                let key = models::EdgeKey::new(outbound_id, t, inbound_id);
+                models::Event::EdgePropertySet(key, name.clone(), value.clone())
+            });
+        }
+
+        self.db.write(batch)?;
+        events.publish();
+        Ok(())
+    }
+
+    fn delete_edge_properties(&self, q: EdgePropertyQuery) -> Result<()> {
+        let manager = EdgePropertyManager::new(self.db.clone())?;
+        let mut batch = DistributedBatch::default();
+        let mut events = self.events.pending();
+        let name = &q.name;
+
+        for item in self.edge_query_to_iterator(q.inner)? {
+            let (outbound_id, t, _, inbound_id) = item?;
+            manager.delete(&mut batch, outbound_id, &t, inbound_id, &q.name)?;
+            events.push(|| {
+                let key = models::EdgeKey::new(outbound_id, t, inbound_id);
+                models::Event::EdgePropertyDeleted(key, name.clone())
+            });
+        }
+
+        self.db.write(batch)?;
+        events.publish();
+        Ok(())
+    }
+
+    fn get_all_edge_properties<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<Vec<models::EdgeProperties>> {
+        let manager = EdgePropertyManager::new(self.db.clone())?;
+        let mut results = Vec::new();
+
+        for item in self.edge_query_to_iterator(q.into())? {
+            let (outbound_id, t, update_datetime, inbound_id) = item?;
+            let mut props = Vec::new();
+
+            for item in manager.iterate_for_owner(outbound_id, &t, inbound_id)? {
+                let ((_, _, _, name), value) = item?;
+                props.push(models::NamedProperty::new(name, value));
+            }
+
+            let key = models::EdgeKey::new(outbound_id, t, inbound_id);
+            results.push(models::EdgeProperties::new(
+                models::Edge::new(key, update_datetime),
+                props,
+            ));
+        }
+
+        Ok(results)
+    }
+
+    fn get_keys_scanned(&self) -> Option<u64> {
+        Some(self.keys_scanned.load(Ordering::Relaxed))
+    }
+}
@@ -0,0 +1,432 @@
+//! The storage engine that the managers read and write through. It's an
+//! ordered key-value store with named keyspaces, so that the key layouts in
+//! `bytes` and the edge range and property logic in `managers` can be shared
+//! by any backend that provides one.
+
+use super::bytes::{
+    build_bitmap, build_bitmap_update, build_counter, build_sketch_update, prefix_successor, read_bitmap,
+    read_bitmap_update, read_counter, read_sketch_update, BitmapUpdate,
+};
+use errors::{ErrorKind, Result, ResultExt};
+use hll::{register_update, HyperLogLog};
+use rocksdb::{ColumnFamily, Direction, IteratorMode, MergeOperands, ReadOptions, WriteBatch, DB};
+use std::io::Cursor;
+use std::ops::Deref;
+
+/// A key and value read from a keyspace.
+pub type KvItem = (Box<[u8]>, Box<[u8]>);
+
+/// Where to start iterating over a keyspace, and in which direction.
+#[derive(Clone, Copy, Debug)]
+pub enum KvIteratorMode<'a> {
+    /// Iterate forward from the first key.
+    Start,
+    /// Iterate in reverse from the last key.
+    End,
+    /// Iterate forward from the first key at or after the given one.
+    Forward(&'a [u8]),
+    /// Iterate in reverse from the last key at or before the given one.
+    Reverse(&'a [u8]),
+}
+
+/// Options for iterating over a keyspace.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KvReadOptions {
+    /// The key to stop at when iterating forward, exclusive. This lets the
+    /// engine stop at the end of a range, rather than reading past it.
+    pub upper_bound: Option<Vec<u8>>,
+
+    /// Whether to keep what's read out of the engine's cache, so that large
+    /// scans don't evict the hot working set.
+    pub skip_cache: bool,
+
+    /// How many bytes to read ahead of the iterator, or `None` for the
+    /// engine's default.
+    pub readahead_size: Option<usize>,
+}
+
+impl KvReadOptions {
+    /// Gets a copy of these options that stops at the end of the keys
+    /// starting with `prefix`.
+    ///
+    /// # Arguments
+    /// * `prefix` - The prefix.
+    pub fn bounded_to_prefix(&self, prefix: &[u8]) -> Self {
+        KvReadOptions {
+            upper_bound: prefix_successor(prefix),
+            ..self.clone()
+        }
+    }
+}
+
+/// An ordered key-value store with named keyspaces. Writes are staged in a
+/// batch, which is applied atomically.
+pub trait KvEngine: Send + Sync + 'static {
+    /// Writes that haven't been applied yet.
+    type Batch: Default;
+
+    /// A value read from a keyspace.
+    type Value: Deref<Target = [u8]>;
+
+    /// Gets a value.
+    ///
+    /// # Arguments
+    /// * `keyspace` - The name of the keyspace.
+    /// * `key` - The key.
+    fn get(&self, keyspace: &str, key: &[u8]) -> Result<Option<Self::Value>>;
+
+    /// Gets a value and decodes it in place. Engines that can read values
+    /// without copying them out of their own buffers should override this,
+    /// so that hot point reads don't allocate; by default, it decodes the
+    /// value returned by `get`.
+    ///
+    /// # Arguments
+    /// * `keyspace` - The name of the keyspace.
+    /// * `key` - The key.
+    /// * `f` - Decodes the value.
+    fn get_with<T, F>(&self, keyspace: &str, key: &[u8], f: F) -> Result<Option<T>>
+    where
+        F: FnOnce(&[u8]) -> Result<T>,
+    {
+        match self.get(keyspace, key)? {
+            Some(value) => Ok(Some(f(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Checks which of a set of keys exist, in the same order. Engines that
+    /// can look up many keys in one call should override this; by default,
+    /// each key is looked up with `get_with`.
+    ///
+    /// # Arguments
+    /// * `keyspace` - The name of the keyspace.
+    /// * `keys` - The keys.
+    fn exists_many(&self, keyspace: &str, keys: &[Vec<u8>]) -> Result<Vec<bool>> {
+        keys.iter()
+            .map(|key| Ok(self.get_with(keyspace, key, |_| Ok(()))?.is_some()))
+            .collect()
+    }
+
+    /// Iterates over a keyspace, ordered by key. The iterator borrows the
+    /// engine, so it can't outlive it.
+    ///
+    /// # Arguments
+    /// * `keyspace` - The name of the keyspace.
+    /// * `mode` - Where to start, and in which direction.
+    fn iterate<'a>(&'a self, keyspace: &str, mode: KvIteratorMode) -> Result<Box<dyn Iterator<Item = KvItem> + 'a>>;
+
+    /// Iterates over a keyspace with the given options, ordered by key.
+    /// Engines that can stop at the upper bound or bypass their cache
+    /// themselves should override this; by default, the upper bound is
+    /// applied to the keys `iterate` returns, and the other options are
+    /// ignored.
+    ///
+    /// # Arguments
+    /// * `keyspace` - The name of the keyspace.
+    /// * `mode` - Where to start, and in which direction.
+    /// * `options` - How to read.
+    fn iterate_with_options<'a>(
+        &'a self,
+        keyspace: &str,
+        mode: KvIteratorMode,
+        options: &KvReadOptions,
+    ) -> Result<Box<dyn Iterator<Item = KvItem> + 'a>> {
+        let iterator = self.iterate(keyspace, mode)?;
+
+        match (mode, options.upper_bound.clone()) {
+            (KvIteratorMode::Start, Some(upper_bound)) | (KvIteratorMode::Forward(_), Some(upper_bound)) => {
+                Ok(Box::new(iterator.take_while(move |item| item.0[..] < upper_bound[..])))
+            }
+            _ => Ok(iterator),
+        }
+    }
+
+    /// Stages setting a value.
+    ///
+    /// # Arguments
+    /// * `batch` - The batch to stage the write in.
+    /// * `keyspace` - The name of the keyspace.
+    /// * `key` - The key.
+    /// * `value` - The value.
+    fn put(&self, batch: &mut Self::Batch, keyspace: &str, key: &[u8], value: &[u8]) -> Result<()>;
+
+    /// Stages deleting a value.
+    ///
+    /// # Arguments
+    /// * `batch` - The batch to stage the write in.
+    /// * `keyspace` - The name of the keyspace.
+    /// * `key` - The key.
+    fn delete(&self, batch: &mut Self::Batch, keyspace: &str, key: &[u8]) -> Result<()>;
+
+    /// Stages adding to a counter, which is stored as a value built by
+    /// `build_counter`. A missing counter counts as zero. Unlike reading the
+    /// counter and putting the sum, concurrent increments can't overwrite
+    /// each other.
+    ///
+    /// # Arguments
+    /// * `batch` - The batch to stage the write in.
+    /// * `keyspace` - The name of the keyspace.
+    /// * `key` - The key.
+    /// * `delta` - The amount to add.
+    fn increment(&self, batch: &mut Self::Batch, keyspace: &str, key: &[u8], delta: i64) -> Result<()>;
+
+    /// Stages adding an item to a HyperLogLog sketch, which is stored as the
+    /// bytes of a `HyperLogLog`. A missing sketch is created with the given
+    /// precision. Like with `increment`, concurrent additions can't
+    /// overwrite each other.
+    ///
+    /// # Arguments
+    /// * `batch` - The batch to stage the write in.
+    /// * `keyspace` - The name of the keyspace.
+    /// * `key` - The key.
+    /// * `precision` - The precision of the sketch.
+    /// * `item` - The item to add.
+    fn add_to_sketch(
+        &self,
+        batch: &mut Self::Batch,
+        keyspace: &str,
+        key: &[u8],
+        precision: u8,
+        item: &[u8],
+    ) -> Result<()>;
+
+    /// Stages an update to an adjacency bitmap, which is stored as the bytes
+    /// built by `build_bitmap`, or as no bytes if there isn't one. Like with
+    /// `increment`, concurrent updates can't overwrite each other.
+    ///
+    /// # Arguments
+    /// * `batch` - The batch to stage the write in.
+    /// * `keyspace` - The name of the keyspace.
+    /// * `key` - The key.
+    /// * `update` - The update.
+    fn update_bitmap(&self, batch: &mut Self::Batch, keyspace: &str, key: &[u8], update: &BitmapUpdate) -> Result<()>;
+
+    /// Applies a batch of writes.
+    ///
+    /// # Arguments
+    /// * `batch` - The batch.
+    fn write(&self, batch: Self::Batch) -> Result<()>;
+}
+
+// Gets a column family handle, returning an error rather than panicking if
+// the column family doesn't exist.
+pub fn cf_handle<'a>(db: &'a DB, name: &str) -> Result<&'a ColumnFamily> {
+    db.cf_handle(name)
+        .ok_or_else(|| ErrorKind::NotFound(format!("column family {}", name)).into())
+}
+
+// The merge operator that sums counters. Operands that can't be read are
+// ignored rather than failing the merge, since there'd be no way to recover
+// from that.
+pub fn merge_counters(_: &[u8], existing: Option<&[u8]>, operands: &MergeOperands) -> Option<Vec<u8>> {
+    let read = |value: &[u8]| read_counter(&mut Cursor::new(value)).unwrap_or(0);
+    let mut sum = existing.map(read).unwrap_or(0);
+
+    for operand in operands {
+        sum = sum.wrapping_add(read(operand));
+    }
+
+    Some(build_counter(sum))
+}
+
+// Applies merge operands to a HyperLogLog sketch, returning the merged
+// sketch. Operands are either sketch updates, or whole sketches. As with
+// counters, operands that can't be read are ignored.
+pub fn apply_sketch_operands<'a, I>(existing: Option<&[u8]>, operands: I) -> Vec<u8>
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    let mut sketch = existing.and_then(|value| HyperLogLog::from_bytes(value).ok());
+
+    for operand in operands {
+        if let Ok((precision, index, rank)) = read_sketch_update(operand) {
+            if sketch.is_none() {
+                sketch = HyperLogLog::new(precision).ok();
+            }
+
+            if let Some(ref mut sketch) = sketch {
+                if sketch.precision() == precision {
+                    sketch.update(index, rank);
+                }
+            }
+        } else if let Ok(other) = HyperLogLog::from_bytes(operand) {
+            match sketch {
+                Some(ref mut sketch) => {
+                    let _ = sketch.merge(&other);
+                }
+                None => sketch = Some(other),
+            }
+        }
+    }
+
+    sketch.map(|sketch| sketch.as_bytes().to_vec()).unwrap_or_default()
+}
+
+// The merge operator that merges HyperLogLog sketches.
+pub fn merge_sketches(_: &[u8], existing: Option<&[u8]>, operands: &MergeOperands) -> Option<Vec<u8>> {
+    Some(apply_sketch_operands(existing, operands))
+}
+
+// Applies merge operands to an adjacency bitmap, returning the merged
+// bitmap, or no bytes if there still isn't one. Only a seed creates a
+// bitmap, so that updates racing with a bitmap being deleted don't leave a
+// partial one behind. As with counters, operands that can't be read are
+// ignored.
+pub fn apply_bitmap_operands<'a, I>(existing: Option<&[u8]>, operands: I) -> Vec<u8>
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    let mut bitmap = existing
+        .filter(|value| !value.is_empty())
+        .and_then(|value| read_bitmap(value).ok());
+
+    for operand in operands {
+        match read_bitmap_update(operand) {
+            Ok(BitmapUpdate::Insert(neighbor_id)) => {
+                if let Some(ref mut bitmap) = bitmap {
+                    bitmap.insert(neighbor_id);
+                }
+            }
+            Ok(BitmapUpdate::Remove(neighbor_id)) => {
+                if let Some(ref mut bitmap) = bitmap {
+                    bitmap.remove(neighbor_id);
+                }
+            }
+            Ok(BitmapUpdate::Seed(seed)) => match bitmap {
+                Some(ref mut bitmap) => *bitmap |= seed,
+                None => bitmap = Some(seed),
+            },
+            Err(_) => (),
+        }
+    }
+
+    bitmap.map(|bitmap| build_bitmap(&bitmap)).unwrap_or_default()
+}
+
+// The merge operator that updates adjacency bitmaps.
+pub fn merge_bitmaps(_: &[u8], existing: Option<&[u8]>, operands: &MergeOperands) -> Option<Vec<u8>> {
+    Some(apply_bitmap_operands(existing, operands))
+}
+
+fn to_iterator_mode(mode: KvIteratorMode) -> IteratorMode {
+    match mode {
+        KvIteratorMode::Start => IteratorMode::Start,
+        KvIteratorMode::End => IteratorMode::End,
+        KvIteratorMode::Forward(key) => IteratorMode::From(key, Direction::Forward),
+        KvIteratorMode::Reverse(key) => IteratorMode::From(key, Direction::Reverse),
+    }
+}
+
+// Keyspaces are column families.
+impl KvEngine for DB {
+    type Batch = WriteBatch;
+    type Value = Vec<u8>;
+
+    fn get(&self, keyspace: &str, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        // Keeps the column family and key as context if the read fails
+        self.get_cf(cf_handle(self, keyspace)?, key)
+            .chain_err(|| ErrorKind::Storage(keyspace.to_string(), key.to_vec()))
+    }
+
+    // Pinned reads point into the block cache or memtable, rather than
+    // copying the value into a new buffer like `get_cf` does.
+    fn get_with<T, F>(&self, keyspace: &str, key: &[u8], f: F) -> Result<Option<T>>
+    where
+        F: FnOnce(&[u8]) -> Result<T>,
+    {
+        let value = self
+            .get_pinned_cf(cf_handle(self, keyspace)?, key)
+            .chain_err(|| ErrorKind::Storage(keyspace.to_string(), key.to_vec()))?;
+
+        match value {
+            Some(value) => Ok(Some(f(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    // Looks the keys up in a single `multi_get`, which sorts them and reads
+    // keys that share a block together, rather than seeking for each one.
+    fn exists_many(&self, keyspace: &str, keys: &[Vec<u8>]) -> Result<Vec<bool>> {
+        let cf = cf_handle(self, keyspace)?;
+
+        self.multi_get_cf(keys.iter().map(|key| (cf, key)))
+            .into_iter()
+            .zip(keys)
+            .map(|(value, key)| {
+                let value = value.chain_err(|| ErrorKind::Storage(keyspace.to_string(), key.clone()))?;
+                Ok(value.is_some())
+            })
+            .collect()
+    }
+
+    fn iterate<'a>(&'a self, keyspace: &str, mode: KvIteratorMode) -> Result<Box<dyn Iterator<Item = KvItem> + 'a>> {
+        Ok(Box::new(
+            self.iterator_cf(cf_handle(self, keyspace)?, to_iterator_mode(mode)),
+        ))
+    }
+
+    fn iterate_with_options<'a>(
+        &'a self,
+        keyspace: &str,
+        mode: KvIteratorMode,
+        options: &KvReadOptions,
+    ) -> Result<Box<dyn Iterator<Item = KvItem> + 'a>> {
+        let mut opts = ReadOptions::default();
+        opts.fill_cache(!options.skip_cache);
+
+        if let Some(readahead_size) = options.readahead_size {
+            opts.set_readahead_size(readahead_size);
+        }
+
+        if let Some(ref upper_bound) = options.upper_bound {
+            opts.set_iterate_upper_bound(upper_bound.clone());
+        }
+
+        let iterator = self.iterator_cf_opt(cf_handle(self, keyspace)?, opts, to_iterator_mode(mode));
+        Ok(Box::new(iterator))
+    }
+
+    fn put(&self, batch: &mut WriteBatch, keyspace: &str, key: &[u8], value: &[u8]) -> Result<()> {
+        batch.put_cf(cf_handle(self, keyspace)?, key, value);
+        Ok(())
+    }
+
+    fn delete(&self, batch: &mut WriteBatch, keyspace: &str, key: &[u8]) -> Result<()> {
+        batch.delete_cf(cf_handle(self, keyspace)?, key);
+        Ok(())
+    }
+
+    fn increment(&self, batch: &mut WriteBatch, keyspace: &str, key: &[u8], delta: i64) -> Result<()> {
+        batch.merge_cf(cf_handle(self, keyspace)?, key, build_counter(delta));
+        Ok(())
+    }
+
+    // Only the register the item raises is written, rather than the whole
+    // sketch.
+    fn add_to_sketch(
+        &self,
+        batch: &mut WriteBatch,
+        keyspace: &str,
+        key: &[u8],
+        precision: u8,
+        item: &[u8],
+    ) -> Result<()> {
+        let (index, rank) = register_update(precision, item);
+        batch.merge_cf(
+            cf_handle(self, keyspace)?,
+            key,
+            build_sketch_update(precision, index, rank),
+        );
+        Ok(())
+    }
+
+    fn update_bitmap(&self, batch: &mut WriteBatch, keyspace: &str, key: &[u8], update: &BitmapUpdate) -> Result<()> {
+        batch.merge_cf(cf_handle(self, keyspace)?, key, build_bitmap_update(update));
+        Ok(())
+    }
+
+    fn write(&self, batch: WriteBatch) -> Result<()> {
+        DB::write(self, batch)?;
+        Ok(())
+    }
+}
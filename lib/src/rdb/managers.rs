@@ -1,40 +1,201 @@
 use super::bytes::*;
+use super::engine::{KvEngine, KvItem, KvIteratorMode, KvReadOptions};
 use chrono::offset::Utc;
 use chrono::DateTime;
-use errors::Result;
+use clock::Clock;
+use errors::{ErrorKind, Result};
+use hll::HyperLogLog;
+use memory::RetentionPolicy;
 use models;
-use rocksdb::{ColumnFamily, DBIterator, Direction, IteratorMode, WriteBatch, DB};
+use roaring::RoaringBitmap;
 use serde_json;
 use serde_json::Value as JsonValue;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashSet};
 use std::io::Cursor;
-use std::ops::Deref;
-use std::sync::Arc;
+use std::iter::Peekable;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use std::u32;
 use std::u8;
+use util::{datetime_from_signed_nanos, signed_nanos_since_epoch};
 use uuid::Uuid;
 
 pub type OwnedPropertyItem = ((Uuid, String), JsonValue);
 pub type VertexItem = (Uuid, models::Type);
 pub type EdgeRangeItem = (Uuid, models::Type, DateTime<Utc>, Uuid);
 pub type EdgePropertyItem = ((Uuid, models::Type, Uuid, String), JsonValue);
+pub type IndexEntryItem = (Box<[u8]>, Uuid);
+pub type NeighborSketchItem = ((models::Type, DateTime<Utc>), HyperLogLog);
+
+// The precision of the inbound neighbor sketches, which take 1kb each and
+// have a standard error of about 3%.
+pub const NEIGHBOR_SKETCH_PRECISION: u8 = 10;
+
+// Iterates forward from `start_key` over the keys starting with `prefix`.
+// The scan is bounded by the prefix's successor, so the engine stops at the
+// end of the prefix rather than handing back keys past it.
+fn iterate_prefixed<'a, E: KvEngine>(
+    db: &'a E,
+    keyspace: &str,
+    start_key: &[u8],
+    prefix: &[u8],
+) -> Result<Box<dyn Iterator<Item = KvItem> + 'a>> {
+    db.iterate_with_options(
+        keyspace,
+        KvIteratorMode::Forward(start_key),
+        &KvReadOptions::default().bounded_to_prefix(prefix),
+    )
+}
 
-fn take_while_prefixed(iterator: DBIterator, prefix: Vec<u8>) -> impl Iterator<Item = (Box<[u8]>, Box<[u8]>)> {
-    iterator.take_while(move |item| -> bool {
-        let (ref k, _) = *item;
-        k.starts_with(&prefix)
-    })
+// Turns the result of reading an item that may be skipped into what
+// `filter_map` expects.
+fn transpose<T>(result: Result<Option<T>>) -> Option<Result<T>> {
+    match result {
+        Ok(Some(item)) => Some(Ok(item)),
+        Ok(None) => None,
+        Err(err) => Some(Err(err)),
+    }
+}
+
+// Merges the items of two keyspaces with the same key layout, ordered by
+// key. A key should only be in one of them, but if it's in both, the first
+// keyspace's item wins.
+struct MergedItems<'a> {
+    first: Peekable<Box<dyn Iterator<Item = KvItem> + 'a>>,
+    second: Peekable<Box<dyn Iterator<Item = KvItem> + 'a>>,
+}
+
+impl<'a> Iterator for MergedItems<'a> {
+    type Item = KvItem;
+
+    fn next(&mut self) -> Option<KvItem> {
+        let ordering = match (self.first.peek(), self.second.peek()) {
+            (Some(first), Some(second)) => first.0.cmp(&second.0),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => return None,
+        };
+
+        match ordering {
+            Ordering::Less => self.first.next(),
+            Ordering::Greater => self.second.next(),
+            Ordering::Equal => {
+                self.second.next();
+                self.first.next()
+            }
+        }
+    }
+}
+
+// Property values are stored in a hot and a cold keyspace, which share a key
+// layout. Properties are written to the cold keyspace if their name is one
+// of the cold names, and to the hot one otherwise. Reads and scans check
+// both keyspaces, so that properties written under a different set of cold
+// names are still found.
+struct PropertyKeyspaces<'a> {
+    hot: &'static str,
+    cold: &'static str,
+    cold_names: &'a HashSet<String>,
+}
+
+impl<'a> PropertyKeyspaces<'a> {
+    // Gets the keyspace a property is written to, followed by the other
+    // one.
+    fn for_name(&self, name: &str) -> [&'static str; 2] {
+        if self.cold_names.contains(name) {
+            [self.cold, self.hot]
+        } else {
+            [self.hot, self.cold]
+        }
+    }
+
+    fn get_with<E, T, F>(&self, db: &E, name: &str, key: &[u8], f: F) -> Result<Option<T>>
+    where
+        E: KvEngine,
+        F: FnOnce(&[u8]) -> Result<T>,
+    {
+        let mut f = Some(f);
+
+        // The other keyspace is only checked if the property isn't in the
+        // one it's written to; an expired value still shadows it
+        for keyspace in &self.for_name(name) {
+            let value = db.get_with(keyspace, key, |value_bytes| -> Result<Option<T>> {
+                match read_property_value(value_bytes, Utc::now())? {
+                    Some(value_json) => Ok(Some((f.take().unwrap())(value_json)?)),
+                    None => Ok(None),
+                }
+            })?;
+
+            if let Some(value) = value {
+                return Ok(value);
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn put<E: KvEngine>(&self, db: &E, batch: &mut E::Batch, name: &str, key: &[u8], value: &[u8]) -> Result<()> {
+        let [keyspace, other_keyspace] = self.for_name(name);
+        db.put(batch, keyspace, key, value)?;
+        db.delete(batch, other_keyspace, key)
+    }
+
+    fn delete<E: KvEngine>(&self, db: &E, batch: &mut E::Batch, key: &[u8]) -> Result<()> {
+        db.delete(batch, self.hot, key)?;
+        db.delete(batch, self.cold, key)
+    }
+
+    fn iterate<'b, E: KvEngine>(
+        &self,
+        db: &'b E,
+        start_key: &[u8],
+        options: &KvReadOptions,
+    ) -> Result<Box<dyn Iterator<Item = KvItem> + 'b>> {
+        let hot = db.iterate_with_options(self.hot, KvIteratorMode::Forward(start_key), options)?;
+        let cold = db.iterate_with_options(self.cold, KvIteratorMode::Forward(start_key), options)?;
+
+        Ok(Box::new(MergedItems {
+            first: hot.peekable(),
+            second: cold.peekable(),
+        }))
+    }
 }
 
-pub struct VertexManager {
-    pub db: Arc<DB>,
-    pub cf: ColumnFamily,
+pub struct VertexManager<E: KvEngine> {
+    pub db: Arc<E>,
+    // How scans read from the engine.
+    pub read_options: KvReadOptions,
+    // The settings for recording the history of vertices that are written,
+    // and of the properties and edges deleted along with them, if it's
+    // recorded at all.
+    pub history: Option<Arc<HistorySettings>>,
 }
 
-impl VertexManager {
-    pub fn new(db: Arc<DB>) -> Self {
-        VertexManager {
-            cf: db.cf_handle("vertices:v1").unwrap(),
+impl<E: KvEngine> VertexManager<E> {
+    pub fn new(db: Arc<E>) -> Result<Self> {
+        Ok(VertexManager {
             db,
+            read_options: KvReadOptions::default(),
+            history: None,
+        })
+    }
+
+    pub fn with_read_options(self, read_options: KvReadOptions) -> Self {
+        VertexManager { read_options, ..self }
+    }
+
+    pub fn with_history(self, history: Option<Arc<HistorySettings>>) -> Self {
+        VertexManager { history, ..self }
+    }
+
+    fn record(&self, batch: &mut E::Batch, id: Uuid, t: Option<&models::Type>) -> Result<()> {
+        if let Some(ref history) = self.history {
+            let value = t.map(|t| build(&[Component::Type(t)]));
+            HistoryManager::new(self.db.clone())?.record(batch, history, &vertex_history_key(id), value.as_deref())?;
         }
+
+        Ok(())
     }
 
     fn key(&self, id: Uuid) -> Vec<u8> {
@@ -42,62 +203,80 @@ impl VertexManager {
     }
 
     pub fn exists(&self, id: Uuid) -> Result<bool> {
-        Ok(self.db.get_cf(self.cf, &self.key(id))?.is_some())
+        Ok(self.db.get_with("vertices:v1", &self.key(id), |_| Ok(()))?.is_some())
+    }
+
+    pub fn exists_many(&self, ids: &[Uuid]) -> Result<Vec<bool>> {
+        let keys: Vec<Vec<u8>> = ids.iter().map(|id| self.key(*id)).collect();
+        self.db.exists_many("vertices:v1", &keys)
     }
 
     pub fn get(&self, id: Uuid) -> Result<Option<models::Type>> {
-        match self.db.get_cf(self.cf, &self.key(id))? {
-            Some(value_bytes) => {
-                let mut cursor = Cursor::new(value_bytes.deref());
-                Ok(Some(read_type(&mut cursor)))
-            }
-            None => Ok(None),
-        }
+        self.db.get_with("vertices:v1", &self.key(id), |value_bytes| {
+            read_type(&mut Cursor::new(value_bytes))
+        })
     }
 
-    fn iterate(&self, iterator: DBIterator) -> Result<impl Iterator<Item = Result<VertexItem>>> {
+    fn iterate<I>(&self, iterator: I) -> Result<impl Iterator<Item = Result<VertexItem>>>
+    where
+        I: Iterator<Item = KvItem>,
+    {
         Ok(iterator.map(|item| -> Result<VertexItem> {
             let (k, v) = item;
 
             let id = {
                 debug_assert_eq!(k.len(), 16);
                 let mut cursor = Cursor::new(k);
-                read_uuid(&mut cursor)
+                read_uuid(&mut cursor)?
             };
 
             let mut cursor = Cursor::new(v);
-            let t = read_type(&mut cursor);
+            let t = read_type(&mut cursor)?;
             Ok((id, t))
         }))
     }
 
-    pub fn iterate_for_range(&self, id: Uuid) -> Result<impl Iterator<Item = Result<VertexItem>>> {
+    pub fn iterate_for_range<'a>(&'a self, id: Uuid) -> Result<impl Iterator<Item = Result<VertexItem>> + 'a> {
         let low_key = build(&[Component::Uuid(id)]);
-        let iter = self
-            .db
-            .iterator_cf(self.cf, IteratorMode::From(&low_key, Direction::Forward))?;
+        let iter =
+            self.db
+                .iterate_with_options("vertices:v1", KvIteratorMode::Forward(&low_key), &self.read_options)?;
         self.iterate(iter)
     }
 
-    pub fn create(&self, batch: &mut WriteBatch, vertex: &models::Vertex) -> Result<()> {
+    pub fn create(&self, batch: &mut E::Batch, vertex: &models::Vertex) -> Result<()> {
+        let type_count_manager = TypeCountManager::new_for_vertices(self.db.clone())?;
+
+        // Vertices can be overwritten, possibly with a different type
+        if let Some(t) = self.get(vertex.id)? {
+            type_count_manager.add(batch, &t, -1)?;
+        }
+
         let key = self.key(vertex.id);
-        batch.put_cf(self.cf, &key, &build(&[Component::Type(&vertex.t)]))?;
-        Ok(())
+        self.db
+            .put(batch, "vertices:v1", &key, &build(&[Component::Type(&vertex.t)]))?;
+        type_count_manager.add(batch, &vertex.t, 1)?;
+        self.record(batch, vertex.id, Some(&vertex.t))
     }
 
-    pub fn delete(&self, mut batch: &mut WriteBatch, id: Uuid) -> Result<()> {
-        batch.delete_cf(self.cf, &self.key(id))?;
+    pub fn delete(&self, mut batch: &mut E::Batch, id: Uuid) -> Result<()> {
+        if let Some(t) = self.get(id)? {
+            TypeCountManager::new_for_vertices(self.db.clone())?.add(batch, &t, -1)?;
+        }
+
+        self.db.delete(batch, "vertices:v1", &self.key(id))?;
+        self.record(batch, id, None)?;
 
-        let vertex_property_manager = VertexPropertyManager::new(self.db.clone());
+        let vertex_property_manager = VertexPropertyManager::new(self.db.clone())?.with_history(self.history.clone());
         for item in vertex_property_manager.iterate_for_owner(id)? {
             let ((vertex_property_owner_id, vertex_property_name), _) = item?;
             vertex_property_manager.delete(&mut batch, vertex_property_owner_id, &vertex_property_name[..])?;
         }
 
-        let edge_manager = EdgeManager::new(self.db.clone());
+        let edge_manager = EdgeManager::new(self.db.clone())?.with_history(self.history.clone());
 
         {
-            let edge_range_manager = EdgeRangeManager::new(self.db.clone());
+            let edge_range_manager = EdgeRangeManager::new(self.db.clone())?;
             for item in edge_range_manager.iterate_for_owner(id)? {
                 let (edge_range_outbound_id, edge_range_t, edge_range_update_datetime, edge_range_inbound_id) = item?;
                 debug_assert_eq!(edge_range_outbound_id, id);
@@ -112,7 +291,7 @@ impl VertexManager {
         }
 
         {
-            let reversed_edge_range_manager = EdgeRangeManager::new_reversed(self.db.clone());
+            let reversed_edge_range_manager = EdgeRangeManager::new_reversed(self.db.clone())?;
             for item in reversed_edge_range_manager.iterate_for_owner(id)? {
                 let (
                     reversed_edge_range_inbound_id,
@@ -121,6 +300,12 @@ impl VertexManager {
                     reversed_edge_range_outbound_id,
                 ) = item?;
                 debug_assert_eq!(reversed_edge_range_inbound_id, id);
+
+                // Edges from the vertex to itself were already deleted above
+                if reversed_edge_range_outbound_id == id {
+                    continue;
+                }
+
                 edge_manager.delete(
                     &mut batch,
                     reversed_edge_range_outbound_id,
@@ -131,21 +316,209 @@ impl VertexManager {
             }
         }
 
+        let neighbor_sketch_manager = NeighborSketchManager::new(self.db.clone())?;
+        for item in neighbor_sketch_manager.iterate_for_owner(id, None)? {
+            let ((t, window_start), _) = item?;
+            neighbor_sketch_manager.delete(&mut batch, id, &t, window_start)?;
+        }
+
+        for adjacency_bitmap_manager in &[
+            AdjacencyBitmapManager::new(self.db.clone())?,
+            AdjacencyBitmapManager::new_reversed(self.db.clone())?,
+        ] {
+            for item in adjacency_bitmap_manager.iterate_for_owner(id)? {
+                adjacency_bitmap_manager.delete(&mut batch, id, &item?)?;
+            }
+        }
+
         Ok(())
     }
+
+    pub fn delete_in_chunks<F>(&self, id: Uuid, chunk_size: usize, mut commit: F) -> Result<()>
+    where
+        F: FnMut(E::Batch) -> Result<()>,
+    {
+        let mut batch = E::Batch::default();
+        let mut batch_size = 0;
+
+        macro_rules! bump_batch_size {
+            () => {
+                batch_size += 1;
+
+                if batch_size == chunk_size {
+                    commit(batch)?;
+                    batch = E::Batch::default();
+                    batch_size = 0;
+                }
+            };
+        }
+
+        let vertex_property_manager = VertexPropertyManager::new(self.db.clone())?.with_history(self.history.clone());
+        for item in vertex_property_manager.iterate_for_owner(id)? {
+            let ((vertex_property_owner_id, vertex_property_name), _) = item?;
+            vertex_property_manager.delete(&mut batch, vertex_property_owner_id, &vertex_property_name[..])?;
+            bump_batch_size!();
+        }
+
+        let edge_manager = EdgeManager::new(self.db.clone())?.with_history(self.history.clone());
+
+        {
+            let edge_range_manager = EdgeRangeManager::new(self.db.clone())?;
+            for item in edge_range_manager.iterate_for_owner(id)? {
+                let (edge_range_outbound_id, edge_range_t, edge_range_update_datetime, edge_range_inbound_id) = item?;
+                edge_manager.delete(
+                    &mut batch,
+                    edge_range_outbound_id,
+                    &edge_range_t,
+                    edge_range_inbound_id,
+                    edge_range_update_datetime,
+                )?;
+                bump_batch_size!();
+            }
+        }
+
+        {
+            let reversed_edge_range_manager = EdgeRangeManager::new_reversed(self.db.clone())?;
+            for item in reversed_edge_range_manager.iterate_for_owner(id)? {
+                let (
+                    reversed_edge_range_inbound_id,
+                    reversed_edge_range_t,
+                    reversed_edge_range_update_datetime,
+                    reversed_edge_range_outbound_id,
+                ) = item?;
+
+                if reversed_edge_range_outbound_id == id {
+                    continue;
+                }
+
+                edge_manager.delete(
+                    &mut batch,
+                    reversed_edge_range_outbound_id,
+                    &reversed_edge_range_t,
+                    reversed_edge_range_inbound_id,
+                    reversed_edge_range_update_datetime,
+                )?;
+                bump_batch_size!();
+            }
+        }
+
+        let neighbor_sketch_manager = NeighborSketchManager::new(self.db.clone())?;
+        for item in neighbor_sketch_manager.iterate_for_owner(id, None)? {
+            let ((t, window_start), _) = item?;
+            neighbor_sketch_manager.delete(&mut batch, id, &t, window_start)?;
+            bump_batch_size!();
+        }
+
+        for adjacency_bitmap_manager in &[
+            AdjacencyBitmapManager::new(self.db.clone())?,
+            AdjacencyBitmapManager::new_reversed(self.db.clone())?,
+        ] {
+            for item in adjacency_bitmap_manager.iterate_for_owner(id)? {
+                adjacency_bitmap_manager.delete(&mut batch, id, &item?)?;
+                bump_batch_size!();
+            }
+        }
+
+        // The vertex itself is deleted last, so that if a commit fails
+        // partway through, the deletion can be retried
+        if let Some(t) = self.get(id)? {
+            TypeCountManager::new_for_vertices(self.db.clone())?.add(&mut batch, &t, -1)?;
+        }
+
+        self.db.delete(&mut batch, "vertices:v1", &self.key(id))?;
+        self.record(&mut batch, id, None)?;
+        commit(batch)
+    }
 }
 
-pub struct EdgeManager {
-    pub db: Arc<DB>,
-    pub cf: ColumnFamily,
+// Gets the key of an entry in the edge log. Datetimes are encoded so that
+// newer ones sort first, so the log is ordered newest first.
+pub fn edge_log_key(update_datetime: DateTime<Utc>, outbound_id: Uuid, t: &models::Type, inbound_id: Uuid) -> Vec<u8> {
+    build(&[
+        Component::DateTime(update_datetime),
+        Component::Uuid(outbound_id),
+        Component::Type(t),
+        Component::Uuid(inbound_id),
+    ])
 }
 
-impl EdgeManager {
-    pub fn new(db: Arc<DB>) -> Self {
-        EdgeManager {
-            cf: db.cf_handle("edges:v1").unwrap(),
+// Gets the key of an entry in the index of edges by type. Datetimes are
+// encoded so that newer ones sort first, so the edges of a type are ordered
+// newest first.
+pub fn edge_type_key(t: &models::Type, update_datetime: DateTime<Utc>, outbound_id: Uuid, inbound_id: Uuid) -> Vec<u8> {
+    build(&[
+        Component::Type(t),
+        Component::DateTime(update_datetime),
+        Component::Uuid(outbound_id),
+        Component::Uuid(inbound_id),
+    ])
+}
+
+pub struct EdgeManager<E: KvEngine> {
+    pub db: Arc<E>,
+    // Whether edges that are set are also written to the edge log. Log
+    // entries are always cleaned up, whether or not this is set.
+    pub log: bool,
+    // The width of the windows that edges that are set are counted in by
+    // the inbound neighbor sketches, if they're counted at all.
+    pub neighbor_sketch_window: Option<Duration>,
+    // The settings for the adjacency bitmaps of edges that are set, if
+    // they're kept at all. Existing bitmaps are updated when edges are
+    // deleted, whether or not this is set.
+    pub adjacency_bitmaps: Option<Arc<AdjacencyBitmapSettings>>,
+    // The settings for recording the history of edges that are written, and
+    // of the properties deleted along with them, if it's recorded at all.
+    pub history: Option<Arc<HistorySettings>>,
+}
+
+impl<E: KvEngine> EdgeManager<E> {
+    pub fn new(db: Arc<E>) -> Result<Self> {
+        Ok(EdgeManager {
             db,
+            log: false,
+            neighbor_sketch_window: None,
+            adjacency_bitmaps: None,
+            history: None,
+        })
+    }
+
+    pub fn with_log(self, log: bool) -> Self {
+        EdgeManager { log, ..self }
+    }
+
+    pub fn with_neighbor_sketches(self, neighbor_sketch_window: Option<Duration>) -> Self {
+        EdgeManager {
+            neighbor_sketch_window,
+            ..self
+        }
+    }
+
+    pub fn with_adjacency_bitmaps(self, adjacency_bitmaps: Option<Arc<AdjacencyBitmapSettings>>) -> Self {
+        EdgeManager {
+            adjacency_bitmaps,
+            ..self
+        }
+    }
+
+    pub fn with_history(self, history: Option<Arc<HistorySettings>>) -> Self {
+        EdgeManager { history, ..self }
+    }
+
+    fn record(
+        &self,
+        batch: &mut E::Batch,
+        outbound_id: Uuid,
+        t: &models::Type,
+        inbound_id: Uuid,
+        update_datetime: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        if let Some(ref history) = self.history {
+            let key = edge_history_key(outbound_id, t, inbound_id);
+            let value = update_datetime.map(|update_datetime| build(&[Component::DateTime(update_datetime)]));
+            HistoryManager::new(self.db.clone())?.record(batch, history, &key, value.as_deref())?;
         }
+
+        Ok(())
     }
 
     fn key(&self, outbound_id: Uuid, t: &models::Type, inbound_id: Uuid) -> Vec<u8> {
@@ -156,56 +529,214 @@ impl EdgeManager {
         ])
     }
 
+    // The edges column family has a bloom filter, so checking for a missing
+    // edge usually doesn't read from disk.
+    pub fn exists(&self, outbound_id: Uuid, t: &models::Type, inbound_id: Uuid) -> Result<bool> {
+        let key = self.key(outbound_id, t, inbound_id);
+        Ok(self.db.get_with("edges:v1", &key, |_| Ok(()))?.is_some())
+    }
+
+    pub fn exists_many(&self, keys: &[models::EdgeKey]) -> Result<Vec<bool>> {
+        let keys: Vec<Vec<u8>> = keys
+            .iter()
+            .map(|key| self.key(key.outbound_id, &key.t, key.inbound_id))
+            .collect();
+        self.db.exists_many("edges:v1", &keys)
+    }
+
     pub fn get(&self, outbound_id: Uuid, t: &models::Type, inbound_id: Uuid) -> Result<Option<DateTime<Utc>>> {
-        match self.db.get_cf(self.cf, &self.key(outbound_id, t, inbound_id))? {
-            Some(value_bytes) => {
-                let mut cursor = Cursor::new(value_bytes.deref());
-                Ok(Some(read_datetime(&mut cursor)))
-            }
-            None => Ok(None),
-        }
+        self.db
+            .get_with("edges:v1", &self.key(outbound_id, t, inbound_id), |value_bytes| {
+                read_datetime(&mut Cursor::new(value_bytes))
+            })
+    }
+
+    // Iterates over the inbound ids of a vertex's outbound edges of a type.
+    // Edges are keyed by their inbound ids after the type, so they're in
+    // ascending order.
+    pub fn iterate_inbound_ids<'a>(
+        &'a self,
+        outbound_id: Uuid,
+        t: &models::Type,
+    ) -> Result<impl Iterator<Item = Result<Uuid>> + 'a> {
+        let prefix = build(&[Component::Uuid(outbound_id), Component::Type(t)]);
+        let iterator = iterate_prefixed(&*self.db, "edges:v1", &prefix, &prefix)?;
+
+        Ok(iterator.map(move |item| -> Result<Uuid> {
+            let (k, _) = item;
+            let mut cursor = Cursor::new(k);
+            read_uuid(&mut cursor)?;
+            read_type(&mut cursor)?;
+            read_uuid(&mut cursor)
+        }))
+    }
+
+    // Iterates over the edges of the given type, newest first, starting at
+    // the given update datetime if there is one.
+    pub fn iterate_for_type<'a>(
+        &'a self,
+        t: &models::Type,
+        high: Option<DateTime<Utc>>,
+    ) -> Result<impl Iterator<Item = Result<EdgeRangeItem>> + 'a> {
+        let prefix = build(&[Component::Type(t)]);
+        let start_key = build(&[
+            Component::Type(t),
+            Component::DateTime(high.unwrap_or_else(|| *MAX_DATETIME)),
+        ]);
+        let iterator = iterate_prefixed(&*self.db, "edge_types:v1", &start_key, &prefix)?;
+
+        Ok(iterator.map(move |item| -> Result<EdgeRangeItem> {
+            let (k, _) = item;
+            let mut cursor = Cursor::new(k);
+            let t = read_type(&mut cursor)?;
+            let update_datetime = read_datetime(&mut cursor)?;
+            let outbound_id = read_uuid(&mut cursor)?;
+            let inbound_id = read_uuid(&mut cursor)?;
+            Ok((outbound_id, t, update_datetime, inbound_id))
+        }))
+    }
+
+    // Iterates over the edge log, newest first, starting at the given update
+    // datetime if there is one.
+    pub fn iterate_log<'a>(
+        &'a self,
+        high: Option<DateTime<Utc>>,
+    ) -> Result<impl Iterator<Item = Result<EdgeRangeItem>> + 'a> {
+        let start_key = build(&[Component::DateTime(high.unwrap_or_else(|| *MAX_DATETIME))]);
+        let iterator = self.db.iterate("edge_log:v1", KvIteratorMode::Forward(&start_key))?;
+
+        Ok(iterator.map(move |item| -> Result<EdgeRangeItem> {
+            let (k, _) = item;
+            let mut cursor = Cursor::new(k);
+            let update_datetime = read_datetime(&mut cursor)?;
+            let outbound_id = read_uuid(&mut cursor)?;
+            let t = read_type(&mut cursor)?;
+            let inbound_id = read_uuid(&mut cursor)?;
+            Ok((outbound_id, t, update_datetime, inbound_id))
+        }))
+    }
+
+    // Deletes the entries that index an edge by its update datetime, but not
+    // the edge itself.
+    pub fn delete_ranges(
+        &self,
+        mut batch: &mut E::Batch,
+        outbound_id: Uuid,
+        t: &models::Type,
+        inbound_id: Uuid,
+        update_datetime: DateTime<Utc>,
+    ) -> Result<()> {
+        let edge_range_manager = EdgeRangeManager::new(self.db.clone())?;
+        edge_range_manager.delete(&mut batch, outbound_id, t, update_datetime, inbound_id)?;
+
+        let reversed_edge_range_manager = EdgeRangeManager::new_reversed(self.db.clone())?;
+        reversed_edge_range_manager.delete(&mut batch, inbound_id, t, update_datetime, outbound_id)?;
+
+        self.db.delete(
+            batch,
+            "edge_types:v1",
+            &edge_type_key(t, update_datetime, outbound_id, inbound_id),
+        )?;
+        self.db.delete(
+            batch,
+            "edge_log:v1",
+            &edge_log_key(update_datetime, outbound_id, t, inbound_id),
+        )
     }
 
     pub fn set(
         &self,
-        mut batch: &mut WriteBatch,
+        mut batch: &mut E::Batch,
         outbound_id: Uuid,
         t: &models::Type,
         inbound_id: Uuid,
         new_update_datetime: DateTime<Utc>,
     ) -> Result<()> {
-        let edge_range_manager = EdgeRangeManager::new(self.db.clone());
-        let reversed_edge_range_manager = EdgeRangeManager::new_reversed(self.db.clone());
-
-        if let Some(update_datetime) = self.get(outbound_id, t, inbound_id)? {
-            edge_range_manager.delete(&mut batch, outbound_id, t, update_datetime, inbound_id)?;
-            reversed_edge_range_manager.delete(&mut batch, inbound_id, t, update_datetime, outbound_id)?;
+        match self.get(outbound_id, t, inbound_id)? {
+            Some(update_datetime) => self.delete_ranges(&mut batch, outbound_id, t, inbound_id, update_datetime)?,
+            None => {
+                TypeCountManager::new_for_edges(self.db.clone())?.add(batch, t, 1)?;
+
+                if let Some(ref adjacency_bitmaps) = self.adjacency_bitmaps {
+                    AdjacencyBitmapManager::new(self.db.clone())?.add(
+                        batch,
+                        adjacency_bitmaps,
+                        outbound_id,
+                        t,
+                        inbound_id,
+                    )?;
+                    AdjacencyBitmapManager::new_reversed(self.db.clone())?.add(
+                        batch,
+                        adjacency_bitmaps,
+                        inbound_id,
+                        t,
+                        outbound_id,
+                    )?;
+                }
+            }
         }
 
+        let edge_range_manager = EdgeRangeManager::new(self.db.clone())?;
+        let reversed_edge_range_manager = EdgeRangeManager::new_reversed(self.db.clone())?;
+
         let key = self.key(outbound_id, t, inbound_id);
-        batch.put_cf(self.cf, &key, &build(&[Component::DateTime(new_update_datetime)]))?;
+        self.db.put(
+            batch,
+            "edges:v1",
+            &key,
+            &build(&[Component::DateTime(new_update_datetime)]),
+        )?;
         edge_range_manager.set(&mut batch, outbound_id, t, new_update_datetime, inbound_id)?;
         reversed_edge_range_manager.set(&mut batch, inbound_id, t, new_update_datetime, outbound_id)?;
-        Ok(())
+        self.db.put(
+            batch,
+            "edge_types:v1",
+            &edge_type_key(t, new_update_datetime, outbound_id, inbound_id),
+            &[],
+        )?;
+
+        if self.log {
+            self.db.put(
+                batch,
+                "edge_log:v1",
+                &edge_log_key(new_update_datetime, outbound_id, t, inbound_id),
+                &[],
+            )?;
+        }
+
+        if let Some(window) = self.neighbor_sketch_window {
+            let window_start = neighbor_sketch_window_start(new_update_datetime, window);
+            NeighborSketchManager::new(self.db.clone())?.add(batch, inbound_id, t, window_start, outbound_id)?;
+        }
+
+        self.record(batch, outbound_id, t, inbound_id, Some(new_update_datetime))
     }
 
     pub fn delete(
         &self,
-        mut batch: &mut WriteBatch,
+        mut batch: &mut E::Batch,
         outbound_id: Uuid,
         t: &models::Type,
         inbound_id: Uuid,
         update_datetime: DateTime<Utc>,
     ) -> Result<()> {
-        batch.delete_cf(self.cf, &self.key(outbound_id, t, inbound_id))?;
-
-        let edge_range_manager = EdgeRangeManager::new(self.db.clone());
-        edge_range_manager.delete(&mut batch, outbound_id, t, update_datetime, inbound_id)?;
-
-        let reversed_edge_range_manager = EdgeRangeManager::new_reversed(self.db.clone());
-        reversed_edge_range_manager.delete(&mut batch, inbound_id, t, update_datetime, outbound_id)?;
-
-        let edge_property_manager = EdgePropertyManager::new(self.db.clone());
+        self.db
+            .delete(batch, "edges:v1", &self.key(outbound_id, t, inbound_id))?;
+        self.delete_ranges(&mut batch, outbound_id, t, inbound_id, update_datetime)?;
+        TypeCountManager::new_for_edges(self.db.clone())?.add(batch, t, -1)?;
+        self.record(batch, outbound_id, t, inbound_id, None)?;
+
+        let adjacency_bitmaps = self.adjacency_bitmaps.as_ref().map(|settings| &**settings);
+        AdjacencyBitmapManager::new(self.db.clone())?.remove(batch, adjacency_bitmaps, outbound_id, t, inbound_id)?;
+        AdjacencyBitmapManager::new_reversed(self.db.clone())?.remove(
+            batch,
+            adjacency_bitmaps,
+            inbound_id,
+            t,
+            outbound_id,
+        )?;
+
+        let edge_property_manager = EdgePropertyManager::new(self.db.clone())?.with_history(self.history.clone());
         for item in edge_property_manager.iterate_for_owner(outbound_id, t, inbound_id)? {
             let ((edge_property_outbound_id, edge_property_t, edge_property_inbound_id, edge_property_name), _) = item?;
             edge_property_manager.delete(
@@ -221,24 +752,43 @@ impl EdgeManager {
     }
 }
 
-pub struct EdgeRangeManager {
-    pub db: Arc<DB>,
-    pub cf: ColumnFamily,
+pub struct EdgeRangeManager<E: KvEngine> {
+    pub db: Arc<E>,
+    pub keyspace: &'static str,
+    // How scans read from the engine. Scans are bounded to their prefix
+    // on top of these.
+    pub read_options: KvReadOptions,
 }
 
-impl EdgeRangeManager {
-    pub fn new(db: Arc<DB>) -> Self {
-        EdgeRangeManager {
-            cf: db.cf_handle("edge_ranges:v1").unwrap(),
+impl<E: KvEngine> EdgeRangeManager<E> {
+    pub fn new(db: Arc<E>) -> Result<Self> {
+        Ok(EdgeRangeManager {
             db,
-        }
+            keyspace: "edge_ranges:v1",
+            read_options: KvReadOptions::default(),
+        })
     }
 
-    pub fn new_reversed(db: Arc<DB>) -> Self {
-        EdgeRangeManager {
-            cf: db.cf_handle("reversed_edge_ranges:v1").unwrap(),
+    pub fn new_reversed(db: Arc<E>) -> Result<Self> {
+        Ok(EdgeRangeManager {
             db,
-        }
+            keyspace: "reversed_edge_ranges:v1",
+            read_options: KvReadOptions::default(),
+        })
+    }
+
+    pub fn with_read_options(self, read_options: KvReadOptions) -> Self {
+        EdgeRangeManager { read_options, ..self }
+    }
+
+    // Iterates forward from `start_key` over the keys starting with
+    // `prefix`, stopping at the end of the prefix.
+    fn iterate_prefix<'a>(&'a self, start_key: &[u8], prefix: &[u8]) -> Result<Box<dyn Iterator<Item = KvItem> + 'a>> {
+        self.db.iterate_with_options(
+            self.keyspace,
+            KvIteratorMode::Forward(start_key),
+            &self.read_options.bounded_to_prefix(prefix),
+        )
     }
 
     fn key(&self, first_id: Uuid, t: &models::Type, update_datetime: DateTime<Utc>, second_id: Uuid) -> Vec<u8> {
@@ -250,42 +800,39 @@ impl EdgeRangeManager {
         ])
     }
 
-    fn iterate(&self, iterator: DBIterator, prefix: Vec<u8>) -> Result<impl Iterator<Item = Result<EdgeRangeItem>>> {
-        let filtered = take_while_prefixed(iterator, prefix);
-
-        Ok(filtered.map(move |item| -> Result<EdgeRangeItem> {
+    fn iterate<I>(&self, iterator: I) -> Result<impl Iterator<Item = Result<EdgeRangeItem>>>
+    where
+        I: Iterator<Item = KvItem>,
+    {
+        Ok(iterator.map(move |item| -> Result<EdgeRangeItem> {
             let (k, _) = item;
             let mut cursor = Cursor::new(k);
-            let first_id = read_uuid(&mut cursor);
-            let t = read_type(&mut cursor);
-            let update_datetime = read_datetime(&mut cursor);
-            let second_id = read_uuid(&mut cursor);
+            let first_id = read_uuid(&mut cursor)?;
+            let t = read_type(&mut cursor)?;
+            let update_datetime = read_datetime(&mut cursor)?;
+            let second_id = read_uuid(&mut cursor)?;
             Ok((first_id, t, update_datetime, second_id))
         }))
     }
 
-    pub fn iterate_for_range(
-        &self,
+    pub fn iterate_for_range<'a>(
+        &'a self,
         id: Uuid,
         t: Option<&models::Type>,
         high: Option<DateTime<Utc>>,
-    ) -> Result<Box<dyn Iterator<Item = Result<EdgeRangeItem>>>> {
+    ) -> Result<Box<dyn Iterator<Item = Result<EdgeRangeItem>> + 'a>> {
         match t {
             Some(t) => {
                 let high = high.unwrap_or_else(|| *MAX_DATETIME);
                 let prefix = build(&[Component::Uuid(id), Component::Type(t)]);
                 let low_key = build(&[Component::Uuid(id), Component::Type(t), Component::DateTime(high)]);
-                let iterator = self
-                    .db
-                    .iterator_cf(self.cf, IteratorMode::From(&low_key, Direction::Forward))?;
-                Ok(Box::new(self.iterate(iterator, prefix)?))
+                let iterator = self.iterate_prefix(&low_key, &prefix)?;
+                Ok(Box::new(self.iterate(iterator)?))
             }
             None => {
                 let prefix = build(&[Component::Uuid(id)]);
-                let iterator = self
-                    .db
-                    .iterator_cf(self.cf, IteratorMode::From(&prefix, Direction::Forward))?;
-                let mapped = self.iterate(iterator, prefix)?;
+                let iterator = self.iterate_prefix(&prefix, &prefix)?;
+                let mapped = self.iterate(iterator)?;
 
                 if let Some(high) = high {
                     // We can filter out `update_datetime`s greater than
@@ -307,107 +854,353 @@ impl EdgeRangeManager {
         }
     }
 
-    pub fn iterate_for_owner(&self, id: Uuid) -> Result<impl Iterator<Item = Result<EdgeRangeItem>>> {
+    pub fn count_for_range(
+        &self,
+        id: Uuid,
+        t: Option<&models::Type>,
+        low: Option<DateTime<Utc>>,
+        high: Option<DateTime<Utc>>,
+        tombstones: &HashSet<Uuid>,
+    ) -> Result<u64> {
+        let prefix = match t {
+            Some(t) => build(&[Component::Uuid(id), Component::Type(t)]),
+            None => build(&[Component::Uuid(id)]),
+        };
+
+        let start_key = match (t, high) {
+            (Some(t), Some(high)) => build(&[Component::Uuid(id), Component::Type(t), Component::DateTime(high)]),
+            _ => prefix.clone(),
+        };
+
+        // Datetimes are encoded so that newer ones sort first, and so that
+        // they can be compared without decoding them
+        let low_bytes = low.map(|low| build(&[Component::DateTime(low)]));
+        let high_bytes = high.map(|high| build(&[Component::DateTime(high)]));
+
+        let mut count = 0;
+
+        for (k, _) in self.iterate_prefix(&start_key, &prefix)? {
+            let datetime_offset = 17 + k[16] as usize;
+            let datetime_bytes = &k[datetime_offset..datetime_offset + 8];
+
+            if let Some(ref low_bytes) = low_bytes {
+                if datetime_bytes > &low_bytes[..] {
+                    // With a type, every remaining edge is older still
+                    if t.is_some() {
+                        break;
+                    }
+
+                    continue;
+                }
+            }
+
+            if let Some(ref high_bytes) = high_bytes {
+                if datetime_bytes < &high_bytes[..] {
+                    continue;
+                }
+            }
+
+            if !tombstones.is_empty() {
+                let second_id = Uuid::from_slice(&k[datetime_offset + 8..]).unwrap();
+
+                if tombstones.contains(&second_id) {
+                    continue;
+                }
+            }
+
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    pub fn iterate_for_range_after<'a>(
+        &'a self,
+        id: Uuid,
+        t: &models::Type,
+        after: Option<(DateTime<Utc>, Uuid)>,
+    ) -> Result<impl Iterator<Item = Result<EdgeRangeItem>> + 'a> {
+        let prefix = build(&[Component::Uuid(id), Component::Type(t)]);
+
+        let low_key = match after {
+            Some((update_datetime, second_id)) => self.key(id, t, update_datetime, second_id),
+            None => prefix.clone(),
+        };
+
+        let iterator = self.iterate_prefix(&low_key, &prefix)?;
+
+        // The cursor is exclusive, so skip the edge it points to, if it
+        // still exists
+        Ok(self.iterate(iterator)?.filter(move |item| match (item, after) {
+            (Ok((_, _, update_datetime, second_id)), Some(after)) => (*update_datetime, *second_id) != after,
+            _ => true,
+        }))
+    }
+
+    pub fn iterate_for_owner<'a>(&'a self, id: Uuid) -> Result<impl Iterator<Item = Result<EdgeRangeItem>> + 'a> {
         let prefix = build(&[Component::Uuid(id)]);
-        let iterator = self
-            .db
-            .iterator_cf(self.cf, IteratorMode::From(&prefix, Direction::Forward))?;
-        self.iterate(iterator, prefix)
+        let iterator = self.iterate_prefix(&prefix, &prefix)?;
+        self.iterate(iterator)
     }
 
     pub fn set(
         &self,
-        batch: &mut WriteBatch,
+        batch: &mut E::Batch,
         first_id: Uuid,
         t: &models::Type,
         update_datetime: DateTime<Utc>,
         second_id: Uuid,
     ) -> Result<()> {
         let key = self.key(first_id, t, update_datetime, second_id);
-        batch.put_cf(self.cf, &key, &[])?;
+        self.db.put(batch, self.keyspace, &key, &[])?;
         Ok(())
     }
 
     pub fn delete(
         &self,
-        batch: &mut WriteBatch,
+        batch: &mut E::Batch,
         first_id: Uuid,
         t: &models::Type,
         update_datetime: DateTime<Utc>,
         second_id: Uuid,
     ) -> Result<()> {
-        batch.delete_cf(self.cf, &self.key(first_id, t, update_datetime, second_id))?;
+        let key = self.key(first_id, t, update_datetime, second_id);
+        self.db.delete(batch, self.keyspace, &key)?;
         Ok(())
     }
 }
 
-pub struct VertexPropertyManager {
-    pub db: Arc<DB>,
-    pub cf: ColumnFamily,
+pub struct VertexPropertyManager<E: KvEngine> {
+    pub db: Arc<E>,
+    // How scans read from the engine.
+    pub read_options: KvReadOptions,
+    // The names of the properties written to the cold keyspace.
+    pub cold_names: Arc<HashSet<String>>,
+    // The settings for recording the history of properties that are
+    // written, if it's recorded at all.
+    pub history: Option<Arc<HistorySettings>>,
 }
 
-impl VertexPropertyManager {
-    pub fn new(db: Arc<DB>) -> Self {
-        VertexPropertyManager {
-            cf: db.cf_handle("vertex_properties:v1").unwrap(),
+impl<E: KvEngine> VertexPropertyManager<E> {
+    pub fn new(db: Arc<E>) -> Result<Self> {
+        Ok(VertexPropertyManager {
             db,
-        }
+            read_options: KvReadOptions::default(),
+            cold_names: Arc::new(HashSet::new()),
+            history: None,
+        })
     }
 
-    fn key(&self, vertex_id: Uuid, name: &str) -> Vec<u8> {
-        build(&[Component::Uuid(vertex_id), Component::UnsizedString(name)])
+    pub fn with_read_options(self, read_options: KvReadOptions) -> Self {
+        VertexPropertyManager { read_options, ..self }
     }
 
-    pub fn iterate_for_owner(&self, vertex_id: Uuid) -> Result<impl Iterator<Item = Result<OwnedPropertyItem>>> {
-        let prefix = build(&[Component::Uuid(vertex_id)]);
-        let iterator = self
-            .db
-            .iterator_cf(self.cf, IteratorMode::From(&prefix, Direction::Forward))?;
-        let filtered = take_while_prefixed(iterator, prefix);
-
-        Ok(filtered.map(move |item| -> Result<OwnedPropertyItem> {
-            let (k, v) = item;
-            let mut cursor = Cursor::new(k);
-            let owner_id = read_uuid(&mut cursor);
-            debug_assert_eq!(vertex_id, owner_id);
-            let name = read_unsized_string(&mut cursor);
-            let value = serde_json::from_slice(&v)?;
-            Ok(((owner_id, name), value))
-        }))
+    pub fn with_cold_names(self, cold_names: Arc<HashSet<String>>) -> Self {
+        VertexPropertyManager { cold_names, ..self }
     }
 
-    pub fn get(&self, vertex_id: Uuid, name: &str) -> Result<Option<JsonValue>> {
-        let key = self.key(vertex_id, name);
+    pub fn with_history(self, history: Option<Arc<HistorySettings>>) -> Self {
+        VertexPropertyManager { history, ..self }
+    }
 
-        match self.db.get_cf(self.cf, &key)? {
-            Some(value_bytes) => Ok(Some(serde_json::from_slice(&value_bytes)?)),
-            None => Ok(None),
+    fn keyspaces(&self) -> PropertyKeyspaces<'_> {
+        PropertyKeyspaces {
+            hot: "vertex_properties:v1",
+            cold: "cold_vertex_properties:v1",
+            cold_names: &self.cold_names,
         }
     }
 
-    pub fn set(&self, batch: &mut WriteBatch, vertex_id: Uuid, name: &str, value: &JsonValue) -> Result<()> {
-        let key = self.key(vertex_id, name);
-        let value_json = serde_json::to_vec(value)?;
-        batch.put_cf(self.cf, &key, &value_json)?;
-        Ok(())
+    fn key(&self, vertex_id: Uuid, name: &str) -> Vec<u8> {
+        build(&[Component::Uuid(vertex_id), Component::UnsizedString(name)])
     }
 
-    pub fn delete(&self, batch: &mut WriteBatch, vertex_id: Uuid, name: &str) -> Result<()> {
-        batch.delete_cf(self.cf, &self.key(vertex_id, name))?;
-        Ok(())
+    pub fn iterate_for_owner<'a>(
+        &'a self,
+        vertex_id: Uuid,
+    ) -> Result<impl Iterator<Item = Result<OwnedPropertyItem>> + 'a> {
+        self.iterate_for_owner_with_prefix(vertex_id, "")
     }
-}
 
-pub struct EdgePropertyManager {
-    pub db: Arc<DB>,
-    pub cf: ColumnFamily,
-}
+    // Property names are the unsized tail of the key, so names that share a
+    // prefix are adjacent and can be read with a single seek.
+    pub fn iterate_for_owner_with_prefix<'a>(
+        &'a self,
+        vertex_id: Uuid,
+        name_prefix: &str,
+    ) -> Result<impl Iterator<Item = Result<OwnedPropertyItem>> + 'a> {
+        let prefix = self.key(vertex_id, name_prefix);
+        let iterator = self
+            .keyspaces()
+            .iterate(&*self.db, &prefix, &self.read_options.bounded_to_prefix(&prefix))?;
 
-impl EdgePropertyManager {
-    pub fn new(db: Arc<DB>) -> Self {
-        EdgePropertyManager {
-            cf: db.cf_handle("edge_properties:v1").unwrap(),
+        // Expired properties are skipped
+        Ok(iterator.filter_map(move |item| {
+            let read_item = || -> Result<Option<OwnedPropertyItem>> {
+                let (k, v) = item;
+                let mut cursor = Cursor::new(k);
+                let owner_id = read_uuid(&mut cursor)?;
+                debug_assert_eq!(vertex_id, owner_id);
+                let name = read_unsized_string(&mut cursor)?;
+
+                match read_property_value(&v, Utc::now())? {
+                    Some(value_json) => Ok(Some(((owner_id, name), serde_json::from_slice(value_json)?))),
+                    None => Ok(None),
+                }
+            };
+
+            transpose(read_item())
+        }))
+    }
+
+    // Iterates over every vertex property, ordered by vertex id and then
+    // name, starting after the given vertex property if there is one.
+    pub fn iterate_all<'a>(
+        &'a self,
+        after: Option<(Uuid, &str)>,
+    ) -> Result<impl Iterator<Item = Result<OwnedPropertyItem>> + 'a> {
+        // Appending a zero byte to a key gets the lowest key that sorts after
+        // it
+        let start_key = match after {
+            Some((vertex_id, name)) => {
+                let mut start_key = self.key(vertex_id, name);
+                start_key.push(0);
+                start_key
+            }
+            None => Vec::new(),
+        };
+
+        let iterator = self
+            .keyspaces()
+            .iterate(&*self.db, &start_key, &KvReadOptions::default())?;
+
+        // Expired properties are skipped
+        Ok(iterator.filter_map(move |item| {
+            let read_item = || -> Result<Option<OwnedPropertyItem>> {
+                let (k, v) = item;
+                let mut cursor = Cursor::new(k);
+                let owner_id = read_uuid(&mut cursor)?;
+                let name = read_unsized_string(&mut cursor)?;
+
+                match read_property_value(&v, Utc::now())? {
+                    Some(value_json) => Ok(Some(((owner_id, name), serde_json::from_slice(value_json)?))),
+                    None => Ok(None),
+                }
+            };
+
+            transpose(read_item())
+        }))
+    }
+
+    pub fn get(&self, vertex_id: Uuid, name: &str) -> Result<Option<JsonValue>> {
+        self.get_with(vertex_id, name, |value_json| Ok(serde_json::from_slice(value_json)?))
+    }
+
+    pub fn get_raw(&self, vertex_id: Uuid, name: &str) -> Result<Option<Vec<u8>>> {
+        self.get_with(vertex_id, name, |value_json| Ok(value_json.to_vec()))
+    }
+
+    // Decodes a property value's JSON in place, without copying it.
+    pub fn get_with<T, F>(&self, vertex_id: Uuid, name: &str, f: F) -> Result<Option<T>>
+    where
+        F: FnOnce(&[u8]) -> Result<T>,
+    {
+        let key = self.key(vertex_id, name);
+        self.keyspaces().get_with(&*self.db, name, &key, f)
+    }
+
+    pub fn set(&self, batch: &mut E::Batch, vertex_id: Uuid, name: &str, value: &JsonValue) -> Result<()> {
+        self.set_with_expiry(batch, vertex_id, name, value, None)
+    }
+
+    pub fn set_with_expiry(
+        &self,
+        batch: &mut E::Batch,
+        vertex_id: Uuid,
+        name: &str,
+        value: &JsonValue,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        let index_manager = IndexManager::new(self.db.clone())?;
+        index_manager.update(batch, vertex_id, name, Some(value))?;
+
+        let key = self.key(vertex_id, name);
+        let value_json = serde_json::to_vec(value)?;
+        self.record(batch, vertex_id, name, Some(&value_json))?;
+        let value_bytes = build_property_value(value_json, expires_at);
+        self.keyspaces().put(&*self.db, batch, name, &key, &value_bytes)
+    }
+
+    pub fn delete(&self, batch: &mut E::Batch, vertex_id: Uuid, name: &str) -> Result<()> {
+        let index_manager = IndexManager::new(self.db.clone())?;
+        index_manager.update(batch, vertex_id, name, None)?;
+
+        self.record(batch, vertex_id, name, None)?;
+        self.keyspaces().delete(&*self.db, batch, &self.key(vertex_id, name))
+    }
+
+    // Property expiries aren't recorded, so a property that's expired is
+    // still in the history until it's written again.
+    fn record(&self, batch: &mut E::Batch, vertex_id: Uuid, name: &str, value_json: Option<&[u8]>) -> Result<()> {
+        if let Some(ref history) = self.history {
+            let key = vertex_property_history_key(vertex_id, name);
+            HistoryManager::new(self.db.clone())?.record(batch, history, &key, value_json)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Gets the key of an entry in the index of edge properties by name.
+// Property names aren't limited in length, so the name can't be a
+// `SizedString`.
+pub fn edge_property_name_key(name: &str, outbound_id: Uuid, t: &models::Type, inbound_id: Uuid) -> Vec<u8> {
+    build(&[
+        Component::LongSizedString(name),
+        Component::Uuid(outbound_id),
+        Component::Type(t),
+        Component::Uuid(inbound_id),
+    ])
+}
+
+pub struct EdgePropertyManager<E: KvEngine> {
+    pub db: Arc<E>,
+    // How scans read from the engine.
+    pub read_options: KvReadOptions,
+    // The names of the properties written to the cold keyspace.
+    pub cold_names: Arc<HashSet<String>>,
+    // The settings for recording the history of properties that are
+    // written, if it's recorded at all.
+    pub history: Option<Arc<HistorySettings>>,
+}
+
+impl<E: KvEngine> EdgePropertyManager<E> {
+    pub fn new(db: Arc<E>) -> Result<Self> {
+        Ok(EdgePropertyManager {
             db,
+            read_options: KvReadOptions::default(),
+            cold_names: Arc::new(HashSet::new()),
+            history: None,
+        })
+    }
+
+    pub fn with_read_options(self, read_options: KvReadOptions) -> Self {
+        EdgePropertyManager { read_options, ..self }
+    }
+
+    pub fn with_cold_names(self, cold_names: Arc<HashSet<String>>) -> Self {
+        EdgePropertyManager { cold_names, ..self }
+    }
+
+    pub fn with_history(self, history: Option<Arc<HistorySettings>>) -> Self {
+        EdgePropertyManager { history, ..self }
+    }
+
+    fn keyspaces(&self) -> PropertyKeyspaces<'_> {
+        PropertyKeyspaces {
+            hot: "edge_properties:v1",
+            cold: "cold_edge_properties:v1",
+            cold_names: &self.cold_names,
         }
     }
 
@@ -421,7 +1214,7 @@ impl EdgePropertyManager {
     }
 
     pub fn iterate_for_owner<'a>(
-        &self,
+        &'a self,
         outbound_id: Uuid,
         t: &'a models::Type,
         inbound_id: Uuid,
@@ -433,73 +1226,1201 @@ impl EdgePropertyManager {
         ]);
 
         let iterator = self
-            .db
-            .iterator_cf(self.cf, IteratorMode::From(&prefix, Direction::Forward))?;
-        let filtered = take_while_prefixed(iterator, prefix);
+            .keyspaces()
+            .iterate(&*self.db, &prefix, &self.read_options.bounded_to_prefix(&prefix))?;
 
-        let mapped = filtered.map(move |item| -> Result<EdgePropertyItem> {
-            let (k, v) = item;
-            let mut cursor = Cursor::new(k);
+        // Expired properties are skipped
+        let mapped = iterator.filter_map(move |item| {
+            let read_item = || -> Result<Option<EdgePropertyItem>> {
+                let (k, v) = item;
+                let mut cursor = Cursor::new(k);
+
+                let edge_property_outbound_id = read_uuid(&mut cursor)?;
+                debug_assert_eq!(edge_property_outbound_id, outbound_id);
 
-            let edge_property_outbound_id = read_uuid(&mut cursor);
-            debug_assert_eq!(edge_property_outbound_id, outbound_id);
+                let edge_property_t = read_type(&mut cursor)?;
+                debug_assert_eq!(&edge_property_t, t);
 
-            let edge_property_t = read_type(&mut cursor);
-            debug_assert_eq!(&edge_property_t, t);
+                let edge_property_inbound_id = read_uuid(&mut cursor)?;
+                debug_assert_eq!(edge_property_inbound_id, inbound_id);
 
-            let edge_property_inbound_id = read_uuid(&mut cursor);
-            debug_assert_eq!(edge_property_inbound_id, inbound_id);
+                let edge_property_name = read_unsized_string(&mut cursor)?;
 
-            let edge_property_name = read_unsized_string(&mut cursor);
+                let value_json = match read_property_value(&v, Utc::now())? {
+                    Some(value_json) => value_json,
+                    None => return Ok(None),
+                };
 
-            let value = serde_json::from_slice(&v)?;
-            Ok((
-                (
-                    edge_property_outbound_id,
-                    edge_property_t,
-                    edge_property_inbound_id,
-                    edge_property_name,
-                ),
-                value,
-            ))
+                Ok(Some((
+                    (
+                        edge_property_outbound_id,
+                        edge_property_t,
+                        edge_property_inbound_id,
+                        edge_property_name,
+                    ),
+                    serde_json::from_slice(value_json)?,
+                )))
+            };
+
+            transpose(read_item())
+        });
+
+        Ok(Box::new(mapped))
+    }
+
+    // Iterates over the edge properties with the given name, ordered by edge
+    // key, starting after the given edge if there is one.
+    pub fn iterate_for_name<'a>(
+        &'a self,
+        name: &str,
+        after: Option<&models::EdgeKey>,
+    ) -> Result<Box<dyn Iterator<Item = Result<EdgePropertyItem>> + 'a>> {
+        let prefix = build(&[Component::LongSizedString(name)]);
+
+        // No index entry's key is a prefix of another's, so appending a zero
+        // byte to a key gets the lowest key that sorts after it
+        let start_key = match after {
+            Some(key) => {
+                let mut start_key = edge_property_name_key(name, key.outbound_id, &key.t, key.inbound_id);
+                start_key.push(0);
+                start_key
+            }
+            None => prefix.clone(),
+        };
+
+        let iterator = iterate_prefixed(&*self.db, "edge_property_names:v1", &start_key, &prefix)?;
+        let manager = EdgePropertyManager::new(self.db.clone())?.with_cold_names(self.cold_names.clone());
+
+        // Entries can outlive the property they point to if it expired, so
+        // the property is looked up and skipped if it's gone
+        let mapped = iterator.filter_map(move |item| {
+            let read_item = || -> Result<Option<EdgePropertyItem>> {
+                let (k, _) = item;
+                let mut cursor = Cursor::new(k);
+                let name = read_long_sized_string(&mut cursor)?;
+                let outbound_id = read_uuid(&mut cursor)?;
+                let t = read_type(&mut cursor)?;
+                let inbound_id = read_uuid(&mut cursor)?;
+
+                match manager.get(outbound_id, &t, inbound_id, &name)? {
+                    Some(value) => Ok(Some(((outbound_id, t, inbound_id, name), value))),
+                    None => Ok(None),
+                }
+            };
+
+            transpose(read_item())
+        });
+
+        Ok(Box::new(mapped))
+    }
+
+    // Iterates over every edge property, ordered by edge key and then name,
+    // starting after the given edge property if there is one.
+    pub fn iterate_all<'a>(
+        &'a self,
+        after: Option<(&models::EdgeKey, &str)>,
+    ) -> Result<Box<dyn Iterator<Item = Result<EdgePropertyItem>> + 'a>> {
+        // Appending a zero byte to a key gets the lowest key that sorts after
+        // it
+        let start_key = match after {
+            Some((key, name)) => {
+                let mut start_key = self.key(key.outbound_id, &key.t, key.inbound_id, name);
+                start_key.push(0);
+                start_key
+            }
+            None => Vec::new(),
+        };
+
+        let iterator = self
+            .keyspaces()
+            .iterate(&*self.db, &start_key, &KvReadOptions::default())?;
+
+        // Expired properties are skipped
+        let mapped = iterator.filter_map(move |item| {
+            let read_item = || -> Result<Option<EdgePropertyItem>> {
+                let (k, v) = item;
+                let mut cursor = Cursor::new(k);
+                let outbound_id = read_uuid(&mut cursor)?;
+                let t = read_type(&mut cursor)?;
+                let inbound_id = read_uuid(&mut cursor)?;
+                let name = read_unsized_string(&mut cursor)?;
+
+                match read_property_value(&v, Utc::now())? {
+                    Some(value_json) => Ok(Some((
+                        (outbound_id, t, inbound_id, name),
+                        serde_json::from_slice(value_json)?,
+                    ))),
+                    None => Ok(None),
+                }
+            };
+
+            transpose(read_item())
         });
 
         Ok(Box::new(mapped))
     }
 
     pub fn get(&self, outbound_id: Uuid, t: &models::Type, inbound_id: Uuid, name: &str) -> Result<Option<JsonValue>> {
-        let key = self.key(outbound_id, t, inbound_id, name);
+        self.get_with(outbound_id, t, inbound_id, name, |value_json| {
+            Ok(serde_json::from_slice(value_json)?)
+        })
+    }
 
-        match self.db.get_cf(self.cf, &key)? {
-            Some(value_bytes) => Ok(Some(serde_json::from_slice(&value_bytes)?)),
-            None => Ok(None),
-        }
+    pub fn get_raw(
+        &self,
+        outbound_id: Uuid,
+        t: &models::Type,
+        inbound_id: Uuid,
+        name: &str,
+    ) -> Result<Option<Vec<u8>>> {
+        self.get_with(outbound_id, t, inbound_id, name, |value_json| Ok(value_json.to_vec()))
+    }
+
+    // Decodes a property value's JSON in place, without copying it.
+    pub fn get_with<T, F>(
+        &self,
+        outbound_id: Uuid,
+        t: &models::Type,
+        inbound_id: Uuid,
+        name: &str,
+        f: F,
+    ) -> Result<Option<T>>
+    where
+        F: FnOnce(&[u8]) -> Result<T>,
+    {
+        let key = self.key(outbound_id, t, inbound_id, name);
+        self.keyspaces().get_with(&*self.db, name, &key, f)
     }
 
     pub fn set(
         &self,
-        batch: &mut WriteBatch,
+        batch: &mut E::Batch,
+        outbound_id: Uuid,
+        t: &models::Type,
+        inbound_id: Uuid,
+        name: &str,
+        value: &JsonValue,
+    ) -> Result<()> {
+        self.set_with_expiry(batch, outbound_id, t, inbound_id, name, value, None)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_with_expiry(
+        &self,
+        batch: &mut E::Batch,
         outbound_id: Uuid,
         t: &models::Type,
         inbound_id: Uuid,
         name: &str,
         value: &JsonValue,
+        expires_at: Option<DateTime<Utc>>,
     ) -> Result<()> {
         let key = self.key(outbound_id, t, inbound_id, name);
         let value_json = serde_json::to_vec(value)?;
-        batch.put_cf(self.cf, &key, &value_json)?;
+        self.record(batch, outbound_id, t, inbound_id, name, Some(&value_json))?;
+        let value_bytes = build_property_value(value_json, expires_at);
+        self.keyspaces().put(&*self.db, batch, name, &key, &value_bytes)?;
+        let name_key = edge_property_name_key(name, outbound_id, t, inbound_id);
+        self.db.put(batch, "edge_property_names:v1", &name_key, &[])?;
         Ok(())
     }
 
     pub fn delete(
         &self,
-        batch: &mut WriteBatch,
+        batch: &mut E::Batch,
         outbound_id: Uuid,
         t: &models::Type,
         inbound_id: Uuid,
         name: &str,
     ) -> Result<()> {
-        batch.delete_cf(self.cf, &self.key(outbound_id, t, inbound_id, name))?;
+        let key = self.key(outbound_id, t, inbound_id, name);
+        self.keyspaces().delete(&*self.db, batch, &key)?;
+        let name_key = edge_property_name_key(name, outbound_id, t, inbound_id);
+        self.db.delete(batch, "edge_property_names:v1", &name_key)?;
+        self.record(batch, outbound_id, t, inbound_id, name, None)
+    }
+
+    // Property expiries aren't recorded, so a property that's expired is
+    // still in the history until it's written again.
+    fn record(
+        &self,
+        batch: &mut E::Batch,
+        outbound_id: Uuid,
+        t: &models::Type,
+        inbound_id: Uuid,
+        name: &str,
+        value_json: Option<&[u8]>,
+    ) -> Result<()> {
+        if let Some(ref history) = self.history {
+            let key = edge_property_history_key(outbound_id, t, inbound_id, name);
+            HistoryManager::new(self.db.clone())?.record(batch, history, &key, value_json)?;
+        }
+
+        Ok(())
+    }
+}
+
+pub struct IndexManager<E: KvEngine> {
+    pub db: Arc<E>,
+}
+
+impl<E: KvEngine> IndexManager<E> {
+    pub fn new(db: Arc<E>) -> Result<Self> {
+        Ok(IndexManager { db })
+    }
+
+    fn definition_key(&self, name: &str) -> Vec<u8> {
+        build(&[Component::UnsizedString(name)])
+    }
+
+    fn entry_prefix(&self, name: &str, values: &[JsonValue]) -> Vec<u8> {
+        let mut key = build(&[Component::SizedString(name)]);
+
+        for value in values {
+            key.extend(encode_index_value(value));
+        }
+
+        key
+    }
+
+    fn entry_key(&self, name: &str, values: &[JsonValue], vertex_id: Uuid) -> Vec<u8> {
+        let mut key = self.entry_prefix(name, values);
+        key.extend_from_slice(vertex_id.as_bytes());
+        key
+    }
+
+    pub fn get(&self, name: &str) -> Result<Option<models::PropertyIndex>> {
+        self.db
+            .get_with("index_definitions:v1", &self.definition_key(name), |value_bytes| {
+                read_index_definition(name.to_string(), value_bytes)
+            })
+    }
+
+    pub fn iterate<'a>(&'a self) -> Result<impl Iterator<Item = Result<models::PropertyIndex>> + 'a> {
+        let iterator = self.db.iterate("index_definitions:v1", KvIteratorMode::Start)?;
+
+        Ok(iterator.map(|item| -> Result<models::PropertyIndex> {
+            let (k, v) = item;
+            let mut cursor = Cursor::new(k);
+            let name = read_unsized_string(&mut cursor)?;
+            read_index_definition(name, &v)
+        }))
+    }
+
+    pub fn create(&self, batch: &mut E::Batch, index: &models::PropertyIndex) -> Result<()> {
+        let mut map = serde_json::Map::new();
+        map.insert("properties".to_string(), JsonValue::from(index.properties.clone()));
+        map.insert("multi_valued".to_string(), JsonValue::Bool(index.multi_valued));
+        let value = serde_json::to_vec(&JsonValue::Object(map))?;
+        self.db
+            .put(batch, "index_definitions:v1", &self.definition_key(&index.name), &value)?;
         Ok(())
     }
+
+    pub fn delete(&self, batch: &mut E::Batch, name: &str) -> Result<()> {
+        self.db
+            .delete(batch, "index_definitions:v1", &self.definition_key(name))?;
+        Ok(())
+    }
+
+    pub fn iterate_entries<'a>(&'a self, name: &str) -> Result<Box<dyn Iterator<Item = Result<IndexEntryItem>> + 'a>> {
+        self.iterate_for_range(name, &[], &[], models::IndexOrder::Ascending)
+    }
+
+    pub fn delete_entry(&self, batch: &mut E::Batch, key: &[u8]) -> Result<()> {
+        self.db.delete(batch, "index_entries:v1", key)?;
+        Ok(())
+    }
+
+    pub fn index_vertex(&self, batch: &mut E::Batch, vertex_id: Uuid, index: &models::PropertyIndex) -> Result<()> {
+        for values in self.get_entries(vertex_id, index, None)? {
+            let key = self.entry_key(&index.name, &values, vertex_id);
+            self.db.put(batch, "index_entries:v1", &key, &[])?;
+        }
+
+        Ok(())
+    }
+
+    pub fn iterate_for_range<'a>(
+        &'a self,
+        name: &str,
+        low: &[JsonValue],
+        high: &[JsonValue],
+        order: models::IndexOrder,
+    ) -> Result<Box<dyn Iterator<Item = Result<IndexEntryItem>> + 'a>> {
+        let low_key = self.entry_prefix(name, low);
+        let high_key = self.entry_prefix(name, high);
+        // The high bound is inclusive of every entry that it's a prefix of,
+        // so the range ends at its successor
+        let read_options = KvReadOptions::default().bounded_to_prefix(&high_key);
+        let is_below_high = move |k: &[u8]| k.starts_with(&high_key) || k <= &high_key[..];
+
+        let filtered: Box<dyn Iterator<Item = KvItem> + 'a> = match order {
+            models::IndexOrder::Ascending => {
+                self.db
+                    .iterate_with_options("index_entries:v1", KvIteratorMode::Forward(&low_key), &read_options)?
+            }
+            models::IndexOrder::Descending => {
+                let iterator = match read_options.upper_bound {
+                    Some(start_key) => self
+                        .db
+                        .iterate("index_entries:v1", KvIteratorMode::Reverse(&start_key))?,
+                    None => self.db.iterate("index_entries:v1", KvIteratorMode::End)?,
+                };
+
+                Box::new(
+                    iterator
+                        .skip_while(move |item| !is_below_high(&item.0[..]))
+                        .take_while(move |item| item.0[..] >= low_key[..]),
+                )
+            }
+        };
+
+        let mapped = filtered.map(|item| -> Result<IndexEntryItem> {
+            let (k, _) = item;
+            debug_assert!(k.len() >= 16);
+            let id = Uuid::from_slice(&k[k.len() - 16..]).unwrap();
+            Ok((k, id))
+        });
+
+        Ok(Box::new(mapped))
+    }
+
+    // Gets the values of each index entry for a vertex, with an optional
+    // pending change to one of its properties applied. A vertex has no
+    // entries if it doesn't have all of the indexed properties. Otherwise it
+    // has one, unless the index is multi-valued, in which case there's an
+    // entry for every combination of the elements of array values.
+    fn get_entries(
+        &self,
+        vertex_id: Uuid,
+        index: &models::PropertyIndex,
+        change: Option<(&str, Option<&JsonValue>)>,
+    ) -> Result<Vec<Vec<JsonValue>>> {
+        let vertex_property_manager = VertexPropertyManager::new(self.db.clone())?;
+        let mut entries = vec![Vec::with_capacity(index.properties.len())];
+
+        for property in &index.properties {
+            let value = match change {
+                Some((name, value)) if name == property.as_str() => value.cloned(),
+                _ => vertex_property_manager.get(vertex_id, property)?,
+            };
+
+            let elements = match value {
+                Some(JsonValue::Array(mut elements)) if index.multi_valued => {
+                    elements.sort_by_key(encode_index_value);
+                    elements.dedup();
+                    elements
+                }
+                Some(value) => vec![value],
+                None => return Ok(Vec::new()),
+            };
+
+            entries = entries
+                .into_iter()
+                .flat_map(|entry| {
+                    elements.iter().map(move |element| {
+                        let mut entry = entry.clone();
+                        entry.push(element.clone());
+                        entry
+                    })
+                })
+                .collect();
+        }
+
+        Ok(entries)
+    }
+
+    pub fn update(&self, batch: &mut E::Batch, vertex_id: Uuid, name: &str, value: Option<&JsonValue>) -> Result<()> {
+        for index in self.iterate()? {
+            let index = index?;
+
+            if !index.properties.iter().any(|property| property == name) {
+                continue;
+            }
+
+            for old_values in self.get_entries(vertex_id, &index, None)? {
+                let key = self.entry_key(&index.name, &old_values, vertex_id);
+                self.db.delete(batch, "index_entries:v1", &key)?;
+            }
+
+            for new_values in self.get_entries(vertex_id, &index, Some((name, value)))? {
+                let key = self.entry_key(&index.name, &new_values, vertex_id);
+                self.db.put(batch, "index_entries:v1", &key, &[])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Gets the values of an index entry, if it matches the current values
+    // of the vertex's indexed properties. Entries can go stale if a property
+    // is set more than once in the same batch, since each change only
+    // sees the values that were committed before the batch.
+    pub fn get_current_values(
+        &self,
+        index: &models::PropertyIndex,
+        key: &[u8],
+        vertex_id: Uuid,
+    ) -> Result<Option<Vec<JsonValue>>> {
+        let entries = self.get_entries(vertex_id, index, None)?;
+        Ok(entries
+            .into_iter()
+            .find(|values| self.entry_key(&index.name, values, vertex_id)[..] == key[..]))
+    }
+}
+
+fn read_index_definition(name: String, value_bytes: &[u8]) -> Result<models::PropertyIndex> {
+    let value: JsonValue = serde_json::from_slice(value_bytes)?;
+    let properties = value
+        .get("properties")
+        .and_then(|properties| properties.as_array())
+        .ok_or("Invalid index definition")?;
+    let properties = properties
+        .iter()
+        .map(|property| property.as_str().map(|property| property.to_string()))
+        .collect::<Option<Vec<String>>>()
+        .ok_or("Invalid index definition")?;
+    let multi_valued = value
+        .get("multi_valued")
+        .and_then(|multi_valued| multi_valued.as_bool());
+
+    Ok(models::PropertyIndex {
+        name,
+        properties,
+        multi_valued: multi_valued.unwrap_or(false),
+    })
+}
+
+pub struct TagManager<E: KvEngine> {
+    pub db: Arc<E>,
+}
+
+impl<E: KvEngine> TagManager<E> {
+    pub fn new(db: Arc<E>) -> Result<Self> {
+        Ok(TagManager { db })
+    }
+
+    fn key(&self, name: &str) -> Vec<u8> {
+        build(&[Component::UnsizedString(name)])
+    }
+
+    pub fn get(&self, name: &str) -> Result<Option<DateTime<Utc>>> {
+        self.db.get_with("snapshot_tags:v1", &self.key(name), |value_bytes| {
+            read_datetime(&mut Cursor::new(value_bytes))
+        })
+    }
+
+    pub fn iterate<'a>(&'a self) -> Result<impl Iterator<Item = Result<(String, DateTime<Utc>)>> + 'a> {
+        let iterator = self.db.iterate("snapshot_tags:v1", KvIteratorMode::Start)?;
+
+        Ok(iterator.map(|item| -> Result<(String, DateTime<Utc>)> {
+            let (k, v) = item;
+            let name = read_unsized_string(&mut Cursor::new(k))?;
+            let created_datetime = read_datetime(&mut Cursor::new(v))?;
+            Ok((name, created_datetime))
+        }))
+    }
+
+    pub fn create(&self, batch: &mut E::Batch, name: &str, created_datetime: DateTime<Utc>) -> Result<()> {
+        let value = build(&[Component::DateTime(created_datetime)]);
+        self.db.put(batch, "snapshot_tags:v1", &self.key(name), &value)?;
+        Ok(())
+    }
+
+    pub fn delete(&self, batch: &mut E::Batch, name: &str) -> Result<()> {
+        self.db.delete(batch, "snapshot_tags:v1", &self.key(name))?;
+        Ok(())
+    }
+}
+
+// Records responses by idempotency key. Records are stored like property
+// values with an expiry, so the compaction filter for expired properties
+// purges them as well.
+pub struct IdempotencyManager<E: KvEngine> {
+    pub db: Arc<E>,
+}
+
+impl<E: KvEngine> IdempotencyManager<E> {
+    pub fn new(db: Arc<E>) -> Result<Self> {
+        Ok(IdempotencyManager { db })
+    }
+
+    fn key(&self, key: &str) -> Vec<u8> {
+        build(&[Component::UnsizedString(key)])
+    }
+
+    pub fn get(&self, key: &str, now: DateTime<Utc>) -> Result<Option<Vec<u8>>> {
+        match self.db.get("idempotency_keys:v1", &self.key(key))? {
+            Some(value_bytes) => Ok(read_property_value(&value_bytes, now)?.map(|response| response.to_vec())),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set(&self, batch: &mut E::Batch, key: &str, response: &[u8], expires_at: DateTime<Utc>) -> Result<()> {
+        let value = build_property_value(response.to_vec(), Some(expires_at));
+        self.db.put(batch, "idempotency_keys:v1", &self.key(key), &value)?;
+        Ok(())
+    }
+}
+
+// Counts vertices or edges by type. The counts are kept up to date as
+// vertices and edges are created and deleted.
+pub struct TypeCountManager<E: KvEngine> {
+    pub db: Arc<E>,
+    pub keyspace: &'static str,
+}
+
+impl<E: KvEngine> TypeCountManager<E> {
+    pub fn new_for_vertices(db: Arc<E>) -> Result<Self> {
+        Ok(TypeCountManager {
+            db,
+            keyspace: "vertex_type_counts:v1",
+        })
+    }
+
+    pub fn new_for_edges(db: Arc<E>) -> Result<Self> {
+        Ok(TypeCountManager {
+            db,
+            keyspace: "edge_type_counts:v1",
+        })
+    }
+
+    fn key(&self, t: &models::Type) -> Vec<u8> {
+        build(&[Component::Type(t)])
+    }
+
+    pub fn iterate<'a>(&'a self) -> Result<impl Iterator<Item = Result<(models::Type, i64)>> + 'a> {
+        let iterator = self.db.iterate(self.keyspace, KvIteratorMode::Start)?;
+
+        Ok(iterator.map(|item| -> Result<(models::Type, i64)> {
+            let (k, v) = item;
+            let t = read_type(&mut Cursor::new(k))?;
+            let count = read_counter(&mut Cursor::new(v))?;
+            Ok((t, count))
+        }))
+    }
+
+    pub fn add(&self, batch: &mut E::Batch, t: &models::Type, delta: i64) -> Result<()> {
+        self.db.increment(batch, self.keyspace, &self.key(t), delta)
+    }
+}
+
+// Gets the start of the window that a datetime falls in. Windows are
+// aligned to unix epoch.
+pub fn neighbor_sketch_window_start(datetime: DateTime<Utc>, window: Duration) -> DateTime<Utc> {
+    let window_nanos = (window.as_secs() as i64)
+        .saturating_mul(1_000_000_000)
+        .saturating_add(i64::from(window.subsec_nanos()))
+        .max(1);
+
+    match signed_nanos_since_epoch(&datetime) {
+        Some(nanos) => {
+            let offset = nanos % window_nanos;
+            let offset = if offset < 0 { offset + window_nanos } else { offset };
+            datetime_from_signed_nanos(nanos - offset)
+        }
+        None => datetime,
+    }
+}
+
+// Sketches the distinct outbound vertices of the edges going into each
+// vertex, by edge type and window of update datetimes. Sketches are only
+// added to, so edges that are deleted are still counted in the windows they
+// were written in.
+pub struct NeighborSketchManager<E: KvEngine> {
+    pub db: Arc<E>,
+}
+
+impl<E: KvEngine> NeighborSketchManager<E> {
+    pub fn new(db: Arc<E>) -> Result<Self> {
+        Ok(NeighborSketchManager { db })
+    }
+
+    fn key(&self, id: Uuid, t: &models::Type, window_start: DateTime<Utc>) -> Vec<u8> {
+        build(&[
+            Component::Uuid(id),
+            Component::Type(t),
+            Component::DateTime(window_start),
+        ])
+    }
+
+    // Iterates over the sketches of a vertex, optionally only those for an
+    // edge type.
+    pub fn iterate_for_owner<'a>(
+        &'a self,
+        id: Uuid,
+        t: Option<&models::Type>,
+    ) -> Result<impl Iterator<Item = Result<NeighborSketchItem>> + 'a> {
+        let prefix = match t {
+            Some(t) => build(&[Component::Uuid(id), Component::Type(t)]),
+            None => build(&[Component::Uuid(id)]),
+        };
+
+        let iterator = iterate_prefixed(&*self.db, "inbound_neighbor_sketches:v1", &prefix, &prefix)?;
+
+        Ok(iterator.map(move |item| -> Result<NeighborSketchItem> {
+            let (k, v) = item;
+            let mut cursor = Cursor::new(k);
+            let owner_id = read_uuid(&mut cursor)?;
+            debug_assert_eq!(id, owner_id);
+            let t = read_type(&mut cursor)?;
+            let window_start = read_datetime(&mut cursor)?;
+            let sketch = HyperLogLog::from_bytes(&v).map_err(|_| ErrorKind::Corrupt("invalid sketch".to_string()))?;
+            Ok(((t, window_start), sketch))
+        }))
+    }
+
+    pub fn add(
+        &self,
+        batch: &mut E::Batch,
+        inbound_id: Uuid,
+        t: &models::Type,
+        window_start: DateTime<Utc>,
+        outbound_id: Uuid,
+    ) -> Result<()> {
+        self.db.add_to_sketch(
+            batch,
+            "inbound_neighbor_sketches:v1",
+            &self.key(inbound_id, t, window_start),
+            NEIGHBOR_SKETCH_PRECISION,
+            outbound_id.as_bytes(),
+        )
+    }
+
+    pub fn delete(&self, batch: &mut E::Batch, id: Uuid, t: &models::Type, window_start: DateTime<Utc>) -> Result<()> {
+        self.db
+            .delete(batch, "inbound_neighbor_sketches:v1", &self.key(id, t, window_start))
+    }
+}
+
+// The settings for adjacency bitmaps. They're shared by everything that
+// writes edges, so that neighbor ids are allocated under the same lock.
+#[derive(Debug)]
+pub struct AdjacencyBitmapSettings {
+    // How many edges of a type a vertex needs in a direction before a
+    // bitmap is kept of their neighbors.
+    pub min_degree: u64,
+    // The next neighbor id to allocate, once it's been read from the
+    // dictionary.
+    next_neighbor_id: Mutex<Option<u64>>,
+}
+
+impl AdjacencyBitmapSettings {
+    pub fn new(min_degree: u64) -> Self {
+        AdjacencyBitmapSettings {
+            min_degree,
+            next_neighbor_id: Mutex::new(None),
+        }
+    }
+}
+
+// The settings for recording the history of vertices, edges and
+// properties. See `RocksdbDatastore::with_history`.
+#[derive(Debug)]
+pub struct HistorySettings {
+    // How much history to retain.
+    pub retention: RetentionPolicy,
+    // The clock that versions are timestamped with.
+    pub clock: Arc<dyn Clock>,
+}
+
+// The kinds of items recorded in the history, which their keys start with.
+pub const HISTORY_VERTEX_TAG: u8 = 1;
+pub const HISTORY_EDGE_TAG: u8 = 2;
+pub const HISTORY_VERTEX_PROPERTY_TAG: u8 = 3;
+pub const HISTORY_EDGE_PROPERTY_TAG: u8 = 4;
+
+// Gets the key that the versions of a vertex in the history start with.
+pub fn vertex_history_key(id: Uuid) -> Vec<u8> {
+    build(&[Component::Bytes(&[HISTORY_VERTEX_TAG]), Component::Uuid(id)])
+}
+
+// Gets the key that the versions of an edge in the history start with.
+pub fn edge_history_key(outbound_id: Uuid, t: &models::Type, inbound_id: Uuid) -> Vec<u8> {
+    build(&[
+        Component::Bytes(&[HISTORY_EDGE_TAG]),
+        Component::Uuid(outbound_id),
+        Component::Type(t),
+        Component::Uuid(inbound_id),
+    ])
+}
+
+// Gets the key that the versions of a vertex property in the history start
+// with. The name is sized, so that no item's key is a prefix of another's.
+pub fn vertex_property_history_key(vertex_id: Uuid, name: &str) -> Vec<u8> {
+    build(&[
+        Component::Bytes(&[HISTORY_VERTEX_PROPERTY_TAG]),
+        Component::Uuid(vertex_id),
+        Component::LongSizedString(name),
+    ])
+}
+
+// Gets the key that the versions of an edge property in the history start
+// with.
+pub fn edge_property_history_key(outbound_id: Uuid, t: &models::Type, inbound_id: Uuid, name: &str) -> Vec<u8> {
+    build(&[
+        Component::Bytes(&[HISTORY_EDGE_PROPERTY_TAG]),
+        Component::Uuid(outbound_id),
+        Component::Type(t),
+        Component::Uuid(inbound_id),
+        Component::LongSizedString(name),
+    ])
+}
+
+// An item recorded in the history, as read from its key.
+enum HistoryItem {
+    Vertex(Uuid),
+    Edge(models::EdgeKey),
+    VertexProperty(Uuid, String),
+    EdgeProperty(models::EdgeKey, String),
+}
+
+impl HistoryItem {
+    fn read(item_key: &[u8]) -> Result<Self> {
+        let mut cursor = Cursor::new(item_key);
+        let tag = item_key.first().cloned();
+        cursor.set_position(1);
+
+        let item = match tag {
+            Some(HISTORY_VERTEX_TAG) => HistoryItem::Vertex(read_uuid(&mut cursor)?),
+            Some(HISTORY_EDGE_TAG) => HistoryItem::Edge(read_history_edge_key(&mut cursor)?),
+            Some(HISTORY_VERTEX_PROPERTY_TAG) => {
+                let vertex_id = read_uuid(&mut cursor)?;
+                HistoryItem::VertexProperty(vertex_id, read_long_sized_string(&mut cursor)?)
+            }
+            Some(HISTORY_EDGE_PROPERTY_TAG) => {
+                let key = read_history_edge_key(&mut cursor)?;
+                HistoryItem::EdgeProperty(key, read_long_sized_string(&mut cursor)?)
+            }
+            _ => return Err(ErrorKind::Corrupt("unknown history item".to_string()).into()),
+        };
+
+        Ok(item)
+    }
+
+    fn involves(&self, ids: &HashSet<Uuid>) -> bool {
+        match self {
+            HistoryItem::Vertex(id) | HistoryItem::VertexProperty(id, _) => ids.contains(id),
+            HistoryItem::Edge(key) | HistoryItem::EdgeProperty(key, _) => {
+                ids.contains(&key.outbound_id) || ids.contains(&key.inbound_id)
+            }
+        }
+    }
+}
+
+fn read_history_edge_key(cursor: &mut Cursor<&[u8]>) -> Result<models::EdgeKey> {
+    let outbound_id = read_uuid(cursor)?;
+    let t = read_type(cursor)?;
+    let inbound_id = read_uuid(cursor)?;
+    Ok(models::EdgeKey::new(outbound_id, t, inbound_id))
+}
+
+// Splits the key of a version in the history into the key of its item and
+// the datetime it was committed.
+fn split_history_key(key: &[u8]) -> Result<(&[u8], DateTime<Utc>)> {
+    if key.len() < 9 {
+        return Err(ErrorKind::Corrupt("truncated history key".to_string()).into());
+    }
+
+    let (item_key, datetime_bytes) = key.split_at(key.len() - 8);
+    Ok((item_key, read_datetime(&mut Cursor::new(datetime_bytes))?))
+}
+
+// The items that existed at a point in time, rebuilt from the history.
+#[derive(Default)]
+pub struct HistoryItems {
+    pub vertices: BTreeMap<Uuid, models::Type>,
+    pub edges: BTreeMap<models::EdgeKey, DateTime<Utc>>,
+    pub vertex_properties: BTreeMap<(Uuid, String), JsonValue>,
+    pub edge_properties: BTreeMap<(models::EdgeKey, String), JsonValue>,
+}
+
+// Records previous versions of vertices, edges and properties. An item's
+// versions are keyed by the item followed by the datetime they were
+// committed, encoded so that newer ones sort first. Each version is stored
+// with the datetime it falls out of the retention window, once it has one:
+// a version starts aging out when it's superseded, and a deletion as soon
+// as it's recorded. Versions that have aged out are ignored by reads and
+// purged on compaction.
+pub struct HistoryManager<E: KvEngine> {
+    pub db: Arc<E>,
+}
+
+impl<E: KvEngine> HistoryManager<E> {
+    pub fn new(db: Arc<E>) -> Result<Self> {
+        Ok(HistoryManager { db })
+    }
+
+    // Records a new version of an item, or its deletion if `value` is
+    // `None`, and applies the retention policy to its older versions.
+    // Versions are read from the database rather than the batch, so if an
+    // item is recorded twice in the same batch, only the last version is
+    // kept.
+    pub fn record(
+        &self,
+        batch: &mut E::Batch,
+        settings: &HistorySettings,
+        item_key: &[u8],
+        value: Option<&[u8]>,
+    ) -> Result<()> {
+        let now = settings.clock.now();
+        let retention = &settings.retention;
+        let ages_out_at = retention.max_age.map(|max_age| now + max_age);
+        // The new version counts towards the maximum number of versions
+        let mut version_count = 1;
+
+        for (key, version) in iterate_prefixed(&*self.db, "history:v1", item_key, item_key)? {
+            let (expires_at, old_value) = read_history_version(&version)?;
+
+            // Older versions have aged out as well, and are left to the
+            // compaction filter
+            if expires_at.map_or(false, |expires_at| expires_at <= now) {
+                break;
+            }
+
+            if retention
+                .max_versions
+                .map_or(false, |max_versions| version_count >= max_versions)
+            {
+                self.db.delete(batch, "history:v1", &key)?;
+                continue;
+            }
+
+            // The version being superseded starts aging out
+            if version_count == 1 && expires_at.is_none() && ages_out_at.is_some() {
+                self.db.put(
+                    batch,
+                    "history:v1",
+                    &key,
+                    &build_history_version(ages_out_at, old_value),
+                )?;
+            }
+
+            version_count += 1;
+        }
+
+        if retention.max_versions == Some(0) {
+            return Ok(());
+        }
+
+        // A deletion in effect at the cutoff is indistinguishable from not
+        // having a version at all, so it starts aging out right away
+        let expires_at = if value.is_none() { ages_out_at } else { None };
+        let key = build(&[Component::Bytes(item_key), Component::DateTime(now)]);
+        self.db
+            .put(batch, "history:v1", &key, &build_history_version(expires_at, value))?;
+        Ok(())
+    }
+
+    // Rebuilds the items that existed as of `datetime`, ignoring versions
+    // that had aged out as of `now`.
+    pub fn items_as_of(&self, datetime: DateTime<Utc>, now: DateTime<Utc>) -> Result<HistoryItems> {
+        let mut items = HistoryItems::default();
+        // The item whose version as of the datetime was last read, so that
+        // its older versions are skipped
+        let mut last_item_key: Option<Vec<u8>> = None;
+
+        for (key, version) in self.db.iterate("history:v1", KvIteratorMode::Start)? {
+            let (item_key, committed_datetime) = split_history_key(&key)?;
+
+            if committed_datetime > datetime || last_item_key.as_ref().map_or(false, |last| &last[..] == item_key) {
+                continue;
+            }
+
+            last_item_key = Some(item_key.to_vec());
+            let value = match read_history_version(&version)? {
+                (Some(expires_at), _) if expires_at <= now => continue,
+                (_, Some(value)) => value,
+                (_, None) => continue,
+            };
+
+            match HistoryItem::read(item_key)? {
+                HistoryItem::Vertex(id) => {
+                    items.vertices.insert(id, read_type(&mut Cursor::new(value))?);
+                }
+                HistoryItem::Edge(key) => {
+                    items.edges.insert(key, read_datetime(&mut Cursor::new(value))?);
+                }
+                HistoryItem::VertexProperty(vertex_id, name) => {
+                    items
+                        .vertex_properties
+                        .insert((vertex_id, name), serde_json::from_slice(value)?);
+                }
+                HistoryItem::EdgeProperty(key, name) => {
+                    items
+                        .edge_properties
+                        .insert((key, name), serde_json::from_slice(value)?);
+                }
+            }
+        }
+
+        Ok(items)
+    }
+
+    // Discards all but the current versions of the given vertices, and of
+    // the properties, edges and edge properties associated with them. Items
+    // that are currently deleted are discarded entirely.
+    pub fn vacuum(&self, batch: &mut E::Batch, ids: &HashSet<Uuid>) -> Result<()> {
+        let mut last_item_key: Option<Vec<u8>> = None;
+
+        for (key, version) in self.db.iterate("history:v1", KvIteratorMode::Start)? {
+            let (item_key, _) = split_history_key(&key)?;
+
+            if !HistoryItem::read(item_key)?.involves(ids) {
+                continue;
+            }
+
+            // The newest version of an item comes first
+            if last_item_key.as_ref().map_or(true, |last| &last[..] != item_key) {
+                last_item_key = Some(item_key.to_vec());
+
+                if read_history_version(&version)?.1.is_some() {
+                    continue;
+                }
+            }
+
+            self.db.delete(batch, "history:v1", &key)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Maps vertex ids to the dense integers that adjacency bitmaps store, and
+// back. Neighbor ids are allocated the first time a vertex is added to a
+// bitmap, and are kept even once the vertex is deleted.
+pub struct NeighborIdManager<E: KvEngine> {
+    pub db: Arc<E>,
+}
+
+impl<E: KvEngine> NeighborIdManager<E> {
+    pub fn new(db: Arc<E>) -> Result<Self> {
+        Ok(NeighborIdManager { db })
+    }
+
+    fn key(&self, id: Uuid) -> Vec<u8> {
+        build(&[Component::Uuid(id)])
+    }
+
+    // The neighbor ids column family has a bloom filter, so looking up a
+    // vertex that was never added to a bitmap usually doesn't read from
+    // disk.
+    pub fn get(&self, id: Uuid) -> Result<Option<u32>> {
+        self.db.get_with("neighbor_ids:v1", &self.key(id), read_neighbor_id)
+    }
+
+    pub fn get_vertex_id(&self, neighbor_id: u32) -> Result<Option<Uuid>> {
+        self.db.get_with(
+            "neighbor_id_vertices:v1",
+            &build_neighbor_id(neighbor_id),
+            |value_bytes| read_uuid(&mut Cursor::new(value_bytes)),
+        )
+    }
+
+    // Gets the neighbor id of a vertex, allocating one if it doesn't have
+    // one yet. Allocations are written right away under the settings' lock,
+    // rather than staged in the batch, so that concurrent batches can't give
+    // a vertex two different ids. If the batch isn't committed, the id is
+    // just left unused.
+    pub fn get_or_allocate(&self, settings: &AdjacencyBitmapSettings, id: Uuid) -> Result<u32> {
+        if let Some(neighbor_id) = self.get(id)? {
+            return Ok(neighbor_id);
+        }
+
+        let mut next_neighbor_id = settings.next_neighbor_id.lock().unwrap();
+
+        // Checked again, in case it was allocated while waiting for the lock
+        if let Some(neighbor_id) = self.get(id)? {
+            return Ok(neighbor_id);
+        }
+
+        let neighbor_id = match *next_neighbor_id {
+            Some(next_neighbor_id) => next_neighbor_id,
+            None => match self.db.iterate("neighbor_id_vertices:v1", KvIteratorMode::End)?.next() {
+                Some((k, _)) => u64::from(read_neighbor_id(&k)?) + 1,
+                None => 0,
+            },
+        };
+
+        if neighbor_id > u64::from(u32::MAX) {
+            return Err("Could not allocate a neighbor id".into());
+        }
+
+        let neighbor_id_bytes = build_neighbor_id(neighbor_id as u32);
+        let mut batch = E::Batch::default();
+        self.db
+            .put(&mut batch, "neighbor_ids:v1", id.as_bytes(), &neighbor_id_bytes)?;
+        self.db
+            .put(&mut batch, "neighbor_id_vertices:v1", &neighbor_id_bytes, &self.key(id))?;
+        self.db.write(batch)?;
+
+        *next_neighbor_id = Some(neighbor_id + 1);
+        Ok(neighbor_id as u32)
+    }
+}
+
+// Keeps roaring bitmaps of the neighbor ids of high-degree vertices, by edge
+// type, so that their neighbors can be intersected without scanning their
+// edges. Until a vertex has enough edges of a type, they're only counted;
+// the bitmap is then seeded from the vertex's edges, and kept up to date
+// until the vertex is deleted, even if its degree drops again. Like type
+// counts, a bitmap can miss an edge written concurrently with it being
+// seeded.
+pub struct AdjacencyBitmapManager<E: KvEngine> {
+    pub db: Arc<E>,
+    pub keyspace: &'static str,
+    pub degree_keyspace: &'static str,
+    // Whether the bitmaps are of the outbound vertices of inbound edges,
+    // rather than the inbound vertices of outbound edges.
+    pub reversed: bool,
+}
+
+impl<E: KvEngine> AdjacencyBitmapManager<E> {
+    pub fn new(db: Arc<E>) -> Result<Self> {
+        Ok(AdjacencyBitmapManager {
+            db,
+            keyspace: "adjacency_bitmaps:v1",
+            degree_keyspace: "adjacency_degrees:v1",
+            reversed: false,
+        })
+    }
+
+    pub fn new_reversed(db: Arc<E>) -> Result<Self> {
+        Ok(AdjacencyBitmapManager {
+            db,
+            keyspace: "reversed_adjacency_bitmaps:v1",
+            degree_keyspace: "reversed_adjacency_degrees:v1",
+            reversed: true,
+        })
+    }
+
+    fn key(&self, id: Uuid, t: &models::Type) -> Vec<u8> {
+        build(&[Component::Uuid(id), Component::Type(t)])
+    }
+
+    // Gets the bitmap of a vertex's neighbors by an edge type, or `None` if
+    // the vertex doesn't have one.
+    pub fn get(&self, id: Uuid, t: &models::Type) -> Result<Option<RoaringBitmap>> {
+        let bitmap = self.db.get_with(self.keyspace, &self.key(id, t), |value_bytes| {
+            if value_bytes.is_empty() {
+                Ok(None)
+            } else {
+                read_bitmap(value_bytes).map(Some)
+            }
+        })?;
+
+        Ok(bitmap.unwrap_or(None))
+    }
+
+    fn exists(&self, key: &[u8]) -> Result<bool> {
+        let exists = self
+            .db
+            .get_with(self.keyspace, key, |value_bytes| Ok(!value_bytes.is_empty()))?;
+        Ok(exists.unwrap_or(false))
+    }
+
+    // Iterates over the edge types that a vertex's edges are counted by.
+    // Bitmaps are only seeded once edges are counted, so these are also the
+    // edge types it can have bitmaps for.
+    pub fn iterate_for_owner<'a>(&'a self, id: Uuid) -> Result<impl Iterator<Item = Result<models::Type>> + 'a> {
+        let prefix = build(&[Component::Uuid(id)]);
+        let iterator = iterate_prefixed(&*self.db, self.degree_keyspace, &prefix, &prefix)?;
+
+        Ok(iterator.map(move |item| -> Result<models::Type> {
+            let (k, _) = item;
+            let mut cursor = Cursor::new(k);
+            let owner_id = read_uuid(&mut cursor)?;
+            debug_assert_eq!(id, owner_id);
+            read_type(&mut cursor)
+        }))
+    }
+
+    // Counts a new edge of a vertex, adding its neighbor to the vertex's
+    // bitmap if it has one, or seeding the bitmap if the vertex now has
+    // enough edges.
+    pub fn add(
+        &self,
+        batch: &mut E::Batch,
+        settings: &AdjacencyBitmapSettings,
+        id: Uuid,
+        t: &models::Type,
+        neighbor_vertex_id: Uuid,
+    ) -> Result<()> {
+        let key = self.key(id, t);
+        let degree = self
+            .db
+            .get_with(self.degree_keyspace, &key, |value_bytes| {
+                read_counter(&mut Cursor::new(value_bytes))
+            })?
+            .unwrap_or(0);
+        self.db.increment(batch, self.degree_keyspace, &key, 1)?;
+
+        // The degree doesn't include the edge being added yet
+        if (degree.max(0) as u64) + 1 < settings.min_degree {
+            return Ok(());
+        }
+
+        let neighbor_id_manager = NeighborIdManager::new(self.db.clone())?;
+        let neighbor_id = neighbor_id_manager.get_or_allocate(settings, neighbor_vertex_id)?;
+
+        if self.exists(&key)? {
+            return self
+                .db
+                .update_bitmap(batch, self.keyspace, &key, &BitmapUpdate::Insert(neighbor_id));
+        }
+
+        // Seeded from every edge the vertex has, including those written
+        // before the bitmaps were enabled and so weren't counted
+        let edge_range_manager = if self.reversed {
+            EdgeRangeManager::new_reversed(self.db.clone())?
+        } else {
+            EdgeRangeManager::new(self.db.clone())?
+        };
+
+        let mut bitmap = RoaringBitmap::new();
+        bitmap.insert(neighbor_id);
+
+        for item in edge_range_manager.iterate_for_range(id, Some(t), None)? {
+            let (_, _, _, second_id) = item?;
+            bitmap.insert(neighbor_id_manager.get_or_allocate(settings, second_id)?);
+        }
+
+        self.db
+            .update_bitmap(batch, self.keyspace, &key, &BitmapUpdate::Seed(bitmap))
+    }
+
+    // Removes a deleted edge's neighbor from a vertex's bitmap, if it has
+    // one. The edge is only uncounted if the settings are given; degrees
+    // that are too high just mean a bitmap is seeded a little early.
+    pub fn remove(
+        &self,
+        batch: &mut E::Batch,
+        settings: Option<&AdjacencyBitmapSettings>,
+        id: Uuid,
+        t: &models::Type,
+        neighbor_vertex_id: Uuid,
+    ) -> Result<()> {
+        let key = self.key(id, t);
+
+        if settings.is_some() {
+            self.db.increment(batch, self.degree_keyspace, &key, -1)?;
+        }
+
+        // A vertex that doesn't have a neighbor id isn't in any bitmaps
+        if let Some(neighbor_id) = NeighborIdManager::new(self.db.clone())?.get(neighbor_vertex_id)? {
+            if self.exists(&key)? {
+                self.db
+                    .update_bitmap(batch, self.keyspace, &key, &BitmapUpdate::Remove(neighbor_id))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn delete(&self, batch: &mut E::Batch, id: Uuid, t: &models::Type) -> Result<()> {
+        let key = self.key(id, t);
+        self.db.delete(batch, self.keyspace, &key)?;
+        self.db.delete(batch, self.degree_keyspace, &key)
+    }
 }
@@ -1,13 +1,34 @@
 //! The rocksdb datastore implementation.
 
-mod bytes;
+mod batch;
+pub mod bytes;
+mod coalescer;
 mod datastore;
+mod distributed;
+mod engine;
 mod managers;
+mod query;
+mod segments;
+mod tiered;
+mod tuner;
+mod verify;
+mod workers;
 
 #[cfg(feature = "test-suite")]
 mod tests;
 
-pub use self::datastore::{RocksdbDatastore, RocksdbTransaction};
+pub use self::batch::{BatchTransaction, Savepoint};
+pub use self::coalescer::{CoalesceOptions, WriteCoalescer};
+pub use self::datastore::{
+    CommitOptions, IndexBackfillProgress, ResourceOptions, RocksdbDatastore, RocksdbTransaction, ScanOptions,
+    SnapshotTag, TypeStats,
+};
+pub use self::distributed::{DistributedDatastore, DistributedTransaction, OrderedKvClient, OrderedKvTransaction};
+pub use self::segments::ObjectStore;
+pub use self::tiered::{TieredDatastore, TieredOptions, TieredStore, TieredTransaction};
+pub use self::tuner::{AutoTuneOptions, TuningChange};
+pub use self::verify::CorruptEntry;
+pub use self::workers::BackgroundTaskMetrics;
 
 mod normal_config {
     #[cfg(feature = "bench-suite")]
@@ -40,3 +61,29 @@ mod bulk_load_optimized_config {
         RocksdbDatastore::new(&generate_temporary_path(), Some(1), true).unwrap()
     });
 }
+
+mod distributed_config {
+    #[cfg(feature = "test-suite")]
+    full_test_impl!({
+        use super::tests::memory_kv::MemoryKvClient;
+        use super::DistributedDatastore;
+        DistributedDatastore::new(MemoryKvClient::default())
+    });
+}
+
+mod tiered_config {
+    #[cfg(feature = "test-suite")]
+    full_test_impl!({
+        use super::tests::memory_kv::MemoryObjectStore;
+        use super::{DistributedDatastore, TieredOptions, TieredStore};
+        use util::generate_temporary_path;
+        // Small enough that the tests' writes are flushed and compacted
+        let options = TieredOptions::new()
+            .block_size(256)
+            .cache_capacity(4096)
+            .buffer_size(512)
+            .max_segments(4);
+        let store = TieredStore::new(&generate_temporary_path(), MemoryObjectStore::default(), options).unwrap();
+        DistributedDatastore::new(store)
+    });
+}
@@ -0,0 +1,263 @@
+use super::super::{EdgeDirection, EdgeQuery, VertexQuery};
+use super::engine::{KvEngine, KvReadOptions};
+use super::managers::*;
+use errors::Result;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use util::next_uuid;
+use uuid::Uuid;
+
+fn remove_nones_from_iterator<I, T>(iter: I) -> impl Iterator<Item = Result<T>>
+where
+    I: Iterator<Item = Result<Option<T>>>,
+{
+    iter.filter_map(|item| match item {
+        Err(err) => Some(Err(err)),
+        Ok(Some(value)) => Some(Ok(value)),
+        _ => None,
+    })
+}
+
+/// Runs vertex and edge queries against the managers of an engine, so that
+/// every datastore built on one answers them the same way.
+pub struct QueryRunner<E: KvEngine> {
+    pub db: Arc<E>,
+    // Vertices that queries should treat as already deleted.
+    pub tombstones: HashSet<Uuid>,
+    // Counts the keys that queries read.
+    pub keys_scanned: Arc<AtomicU64>,
+    pub read_options: KvReadOptions,
+}
+
+impl<E: KvEngine> QueryRunner<E> {
+    pub fn vertex_query_to_iterator(&self, q: VertexQuery) -> Result<Box<dyn Iterator<Item = Result<VertexItem>>>> {
+        let tombstones = self.tombstones.clone();
+
+        match q {
+            VertexQuery::Range(q) => {
+                let vertex_manager = VertexManager::new(self.db.clone())?.with_read_options(self.read_options.clone());
+
+                let next_uuid = match q.start_id {
+                    Some(start_id) => {
+                        match next_uuid(start_id) {
+                            Ok(next_uuid) => next_uuid,
+                            // If we get an error back, it's because
+                            // `start_id` is the maximum possible value. We
+                            // know that no vertices exist whose ID is greater
+                            // than the maximum possible value, so just return
+                            // an empty list.
+                            Err(_) => return Ok(Box::new(vec![].into_iter())),
+                        }
+                    }
+                    None => Uuid::default(),
+                };
+
+                let keys_scanned = self.keys_scanned.clone();
+                let mut iter: Box<dyn Iterator<Item = Result<VertexItem>>> =
+                    Box::new(vertex_manager.iterate_for_range(next_uuid)?.inspect(move |_| {
+                        keys_scanned.fetch_add(1, Ordering::Relaxed);
+                    }));
+
+                if !tombstones.is_empty() {
+                    iter = Box::new(iter.filter(move |item| match item {
+                        Ok((id, _)) => !tombstones.contains(id),
+                        Err(_) => true,
+                    }));
+                }
+
+                if let Some(ref t) = q.t {
+                    iter = Box::new(iter.filter(move |item| match item {
+                        Ok((_, v)) => v == t,
+                        Err(_) => true,
+                    }));
+                }
+
+                let results: Vec<Result<VertexItem>> = iter.take(q.limit as usize).collect();
+                Ok(Box::new(results.into_iter()))
+            }
+            VertexQuery::Specific(q) => {
+                let vertex_manager = VertexManager::new(self.db.clone())?;
+                let keys_scanned = self.keys_scanned.clone();
+
+                let iter = q.ids.into_iter().map(move |id| {
+                    if tombstones.contains(&id) {
+                        return Ok(None);
+                    }
+
+                    keys_scanned.fetch_add(1, Ordering::Relaxed);
+
+                    match vertex_manager.get(id)? {
+                        Some(value) => Ok(Some((id, value))),
+                        None => Ok(None),
+                    }
+                });
+
+                Ok(Box::new(remove_nones_from_iterator(iter)))
+            }
+            VertexQuery::Pipe(q) => {
+                let vertex_manager = VertexManager::new(self.db.clone())?;
+                let edge_iterator = self.edge_query_to_iterator(*q.inner)?;
+                let direction = q.direction;
+                let mut seen_ids = HashSet::new();
+
+                let iter = edge_iterator
+                    .flat_map(move |item| match item {
+                        Ok((outbound_id, _, _, inbound_id)) => match direction {
+                            EdgeDirection::Outbound => vec![Ok(outbound_id)],
+                            EdgeDirection::Inbound => vec![Ok(inbound_id)],
+                            EdgeDirection::Both => vec![Ok(outbound_id), Ok(inbound_id)],
+                        },
+                        Err(err) => vec![Err(err)],
+                    })
+                    .filter(move |item| match item {
+                        Ok(id) => direction != EdgeDirection::Both || seen_ids.insert(*id),
+                        Err(_) => true,
+                    });
+
+                let keys_scanned = self.keys_scanned.clone();
+
+                let iter = iter.map(move |item: Result<Uuid>| {
+                    let id = item?;
+                    keys_scanned.fetch_add(1, Ordering::Relaxed);
+
+                    match vertex_manager.get(id)? {
+                        Some(value) => Ok(Some((id, value))),
+                        None => Ok(None),
+                    }
+                });
+
+                let mut iter: Box<dyn Iterator<Item = Result<VertexItem>>> = Box::new(remove_nones_from_iterator(iter));
+
+                if let Some(ref t) = q.t {
+                    iter = Box::new(iter.filter(move |item| match item {
+                        Ok((_, v)) => v == t,
+                        Err(_) => true,
+                    }));
+                }
+
+                let results: Vec<Result<VertexItem>> = iter.take(q.limit as usize).collect();
+                Ok(Box::new(results.into_iter()))
+            }
+        }
+    }
+
+    pub fn edge_query_to_iterator(&self, q: EdgeQuery) -> Result<Box<dyn Iterator<Item = Result<EdgeRangeItem>>>> {
+        let tombstones = self.tombstones.clone();
+
+        match q {
+            EdgeQuery::Specific(q) => {
+                let edge_manager = EdgeManager::new(self.db.clone())?;
+                let keys_scanned = self.keys_scanned.clone();
+
+                let edges = q.keys.into_iter().map(move |key| {
+                    if tombstones.contains(&key.outbound_id) || tombstones.contains(&key.inbound_id) {
+                        return Ok(None);
+                    }
+
+                    keys_scanned.fetch_add(1, Ordering::Relaxed);
+
+                    match edge_manager.get(key.outbound_id, &key.t, key.inbound_id)? {
+                        Some(update_datetime) => {
+                            Ok(Some((key.outbound_id, key.t.clone(), update_datetime, key.inbound_id)))
+                        }
+                        None => Ok(None),
+                    }
+                });
+
+                let iterator = remove_nones_from_iterator(edges);
+                Ok(Box::new(iterator))
+            }
+            EdgeQuery::Pipe(q) => {
+                let vertex_iterator = self.vertex_query_to_iterator(*q.inner)?;
+
+                let edge_range_managers = match q.direction {
+                    EdgeDirection::Outbound => vec![(
+                        EdgeDirection::Outbound,
+                        EdgeRangeManager::new(self.db.clone())?.with_read_options(self.read_options.clone()),
+                    )],
+                    EdgeDirection::Inbound => {
+                        vec![(
+                            EdgeDirection::Inbound,
+                            EdgeRangeManager::new_reversed(self.db.clone())?
+                                .with_read_options(self.read_options.clone()),
+                        )]
+                    }
+                    EdgeDirection::Both => vec![
+                        (
+                            EdgeDirection::Outbound,
+                            EdgeRangeManager::new(self.db.clone())?.with_read_options(self.read_options.clone()),
+                        ),
+                        (
+                            EdgeDirection::Inbound,
+                            EdgeRangeManager::new_reversed(self.db.clone())?
+                                .with_read_options(self.read_options.clone()),
+                        ),
+                    ],
+                };
+
+                // Ideally we'd use iterators all the way down, but things
+                // start breaking apart due to conditional expressions not
+                // returning the same type signature, issues with `Result`s
+                // and some of the iterators, etc. So at this point, we'll
+                // just resort to building a vector.
+                let mut edges: Vec<Result<EdgeRangeItem>> = Vec::new();
+
+                // Edges between two of the vertices, and self-loops, are
+                // found by both scans when going in both directions
+                let mut seen_keys = HashSet::new();
+
+                'vertices: for item in vertex_iterator {
+                    let (id, _) = item?;
+
+                    for &(direction, ref edge_range_manager) in &edge_range_managers {
+                        let edge_iterator = edge_range_manager.iterate_for_range(id, q.t.as_ref(), q.high)?;
+
+                        for item in edge_iterator {
+                            self.keys_scanned.fetch_add(1, Ordering::Relaxed);
+
+                            match item {
+                                Ok((
+                                    edge_range_first_id,
+                                    edge_range_t,
+                                    edge_range_update_datetime,
+                                    edge_range_second_id,
+                                )) => {
+                                    if let Some(low) = q.low {
+                                        if edge_range_update_datetime < low {
+                                            break;
+                                        }
+                                    }
+
+                                    if tombstones.contains(&edge_range_second_id) {
+                                        continue;
+                                    }
+
+                                    let (outbound_id, inbound_id) = match direction {
+                                        EdgeDirection::Inbound => (edge_range_second_id, edge_range_first_id),
+                                        _ => (edge_range_first_id, edge_range_second_id),
+                                    };
+
+                                    if q.direction == EdgeDirection::Both
+                                        && !seen_keys.insert((outbound_id, edge_range_t.clone(), inbound_id))
+                                    {
+                                        continue;
+                                    }
+
+                                    edges.push(Ok((outbound_id, edge_range_t, edge_range_update_datetime, inbound_id)));
+                                }
+                                Err(_) => edges.push(item),
+                            }
+
+                            if edges.len() == q.limit as usize {
+                                break 'vertices;
+                            }
+                        }
+                    }
+                }
+
+                Ok(Box::new(edges.into_iter()))
+            }
+        }
+    }
+}
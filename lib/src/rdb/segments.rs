@@ -0,0 +1,424 @@
+//! Immutable, sorted runs of keys and values that are stored as objects, for
+//! the tiered store. Each segment is two objects: its data, which is split
+//! into blocks that are fetched one at a time, and an index of the blocks'
+//! first keys that's small enough to keep in memory.
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use errors::{ErrorKind, Result};
+use std::collections::VecDeque;
+use std::io::{Cursor, Read};
+use std::iter::Peekable;
+use std::sync::Arc;
+
+/// A key, along with its value or `None` if it was deleted. Deletions are
+/// kept in segments so that they hide the key in older ones.
+pub type Entry = (Vec<u8>, Option<Vec<u8>>);
+
+/// Object storage that segments are kept in, e.g. an S3 bucket. Implement
+/// this for the storage's own client to tier a store's data to it.
+pub trait ObjectStore: Send + Sync + 'static {
+    /// Writes an object, replacing it if it already exists.
+    ///
+    /// # Arguments
+    /// * `name` - The object's name.
+    /// * `data` - The object's contents.
+    fn put(&self, name: &str, data: &[u8]) -> Result<()>;
+
+    /// Reads an object, or `None` if it doesn't exist.
+    ///
+    /// # Arguments
+    /// * `name` - The object's name.
+    fn get(&self, name: &str) -> Result<Option<Vec<u8>>>;
+
+    /// Reads part of an object, e.g. with a ranged GET.
+    ///
+    /// # Arguments
+    /// * `name` - The object's name.
+    /// * `offset` - The offset of the first byte to read.
+    /// * `len` - The number of bytes to read.
+    ///
+    /// # Errors
+    /// Returns an error if the object doesn't exist, or is too short.
+    fn get_range(&self, name: &str, offset: u64, len: u64) -> Result<Vec<u8>>;
+
+    /// Lists the names of the objects that start with a prefix.
+    ///
+    /// # Arguments
+    /// * `prefix` - The prefix.
+    fn list(&self, prefix: &str) -> Result<Vec<String>>;
+
+    /// Deletes an object, if it exists.
+    ///
+    /// # Arguments
+    /// * `name` - The object's name.
+    fn delete(&self, name: &str) -> Result<()>;
+}
+
+// Where a block is in a segment's data, along with its first key.
+#[derive(Clone, Debug, PartialEq)]
+struct BlockHandle {
+    first_key: Vec<u8>,
+    offset: u64,
+    len: u64,
+}
+
+/// The in-memory part of a segment: its sequence number, and where its
+/// blocks are.
+#[derive(Debug)]
+pub struct Segment {
+    /// The segment's sequence number. Newer segments have higher ones, and
+    /// their entries replace older segments' entries for the same keys.
+    pub seq: u64,
+    blocks: Vec<BlockHandle>,
+}
+
+/// Gets the name of a segment's data object.
+pub fn data_name(seq: u64) -> String {
+    format!("segments/{:020}.data", seq)
+}
+
+/// Gets the name of a segment's index object. It's written after the data,
+/// so a segment exists once its index does.
+pub fn index_name(seq: u64) -> String {
+    format!("segments/{:020}.index", seq)
+}
+
+/// Parses the sequence number out of an index object's name, or `None` if
+/// it isn't one.
+pub fn parse_index_name(name: &str) -> Option<u64> {
+    let seq = name.strip_prefix("segments/")?.strip_suffix(".index")?;
+    seq.parse().ok()
+}
+
+fn write_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.write_u32::<BigEndian>(bytes.len() as u32).unwrap();
+    buf.extend_from_slice(bytes);
+}
+
+fn read_bytes(cursor: &mut Cursor<&[u8]>) -> Result<Vec<u8>> {
+    let len = cursor.read_u32::<BigEndian>()? as usize;
+    let mut bytes = vec![0; len];
+    cursor.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Builds a segment's data and index objects out of entries sorted by key.
+///
+/// # Arguments
+/// * `entries` - The entries, in ascending order of key.
+/// * `block_size` - How many bytes of entries to put in each block, at
+///   least. Blocks are the unit that's fetched and cached.
+pub fn build_segment<I>(entries: I, block_size: usize) -> (Vec<u8>, Vec<u8>)
+where
+    I: IntoIterator<Item = Entry>,
+{
+    let mut data = Vec::new();
+    let mut blocks = Vec::new();
+    let mut block_start = 0;
+
+    for (key, value) in entries {
+        if data.len() == block_start {
+            blocks.push(BlockHandle {
+                first_key: key.clone(),
+                offset: block_start as u64,
+                len: 0,
+            });
+        }
+
+        write_bytes(&mut data, &key);
+
+        match value {
+            Some(value) => {
+                data.push(1);
+                write_bytes(&mut data, &value);
+            }
+            None => data.push(0),
+        }
+
+        if data.len() - block_start >= block_size {
+            blocks.last_mut().unwrap().len = (data.len() - block_start) as u64;
+            block_start = data.len();
+        }
+    }
+
+    if data.len() > block_start {
+        blocks.last_mut().unwrap().len = (data.len() - block_start) as u64;
+    }
+
+    let mut index = Vec::new();
+    index.write_u32::<BigEndian>(blocks.len() as u32).unwrap();
+
+    for block in &blocks {
+        write_bytes(&mut index, &block.first_key);
+        index.write_u64::<BigEndian>(block.offset).unwrap();
+        index.write_u64::<BigEndian>(block.len).unwrap();
+    }
+
+    (data, index)
+}
+
+/// Reads the entries in a block.
+///
+/// # Arguments
+/// * `block` - The block's contents.
+pub fn read_block(block: &[u8]) -> Result<Vec<Entry>> {
+    let mut cursor = Cursor::new(block);
+    let mut entries = Vec::new();
+
+    while (cursor.position() as usize) < block.len() {
+        let key = read_bytes(&mut cursor)?;
+
+        let value = match cursor.read_u8()? {
+            0 => None,
+            1 => Some(read_bytes(&mut cursor)?),
+            flag => return Err(ErrorKind::Corrupt(format!("segment entry flag {}", flag)).into()),
+        };
+
+        entries.push((key, value));
+    }
+
+    Ok(entries)
+}
+
+impl Segment {
+    /// Reads a segment's index.
+    ///
+    /// # Arguments
+    /// * `seq` - The segment's sequence number.
+    /// * `index` - The contents of its index object.
+    pub fn from_index(seq: u64, index: &[u8]) -> Result<Self> {
+        let mut cursor = Cursor::new(index);
+        let count = cursor.read_u32::<BigEndian>()?;
+        let mut blocks = Vec::with_capacity(count as usize);
+
+        for _ in 0..count {
+            blocks.push(BlockHandle {
+                first_key: read_bytes(&mut cursor)?,
+                offset: cursor.read_u64::<BigEndian>()?,
+                len: cursor.read_u64::<BigEndian>()?,
+            });
+        }
+
+        Ok(Segment { seq, blocks })
+    }
+
+    /// The number of blocks in the segment.
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Gets where a block is in the segment's data, as its offset and
+    /// length.
+    ///
+    /// # Arguments
+    /// * `block` - The block's position in the segment.
+    pub fn block_range(&self, block: usize) -> (u64, u64) {
+        (self.blocks[block].offset, self.blocks[block].len)
+    }
+
+    /// Gets the position of the block that a key would be in, or `None` if
+    /// it's before the segment's first key.
+    ///
+    /// # Arguments
+    /// * `key` - The key.
+    pub fn find_block(&self, key: &[u8]) -> Option<usize> {
+        self.blocks
+            .partition_point(|block| &block.first_key[..] <= key)
+            .checked_sub(1)
+    }
+}
+
+/// Iterates over a range of a segment's entries, loading its blocks as
+/// they're reached.
+pub struct SegmentScan<F: Fn(&Segment, usize) -> Result<Vec<Entry>>> {
+    segment: Arc<Segment>,
+    load_block: F,
+    start: Vec<u8>,
+    end: Option<Vec<u8>>,
+    reverse: bool,
+    // The next block to load, or `None` once there are no more in range.
+    next_block: Option<usize>,
+    entries: VecDeque<Entry>,
+}
+
+impl<F: Fn(&Segment, usize) -> Result<Vec<Entry>>> SegmentScan<F> {
+    /// Creates a scan over the entries with keys in a range.
+    ///
+    /// # Arguments
+    /// * `segment` - The segment.
+    /// * `load_block` - Loads the entries in one of the segment's blocks.
+    /// * `start` - The first key in the range, inclusive.
+    /// * `end` - The last key in the range, exclusive, or `None` to scan to
+    ///   the end of the segment.
+    /// * `reverse` - Whether to scan from the end of the range.
+    pub fn new(segment: Arc<Segment>, load_block: F, start: &[u8], end: Option<&[u8]>, reverse: bool) -> Self {
+        let next_block = if reverse {
+            // Every key in the block that `end` would be in may be before it
+            match end {
+                Some(end) => segment.find_block(end),
+                None => segment.block_count().checked_sub(1),
+            }
+        } else {
+            match segment.find_block(start) {
+                Some(block) => Some(block),
+                None if segment.block_count() > 0 => Some(0),
+                None => None,
+            }
+        };
+
+        SegmentScan {
+            segment,
+            load_block,
+            start: start.to_vec(),
+            end: end.map(|end| end.to_vec()),
+            reverse,
+            next_block,
+            entries: VecDeque::new(),
+        }
+    }
+
+    fn load_next_block(&mut self) -> Result<()> {
+        let block = match self.next_block {
+            Some(block) => block,
+            None => return Ok(()),
+        };
+
+        let (start, end) = (&self.start, &self.end);
+        let entries = (self.load_block)(&self.segment, block)?;
+        let entries = entries
+            .into_iter()
+            .filter(|(key, _)| key >= start && end.as_ref().map_or(true, |end| key < end));
+
+        if self.reverse {
+            self.entries.extend(entries.rev());
+
+            // The previous block is in range unless this one starts at or
+            // before the start of the range
+            self.next_block = match block.checked_sub(1) {
+                Some(_) if self.segment.blocks[block].first_key <= self.start => None,
+                previous => previous,
+            };
+        } else {
+            self.entries.extend(entries);
+
+            self.next_block = match block + 1 {
+                next if next < self.segment.block_count() => match self.end {
+                    Some(ref end) if self.segment.blocks[next].first_key >= *end => None,
+                    _ => Some(next),
+                },
+                _ => None,
+            };
+        }
+
+        Ok(())
+    }
+}
+
+impl<F: Fn(&Segment, usize) -> Result<Vec<Entry>>> Iterator for SegmentScan<F> {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Result<Entry>> {
+        while self.entries.is_empty() && self.next_block.is_some() {
+            if let Err(err) = self.load_next_block() {
+                self.next_block = None;
+                return Some(Err(err));
+            }
+        }
+
+        self.entries.pop_front().map(Ok)
+    }
+}
+
+/// Merges sorted runs of entries into one, so that the first run's entry
+/// for a key replaces the others' entries for the same key. Deleted keys
+/// are skipped.
+///
+/// # Arguments
+/// * `runs` - The runs, from newest to oldest. Each is in ascending order
+///   of key, or descending order if `reverse` is set.
+/// * `reverse` - Whether the runs are in descending order of key.
+pub fn merge_runs<'a>(
+    runs: Vec<Box<dyn Iterator<Item = Result<Entry>> + 'a>>,
+    reverse: bool,
+) -> impl Iterator<Item = Result<(Vec<u8>, Vec<u8>)>> + 'a {
+    MergeRuns {
+        runs: runs.into_iter().map(Iterator::peekable).collect(),
+        reverse,
+    }
+    .filter_map(|item| match item {
+        Ok((key, Some(value))) => Some(Ok((key, value))),
+        Ok((_, None)) => None,
+        Err(err) => Some(Err(err)),
+    })
+}
+
+/// Merges sorted runs of entries into one, keeping deleted keys so that
+/// the result can replace the runs.
+///
+/// # Arguments
+/// * `runs` - The runs, from newest to oldest, in ascending order of key.
+pub fn merge_runs_with_deletions<'a>(
+    runs: Vec<Box<dyn Iterator<Item = Result<Entry>> + 'a>>,
+) -> impl Iterator<Item = Result<Entry>> + 'a {
+    MergeRuns {
+        runs: runs.into_iter().map(Iterator::peekable).collect(),
+        reverse: false,
+    }
+}
+
+type Run<'a> = Peekable<Box<dyn Iterator<Item = Result<Entry>> + 'a>>;
+
+struct MergeRuns<'a> {
+    runs: Vec<Run<'a>>,
+    reverse: bool,
+}
+
+impl<'a> Iterator for MergeRuns<'a> {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Result<Entry>> {
+        // Errors are returned as soon as they're reached
+        for run in &mut self.runs {
+            if let Some(Err(_)) = run.peek() {
+                return run.next();
+            }
+        }
+
+        // Finds the run with the next key, preferring newer runs on ties
+        let reverse = self.reverse;
+        let mut next: Option<(usize, &Vec<u8>)> = None;
+
+        for (i, run) in self.runs.iter_mut().enumerate() {
+            if let Some(Ok((key, _))) = run.peek() {
+                let is_next = match next {
+                    None => true,
+                    Some((_, next_key)) if reverse => key > next_key,
+                    Some((_, next_key)) => key < next_key,
+                };
+
+                if is_next {
+                    next = Some((i, key));
+                }
+            }
+        }
+
+        let next = next?.0;
+        let (key, value) = match self.runs[next].next() {
+            Some(Ok(entry)) => entry,
+            _ => unreachable!(),
+        };
+
+        // Skips the older runs' entries for the same key
+        for run in &mut self.runs {
+            while let Some(Ok((other_key, _))) = run.peek() {
+                if *other_key != key {
+                    break;
+                }
+
+                run.next();
+            }
+        }
+
+        Some(Ok((key, value)))
+    }
+}
@@ -11,3 +11,3047 @@ fn should_repair() {
     // Now try to repair
     RocksdbDatastore::repair(&path, Some(1)).unwrap();
 }
+
+#[test]
+fn should_commit_batch() {
+    use super::RocksdbDatastore;
+    use models::{EdgeDirection, EdgeKey, SpecificEdgeQuery, SpecificVertexQuery, Type, Vertex};
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let outbound_v = Vertex::new(Type::new("foo").unwrap());
+    let inbound_v = Vertex::new(Type::new("foo").unwrap());
+    let key = EdgeKey::new(outbound_v.id, Type::new("bar").unwrap(), inbound_v.id);
+
+    let mut batch = datastore.batch();
+    batch.create_vertex(&outbound_v);
+    batch.create_vertex(&inbound_v);
+    batch.create_edge(&key);
+    batch.create_edge(&key);
+
+    let trans = datastore.transaction().unwrap();
+    assert_eq!(trans.get_vertex_count().unwrap(), 0);
+
+    batch.commit().unwrap();
+    assert_eq!(trans.get_vertex_count().unwrap(), 2);
+    assert_eq!(
+        trans.get_edges(SpecificEdgeQuery::single(key.clone())).unwrap().len(),
+        1
+    );
+    assert_eq!(
+        trans
+            .get_edge_count(outbound_v.id, None, EdgeDirection::Outbound)
+            .unwrap(),
+        1
+    );
+
+    let mut batch = datastore.batch();
+    batch.delete_edge(&key);
+    batch.delete_vertex(inbound_v.id);
+    batch.commit().unwrap();
+    assert_eq!(
+        trans
+            .get_vertices(SpecificVertexQuery::single(inbound_v.id))
+            .unwrap()
+            .len(),
+        0
+    );
+    assert_eq!(
+        trans
+            .get_edge_count(outbound_v.id, None, EdgeDirection::Outbound)
+            .unwrap(),
+        0
+    );
+}
+
+#[test]
+fn should_roll_back_batch_to_savepoint() {
+    use super::RocksdbDatastore;
+    use models::{Type, Vertex};
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let mut batch = datastore.batch();
+    batch.create_vertex(&Vertex::new(Type::new("foo").unwrap()));
+    let outer = batch.savepoint();
+    batch.create_vertex(&Vertex::new(Type::new("foo").unwrap()));
+    let inner = batch.savepoint();
+    batch.create_vertex(&Vertex::new(Type::new("foo").unwrap()));
+    batch.rollback_to(outer).unwrap();
+    assert!(batch.rollback_to(inner).is_err());
+    assert!(batch.release(outer).is_err());
+    batch.commit().unwrap();
+
+    let trans = datastore.transaction().unwrap();
+    assert_eq!(trans.get_vertex_count().unwrap(), 1);
+}
+
+#[test]
+fn should_commit_with_options() {
+    use super::{CommitOptions, RocksdbDatastore};
+    use models::{Type, Vertex};
+    use traits::Transaction;
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let trans = datastore
+        .transaction_with_options(CommitOptions::new().sync(true))
+        .unwrap();
+    assert!(trans.create_vertex(&Vertex::new(Type::new("foo").unwrap())).unwrap());
+
+    let mut batch = datastore.batch();
+    batch.create_vertex(&Vertex::new(Type::new("foo").unwrap()));
+    batch
+        .commit_with_options(CommitOptions::new().disable_wal(true))
+        .unwrap();
+    assert_eq!(trans.get_vertex_count().unwrap(), 2);
+
+    let invalid_options = CommitOptions::new().sync(true).disable_wal(true);
+    assert!(datastore.transaction_with_options(invalid_options).is_err());
+}
+
+#[test]
+fn should_open_with_resource_options() {
+    use super::{ResourceOptions, RocksdbDatastore};
+    use models::{BulkInsertItem, Type, Vertex};
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let resources = ResourceOptions::new()
+        .rate_limit_bytes_per_sec(16 * 1024 * 1024)
+        .compaction_threads(1)
+        .flush_threads(1);
+    let datastore = RocksdbDatastore::new_with_resources(&generate_temporary_path(), Some(1), true, resources).unwrap();
+    let items = (0..100).map(|_| BulkInsertItem::Vertex(Vertex::new(Type::new("foo").unwrap())));
+    datastore.bulk_insert(items).unwrap();
+    datastore.compact().unwrap();
+    assert_eq!(datastore.transaction().unwrap().get_vertex_count().unwrap(), 100);
+
+    let invalid_resources = ResourceOptions::new().compaction_threads(-1);
+    assert!(RocksdbDatastore::new_with_resources(&generate_temporary_path(), None, false, invalid_resources).is_err());
+}
+
+#[test]
+fn should_delete_vertices_in_chunks() {
+    use super::RocksdbDatastore;
+    use models::{EdgeDirection, EdgeKey, SpecificVertexQuery, Type, Vertex, VertexQueryExt};
+    use serde_json::Value as JsonValue;
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let trans = datastore.transaction().unwrap();
+    let t = Type::new("foo").unwrap();
+    let hub_id = trans.create_vertex_from_type(t.clone()).unwrap();
+    let q = SpecificVertexQuery::single(hub_id);
+    trans
+        .set_vertex_properties(q.clone().property("bar"), &JsonValue::Bool(true))
+        .unwrap();
+
+    for _ in 0..10 {
+        let v = Vertex::new(t.clone());
+        trans.create_vertex(&v).unwrap();
+        trans.create_edge(&EdgeKey::new(hub_id, t.clone(), v.id)).unwrap();
+        trans.create_edge(&EdgeKey::new(v.id, t.clone(), hub_id)).unwrap();
+    }
+
+    assert!(trans.delete_vertices_in_chunks(q.clone(), 0).is_err());
+    trans.delete_vertices_in_chunks(q.clone(), 3).unwrap();
+    assert_eq!(trans.get_vertices(q.clone()).unwrap().len(), 0);
+    assert_eq!(trans.get_vertex_count().unwrap(), 10);
+    assert_eq!(trans.get_edge_count(hub_id, None, EdgeDirection::Outbound).unwrap(), 0);
+    assert_eq!(trans.get_edge_count(hub_id, None, EdgeDirection::Inbound).unwrap(), 0);
+    assert_eq!(trans.get_vertex_properties(q.property("bar")).unwrap().len(), 0);
+}
+
+#[test]
+fn should_delete_vertices_in_background() {
+    use super::RocksdbDatastore;
+    use models::{EdgeKey, RangeVertexQuery, SpecificEdgeQuery, Type};
+    use std::thread::sleep;
+    use std::time::Duration;
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let trans = datastore.transaction().unwrap();
+    let t = Type::new("foo").unwrap();
+    let outbound_id = trans.create_vertex_from_type(t.clone()).unwrap();
+    let inbound_id = trans.create_vertex_from_type(t.clone()).unwrap();
+    let key = EdgeKey::new(outbound_id, t, inbound_id);
+    trans.create_edge(&key).unwrap();
+
+    trans
+        .delete_vertices_in_background(RangeVertexQuery::new(u32::MAX))
+        .unwrap();
+    assert_eq!(trans.get_vertex_count().unwrap(), 0);
+
+    while datastore.pending_deletion_count() > 0 {
+        sleep(Duration::from_millis(10));
+    }
+
+    assert_eq!(trans.get_vertex_count().unwrap(), 0);
+    assert_eq!(trans.get_edges(SpecificEdgeQuery::single(key)).unwrap().len(), 0);
+}
+
+#[test]
+fn should_get_vertices_by_composite_index() {
+    use super::RocksdbDatastore;
+    use models::{PropertyIndex, SpecificVertexQuery, Type, VertexQueryExt};
+    use serde_json::Value as JsonValue;
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let trans = datastore.transaction().unwrap();
+    let index = PropertyIndex::new("location", vec!["country".to_string(), "city".to_string()]).unwrap();
+    trans.create_index(&index).unwrap();
+    assert!(trans.create_index(&index).is_err());
+
+    let t = Type::new("foo").unwrap();
+    let mut ids = Vec::new();
+
+    for &(country, city) in &[("US", "Seattle"), ("US", "Boston"), ("FR", "Paris"), ("US", "Austin")] {
+        let id = trans.create_vertex_from_type(t.clone()).unwrap();
+        let q = SpecificVertexQuery::single(id);
+        trans
+            .set_vertex_properties(q.clone().property("country"), &JsonValue::String(country.to_string()))
+            .unwrap();
+        trans
+            .set_vertex_properties(q.property("city"), &JsonValue::String(city.to_string()))
+            .unwrap();
+        ids.push(id);
+    }
+
+    // A vertex with only some of the indexed properties isn't indexed
+    let partial_id = trans.create_vertex_from_type(t).unwrap();
+    trans
+        .set_vertex_properties(
+            SpecificVertexQuery::single(partial_id).property("country"),
+            &JsonValue::String("US".to_string()),
+        )
+        .unwrap();
+
+    let us = [JsonValue::String("US".to_string())];
+    let result = trans.get_vertices_by_index("location", &us, &us, 10).unwrap();
+    let result_ids: Vec<_> = result.into_iter().map(|v| v.id).collect();
+    assert_eq!(result_ids, vec![ids[3], ids[1], ids[0]]);
+
+    let low = [JsonValue::String("US".to_string()), JsonValue::String("B".to_string())];
+    let result = trans.get_vertices_by_index("location", &low, &[], 10).unwrap();
+    let result_ids: Vec<_> = result.into_iter().map(|v| v.id).collect();
+    assert_eq!(result_ids, vec![ids[1], ids[0]]);
+
+    assert_eq!(trans.get_vertices_by_index("location", &[], &[], 2).unwrap().len(), 2);
+
+    // Changing or deleting a property updates the index
+    trans
+        .set_vertex_properties(
+            SpecificVertexQuery::single(ids[0]).property("country"),
+            &JsonValue::String("CA".to_string()),
+        )
+        .unwrap();
+    trans.delete_vertices(SpecificVertexQuery::single(ids[1])).unwrap();
+    let result = trans.get_vertices_by_index("location", &us, &us, 10).unwrap();
+    let result_ids: Vec<_> = result.into_iter().map(|v| v.id).collect();
+    assert_eq!(result_ids, vec![ids[3]]);
+
+    assert!(trans.get_vertices_by_index("missing", &[], &[], 10).is_err());
+}
+
+#[test]
+fn should_manage_indexes() {
+    use super::RocksdbDatastore;
+    use models::{PropertyIndex, SpecificVertexQuery, Type, VertexQueryExt};
+    use serde_json::Value as JsonValue;
+    use std::thread::sleep;
+    use std::time::Duration;
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let trans = datastore.transaction().unwrap();
+    let t = Type::new("foo").unwrap();
+    let mut ids = Vec::new();
+
+    for i in 0..3 {
+        let id = trans.create_vertex_from_type(t.clone()).unwrap();
+        trans
+            .set_vertex_properties(SpecificVertexQuery::single(id).property("age"), &JsonValue::from(i))
+            .unwrap();
+        ids.push(id);
+    }
+
+    // Existing vertices are indexed by the backfill
+    let index = PropertyIndex::new("age", vec!["age".to_string()]).unwrap();
+    trans.create_index(&index).unwrap();
+
+    let progress = loop {
+        let progress = trans.get_index_backfill_progress("age").unwrap();
+
+        if progress.done {
+            break progress;
+        }
+
+        sleep(Duration::from_millis(10));
+    };
+
+    assert_eq!(progress.indexed_vertex_count, 3);
+    assert_eq!(progress.total_vertex_count, Some(3));
+    assert_eq!(progress.error, None);
+    assert_eq!(trans.get_vertices_by_index("age", &[], &[], 10).unwrap().len(), 3);
+    assert_eq!(trans.list_indexes().unwrap(), vec![index]);
+
+    trans.drop_index("age").unwrap();
+    assert!(trans.drop_index("age").is_err());
+    assert!(trans.get_vertices_by_index("age", &[], &[], 10).is_err());
+    assert_eq!(trans.list_indexes().unwrap(), vec![]);
+    assert_eq!(trans.get_index_backfill_progress("age"), None);
+}
+
+#[test]
+fn should_scan_index_in_order() {
+    use super::RocksdbDatastore;
+    use models::{IndexOrder, PropertyIndex, SpecificVertexQuery, Type, VertexQueryExt};
+    use serde_json::Value as JsonValue;
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let trans = datastore.transaction().unwrap();
+    let index = PropertyIndex::new("score", vec!["score".to_string()]).unwrap();
+    trans.create_index(&index).unwrap();
+
+    let t = Type::new("user").unwrap();
+
+    for score in &["b", "d", "a", "c"] {
+        let id = trans.create_vertex_from_type(t.clone()).unwrap();
+        trans
+            .set_vertex_properties(
+                SpecificVertexQuery::single(id).property("score"),
+                &JsonValue::String(score.to_string()),
+            )
+            .unwrap();
+    }
+
+    let results = trans.scan_index("score", &[], &[], IndexOrder::Descending, 2).unwrap();
+    let values: Vec<JsonValue> = results.into_iter().map(|result| result.values[0].clone()).collect();
+    assert_eq!(
+        values,
+        vec![JsonValue::String("d".to_string()), JsonValue::String("c".to_string())]
+    );
+
+    let low = [JsonValue::String("b".to_string())];
+    let high = [JsonValue::String("c".to_string())];
+    let results = trans
+        .scan_index("score", &low, &high, IndexOrder::Descending, 10)
+        .unwrap();
+    let values: Vec<JsonValue> = results.into_iter().map(|result| result.values[0].clone()).collect();
+    assert_eq!(
+        values,
+        vec![JsonValue::String("c".to_string()), JsonValue::String("b".to_string())]
+    );
+
+    let results = trans
+        .scan_index("score", &low, &high, IndexOrder::Ascending, 10)
+        .unwrap();
+    assert_eq!(results[0].values, vec![JsonValue::String("b".to_string())]);
+}
+
+#[test]
+fn should_scan_numeric_index_ranges() {
+    use super::RocksdbDatastore;
+    use models::{IndexOrder, PropertyIndex, SpecificVertexQuery, Type, VertexQueryExt};
+    use serde_json::Value as JsonValue;
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let trans = datastore.transaction().unwrap();
+    let index = PropertyIndex::new("score", vec!["score".to_string()]).unwrap();
+    trans.create_index(&index).unwrap();
+
+    let t = Type::new("user").unwrap();
+    let scores = [250.0, -3.5, 100.0, 199.9, 0.0, 150.0, -100.0, 99.5, 1e12];
+
+    for &score in &scores {
+        let id = trans.create_vertex_from_type(t.clone()).unwrap();
+        trans
+            .set_vertex_properties(
+                SpecificVertexQuery::single(id).property("score"),
+                &JsonValue::from(score),
+            )
+            .unwrap();
+    }
+
+    // Non-numeric values sort separately from numbers
+    let id = trans.create_vertex_from_type(t).unwrap();
+    trans
+        .set_vertex_properties(
+            SpecificVertexQuery::single(id).property("score"),
+            &JsonValue::String("150".to_string()),
+        )
+        .unwrap();
+
+    let get_scores = |low: f64, high: f64| -> Vec<f64> {
+        let low = [JsonValue::from(low)];
+        let high = [JsonValue::from(high)];
+        trans
+            .scan_index("score", &low, &high, IndexOrder::Ascending, 100)
+            .unwrap()
+            .into_iter()
+            .map(|result| result.values[0].as_f64().unwrap())
+            .collect()
+    };
+
+    assert_eq!(get_scores(100.0, 199.99), vec![100.0, 150.0, 199.9]);
+    assert_eq!(get_scores(-100.0, 0.0), vec![-100.0, -3.5, 0.0]);
+    assert_eq!(get_scores(-1e15, 1e15).len(), scores.len());
+}
+
+#[test]
+fn should_scan_datetime_index_ranges() {
+    use super::RocksdbDatastore;
+    use chrono::{DateTime, Duration, Utc};
+    use models::{DateTimeValue, IndexOrder, PropertyIndex, SpecificVertexQuery, Type, VertexQueryExt};
+    use serde_json::Value as JsonValue;
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let trans = datastore.transaction().unwrap();
+    let index = PropertyIndex::new("occurred_at", vec!["occurred_at".to_string()]).unwrap();
+    trans.create_index(&index).unwrap();
+
+    let t = Type::new("event").unwrap();
+    let start: DateTime<Utc> = "1960-01-01T00:00:00Z".parse().unwrap();
+    let offsets = [
+        Duration::days(365 * 70),
+        Duration::nanoseconds(1),
+        Duration::days(1),
+        Duration::zero(),
+    ];
+
+    for &offset in &offsets {
+        let id = trans.create_vertex_from_type(t.clone()).unwrap();
+        trans
+            .set_vertex_properties(
+                SpecificVertexQuery::single(id).property("occurred_at"),
+                &JsonValue::from(DateTimeValue(start + offset)),
+            )
+            .unwrap();
+    }
+
+    let low = [JsonValue::from(DateTimeValue(start + Duration::nanoseconds(1)))];
+    let high = [JsonValue::from(DateTimeValue(start + Duration::days(2)))];
+    let results = trans
+        .scan_index("occurred_at", &low, &high, IndexOrder::Ascending, 10)
+        .unwrap();
+    let datetimes: Vec<DateTimeValue> = results
+        .into_iter()
+        .map(|result| DateTimeValue::from_json(&result.values[0]).unwrap())
+        .collect();
+    assert_eq!(
+        datetimes,
+        vec![
+            DateTimeValue(start + Duration::nanoseconds(1)),
+            DateTimeValue(start + Duration::days(1)),
+        ]
+    );
+
+    let results = trans
+        .scan_index("occurred_at", &[], &[], IndexOrder::Descending, 1)
+        .unwrap();
+    assert_eq!(
+        DateTimeValue::from_json(&results[0].values[0]),
+        Some(DateTimeValue(start + Duration::days(365 * 70)))
+    );
+}
+
+#[test]
+fn should_get_vertices_by_multi_valued_index() {
+    use super::RocksdbDatastore;
+    use models::{PropertyIndex, SpecificVertexQuery, Type, VertexQueryExt};
+    use serde_json::Value as JsonValue;
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let trans = datastore.transaction().unwrap();
+    let index = PropertyIndex::new("tags", vec!["tags".to_string()])
+        .unwrap()
+        .multi_valued(true);
+    trans.create_index(&index).unwrap();
+    assert_eq!(trans.list_indexes().unwrap(), vec![index]);
+
+    let t = Type::new("post").unwrap();
+    let id_1 = trans.create_vertex_from_type(t.clone()).unwrap();
+    let id_2 = trans.create_vertex_from_type(t).unwrap();
+    let q_1 = SpecificVertexQuery::single(id_1).property("tags");
+    let q_2 = SpecificVertexQuery::single(id_2).property("tags");
+    trans
+        .set_vertex_properties(q_1.clone(), &serde_json::from_str(r#"["a", "b", "b"]"#).unwrap())
+        .unwrap();
+    trans
+        .set_vertex_properties(q_2, &serde_json::from_str(r#"["b", "c"]"#).unwrap())
+        .unwrap();
+
+    let get_ids = |tag: &str| {
+        let tag = [JsonValue::String(tag.to_string())];
+        let mut ids: Vec<_> = trans
+            .get_vertices_by_index("tags", &tag, &tag, 10)
+            .unwrap()
+            .into_iter()
+            .map(|v| v.id)
+            .collect();
+        ids.sort();
+        ids
+    };
+
+    let mut both_ids = vec![id_1, id_2];
+    both_ids.sort();
+    assert_eq!(get_ids("a"), vec![id_1]);
+    assert_eq!(get_ids("b"), both_ids);
+    assert_eq!(get_ids("c"), vec![id_2]);
+
+    // A vertex is only returned once, even if multiple elements are in range
+    assert_eq!(trans.get_vertices_by_index("tags", &[], &[], 10).unwrap().len(), 2);
+
+    trans
+        .set_vertex_properties(q_1, &serde_json::from_str(r#"["c"]"#).unwrap())
+        .unwrap();
+    assert_eq!(get_ids("a"), vec![]);
+    assert_eq!(get_ids("b"), vec![id_2]);
+    assert_eq!(get_ids("c"), both_ids);
+}
+
+#[test]
+fn should_expire_properties() {
+    use super::RocksdbDatastore;
+    use chrono::{Duration, Utc};
+    use models::{EdgeKey, EdgeQueryExt, SpecificEdgeQuery, SpecificVertexQuery, Type, VertexQueryExt};
+    use serde_json::Value as JsonValue;
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let trans = datastore.transaction().unwrap();
+    let t = Type::new("user").unwrap();
+    let outbound_id = trans.create_vertex_from_type(t.clone()).unwrap();
+    let inbound_id = trans.create_vertex_from_type(t.clone()).unwrap();
+    let key = EdgeKey::new(outbound_id, t, inbound_id);
+    trans.create_edge(&key).unwrap();
+
+    let vertex_q = SpecificVertexQuery::single(outbound_id);
+    let edge_q = SpecificEdgeQuery::single(key);
+    let value = JsonValue::String("token".to_string());
+    let past = Utc::now() - Duration::seconds(1);
+    let future = Utc::now() + Duration::hours(1);
+
+    trans
+        .set_vertex_properties_with_expiry(vertex_q.clone().property("session"), &value, future)
+        .unwrap();
+    trans
+        .set_vertex_properties_with_expiry(vertex_q.clone().property("expired"), &value, past)
+        .unwrap();
+    trans
+        .set_edge_properties_with_expiry(edge_q.clone().property("expired"), &value, past)
+        .unwrap();
+
+    let properties = trans
+        .get_vertex_properties(vertex_q.clone().property("session"))
+        .unwrap();
+    assert_eq!(properties[0].value, value);
+    let raw_properties = trans
+        .get_vertex_properties_raw(vertex_q.clone().property("session"))
+        .unwrap();
+    assert_eq!(raw_properties[0].value, b"\"token\"".to_vec());
+    assert_eq!(
+        trans
+            .get_vertex_properties(vertex_q.clone().property("expired"))
+            .unwrap(),
+        vec![]
+    );
+    assert_eq!(
+        trans
+            .get_edge_properties(edge_q.clone().property("expired"))
+            .unwrap()
+            .len(),
+        0
+    );
+
+    let all_properties = trans.get_all_vertex_properties(vertex_q.clone()).unwrap();
+    assert_eq!(all_properties[0].props.len(), 1);
+    assert_eq!(trans.get_all_edge_properties(edge_q).unwrap()[0].props.len(), 0);
+
+    // Setting a property without an expiry clears the old one
+    trans
+        .set_vertex_properties(vertex_q.clone().property("expired"), &value)
+        .unwrap();
+    assert_eq!(
+        trans.get_vertex_properties(vertex_q.property("expired")).unwrap().len(),
+        1
+    );
+}
+
+#[test]
+fn should_page_through_edges() {
+    use super::RocksdbDatastore;
+    use models::{EdgeDirection, EdgeKey, Type};
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let trans = datastore.transaction().unwrap();
+    let vertex_t = Type::new("user").unwrap();
+    let edge_t = Type::new("follows").unwrap();
+    let outbound_id = trans.create_vertex_from_type(vertex_t.clone()).unwrap();
+
+    for _ in 0..5 {
+        let inbound_id = trans.create_vertex_from_type(vertex_t.clone()).unwrap();
+        trans
+            .create_edge(&EdgeKey::new(outbound_id, edge_t.clone(), inbound_id))
+            .unwrap();
+    }
+
+    let mut edges = Vec::new();
+    let mut after = None;
+
+    loop {
+        let page = trans
+            .get_edge_page(outbound_id, &edge_t, EdgeDirection::Outbound, after, 2)
+            .unwrap();
+
+        if page.is_empty() {
+            break;
+        }
+
+        let last = page.last().unwrap();
+        after = Some((last.created_datetime, last.key.inbound_id));
+        edges.extend(page);
+    }
+
+    assert_eq!(edges.len(), 5);
+
+    for window in edges.windows(2) {
+        assert!(
+            window[0].created_datetime > window[1].created_datetime
+                || (window[0].created_datetime == window[1].created_datetime
+                    && window[0].key.inbound_id < window[1].key.inbound_id)
+        );
+    }
+
+    let inbound_page = trans
+        .get_edge_page(edges[0].key.inbound_id, &edge_t, EdgeDirection::Inbound, None, 10)
+        .unwrap();
+    assert_eq!(inbound_page.len(), 1);
+    assert_eq!(inbound_page[0].key.outbound_id, outbound_id);
+}
+
+#[test]
+fn should_get_edge_properties_by_name() {
+    use super::RocksdbDatastore;
+    use models::{EdgeKey, EdgeQueryExt, SpecificEdgeQuery, Type};
+    use serde_json::Value as JsonValue;
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let trans = datastore.transaction().unwrap();
+    let t = Type::new("foo").unwrap();
+    let outbound_id = trans.create_vertex_from_type(t.clone()).unwrap();
+    let mut keys = Vec::new();
+
+    for i in 0..5 {
+        let inbound_id = trans.create_vertex_from_type(t.clone()).unwrap();
+        let key = EdgeKey::new(outbound_id, t.clone(), inbound_id);
+        trans.create_edge(&key).unwrap();
+
+        // Only some of the edges have a weight
+        let name = if i % 2 == 0 { "weight" } else { "color" };
+        let q = SpecificEdgeQuery::single(key.clone()).property(name);
+        trans.set_edge_properties(q, &JsonValue::from(i)).unwrap();
+        keys.push(key);
+    }
+
+    let first_page = trans.get_edge_properties_by_name("weight", None, 2).unwrap();
+    assert_eq!(first_page.len(), 2);
+    let after = first_page[1].key.clone();
+    let second_page = trans.get_edge_properties_by_name("weight", Some(&after), 2).unwrap();
+    assert_eq!(second_page.len(), 1);
+
+    let mut weighted_keys: Vec<EdgeKey> = first_page.into_iter().chain(second_page).map(|p| p.key).collect();
+    let mut expected_keys = vec![keys[0].clone(), keys[2].clone(), keys[4].clone()];
+    expected_keys.sort();
+    assert_eq!(weighted_keys, expected_keys);
+
+    trans.delete_edges(SpecificEdgeQuery::single(keys[2].clone())).unwrap();
+    weighted_keys.retain(|key| key != &keys[2]);
+    let properties = trans.get_edge_properties_by_name("weight", None, 10).unwrap();
+    assert_eq!(
+        properties.into_iter().map(|p| p.key).collect::<Vec<EdgeKey>>(),
+        weighted_keys
+    );
+}
+
+#[test]
+fn should_get_edges_by_type() {
+    use super::RocksdbDatastore;
+    use chrono::{Duration, Utc};
+    use models::{EdgeKey, SpecificVertexQuery, Type};
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let trans = datastore.transaction().unwrap();
+    let purchase_t = Type::new("purchase").unwrap();
+    let view_t = Type::new("view").unwrap();
+    let outbound_id = trans.create_vertex_from_type(purchase_t.clone()).unwrap();
+    let now = Utc::now();
+    let mut keys = Vec::new();
+
+    for i in 0..3 {
+        let inbound_id = trans.create_vertex_from_type(purchase_t.clone()).unwrap();
+        let key = EdgeKey::new(outbound_id, purchase_t.clone(), inbound_id);
+        trans.create_edge_with_datetime(&key, now - Duration::hours(i)).unwrap();
+        trans
+            .create_edge(&EdgeKey::new(outbound_id, view_t.clone(), inbound_id))
+            .unwrap();
+        keys.push(key);
+    }
+
+    // Edges are returned newest first
+    let edges = trans.get_edges_by_type(&purchase_t, None, None, 10).unwrap();
+    let edge_keys: Vec<EdgeKey> = edges.into_iter().map(|edge| edge.key).collect();
+    assert_eq!(edge_keys, keys);
+
+    let edges = trans
+        .get_edges_by_type(&purchase_t, Some(now - Duration::minutes(90)), None, 10)
+        .unwrap();
+    assert_eq!(edges.len(), 2);
+    let edges = trans
+        .get_edges_by_type(&purchase_t, None, Some(now - Duration::minutes(30)), 1)
+        .unwrap();
+    assert_eq!(edges.len(), 1);
+    assert_eq!(edges[0].key, keys[1]);
+
+    // Updating an edge moves it in the index
+    trans.create_edge(&keys[2]).unwrap();
+    let edges = trans.get_edges_by_type(&purchase_t, None, None, 10).unwrap();
+    assert_eq!(edges.len(), 3);
+    assert_eq!(edges[0].key, keys[2]);
+
+    // So does updating an edge that was created earlier in the same batch
+    let mut batch = datastore.batch();
+    batch.create_edge_with_datetime(&keys[1], now + Duration::hours(1));
+    batch.create_edge_with_datetime(&keys[1], now + Duration::hours(2));
+    batch.commit().unwrap();
+    let edges = trans.get_edges_by_type(&purchase_t, None, None, 10).unwrap();
+    assert_eq!(edges.len(), 3);
+    assert_eq!(edges[0].key, keys[1]);
+
+    trans
+        .delete_vertices(SpecificVertexQuery::single(keys[0].inbound_id))
+        .unwrap();
+    assert_eq!(trans.get_edges_by_type(&purchase_t, None, None, 10).unwrap().len(), 2);
+}
+
+#[test]
+fn should_get_newest_edges() {
+    use super::RocksdbDatastore;
+    use chrono::{Duration, Utc};
+    use models::{EdgeKey, SpecificEdgeQuery, Type};
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let t = Type::new("foo").unwrap();
+    let now = Utc::now();
+
+    // The log is disabled by default
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let trans = datastore.transaction().unwrap();
+    let outbound_id = trans.create_vertex_from_type(t.clone()).unwrap();
+    let inbound_id = trans.create_vertex_from_type(t.clone()).unwrap();
+    trans
+        .create_edge(&EdgeKey::new(outbound_id, t.clone(), inbound_id))
+        .unwrap();
+    assert_eq!(trans.get_newest_edges(None, 10).unwrap().len(), 0);
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false)
+        .unwrap()
+        .with_edge_log(true);
+    let trans = datastore.transaction().unwrap();
+    let outbound_id = trans.create_vertex_from_type(t.clone()).unwrap();
+    let mut keys = Vec::new();
+
+    for i in 0..3 {
+        let inbound_id = trans.create_vertex_from_type(t.clone()).unwrap();
+        let key = EdgeKey::new(outbound_id, t.clone(), inbound_id);
+        trans.create_edge_with_datetime(&key, now - Duration::hours(i)).unwrap();
+        keys.push(key);
+    }
+
+    let edges = trans.get_newest_edges(None, 2).unwrap();
+    let edge_keys: Vec<EdgeKey> = edges.into_iter().map(|edge| edge.key).collect();
+    assert_eq!(edge_keys, vec![keys[0].clone(), keys[1].clone()]);
+    let edges = trans.get_newest_edges(Some(now - Duration::minutes(90)), 10).unwrap();
+    assert_eq!(edges.len(), 1);
+    assert_eq!(edges[0].key, keys[2]);
+
+    // Updating an edge moves it to the front of the log, and deleting one
+    // removes it
+    let mut batch = datastore.batch();
+    batch.create_edge(&keys[2]);
+    batch.commit().unwrap();
+    trans.delete_edges(SpecificEdgeQuery::single(keys[0].clone())).unwrap();
+    let edges = trans.get_newest_edges(None, 10).unwrap();
+    let edge_keys: Vec<EdgeKey> = edges.into_iter().map(|edge| edge.key).collect();
+    assert_eq!(edge_keys, vec![keys[2].clone(), keys[1].clone()]);
+}
+
+#[test]
+fn should_get_inbound_neighbor_sketches() {
+    use super::RocksdbDatastore;
+    use chrono::offset::TimeZone;
+    use chrono::{Duration, Utc};
+    use models::{EdgeKey, SpecificVertexQuery, Type};
+    use std::time::Duration as StdDuration;
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let t = Type::new("person").unwrap();
+    let follows_t = Type::new("follows").unwrap();
+    let likes_t = Type::new("likes").unwrap();
+    // Half past an hour, so that the sketches' windows start on the hour
+    let now = Utc.timestamp_opt(1_577_881_800, 0).unwrap();
+
+    // The sketches are disabled by default
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let trans = datastore.transaction().unwrap();
+    let outbound_id = trans.create_vertex_from_type(t.clone()).unwrap();
+    let inbound_id = trans.create_vertex_from_type(t.clone()).unwrap();
+    trans
+        .create_edge(&EdgeKey::new(outbound_id, follows_t.clone(), inbound_id))
+        .unwrap();
+    let sketch = trans.get_inbound_neighbor_sketch(inbound_id, None, None, None).unwrap();
+    assert_eq!(sketch.estimate(), 0);
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false)
+        .unwrap()
+        .with_neighbor_sketches(Some(StdDuration::from_secs(3600)));
+    let trans = datastore.transaction().unwrap();
+    let inbound_id = trans.create_vertex_from_type(t.clone()).unwrap();
+    let mut outbound_ids = Vec::new();
+
+    for _ in 0..6 {
+        outbound_ids.push(trans.create_vertex_from_type(t.clone()).unwrap());
+    }
+
+    for &outbound_id in &outbound_ids[..5] {
+        let key = EdgeKey::new(outbound_id, follows_t.clone(), inbound_id);
+        trans.create_edge_with_datetime(&key, now).unwrap();
+    }
+
+    let mut batch = datastore.batch();
+
+    for &outbound_id in &outbound_ids[2..] {
+        let key = EdgeKey::new(outbound_id, likes_t.clone(), inbound_id);
+        batch.create_edge_with_datetime(&key, now - Duration::hours(2));
+    }
+
+    batch.commit().unwrap();
+
+    // Estimates of small counts are nearly exact, but can be off by one if
+    // two neighbors land in the same register
+    let estimate = |t: Option<&Type>, low, high| {
+        trans
+            .get_inbound_neighbor_sketch(inbound_id, t, low, high)
+            .unwrap()
+            .estimate() as i64
+    };
+    let assert_about = |estimate: i64, expected: i64| assert!((estimate - expected).abs() <= 1);
+    assert_about(estimate(None, None, None), 6);
+    assert_about(estimate(Some(&follows_t), None, None), 5);
+    assert_about(estimate(Some(&likes_t), None, None), 4);
+    assert_about(estimate(None, Some(now - Duration::minutes(10)), None), 5);
+    assert_about(estimate(None, None, Some(now - Duration::minutes(90))), 4);
+    assert_eq!(estimate(None, Some(now + Duration::hours(1)), None), 0);
+
+    trans.delete_vertices(SpecificVertexQuery::single(inbound_id)).unwrap();
+    assert_eq!(estimate(None, None, None), 0);
+}
+
+#[test]
+fn should_get_adjacency_bitmaps() {
+    use super::RocksdbDatastore;
+    use models::{EdgeDirection, EdgeKey, SpecificEdgeQuery, SpecificVertexQuery, Type};
+    use std::collections::HashSet;
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+    use uuid::Uuid;
+
+    let t = Type::new("person").unwrap();
+    let follows_t = Type::new("follows").unwrap();
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false)
+        .unwrap()
+        .with_adjacency_bitmaps(Some(3));
+    let trans = datastore.transaction().unwrap();
+    let first_id = trans.create_vertex_from_type(t.clone()).unwrap();
+    let second_id = trans.create_vertex_from_type(t.clone()).unwrap();
+    let mut follower_ids = Vec::new();
+
+    for _ in 0..5 {
+        follower_ids.push(trans.create_vertex_from_type(t.clone()).unwrap());
+    }
+
+    let follow = |outbound_id: Uuid, inbound_id: Uuid| {
+        let key = EdgeKey::new(outbound_id, follows_t.clone(), inbound_id);
+        assert!(trans.create_edge(&key).unwrap());
+    };
+    let get_bitmap = |id: Uuid| {
+        trans
+            .get_adjacency_bitmap(id, &follows_t, EdgeDirection::Inbound)
+            .unwrap()
+    };
+    let get_followers = |id: Uuid| -> Option<HashSet<Uuid>> {
+        get_bitmap(id).map(|bitmap| trans.resolve_neighbor_ids(&bitmap).unwrap().into_iter().collect())
+    };
+
+    // Bitmaps are only kept once a vertex has enough edges, and are then
+    // seeded from all of them
+    follow(follower_ids[0], first_id);
+    follow(follower_ids[1], first_id);
+    assert_eq!(get_followers(first_id), None);
+    follow(follower_ids[2], first_id);
+    follow(follower_ids[3], first_id);
+    assert_eq!(
+        get_followers(first_id),
+        Some(follower_ids[..4].iter().cloned().collect())
+    );
+
+    for &follower_id in &follower_ids[2..] {
+        follow(follower_id, second_id);
+    }
+
+    let mut mutual_ids = trans
+        .resolve_neighbor_ids(&(get_bitmap(first_id).unwrap() & get_bitmap(second_id).unwrap()))
+        .unwrap();
+    mutual_ids.sort();
+    let mut expected_mutual_ids = follower_ids[2..4].to_vec();
+    expected_mutual_ids.sort();
+    assert_eq!(mutual_ids, expected_mutual_ids);
+
+    // Common neighbors are read from the bitmaps when both vertices have
+    // them, in the same order as when they're scanned
+    let common_ids = trans
+        .get_common_neighbors(first_id, second_id, &follows_t, EdgeDirection::Inbound, 10)
+        .unwrap();
+    assert_eq!(common_ids, expected_mutual_ids);
+    let common_ids = trans
+        .get_common_neighbors(first_id, second_id, &follows_t, EdgeDirection::Inbound, 1)
+        .unwrap();
+    assert_eq!(common_ids, expected_mutual_ids[..1].to_vec());
+
+    // The followers don't have enough edges, and the vertices they follow
+    // don't have outbound edges
+    let bitmap = trans
+        .get_adjacency_bitmap(follower_ids[2], &follows_t, EdgeDirection::Outbound)
+        .unwrap();
+    assert_eq!(bitmap, None);
+    let bitmap = trans
+        .get_adjacency_bitmap(first_id, &follows_t, EdgeDirection::Both)
+        .unwrap();
+    assert_eq!(bitmap, None);
+
+    trans
+        .delete_edges(SpecificEdgeQuery::single(EdgeKey::new(
+            follower_ids[2],
+            follows_t.clone(),
+            first_id,
+        )))
+        .unwrap();
+    trans
+        .delete_vertices(SpecificVertexQuery::single(follower_ids[3]))
+        .unwrap();
+    assert_eq!(
+        get_followers(first_id),
+        Some(follower_ids[..2].iter().cloned().collect())
+    );
+    assert_eq!(
+        get_followers(second_id),
+        Some(vec![follower_ids[2], follower_ids[4]].into_iter().collect())
+    );
+
+    trans.delete_vertices(SpecificVertexQuery::single(first_id)).unwrap();
+    assert_eq!(get_followers(first_id), None);
+}
+
+#[test]
+fn should_get_type_stats() {
+    use super::RocksdbDatastore;
+    use models::{EdgeKey, SpecificVertexQuery, Type};
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let trans = datastore.transaction().unwrap();
+    let person_t = Type::new("person").unwrap();
+    let follows_t = Type::new("follows").unwrap();
+    let mut ids = Vec::new();
+
+    for _ in 0..3 {
+        ids.push(trans.create_vertex_from_type(person_t.clone()).unwrap());
+    }
+
+    trans
+        .create_edge(&EdgeKey::new(ids[0], follows_t.clone(), ids[1]))
+        .unwrap();
+    trans
+        .create_edge(&EdgeKey::new(ids[1], follows_t.clone(), ids[2]))
+        .unwrap();
+    trans
+        .create_edge(&EdgeKey::new(ids[1], follows_t.clone(), ids[2]))
+        .unwrap();
+
+    let stats = trans.get_type_stats().unwrap();
+    assert_eq!(stats.vertex_counts.get(&person_t), Some(&3));
+    assert_eq!(stats.edge_counts.get(&follows_t), Some(&2));
+
+    trans.delete_vertices(SpecificVertexQuery::single(ids[1])).unwrap();
+    let stats = trans.get_type_stats().unwrap();
+    assert_eq!(stats.vertex_counts.get(&person_t), Some(&2));
+    assert_eq!(stats.edge_counts.get(&follows_t), None);
+
+    datastore.recount_type_stats().unwrap();
+    assert_eq!(trans.get_type_stats().unwrap(), stats);
+}
+
+#[test]
+fn should_scan_all_properties() {
+    use super::RocksdbDatastore;
+    use models::{EdgeKey, EdgeQueryExt, SpecificEdgeQuery, SpecificVertexQuery, Type, VertexQueryExt};
+    use serde_json::Value as JsonValue;
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let trans = datastore.transaction().unwrap();
+    let t = Type::new("foo").unwrap();
+    let mut ids = Vec::new();
+
+    for i in 0..3 {
+        let id = trans.create_vertex_from_type(t.clone()).unwrap();
+        let q = SpecificVertexQuery::single(id);
+        trans
+            .set_vertex_properties(q.clone().property("a"), &JsonValue::from(i))
+            .unwrap();
+        trans
+            .set_vertex_properties(q.property("b"), &JsonValue::from(i))
+            .unwrap();
+        ids.push(id);
+    }
+
+    ids.sort();
+    let first_page = trans.scan_vertex_properties(None, 4).unwrap();
+    assert_eq!(first_page.len(), 4);
+    let last = &first_page[3];
+    assert_eq!((last.id, last.name.as_str()), (ids[1], "b"));
+    let second_page = trans.scan_vertex_properties(Some((last.id, &last.name)), 4).unwrap();
+    assert_eq!(second_page.len(), 2);
+    assert_eq!(second_page[0].id, ids[2]);
+
+    // Properties of deleted vertices are skipped
+    trans.delete_vertices(SpecificVertexQuery::single(ids[0])).unwrap();
+    assert_eq!(trans.scan_vertex_properties(None, 10).unwrap().len(), 4);
+
+    let key = EdgeKey::new(ids[1], t.clone(), ids[2]);
+    trans.create_edge(&key).unwrap();
+    let q = SpecificEdgeQuery::single(key.clone());
+    trans
+        .set_edge_properties(q.clone().property("a"), &JsonValue::Null)
+        .unwrap();
+    trans.set_edge_properties(q.property("b"), &JsonValue::Null).unwrap();
+    let first_page = trans.scan_edge_properties(None, 1).unwrap();
+    assert_eq!(first_page.len(), 1);
+    assert_eq!((&first_page[0].key, first_page[0].name.as_str()), (&key, "a"));
+    let second_page = trans.scan_edge_properties(Some((&key, "a")), 10).unwrap();
+    assert_eq!(second_page.len(), 1);
+    assert_eq!(second_page[0].name, "b");
+}
+
+#[test]
+fn should_return_typed_errors() {
+    use super::RocksdbDatastore;
+    use errors::ErrorKind;
+    use models::{PropertyIndex, SpecificVertexQuery};
+    use traits::Datastore;
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let trans = datastore.transaction().unwrap();
+    let index = PropertyIndex::new("age", vec!["age".to_string()]).unwrap();
+    trans.create_index(&index).unwrap();
+
+    match trans.create_index(&index).unwrap_err().kind() {
+        ErrorKind::Conflict(_) => (),
+        kind => panic!("Unexpected error kind: {:?}", kind),
+    }
+
+    match trans.drop_index("missing").unwrap_err().kind() {
+        ErrorKind::NotFound(_) => (),
+        kind => panic!("Unexpected error kind: {:?}", kind),
+    }
+
+    let q = SpecificVertexQuery::single(Default::default());
+
+    match trans.delete_vertices_in_chunks(q, 0).unwrap_err().kind() {
+        ErrorKind::Validation(_) => (),
+        kind => panic!("Unexpected error kind: {:?}", kind),
+    }
+}
+
+#[test]
+fn should_create_missing_column_families() {
+    use super::RocksdbDatastore;
+    use models::{PropertyIndex, SpecificVertexQuery, Type, VertexQueryExt};
+    use rocksdb::{Options, DB};
+    use serde_json::Value as JsonValue;
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    // Simulate a database created before indexes were added
+    let path = generate_temporary_path();
+
+    {
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        DB::open_cf(&opts, &path, ["vertices:v1", "edges:v1", "vertex_properties:v1"]).unwrap();
+    }
+
+    let datastore = RocksdbDatastore::new(&path, Some(1), false).unwrap();
+    let trans = datastore.transaction().unwrap();
+    trans
+        .create_index(&PropertyIndex::new("age", vec!["age".to_string()]).unwrap())
+        .unwrap();
+    let id = trans.create_vertex_from_type(Type::new("foo").unwrap()).unwrap();
+    trans
+        .set_vertex_properties(SpecificVertexQuery::single(id).property("age"), &JsonValue::from(30))
+        .unwrap();
+    let vertices = trans
+        .get_vertices_by_index("age", &[JsonValue::from(30)], &[JsonValue::from(30)], 10)
+        .unwrap();
+    assert_eq!(vertices.len(), 1);
+}
+
+#[test]
+fn should_open_read_only() {
+    use super::RocksdbDatastore;
+    use errors::ErrorKind;
+    use models::{SpecificVertexQuery, Type, Vertex};
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let path = generate_temporary_path();
+    let datastore = RocksdbDatastore::new(&path, Some(1), false).unwrap();
+    let trans = datastore.transaction().unwrap();
+    let t = Type::new("foo").unwrap();
+    let id = trans.create_vertex_from_type(t.clone()).unwrap();
+
+    // The database can be read while the primary still has it open
+    let read_only_datastore = RocksdbDatastore::new_read_only(&path, Some(1)).unwrap();
+    let read_only_trans = read_only_datastore.transaction().unwrap();
+    assert_eq!(read_only_trans.get_vertex_count().unwrap(), 1);
+    assert_eq!(
+        read_only_trans
+            .get_vertices(SpecificVertexQuery::single(id))
+            .unwrap()
+            .len(),
+        1
+    );
+
+    match read_only_trans.create_vertex(&Vertex::new(t)).unwrap_err().kind() {
+        ErrorKind::Validation(_) => (),
+        kind => panic!("Unexpected error kind: {:?}", kind),
+    }
+
+    assert!(read_only_datastore.get_background_task_metrics().is_empty());
+}
+
+#[test]
+fn should_open_as_secondary() {
+    use super::RocksdbDatastore;
+    use errors::ErrorKind;
+    use models::{SpecificVertexQuery, Type, Vertex};
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let path = generate_temporary_path();
+    let datastore = RocksdbDatastore::new(&path, Some(1), false).unwrap();
+    let trans = datastore.transaction().unwrap();
+    let t = Type::new("foo").unwrap();
+    trans.create_vertex_from_type(t.clone()).unwrap();
+
+    let secondary_datastore = RocksdbDatastore::new_secondary(&path, &generate_temporary_path()).unwrap();
+    assert_eq!(
+        secondary_datastore.transaction().unwrap().get_vertex_count().unwrap(),
+        1
+    );
+    assert!(secondary_datastore
+        .get_background_task_metrics()
+        .contains_key("catch_up"));
+
+    // Writes the primary makes afterwards are seen once caught up
+    let id = trans.create_vertex_from_type(t.clone()).unwrap();
+    secondary_datastore.catch_up_with_primary().unwrap();
+    let secondary_trans = secondary_datastore.transaction().unwrap();
+    assert_eq!(secondary_trans.get_vertex_count().unwrap(), 2);
+    assert_eq!(
+        secondary_trans
+            .get_vertices(SpecificVertexQuery::single(id))
+            .unwrap()
+            .len(),
+        1
+    );
+
+    match secondary_trans.create_vertex(&Vertex::new(t)).unwrap_err().kind() {
+        ErrorKind::Validation(_) => (),
+        kind => panic!("Unexpected error kind: {:?}", kind),
+    }
+}
+
+#[test]
+fn should_read_from_secondaries_at_least_as_of() {
+    use super::RocksdbDatastore;
+    use chrono::Utc;
+    use models::{SpecificVertexQuery, Type};
+    use replicated::ReplicatedDatastore;
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let path = generate_temporary_path();
+    let primary = RocksdbDatastore::new(&path, Some(1), false).unwrap();
+    let secondary = RocksdbDatastore::new_secondary(&path, &generate_temporary_path()).unwrap();
+    let datastore = ReplicatedDatastore::new(primary, vec![secondary]);
+    let id = datastore
+        .transaction()
+        .unwrap()
+        .create_vertex_from_type(Type::new("foo").unwrap())
+        .unwrap();
+
+    // Catches the secondary up, rather than waiting for its background task
+    assert_eq!(datastore.get_caught_up_datetime(), None);
+    let trans = datastore.read_at_least(Utc::now()).unwrap();
+    assert_eq!(trans.get_vertices(SpecificVertexQuery::single(id)).unwrap().len(), 1);
+    assert!(datastore.get_caught_up_datetime().is_some());
+}
+
+#[test]
+fn should_wait_for_a_locked_datastore() {
+    use super::RocksdbDatastore;
+    use errors::ErrorKind;
+    use std::thread;
+    use std::time::Duration;
+    use util::generate_temporary_path;
+
+    let path = generate_temporary_path();
+    let datastore = RocksdbDatastore::new(&path, Some(1), false).unwrap();
+
+    match RocksdbDatastore::new(&path, Some(1), false).unwrap_err().kind() {
+        ErrorKind::Locked(_) => (),
+        kind => panic!("Unexpected error kind: {:?}", kind),
+    }
+
+    assert!(RocksdbDatastore::new_with_lock_timeout(&path, Some(1), false, Duration::from_millis(200)).is_err());
+
+    let handle = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(200));
+        drop(datastore);
+    });
+
+    RocksdbDatastore::new_with_lock_timeout(&path, Some(1), false, Duration::from_secs(10)).unwrap();
+    handle.join().unwrap();
+}
+
+#[test]
+fn should_publish_events_to_subscribers() {
+    use super::RocksdbDatastore;
+    use models::{EdgeKey, Event, EventFilter, EventKind, SpecificEdgeQuery, Type};
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let events = datastore.subscribe(EventFilter::new().kind(EventKind::Edge)).unwrap();
+    let trans = datastore.transaction().unwrap();
+    let t = Type::new("foo").unwrap();
+    let outbound_id = trans.create_vertex_from_type(t.clone()).unwrap();
+    let inbound_id = trans.create_vertex_from_type(t.clone()).unwrap();
+    let key = EdgeKey::new(outbound_id, t, inbound_id);
+    trans.create_edge(&key).unwrap();
+    trans.delete_edges(SpecificEdgeQuery::single(key.clone())).unwrap();
+
+    let events: Vec<Event> = events.try_iter().collect();
+    assert_eq!(events.len(), 2);
+
+    match (&events[0], &events[1]) {
+        (Event::EdgeCreated(created_key), Event::EdgeDeleted(deleted_key)) => {
+            assert_eq!(created_key, &key);
+            assert_eq!(deleted_key, &key);
+        }
+        events => panic!("Unexpected events: {:?}", events),
+    }
+}
+
+#[test]
+fn should_compact() {
+    use super::RocksdbDatastore;
+    use models::{SpecificVertexQuery, Type};
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let trans = datastore.transaction().unwrap();
+    let kept_id = trans.create_vertex_from_type(Type::new("foo").unwrap()).unwrap();
+    let deleted_id = trans.create_vertex_from_type(Type::new("foo").unwrap()).unwrap();
+    trans.delete_vertices(SpecificVertexQuery::single(deleted_id)).unwrap();
+    datastore.compact().unwrap();
+    let trans = datastore.transaction().unwrap();
+    assert_eq!(trans.get_vertex_count().unwrap(), 1);
+    assert_eq!(
+        trans.get_vertices(SpecificVertexQuery::single(kept_id)).unwrap().len(),
+        1
+    );
+}
+
+#[test]
+fn should_clone_to() {
+    use super::RocksdbDatastore;
+    use models::{EdgeKey, EdgeQueryExt, SpecificEdgeQuery, SpecificVertexQuery, Type, VertexQueryExt};
+    use serde_json::Value as JsonValue;
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let trans = datastore.transaction().unwrap();
+    let t = Type::new("foo").unwrap();
+    let outbound_id = trans.create_vertex_from_type(t.clone()).unwrap();
+    let inbound_id = trans.create_vertex_from_type(t.clone()).unwrap();
+    let deleted_id = trans.create_vertex_from_type(t.clone()).unwrap();
+    let key = EdgeKey::new(outbound_id, t, inbound_id);
+    trans.create_edge(&key).unwrap();
+    trans
+        .set_vertex_properties(
+            SpecificVertexQuery::single(outbound_id).property("a"),
+            &JsonValue::from(1),
+        )
+        .unwrap();
+    trans
+        .set_edge_properties(
+            SpecificEdgeQuery::single(key.clone()).property("b"),
+            &JsonValue::from(2),
+        )
+        .unwrap();
+    trans.delete_vertices(SpecificVertexQuery::single(deleted_id)).unwrap();
+
+    let path = generate_temporary_path();
+    datastore.clone_to(&path).unwrap();
+    assert!(datastore.clone_to(&path).is_err());
+
+    let cloned = RocksdbDatastore::new(&path, Some(1), false).unwrap();
+    let cloned_trans = cloned.transaction().unwrap();
+    assert_eq!(cloned_trans.get_vertex_count().unwrap(), 2);
+    assert_eq!(
+        cloned_trans
+            .get_edges(SpecificVertexQuery::single(outbound_id).outbound(10))
+            .unwrap()
+            .len(),
+        1
+    );
+    let properties = cloned_trans
+        .get_vertex_properties(SpecificVertexQuery::single(outbound_id).property("a"))
+        .unwrap();
+    assert_eq!(properties[0].value, JsonValue::from(1));
+    let properties = cloned_trans
+        .get_edge_properties(SpecificEdgeQuery::single(key).property("b"))
+        .unwrap();
+    assert_eq!(properties[0].value, JsonValue::from(2));
+}
+
+#[test]
+fn should_read_tagged_snapshots() {
+    use super::RocksdbDatastore;
+    use errors::ErrorKind;
+    use models::{SpecificVertexQuery, Type, Vertex};
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let trans = datastore.transaction().unwrap();
+    let t = Type::new("foo").unwrap();
+    let id = trans.create_vertex_from_type(t.clone()).unwrap();
+    datastore.create_tag("release-2024-05").unwrap();
+    trans.delete_vertices(SpecificVertexQuery::single(id)).unwrap();
+    trans.create_vertex_from_type(t.clone()).unwrap();
+
+    let tags = datastore.list_tags().unwrap();
+    assert_eq!(tags.len(), 1);
+    assert_eq!(tags[0].name, "release-2024-05");
+
+    // The tagged transaction sees the datastore as it was when tagged
+    let tagged_trans = datastore.tagged_transaction("release-2024-05").unwrap();
+    assert_eq!(tagged_trans.get_vertex_count().unwrap(), 1);
+    assert_eq!(
+        tagged_trans
+            .get_vertices(SpecificVertexQuery::single(id))
+            .unwrap()
+            .len(),
+        1
+    );
+
+    match tagged_trans.create_vertex(&Vertex::new(t)).unwrap_err().kind() {
+        ErrorKind::Validation(_) => (),
+        kind => panic!("Unexpected error kind: {:?}", kind),
+    }
+
+    match datastore.create_tag("release-2024-05").unwrap_err().kind() {
+        ErrorKind::Conflict(_) => (),
+        kind => panic!("Unexpected error kind: {:?}", kind),
+    }
+
+    assert!(datastore.create_tag("../escape").is_err());
+
+    drop(tagged_trans);
+    datastore.delete_tag("release-2024-05").unwrap();
+    assert!(datastore.list_tags().unwrap().is_empty());
+
+    match datastore.tagged_transaction("release-2024-05").unwrap_err().kind() {
+        ErrorKind::NotFound(_) => (),
+        kind => panic!("Unexpected error kind: {:?}", kind),
+    }
+}
+
+#[test]
+fn should_record_idempotency_keys() {
+    use super::RocksdbDatastore;
+    use chrono::{Duration, Utc};
+    use traits::Datastore;
+    use util::generate_temporary_path;
+
+    let path = generate_temporary_path();
+
+    {
+        let datastore = RocksdbDatastore::new(&path, Some(1), false).unwrap();
+        assert_eq!(datastore.get_idempotency_record("a").unwrap(), None);
+        datastore
+            .set_idempotency_record("a", b"first", Utc::now() + Duration::hours(1))
+            .unwrap();
+        datastore
+            .set_idempotency_record("b", b"second", Utc::now() - Duration::seconds(1))
+            .unwrap();
+        assert!(datastore
+            .set_idempotency_record("c", b"", Utc::now() + Duration::hours(1))
+            .is_err());
+        assert_eq!(datastore.verify().unwrap(), vec![]);
+    }
+
+    // Records survive reopening, and expired ones aren't cloned
+    let datastore = RocksdbDatastore::new(&path, Some(1), false).unwrap();
+    assert_eq!(datastore.get_idempotency_record("a").unwrap(), Some(b"first".to_vec()));
+    assert_eq!(datastore.get_idempotency_record("b").unwrap(), None);
+    let clone_path = generate_temporary_path();
+    datastore.clone_to(&clone_path).unwrap();
+    let clone = RocksdbDatastore::new(&clone_path, Some(1), false).unwrap();
+    assert_eq!(clone.get_idempotency_record("a").unwrap(), Some(b"first".to_vec()));
+}
+
+#[test]
+fn should_read_history_as_of() {
+    use super::RocksdbDatastore;
+    use chrono::Utc;
+    use memory::RetentionPolicy;
+    use models::{EdgeKey, EdgeQueryExt, SpecificEdgeQuery, SpecificVertexQuery, Type, Vertex, VertexQueryExt};
+    use serde_json::Value as JsonValue;
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false)
+        .unwrap()
+        .with_history(Some(RetentionPolicy::new()));
+    let trans = datastore.transaction().unwrap();
+    let t = Type::new("foo").unwrap();
+    let outbound_v = Vertex::new(t.clone());
+    let inbound_v = Vertex::new(t.clone());
+    trans.create_vertex(&outbound_v).unwrap();
+    trans.create_vertex(&inbound_v).unwrap();
+    let key = EdgeKey::new(outbound_v.id, t, inbound_v.id);
+    trans.create_edge(&key).unwrap();
+
+    let vertex_q = SpecificVertexQuery::single(outbound_v.id);
+    let edge_q = SpecificEdgeQuery::single(key);
+    trans
+        .set_vertex_properties(vertex_q.clone().property("bar"), &JsonValue::Bool(true))
+        .unwrap();
+    trans
+        .set_edge_properties(edge_q.clone().property("baz"), &JsonValue::from(1))
+        .unwrap();
+    let checkpoint = Utc::now();
+    trans
+        .set_vertex_properties(vertex_q.clone().property("bar"), &JsonValue::Bool(false))
+        .unwrap();
+    trans.delete_vertices(vertex_q.clone()).unwrap();
+
+    assert_eq!(trans.get_vertices(vertex_q.clone()).unwrap().len(), 0);
+    assert_eq!(trans.get_edges(edge_q.clone()).unwrap().len(), 0);
+
+    let past_trans = datastore.as_of(checkpoint).unwrap().transaction().unwrap();
+    assert_eq!(past_trans.get_vertex_count().unwrap(), 2);
+    assert_eq!(past_trans.get_edges(edge_q.clone()).unwrap().len(), 1);
+    let properties = past_trans.get_vertex_properties(vertex_q.property("bar")).unwrap();
+    assert_eq!(properties[0].value, JsonValue::Bool(true));
+    let properties = past_trans.get_edge_properties(edge_q.property("baz")).unwrap();
+    assert_eq!(properties[0].value, JsonValue::from(1));
+    assert_eq!(datastore.verify().unwrap(), vec![]);
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    assert!(datastore.as_of(checkpoint).is_err());
+}
+
+#[test]
+fn should_enforce_history_retention() {
+    use super::RocksdbDatastore;
+    use chrono::{Duration, Utc};
+    use clock::ManualClock;
+    use memory::RetentionPolicy;
+    use models::{SpecificVertexQuery, Type, VertexQueryExt};
+    use rocksdb::{IteratorMode, Options, DB};
+    use serde_json::Value as JsonValue;
+    use std::sync::Arc;
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let path = generate_temporary_path();
+    let start = Utc::now() - Duration::hours(3);
+    let clock = Arc::new(ManualClock::new(start));
+
+    {
+        let retention = RetentionPolicy::new().max_versions(2).max_age(Duration::hours(1));
+        let datastore = RocksdbDatastore::new(&path, Some(1), false)
+            .unwrap()
+            .with_clock(clock.clone())
+            .with_history(Some(retention));
+        let trans = datastore.transaction().unwrap();
+        let id = trans.create_vertex_from_type(Type::new("foo").unwrap()).unwrap();
+        let q = SpecificVertexQuery::single(id).property("bar");
+
+        for i in 0..3 {
+            clock.advance(Duration::minutes(1));
+            trans.set_vertex_properties(q.clone(), &JsonValue::from(i)).unwrap();
+        }
+
+        // Only the newest two versions are kept
+        let past_trans = datastore
+            .as_of(start + Duration::minutes(1))
+            .unwrap()
+            .transaction()
+            .unwrap();
+        assert_eq!(past_trans.get_vertex_properties(q.clone()).unwrap().len(), 0);
+        let past_trans = datastore
+            .as_of(start + Duration::minutes(2))
+            .unwrap()
+            .transaction()
+            .unwrap();
+        assert_eq!(
+            past_trans.get_vertex_properties(q.clone()).unwrap()[0].value,
+            JsonValue::from(1)
+        );
+
+        // Superseded versions age out, but the current one doesn't
+        clock.set(Utc::now());
+        let past_trans = datastore
+            .as_of(start + Duration::minutes(2))
+            .unwrap()
+            .transaction()
+            .unwrap();
+        assert_eq!(past_trans.get_vertex_properties(q.clone()).unwrap().len(), 0);
+        let present_trans = datastore.as_of(Utc::now()).unwrap().transaction().unwrap();
+        assert_eq!(
+            present_trans.get_vertex_properties(q).unwrap()[0].value,
+            JsonValue::from(2)
+        );
+
+        datastore.compact().unwrap();
+    }
+
+    // Compaction purges the version that aged out, leaving the vertex and
+    // the current version of its property
+    let cf_names = DB::list_cf(&Options::default(), &path).unwrap();
+    let db = DB::open_cf(&Options::default(), &path, &cf_names).unwrap();
+    let cf = db.cf_handle("history:v1").unwrap();
+    assert_eq!(db.iterator_cf(cf, IteratorMode::Start).count(), 2);
+}
+
+#[test]
+fn should_vacuum_history() {
+    use super::RocksdbDatastore;
+    use chrono::Utc;
+    use memory::RetentionPolicy;
+    use models::{EdgeKey, SpecificEdgeQuery, SpecificVertexQuery, Type, VertexQueryExt};
+    use serde_json::Value as JsonValue;
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false)
+        .unwrap()
+        .with_history(Some(RetentionPolicy::new()));
+    let trans = datastore.transaction().unwrap();
+    let t = Type::new("foo").unwrap();
+    let outbound_id = trans.create_vertex_from_type(t.clone()).unwrap();
+    let inbound_id = trans.create_vertex_from_type(t.clone()).unwrap();
+    let key = EdgeKey::new(outbound_id, t, inbound_id);
+    trans.create_edge(&key).unwrap();
+    let q = SpecificVertexQuery::single(inbound_id).property("bar");
+    trans.set_vertex_properties(q.clone(), &JsonValue::Bool(true)).unwrap();
+    let checkpoint = Utc::now();
+    trans.set_vertex_properties(q.clone(), &JsonValue::Bool(false)).unwrap();
+    trans.delete_vertices(SpecificVertexQuery::single(outbound_id)).unwrap();
+    datastore.vacuum_history(&[outbound_id, inbound_id]).unwrap();
+
+    // The deleted vertex and its edge are gone, and only the current
+    // version of the other vertex's property is left
+    let past_trans = datastore.as_of(checkpoint).unwrap().transaction().unwrap();
+    assert_eq!(past_trans.get_vertex_count().unwrap(), 1);
+    assert_eq!(past_trans.get_edges(SpecificEdgeQuery::single(key)).unwrap().len(), 0);
+    assert_eq!(past_trans.get_vertex_properties(q.clone()).unwrap().len(), 0);
+    let present_trans = datastore.as_of(Utc::now()).unwrap().transaction().unwrap();
+    assert_eq!(
+        present_trans.get_vertex_properties(q).unwrap()[0].value,
+        JsonValue::Bool(false)
+    );
+}
+
+#[test]
+fn should_check_entries() {
+    use super::bytes::{build, build_history_version, Component};
+    use super::managers::vertex_property_history_key;
+    use super::verify::check_entry;
+    use chrono::Utc;
+    use util::generate_uuid_v1;
+
+    let id = generate_uuid_v1();
+    let key = build(&[Component::Uuid(id), Component::UnsizedString("name")]);
+    assert!(check_entry("vertex_properties:v1", &key, b"{\"a\":1}").is_ok());
+    assert!(check_entry("vertex_properties:v1", &key, b"{").is_err());
+    assert!(check_entry("vertex_properties:v1", &key[..10], b"1").is_err());
+    assert!(check_entry("vertex_properties:v1", &[&key[..16], &[0xFF][..]].concat(), b"1").is_err());
+    assert!(check_entry("vertices:v1", id.as_bytes(), &[3, b'f', b'o']).is_err());
+    assert!(check_entry("vertices:v1", id.as_bytes(), &[3, b'f', b'o', b'o']).is_ok());
+    assert!(check_entry("vertices:v1", id.as_bytes(), &[2, b'f', b'o', b'o']).is_err());
+
+    let key = [
+        &vertex_property_history_key(id, "name")[..],
+        &build(&[Component::DateTime(Utc::now())]),
+    ]
+    .concat();
+    assert!(check_entry("history:v1", &key, &build_history_version(None, Some(b"1"))).is_ok());
+    assert!(check_entry("history:v1", &key, &build_history_version(None, Some(b"{"))).is_err());
+    assert!(check_entry("history:v1", &key, &build_history_version(Some(Utc::now()), None)).is_ok());
+    assert!(check_entry("history:v1", &key[..20], &build_history_version(None, None)).is_err());
+}
+
+#[test]
+fn should_verify() {
+    use super::RocksdbDatastore;
+    use models::Type;
+    use rocksdb::{Options, DB};
+    use traits::{Datastore, Transaction};
+    use util::{generate_temporary_path, generate_uuid_v1};
+
+    let path = generate_temporary_path();
+
+    {
+        let datastore = RocksdbDatastore::new(&path, Some(1), false).unwrap();
+        let trans = datastore.transaction().unwrap();
+        trans.create_vertex_from_type(Type::new("foo").unwrap()).unwrap();
+        assert_eq!(datastore.verify().unwrap(), vec![]);
+    }
+
+    let corrupt_id = generate_uuid_v1();
+
+    {
+        let cf_names = DB::list_cf(&Options::default(), &path).unwrap();
+        let db = DB::open_cf(&Options::default(), &path, &cf_names).unwrap();
+        let cf = db.cf_handle("vertices:v1").unwrap();
+        db.put_cf(cf, corrupt_id.as_bytes(), [0xFF]).unwrap();
+    }
+
+    let datastore = RocksdbDatastore::new(&path, Some(1), false).unwrap();
+    let corrupt_entries = datastore.verify().unwrap();
+    assert_eq!(corrupt_entries.len(), 1);
+    assert_eq!(corrupt_entries[0].cf, "vertices:v1");
+    assert_eq!(corrupt_entries[0].key, corrupt_id.as_bytes().to_vec());
+}
+
+#[test]
+fn should_not_panic_reading_corrupt_bytes() {
+    use super::bytes::{read_datetime, read_type, read_unsized_string, read_uuid};
+    use errors::ErrorKind;
+    use std::io::Cursor;
+
+    let is_corrupt = |err: ::errors::Error| match *err.kind() {
+        ErrorKind::Corrupt(_) => true,
+        _ => false,
+    };
+
+    assert!(is_corrupt(read_uuid(&mut Cursor::new(&[1, 2, 3][..])).unwrap_err()));
+    assert!(is_corrupt(read_type(&mut Cursor::new(&[3, b'f'][..])).unwrap_err()));
+    assert!(is_corrupt(read_type(&mut Cursor::new(&[1, 0xFF][..])).unwrap_err()));
+    assert!(is_corrupt(
+        read_unsized_string(&mut Cursor::new(&[0xFF, 0xFE][..])).unwrap_err()
+    ));
+    assert!(is_corrupt(read_datetime(&mut Cursor::new(&[0xFF; 8][..])).unwrap_err()));
+    assert!(is_corrupt(read_datetime(&mut Cursor::new(&[0; 4][..])).unwrap_err()));
+    assert_eq!(read_type(&mut Cursor::new(&[1, b'a'][..])).unwrap().0, "a");
+}
+
+// Builds the arbitrary values used by the key encoding property tests.
+#[cfg(test)]
+mod arbitrary {
+    use super::super::bytes::MAX_DATETIME;
+    use chrono::offset::Utc;
+    use chrono::{DateTime, Duration};
+    use models::Type;
+    use std::i64;
+    use uuid::Uuid;
+
+    pub fn uuid(high: u64, low: u64) -> Uuid {
+        let mut bytes = [0; 16];
+
+        for i in 0..8 {
+            bytes[i] = (high >> (56 - i * 8)) as u8;
+            bytes[i + 8] = (low >> (56 - i * 8)) as u8;
+        }
+
+        Uuid::from_slice(&bytes).unwrap()
+    }
+
+    pub fn datetime(nanos: u64) -> DateTime<Utc> {
+        *MAX_DATETIME - Duration::nanoseconds((nanos % i64::MAX as u64) as i64)
+    }
+
+    pub fn sized_string(s: &str) -> String {
+        let mut sized = String::new();
+
+        for c in s.chars() {
+            if sized.len() + c.len_utf8() > 255 {
+                break;
+            }
+
+            sized.push(c);
+        }
+
+        sized
+    }
+
+    pub fn t(s: &str) -> Type {
+        let s: String = s
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+            .take(255)
+            .collect();
+
+        Type::new(if s.is_empty() { "t".to_string() } else { s }).unwrap()
+    }
+}
+
+#[test]
+fn should_round_trip_key_components() {
+    use self::arbitrary;
+    use super::bytes::{
+        build, read_datetime, read_long_sized_string, read_sized_string, read_type, read_unsized_string, read_uuid,
+        Component,
+    };
+    use chrono::offset::Utc;
+    use chrono::DateTime;
+    use models::Type;
+    use quickcheck::quickcheck;
+    use std::io::Cursor;
+    use uuid::Uuid;
+
+    enum Value {
+        Uuid(Uuid),
+        SizedString(String),
+        LongSizedString(String),
+        Type(Type),
+        DateTime(DateTime<Utc>),
+    }
+
+    fn prop(parts: Vec<(u8, u64, u64, String)>, last: Option<String>) -> bool {
+        let values: Vec<Value> = parts
+            .into_iter()
+            .map(|(kind, a, b, s)| match kind % 5 {
+                0 => Value::Uuid(arbitrary::uuid(a, b)),
+                1 => Value::SizedString(arbitrary::sized_string(&s)),
+                2 => Value::LongSizedString(s),
+                3 => Value::Type(arbitrary::t(&s)),
+                _ => Value::DateTime(arbitrary::datetime(a)),
+            })
+            .collect();
+
+        let mut components: Vec<Component> = values
+            .iter()
+            .map(|value| match *value {
+                Value::Uuid(id) => Component::Uuid(id),
+                Value::SizedString(ref s) => Component::SizedString(s),
+                Value::LongSizedString(ref s) => Component::LongSizedString(s),
+                Value::Type(ref t) => Component::Type(t),
+                Value::DateTime(datetime) => Component::DateTime(datetime),
+            })
+            .collect();
+
+        if let Some(ref last) = last {
+            components.push(Component::UnsizedString(last));
+        }
+
+        let key = build(&components);
+        let mut cursor = Cursor::new(&key[..]);
+
+        for value in &values {
+            let is_equal = match *value {
+                Value::Uuid(id) => read_uuid(&mut cursor).unwrap() == id,
+                Value::SizedString(ref s) => read_sized_string(&mut cursor).unwrap() == *s,
+                Value::LongSizedString(ref s) => read_long_sized_string(&mut cursor).unwrap() == *s,
+                Value::Type(ref t) => read_type(&mut cursor).unwrap() == *t,
+                Value::DateTime(datetime) => read_datetime(&mut cursor).unwrap() == datetime,
+            };
+
+            if !is_equal {
+                return false;
+            }
+        }
+
+        match last {
+            Some(last) => read_unsized_string(&mut cursor).unwrap() == last,
+            None => cursor.position() as usize == key.len(),
+        }
+    }
+
+    quickcheck(prop as fn(Vec<(u8, u64, u64, String)>, Option<String>) -> bool);
+}
+
+#[test]
+fn should_not_decode_truncated_keys() {
+    use self::arbitrary;
+    use super::bytes::{build, read_datetime, read_type, read_uuid, Component};
+    use quickcheck::quickcheck;
+    use std::io::Cursor;
+
+    // Edge range keys, which have every kind of fixed size component
+    fn prop(high: u64, low: u64, t: String, nanos: u64, cut: usize) -> bool {
+        let id = arbitrary::uuid(high, low);
+        let t = arbitrary::t(&t);
+        let key = build(&[
+            Component::Uuid(id),
+            Component::Type(&t),
+            Component::DateTime(arbitrary::datetime(nanos)),
+            Component::Uuid(id),
+        ]);
+        let mut cursor = Cursor::new(&key[..cut % key.len()]);
+
+        let result = read_uuid(&mut cursor)
+            .and_then(|_| read_type(&mut cursor))
+            .and_then(|_| read_datetime(&mut cursor))
+            .and_then(|_| read_uuid(&mut cursor));
+
+        result.is_err()
+    }
+
+    quickcheck(prop as fn(u64, u64, String, u64, usize) -> bool);
+}
+
+#[test]
+fn should_not_panic_decoding_arbitrary_bytes() {
+    use super::bytes::{read_datetime, read_sized_string, read_type, read_unsized_string, read_uuid};
+    use quickcheck::quickcheck;
+    use std::io::Cursor;
+
+    fn prop(bytes: Vec<u8>) -> bool {
+        let mut cursor = Cursor::new(&bytes[..]);
+
+        let _ = read_uuid(&mut cursor)
+            .and_then(|_| read_type(&mut cursor))
+            .and_then(|_| read_datetime(&mut cursor))
+            .and_then(|_| read_sized_string(&mut cursor))
+            .and_then(|_| read_unsized_string(&mut cursor));
+
+        true
+    }
+
+    quickcheck(prop as fn(Vec<u8>) -> bool);
+}
+
+#[test]
+fn should_get_prefix_successors() {
+    use super::bytes::prefix_successor;
+    use quickcheck::quickcheck;
+
+    assert_eq!(prefix_successor(b"ab"), Some(b"ac".to_vec()));
+    assert_eq!(prefix_successor(&[1, 0xFF, 0xFF]), Some(vec![2]));
+    assert_eq!(prefix_successor(&[0xFF, 0xFF]), None);
+    assert_eq!(prefix_successor(&[]), None);
+
+    // Every key starting with the prefix sorts below its successor
+    fn bounds(prefix: Vec<u8>, suffix: Vec<u8>) -> bool {
+        let mut key = prefix.clone();
+        key.extend(suffix);
+
+        match prefix_successor(&prefix) {
+            Some(successor) => key < successor && prefix < successor,
+            None => prefix.iter().all(|b| *b == 0xFF),
+        }
+    }
+
+    quickcheck(bounds as fn(Vec<u8>, Vec<u8>) -> bool);
+}
+
+#[test]
+fn should_order_encoded_keys() {
+    use self::arbitrary;
+    use super::bytes::{build, encode_index_value, encode_sortable_f64, encode_sortable_i64, Component};
+    use quickcheck::quickcheck;
+    use serde_json::Value as JsonValue;
+
+    // Later datetimes sort first
+    fn datetimes(a: u64, b: u64) -> bool {
+        let (a, b) = (arbitrary::datetime(a), arbitrary::datetime(b));
+        a.cmp(&b) == build(&[Component::DateTime(b)]).cmp(&build(&[Component::DateTime(a)]))
+    }
+
+    fn uuids(a: (u64, u64), b: (u64, u64)) -> bool {
+        let (a, b) = (arbitrary::uuid(a.0, a.1), arbitrary::uuid(b.0, b.1));
+        a.as_bytes().cmp(b.as_bytes()) == build(&[Component::Uuid(a)]).cmp(&build(&[Component::Uuid(b)]))
+    }
+
+    fn f64s(a: f64, b: f64) -> bool {
+        a.partial_cmp(&b).unwrap() == encode_sortable_f64(a).cmp(&encode_sortable_f64(b))
+    }
+
+    fn i64s(a: i64, b: i64) -> bool {
+        a.cmp(&b) == encode_sortable_i64(a).cmp(&encode_sortable_i64(b))
+    }
+
+    // Encoded strings are self-delimiting, so a shorter string sorts first
+    // even when followed by more components
+    fn strings(a: String, b: String, suffix: Vec<u8>) -> bool {
+        let mut a_key = encode_index_value(&JsonValue::String(a.clone()));
+        a_key.extend(suffix);
+        let b_key = encode_index_value(&JsonValue::String(b.clone()));
+        a == b || a.as_bytes().cmp(b.as_bytes()) == a_key.cmp(&b_key)
+    }
+
+    quickcheck(datetimes as fn(u64, u64) -> bool);
+    quickcheck(uuids as fn((u64, u64), (u64, u64)) -> bool);
+    quickcheck(f64s as fn(f64, f64) -> bool);
+    quickcheck(i64s as fn(i64, i64) -> bool);
+    quickcheck(strings as fn(String, String, Vec<u8>) -> bool);
+}
+
+#[test]
+fn should_timestamp_edges_with_clock() {
+    use super::RocksdbDatastore;
+    use chrono::{Duration, Utc};
+    use clock::ManualClock;
+    use models::{EdgeKey, SpecificEdgeQuery, SpecificVertexQuery, Type, Vertex, VertexQueryExt};
+    use std::sync::Arc;
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let start = Utc::now();
+    let clock = Arc::new(ManualClock::new(start));
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false)
+        .unwrap()
+        .with_clock(clock.clone());
+    let trans = datastore.transaction().unwrap();
+    let t = Type::new("foo").unwrap();
+    let outbound_v = Vertex::new(t.clone());
+    let first_inbound_v = Vertex::new(t.clone());
+    let second_inbound_v = Vertex::new(t.clone());
+    trans.create_vertex(&outbound_v).unwrap();
+    trans.create_vertex(&first_inbound_v).unwrap();
+    trans.create_vertex(&second_inbound_v).unwrap();
+
+    let first_key = EdgeKey::new(outbound_v.id, t.clone(), first_inbound_v.id);
+    trans.create_edge(&first_key).unwrap();
+
+    clock.set(start - Duration::days(1));
+    let second_key = EdgeKey::new(outbound_v.id, t, second_inbound_v.id);
+    let mut batch = datastore.batch();
+    batch.create_edge(&second_key);
+    batch.commit().unwrap();
+
+    let edges = trans.get_edges(SpecificEdgeQuery::single(first_key)).unwrap();
+    assert_eq!(edges[0].created_datetime, start);
+
+    // Edge ranges are ordered newest first, so the backdated edge comes last
+    let edges = trans
+        .get_edges(SpecificVertexQuery::single(outbound_v.id).outbound(10))
+        .unwrap();
+    assert_eq!(edges.len(), 2);
+    assert_eq!(edges[1].key, second_key);
+    assert_eq!(edges[1].created_datetime, start - Duration::days(1));
+}
+
+#[test]
+fn should_create_edges_with_datetime() {
+    use super::RocksdbDatastore;
+    use chrono::{Duration, Utc};
+    use models::{EdgeKey, SpecificVertexQuery, Type, Vertex, VertexQueryExt};
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let trans = datastore.transaction().unwrap();
+    let t = Type::new("foo").unwrap();
+    let outbound_v = Vertex::new(t.clone());
+    let first_inbound_v = Vertex::new(t.clone());
+    let second_inbound_v = Vertex::new(t.clone());
+    trans.create_vertex(&outbound_v).unwrap();
+    trans.create_vertex(&first_inbound_v).unwrap();
+    trans.create_vertex(&second_inbound_v).unwrap();
+
+    let now = Utc::now();
+    let first_key = EdgeKey::new(outbound_v.id, t.clone(), first_inbound_v.id);
+    let second_key = EdgeKey::new(outbound_v.id, t, second_inbound_v.id);
+    trans.create_edge(&first_key).unwrap();
+    assert!(trans
+        .create_edge_with_datetime(&first_key, now - Duration::days(2))
+        .unwrap());
+
+    let mut batch = datastore.batch();
+    batch.create_edge_with_datetime(&second_key, now - Duration::days(1));
+    batch.commit().unwrap();
+
+    // Replacing the update datetime moves the edge within the edge ranges
+    let edges = trans
+        .get_edges(SpecificVertexQuery::single(outbound_v.id).outbound(10))
+        .unwrap();
+    assert_eq!(edges.len(), 2);
+    assert_eq!(edges[0].key, second_key);
+    assert_eq!(edges[0].created_datetime, now - Duration::days(1));
+    assert_eq!(edges[1].key, first_key);
+    assert_eq!(edges[1].created_datetime, now - Duration::days(2));
+}
+
+#[test]
+fn should_scan_with_options() {
+    use super::{RocksdbDatastore, ScanOptions};
+    use models::{EdgeDirection, EdgeKey, RangeVertexQuery, SpecificVertexQuery, Type, VertexQueryExt};
+    use serde_json::Value as JsonValue;
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let trans = datastore.transaction().unwrap();
+    let t = Type::new("foo").unwrap();
+    let ids: Vec<_> = (0..3)
+        .map(|_| trans.create_vertex_from_type(t.clone()).unwrap())
+        .collect();
+    trans.create_edge(&EdgeKey::new(ids[0], t.clone(), ids[1])).unwrap();
+    trans.create_edge(&EdgeKey::new(ids[1], t.clone(), ids[2])).unwrap();
+    trans
+        .set_vertex_properties(
+            SpecificVertexQuery::new(ids.clone()).property("bar"),
+            &JsonValue::from(1),
+        )
+        .unwrap();
+
+    // Scan options change how queries read, not what they return
+    let trans = datastore
+        .transaction()
+        .unwrap()
+        .with_scan_options(ScanOptions::new().fill_cache(false).readahead_size(2 * 1024 * 1024));
+    assert_eq!(
+        trans
+            .get_vertices(RangeVertexQuery::new(u32::max_value()))
+            .unwrap()
+            .len(),
+        3
+    );
+    assert_eq!(trans.get_vertex_count().unwrap(), 3);
+    let edges = trans
+        .get_edges(SpecificVertexQuery::single(ids[1]).outbound(10))
+        .unwrap();
+    assert_eq!(edges.len(), 1);
+    assert_eq!(edges[0].key.inbound_id, ids[2]);
+    let properties = trans
+        .get_all_vertex_properties(RangeVertexQuery::new(u32::max_value()))
+        .unwrap();
+    assert!(properties.iter().all(|vertex| vertex.props.len() == 1));
+    assert_eq!(trans.get_edge_count(ids[1], None, EdgeDirection::Inbound).unwrap(), 1);
+}
+
+// A minimal engine over in-memory maps, to check that the managers only
+// depend on the `KvEngine` interface.
+#[cfg(test)]
+mod memory_engine {
+    use super::super::bytes::{build_bitmap_update, build_counter, build_sketch_update, read_counter, BitmapUpdate};
+    use super::super::engine::{apply_bitmap_operands, apply_sketch_operands, KvEngine, KvItem, KvIteratorMode};
+    use errors::Result;
+    use hll::register_update;
+    use std::collections::{BTreeMap, HashMap};
+    use std::io::Cursor;
+    use std::sync::RwLock;
+
+    type Keyspace = BTreeMap<Vec<u8>, Vec<u8>>;
+
+    pub enum Write {
+        Put(Vec<u8>),
+        Delete,
+        Increment(i64),
+        AddToSketch(Vec<u8>),
+        UpdateBitmap(Vec<u8>),
+    }
+
+    #[derive(Default)]
+    pub struct MemoryEngine {
+        keyspaces: RwLock<HashMap<String, Keyspace>>,
+    }
+
+    impl KvEngine for MemoryEngine {
+        type Batch = Vec<(String, Vec<u8>, Write)>;
+        type Value = Vec<u8>;
+
+        fn get(&self, keyspace: &str, key: &[u8]) -> Result<Option<Vec<u8>>> {
+            let keyspaces = self.keyspaces.read().unwrap();
+            Ok(keyspaces.get(keyspace).and_then(|keyspace| keyspace.get(key).cloned()))
+        }
+
+        fn iterate(&self, keyspace: &str, mode: KvIteratorMode) -> Result<Box<dyn Iterator<Item = KvItem>>> {
+            let keyspaces = self.keyspaces.read().unwrap();
+            let empty = Keyspace::new();
+            let keyspace = keyspaces.get(keyspace).unwrap_or(&empty);
+            let item = |(k, v): (&Vec<u8>, &Vec<u8>)| -> KvItem { (k.clone().into(), v.clone().into()) };
+
+            let items: Vec<KvItem> = match mode {
+                KvIteratorMode::Start => keyspace.iter().map(item).collect(),
+                KvIteratorMode::End => keyspace.iter().rev().map(item).collect(),
+                KvIteratorMode::Forward(key) => keyspace.range(key.to_vec()..).map(item).collect(),
+                KvIteratorMode::Reverse(key) => keyspace.range(..=key.to_vec()).rev().map(item).collect(),
+            };
+
+            Ok(Box::new(items.into_iter()))
+        }
+
+        fn put(&self, batch: &mut Self::Batch, keyspace: &str, key: &[u8], value: &[u8]) -> Result<()> {
+            batch.push((keyspace.to_string(), key.to_vec(), Write::Put(value.to_vec())));
+            Ok(())
+        }
+
+        fn delete(&self, batch: &mut Self::Batch, keyspace: &str, key: &[u8]) -> Result<()> {
+            batch.push((keyspace.to_string(), key.to_vec(), Write::Delete));
+            Ok(())
+        }
+
+        fn increment(&self, batch: &mut Self::Batch, keyspace: &str, key: &[u8], delta: i64) -> Result<()> {
+            batch.push((keyspace.to_string(), key.to_vec(), Write::Increment(delta)));
+            Ok(())
+        }
+
+        fn add_to_sketch(
+            &self,
+            batch: &mut Self::Batch,
+            keyspace: &str,
+            key: &[u8],
+            precision: u8,
+            item: &[u8],
+        ) -> Result<()> {
+            let (index, rank) = register_update(precision, item);
+            let update = build_sketch_update(precision, index, rank);
+            batch.push((keyspace.to_string(), key.to_vec(), Write::AddToSketch(update)));
+            Ok(())
+        }
+
+        fn update_bitmap(
+            &self,
+            batch: &mut Self::Batch,
+            keyspace: &str,
+            key: &[u8],
+            update: &BitmapUpdate,
+        ) -> Result<()> {
+            let update = build_bitmap_update(update);
+            batch.push((keyspace.to_string(), key.to_vec(), Write::UpdateBitmap(update)));
+            Ok(())
+        }
+
+        fn write(&self, batch: Self::Batch) -> Result<()> {
+            let mut keyspaces = self.keyspaces.write().unwrap();
+
+            for (keyspace, key, write) in batch {
+                let keyspace = keyspaces.entry(keyspace).or_insert_with(Keyspace::new);
+
+                match write {
+                    Write::Put(value) => {
+                        keyspace.insert(key, value);
+                    }
+                    Write::Delete => {
+                        keyspace.remove(&key);
+                    }
+                    Write::Increment(delta) => {
+                        let value = keyspace.entry(key).or_insert_with(|| build_counter(0));
+                        let sum = read_counter(&mut Cursor::new(&value[..]))? + delta;
+                        *value = build_counter(sum);
+                    }
+                    Write::AddToSketch(update) => {
+                        let sketch =
+                            apply_sketch_operands(keyspace.get(&key).map(|value| &value[..]), vec![&update[..]]);
+                        keyspace.insert(key, sketch);
+                    }
+                    Write::UpdateBitmap(update) => {
+                        let bitmap =
+                            apply_bitmap_operands(keyspace.get(&key).map(|value| &value[..]), vec![&update[..]]);
+                        keyspace.insert(key, bitmap);
+                    }
+                }
+            }
+
+            Ok(())
+        }
+    }
+}
+
+#[test]
+fn should_manage_edges_over_any_engine() {
+    use self::memory_engine::MemoryEngine;
+    use super::engine::KvEngine;
+    use super::managers::{EdgeManager, EdgePropertyManager, EdgeRangeManager, VertexManager};
+    use chrono::{Duration, Utc};
+    use models::{EdgeKey, Type, Vertex};
+    use serde_json::Value as JsonValue;
+    use std::sync::Arc;
+
+    let engine = Arc::new(MemoryEngine::default());
+    let vertex_manager = VertexManager::new(engine.clone()).unwrap();
+    let edge_manager = EdgeManager::new(engine.clone()).unwrap().with_log(true);
+    let edge_range_manager = EdgeRangeManager::new(engine.clone()).unwrap();
+    let edge_property_manager = EdgePropertyManager::new(engine.clone()).unwrap();
+
+    let t = Type::new("foo").unwrap();
+    let outbound_v = Vertex::new(t.clone());
+    let inbound_v = Vertex::new(t.clone());
+    let now = Utc::now();
+
+    let mut batch = Vec::new();
+    vertex_manager.create(&mut batch, &outbound_v).unwrap();
+    vertex_manager.create(&mut batch, &inbound_v).unwrap();
+    edge_manager
+        .set(&mut batch, outbound_v.id, &t, inbound_v.id, now - Duration::days(1))
+        .unwrap();
+    engine.write(batch).unwrap();
+
+    // Moving the edge replaces its edge range entry
+    let mut batch = Vec::new();
+    edge_manager
+        .set(&mut batch, outbound_v.id, &t, inbound_v.id, now)
+        .unwrap();
+    edge_property_manager
+        .set(
+            &mut batch,
+            outbound_v.id,
+            &t,
+            inbound_v.id,
+            "bar",
+            &JsonValue::Bool(true),
+        )
+        .unwrap();
+    engine.write(batch).unwrap();
+
+    assert_eq!(vertex_manager.get(inbound_v.id).unwrap(), Some(t.clone()));
+    assert_eq!(edge_manager.get(outbound_v.id, &t, inbound_v.id).unwrap(), Some(now));
+    let ranges: Vec<_> = edge_range_manager
+        .iterate_for_owner(outbound_v.id)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(ranges, vec![(outbound_v.id, t.clone(), now, inbound_v.id)]);
+    let typed: Vec<_> = edge_manager
+        .iterate_for_type(&t, None)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(typed, ranges);
+    let logged: Vec<_> = edge_manager
+        .iterate_log(None)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(logged, ranges);
+    assert_eq!(edge_property_manager.iterate_for_name("bar", None).unwrap().count(), 1);
+    assert_eq!(edge_property_manager.iterate_for_name("ba", None).unwrap().count(), 0);
+    let edge_key = EdgeKey::new(outbound_v.id, t.clone(), inbound_v.id);
+    assert_eq!(edge_property_manager.iterate_all(None).unwrap().count(), 1);
+    assert_eq!(
+        edge_property_manager
+            .iterate_all(Some((&edge_key, "ba")))
+            .unwrap()
+            .count(),
+        1
+    );
+    assert_eq!(
+        edge_property_manager
+            .iterate_all(Some((&edge_key, "bar")))
+            .unwrap()
+            .count(),
+        0
+    );
+
+    // Deleting a vertex deletes its edges and their properties
+    let mut batch = Vec::new();
+    vertex_manager.delete(&mut batch, inbound_v.id).unwrap();
+    engine.write(batch).unwrap();
+    assert_eq!(vertex_manager.get(inbound_v.id).unwrap(), None);
+    assert_eq!(edge_manager.get(outbound_v.id, &t, inbound_v.id).unwrap(), None);
+    assert_eq!(edge_range_manager.iterate_for_owner(outbound_v.id).unwrap().count(), 0);
+    assert_eq!(edge_manager.iterate_for_type(&t, None).unwrap().count(), 0);
+    assert_eq!(edge_manager.iterate_log(None).unwrap().count(), 0);
+    assert_eq!(
+        edge_property_manager
+            .get(outbound_v.id, &t, inbound_v.id, "bar")
+            .unwrap(),
+        None
+    );
+    assert_eq!(edge_property_manager.iterate_for_name("bar", None).unwrap().count(), 0);
+}
+
+#[test]
+fn should_find_cold_properties_over_any_engine() {
+    use self::memory_engine::MemoryEngine;
+    use super::engine::{KvEngine, KvIteratorMode};
+    use super::managers::VertexPropertyManager;
+    use serde_json::Value as JsonValue;
+    use std::collections::HashSet;
+    use std::sync::Arc;
+    use util::generate_uuid_v1;
+
+    let engine = Arc::new(MemoryEngine::default());
+    let cold_names: HashSet<String> = vec!["blob".to_string()].into_iter().collect();
+    let cold_manager = VertexPropertyManager::new(engine.clone())
+        .unwrap()
+        .with_cold_names(Arc::new(cold_names));
+    let hot_manager = VertexPropertyManager::new(engine.clone()).unwrap();
+    let id = generate_uuid_v1();
+
+    let mut batch = Vec::new();
+    cold_manager.set(&mut batch, id, "a", &JsonValue::from(1)).unwrap();
+    cold_manager.set(&mut batch, id, "blob", &JsonValue::from("x")).unwrap();
+    cold_manager.set(&mut batch, id, "c", &JsonValue::from(3)).unwrap();
+    engine.write(batch).unwrap();
+    assert_eq!(
+        engine
+            .iterate("cold_vertex_properties:v1", KvIteratorMode::Start)
+            .unwrap()
+            .count(),
+        1
+    );
+
+    // Properties are found, and scanned in order, wherever they're stored
+    assert_eq!(hot_manager.get(id, "blob").unwrap(), Some(JsonValue::from("x")));
+    let names: Vec<String> = hot_manager
+        .iterate_for_owner(id)
+        .unwrap()
+        .map(|item| (item.unwrap().0).1)
+        .collect();
+    assert_eq!(names, vec!["a", "blob", "c"]);
+    assert_eq!(hot_manager.iterate_all(Some((id, "a"))).unwrap().count(), 2);
+
+    // Writing a property moves it to where it belongs now
+    let mut batch = Vec::new();
+    hot_manager.set(&mut batch, id, "blob", &JsonValue::from("y")).unwrap();
+    engine.write(batch).unwrap();
+    assert_eq!(
+        engine
+            .iterate("cold_vertex_properties:v1", KvIteratorMode::Start)
+            .unwrap()
+            .count(),
+        0
+    );
+    assert_eq!(cold_manager.get(id, "blob").unwrap(), Some(JsonValue::from("y")));
+
+    let mut batch = Vec::new();
+    cold_manager.delete(&mut batch, id, "blob").unwrap();
+    engine.write(batch).unwrap();
+    assert_eq!(hot_manager.get(id, "blob").unwrap(), None);
+}
+
+#[test]
+fn should_store_cold_properties() {
+    use super::RocksdbDatastore;
+    use models::{EdgeKey, EdgeQueryExt, SpecificEdgeQuery, SpecificVertexQuery, Type, VertexQueryExt};
+    use serde_json::Value as JsonValue;
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let path = generate_temporary_path();
+    let names = vec!["blob".to_string()].into_iter().collect();
+    let datastore = RocksdbDatastore::new(&path, Some(1), false)
+        .unwrap()
+        .with_cold_properties(names);
+    let trans = datastore.transaction().unwrap();
+    let t = Type::new("foo").unwrap();
+    let outbound_id = trans.create_vertex_from_type(t.clone()).unwrap();
+    let inbound_id = trans.create_vertex_from_type(t.clone()).unwrap();
+    let key = EdgeKey::new(outbound_id, t, inbound_id);
+    trans.create_edge(&key).unwrap();
+
+    let q = SpecificVertexQuery::single(outbound_id);
+    trans
+        .set_vertex_properties(q.clone().property("blob"), &JsonValue::from("x"))
+        .unwrap();
+    trans
+        .set_vertex_properties(q.clone().property("flag"), &JsonValue::Bool(true))
+        .unwrap();
+    trans
+        .set_edge_properties(
+            SpecificEdgeQuery::single(key.clone()).property("blob"),
+            &JsonValue::from("y"),
+        )
+        .unwrap();
+
+    assert_eq!(
+        trans.get_vertex_properties(q.clone().property("blob")).unwrap()[0].value,
+        JsonValue::from("x")
+    );
+    assert_eq!(trans.get_all_vertex_properties(q.clone()).unwrap()[0].props.len(), 2);
+    assert_eq!(
+        trans
+            .get_edge_properties(SpecificEdgeQuery::single(key.clone()).property("blob"))
+            .unwrap()
+            .len(),
+        1
+    );
+    assert!(datastore.verify().unwrap().is_empty());
+
+    // Cold properties are still found once they're no longer configured
+    drop(trans);
+    drop(datastore);
+    let datastore = RocksdbDatastore::new(&path, Some(1), false).unwrap();
+    let trans = datastore.transaction().unwrap();
+    assert_eq!(
+        trans.get_vertex_properties(q.clone().property("blob")).unwrap().len(),
+        1
+    );
+    trans.delete_vertices(q.clone()).unwrap();
+    assert!(trans.get_all_vertex_properties(q).unwrap().is_empty());
+}
+
+#[test]
+fn should_count_types_over_any_engine() {
+    use self::memory_engine::MemoryEngine;
+    use super::engine::KvEngine;
+    use super::managers::{EdgeManager, TypeCountManager, VertexManager};
+    use chrono::Utc;
+    use models::{Type, Vertex};
+    use std::sync::Arc;
+
+    let engine = Arc::new(MemoryEngine::default());
+    let vertex_manager = VertexManager::new(engine.clone()).unwrap();
+    let edge_manager = EdgeManager::new(engine.clone()).unwrap();
+    let counts = |manager: TypeCountManager<MemoryEngine>| -> Vec<(Type, i64)> {
+        manager.iterate().unwrap().collect::<Result<_, _>>().unwrap()
+    };
+
+    let foo_t = Type::new("foo").unwrap();
+    let bar_t = Type::new("bar").unwrap();
+    let first_v = Vertex::new(foo_t.clone());
+    let second_v = Vertex::new(foo_t.clone());
+
+    let mut batch = Vec::new();
+    vertex_manager.create(&mut batch, &first_v).unwrap();
+    vertex_manager.create(&mut batch, &second_v).unwrap();
+    engine.write(batch).unwrap();
+
+    let mut batch = Vec::new();
+    vertex_manager
+        .create(&mut batch, &Vertex::with_id(second_v.id, bar_t.clone()))
+        .unwrap();
+    edge_manager
+        .set(&mut batch, first_v.id, &foo_t, second_v.id, Utc::now())
+        .unwrap();
+    edge_manager
+        .set(&mut batch, first_v.id, &bar_t, first_v.id, Utc::now())
+        .unwrap();
+    engine.write(batch).unwrap();
+
+    // Overwriting a vertex moves it to its new type, and moving an edge
+    // doesn't count it twice
+    let mut batch = Vec::new();
+    edge_manager
+        .set(&mut batch, first_v.id, &foo_t, second_v.id, Utc::now())
+        .unwrap();
+    engine.write(batch).unwrap();
+    let vertex_counts = counts(TypeCountManager::new_for_vertices(engine.clone()).unwrap());
+    assert_eq!(vertex_counts, vec![(bar_t.clone(), 1), (foo_t.clone(), 1)]);
+    let edge_counts = counts(TypeCountManager::new_for_edges(engine.clone()).unwrap());
+    assert_eq!(edge_counts, vec![(bar_t.clone(), 1), (foo_t.clone(), 1)]);
+
+    // Edges from a vertex to itself are only counted down once
+    let mut batch = Vec::new();
+    vertex_manager.delete(&mut batch, first_v.id).unwrap();
+    engine.write(batch).unwrap();
+    let vertex_counts = counts(TypeCountManager::new_for_vertices(engine.clone()).unwrap());
+    assert_eq!(vertex_counts, vec![(bar_t.clone(), 1), (foo_t.clone(), 0)]);
+    let edge_counts = counts(TypeCountManager::new_for_edges(engine.clone()).unwrap());
+    assert_eq!(edge_counts, vec![(bar_t, 0), (foo_t, 0)]);
+}
+
+#[test]
+fn should_upsert_vertices() {
+    use super::RocksdbDatastore;
+    use models::{SpecificVertexQuery, Type, Vertex};
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let trans = datastore.transaction().unwrap();
+    let foo_t = Type::new("foo").unwrap();
+    let bar_t = Type::new("bar").unwrap();
+    let v = Vertex::new(foo_t.clone());
+    assert_eq!(trans.upsert_vertex(&v).unwrap(), None);
+    assert_eq!(trans.upsert_vertex(&v).unwrap(), Some(foo_t.clone()));
+    assert_eq!(
+        trans.upsert_vertex(&Vertex::with_id(v.id, bar_t.clone())).unwrap(),
+        Some(foo_t)
+    );
+
+    let vertices = trans.get_vertices(SpecificVertexQuery::single(v.id)).unwrap();
+    assert_eq!(vertices, vec![Vertex::with_id(v.id, bar_t)]);
+    assert_eq!(trans.get_vertex_count().unwrap(), 1);
+}
+
+#[test]
+fn should_delete_edges_unmodified_since() {
+    use super::RocksdbDatastore;
+    use chrono::{Duration, Utc};
+    use models::{EdgeKey, SpecificVertexQuery, Type, Vertex, VertexQueryExt};
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let trans = datastore.transaction().unwrap();
+    let t = Type::new("foo").unwrap();
+    let outbound_v = Vertex::new(t.clone());
+    trans.create_vertex(&outbound_v).unwrap();
+    let now = Utc::now();
+    let mut keys = Vec::new();
+
+    for days in 1..4 {
+        let inbound_v = Vertex::new(t.clone());
+        trans.create_vertex(&inbound_v).unwrap();
+        let key = EdgeKey::new(outbound_v.id, t.clone(), inbound_v.id);
+        trans
+            .create_edge_with_datetime(&key, now - Duration::days(days))
+            .unwrap();
+        keys.push(key);
+    }
+
+    let q = SpecificVertexQuery::single(outbound_v.id).outbound(10);
+    let count = trans
+        .delete_edges_unmodified_since(q.clone(), now - Duration::days(2))
+        .unwrap();
+    assert_eq!(count, 2);
+    let remaining: Vec<EdgeKey> = trans.get_edges(q).unwrap().into_iter().map(|e| e.key).collect();
+    assert_eq!(remaining, vec![keys[0].clone()]);
+}
+
+#[test]
+fn should_manage_background_tasks() {
+    use super::RocksdbDatastore;
+    use models::{RangeVertexQuery, Type};
+    use std::thread::sleep;
+    use std::time::Duration;
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let names: Vec<String> = datastore.get_background_task_metrics().keys().cloned().collect();
+    assert_eq!(names, vec!["expired_values", "index_backfills", "tombstones"]);
+    assert!(datastore.pause_background_task("missing").is_err());
+
+    // Deletions wait until the task is resumed
+    datastore.pause_background_task("tombstones").unwrap();
+    let trans = datastore.transaction().unwrap();
+    trans.create_vertex_from_type(Type::new("foo").unwrap()).unwrap();
+    trans
+        .delete_vertices_in_background(RangeVertexQuery::new(u32::MAX))
+        .unwrap();
+    sleep(Duration::from_millis(100));
+    assert_eq!(datastore.pending_deletion_count(), 1);
+    assert!(datastore.get_background_task_metrics()["tombstones"].paused);
+
+    datastore.resume_background_task("tombstones").unwrap();
+
+    while datastore.get_background_task_metrics()["tombstones"].run_count == 0 {
+        sleep(Duration::from_millis(10));
+    }
+
+    let metrics = datastore.get_background_task_metrics()["tombstones"].clone();
+    assert!(!metrics.paused);
+    assert_eq!(metrics.processed_count, 1);
+    assert_eq!(metrics.failure_count, 0);
+    assert!(metrics.last_run_datetime.is_some());
+    assert_eq!(datastore.pending_deletion_count(), 0);
+    assert_eq!(trans.get_vertex_count().unwrap(), 0);
+}
+
+#[test]
+fn should_plan_tuning_changes() {
+    use super::tuner::{AutoTuneOptions, Tuner};
+    use models::WritePressure;
+
+    let options = AutoTuneOptions::new()
+        .write_buffer_size(32_000_000, 100_000_000)
+        .level_zero_slowdown_writes_trigger(8, 20)
+        .calm_runs_before_shrinking(2);
+    let mut tuner = Tuner::new(options);
+    let stalled = WritePressure {
+        delayed_write_rate: Some(1024),
+        ..WritePressure::default()
+    };
+    let calm = WritePressure::default();
+
+    let plan = |tuner: &mut Tuner, pressure: &WritePressure| -> Vec<(&'static str, u64)> {
+        let changes = tuner.plan(pressure);
+
+        for change in &changes {
+            tuner.commit(change);
+        }
+
+        changes
+            .into_iter()
+            .map(|change| (change.option, change.new_value))
+            .collect()
+    };
+
+    // Settings grow while writes are stalled, up to their bounds
+    assert_eq!(
+        plan(&mut tuner, &stalled),
+        vec![
+            ("write_buffer_size", 100_000_000),
+            ("level0_slowdown_writes_trigger", 20)
+        ]
+    );
+    assert!(plan(&mut tuner, &stalled).is_empty());
+
+    // And shrink once writes have been calm for long enough
+    assert!(plan(&mut tuner, &calm).is_empty());
+    assert_eq!(
+        plan(&mut tuner, &calm),
+        vec![
+            ("write_buffer_size", 50_000_000),
+            ("level0_slowdown_writes_trigger", 16)
+        ]
+    );
+
+    // Tightened bounds are applied on the next run
+    tuner.set_options(options.write_buffer_size(10_000_000, 20_000_000));
+    assert_eq!(plan(&mut tuner, &calm), vec![("write_buffer_size", 20_000_000)]);
+    assert!(AutoTuneOptions::new().write_buffer_size(2, 1).validate().is_err());
+}
+
+#[test]
+fn should_enable_auto_tuning() {
+    use super::{AutoTuneOptions, RocksdbDatastore};
+    use std::thread::sleep;
+    use std::time::Duration;
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let invalid_options = AutoTuneOptions::new().level_zero_slowdown_writes_trigger(0, 10);
+    assert!(datastore.enable_auto_tuning(invalid_options).is_err());
+
+    // The default bounds include the standard settings, so an idle
+    // datastore isn't changed until it's been calm for a while
+    let options = AutoTuneOptions::new()
+        .interval(Duration::from_millis(10))
+        .calm_runs_before_shrinking(1000);
+    datastore.enable_auto_tuning(options).unwrap();
+    datastore.enable_auto_tuning(options).unwrap();
+    sleep(Duration::from_millis(100));
+    let metrics = datastore.get_background_task_metrics();
+    assert!(metrics["auto_tuner"].run_count > 0);
+    assert_eq!(metrics["auto_tuner"].failure_count, 0);
+    assert!(datastore.get_tuning_changes().is_empty());
+}
+
+#[test]
+fn should_coalesce_writes() {
+    use super::{CoalesceOptions, CommitOptions, RocksdbDatastore};
+    use models::{EdgeKey, SpecificEdgeQuery, Type, Vertex};
+    use std::thread;
+    use std::time::Duration;
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    assert!(datastore
+        .write_coalescer(CoalesceOptions::new().max_batch_operations(0))
+        .is_err());
+    assert!(datastore
+        .write_coalescer(CoalesceOptions::new().commit(CommitOptions::new().sync(true).disable_wal(true)))
+        .is_err());
+
+    let coalescer = datastore
+        .write_coalescer(CoalesceOptions::new().max_delay(Duration::from_millis(20)))
+        .unwrap();
+    let t = Type::new("foo").unwrap();
+    let vertices: Vec<Vertex> = (0..8).map(|_| Vertex::new(t.clone())).collect();
+
+    let handles: Vec<_> = vertices
+        .chunks(2)
+        .map(|pair| {
+            let coalescer = coalescer.clone();
+            let mut batch = datastore.batch();
+            batch.create_vertex(&pair[0]);
+            batch.create_vertex(&pair[1]);
+            batch.create_edge(&EdgeKey::new(pair[0].id, pair[0].t.clone(), pair[1].id));
+            thread::spawn(move || coalescer.write(batch))
+        })
+        .collect();
+
+    for handle in handles {
+        handle.join().unwrap().unwrap();
+    }
+
+    let trans = datastore.transaction().unwrap();
+    assert_eq!(trans.get_vertex_count().unwrap(), 8);
+    let key = EdgeKey::new(vertices[2].id, t, vertices[3].id);
+    assert_eq!(trans.get_edges(SpecificEdgeQuery::single(key)).unwrap().len(), 1);
+
+    // Batches can only be written through their own datastore's coalescer
+    let other_datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let mut batch = other_datastore.batch();
+    batch.create_vertex(&vertices[0]);
+    assert!(coalescer.write(batch).is_err());
+}
+
+#[test]
+fn should_read_raw_properties_in_place() {
+    use super::RocksdbDatastore;
+    use models::{EdgeKey, EdgeQueryExt, SpecificEdgeQuery, SpecificVertexQuery, Type, VertexQueryExt};
+    use serde_json::Value as JsonValue;
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let datastore = RocksdbDatastore::new(&generate_temporary_path(), Some(1), false).unwrap();
+    let trans = datastore.transaction().unwrap();
+    let t = Type::new("foo").unwrap();
+    let outbound_id = trans.create_vertex_from_type(t.clone()).unwrap();
+    let inbound_id = trans.create_vertex_from_type(t.clone()).unwrap();
+    let key = EdgeKey::new(outbound_id, t, inbound_id);
+    trans.create_edge(&key).unwrap();
+
+    trans
+        .set_vertex_properties(
+            SpecificVertexQuery::single(outbound_id).property("name"),
+            &JsonValue::from("alice"),
+        )
+        .unwrap();
+    trans
+        .set_edge_properties(
+            SpecificEdgeQuery::single(key.clone()).property("weight"),
+            &JsonValue::from(3),
+        )
+        .unwrap();
+
+    let len = trans
+        .with_raw_vertex_property(outbound_id, "name", |value| Ok(value.len()))
+        .unwrap();
+    assert_eq!(len, Some(7));
+    let weight = trans
+        .with_raw_edge_property(&key, "weight", |value| Ok(value.to_vec()))
+        .unwrap();
+    assert_eq!(weight, Some(b"3".to_vec()));
+    assert_eq!(
+        trans.with_raw_vertex_property(inbound_id, "name", |_| Ok(())).unwrap(),
+        None
+    );
+}
+
+#[cfg(test)]
+pub mod memory_kv {
+    use super::super::{ObjectStore, OrderedKvClient, OrderedKvTransaction};
+    use errors::{self, ErrorKind};
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+
+    /// An in-memory ordered key-value store, for testing the distributed
+    /// datastore. Transactions buffer their writes, and apply them when
+    /// they're committed.
+    #[derive(Clone, Debug, Default)]
+    pub struct MemoryKvClient {
+        data: Arc<Mutex<BTreeMap<Vec<u8>, Vec<u8>>>>,
+    }
+
+    /// A transaction against a `MemoryKvClient`.
+    #[derive(Debug)]
+    pub struct MemoryKvTransaction {
+        data: Arc<Mutex<BTreeMap<Vec<u8>, Vec<u8>>>>,
+        // The buffered writes, where `None` is a delete.
+        writes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    }
+
+    impl OrderedKvTransaction for MemoryKvTransaction {
+        fn get(&mut self, key: &[u8]) -> errors::Result<Option<Vec<u8>>> {
+            match self.writes.get(key) {
+                Some(value) => Ok(value.clone()),
+                None => Ok(self.data.lock().unwrap().get(key).cloned()),
+            }
+        }
+
+        fn scan(
+            &mut self,
+            start: &[u8],
+            end: &[u8],
+            limit: usize,
+            reverse: bool,
+        ) -> errors::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+            if start >= end {
+                return Ok(Vec::new());
+            }
+
+            let mut range: BTreeMap<Vec<u8>, Vec<u8>> = self
+                .data
+                .lock()
+                .unwrap()
+                .range(start.to_vec()..end.to_vec())
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect();
+
+            for (key, value) in self.writes.range(start.to_vec()..end.to_vec()) {
+                match value {
+                    Some(value) => range.insert(key.clone(), value.clone()),
+                    None => range.remove(key),
+                };
+            }
+
+            if reverse {
+                Ok(range.into_iter().rev().take(limit).collect())
+            } else {
+                Ok(range.into_iter().take(limit).collect())
+            }
+        }
+
+        fn put(&mut self, key: &[u8], value: &[u8]) -> errors::Result<()> {
+            self.writes.insert(key.to_vec(), Some(value.to_vec()));
+            Ok(())
+        }
+
+        fn delete(&mut self, key: &[u8]) -> errors::Result<()> {
+            self.writes.insert(key.to_vec(), None);
+            Ok(())
+        }
+
+        fn commit(self) -> errors::Result<()> {
+            let mut data = self.data.lock().unwrap();
+
+            for (key, value) in self.writes {
+                match value {
+                    Some(value) => data.insert(key, value),
+                    None => data.remove(&key),
+                };
+            }
+
+            Ok(())
+        }
+
+        fn rollback(self) -> errors::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl OrderedKvClient for MemoryKvClient {
+        type Transaction = MemoryKvTransaction;
+
+        fn begin(&self) -> errors::Result<MemoryKvTransaction> {
+            Ok(MemoryKvTransaction {
+                data: self.data.clone(),
+                writes: BTreeMap::new(),
+            })
+        }
+    }
+
+    /// In-memory object storage, for testing the tiered store. It counts
+    /// the ranged reads made against it, so tests can tell whether blocks
+    /// were cached.
+    #[derive(Clone, Debug, Default)]
+    pub struct MemoryObjectStore {
+        objects: Arc<Mutex<BTreeMap<String, Vec<u8>>>>,
+        pub range_reads: Arc<Mutex<u64>>,
+    }
+
+    impl ObjectStore for MemoryObjectStore {
+        fn put(&self, name: &str, data: &[u8]) -> errors::Result<()> {
+            self.objects.lock().unwrap().insert(name.to_string(), data.to_vec());
+            Ok(())
+        }
+
+        fn get(&self, name: &str) -> errors::Result<Option<Vec<u8>>> {
+            Ok(self.objects.lock().unwrap().get(name).cloned())
+        }
+
+        fn get_range(&self, name: &str, offset: u64, len: u64) -> errors::Result<Vec<u8>> {
+            *self.range_reads.lock().unwrap() += 1;
+            let objects = self.objects.lock().unwrap();
+
+            match objects.get(name) {
+                Some(data) if offset + len <= data.len() as u64 => {
+                    Ok(data[offset as usize..(offset + len) as usize].to_vec())
+                }
+                _ => Err(ErrorKind::NotFound(name.to_string()).into()),
+            }
+        }
+
+        fn list(&self, prefix: &str) -> errors::Result<Vec<String>> {
+            let objects = self.objects.lock().unwrap();
+            Ok(objects
+                .keys()
+                .filter(|name| name.starts_with(prefix))
+                .cloned()
+                .collect())
+        }
+
+        fn delete(&self, name: &str) -> errors::Result<()> {
+            self.objects.lock().unwrap().remove(name);
+            Ok(())
+        }
+    }
+}
+
+#[test]
+fn should_page_through_distributed_scans() {
+    use super::tests::memory_kv::MemoryKvClient;
+    use super::DistributedDatastore;
+    use chrono::{Duration, Utc};
+    use models::{EdgeDirection, EdgeKey, RangeVertexQuery, SpecificVertexQuery, Type, Vertex, VertexQueryExt};
+    use traits::{Datastore, Transaction};
+
+    let datastore = DistributedDatastore::new(MemoryKvClient::default());
+    let trans = datastore.transaction().unwrap();
+    let t = Type::new("foo").unwrap();
+    let outbound_v = Vertex::new(t.clone());
+    trans.create_vertex(&outbound_v).unwrap();
+    let start = Utc::now();
+
+    // More vertices and edges than fit in a page of a scan
+    for i in 0..600 {
+        let inbound_v = Vertex::new(t.clone());
+        trans.create_vertex(&inbound_v).unwrap();
+        let key = EdgeKey::new(outbound_v.id, t.clone(), inbound_v.id);
+        trans
+            .create_edge_with_datetime(&key, start + Duration::seconds(i))
+            .unwrap();
+    }
+
+    assert_eq!(trans.get_vertex_count().unwrap(), 601);
+    assert_eq!(trans.get_vertices(RangeVertexQuery::new(1000)).unwrap().len(), 601);
+    assert_eq!(
+        trans
+            .get_edge_count(outbound_v.id, Some(&t), EdgeDirection::Outbound)
+            .unwrap(),
+        600
+    );
+
+    // Edges are scanned in reverse, newest first, from the high bound
+    let edges = trans
+        .get_edges(
+            SpecificVertexQuery::single(outbound_v.id)
+                .outbound(1000)
+                .high(start + Duration::seconds(299)),
+        )
+        .unwrap();
+    assert_eq!(edges.len(), 300);
+    assert_eq!(edges[0].created_datetime, start + Duration::seconds(299));
+    assert_eq!(edges[299].created_datetime, start);
+
+    trans
+        .delete_vertices(SpecificVertexQuery::single(outbound_v.id))
+        .unwrap();
+    assert_eq!(trans.get_vertex_count().unwrap(), 600);
+    assert!(trans.get_keys_scanned().unwrap() > 0);
+}
+
+#[test]
+fn should_tier_to_object_storage() {
+    use super::tests::memory_kv::MemoryObjectStore;
+    use super::{DistributedDatastore, TieredOptions, TieredStore};
+    use models::{SpecificVertexQuery, Type, Vertex, VertexQueryExt};
+    use serde_json::Value as JsonValue;
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    let objects = MemoryObjectStore::default();
+    let options = TieredOptions::new().block_size(128).cache_capacity(1024);
+    let store = TieredStore::new(&generate_temporary_path(), objects.clone(), options).unwrap();
+    let datastore = DistributedDatastore::new(store.clone());
+    let trans = datastore.transaction().unwrap();
+    let t = Type::new("foo").unwrap();
+    let vertices: Vec<Vertex> = (0..50).map(|_| Vertex::new(t.clone())).collect();
+
+    for v in &vertices {
+        trans.create_vertex(v).unwrap();
+    }
+
+    store.flush().unwrap();
+    assert_eq!(store.segment_count(), 1);
+
+    // Newer segments replace older ones' values, and hide deleted vertices
+    let q = SpecificVertexQuery::single(vertices[0].id).property("name");
+    trans
+        .set_vertex_properties(q.clone(), &JsonValue::from("alice"))
+        .unwrap();
+    store.flush().unwrap();
+    trans.set_vertex_properties(q.clone(), &JsonValue::from("bob")).unwrap();
+    trans
+        .delete_vertices(SpecificVertexQuery::single(vertices[1].id))
+        .unwrap();
+    store.flush().unwrap();
+    assert_eq!(store.segment_count(), 3);
+    assert_eq!(trans.get_vertex_count().unwrap(), 49);
+    assert_eq!(
+        trans.get_vertex_properties(q.clone()).unwrap()[0].value,
+        JsonValue::from("bob")
+    );
+
+    store.compact().unwrap();
+    assert_eq!(store.segment_count(), 1);
+    assert_eq!(trans.get_vertex_count().unwrap(), 49);
+
+    // Blocks are read from object storage once, and then from the cache
+    let range_reads = *objects.range_reads.lock().unwrap();
+    assert_eq!(
+        trans
+            .get_vertices(SpecificVertexQuery::single(vertices[2].id))
+            .unwrap()
+            .len(),
+        1
+    );
+    let after_first_read = *objects.range_reads.lock().unwrap();
+    assert!(after_first_read > range_reads);
+    assert_eq!(
+        trans
+            .get_vertices(SpecificVertexQuery::single(vertices[2].id))
+            .unwrap()
+            .len(),
+        1
+    );
+    assert_eq!(*objects.range_reads.lock().unwrap(), after_first_read);
+
+    // Another store finds the segments in object storage
+    let other_store = TieredStore::new(&generate_temporary_path(), objects, options).unwrap();
+    let other_trans = DistributedDatastore::new(other_store).transaction().unwrap();
+    assert_eq!(other_trans.get_vertex_count().unwrap(), 49);
+    assert_eq!(
+        other_trans.get_vertex_properties(q).unwrap()[0].value,
+        JsonValue::from("bob")
+    );
+}
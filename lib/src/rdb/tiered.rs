@@ -0,0 +1,570 @@
+//! An ordered key-value store that keeps its data in object storage, e.g.
+//! S3, with a local rocksdb database as its write buffer and cache, so that
+//! graphs can be much larger than local disk. Graphs are stored in it
+//! through the distributed datastore.
+
+use super::distributed::{DistributedDatastore, OrderedKvClient, OrderedKvTransaction};
+use super::engine::cf_handle;
+use super::segments::{
+    build_segment, data_name, index_name, merge_runs, merge_runs_with_deletions, parse_index_name, read_block, Entry,
+    ObjectStore, Segment, SegmentScan,
+};
+use errors::{ErrorKind, Result, ValidationError};
+use rocksdb::{Direction, IteratorMode, Options, WriteBatch, DB};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+// Writes that haven't been flushed to a segment yet.
+const BUFFER_CF: &str = "buffer:v1";
+// Segment blocks that have been fetched from object storage.
+const CACHE_CF: &str = "cache:v1";
+
+/// A datastore whose data is tiered to object storage.
+pub type TieredDatastore<S> = DistributedDatastore<TieredStore<S>>;
+
+/// Controls how a tiered store lays out segments, and how much of them it
+/// caches locally.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TieredOptions {
+    /// How many bytes of keys and values go in each segment block. Blocks
+    /// are fetched and cached whole, so larger ones mean fewer requests to
+    /// object storage for scans, but more bytes fetched for point reads.
+    pub block_size: usize,
+
+    /// The most bytes of blocks that are cached locally. The blocks that
+    /// were cached first are evicted first.
+    pub cache_capacity: u64,
+
+    /// Roughly how many bytes of keys and values are committed to the
+    /// write buffer before it's flushed to a new segment.
+    pub buffer_size: u64,
+
+    /// The most segments there are before they're compacted into one.
+    /// Compactions rewrite every segment, so fewer of them means faster
+    /// reads but more bytes written to object storage.
+    pub max_segments: usize,
+}
+
+impl Default for TieredOptions {
+    fn default() -> Self {
+        TieredOptions {
+            block_size: 64 * 1024,
+            cache_capacity: 1024 * 1024 * 1024,
+            buffer_size: 64 * 1024 * 1024,
+            max_segments: 16,
+        }
+    }
+}
+
+impl TieredOptions {
+    /// Creates new tiered options, with 64 KiB blocks, a 1 GiB cache, a
+    /// 64 MiB write buffer and up to 16 segments.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how many bytes go in each segment block.
+    ///
+    /// # Arguments
+    /// * `block_size` - The number of bytes.
+    pub fn block_size(self, block_size: usize) -> Self {
+        TieredOptions { block_size, ..self }
+    }
+
+    /// Sets the most bytes of blocks that are cached locally.
+    ///
+    /// # Arguments
+    /// * `cache_capacity` - The number of bytes.
+    pub fn cache_capacity(self, cache_capacity: u64) -> Self {
+        TieredOptions { cache_capacity, ..self }
+    }
+
+    /// Sets roughly how many bytes are committed before the write buffer is
+    /// flushed.
+    ///
+    /// # Arguments
+    /// * `buffer_size` - The number of bytes.
+    pub fn buffer_size(self, buffer_size: u64) -> Self {
+        TieredOptions { buffer_size, ..self }
+    }
+
+    /// Sets the most segments there are before they're compacted.
+    ///
+    /// # Arguments
+    /// * `max_segments` - The number of segments.
+    pub fn max_segments(self, max_segments: usize) -> Self {
+        TieredOptions { max_segments, ..self }
+    }
+
+    fn validate(self) -> Result<()> {
+        if self.block_size == 0 {
+            return Err(ValidationError::from("Segment blocks cannot be empty").into());
+        }
+
+        if self.max_segments == 0 {
+            return Err(ValidationError::from("There must be at least one segment").into());
+        }
+
+        Ok(())
+    }
+}
+
+// The blocks in the cache, in the order they were cached, along with their
+// sizes.
+#[derive(Default)]
+struct CacheState {
+    bytes: u64,
+    blocks: VecDeque<(Vec<u8>, u64)>,
+}
+
+struct Inner<S: ObjectStore> {
+    db: DB,
+    objects: S,
+    options: TieredOptions,
+    // The segments, from newest to oldest.
+    segments: RwLock<Vec<Arc<Segment>>>,
+    // Segments that were replaced by a compaction, whose objects are
+    // deleted once no scans are reading them.
+    retired: Mutex<Vec<Arc<Segment>>>,
+    next_seq: AtomicU64,
+    // Roughly how many bytes have been committed since the last flush.
+    buffered_bytes: AtomicU64,
+    // Held while committing, flushing or compacting, so that each sees the
+    // others' writes in full.
+    write_lock: Mutex<()>,
+    cache: Mutex<CacheState>,
+}
+
+/// An ordered key-value store that keeps its data in object storage.
+///
+/// Commits go to a local rocksdb write buffer, which is flushed into an
+/// immutable segment in object storage once it's large enough. Reads check
+/// the buffer and then the segments from newest to oldest, fetching the
+/// blocks they need and caching them locally. Once there are too many
+/// segments, they're compacted into one, so that reads check fewer of them.
+/// Flushes and compactions run as part of the commit that triggers them;
+/// if one fails, the commit still succeeds, and it's retried on the next
+/// one.
+///
+/// Transactions aren't isolated from concurrent commits while they read,
+/// but a commit fails with a conflict if a key that its transaction read
+/// has changed since.
+pub struct TieredStore<S: ObjectStore> {
+    inner: Arc<Inner<S>>,
+}
+
+impl<S: ObjectStore> Clone for TieredStore<S> {
+    fn clone(&self) -> Self {
+        TieredStore {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+fn encode_buffered(value: Option<&[u8]>) -> Vec<u8> {
+    match value {
+        Some(value) => {
+            let mut encoded = Vec::with_capacity(value.len() + 1);
+            encoded.push(1);
+            encoded.extend_from_slice(value);
+            encoded
+        }
+        None => vec![0],
+    }
+}
+
+fn decode_buffered(encoded: &[u8]) -> Result<Option<Vec<u8>>> {
+    match encoded.first() {
+        Some(0) => Ok(None),
+        Some(1) => Ok(Some(encoded[1..].to_vec())),
+        _ => Err(ErrorKind::Corrupt("buffered value".to_string()).into()),
+    }
+}
+
+fn cache_key(segment: &Segment, block: usize) -> Vec<u8> {
+    let mut key = segment.seq.to_be_bytes().to_vec();
+    key.extend_from_slice(&(block as u64).to_be_bytes());
+    key
+}
+
+impl<S: ObjectStore> TieredStore<S> {
+    /// Opens a tiered store, creating its local database if it doesn't
+    /// exist yet.
+    ///
+    /// # Arguments
+    /// * `path` - The path to the local database.
+    /// * `objects` - The object storage that segments are kept in.
+    /// * `options` - How to lay out and cache segments.
+    ///
+    /// # Errors
+    /// Returns an error if the options are invalid, or the segments'
+    /// indexes can't be read.
+    pub fn new(path: &str, objects: S, options: TieredOptions) -> Result<Self> {
+        options.validate()?;
+
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        let mut db = DB::open_cf(&opts, path, [BUFFER_CF, CACHE_CF])?;
+
+        // The cache's size is only tracked in memory, so it's started over
+        db.drop_cf(CACHE_CF)?;
+        db.create_cf(CACHE_CF, &Options::default())?;
+
+        let mut seqs: Vec<u64> = objects
+            .list("segments/")?
+            .iter()
+            .filter_map(|name| parse_index_name(name))
+            .collect();
+        seqs.sort_unstable_by(|a, b| b.cmp(a));
+        let buffered_bytes = db
+            .iterator_cf(cf_handle(&db, BUFFER_CF)?, IteratorMode::Start)
+            .map(|(key, value)| (key.len() + value.len()) as u64)
+            .sum();
+        let mut segments = Vec::with_capacity(seqs.len());
+
+        for &seq in &seqs {
+            let index = match objects.get(&index_name(seq))? {
+                Some(index) => index,
+                None => return Err(ErrorKind::NotFound(index_name(seq)).into()),
+            };
+
+            segments.push(Arc::new(Segment::from_index(seq, &index)?));
+        }
+
+        Ok(TieredStore {
+            inner: Arc::new(Inner {
+                db,
+                objects,
+                options,
+                segments: RwLock::new(segments),
+                retired: Mutex::new(Vec::new()),
+                next_seq: AtomicU64::new(seqs.first().map_or(0, |seq| seq + 1)),
+                buffered_bytes: AtomicU64::new(buffered_bytes),
+                write_lock: Mutex::new(()),
+                cache: Mutex::new(CacheState::default()),
+            }),
+        })
+    }
+
+    /// Moves the write buffer into a new segment in object storage, without
+    /// waiting for it to fill up. Until it's flushed, the buffer is only
+    /// stored locally.
+    pub fn flush(&self) -> Result<()> {
+        let _guard = self.inner.write_lock.lock().unwrap();
+        self.flush_locked()
+    }
+
+    /// Merges every segment into one, dropping deleted keys and the values
+    /// that newer segments replaced, without waiting for there to be too
+    /// many.
+    pub fn compact(&self) -> Result<()> {
+        let _guard = self.inner.write_lock.lock().unwrap();
+        self.compact_locked()
+    }
+
+    // Flushes the buffer, with the write lock held.
+    fn flush_locked(&self) -> Result<()> {
+        let inner = &self.inner;
+        let buffer_cf = cf_handle(&inner.db, BUFFER_CF)?;
+
+        let entries = inner
+            .db
+            .iterator_cf(buffer_cf, IteratorMode::Start)
+            .map(|(key, value)| Ok((key.into_vec(), decode_buffered(&value)?)))
+            .collect::<Result<Vec<Entry>>>()?;
+
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        let keys: Vec<Vec<u8>> = entries.iter().map(|(key, _)| key.clone()).collect();
+        let seq = inner.next_seq.fetch_add(1, Ordering::SeqCst);
+        let segment = self.write_segment(seq, entries)?;
+
+        // The segment is added before the buffer is cleared, so that reads
+        // that miss the buffer find it
+        inner.segments.write().unwrap().insert(0, Arc::new(segment));
+        let mut batch = WriteBatch::default();
+
+        for key in keys {
+            batch.delete_cf(buffer_cf, key);
+        }
+
+        inner.db.write(batch)?;
+        inner.buffered_bytes.store(0, Ordering::SeqCst);
+        self.delete_retired_segments()
+    }
+
+    // Compacts the segments, with the write lock held.
+    fn compact_locked(&self) -> Result<()> {
+        let inner = &self.inner;
+        let segments = inner.segments.read().unwrap().clone();
+
+        if segments.len() > 1 {
+            let runs = segments
+                .iter()
+                .map(|segment| self.scan_segment(segment.clone(), &[], None, false))
+                .collect();
+
+            // The oldest segment is replaced too, so deletions don't need
+            // to be kept to hide anything
+            let entries = merge_runs_with_deletions(runs)
+                .filter(|item| match item {
+                    Ok((_, value)) => value.is_some(),
+                    Err(_) => true,
+                })
+                .collect::<Result<Vec<Entry>>>()?;
+
+            let seq = inner.next_seq.fetch_add(1, Ordering::SeqCst);
+            let segment = self.write_segment(seq, entries)?;
+            *inner.segments.write().unwrap() = vec![Arc::new(segment)];
+            inner.retired.lock().unwrap().extend(segments);
+        }
+
+        self.delete_retired_segments()
+    }
+
+    /// Gets the number of segments in object storage.
+    pub fn segment_count(&self) -> usize {
+        self.inner.segments.read().unwrap().len()
+    }
+
+    // Writes a segment's data, then its index, so that it's only found once
+    // it's complete.
+    fn write_segment(&self, seq: u64, entries: Vec<Entry>) -> Result<Segment> {
+        let (data, index) = build_segment(entries, self.inner.options.block_size);
+        self.inner.objects.put(&data_name(seq), &data)?;
+        self.inner.objects.put(&index_name(seq), &index)?;
+        Segment::from_index(seq, &index)
+    }
+
+    fn delete_retired_segments(&self) -> Result<()> {
+        let mut retired = self.inner.retired.lock().unwrap();
+        let mut remaining = Vec::new();
+
+        for segment in retired.drain(..) {
+            if Arc::strong_count(&segment) > 1 {
+                remaining.push(segment);
+            } else {
+                self.inner.objects.delete(&index_name(segment.seq))?;
+                self.inner.objects.delete(&data_name(segment.seq))?;
+            }
+        }
+
+        *retired = remaining;
+        Ok(())
+    }
+
+    // Loads a block's entries, from the cache if it's there, and otherwise
+    // from object storage.
+    fn load_block(&self, segment: &Segment, block: usize) -> Result<Vec<Entry>> {
+        let inner = &self.inner;
+        let cache_cf = cf_handle(&inner.db, CACHE_CF)?;
+        let key = cache_key(segment, block);
+
+        if let Some(cached) = inner.db.get_pinned_cf(cache_cf, &key)? {
+            return read_block(&cached);
+        }
+
+        let (offset, len) = segment.block_range(block);
+        let data = inner.objects.get_range(&data_name(segment.seq), offset, len)?;
+        let entries = read_block(&data)?;
+
+        if len <= inner.options.cache_capacity {
+            let mut cache = inner.cache.lock().unwrap();
+            let mut batch = WriteBatch::default();
+            batch.put_cf(cache_cf, &key, &data);
+            cache.blocks.push_back((key, len));
+            cache.bytes += len;
+
+            while cache.bytes > inner.options.cache_capacity {
+                let (evicted_key, evicted_len) = cache.blocks.pop_front().unwrap();
+                batch.delete_cf(cache_cf, evicted_key);
+                cache.bytes -= evicted_len;
+            }
+
+            inner.db.write(batch)?;
+        }
+
+        Ok(entries)
+    }
+
+    fn scan_segment<'a>(
+        &'a self,
+        segment: Arc<Segment>,
+        start: &[u8],
+        end: Option<&[u8]>,
+        reverse: bool,
+    ) -> Box<dyn Iterator<Item = Result<Entry>> + 'a> {
+        Box::new(SegmentScan::new(
+            segment,
+            move |segment: &Segment, block| self.load_block(segment, block),
+            start,
+            end,
+            reverse,
+        ))
+    }
+
+    // Looks up a key in the buffer and then the segments. The buffer is
+    // read first, since a flush adds its segment before clearing the
+    // buffer.
+    fn lookup(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let inner = &self.inner;
+
+        if let Some(buffered) = inner.db.get_pinned_cf(cf_handle(&inner.db, BUFFER_CF)?, key)? {
+            return decode_buffered(&buffered);
+        }
+
+        let segments = inner.segments.read().unwrap().clone();
+
+        for segment in segments {
+            let block = match segment.find_block(key) {
+                Some(block) => block,
+                None => continue,
+            };
+
+            let entries = self.load_block(&segment, block)?;
+
+            if let Ok(i) = entries.binary_search_by(|(other_key, _)| other_key[..].cmp(key)) {
+                return Ok(entries[i].1.clone());
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// A transaction against a tiered store.
+pub struct TieredTransaction<S: ObjectStore> {
+    store: TieredStore<S>,
+    // The values of the keys that have been read, as they were first read.
+    reads: HashMap<Vec<u8>, Option<Vec<u8>>>,
+    // The buffered writes, where `None` is a delete.
+    writes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+impl<S: ObjectStore> OrderedKvTransaction for TieredTransaction<S> {
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        if let Some(value) = self.writes.get(key) {
+            return Ok(value.clone());
+        }
+
+        let value = self.store.lookup(key)?;
+        self.reads.entry(key.to_vec()).or_insert_with(|| value.clone());
+        Ok(value)
+    }
+
+    fn scan(&mut self, start: &[u8], end: &[u8], limit: usize, reverse: bool) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        if start >= end {
+            return Ok(Vec::new());
+        }
+
+        let store = &self.store;
+        let db = &store.inner.db;
+        let range = start.to_vec()..end.to_vec();
+
+        let writes: Box<dyn Iterator<Item = Result<Entry>>> = if reverse {
+            Box::new(self.writes.range(range).rev().map(|(k, v)| Ok((k.clone(), v.clone()))))
+        } else {
+            Box::new(self.writes.range(range).map(|(k, v)| Ok((k.clone(), v.clone()))))
+        };
+
+        // The buffer's iterator is created before the segments are read,
+        // for the same reason as in lookups
+        let buffer_mode = if reverse {
+            IteratorMode::From(end, Direction::Reverse)
+        } else {
+            IteratorMode::From(start, Direction::Forward)
+        };
+
+        let (start_key, end_key) = (start.to_vec(), end.to_vec());
+        let buffer: Box<dyn Iterator<Item = Result<Entry>>> = Box::new(
+            db.iterator_cf(cf_handle(db, BUFFER_CF)?, buffer_mode)
+                .skip_while(move |(key, _)| reverse && key[..] >= end_key[..])
+                .take_while(move |(key, _)| {
+                    if reverse {
+                        key[..] >= start_key[..]
+                    } else {
+                        key[..] < end[..]
+                    }
+                })
+                .map(|(key, value)| Ok((key.into_vec(), decode_buffered(&value)?))),
+        );
+
+        let mut runs = vec![writes, buffer];
+        let segments = store.inner.segments.read().unwrap().clone();
+
+        for segment in segments {
+            runs.push(store.scan_segment(segment, start, Some(end), reverse));
+        }
+
+        merge_runs(runs, reverse).take(limit).collect()
+    }
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.writes.insert(key.to_vec(), Some(value.to_vec()));
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<()> {
+        self.writes.insert(key.to_vec(), None);
+        Ok(())
+    }
+
+    fn commit(self) -> Result<()> {
+        if self.writes.is_empty() {
+            return Ok(());
+        }
+
+        let inner = &self.store.inner;
+        let _guard = inner.write_lock.lock().unwrap();
+
+        for (key, value) in &self.reads {
+            if self.store.lookup(key)? != *value {
+                return Err(ErrorKind::Conflict(format!("key {:?}", key)).into());
+            }
+        }
+
+        let buffer_cf = cf_handle(&inner.db, BUFFER_CF)?;
+        let mut batch = WriteBatch::default();
+        let mut bytes = 0;
+
+        for (key, value) in &self.writes {
+            let value = encode_buffered(value.as_ref().map(|v| &v[..]));
+            bytes += (key.len() + value.len()) as u64;
+            batch.put_cf(buffer_cf, key, value);
+        }
+
+        inner.db.write(batch)?;
+        let buffered_bytes = inner.buffered_bytes.fetch_add(bytes, Ordering::SeqCst) + bytes;
+
+        // The writes are already committed, so a failure here is left for
+        // the next commit to retry
+        if buffered_bytes >= inner.options.buffer_size
+            && self.store.flush_locked().is_ok()
+            && inner.segments.read().unwrap().len() > inner.options.max_segments
+        {
+            let _ = self.store.compact_locked();
+        }
+
+        Ok(())
+    }
+
+    fn rollback(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<S: ObjectStore> OrderedKvClient for TieredStore<S> {
+    type Transaction = TieredTransaction<S>;
+
+    fn begin(&self) -> Result<TieredTransaction<S>> {
+        Ok(TieredTransaction {
+            store: self.clone(),
+            reads: HashMap::new(),
+            writes: BTreeMap::new(),
+        })
+    }
+}
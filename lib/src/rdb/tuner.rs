@@ -0,0 +1,308 @@
+//! Adapts rocksdb's memtable and compaction settings to the workload. The
+//! tuner watches for write stalls, and when writes are being slowed down,
+//! grows the memtables and lets more level 0 files build up before writes are
+//! slowed; once writes have been flowing freely for a while, it shrinks them
+//! back down. Every setting stays within the bounds the operator configured,
+//! and every change is recorded.
+
+use super::engine::cf_handle;
+use chrono::offset::Utc;
+use chrono::DateTime;
+use errors::{Result, ValidationError};
+use models::WritePressure;
+use rocksdb::DB;
+use std::cmp::{max, min};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// The settings the datastore is opened with, which the tuner starts from.
+const DEFAULT_WRITE_BUFFER_SIZE: u64 = 67_108_864;
+const DEFAULT_LEVEL_ZERO_SLOWDOWN_WRITES_TRIGGER: u64 = 17;
+
+// How much the level 0 slowdown trigger moves per adjustment.
+const LEVEL_ZERO_SLOWDOWN_WRITES_TRIGGER_STEP: u64 = 4;
+
+// The number of changes that are kept for `RocksdbDatastore::get_tuning_changes`.
+const MAX_RECORDED_CHANGES: usize = 100;
+
+/// The bounds that the auto-tuner keeps rocksdb's settings within, and how
+/// often it runs. See `RocksdbDatastore::enable_auto_tuning`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AutoTuneOptions {
+    /// How often the tuner checks the datastore's statistics.
+    pub interval: Duration,
+
+    /// The smallest that memtables are shrunk to, in bytes.
+    pub min_write_buffer_size: u64,
+
+    /// The largest that memtables are grown to, in bytes.
+    pub max_write_buffer_size: u64,
+
+    /// The fewest level 0 files that writes are slowed down at.
+    pub min_level_zero_slowdown_writes_trigger: u64,
+
+    /// The most level 0 files that writes are slowed down at. This should
+    /// be lower than the number that writes are stopped at, which is 24.
+    pub max_level_zero_slowdown_writes_trigger: u64,
+
+    /// The number of runs in a row without write stalls after which the
+    /// settings are shrunk back down.
+    pub calm_runs_before_shrinking: u32,
+}
+
+impl Default for AutoTuneOptions {
+    fn default() -> Self {
+        AutoTuneOptions {
+            interval: Duration::from_secs(60),
+            min_write_buffer_size: 16_777_216,  // 16mb
+            max_write_buffer_size: 268_435_456, // 256mb
+            min_level_zero_slowdown_writes_trigger: 8,
+            max_level_zero_slowdown_writes_trigger: 20,
+            calm_runs_before_shrinking: 10,
+        }
+    }
+}
+
+impl AutoTuneOptions {
+    /// Creates new auto-tune options, which run every minute and keep
+    /// memtables between 16mb and 256mb, and the level 0 slowdown trigger
+    /// between 8 and 20 files.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how often the tuner runs.
+    ///
+    /// # Arguments
+    /// * `interval` - How often to run.
+    pub fn interval(self, interval: Duration) -> Self {
+        AutoTuneOptions { interval, ..self }
+    }
+
+    /// Sets the bounds on the size of memtables.
+    ///
+    /// # Arguments
+    /// * `min` - The smallest size, in bytes.
+    /// * `max` - The largest size, in bytes.
+    pub fn write_buffer_size(self, min: u64, max: u64) -> Self {
+        AutoTuneOptions {
+            min_write_buffer_size: min,
+            max_write_buffer_size: max,
+            ..self
+        }
+    }
+
+    /// Sets the bounds on the number of level 0 files that writes are
+    /// slowed down at.
+    ///
+    /// # Arguments
+    /// * `min` - The fewest files.
+    /// * `max` - The most files.
+    pub fn level_zero_slowdown_writes_trigger(self, min: u64, max: u64) -> Self {
+        AutoTuneOptions {
+            min_level_zero_slowdown_writes_trigger: min,
+            max_level_zero_slowdown_writes_trigger: max,
+            ..self
+        }
+    }
+
+    /// Sets the number of calm runs after which settings are shrunk.
+    ///
+    /// # Arguments
+    /// * `calm_runs_before_shrinking` - The number of runs.
+    pub fn calm_runs_before_shrinking(self, calm_runs_before_shrinking: u32) -> Self {
+        AutoTuneOptions {
+            calm_runs_before_shrinking,
+            ..self
+        }
+    }
+
+    pub(crate) fn validate(&self) -> Result<()> {
+        if self.min_write_buffer_size == 0 || self.min_write_buffer_size > self.max_write_buffer_size {
+            return Err(ValidationError::from("Invalid write buffer size bounds").into());
+        }
+
+        if self.min_level_zero_slowdown_writes_trigger == 0
+            || self.min_level_zero_slowdown_writes_trigger > self.max_level_zero_slowdown_writes_trigger
+        {
+            return Err(ValidationError::from("Invalid level 0 slowdown trigger bounds").into());
+        }
+
+        if self.calm_runs_before_shrinking == 0 {
+            return Err(ValidationError::from("Calm runs before shrinking must be greater than zero").into());
+        }
+
+        Ok(())
+    }
+}
+
+/// A change the auto-tuner made to a rocksdb setting.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TuningChange {
+    /// When the change was made.
+    pub datetime: DateTime<Utc>,
+
+    /// The name of the rocksdb option that was changed.
+    pub option: String,
+
+    /// The option's value before the change.
+    pub old_value: u64,
+
+    /// The option's value after the change.
+    pub new_value: u64,
+
+    /// Why the change was made.
+    pub reason: String,
+}
+
+// A change that's been planned, but not applied yet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlannedChange {
+    pub option: &'static str,
+    pub old_value: u64,
+    pub new_value: u64,
+    pub reason: &'static str,
+}
+
+#[derive(Debug)]
+pub struct Tuner {
+    options: AutoTuneOptions,
+    write_buffer_size: u64,
+    level_zero_slowdown_writes_trigger: u64,
+    // The number of runs in a row that writes weren't stalled.
+    calm_runs: u32,
+}
+
+impl Tuner {
+    pub fn new(options: AutoTuneOptions) -> Self {
+        Tuner {
+            options,
+            write_buffer_size: DEFAULT_WRITE_BUFFER_SIZE,
+            level_zero_slowdown_writes_trigger: DEFAULT_LEVEL_ZERO_SLOWDOWN_WRITES_TRIGGER,
+            calm_runs: 0,
+        }
+    }
+
+    // Replaces the bounds, keeping the current settings; any that are now
+    // out of bounds are brought back in on the next run.
+    pub fn set_options(&mut self, options: AutoTuneOptions) {
+        self.options = options;
+    }
+
+    // Works out which settings to change, given the current write pressure.
+    pub fn plan(&mut self, pressure: &WritePressure) -> Vec<PlannedChange> {
+        let options = self.options;
+
+        let (write_buffer_size, level_zero_slowdown_writes_trigger, reason) = if pressure.is_stalled() {
+            self.calm_runs = 0;
+            (
+                self.write_buffer_size.saturating_mul(2),
+                self.level_zero_slowdown_writes_trigger + LEVEL_ZERO_SLOWDOWN_WRITES_TRIGGER_STEP,
+                "Writes are being stalled",
+            )
+        } else {
+            self.calm_runs += 1;
+
+            if self.calm_runs >= options.calm_runs_before_shrinking {
+                self.calm_runs = 0;
+                (
+                    self.write_buffer_size / 2,
+                    self.level_zero_slowdown_writes_trigger
+                        .saturating_sub(LEVEL_ZERO_SLOWDOWN_WRITES_TRIGGER_STEP),
+                    "Writes haven't been stalled recently",
+                )
+            } else {
+                // Settings that are out of bounds are still brought back in
+                (
+                    self.write_buffer_size,
+                    self.level_zero_slowdown_writes_trigger,
+                    "The setting is outside of the configured bounds",
+                )
+            }
+        };
+
+        let write_buffer_size = clamp(
+            write_buffer_size,
+            options.min_write_buffer_size,
+            options.max_write_buffer_size,
+        );
+        let level_zero_slowdown_writes_trigger = clamp(
+            level_zero_slowdown_writes_trigger,
+            options.min_level_zero_slowdown_writes_trigger,
+            options.max_level_zero_slowdown_writes_trigger,
+        );
+
+        let mut changes = Vec::new();
+
+        if write_buffer_size != self.write_buffer_size {
+            changes.push(PlannedChange {
+                option: "write_buffer_size",
+                old_value: self.write_buffer_size,
+                new_value: write_buffer_size,
+                reason,
+            });
+        }
+
+        if level_zero_slowdown_writes_trigger != self.level_zero_slowdown_writes_trigger {
+            changes.push(PlannedChange {
+                option: "level0_slowdown_writes_trigger",
+                old_value: self.level_zero_slowdown_writes_trigger,
+                new_value: level_zero_slowdown_writes_trigger,
+                reason,
+            });
+        }
+
+        changes
+    }
+
+    // Updates the tuner's view of the settings once a change is applied.
+    pub fn commit(&mut self, change: &PlannedChange) {
+        match change.option {
+            "write_buffer_size" => self.write_buffer_size = change.new_value,
+            _ => self.level_zero_slowdown_writes_trigger = change.new_value,
+        }
+    }
+
+    // Runs the tuner once, applying any changes to every column family and
+    // recording them. Returns the number of changes made.
+    pub fn run(
+        &mut self,
+        db: &DB,
+        cf_names: &[&str],
+        pressure: &WritePressure,
+        changes: &Mutex<VecDeque<TuningChange>>,
+    ) -> Result<u64> {
+        let planned = self.plan(pressure);
+
+        for change in &planned {
+            let value = change.new_value.to_string();
+
+            for cf_name in cf_names {
+                db.set_options_cf(cf_handle(db, cf_name)?, &[(change.option, &value)])?;
+            }
+
+            self.commit(change);
+
+            let mut changes = changes.lock().unwrap();
+
+            if changes.len() == MAX_RECORDED_CHANGES {
+                changes.pop_front();
+            }
+
+            changes.push_back(TuningChange {
+                datetime: Utc::now(),
+                option: change.option.to_string(),
+                old_value: change.old_value,
+                new_value: change.new_value,
+                reason: change.reason.to_string(),
+            });
+        }
+
+        Ok(planned.len() as u64)
+    }
+}
+
+fn clamp(value: u64, low: u64, high: u64) -> u64 {
+    min(max(value, low), high)
+}
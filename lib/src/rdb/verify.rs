@@ -0,0 +1,244 @@
+//! Checks that stored entries decode cleanly, without panicking on the ones
+//! that don't.
+
+use super::bytes::{
+    read_bitmap, read_counter, read_datetime, read_history_version, read_long_sized_string, read_neighbor_id,
+    read_property_value, read_sized_string, read_type, read_unsized_string, read_uuid,
+};
+use super::managers::{HISTORY_EDGE_PROPERTY_TAG, HISTORY_EDGE_TAG, HISTORY_VERTEX_PROPERTY_TAG, HISTORY_VERTEX_TAG};
+use chrono::offset::Utc;
+use errors::{ErrorKind, Result};
+use hll::HyperLogLog;
+use models;
+use serde_json;
+use serde_json::Value as JsonValue;
+use std::io::Cursor;
+
+/// An entry that couldn't be decoded.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CorruptEntry {
+    /// The name of the column family the entry is in.
+    pub cf: String,
+
+    /// The entry's key.
+    pub key: Vec<u8>,
+
+    /// What's wrong with the entry.
+    pub reason: String,
+}
+
+// Checks that an entry decodes, returning a `Corrupt` error if it doesn't.
+pub fn check_entry(cf_name: &str, key: &[u8], value: &[u8]) -> Result<()> {
+    let mut key = Cursor::new(key);
+    let mut value = Cursor::new(value);
+
+    match cf_name {
+        "vertices:v1" => {
+            read_uuid(&mut key)?;
+            check_type(&mut value)?;
+        }
+        "edges:v1" => {
+            read_uuid(&mut key)?;
+            check_type(&mut key)?;
+            read_uuid(&mut key)?;
+            read_datetime(&mut value)?;
+        }
+        "edge_ranges:v1" | "reversed_edge_ranges:v1" => {
+            read_uuid(&mut key)?;
+            check_type(&mut key)?;
+            read_datetime(&mut key)?;
+            read_uuid(&mut key)?;
+        }
+        "vertex_properties:v1" | "cold_vertex_properties:v1" => {
+            read_uuid(&mut key)?;
+            read_unsized_string(&mut key)?;
+            check_property_value(&mut value)?;
+        }
+        "edge_properties:v1" | "cold_edge_properties:v1" => {
+            read_uuid(&mut key)?;
+            check_type(&mut key)?;
+            read_uuid(&mut key)?;
+            read_unsized_string(&mut key)?;
+            check_property_value(&mut value)?;
+        }
+        "edge_types:v1" => {
+            check_type(&mut key)?;
+            read_datetime(&mut key)?;
+            read_uuid(&mut key)?;
+            read_uuid(&mut key)?;
+        }
+        "edge_log:v1" => {
+            read_datetime(&mut key)?;
+            read_uuid(&mut key)?;
+            check_type(&mut key)?;
+            read_uuid(&mut key)?;
+        }
+        "edge_property_names:v1" => {
+            read_long_sized_string(&mut key)?;
+            read_uuid(&mut key)?;
+            check_type(&mut key)?;
+            read_uuid(&mut key)?;
+        }
+        "index_definitions:v1" => {
+            read_unsized_string(&mut key)?;
+            check_json(&mut value)?;
+        }
+        "index_entries:v1" => {
+            // The encoded values can't be checked without the index
+            // definition, but they're followed by the vertex id.
+            read_sized_string(&mut key)?;
+            let remaining = remaining(&key);
+
+            if remaining < 16 {
+                return Err(ErrorKind::Corrupt("index entry is missing the vertex id".to_string()).into());
+            }
+
+            key.set_position(key.get_ref().len() as u64);
+        }
+        "snapshot_tags:v1" => {
+            read_unsized_string(&mut key)?;
+            read_datetime(&mut value)?;
+        }
+        "vertex_type_counts:v1" | "edge_type_counts:v1" => {
+            check_type(&mut key)?;
+            read_counter(&mut value)?;
+        }
+        "idempotency_keys:v1" => {
+            // Responses are opaque, but they're stored with an expiry
+            read_unsized_string(&mut key)?;
+
+            if value.get_ref().len() < 10 || value.get_ref()[0] != 0 {
+                return Err(ErrorKind::Corrupt("idempotency record is missing its expiry".to_string()).into());
+            }
+
+            value.set_position(value.get_ref().len() as u64);
+        }
+        "inbound_neighbor_sketches:v1" => {
+            read_uuid(&mut key)?;
+            check_type(&mut key)?;
+            read_datetime(&mut key)?;
+
+            if HyperLogLog::from_bytes(value.get_ref()).is_err() {
+                return Err(ErrorKind::Corrupt("invalid sketch".to_string()).into());
+            }
+
+            value.set_position(value.get_ref().len() as u64);
+        }
+        "adjacency_bitmaps:v1" | "reversed_adjacency_bitmaps:v1" => {
+            read_uuid(&mut key)?;
+            check_type(&mut key)?;
+
+            // Empty values are bitmaps that haven't been seeded
+            if !value.get_ref().is_empty() {
+                read_bitmap(value.get_ref())?;
+            }
+
+            value.set_position(value.get_ref().len() as u64);
+        }
+        "adjacency_degrees:v1" | "reversed_adjacency_degrees:v1" => {
+            read_uuid(&mut key)?;
+            check_type(&mut key)?;
+            read_counter(&mut value)?;
+        }
+        "neighbor_ids:v1" => {
+            read_uuid(&mut key)?;
+            read_neighbor_id(value.get_ref())?;
+            value.set_position(value.get_ref().len() as u64);
+        }
+        "neighbor_id_vertices:v1" => {
+            read_neighbor_id(key.get_ref())?;
+            key.set_position(key.get_ref().len() as u64);
+            read_uuid(&mut value)?;
+        }
+        "history:v1" => check_history_version(&mut key, &mut value)?,
+        _ => return Err(ErrorKind::Corrupt(format!("unknown column family {}", cf_name)).into()),
+    }
+
+    check_end(&key, "key")?;
+    check_end(&value, "value")
+}
+
+// Types are written after being validated, so reads don't check them again.
+fn check_type(cursor: &mut Cursor<&[u8]>) -> Result<()> {
+    let t = read_type(cursor)?;
+    models::Type::new(t.0).map_err(|_| ErrorKind::Corrupt("invalid type".to_string()))?;
+    Ok(())
+}
+
+fn check_json(cursor: &mut Cursor<&[u8]>) -> Result<()> {
+    let bytes = &cursor.get_ref()[cursor.position() as usize..];
+    serde_json::from_slice::<JsonValue>(bytes)
+        .map_err(|err| ErrorKind::Corrupt(format!("invalid JSON value: {}", err)))?;
+    cursor.set_position(cursor.get_ref().len() as u64);
+    Ok(())
+}
+
+// Expired values are skipped, since compaction will drop them.
+fn check_property_value(cursor: &mut Cursor<&[u8]>) -> Result<()> {
+    let bytes = &cursor.get_ref()[cursor.position() as usize..];
+
+    if let Some(value_json) = read_property_value(bytes, Utc::now())? {
+        serde_json::from_slice::<JsonValue>(value_json)
+            .map_err(|err| ErrorKind::Corrupt(format!("invalid JSON value: {}", err)))?;
+    }
+
+    cursor.set_position(cursor.get_ref().len() as u64);
+    Ok(())
+}
+
+// The item's value is checked by its kind, unless the version records its
+// deletion. Versions that have aged out are checked anyway, since they're
+// only dropped once their retention window passes.
+fn check_history_version(key: &mut Cursor<&[u8]>, value: &mut Cursor<&[u8]>) -> Result<()> {
+    let tag = match key.get_ref().first() {
+        Some(&tag) => tag,
+        None => return Err(ErrorKind::Corrupt("truncated history item".to_string()).into()),
+    };
+    key.set_position(1);
+
+    match tag {
+        HISTORY_VERTEX_TAG | HISTORY_VERTEX_PROPERTY_TAG => {
+            read_uuid(key)?;
+        }
+        HISTORY_EDGE_TAG | HISTORY_EDGE_PROPERTY_TAG => {
+            read_uuid(key)?;
+            check_type(key)?;
+            read_uuid(key)?;
+        }
+        _ => return Err(ErrorKind::Corrupt("unknown history item".to_string()).into()),
+    }
+
+    if tag == HISTORY_VERTEX_PROPERTY_TAG || tag == HISTORY_EDGE_PROPERTY_TAG {
+        read_long_sized_string(key)?;
+    }
+
+    read_datetime(key)?;
+
+    if let (_, Some(item_value)) = read_history_version(value.get_ref())? {
+        let mut item_value = Cursor::new(item_value);
+
+        match tag {
+            HISTORY_VERTEX_TAG => check_type(&mut item_value)?,
+            HISTORY_EDGE_TAG => {
+                read_datetime(&mut item_value)?;
+            }
+            _ => check_json(&mut item_value)?,
+        }
+
+        check_end(&item_value, "history value")?;
+    }
+
+    value.set_position(value.get_ref().len() as u64);
+    Ok(())
+}
+
+fn remaining(cursor: &Cursor<&[u8]>) -> usize {
+    cursor.get_ref().len() - cursor.position() as usize
+}
+
+fn check_end(cursor: &Cursor<&[u8]>, name: &str) -> Result<()> {
+    match remaining(cursor) {
+        0 => Ok(()),
+        remaining => Err(ErrorKind::Corrupt(format!("{} has {} unexpected trailing bytes", name, remaining)).into()),
+    }
+}
@@ -0,0 +1,176 @@
+//! Runs tasks in the background at an interval, on behalf of a datastore.
+//! Each task gets its own thread, which holds only a weak reference to the
+//! database, so that background tasks never keep it open once the datastore
+//! and its transactions have been dropped.
+
+use chrono::offset::Utc;
+use chrono::DateTime;
+use errors::{ErrorKind, Result};
+use rocksdb::DB;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Metrics for a background task. See
+/// `RocksdbDatastore::get_background_task_metrics`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BackgroundTaskMetrics {
+    /// How long the task waits between runs. Tasks can also be woken early,
+    /// e.g. when work is queued for them.
+    pub interval: Duration,
+
+    /// Whether the task is paused. Paused tasks skip their runs until
+    /// they're resumed.
+    pub paused: bool,
+
+    /// The number of times the task has run.
+    pub run_count: u64,
+
+    /// The number of runs that failed.
+    pub failure_count: u64,
+
+    /// The number of items the task has processed over all of its runs,
+    /// e.g. the number of expired values swept.
+    pub processed_count: u64,
+
+    /// When the task last finished a run.
+    pub last_run_datetime: Option<DateTime<Utc>>,
+
+    /// How long the task's last run took.
+    pub last_run_duration: Option<Duration>,
+
+    /// The error the task's last run failed with, if any.
+    pub last_error: Option<String>,
+}
+
+// A task run, which returns the number of items it processed.
+pub type BackgroundTask = Box<dyn FnMut(&Arc<DB>) -> Result<u64> + Send>;
+
+#[derive(Debug)]
+enum Signal {
+    // Run the task now, rather than waiting out the interval.
+    Run,
+    // The interval changed, so start waiting again.
+    Reconfigure,
+}
+
+#[derive(Debug)]
+struct Worker {
+    metrics: Arc<Mutex<BackgroundTaskMetrics>>,
+    signals: Mutex<Sender<Signal>>,
+}
+
+// The background tasks of a datastore. Their threads exit once this is
+// dropped, or once the database is closed.
+#[derive(Debug, Default)]
+pub struct BackgroundWorkers {
+    workers: RwLock<HashMap<String, Worker>>,
+}
+
+impl BackgroundWorkers {
+    // Registers a task, and spawns the thread that runs it. A task that's
+    // already registered with the same name is replaced; its thread exits
+    // once any run in progress finishes.
+    pub fn register(&self, db: &Arc<DB>, name: &str, interval: Duration, mut task: BackgroundTask) {
+        let db = Arc::downgrade(db);
+        let metrics = Arc::new(Mutex::new(BackgroundTaskMetrics {
+            interval,
+            ..BackgroundTaskMetrics::default()
+        }));
+        let (sender, receiver) = channel();
+        let thread_metrics = metrics.clone();
+
+        thread::spawn(move || loop {
+            let interval = thread_metrics.lock().unwrap().interval;
+
+            match receiver.recv_timeout(interval) {
+                Ok(Signal::Run) | Err(RecvTimeoutError::Timeout) => (),
+                Ok(Signal::Reconfigure) => continue,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+
+            if thread_metrics.lock().unwrap().paused {
+                continue;
+            }
+
+            let result = match Weak::upgrade(&db) {
+                Some(db) => {
+                    let started_at = Instant::now();
+                    let result = task(&db);
+                    (result, started_at.elapsed())
+                }
+                None => return,
+            };
+
+            record_run(&mut thread_metrics.lock().unwrap(), result);
+        });
+
+        self.workers.write().unwrap().insert(
+            name.to_string(),
+            Worker {
+                metrics,
+                signals: Mutex::new(sender),
+            },
+        );
+    }
+
+    pub fn metrics(&self) -> BTreeMap<String, BackgroundTaskMetrics> {
+        self.workers
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, worker)| (name.clone(), worker.metrics.lock().unwrap().clone()))
+            .collect()
+    }
+
+    pub fn set_paused(&self, name: &str, paused: bool) -> Result<()> {
+        self.with_worker(name, |worker| worker.metrics.lock().unwrap().paused = paused)
+    }
+
+    pub fn set_interval(&self, name: &str, interval: Duration) -> Result<()> {
+        self.with_worker(name, |worker| {
+            worker.metrics.lock().unwrap().interval = interval;
+            send(worker, Signal::Reconfigure);
+        })
+    }
+
+    // Wakes a task so that it runs now. Paused tasks stay asleep.
+    pub fn run(&self, name: &str) -> Result<()> {
+        self.with_worker(name, |worker| send(worker, Signal::Run))
+    }
+
+    fn with_worker<F: FnOnce(&Worker)>(&self, name: &str, f: F) -> Result<()> {
+        match self.workers.read().unwrap().get(name) {
+            Some(worker) => {
+                f(worker);
+                Ok(())
+            }
+            None => Err(ErrorKind::NotFound(format!("background task {}", name)).into()),
+        }
+    }
+}
+
+fn send(worker: &Worker, signal: Signal) {
+    // The thread only exits once the database is closed, at which point
+    // there's nothing left for it to do anyway
+    let _ = worker.signals.lock().unwrap().send(signal);
+}
+
+fn record_run(metrics: &mut BackgroundTaskMetrics, (result, duration): (Result<u64>, Duration)) {
+    metrics.run_count += 1;
+    metrics.last_run_datetime = Some(Utc::now());
+    metrics.last_run_duration = Some(duration);
+
+    match result {
+        Ok(count) => {
+            metrics.processed_count += count;
+            metrics.last_error = None;
+        }
+        Err(err) => {
+            metrics.failure_count += 1;
+            metrics.last_error = Some(err.to_string());
+        }
+    }
+}
@@ -0,0 +1,464 @@
+use errors::Result;
+use models::{
+    EdgeKey, EdgeQueryExt, Event, EventFilter, EventKind, SpecificEdgeQuery, SpecificVertexQuery, Type, VertexQueryExt,
+};
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use traits::{Datastore, Transaction};
+use uuid::Uuid;
+
+/// How concurrent writes to the same edge or property on different replicas
+/// are merged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// The last write the reconciler sees wins, whether it creates, sets or
+    /// deletes the item.
+    LastWriterWins,
+
+    /// Writes are merged as a set that only grows. Edges are kept once
+    /// they've been created on any replica, and array property values are
+    /// merged into the union of their elements. Deletions are undone, and
+    /// values that aren't arrays are merged like `LastWriterWins`.
+    SetUnion,
+}
+
+/// Selects which edges and properties a `Reconciler` merges, and how. Items
+/// that don't have a policy aren't reconciled at all.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MergeOptions {
+    /// The policies for edges, by edge type.
+    pub edge_types: HashMap<Type, MergePolicy>,
+
+    /// The policies for vertex properties, by property name.
+    pub vertex_properties: HashMap<String, MergePolicy>,
+
+    /// The policies for edge properties, by property name.
+    pub edge_properties: HashMap<String, MergePolicy>,
+}
+
+impl MergeOptions {
+    /// Creates new merge options, which don't merge anything.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the policy for edges of a type.
+    ///
+    /// # Arguments
+    /// * `t` - The edge type.
+    /// * `policy` - How the edges are merged.
+    pub fn edge_type(mut self, t: Type, policy: MergePolicy) -> Self {
+        self.edge_types.insert(t, policy);
+        self
+    }
+
+    /// Sets the policy for a vertex property.
+    ///
+    /// # Arguments
+    /// * `name` - The property name.
+    /// * `policy` - How the property's values are merged.
+    pub fn vertex_property<S: Into<String>>(mut self, name: S, policy: MergePolicy) -> Self {
+        self.vertex_properties.insert(name.into(), policy);
+        self
+    }
+
+    /// Sets the policy for an edge property.
+    ///
+    /// # Arguments
+    /// * `name` - The property name.
+    /// * `policy` - How the property's values are merged.
+    pub fn edge_property<S: Into<String>>(mut self, name: S, policy: MergePolicy) -> Self {
+        self.edge_properties.insert(name.into(), policy);
+        self
+    }
+}
+
+// Merges a write to a property into its merged value, where `None` is a
+// deletion.
+fn merge_value(policy: MergePolicy, merged: Option<&Option<JsonValue>>, value: Option<JsonValue>) -> Option<JsonValue> {
+    if policy == MergePolicy::LastWriterWins {
+        return value;
+    }
+
+    match (merged, value) {
+        (Some(Some(JsonValue::Array(merged))), Some(JsonValue::Array(elements))) => {
+            let mut union = merged.clone();
+
+            for element in elements {
+                if !union.contains(&element) {
+                    union.push(element);
+                }
+            }
+
+            Some(JsonValue::Array(union))
+        }
+        (Some(Some(merged)), None) => Some(merged.clone()),
+        (_, value) => value,
+    }
+}
+
+// An edge or property that's reconciled.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+enum Item {
+    Edge(EdgeKey),
+    VertexProperty(Uuid, String),
+    EdgeProperty(EdgeKey, String),
+}
+
+// Edges are merged like properties whose value is null while they exist.
+fn edge_value(exists: bool) -> Option<JsonValue> {
+    if exists {
+        Some(JsonValue::Null)
+    } else {
+        None
+    }
+}
+
+/// Reconciles datastores that are all written to, e.g. replicas in
+/// different regions, by replaying each one's writes onto the others
+/// through their change feeds, rather than agreeing on them up front.
+///
+/// Only the edges and properties selected by the merge options are
+/// reconciled, and only once they're written after the reconciler was
+/// created. The reconciler keeps the merged state of every item it has
+/// seen, and skips the events for the writes it replays itself. This has
+/// these drawbacks:
+///
+/// * Vertices aren't reconciled, so they have to exist on every replica for
+///   the edges and properties reconciled onto it to be written.
+/// * Edges and properties that are deleted along with their vertex or edge
+///   don't have events of their own, so they aren't reconciled.
+/// * Which write is the last is decided by the order the reconciler sees
+///   them in, not by when they were made.
+pub struct Reconciler<D: Datastore> {
+    replicas: Vec<D>,
+    events: Vec<Receiver<Event>>,
+    options: MergeOptions,
+    merged: HashMap<Item, Option<JsonValue>>,
+    // The writes replayed onto each replica whose events haven't been seen
+    // yet.
+    replayed: Vec<Vec<(Item, Option<JsonValue>)>>,
+}
+
+impl<D: Datastore> Reconciler<D> {
+    /// Creates a reconciler, which subscribes to the replicas' changes.
+    ///
+    /// # Arguments
+    /// * `replicas` - The datastores to reconcile.
+    /// * `options` - Which edges and properties to merge, and how.
+    ///
+    /// # Errors
+    /// Returns an error if a replica doesn't support subscriptions.
+    pub fn new(replicas: Vec<D>, options: MergeOptions) -> Result<Self> {
+        let filter = EventFilter::new()
+            .kind(EventKind::Edge)
+            .kind(EventKind::VertexProperty)
+            .kind(EventKind::EdgeProperty);
+        let events: Result<Vec<Receiver<Event>>> = replicas
+            .iter()
+            .map(|replica| replica.subscribe(filter.clone()))
+            .collect();
+
+        Ok(Self {
+            replayed: replicas.iter().map(|_| Vec::new()).collect(),
+            replicas,
+            events: events?,
+            options,
+            merged: HashMap::new(),
+        })
+    }
+
+    /// Gets the replicas, e.g. to write to them.
+    pub fn replicas(&self) -> &[D] {
+        &self.replicas
+    }
+
+    /// Replays the writes made to each replica since the last call onto the
+    /// others, until they've all converged. Returns how many writes were
+    /// replayed.
+    ///
+    /// # Errors
+    /// Returns an error if a write can't be replayed, or if a replica has
+    /// stopped publishing events. Writes replayed before the failure are
+    /// kept, and the rest are replayed by the next call.
+    pub fn reconcile(&mut self) -> Result<u64> {
+        let mut count = 0;
+
+        // Merging a write can replay it back onto the replica it came from,
+        // so this keeps going until no replica has any events left
+        loop {
+            let mut received = false;
+
+            for i in 0..self.replicas.len() {
+                loop {
+                    match self.events[i].try_recv() {
+                        Ok(event) => {
+                            received = true;
+                            count += self.merge(i, event)?;
+                        }
+                        Err(TryRecvError::Empty) => break,
+                        Err(TryRecvError::Disconnected) => return Err("A replica stopped publishing events".into()),
+                    }
+                }
+            }
+
+            if !received {
+                return Ok(count);
+            }
+        }
+    }
+
+    // Merges a write made to a replica, and replays the merged result onto
+    // the replicas that don't have it. Returns how many writes were
+    // replayed.
+    fn merge(&mut self, source: usize, event: Event) -> Result<u64> {
+        let (item, value) = match event {
+            Event::EdgeCreated(key) => (Item::Edge(key), edge_value(true)),
+            Event::EdgeDeleted(key) => (Item::Edge(key), edge_value(false)),
+            Event::VertexPropertySet(id, name, value) => (Item::VertexProperty(id, name), Some(value)),
+            Event::VertexPropertyDeleted(id, name) => (Item::VertexProperty(id, name), None),
+            Event::EdgePropertySet(key, name, value) => (Item::EdgeProperty(key, name), Some(value)),
+            Event::EdgePropertyDeleted(key, name) => (Item::EdgeProperty(key, name), None),
+            Event::VertexCreated(_) | Event::VertexDeleted(_) => return Ok(0),
+        };
+
+        let policy = match item {
+            Item::Edge(ref key) => self.options.edge_types.get(&key.t),
+            Item::VertexProperty(_, ref name) => self.options.vertex_properties.get(name),
+            Item::EdgeProperty(_, ref name) => self.options.edge_properties.get(name),
+        };
+
+        let policy = match policy {
+            Some(policy) => *policy,
+            None => return Ok(0),
+        };
+
+        // Skips the events for writes the reconciler replayed itself
+        let replayed = &mut self.replayed[source];
+
+        if let Some(index) = replayed.iter().position(|write| write.0 == item && write.1 == value) {
+            replayed.remove(index);
+            return Ok(0);
+        }
+
+        // If a write has been replayed onto the source since this one was
+        // made, the source no longer has this write's value
+        let overwritten = replayed.iter().any(|write| write.0 == item);
+        let previous = self.merged.get(&item);
+        let merged = merge_value(policy, previous, value.clone());
+
+        if previous == Some(&merged) && merged == value && !overwritten {
+            return Ok(0);
+        }
+
+        self.merged.insert(item.clone(), merged.clone());
+        let mut count = 0;
+
+        // The source only needs the merged result if it doesn't have it
+        for i in 0..self.replicas.len() {
+            if (i != source || merged != value || overwritten) && self.replay(i, &item, &merged)? {
+                self.replayed[i].push((item.clone(), merged.clone()));
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    // Writes a merged value to a replica, returning whether it was written.
+    // Writes are skipped when they wouldn't match anything, since they
+    // wouldn't publish an event either, e.g. when the vertices they need
+    // don't exist on the replica.
+    fn replay(&self, index: usize, item: &Item, value: &Option<JsonValue>) -> Result<bool> {
+        let trans = self.replicas[index].transaction()?;
+
+        match (item, value) {
+            (Item::Edge(key), Some(_)) => trans.create_edge(key),
+            (Item::Edge(key), None) => {
+                if !trans.edge_exists(key)? {
+                    return Ok(false);
+                }
+
+                trans.delete_edges(SpecificEdgeQuery::single(key.clone()))?;
+                Ok(true)
+            }
+            (Item::VertexProperty(id, name), value) => {
+                let q = SpecificVertexQuery::single(*id).property(name.clone());
+
+                match *value {
+                    Some(ref value) => {
+                        if trans.get_vertices(SpecificVertexQuery::single(*id))?.is_empty() {
+                            return Ok(false);
+                        }
+
+                        trans.set_vertex_properties(q, value)?;
+                    }
+                    None => {
+                        if trans.get_vertex_properties(q.clone())?.is_empty() {
+                            return Ok(false);
+                        }
+
+                        trans.delete_vertex_properties(q)?;
+                    }
+                }
+
+                Ok(true)
+            }
+            (Item::EdgeProperty(key, name), value) => {
+                let q = SpecificEdgeQuery::single(key.clone()).property(name.clone());
+
+                match *value {
+                    Some(ref value) => {
+                        if !trans.edge_exists(key)? {
+                            return Ok(false);
+                        }
+
+                        trans.set_edge_properties(q, value)?;
+                    }
+                    None => {
+                        if trans.get_edge_properties(q.clone())?.is_empty() {
+                            return Ok(false);
+                        }
+
+                        trans.delete_edge_properties(q)?;
+                    }
+                }
+
+                Ok(true)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MergeOptions, MergePolicy, Reconciler};
+    use memory::MemoryDatastore;
+    use models::{EdgeKey, SpecificEdgeQuery, SpecificVertexQuery, Type, Vertex, VertexQueryExt};
+    use serde_json::Value as JsonValue;
+    use traits::{Datastore, Transaction};
+
+    // Creates the same vertices on every replica, since they aren't
+    // reconciled.
+    fn create_replicas(options: MergeOptions) -> (Reconciler<MemoryDatastore>, Vertex, Vertex) {
+        let t = Type::new("foo").unwrap();
+        let outbound_v = Vertex::new(t.clone());
+        let inbound_v = Vertex::new(t);
+        let reconciler =
+            Reconciler::new(vec![MemoryDatastore::default(), MemoryDatastore::default()], options).unwrap();
+
+        for replica in reconciler.replicas() {
+            let trans = replica.transaction().unwrap();
+            trans.create_vertex(&outbound_v).unwrap();
+            trans.create_vertex(&inbound_v).unwrap();
+        }
+
+        (reconciler, outbound_v, inbound_v)
+    }
+
+    fn strings(values: &[&str]) -> JsonValue {
+        JsonValue::Array(
+            values
+                .iter()
+                .map(|value| JsonValue::String(value.to_string()))
+                .collect(),
+        )
+    }
+
+    fn get_property(replica: &MemoryDatastore, v: &Vertex, name: &str) -> Option<JsonValue> {
+        let q = SpecificVertexQuery::single(v.id).property(name);
+        let props = replica.transaction().unwrap().get_vertex_properties(q).unwrap();
+        props.into_iter().next().map(|prop| prop.value)
+    }
+
+    #[test]
+    fn should_merge_last_writer_wins() {
+        let t = Type::new("foo").unwrap();
+        let options = MergeOptions::new()
+            .edge_type(t.clone(), MergePolicy::LastWriterWins)
+            .vertex_property("name", MergePolicy::LastWriterWins);
+        let (mut reconciler, outbound_v, inbound_v) = create_replicas(options);
+        let first = reconciler.replicas()[0].transaction().unwrap();
+        let second = reconciler.replicas()[1].transaction().unwrap();
+
+        let key = EdgeKey::new(outbound_v.id, t, inbound_v.id);
+        first.create_edge(&key).unwrap();
+        first
+            .set_vertex_properties(
+                SpecificVertexQuery::single(outbound_v.id).property("name"),
+                &JsonValue::String("a".to_string()),
+            )
+            .unwrap();
+        second
+            .set_vertex_properties(
+                SpecificVertexQuery::single(outbound_v.id).property("name"),
+                &JsonValue::String("b".to_string()),
+            )
+            .unwrap();
+        second
+            .set_vertex_properties(
+                SpecificVertexQuery::single(outbound_v.id).property("other"),
+                &JsonValue::Bool(true),
+            )
+            .unwrap();
+        // The second replica's write is seen last, so it's replayed onto
+        // both, since the first replica's write was replayed over it
+        assert_eq!(reconciler.reconcile().unwrap(), 4);
+
+        for replica in reconciler.replicas() {
+            assert!(replica.transaction().unwrap().edge_exists(&key).unwrap());
+            assert_eq!(
+                get_property(replica, &outbound_v, "name"),
+                Some(JsonValue::String("b".to_string()))
+            );
+        }
+
+        // Properties without a policy aren't reconciled
+        assert_eq!(get_property(&reconciler.replicas()[0], &outbound_v, "other"), None);
+
+        second.delete_edges(SpecificEdgeQuery::single(key.clone())).unwrap();
+        assert_eq!(reconciler.reconcile().unwrap(), 1);
+        assert!(!first.edge_exists(&key).unwrap());
+        assert_eq!(reconciler.reconcile().unwrap(), 0);
+    }
+
+    #[test]
+    fn should_merge_set_union() {
+        let t = Type::new("foo").unwrap();
+        let options = MergeOptions::new()
+            .edge_type(t.clone(), MergePolicy::SetUnion)
+            .vertex_property("tags", MergePolicy::SetUnion);
+        let (mut reconciler, outbound_v, inbound_v) = create_replicas(options);
+        let first = reconciler.replicas()[0].transaction().unwrap();
+        let second = reconciler.replicas()[1].transaction().unwrap();
+        let q = SpecificVertexQuery::single(outbound_v.id).property("tags");
+
+        first.set_vertex_properties(q.clone(), &strings(&["a", "b"])).unwrap();
+        second.set_vertex_properties(q.clone(), &strings(&["c", "a"])).unwrap();
+        reconciler.reconcile().unwrap();
+
+        for replica in reconciler.replicas() {
+            assert_eq!(
+                get_property(replica, &outbound_v, "tags"),
+                Some(strings(&["a", "b", "c"]))
+            );
+        }
+
+        // Deletions are undone
+        let key = EdgeKey::new(outbound_v.id, t, inbound_v.id);
+        first.create_edge(&key).unwrap();
+        reconciler.reconcile().unwrap();
+        second.delete_edges(SpecificEdgeQuery::single(key.clone())).unwrap();
+        second.delete_vertex_properties(q).unwrap();
+        reconciler.reconcile().unwrap();
+
+        for replica in reconciler.replicas() {
+            assert!(replica.transaction().unwrap().edge_exists(&key).unwrap());
+            assert_eq!(
+                get_property(replica, &outbound_v, "tags"),
+                Some(strings(&["a", "b", "c"]))
+            );
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use super::super::Datastore;
+use chrono::offset::Utc;
+use chrono::DateTime;
+use clock::{Clock, SystemClock};
+use errors::Result;
+use models;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, RwLock};
+
+/// How stale the results of a read are allowed to be.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReadConsistency {
+    /// Reads from the leader, which sees every committed write.
+    Leader,
+
+    /// Reads from a follower, however far behind the leader it is. This is
+    /// the cheapest read, since it never waits for a catch-up.
+    Follower,
+
+    /// Reads from a follower that has caught up with every write committed
+    /// at or before a timestamp, e.g. the time a client's own write was
+    /// committed.
+    AtLeast(DateTime<Utc>),
+}
+
+// A follower, along with when it was last caught up through the frontend.
+#[derive(Debug)]
+struct Follower<D: Datastore> {
+    datastore: D,
+    caught_up_at: RwLock<Option<DateTime<Utc>>>,
+}
+
+impl<D: Datastore> Follower<D> {
+    fn is_caught_up(&self, timestamp: DateTime<Utc>) -> bool {
+        match *self.caught_up_at.read().unwrap() {
+            Some(caught_up_at) => caught_up_at >= timestamp,
+            None => false,
+        }
+    }
+
+    // Catches the follower up, returning when it was caught up as of. Writes
+    // committed before the catch-up started are seen afterwards, so that's
+    // the datetime that's recorded.
+    fn catch_up(&self, clock: &dyn Clock) -> Result<DateTime<Utc>> {
+        let started_at = clock.now();
+        self.datastore.catch_up()?;
+        let mut caught_up_at = self.caught_up_at.write().unwrap();
+
+        // A concurrent catch-up may have started later
+        match *caught_up_at {
+            Some(previous) if previous > started_at => Ok(previous),
+            _ => {
+                *caught_up_at = Some(started_at);
+                Ok(started_at)
+            }
+        }
+    }
+}
+
+/// A datastore that writes to a leader datastore, and routes reads to it or
+/// to the read replicas that follow it, depending on how stale the reads
+/// are allowed to be.
+#[derive(Debug)]
+pub struct ReplicatedDatastore<D: Datastore> {
+    leader: D,
+    followers: Vec<Follower<D>>,
+    // The follower that the next read starts looking at, so that reads are
+    // spread across them.
+    next_follower: AtomicUsize,
+    clock: Arc<dyn Clock>,
+}
+
+impl<D: Datastore> ReplicatedDatastore<D> {
+    /// Creates a new replicated datastore. Followers aren't known to be
+    /// caught up with anything until they're first caught up, either by
+    /// `catch_up` or by a read that needs them to be.
+    ///
+    /// # Arguments
+    /// * `leader` - The datastore that's written to.
+    /// * `followers` - The datastores that follow the leader. If there
+    ///   aren't any, every read goes to the leader.
+    pub fn new(leader: D, followers: Vec<D>) -> Self {
+        Self {
+            leader,
+            followers: followers
+                .into_iter()
+                .map(|datastore| Follower {
+                    datastore,
+                    caught_up_at: RwLock::new(None),
+                })
+                .collect(),
+            next_follower: AtomicUsize::new(0),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Sets the clock used to record when followers were caught up, in place
+    /// of the system time.
+    ///
+    /// # Arguments
+    /// * `clock` - The clock to use.
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
+
+    /// Gets the leader datastore.
+    pub fn leader(&self) -> &D {
+        &self.leader
+    }
+
+    /// Creates a transaction to read with, from the leader or a follower
+    /// depending on the consistency the read needs.
+    ///
+    /// Reads that need to be at least as fresh as a timestamp go to a
+    /// follower that has been caught up since then if there is one.
+    /// Otherwise one of the followers is caught up, and used if that makes
+    /// it fresh enough; if it doesn't, because the timestamp is ahead of the
+    /// frontend's clock, the read goes to the leader.
+    ///
+    /// # Arguments
+    /// * `consistency` - How stale the read's results are allowed to be.
+    ///
+    /// # Errors
+    /// Returns an error if a follower fails to catch up.
+    pub fn read(&self, consistency: ReadConsistency) -> Result<D::Trans> {
+        if self.followers.is_empty() {
+            return self.leader.transaction();
+        }
+
+        let start = self.next_follower.fetch_add(1, Ordering::Relaxed);
+        let count = self.followers.len();
+
+        match consistency {
+            ReadConsistency::Leader => self.leader.transaction(),
+            ReadConsistency::Follower => self.followers[start % count].datastore.transaction(),
+            ReadConsistency::AtLeast(timestamp) => {
+                for i in 0..count {
+                    let follower = &self.followers[(start + i) % count];
+
+                    if follower.is_caught_up(timestamp) {
+                        return follower.datastore.transaction();
+                    }
+                }
+
+                let follower = &self.followers[start % count];
+
+                if follower.catch_up(&*self.clock)? >= timestamp {
+                    follower.datastore.transaction()
+                } else {
+                    self.leader.transaction()
+                }
+            }
+        }
+    }
+
+    /// Creates a transaction to read with that sees every write committed
+    /// at or before a timestamp. Shorthand for
+    /// `read(ReadConsistency::AtLeast(timestamp))`.
+    ///
+    /// # Arguments
+    /// * `timestamp` - The oldest writes the read needs to see.
+    ///
+    /// # Errors
+    /// Returns an error if a follower fails to catch up.
+    pub fn read_at_least(&self, timestamp: DateTime<Utc>) -> Result<D::Trans> {
+        self.read(ReadConsistency::AtLeast(timestamp))
+    }
+
+    /// Gets the datetime the freshest follower was last caught up as of
+    /// through the frontend, or `None` if none have been yet. Reads that
+    /// need to see writes up to then can be served by a follower without
+    /// catching it up.
+    pub fn get_caught_up_datetime(&self) -> Option<DateTime<Utc>> {
+        self.followers
+            .iter()
+            .filter_map(|follower| *follower.caught_up_at.read().unwrap())
+            .max()
+    }
+}
+
+impl<D: Datastore> Datastore for ReplicatedDatastore<D> {
+    type Trans = D::Trans;
+
+    fn bulk_insert<I>(&self, items: I) -> Result<()>
+    where
+        I: Iterator<Item = models::BulkInsertItem>,
+    {
+        self.leader.bulk_insert(items)
+    }
+
+    // Transactions go to the leader, so that they can be written to; reads
+    // that can go to a follower are created with `read`
+    fn transaction(&self) -> Result<Self::Trans> {
+        self.leader.transaction()
+    }
+
+    fn subscribe(&self, filter: models::EventFilter) -> Result<Receiver<models::Event>> {
+        self.leader.subscribe(filter)
+    }
+
+    fn get_idempotency_record(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.leader.get_idempotency_record(key)
+    }
+
+    fn set_idempotency_record(&self, key: &str, response: &[u8], expires_at: DateTime<Utc>) -> Result<()> {
+        self.leader.set_idempotency_record(key, response, expires_at)
+    }
+
+    fn get_write_pressure(&self) -> Result<models::WritePressure> {
+        self.leader.get_write_pressure()
+    }
+
+    // Catches up every follower, along with the leader, in case it follows
+    // another datastore itself
+    fn catch_up(&self) -> Result<()> {
+        self.leader.catch_up()?;
+
+        for follower in &self.followers {
+            follower.catch_up(&*self.clock)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ReadConsistency, ReplicatedDatastore};
+    use chrono::{Duration, Utc};
+    use clock::ManualClock;
+    use memory::MemoryDatastore;
+    use models::{SpecificVertexQuery, Type, Vertex};
+    use std::sync::Arc;
+    use traits::{Datastore, Transaction};
+
+    // The follower doesn't actually follow the leader, so which one a read
+    // went to can be told by whether it sees the leader's vertex
+    fn reads_leader(
+        datastore: &ReplicatedDatastore<MemoryDatastore>,
+        consistency: ReadConsistency,
+        v: &Vertex,
+    ) -> bool {
+        let trans = datastore.read(consistency).unwrap();
+        !trans
+            .get_vertices(SpecificVertexQuery::single(v.id))
+            .unwrap()
+            .is_empty()
+    }
+
+    #[test]
+    fn should_route_reads_by_consistency() {
+        let start = Utc::now();
+        let clock = Arc::new(ManualClock::new(start));
+        let datastore = ReplicatedDatastore::new(MemoryDatastore::default(), vec![MemoryDatastore::default()])
+            .with_clock(clock.clone());
+        let v = Vertex::new(Type::new("foo").unwrap());
+        datastore.transaction().unwrap().create_vertex(&v).unwrap();
+
+        assert!(reads_leader(&datastore, ReadConsistency::Leader, &v));
+        assert!(!reads_leader(&datastore, ReadConsistency::Follower, &v));
+        assert_eq!(datastore.get_caught_up_datetime(), None);
+
+        // Catches the follower up, since it hasn't been yet
+        assert!(!reads_leader(&datastore, ReadConsistency::AtLeast(start), &v));
+        assert_eq!(datastore.get_caught_up_datetime(), Some(start));
+
+        // A follower can't be caught up with writes from the future
+        assert!(reads_leader(
+            &datastore,
+            ReadConsistency::AtLeast(start + Duration::seconds(1)),
+            &v
+        ));
+
+        clock.advance(Duration::seconds(5));
+        assert!(!reads_leader(&datastore, ReadConsistency::AtLeast(start), &v));
+        assert_eq!(datastore.get_caught_up_datetime(), Some(start));
+        datastore.read_at_least(start + Duration::seconds(1)).unwrap();
+        assert_eq!(datastore.get_caught_up_datetime(), Some(start + Duration::seconds(5)));
+    }
+
+    #[test]
+    fn should_read_from_leader_without_followers() {
+        let datastore = ReplicatedDatastore::new(MemoryDatastore::default(), Vec::new());
+        let v = Vertex::new(Type::new("foo").unwrap());
+        datastore.transaction().unwrap().create_vertex(&v).unwrap();
+        assert!(reads_leader(&datastore, ReadConsistency::Follower, &v));
+        assert!(reads_leader(&datastore, ReadConsistency::AtLeast(Utc::now()), &v));
+    }
+}
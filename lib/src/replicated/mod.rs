@@ -0,0 +1,27 @@
+//! A datastore frontend that routes reads between a leader datastore and
+//! read replicas that follow it, e.g. rocksdb secondary instances following
+//! their primary. Writes always go to the leader. Each read chooses how
+//! stale its results are allowed to be: reads routed to the leader see every
+//! committed write, reads routed to a follower are cheaper but may lag
+//! behind, and `read_at_least` bounds the lag by a timestamp, catching a
+//! follower up if none is fresh enough. This has these drawbacks:
+//!
+//! * A follower is only known to be caught up as of the last time it was
+//!   caught up through the frontend. Catch-ups it makes on its own, e.g. the
+//!   rocksdb secondaries' `catch_up` background task, aren't counted.
+//! * Staleness is measured with the frontend's clock, so timestamps passed
+//!   to `read_at_least` should come from the same clock as the writes they
+//!   follow, or from one that isn't ahead of it.
+//! * Transactions created for reads can also be written to, but followers
+//!   are usually read-only, so writes made through them fail.
+
+mod datastore;
+
+pub use self::datastore::{ReadConsistency, ReplicatedDatastore};
+
+#[cfg(feature = "test-suite")]
+full_test_impl!({
+    use memory::MemoryDatastore;
+
+    ReplicatedDatastore::new(MemoryDatastore::default(), Vec::new())
+});
@@ -0,0 +1,758 @@
+use super::super::{Datastore, EdgePropertyQuery, EdgeQuery, Transaction, VertexPropertyQuery, VertexQuery};
+use chrono::offset::Utc;
+use chrono::DateTime;
+use errors::Result;
+use models;
+use models::{EdgeQueryExt, VertexQueryExt};
+use serde_json::Value as JsonValue;
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+// Maps a vertex ID to the index of the shard that owns it. The UUID space is
+// split into `count` contiguous ranges based on the first two bytes of the
+// ID, which keeps vertex range queries ordered across shards.
+fn shard_index(id: Uuid, count: usize) -> usize {
+    let bytes = id.as_bytes();
+    let prefix = (u64::from(bytes[0]) << 8) | u64::from(bytes[1]);
+    ((prefix * count as u64) >> 16) as usize
+}
+
+/// A datastore that partitions vertices across multiple underlying
+/// datastores by vertex ID range.
+#[derive(Debug)]
+pub struct ShardedDatastore<D: Datastore> {
+    shards: Vec<D>,
+}
+
+impl<D: Datastore> ShardedDatastore<D> {
+    /// Creates a new sharded datastore.
+    ///
+    /// # Arguments
+    /// * `shards` - The underlying datastores. Vertices are assigned to them
+    ///   by ID range, in the order given. Changing the number or order of
+    ///   shards for an existing graph will make data unreachable.
+    ///
+    /// # Panics
+    /// Panics if `shards` is empty.
+    pub fn new(shards: Vec<D>) -> Self {
+        assert!(!shards.is_empty(), "Expected at least one shard");
+        Self { shards }
+    }
+}
+
+impl<D: Datastore> Datastore for ShardedDatastore<D> {
+    type Trans = ShardedTransaction<D>;
+
+    fn bulk_insert<I>(&self, items: I) -> Result<()>
+    where
+        I: Iterator<Item = models::BulkInsertItem>,
+    {
+        let count = self.shards.len();
+        let mut shard_items: Vec<Vec<models::BulkInsertItem>> = (0..count).map(|_| Vec::new()).collect();
+
+        for item in items {
+            match item {
+                models::BulkInsertItem::Vertex(ref vertex) => {
+                    shard_items[shard_index(vertex.id, count)].push(item.clone());
+                }
+                models::BulkInsertItem::Edge(ref key) => {
+                    let outbound_index = shard_index(key.outbound_id, count);
+                    let inbound_index = shard_index(key.inbound_id, count);
+                    shard_items[outbound_index].push(item.clone());
+
+                    if inbound_index != outbound_index {
+                        shard_items[inbound_index].push(item.clone());
+                    }
+                }
+                models::BulkInsertItem::VertexProperty(id, _, _) => {
+                    shard_items[shard_index(id, count)].push(item.clone());
+                }
+                models::BulkInsertItem::EdgeProperty(ref key, _, _) => {
+                    shard_items[shard_index(key.outbound_id, count)].push(item.clone());
+                }
+            }
+        }
+
+        for (shard, items) in self.shards.iter().zip(shard_items) {
+            if !items.is_empty() {
+                shard.bulk_insert(items.into_iter())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn transaction(&self) -> Result<Self::Trans> {
+        let transactions: Result<Vec<D::Trans>> = self.shards.iter().map(|shard| shard.transaction()).collect();
+
+        Ok(ShardedTransaction {
+            transactions: transactions?,
+        })
+    }
+
+    // Writes to any shard can stall, so this reports the worst of them
+    fn get_write_pressure(&self) -> Result<models::WritePressure> {
+        let mut pressure = models::WritePressure::default();
+
+        for shard in self.shards.iter() {
+            let shard_pressure = shard.get_write_pressure()?;
+            pressure.stopped |= shard_pressure.stopped;
+            pressure.pending_compaction_bytes += shard_pressure.pending_compaction_bytes;
+
+            pressure.delayed_write_rate = match (pressure.delayed_write_rate, shard_pressure.delayed_write_rate) {
+                (Some(rate), Some(shard_rate)) => Some(rate.min(shard_rate)),
+                (rate, shard_rate) => rate.or(shard_rate),
+            };
+        }
+
+        Ok(pressure)
+    }
+
+    fn catch_up(&self) -> Result<()> {
+        for shard in self.shards.iter() {
+            shard.catch_up()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A transaction for manipulating sharded datastores.
+#[derive(Debug)]
+pub struct ShardedTransaction<D: Datastore> {
+    transactions: Vec<D::Trans>,
+}
+
+impl<D: Datastore> ShardedTransaction<D> {
+    fn shard_index(&self, id: Uuid) -> usize {
+        shard_index(id, self.transactions.len())
+    }
+
+    fn group_by_shard<T, F>(&self, items: Vec<T>, f: F) -> Vec<Vec<T>>
+    where
+        F: Fn(&T) -> Uuid,
+    {
+        let mut groups: Vec<Vec<T>> = self.transactions.iter().map(|_| Vec::new()).collect();
+
+        for item in items {
+            let index = self.shard_index(f(&item));
+            groups[index].push(item);
+        }
+
+        groups
+    }
+
+    fn vertex_exists(&self, id: Uuid) -> Result<bool> {
+        let trans = &self.transactions[self.shard_index(id)];
+        Ok(!trans.get_vertices(models::SpecificVertexQuery::single(id))?.is_empty())
+    }
+
+    // Creates an edge with a given update datetime, or the current one if
+    // there isn't one.
+    fn create_edge_on_shards(&self, key: &models::EdgeKey, update_datetime: Option<DateTime<Utc>>) -> Result<bool> {
+        let outbound_index = self.shard_index(key.outbound_id);
+        let inbound_index = self.shard_index(key.inbound_id);
+
+        if outbound_index == inbound_index {
+            let trans = &self.transactions[outbound_index];
+
+            return match update_datetime {
+                Some(update_datetime) => trans.create_edge_with_datetime(key, update_datetime),
+                None => trans.create_edge(key),
+            };
+        }
+
+        if !self.vertex_exists(key.outbound_id)? || !self.vertex_exists(key.inbound_id)? {
+            return Ok(false);
+        }
+
+        let create_mirrored_edge = |trans: &D::Trans, vertex_id: Uuid| match update_datetime {
+            Some(update_datetime) => trans.create_mirrored_edge_with_datetime(key, vertex_id, update_datetime),
+            None => trans.create_mirrored_edge(key, vertex_id),
+        };
+
+        // Neither shard has both vertices, so each one only checks the vertex
+        // it owns. If the inbound copy can't be written, the outbound one is
+        // removed again, unless it was already there.
+        let outbound_trans = &self.transactions[outbound_index];
+        let existed = outbound_trans.edge_exists(key)?;
+
+        if !create_mirrored_edge(outbound_trans, key.outbound_id)? {
+            return Ok(false);
+        }
+
+        let inbound_result = create_mirrored_edge(&self.transactions[inbound_index], key.inbound_id);
+
+        match inbound_result {
+            Ok(true) => Ok(true),
+            _ if existed => inbound_result,
+            _ => {
+                outbound_trans.delete_edges(models::SpecificEdgeQuery::single(key.clone()))?;
+                inbound_result
+            }
+        }
+    }
+
+    fn get_vertices_by_ids(&self, ids: Vec<Uuid>) -> Result<Vec<models::Vertex>> {
+        let mut found = HashMap::new();
+
+        for (trans, shard_ids) in self.transactions.iter().zip(self.group_by_shard(ids.clone(), |id| *id)) {
+            if !shard_ids.is_empty() {
+                for vertex in trans.get_vertices(models::SpecificVertexQuery::new(shard_ids))? {
+                    found.insert(vertex.id, vertex);
+                }
+            }
+        }
+
+        Ok(ids.into_iter().filter_map(|id| found.get(&id).cloned()).collect())
+    }
+
+    fn get_vertices_by_query(&self, q: VertexQuery) -> Result<Vec<models::Vertex>> {
+        match q {
+            VertexQuery::Range(range) => {
+                let start_index = range.start_id.map_or(0, |start_id| self.shard_index(start_id));
+                let mut results = Vec::new();
+
+                for (i, trans) in self.transactions.iter().enumerate().skip(start_index) {
+                    if results.len() >= range.limit as usize {
+                        break;
+                    }
+
+                    let mut shard_range = models::RangeVertexQuery::new(range.limit - results.len() as u32);
+
+                    if let Some(ref t) = range.t {
+                        shard_range = shard_range.t(t.clone());
+                    }
+
+                    // Later shards only contain greater IDs, so the lower
+                    // bound only needs to be passed to the first one
+                    if i == start_index {
+                        if let Some(start_id) = range.start_id {
+                            shard_range = shard_range.start_id(start_id);
+                        }
+                    }
+
+                    results.extend(trans.get_vertices(shard_range)?);
+                }
+
+                Ok(results)
+            }
+            VertexQuery::Specific(specific) => self.get_vertices_by_ids(specific.ids),
+            VertexQuery::Pipe(pipe) => {
+                let direction = pipe.direction;
+                let mut seen_ids = HashSet::new();
+                let ids = self
+                    .get_edges_by_query(*pipe.inner)?
+                    .into_iter()
+                    .flat_map(|edge| match direction {
+                        models::EdgeDirection::Outbound => vec![edge.key.outbound_id],
+                        models::EdgeDirection::Inbound => vec![edge.key.inbound_id],
+                        models::EdgeDirection::Both => vec![edge.key.outbound_id, edge.key.inbound_id],
+                    })
+                    .filter(|id| direction != models::EdgeDirection::Both || seen_ids.insert(*id))
+                    .collect();
+
+                let iter = self.get_vertices_by_ids(ids)?.into_iter();
+
+                let results = match pipe.t {
+                    Some(ref t) => iter.filter(|v| &v.t == t).take(pipe.limit as usize).collect(),
+                    None => iter.take(pipe.limit as usize).collect(),
+                };
+
+                Ok(results)
+            }
+        }
+    }
+
+    fn get_edges_by_query(&self, q: EdgeQuery) -> Result<Vec<models::Edge>> {
+        match q {
+            EdgeQuery::Specific(specific) => {
+                let mut found = HashMap::new();
+                let groups = self.group_by_shard(specific.keys.clone(), |key| key.outbound_id);
+
+                for (trans, keys) in self.transactions.iter().zip(groups) {
+                    if !keys.is_empty() {
+                        for edge in trans.get_edges(models::SpecificEdgeQuery::new(keys))? {
+                            found.insert(edge.key.clone(), edge);
+                        }
+                    }
+                }
+
+                Ok(specific
+                    .keys
+                    .into_iter()
+                    .filter_map(|key| found.get(&key).cloned())
+                    .collect())
+            }
+            EdgeQuery::Pipe(pipe) => {
+                let ids = self
+                    .get_vertices_by_query(*pipe.inner)?
+                    .into_iter()
+                    .map(|vertex| vertex.id)
+                    .collect();
+                let mut results = Vec::new();
+                let mut seen_keys = HashSet::new();
+
+                // Every shard holds all of the edges touching the vertices it
+                // owns, in either direction, so each vertex's edges can be
+                // fetched from its own shard
+                for (trans, shard_ids) in self.transactions.iter().zip(self.group_by_shard(ids, |id| *id)) {
+                    if results.len() >= pipe.limit as usize {
+                        break;
+                    }
+
+                    if shard_ids.is_empty() {
+                        continue;
+                    }
+
+                    let shard_pipe = models::PipeEdgeQuery {
+                        inner: Box::new(models::SpecificVertexQuery::new(shard_ids).into()),
+                        direction: pipe.direction,
+                        limit: pipe.limit - results.len() as u32,
+                        t: pipe.t.clone(),
+                        high: pipe.high,
+                        low: pipe.low,
+                    };
+
+                    for edge in trans.get_edges(shard_pipe)? {
+                        // An edge between vertices on different shards is
+                        // returned by both when piping in both directions
+                        if pipe.direction != models::EdgeDirection::Both || seen_keys.insert(edge.key.clone()) {
+                            results.push(edge);
+                        }
+                    }
+                }
+
+                Ok(results)
+            }
+        }
+    }
+}
+
+impl<D: Datastore> Transaction for ShardedTransaction<D> {
+    fn create_vertex(&self, vertex: &models::Vertex) -> Result<bool> {
+        self.transactions[self.shard_index(vertex.id)].create_vertex(vertex)
+    }
+
+    fn upsert_vertex(&self, vertex: &models::Vertex) -> Result<Option<models::Type>> {
+        self.transactions[self.shard_index(vertex.id)].upsert_vertex(vertex)
+    }
+
+    fn get_vertices<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<Vec<models::Vertex>> {
+        self.get_vertices_by_query(q.into())
+    }
+
+    fn filter_existing_vertices(&self, ids: Vec<Uuid>) -> Result<Vec<bool>> {
+        let mut results = vec![false; ids.len()];
+        let indexed_ids: Vec<(usize, Uuid)> = ids.into_iter().enumerate().collect();
+
+        for (trans, shard_ids) in self
+            .transactions
+            .iter()
+            .zip(self.group_by_shard(indexed_ids, |(_, id)| *id))
+        {
+            if shard_ids.is_empty() {
+                continue;
+            }
+
+            let (indexes, shard_ids): (Vec<usize>, Vec<Uuid>) = shard_ids.into_iter().unzip();
+
+            for (i, exists) in indexes.into_iter().zip(trans.filter_existing_vertices(shard_ids)?) {
+                results[i] = exists;
+            }
+        }
+
+        Ok(results)
+    }
+
+    fn delete_vertices<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<()> {
+        for vertex in self.get_vertices_by_query(q.into())? {
+            let index = self.shard_index(vertex.id);
+            let trans = &self.transactions[index];
+            let q = models::SpecificVertexQuery::single(vertex.id);
+
+            // Deleting the vertex from its own shard takes care of every edge
+            // stored there, but the mirrored copies of edges that cross
+            // shards need to be cleaned up separately
+            let mut edges = trans.get_edges(q.clone().outbound(u32::MAX))?;
+            edges.extend(trans.get_edges(q.clone().inbound(u32::MAX))?);
+
+            for edge in edges {
+                let other_id = if edge.key.outbound_id == vertex.id {
+                    edge.key.inbound_id
+                } else {
+                    edge.key.outbound_id
+                };
+
+                let other_index = self.shard_index(other_id);
+
+                if other_index != index {
+                    self.transactions[other_index].delete_mirrored_edges(vec![edge.key])?;
+                }
+            }
+
+            trans.delete_vertices(q)?;
+        }
+
+        Ok(())
+    }
+
+    fn get_vertex_count(&self) -> Result<u64> {
+        let mut count = 0;
+
+        for trans in &self.transactions {
+            count += trans.get_vertex_count()?;
+        }
+
+        Ok(count)
+    }
+
+    fn create_edge(&self, key: &models::EdgeKey) -> Result<bool> {
+        self.create_edge_on_shards(key, None)
+    }
+
+    fn create_edge_with_datetime(&self, key: &models::EdgeKey, update_datetime: DateTime<Utc>) -> Result<bool> {
+        self.create_edge_on_shards(key, Some(update_datetime))
+    }
+
+    fn get_edges<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<Vec<models::Edge>> {
+        self.get_edges_by_query(q.into())
+    }
+
+    fn edge_exists(&self, key: &models::EdgeKey) -> Result<bool> {
+        self.transactions[self.shard_index(key.outbound_id)].edge_exists(key)
+    }
+
+    fn filter_existing_edges(&self, keys: Vec<models::EdgeKey>) -> Result<Vec<bool>> {
+        let mut results = vec![false; keys.len()];
+        let indexed_keys: Vec<(usize, models::EdgeKey)> = keys.into_iter().enumerate().collect();
+
+        for (trans, shard_keys) in self
+            .transactions
+            .iter()
+            .zip(self.group_by_shard(indexed_keys, |(_, key)| key.outbound_id))
+        {
+            if shard_keys.is_empty() {
+                continue;
+            }
+
+            let (indexes, shard_keys): (Vec<usize>, Vec<models::EdgeKey>) = shard_keys.into_iter().unzip();
+
+            for (i, exists) in indexes.into_iter().zip(trans.filter_existing_edges(shard_keys)?) {
+                results[i] = exists;
+            }
+        }
+
+        Ok(results)
+    }
+
+    fn delete_edges<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<()> {
+        let mut shard_keys: Vec<Vec<models::EdgeKey>> = self.transactions.iter().map(|_| Vec::new()).collect();
+        let mut mirrored_keys: Vec<Vec<models::EdgeKey>> = self.transactions.iter().map(|_| Vec::new()).collect();
+
+        for edge in self.get_edges_by_query(q.into())? {
+            let outbound_index = self.shard_index(edge.key.outbound_id);
+            let inbound_index = self.shard_index(edge.key.inbound_id);
+
+            if inbound_index != outbound_index {
+                mirrored_keys[inbound_index].push(edge.key.clone());
+            }
+
+            shard_keys[outbound_index].push(edge.key);
+        }
+
+        for ((trans, keys), mirrored_keys) in self.transactions.iter().zip(shard_keys).zip(mirrored_keys) {
+            if !keys.is_empty() {
+                trans.delete_edges(models::SpecificEdgeQuery::new(keys))?;
+            }
+
+            if !mirrored_keys.is_empty() {
+                trans.delete_mirrored_edges(mirrored_keys)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Each shard checks the update datetimes of the edges it owns, and the
+    // mirrored copies of the ones it deleted are then deleted too.
+    fn delete_edges_unmodified_since<Q: Into<models::EdgeQuery>>(&self, q: Q, since: DateTime<Utc>) -> Result<u64> {
+        let keys = self
+            .get_edges_by_query(q.into())?
+            .into_iter()
+            .map(|edge| edge.key)
+            .collect();
+        let mut count = 0;
+
+        for (trans, keys) in self
+            .transactions
+            .iter()
+            .zip(self.group_by_shard(keys, |key| key.outbound_id))
+        {
+            if keys.is_empty() {
+                continue;
+            }
+
+            count += trans.delete_edges_unmodified_since(models::SpecificEdgeQuery::new(keys.clone()), since)?;
+
+            let remaining = trans.filter_existing_edges(keys.clone())?;
+            let mut mirrored_keys: Vec<Vec<models::EdgeKey>> = self.transactions.iter().map(|_| Vec::new()).collect();
+
+            for (key, remains) in keys.into_iter().zip(remaining) {
+                let inbound_index = self.shard_index(key.inbound_id);
+
+                if !remains && inbound_index != self.shard_index(key.outbound_id) {
+                    mirrored_keys[inbound_index].push(key);
+                }
+            }
+
+            for (trans, mirrored_keys) in self.transactions.iter().zip(mirrored_keys) {
+                if !mirrored_keys.is_empty() {
+                    trans.delete_mirrored_edges(mirrored_keys)?;
+                }
+            }
+        }
+
+        Ok(count)
+    }
+
+    fn get_edge_count(&self, id: Uuid, t: Option<&models::Type>, direction: models::EdgeDirection) -> Result<u64> {
+        self.transactions[self.shard_index(id)].get_edge_count(id, t, direction)
+    }
+
+    fn get_edge_count_in_range(
+        &self,
+        id: Uuid,
+        t: Option<&models::Type>,
+        low: Option<DateTime<Utc>>,
+        high: Option<DateTime<Utc>>,
+        direction: models::EdgeDirection,
+    ) -> Result<u64> {
+        self.transactions[self.shard_index(id)].get_edge_count_in_range(id, t, low, high, direction)
+    }
+
+    fn get_vertex_properties(&self, q: VertexPropertyQuery) -> Result<Vec<models::VertexProperty>> {
+        let ids: Vec<Uuid> = self.get_vertices_by_query(q.inner)?.into_iter().map(|v| v.id).collect();
+        let mut found = HashMap::new();
+
+        for (trans, shard_ids) in self.transactions.iter().zip(self.group_by_shard(ids.clone(), |id| *id)) {
+            if !shard_ids.is_empty() {
+                let shard_q = models::SpecificVertexQuery::new(shard_ids).property(q.name.clone());
+
+                for property in trans.get_vertex_properties(shard_q)? {
+                    found.insert(property.id, property);
+                }
+            }
+        }
+
+        Ok(ids.into_iter().filter_map(|id| found.remove(&id)).collect())
+    }
+
+    fn set_vertex_properties(&self, q: VertexPropertyQuery, value: &JsonValue) -> Result<()> {
+        let ids: Vec<Uuid> = self.get_vertices_by_query(q.inner)?.into_iter().map(|v| v.id).collect();
+
+        for (trans, shard_ids) in self.transactions.iter().zip(self.group_by_shard(ids, |id| *id)) {
+            if !shard_ids.is_empty() {
+                let shard_q = models::SpecificVertexQuery::new(shard_ids).property(q.name.clone());
+                trans.set_vertex_properties(shard_q, value)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn delete_vertex_properties(&self, q: VertexPropertyQuery) -> Result<()> {
+        let ids: Vec<Uuid> = self.get_vertices_by_query(q.inner)?.into_iter().map(|v| v.id).collect();
+
+        for (trans, shard_ids) in self.transactions.iter().zip(self.group_by_shard(ids, |id| *id)) {
+            if !shard_ids.is_empty() {
+                let shard_q = models::SpecificVertexQuery::new(shard_ids).property(q.name.clone());
+                trans.delete_vertex_properties(shard_q)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn get_edge_properties(&self, q: EdgePropertyQuery) -> Result<Vec<models::EdgeProperty>> {
+        let keys: Vec<models::EdgeKey> = self.get_edges_by_query(q.inner)?.into_iter().map(|e| e.key).collect();
+        let mut found = HashMap::new();
+
+        for (trans, shard_keys) in self
+            .transactions
+            .iter()
+            .zip(self.group_by_shard(keys.clone(), |key| key.outbound_id))
+        {
+            if !shard_keys.is_empty() {
+                let shard_q = models::SpecificEdgeQuery::new(shard_keys).property(q.name.clone());
+
+                for property in trans.get_edge_properties(shard_q)? {
+                    found.insert(property.key.clone(), property);
+                }
+            }
+        }
+
+        Ok(keys.into_iter().filter_map(|key| found.remove(&key)).collect())
+    }
+
+    fn set_edge_properties(&self, q: EdgePropertyQuery, value: &JsonValue) -> Result<()> {
+        let keys: Vec<models::EdgeKey> = self.get_edges_by_query(q.inner)?.into_iter().map(|e| e.key).collect();
+
+        for (trans, shard_keys) in self
+            .transactions
+            .iter()
+            .zip(self.group_by_shard(keys, |key| key.outbound_id))
+        {
+            if !shard_keys.is_empty() {
+                let shard_q = models::SpecificEdgeQuery::new(shard_keys).property(q.name.clone());
+                trans.set_edge_properties(shard_q, value)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn delete_edge_properties(&self, q: EdgePropertyQuery) -> Result<()> {
+        let keys: Vec<models::EdgeKey> = self.get_edges_by_query(q.inner)?.into_iter().map(|e| e.key).collect();
+
+        for (trans, shard_keys) in self
+            .transactions
+            .iter()
+            .zip(self.group_by_shard(keys, |key| key.outbound_id))
+        {
+            if !shard_keys.is_empty() {
+                let shard_q = models::SpecificEdgeQuery::new(shard_keys).property(q.name.clone());
+                trans.delete_edge_properties(shard_q)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn get_all_vertex_properties<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<Vec<models::VertexProperties>> {
+        let ids: Vec<Uuid> = self
+            .get_vertices_by_query(q.into())?
+            .into_iter()
+            .map(|vertex| vertex.id)
+            .collect();
+        let mut found = HashMap::new();
+
+        for (trans, shard_ids) in self.transactions.iter().zip(self.group_by_shard(ids.clone(), |id| *id)) {
+            if !shard_ids.is_empty() {
+                for properties in trans.get_all_vertex_properties(models::SpecificVertexQuery::new(shard_ids))? {
+                    found.insert(properties.vertex.id, properties);
+                }
+            }
+        }
+
+        Ok(ids.into_iter().filter_map(|id| found.get(&id).cloned()).collect())
+    }
+
+    fn get_all_edge_properties<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<Vec<models::EdgeProperties>> {
+        let keys: Vec<models::EdgeKey> = self
+            .get_edges_by_query(q.into())?
+            .into_iter()
+            .map(|edge| edge.key)
+            .collect();
+        let mut found = HashMap::new();
+
+        // Edge properties live on the shard that owns the outbound vertex
+        for (trans, shard_keys) in self
+            .transactions
+            .iter()
+            .zip(self.group_by_shard(keys.clone(), |key| key.outbound_id))
+        {
+            if !shard_keys.is_empty() {
+                for properties in trans.get_all_edge_properties(models::SpecificEdgeQuery::new(shard_keys))? {
+                    found.insert(properties.edge.key.clone(), properties);
+                }
+            }
+        }
+
+        Ok(keys.into_iter().filter_map(|key| found.get(&key).cloned()).collect())
+    }
+
+    fn get_keys_scanned(&self) -> Option<u64> {
+        self.transactions.iter().map(|trans| trans.get_keys_scanned()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{shard_index, ShardedDatastore};
+    use chrono::offset::Utc;
+    use chrono::Duration;
+    use memory::MemoryDatastore;
+    use models::{EdgeKey, SpecificEdgeQuery, SpecificVertexQuery, Type, Vertex, VertexQueryExt};
+    use traits::{Datastore, Transaction};
+    use uuid::Uuid;
+
+    #[test]
+    fn should_assign_shards_by_id_range() {
+        assert_eq!(shard_index(Uuid::nil(), 4), 0);
+        assert_eq!(
+            shard_index(Uuid::parse_str("3fffffff-ffff-ffff-ffff-ffffffffffff").unwrap(), 4),
+            0
+        );
+        assert_eq!(
+            shard_index(Uuid::parse_str("40000000-0000-0000-0000-000000000000").unwrap(), 4),
+            1
+        );
+        assert_eq!(
+            shard_index(Uuid::parse_str("ffffffff-ffff-ffff-ffff-ffffffffffff").unwrap(), 4),
+            3
+        );
+        assert_eq!(
+            shard_index(Uuid::parse_str("ffffffff-ffff-ffff-ffff-ffffffffffff").unwrap(), 1),
+            0
+        );
+    }
+
+    #[test]
+    fn should_backdate_and_conditionally_delete_edges_across_shards() {
+        let datastore = ShardedDatastore::new(vec![MemoryDatastore::default(), MemoryDatastore::default()]);
+        let trans = datastore.transaction().unwrap();
+        let t = Type::new("foo").unwrap();
+        let outbound_v = Vertex::with_id(
+            Uuid::parse_str("00000000-0000-0000-0000-000000000001").unwrap(),
+            t.clone(),
+        );
+        let inbound_v = Vertex::with_id(
+            Uuid::parse_str("ffffffff-0000-0000-0000-000000000001").unwrap(),
+            t.clone(),
+        );
+        trans.create_vertex(&outbound_v).unwrap();
+        trans.create_vertex(&inbound_v).unwrap();
+
+        let now = Utc::now();
+        let key = EdgeKey::new(outbound_v.id, t, inbound_v.id);
+        assert!(trans.create_edge_with_datetime(&key, now - Duration::days(2)).unwrap());
+
+        // Both the outbound copy and the mirrored inbound one are backdated
+        let outbound_edges = trans.get_edges(SpecificEdgeQuery::single(key.clone())).unwrap();
+        assert_eq!(outbound_edges[0].created_datetime, now - Duration::days(2));
+        let inbound_edges = trans
+            .get_edges(SpecificVertexQuery::single(inbound_v.id).inbound(10))
+            .unwrap();
+        assert_eq!(inbound_edges[0].created_datetime, now - Duration::days(2));
+
+        let q = SpecificVertexQuery::single(outbound_v.id).outbound(10);
+        assert_eq!(
+            trans
+                .delete_edges_unmodified_since(q.clone(), now - Duration::days(3))
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            trans
+                .delete_edges_unmodified_since(q.clone(), now - Duration::days(1))
+                .unwrap(),
+            1
+        );
+        assert!(trans.get_edges(q).unwrap().is_empty());
+        assert!(trans
+            .get_edges(SpecificVertexQuery::single(inbound_v.id).inbound(10))
+            .unwrap()
+            .is_empty());
+    }
+}
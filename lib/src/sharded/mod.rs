@@ -0,0 +1,28 @@
+//! A datastore frontend that partitions a graph across multiple underlying
+//! datastores. Vertices are assigned to shards by UUID range, so range
+//! queries can walk the shards in order. Edges are stored on the shard that
+//! owns their outbound vertex, and mirrored onto the shard that owns their
+//! inbound vertex so that inbound queries can be answered by a single shard.
+//! This has these drawbacks:
+//!
+//! * Operations that touch multiple shards are not atomic.
+//! * Edges between vertices on different shards are written through the
+//!   shards' `create_mirrored_edge` and `create_mirrored_edge_with_datetime`
+//!   implementations, so the underlying datastores need to support them.
+//! * Edge properties live only on the shard that owns the outbound vertex.
+
+mod datastore;
+
+pub use self::datastore::{ShardedDatastore, ShardedTransaction};
+
+#[cfg(feature = "test-suite")]
+full_test_impl!({
+    use memory::MemoryDatastore;
+
+    ShardedDatastore::new(vec![
+        MemoryDatastore::default(),
+        MemoryDatastore::default(),
+        MemoryDatastore::default(),
+        MemoryDatastore::default(),
+    ])
+});
@@ -0,0 +1,866 @@
+use super::super::{Datastore, EdgePropertyQuery, EdgeQuery, Transaction, VertexPropertyQuery, VertexQuery};
+use chrono::offset::Utc;
+use chrono::DateTime;
+use clock::{Clock, SystemClock};
+use errors::{ErrorKind, Result, ValidationError};
+use models;
+use rusqlite::types::ToSql;
+use rusqlite::{Connection, OptionalExtension, Row, NO_PARAMS};
+use serde_json;
+use serde_json::Value as JsonValue;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use traits::missing_edge_vertices;
+use util::{datetime_from_signed_nanos, signed_nanos_since_epoch};
+use uuid::Uuid;
+
+// How long to wait for other processes to release their locks on the
+// database before failing.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+// The tables mirror the rocksdb column families. Edges are stored once, with
+// covering indexes that play the part of the edge range column families, so
+// that traversals in either direction are answered from an index alone.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS vertices (
+        id BLOB NOT NULL PRIMARY KEY,
+        t TEXT NOT NULL
+    ) WITHOUT ROWID;
+
+    CREATE TABLE IF NOT EXISTS edges (
+        outbound_id BLOB NOT NULL,
+        t TEXT NOT NULL,
+        inbound_id BLOB NOT NULL,
+        update_datetime INTEGER NOT NULL,
+        PRIMARY KEY (outbound_id, t, inbound_id)
+    ) WITHOUT ROWID;
+
+    CREATE INDEX IF NOT EXISTS edge_ranges
+        ON edges (outbound_id, t, update_datetime DESC, inbound_id);
+
+    CREATE INDEX IF NOT EXISTS reversed_edge_ranges
+        ON edges (inbound_id, t, update_datetime DESC, outbound_id);
+
+    CREATE TABLE IF NOT EXISTS vertex_properties (
+        vertex_id BLOB NOT NULL,
+        name TEXT NOT NULL,
+        value TEXT NOT NULL,
+        PRIMARY KEY (vertex_id, name)
+    ) WITHOUT ROWID;
+
+    CREATE TABLE IF NOT EXISTS edge_properties (
+        outbound_id BLOB NOT NULL,
+        t TEXT NOT NULL,
+        inbound_id BLOB NOT NULL,
+        name TEXT NOT NULL,
+        value TEXT NOT NULL,
+        PRIMARY KEY (outbound_id, t, inbound_id, name)
+    ) WITHOUT ROWID;
+";
+
+fn uuid_to_sql(id: Uuid) -> Vec<u8> {
+    id.as_bytes().to_vec()
+}
+
+fn uuid_from_sql(bytes: &[u8]) -> Result<Uuid> {
+    Uuid::from_slice(bytes).map_err(|_| ErrorKind::Corrupt("invalid uuid".to_string()).into())
+}
+
+// Datetimes are stored as nanoseconds since the epoch, which covers the
+// years 1677 to 2262.
+fn datetime_to_sql(datetime: DateTime<Utc>) -> Result<i64> {
+    signed_nanos_since_epoch(&datetime).ok_or_else(|| ValidationError::from("Datetime is out of range").into())
+}
+
+fn json_to_sql(value: &JsonValue) -> Result<String> {
+    Ok(serde_json::to_string(value)?)
+}
+
+fn json_from_sql(value: &str) -> Result<JsonValue> {
+    Ok(serde_json::from_str(value)?)
+}
+
+fn read_vertex(row: &Row) -> Result<(Uuid, models::Type)> {
+    let id: Vec<u8> = row.get(0)?;
+    let t: String = row.get(1)?;
+    Ok((uuid_from_sql(&id)?, models::Type(t)))
+}
+
+// Reads an edge from a row with the columns `outbound_id`, `t`, `inbound_id`
+// and `update_datetime`.
+fn read_edge(row: &Row) -> Result<(models::EdgeKey, DateTime<Utc>)> {
+    let outbound_id: Vec<u8> = row.get(0)?;
+    let t: String = row.get(1)?;
+    let inbound_id: Vec<u8> = row.get(2)?;
+    let update_datetime: i64 = row.get(3)?;
+
+    let key = models::EdgeKey::new(
+        uuid_from_sql(&outbound_id)?,
+        models::Type(t),
+        uuid_from_sql(&inbound_id)?,
+    );
+
+    Ok((key, datetime_from_signed_nanos(update_datetime)))
+}
+
+fn get_vertex_values_by_query(conn: &Connection, q: VertexQuery) -> Result<Vec<(Uuid, models::Type)>> {
+    match q {
+        VertexQuery::Range(range) => {
+            let mut sql = "SELECT id, t FROM vertices WHERE 1".to_string();
+            let mut params: Vec<Box<dyn ToSql>> = Vec::new();
+
+            if let Some(start_id) = range.start_id {
+                sql.push_str(" AND id >= ?");
+                params.push(Box::new(uuid_to_sql(start_id)));
+            }
+
+            if let Some(t) = range.t {
+                sql.push_str(" AND t = ?");
+                params.push(Box::new(t.0));
+            }
+
+            sql.push_str(" ORDER BY id LIMIT ?");
+            params.push(Box::new(i64::from(range.limit)));
+
+            let mut stmt = conn.prepare_cached(&sql)?;
+            let mut rows = stmt.query(params)?;
+            let mut results = Vec::new();
+
+            while let Some(row) = rows.next()? {
+                results.push(read_vertex(row)?);
+            }
+
+            Ok(results)
+        }
+        VertexQuery::Specific(specific) => {
+            let mut results = Vec::new();
+
+            for id in specific.ids {
+                if let Some(t) = get_vertex_type(conn, id)? {
+                    results.push((id, t));
+                }
+            }
+
+            Ok(results)
+        }
+        VertexQuery::Pipe(pipe) => {
+            let edge_values = get_edge_values_by_query(conn, *pipe.inner)?;
+
+            let ids: Vec<Uuid> = match pipe.direction {
+                models::EdgeDirection::Outbound => edge_values.into_iter().map(|(key, _)| key.outbound_id).collect(),
+                models::EdgeDirection::Inbound => edge_values.into_iter().map(|(key, _)| key.inbound_id).collect(),
+                models::EdgeDirection::Both => {
+                    let mut seen_ids = HashSet::new();
+                    edge_values
+                        .into_iter()
+                        .flat_map(|(key, _)| vec![key.outbound_id, key.inbound_id])
+                        .filter(|id| seen_ids.insert(*id))
+                        .collect()
+                }
+            };
+
+            let mut results = Vec::new();
+
+            for id in ids {
+                if results.len() == pipe.limit as usize {
+                    break;
+                }
+
+                if let Some(t) = get_vertex_type(conn, id)? {
+                    if let Some(ref pipe_t) = pipe.t {
+                        if pipe_t != &t {
+                            continue;
+                        }
+                    }
+
+                    results.push((id, t));
+                }
+            }
+
+            Ok(results)
+        }
+    }
+}
+
+fn get_vertex_type(conn: &Connection, id: Uuid) -> Result<Option<models::Type>> {
+    let mut stmt = conn.prepare_cached("SELECT t FROM vertices WHERE id = ?")?;
+    let t: Option<String> = stmt.query_row(&[uuid_to_sql(id)], |row| row.get(0)).optional()?;
+    Ok(t.map(models::Type))
+}
+
+fn get_edge_values_by_query(conn: &Connection, q: EdgeQuery) -> Result<Vec<(models::EdgeKey, DateTime<Utc>)>> {
+    match q {
+        EdgeQuery::Specific(specific) => {
+            let mut stmt = conn.prepare_cached(
+                "SELECT outbound_id, t, inbound_id, update_datetime FROM edges
+                 WHERE outbound_id = ? AND t = ? AND inbound_id = ?",
+            )?;
+            let mut results = Vec::new();
+
+            for key in specific.keys {
+                let params: Vec<Box<dyn ToSql>> = vec![
+                    Box::new(uuid_to_sql(key.outbound_id)),
+                    Box::new(key.t.0.clone()),
+                    Box::new(uuid_to_sql(key.inbound_id)),
+                ];
+                let mut rows = stmt.query(params)?;
+
+                if let Some(row) = rows.next()? {
+                    results.push(read_edge(row)?);
+                }
+            }
+
+            Ok(results)
+        }
+        EdgeQuery::Pipe(ref pipe) if pipe.direction == models::EdgeDirection::Both => {
+            let mut results = Vec::new();
+            let mut seen_keys = HashSet::new();
+
+            for &direction in &[models::EdgeDirection::Outbound, models::EdgeDirection::Inbound] {
+                let directed_pipe = models::PipeEdgeQuery {
+                    direction,
+                    ..pipe.clone()
+                };
+
+                for (key, update_datetime) in get_edge_values_by_query(conn, directed_pipe.into())? {
+                    if results.len() == pipe.limit as usize {
+                        return Ok(results);
+                    }
+
+                    if seen_keys.insert(key.clone()) {
+                        results.push((key, update_datetime));
+                    }
+                }
+            }
+
+            Ok(results)
+        }
+        EdgeQuery::Pipe(pipe) => {
+            let vertex_values = get_vertex_values_by_query(conn, *pipe.inner)?;
+            let mut results = Vec::new();
+
+            if pipe.limit == 0 {
+                return Ok(results);
+            }
+
+            // Picks the index that matches the direction, so that the rows
+            // come back ordered by type and then newest first.
+            let (id_column, other_id_column) = match pipe.direction {
+                models::EdgeDirection::Outbound => ("outbound_id", "inbound_id"),
+                models::EdgeDirection::Inbound => ("inbound_id", "outbound_id"),
+                // Handled by the previous match arm
+                models::EdgeDirection::Both => unreachable!(),
+            };
+
+            let mut sql = format!(
+                "SELECT outbound_id, t, inbound_id, update_datetime FROM edges WHERE {} = ?",
+                id_column
+            );
+
+            if pipe.t.is_some() {
+                sql.push_str(" AND t = ?");
+            }
+
+            if pipe.high.is_some() {
+                sql.push_str(" AND update_datetime <= ?");
+            }
+
+            if pipe.low.is_some() {
+                sql.push_str(" AND update_datetime >= ?");
+            }
+
+            sql.push_str(&format!(
+                " ORDER BY t, update_datetime DESC, {} LIMIT ?",
+                other_id_column
+            ));
+
+            let mut stmt = conn.prepare_cached(&sql)?;
+
+            for (id, _) in vertex_values {
+                let mut params: Vec<Box<dyn ToSql>> = vec![Box::new(uuid_to_sql(id))];
+
+                if let Some(ref t) = pipe.t {
+                    params.push(Box::new(t.0.clone()));
+                }
+
+                if let Some(high) = pipe.high {
+                    params.push(Box::new(datetime_to_sql(high)?));
+                }
+
+                if let Some(low) = pipe.low {
+                    params.push(Box::new(datetime_to_sql(low)?));
+                }
+
+                params.push(Box::new((pipe.limit as usize - results.len()) as i64));
+                let mut rows = stmt.query(params)?;
+
+                while let Some(row) = rows.next()? {
+                    results.push(read_edge(row)?);
+                }
+
+                if results.len() == pipe.limit as usize {
+                    break;
+                }
+            }
+
+            Ok(results)
+        }
+    }
+}
+
+fn set_edge(conn: &Connection, key: &models::EdgeKey, update_datetime: DateTime<Utc>) -> Result<()> {
+    conn.prepare_cached(
+        "INSERT OR REPLACE INTO edges (outbound_id, t, inbound_id, update_datetime) VALUES (?, ?, ?, ?)",
+    )?
+    .execute(params![
+        uuid_to_sql(key.outbound_id),
+        key.t.0,
+        uuid_to_sql(key.inbound_id),
+        datetime_to_sql(update_datetime)?
+    ])?;
+    Ok(())
+}
+
+fn set_vertex_property(conn: &Connection, id: Uuid, name: &str, value: &JsonValue) -> Result<()> {
+    conn.prepare_cached("INSERT OR REPLACE INTO vertex_properties (vertex_id, name, value) VALUES (?, ?, ?)")?
+        .execute(params![uuid_to_sql(id), name, json_to_sql(value)?])?;
+    Ok(())
+}
+
+fn set_edge_property(conn: &Connection, key: &models::EdgeKey, name: &str, value: &JsonValue) -> Result<()> {
+    conn.prepare_cached(
+        "INSERT OR REPLACE INTO edge_properties (outbound_id, t, inbound_id, name, value) VALUES (?, ?, ?, ?, ?)",
+    )?
+    .execute(params![
+        uuid_to_sql(key.outbound_id),
+        key.t.0,
+        uuid_to_sql(key.inbound_id),
+        name,
+        json_to_sql(value)?
+    ])?;
+    Ok(())
+}
+
+fn delete_edge(conn: &Connection, key: &models::EdgeKey) -> Result<()> {
+    let outbound_id = uuid_to_sql(key.outbound_id);
+    let inbound_id = uuid_to_sql(key.inbound_id);
+    let params = params![outbound_id, key.t.0, inbound_id];
+    conn.prepare_cached("DELETE FROM edge_properties WHERE outbound_id = ? AND t = ? AND inbound_id = ?")?
+        .execute(params)?;
+    conn.prepare_cached("DELETE FROM edges WHERE outbound_id = ? AND t = ? AND inbound_id = ?")?
+        .execute(params)?;
+    Ok(())
+}
+
+fn delete_vertex(conn: &Connection, id: Uuid) -> Result<()> {
+    let id = uuid_to_sql(id);
+    conn.prepare_cached("DELETE FROM vertex_properties WHERE vertex_id = ?")?
+        .execute(&[&id])?;
+    conn.prepare_cached("DELETE FROM edge_properties WHERE outbound_id = ?1 OR inbound_id = ?1")?
+        .execute(&[&id])?;
+    conn.prepare_cached("DELETE FROM edges WHERE outbound_id = ?1 OR inbound_id = ?1")?
+        .execute(&[&id])?;
+    conn.prepare_cached("DELETE FROM vertices WHERE id = ?")?
+        .execute(&[&id])?;
+    Ok(())
+}
+
+/// A datastore that is backed by a single SQLite database file, for small
+/// deployments and desktop or mobile apps. Each transaction method runs in
+/// its own SQLite transaction, so it's applied entirely or not at all.
+/// Method calls are serialized over a single connection.
+#[derive(Debug)]
+pub struct SqliteDatastore {
+    conn: Arc<Mutex<Connection>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl SqliteDatastore {
+    /// Creates a new SQLite datastore, creating the database file and its
+    /// tables if they don't exist.
+    ///
+    /// # Arguments
+    /// * `path` - The file path to the SQLite database.
+    pub fn new(path: &str) -> Result<SqliteDatastore> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    /// Creates a new SQLite datastore that's kept in memory, and discarded
+    /// when the datastore is dropped.
+    pub fn new_in_memory() -> Result<SqliteDatastore> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(conn: Connection) -> Result<SqliteDatastore> {
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        // Write-ahead logging lets readers in other processes work
+        // alongside the writer. In-memory databases stay in `memory` mode.
+        conn.query_row("PRAGMA journal_mode = WAL", NO_PARAMS, |_| Ok(()))?;
+        conn.execute_batch(SCHEMA)?;
+
+        Ok(SqliteDatastore {
+            conn: Arc::new(Mutex::new(conn)),
+            clock: Arc::new(SystemClock),
+        })
+    }
+
+    /// Sets the clock used to timestamp edges, in place of the system time.
+    ///
+    /// # Arguments
+    /// * `clock` - The clock to use.
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> SqliteDatastore {
+        SqliteDatastore { clock, ..self }
+    }
+}
+
+impl Datastore for SqliteDatastore {
+    type Trans = SqliteTransaction;
+
+    // We override the default `bulk_insert` implementation so that all of
+    // the items are inserted in a single SQLite transaction. Like the rocksdb
+    // datastore, this does not verify that the vertices associated with an
+    // inserted edge or property exist.
+    fn bulk_insert<I>(&self, items: I) -> Result<()>
+    where
+        I: Iterator<Item = models::BulkInsertItem>,
+    {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        for item in items {
+            match item {
+                models::BulkInsertItem::Vertex(vertex) => {
+                    tx.prepare_cached("INSERT OR REPLACE INTO vertices (id, t) VALUES (?, ?)")?
+                        .execute(params![uuid_to_sql(vertex.id), vertex.t.0])?;
+                }
+                models::BulkInsertItem::Edge(key) => {
+                    set_edge(&tx, &key, self.clock.now())?;
+                }
+                models::BulkInsertItem::VertexProperty(id, name, value) => {
+                    set_vertex_property(&tx, id, &name, &value)?;
+                }
+                models::BulkInsertItem::EdgeProperty(key, name, value) => {
+                    set_edge_property(&tx, &key, &name, &value)?;
+                }
+            }
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn transaction(&self) -> Result<Self::Trans> {
+        Ok(SqliteTransaction {
+            conn: Arc::clone(&self.conn),
+            clock: Arc::clone(&self.clock),
+        })
+    }
+}
+
+/// A transaction that is backed by SQLite.
+#[derive(Debug)]
+pub struct SqliteTransaction {
+    conn: Arc<Mutex<Connection>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl SqliteTransaction {
+    // Runs a function in a new SQLite transaction, committing it if the
+    // function succeeds and rolling it back otherwise.
+    fn write<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&Connection) -> Result<T>,
+    {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let result = f(&tx)?;
+        tx.commit()?;
+        Ok(result)
+    }
+
+    fn read<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&Connection) -> Result<T>,
+    {
+        f(&self.conn.lock().unwrap())
+    }
+}
+
+impl Transaction for SqliteTransaction {
+    fn create_vertex(&self, vertex: &models::Vertex) -> Result<bool> {
+        self.write(|conn| {
+            let count = conn
+                .prepare_cached("INSERT OR IGNORE INTO vertices (id, t) VALUES (?, ?)")?
+                .execute(params![uuid_to_sql(vertex.id), vertex.t.0])?;
+            Ok(count > 0)
+        })
+    }
+
+    fn upsert_vertex(&self, vertex: &models::Vertex) -> Result<Option<models::Type>> {
+        self.write(|conn| {
+            let existing_t = get_vertex_type(conn, vertex.id)?;
+            conn.prepare_cached("INSERT OR REPLACE INTO vertices (id, t) VALUES (?, ?)")?
+                .execute(params![uuid_to_sql(vertex.id), vertex.t.0])?;
+            Ok(existing_t)
+        })
+    }
+
+    fn get_vertices<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<Vec<models::Vertex>> {
+        let vertex_values = self.read(|conn| get_vertex_values_by_query(conn, q.into()))?;
+        let iter = vertex_values.into_iter().map(|(id, t)| models::Vertex::with_id(id, t));
+        Ok(iter.collect())
+    }
+
+    fn delete_vertices<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<()> {
+        self.write(|conn| {
+            for (id, _) in get_vertex_values_by_query(conn, q.into())? {
+                delete_vertex(conn, id)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn get_vertex_count(&self) -> Result<u64> {
+        self.read(|conn| {
+            let count: i64 = conn.query_row("SELECT COUNT(*) FROM vertices", NO_PARAMS, |row| row.get(0))?;
+            Ok(count as u64)
+        })
+    }
+
+    fn create_edge(&self, key: &models::EdgeKey) -> Result<bool> {
+        self.create_edge_with_datetime(key, self.clock.now())
+    }
+
+    fn create_edge_with_datetime(&self, key: &models::EdgeKey, update_datetime: DateTime<Utc>) -> Result<bool> {
+        self.write(|conn| {
+            if get_vertex_type(conn, key.outbound_id)?.is_none() || get_vertex_type(conn, key.inbound_id)?.is_none() {
+                return Ok(false);
+            }
+
+            set_edge(conn, key, update_datetime)?;
+            Ok(true)
+        })
+    }
+
+    fn touch_edge(&self, key: &models::EdgeKey) -> Result<bool> {
+        self.write(|conn| {
+            if get_vertex_type(conn, key.outbound_id)?.is_none() || get_vertex_type(conn, key.inbound_id)?.is_none() {
+                return Err(missing_edge_vertices(key));
+            }
+
+            let exists = conn
+                .prepare_cached("SELECT 1 FROM edges WHERE outbound_id = ? AND t = ? AND inbound_id = ?")?
+                .exists(params![
+                    uuid_to_sql(key.outbound_id),
+                    key.t.0,
+                    uuid_to_sql(key.inbound_id)
+                ])?;
+            set_edge(conn, key, self.clock.now())?;
+            Ok(!exists)
+        })
+    }
+
+    fn get_edges<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<Vec<models::Edge>> {
+        let edge_values = self.read(|conn| get_edge_values_by_query(conn, q.into()))?;
+        let iter = edge_values
+            .into_iter()
+            .map(|(key, update_datetime)| models::Edge::new(key, update_datetime));
+        Ok(iter.collect())
+    }
+
+    fn delete_edges<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<()> {
+        self.write(|conn| {
+            for (key, _) in get_edge_values_by_query(conn, q.into())? {
+                delete_edge(conn, &key)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn delete_edges_unmodified_since<Q: Into<models::EdgeQuery>>(&self, q: Q, since: DateTime<Utc>) -> Result<u64> {
+        self.write(|conn| {
+            let mut count = 0;
+
+            for (key, update_datetime) in get_edge_values_by_query(conn, q.into())? {
+                if update_datetime <= since {
+                    delete_edge(conn, &key)?;
+                    count += 1;
+                }
+            }
+
+            Ok(count)
+        })
+    }
+
+    fn get_edge_count(&self, id: Uuid, t: Option<&models::Type>, direction: models::EdgeDirection) -> Result<u64> {
+        let mut sql = match direction {
+            models::EdgeDirection::Outbound => "SELECT COUNT(*) FROM edges WHERE outbound_id = ?1",
+            models::EdgeDirection::Inbound => "SELECT COUNT(*) FROM edges WHERE inbound_id = ?1",
+            models::EdgeDirection::Both => "SELECT COUNT(*) FROM edges WHERE (outbound_id = ?1 OR inbound_id = ?1)",
+        }
+        .to_string();
+        let mut params: Vec<Box<dyn ToSql>> = vec![Box::new(uuid_to_sql(id))];
+
+        if let Some(t) = t {
+            sql.push_str(" AND t = ?2");
+            params.push(Box::new(t.0.clone()));
+        }
+
+        self.read(|conn| {
+            let count: i64 = conn.prepare_cached(&sql)?.query_row(params, |row| row.get(0))?;
+            Ok(count as u64)
+        })
+    }
+
+    fn get_vertex_properties(&self, q: VertexPropertyQuery) -> Result<Vec<models::VertexProperty>> {
+        self.read(|conn| {
+            let mut stmt =
+                conn.prepare_cached("SELECT value FROM vertex_properties WHERE vertex_id = ? AND name = ?")?;
+            let mut results = Vec::new();
+
+            for (id, _) in get_vertex_values_by_query(conn, q.inner)? {
+                let value: Option<String> = stmt
+                    .query_row(params![uuid_to_sql(id), q.name], |row| row.get(0))
+                    .optional()?;
+
+                if let Some(value) = value {
+                    results.push(models::VertexProperty::new(id, json_from_sql(&value)?));
+                }
+            }
+
+            Ok(results)
+        })
+    }
+
+    fn set_vertex_properties(&self, q: VertexPropertyQuery, value: &JsonValue) -> Result<()> {
+        self.write(|conn| {
+            for (id, _) in get_vertex_values_by_query(conn, q.inner)? {
+                set_vertex_property(conn, id, &q.name, value)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn delete_vertex_properties(&self, q: VertexPropertyQuery) -> Result<()> {
+        self.write(|conn| {
+            let mut stmt = conn.prepare_cached("DELETE FROM vertex_properties WHERE vertex_id = ? AND name = ?")?;
+
+            for (id, _) in get_vertex_values_by_query(conn, q.inner)? {
+                stmt.execute(params![uuid_to_sql(id), q.name])?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn get_edge_properties(&self, q: EdgePropertyQuery) -> Result<Vec<models::EdgeProperty>> {
+        self.read(|conn| {
+            let mut stmt = conn.prepare_cached(
+                "SELECT value FROM edge_properties WHERE outbound_id = ? AND t = ? AND inbound_id = ? AND name = ?",
+            )?;
+            let mut results = Vec::new();
+
+            for (key, _) in get_edge_values_by_query(conn, q.inner)? {
+                let value: Option<String> = stmt
+                    .query_row(
+                        params![
+                            uuid_to_sql(key.outbound_id),
+                            key.t.0,
+                            uuid_to_sql(key.inbound_id),
+                            q.name
+                        ],
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+
+                if let Some(value) = value {
+                    results.push(models::EdgeProperty::new(key, json_from_sql(&value)?));
+                }
+            }
+
+            Ok(results)
+        })
+    }
+
+    fn set_edge_properties(&self, q: EdgePropertyQuery, value: &JsonValue) -> Result<()> {
+        self.write(|conn| {
+            for (key, _) in get_edge_values_by_query(conn, q.inner)? {
+                set_edge_property(conn, &key, &q.name, value)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn delete_edge_properties(&self, q: EdgePropertyQuery) -> Result<()> {
+        self.write(|conn| {
+            let mut stmt = conn.prepare_cached(
+                "DELETE FROM edge_properties WHERE outbound_id = ? AND t = ? AND inbound_id = ? AND name = ?",
+            )?;
+
+            for (key, _) in get_edge_values_by_query(conn, q.inner)? {
+                stmt.execute(params![
+                    uuid_to_sql(key.outbound_id),
+                    key.t.0,
+                    uuid_to_sql(key.inbound_id),
+                    q.name
+                ])?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn get_all_vertex_properties<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<Vec<models::VertexProperties>> {
+        self.read(|conn| {
+            let mut stmt =
+                conn.prepare_cached("SELECT name, value FROM vertex_properties WHERE vertex_id = ? ORDER BY name")?;
+            let mut results = Vec::new();
+
+            for (id, t) in get_vertex_values_by_query(conn, q.into())? {
+                let mut rows = stmt.query(&[uuid_to_sql(id)])?;
+                let mut props = Vec::new();
+
+                while let Some(row) = rows.next()? {
+                    let name: String = row.get(0)?;
+                    let value: String = row.get(1)?;
+                    props.push(models::NamedProperty::new(name, json_from_sql(&value)?));
+                }
+
+                results.push(models::VertexProperties::new(models::Vertex::with_id(id, t), props));
+            }
+
+            Ok(results)
+        })
+    }
+
+    fn get_all_edge_properties<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<Vec<models::EdgeProperties>> {
+        self.read(|conn| {
+            let mut stmt = conn.prepare_cached(
+                "SELECT name, value FROM edge_properties
+                 WHERE outbound_id = ? AND t = ? AND inbound_id = ? ORDER BY name",
+            )?;
+            let mut results = Vec::new();
+
+            for (key, update_datetime) in get_edge_values_by_query(conn, q.into())? {
+                let mut rows = stmt.query(params![
+                    uuid_to_sql(key.outbound_id),
+                    key.t.0,
+                    uuid_to_sql(key.inbound_id)
+                ])?;
+                let mut props = Vec::new();
+
+                while let Some(row) = rows.next()? {
+                    let name: String = row.get(0)?;
+                    let value: String = row.get(1)?;
+                    props.push(models::NamedProperty::new(name, json_from_sql(&value)?));
+                }
+
+                results.push(models::EdgeProperties::new(
+                    models::Edge::new(key, update_datetime),
+                    props,
+                ));
+            }
+
+            Ok(results)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SqliteDatastore;
+    use chrono::{Duration, Utc};
+    use models::{EdgeKey, SpecificVertexQuery, Type, Vertex, VertexQueryExt};
+    use serde_json::Value as JsonValue;
+    use traits::{Datastore, Transaction};
+    use util::generate_temporary_path;
+
+    #[test]
+    fn should_persist_across_reopens() {
+        let path = generate_temporary_path();
+        let v = Vertex::new(Type::new("foo").unwrap());
+        let q = SpecificVertexQuery::single(v.id);
+
+        {
+            let datastore = SqliteDatastore::new(&path).unwrap();
+            let trans = datastore.transaction().unwrap();
+            trans.create_vertex(&v).unwrap();
+            trans
+                .set_vertex_properties(q.clone().property("bar"), &JsonValue::Bool(true))
+                .unwrap();
+        }
+
+        let datastore = SqliteDatastore::new(&path).unwrap();
+        let trans = datastore.transaction().unwrap();
+        assert_eq!(trans.get_vertices(q.clone()).unwrap(), vec![v]);
+        let properties = trans.get_vertex_properties(q.property("bar")).unwrap();
+        assert_eq!(properties[0].value, JsonValue::Bool(true));
+    }
+
+    #[test]
+    fn should_order_edges_newest_first() {
+        let datastore = SqliteDatastore::new_in_memory().unwrap();
+        let trans = datastore.transaction().unwrap();
+        let t = Type::new("foo").unwrap();
+        let outbound_v = Vertex::new(t.clone());
+        let first_inbound_v = Vertex::new(t.clone());
+        let second_inbound_v = Vertex::new(t.clone());
+        trans.create_vertex(&outbound_v).unwrap();
+        trans.create_vertex(&first_inbound_v).unwrap();
+        trans.create_vertex(&second_inbound_v).unwrap();
+
+        let now = Utc::now();
+        let first_key = EdgeKey::new(outbound_v.id, t.clone(), first_inbound_v.id);
+        let second_key = EdgeKey::new(outbound_v.id, t, second_inbound_v.id);
+        trans
+            .create_edge_with_datetime(&first_key, now - Duration::days(2))
+            .unwrap();
+        trans
+            .create_edge_with_datetime(&second_key, now - Duration::days(1))
+            .unwrap();
+
+        let q = SpecificVertexQuery::single(outbound_v.id).outbound(10);
+        let keys: Vec<EdgeKey> = trans.get_edges(q).unwrap().into_iter().map(|e| e.key).collect();
+        assert_eq!(keys, vec![second_key.clone(), first_key]);
+
+        let q = SpecificVertexQuery::single(second_inbound_v.id).inbound(10);
+        let edges = trans.get_edges(q).unwrap();
+        assert_eq!(edges.len(), 1);
+        assert_eq!(edges[0].key, second_key);
+        assert_eq!(edges[0].created_datetime, now - Duration::days(1));
+    }
+
+    #[test]
+    fn should_delete_edges_unmodified_since() {
+        let datastore = SqliteDatastore::new_in_memory().unwrap();
+        let trans = datastore.transaction().unwrap();
+        let t = Type::new("foo").unwrap();
+        let outbound_v = Vertex::new(t.clone());
+        trans.create_vertex(&outbound_v).unwrap();
+        let now = Utc::now();
+        let mut keys = Vec::new();
+
+        for days in 1..4 {
+            let inbound_v = Vertex::new(t.clone());
+            trans.create_vertex(&inbound_v).unwrap();
+            let key = EdgeKey::new(outbound_v.id, t.clone(), inbound_v.id);
+            trans
+                .create_edge_with_datetime(&key, now - Duration::days(days))
+                .unwrap();
+            keys.push(key);
+        }
+
+        let q = SpecificVertexQuery::single(outbound_v.id).outbound(10);
+        let count = trans
+            .delete_edges_unmodified_since(q.clone(), now - Duration::days(2))
+            .unwrap();
+        assert_eq!(count, 2);
+        let remaining: Vec<EdgeKey> = trans.get_edges(q).unwrap().into_iter().map(|e| e.key).collect();
+        assert_eq!(remaining, vec![keys[0].clone()]);
+    }
+}
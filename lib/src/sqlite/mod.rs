@@ -0,0 +1,24 @@
+//! The SQLite datastore implementation. Everything is stored in a single
+//! database file, which makes it a good fit for small deployments and
+//! desktop or mobile apps. It has these drawbacks:
+//!
+//! * Writes are serialized, both within a process and across processes
+//!   sharing the database file.
+//! * Property TTLs, indexes, subscriptions and the other extensions specific
+//!   to the rocksdb datastore aren't supported.
+
+mod datastore;
+
+pub use self::datastore::{SqliteDatastore, SqliteTransaction};
+
+#[cfg(feature = "bench-suite")]
+full_bench_impl!({
+    use util::generate_temporary_path;
+    SqliteDatastore::new(&generate_temporary_path()).unwrap()
+});
+
+#[cfg(feature = "test-suite")]
+full_test_impl!({
+    use util::generate_temporary_path;
+    SqliteDatastore::new(&generate_temporary_path()).unwrap()
+});
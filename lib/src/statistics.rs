@@ -0,0 +1,724 @@
+//! Sampled statistics about a graph, for estimating how expensive the steps
+//! of a query will be: how many vertices there are of each type, how many
+//! edges of each type the vertices of a type have on average, and how
+//! selective the values of their properties are.
+//!
+//! Statistics are gathered from a random sample of vertices, so they're
+//! estimates. `StatisticsService` keeps them fresh by resampling in the
+//! background, and `match_pattern_with_statistics` uses them to pick the
+//! order a pattern's variables are bound in.
+
+use chrono::offset::Utc;
+use chrono::DateTime;
+use errors::Result;
+use models;
+use models::VertexQueryExt;
+use rand::{thread_rng, Rng};
+use serde_json::Value as JsonValue;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::thread;
+use std::time::Duration;
+use traits::{Datastore, Transaction};
+use util::next_uuid;
+use uuid::Uuid;
+
+// How many vertices are read at a time.
+const SAMPLE_PAGE_SIZE: u32 = 1000;
+
+// How many of the most common values of a property are kept.
+const MAX_COMMON_VALUES: usize = 8;
+
+/// The selectivity of the values of a property.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PropertySketch {
+    /// The fraction of the sampled items that have the property.
+    pub presence: f64,
+
+    /// The number of distinct values of the property in the sample.
+    pub distinct_values: u64,
+
+    /// The most common values of the property, along with the fraction of
+    /// the sampled items that have each of them, most common first. Values
+    /// that were only seen once aren't included.
+    pub common_values: Vec<(JsonValue, f64)>,
+}
+
+impl PropertySketch {
+    /// Estimates the fraction of items that have the property set to a
+    /// value. Values that aren't among the common values are assumed to be
+    /// equally likely.
+    ///
+    /// # Arguments
+    /// * `value` - The value.
+    pub fn equality_selectivity(&self, value: &JsonValue) -> f64 {
+        if let Some(&(_, fraction)) = self.common_values.iter().find(|(common, _)| common == value) {
+            return fraction;
+        }
+
+        let common_fraction: f64 = self.common_values.iter().map(|&(_, fraction)| fraction).sum();
+        let other_values = self
+            .distinct_values
+            .saturating_sub(self.common_values.len() as u64)
+            .max(1);
+        (self.presence - common_fraction).max(0.0) / other_values as f64
+    }
+}
+
+/// Statistics about the vertices of a type.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VertexTypeStatistics {
+    /// The number of vertices of the type in the sample.
+    pub sampled_count: u64,
+
+    /// The estimated number of vertices of the type in the graph.
+    pub estimated_count: u64,
+
+    /// The average number of outbound edges of each type the vertices have.
+    pub out_degrees: BTreeMap<models::Type, f64>,
+
+    /// The average number of inbound edges of each type the vertices have.
+    pub in_degrees: BTreeMap<models::Type, f64>,
+
+    /// The selectivity of the vertices' properties.
+    pub properties: BTreeMap<String, PropertySketch>,
+}
+
+/// Statistics about the edges of a type.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EdgeTypeStatistics {
+    /// The number of edges of the type going out of the sampled vertices.
+    pub sampled_count: u64,
+
+    /// The estimated number of edges of the type in the graph.
+    pub estimated_count: u64,
+
+    /// The selectivity of the edges' properties.
+    pub properties: BTreeMap<String, PropertySketch>,
+}
+
+/// Statistics about a graph, gathered from a sample of its vertices. See
+/// `sample_statistics`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GraphStatistics {
+    /// When the sample was taken.
+    pub sampled_datetime: DateTime<Utc>,
+
+    /// The number of vertices in the graph.
+    pub vertex_count: u64,
+
+    /// The number of vertices that were sampled.
+    pub sampled_vertices: u64,
+
+    /// Whether every vertex was sampled, in which case the statistics are
+    /// exact as of when they were gathered.
+    pub is_complete: bool,
+
+    /// Statistics about each vertex type that was sampled.
+    pub vertex_types: BTreeMap<models::Type, VertexTypeStatistics>,
+
+    /// Statistics about each edge type that was sampled.
+    pub edge_types: BTreeMap<models::Type, EdgeTypeStatistics>,
+}
+
+impl GraphStatistics {
+    /// Estimates the number of vertices of a type.
+    ///
+    /// # Arguments
+    /// * `t` - The type of the vertices, or `None` to estimate the number of
+    ///   vertices of any type.
+    pub fn estimate_vertex_count(&self, t: Option<&models::Type>) -> u64 {
+        match t {
+            Some(t) => self.vertex_types.get(t).map_or(0, |stats| stats.estimated_count),
+            None => self.vertex_count,
+        }
+    }
+
+    /// Estimates the average number of edges a vertex has.
+    ///
+    /// # Arguments
+    /// * `vertex_t` - The type of the vertex, or `None` to average over
+    ///   vertices of any type.
+    /// * `edge_t` - The type of the edges, or `None` to count edges of any
+    ///   type.
+    /// * `direction` - Which edges of the vertex to count.
+    pub fn estimate_degree(
+        &self,
+        vertex_t: Option<&models::Type>,
+        edge_t: Option<&models::Type>,
+        direction: models::EdgeDirection,
+    ) -> f64 {
+        let sum = |degrees: &BTreeMap<models::Type, f64>| -> f64 {
+            match edge_t {
+                Some(t) => degrees.get(t).cloned().unwrap_or(0.0),
+                None => degrees.values().sum(),
+            }
+        };
+
+        let degree = |stats: &VertexTypeStatistics| -> f64 {
+            match direction {
+                models::EdgeDirection::Outbound => sum(&stats.out_degrees),
+                models::EdgeDirection::Inbound => sum(&stats.in_degrees),
+                models::EdgeDirection::Both => sum(&stats.out_degrees) + sum(&stats.in_degrees),
+            }
+        };
+
+        match vertex_t {
+            Some(t) => self.vertex_types.get(t).map_or(0.0, degree),
+            None if self.sampled_vertices == 0 => 0.0,
+            None => {
+                let total: f64 = self
+                    .vertex_types
+                    .values()
+                    .map(|stats| degree(stats) * stats.sampled_count as f64)
+                    .sum();
+                total / self.sampled_vertices as f64
+            }
+        }
+    }
+
+    /// Estimates the fraction of vertices of a type that have a property
+    /// set to a value. Returns `None` if no vertices of the type were
+    /// sampled.
+    ///
+    /// # Arguments
+    /// * `t` - The type of the vertices.
+    /// * `name` - The name of the property.
+    /// * `value` - The value.
+    pub fn estimate_vertex_property_selectivity(&self, t: &models::Type, name: &str, value: &JsonValue) -> Option<f64> {
+        let stats = self.vertex_types.get(t)?;
+        Some(
+            stats
+                .properties
+                .get(name)
+                .map_or(0.0, |sketch| sketch.equality_selectivity(value)),
+        )
+    }
+
+    /// Estimates the fraction of edges of a type that have a property set
+    /// to a value. Returns `None` if no edges of the type were sampled.
+    ///
+    /// # Arguments
+    /// * `t` - The type of the edges.
+    /// * `name` - The name of the property.
+    /// * `value` - The value.
+    pub fn estimate_edge_property_selectivity(&self, t: &models::Type, name: &str, value: &JsonValue) -> Option<f64> {
+        let stats = self.edge_types.get(t)?;
+        Some(
+            stats
+                .properties
+                .get(name)
+                .map_or(0.0, |sketch| sketch.equality_selectivity(value)),
+        )
+    }
+}
+
+#[derive(Default)]
+struct PropertyCounts {
+    count: u64,
+    // Values are keyed by their JSON serialization, since JSON values can't
+    // be hashed.
+    values: HashMap<String, (JsonValue, u64)>,
+}
+
+impl PropertyCounts {
+    fn add(&mut self, value: &JsonValue) {
+        self.count += 1;
+        self.values
+            .entry(value.to_string())
+            .or_insert_with(|| (value.clone(), 0))
+            .1 += 1;
+    }
+
+    fn sketch(self, total: u64) -> PropertySketch {
+        let fraction = |count: u64| count as f64 / total as f64;
+        let distinct_values = self.values.len() as u64;
+        let mut common_values: Vec<(JsonValue, u64)> = self.values.values().filter(|v| v.1 > 1).cloned().collect();
+        common_values.sort_by_key(|value| Reverse(value.1));
+        common_values.truncate(MAX_COMMON_VALUES);
+
+        PropertySketch {
+            presence: fraction(self.count),
+            distinct_values,
+            common_values: common_values
+                .into_iter()
+                .map(|(value, count)| (value, fraction(count)))
+                .collect(),
+        }
+    }
+}
+
+fn sketch_properties(properties: BTreeMap<String, PropertyCounts>, total: u64) -> BTreeMap<String, PropertySketch> {
+    properties
+        .into_iter()
+        .map(|(name, counts)| (name, counts.sketch(total)))
+        .collect()
+}
+
+#[derive(Default)]
+struct VertexTypeCounts {
+    count: u64,
+    out_edges: BTreeMap<models::Type, u64>,
+    in_edges: BTreeMap<models::Type, u64>,
+    properties: BTreeMap<String, PropertyCounts>,
+}
+
+#[derive(Default)]
+struct EdgeTypeCounts {
+    count: u64,
+    properties: BTreeMap<String, PropertyCounts>,
+}
+
+#[derive(Default)]
+struct Sample {
+    vertex_count: u64,
+    vertex_types: BTreeMap<models::Type, VertexTypeCounts>,
+    edge_types: BTreeMap<models::Type, EdgeTypeCounts>,
+}
+
+impl Sample {
+    // Samples up to `limit` vertices with ids from `start_id` up to, but not
+    // including, `end_id`. Returns whether every vertex in the range was
+    // sampled.
+    fn read<T: Transaction>(&mut self, trans: &T, start_id: Uuid, end_id: Option<Uuid>, limit: u64) -> Result<bool> {
+        let is_before_end = |id: Uuid| match end_id {
+            Some(end_id) => id < end_id,
+            None => true,
+        };
+
+        let mut start_id = start_id;
+        let mut remaining = limit;
+
+        while remaining > 0 {
+            let page_size = remaining.min(u64::from(SAMPLE_PAGE_SIZE)) as u32;
+            let page = trans.get_all_vertex_properties(models::RangeVertexQuery::new(page_size).start_id(start_id))?;
+            let is_last_page = page.len() < page_size as usize;
+            let last_id = page.last().map(|vertex_properties| vertex_properties.vertex.id);
+            let page: Vec<models::VertexProperties> = page
+                .into_iter()
+                .filter(|vertex_properties| is_before_end(vertex_properties.vertex.id))
+                .collect();
+
+            self.add_page(trans, &page)?;
+            remaining -= page.len() as u64;
+
+            match last_id {
+                Some(last_id) if !is_last_page && is_before_end(last_id) => match next_uuid(last_id) {
+                    Ok(next_id) => start_id = next_id,
+                    Err(_) => return Ok(true),
+                },
+                _ => return Ok(true),
+            }
+        }
+
+        Ok(false)
+    }
+
+    fn add_page<T: Transaction>(&mut self, trans: &T, page: &[models::VertexProperties]) -> Result<()> {
+        if page.is_empty() {
+            return Ok(());
+        }
+
+        let mut vertex_types = HashMap::with_capacity(page.len());
+
+        for vertex_properties in page {
+            let vertex = &vertex_properties.vertex;
+            vertex_types.insert(vertex.id, vertex.t.clone());
+            let counts = self.vertex_types.entry(vertex.t.clone()).or_default();
+            counts.count += 1;
+
+            for property in &vertex_properties.props {
+                counts
+                    .properties
+                    .entry(property.name.clone())
+                    .or_default()
+                    .add(&property.value);
+            }
+        }
+
+        self.vertex_count += page.len() as u64;
+        let ids: Vec<Uuid> = page
+            .iter()
+            .map(|vertex_properties| vertex_properties.vertex.id)
+            .collect();
+        let q = models::SpecificVertexQuery::new(ids);
+
+        for edge_properties in trans.get_all_edge_properties(q.clone().outbound(u32::MAX))? {
+            let key = &edge_properties.edge.key;
+            let counts = self.edge_types.entry(key.t.clone()).or_default();
+            counts.count += 1;
+
+            for property in &edge_properties.props {
+                counts
+                    .properties
+                    .entry(property.name.clone())
+                    .or_default()
+                    .add(&property.value);
+            }
+
+            let vertex_counts = self.vertex_types.get_mut(&vertex_types[&key.outbound_id]).unwrap();
+            *vertex_counts.out_edges.entry(key.t.clone()).or_insert(0) += 1;
+        }
+
+        for edge in trans.get_edges(q.inbound(u32::MAX))? {
+            let vertex_counts = self.vertex_types.get_mut(&vertex_types[&edge.key.inbound_id]).unwrap();
+            *vertex_counts.in_edges.entry(edge.key.t).or_insert(0) += 1;
+        }
+
+        Ok(())
+    }
+
+    fn into_statistics(self, vertex_count: u64, is_complete: bool) -> GraphStatistics {
+        // How many vertices in the graph each sampled vertex stands for
+        let scale = if is_complete || self.vertex_count == 0 {
+            1.0
+        } else {
+            vertex_count as f64 / self.vertex_count as f64
+        };
+
+        let estimate = |count: u64| (count as f64 * scale).round() as u64;
+
+        let average = |edges: BTreeMap<models::Type, u64>, vertices: u64| -> BTreeMap<models::Type, f64> {
+            edges
+                .into_iter()
+                .map(|(t, count)| (t, count as f64 / vertices as f64))
+                .collect()
+        };
+
+        let vertex_types = self
+            .vertex_types
+            .into_iter()
+            .map(|(t, counts)| {
+                let stats = VertexTypeStatistics {
+                    sampled_count: counts.count,
+                    estimated_count: estimate(counts.count),
+                    out_degrees: average(counts.out_edges, counts.count),
+                    in_degrees: average(counts.in_edges, counts.count),
+                    properties: sketch_properties(counts.properties, counts.count),
+                };
+
+                (t, stats)
+            })
+            .collect();
+
+        let edge_types = self
+            .edge_types
+            .into_iter()
+            .map(|(t, counts)| {
+                let stats = EdgeTypeStatistics {
+                    sampled_count: counts.count,
+                    estimated_count: estimate(counts.count),
+                    properties: sketch_properties(counts.properties, counts.count),
+                };
+
+                (t, stats)
+            })
+            .collect();
+
+        GraphStatistics {
+            sampled_datetime: Utc::now(),
+            vertex_count: if is_complete { self.vertex_count } else { vertex_count },
+            sampled_vertices: self.vertex_count,
+            is_complete,
+            vertex_types,
+            edge_types,
+        }
+    }
+}
+
+/// Gathers statistics about a graph from a sample of its vertices, along
+/// with their properties and edges. The sample is a run of consecutive
+/// vertex ids starting from a random one, wrapping around to the lowest id
+/// if it reaches the end.
+///
+/// # Arguments
+/// * `trans` - The transaction to read from.
+/// * `sample_size` - The maximum number of vertices to sample. Use
+///   `u32::MAX` to sample every vertex.
+pub fn sample_statistics<T: Transaction>(trans: &T, sample_size: u32) -> Result<GraphStatistics> {
+    let vertex_count = trans.get_vertex_count()?;
+    let mut bytes = [0u8; 16];
+    thread_rng().fill(&mut bytes);
+    let start_id = Uuid::from_slice(&bytes).unwrap();
+
+    let mut sample = Sample::default();
+    let mut is_complete = sample.read(trans, start_id, None, u64::from(sample_size))?;
+
+    if is_complete {
+        let remaining = u64::from(sample_size) - sample.vertex_count;
+        is_complete = sample.read(trans, Uuid::default(), Some(start_id), remaining)?;
+    }
+
+    Ok(sample.into_statistics(vertex_count, is_complete))
+}
+
+/// Options for gathering statistics in the background. See
+/// `StatisticsService`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StatisticsOptions {
+    /// The maximum number of vertices to sample. Defaults to 10,000.
+    pub sample_size: u32,
+
+    /// How long to wait between refreshes. Defaults to 5 minutes.
+    pub refresh_interval: Duration,
+}
+
+impl Default for StatisticsOptions {
+    fn default() -> Self {
+        StatisticsOptions {
+            sample_size: 10_000,
+            refresh_interval: Duration::from_secs(300),
+        }
+    }
+}
+
+impl StatisticsOptions {
+    /// Sets the maximum number of vertices to sample.
+    ///
+    /// # Arguments
+    /// * `sample_size` - The maximum number of vertices.
+    pub fn sample_size(self, sample_size: u32) -> Self {
+        StatisticsOptions { sample_size, ..self }
+    }
+
+    /// Sets how long to wait between refreshes.
+    ///
+    /// # Arguments
+    /// * `refresh_interval` - How long to wait.
+    pub fn refresh_interval(self, refresh_interval: Duration) -> Self {
+        StatisticsOptions {
+            refresh_interval,
+            ..self
+        }
+    }
+}
+
+// Samples the datastore, or returns `None` if it's been dropped.
+type Sampler = dyn Fn() -> Option<Result<GraphStatistics>> + Send + Sync;
+
+#[derive(Default)]
+struct State {
+    statistics: Option<Arc<GraphStatistics>>,
+    last_error: Option<String>,
+}
+
+/// Keeps statistics about a datastore fresh, by resampling it on a thread
+/// in the background. The thread holds only a weak reference to the
+/// datastore, and exits once either the datastore or the service is
+/// dropped.
+pub struct StatisticsService {
+    sampler: Arc<Sampler>,
+    state: Arc<RwLock<State>>,
+    signals: Mutex<Sender<()>>,
+}
+
+impl StatisticsService {
+    /// Starts gathering statistics about a datastore. The first sample is
+    /// taken right away, in the background.
+    ///
+    /// # Arguments
+    /// * `datastore` - The datastore to gather statistics about.
+    /// * `options` - The options.
+    pub fn start<D>(datastore: &Arc<D>, options: StatisticsOptions) -> Self
+    where
+        D: Datastore + Send + Sync + 'static,
+    {
+        let datastore: Weak<D> = Arc::downgrade(datastore);
+        let sample_size = options.sample_size;
+
+        let sampler: Arc<Sampler> = Arc::new(move || {
+            let datastore = Weak::upgrade(&datastore)?;
+            Some(
+                datastore
+                    .transaction()
+                    .and_then(|trans| sample_statistics(&trans, sample_size)),
+            )
+        });
+
+        let state = Arc::new(RwLock::new(State::default()));
+        let (sender, receiver) = channel();
+        let thread_sampler = sampler.clone();
+        let thread_state = state.clone();
+
+        thread::spawn(move || loop {
+            match thread_sampler() {
+                Some(result) => record_sample(&thread_state, result),
+                None => return,
+            };
+
+            match receiver.recv_timeout(options.refresh_interval) {
+                Ok(()) | Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        });
+
+        StatisticsService {
+            sampler,
+            state,
+            signals: Mutex::new(sender),
+        }
+    }
+
+    /// Gets the latest statistics, or `None` if none have been gathered
+    /// yet.
+    pub fn get(&self) -> Option<Arc<GraphStatistics>> {
+        self.state.read().unwrap().statistics.clone()
+    }
+
+    /// Gets the error the latest refresh failed with, if any. The
+    /// statistics from the last refresh that succeeded are kept.
+    pub fn last_error(&self) -> Option<String> {
+        self.state.read().unwrap().last_error.clone()
+    }
+
+    /// Refreshes the statistics now, on the calling thread, and returns
+    /// them.
+    ///
+    /// # Errors
+    /// Returns an error if sampling fails, or if the datastore has been
+    /// dropped.
+    pub fn refresh(&self) -> Result<Arc<GraphStatistics>> {
+        let result = match (self.sampler)() {
+            Some(result) => result,
+            None => return Err("The datastore has been dropped".into()),
+        };
+
+        record_sample(&self.state, result);
+        let state = self.state.read().unwrap();
+
+        match state.last_error {
+            Some(ref err) => Err(err.clone().into()),
+            None => Ok(state.statistics.clone().unwrap()),
+        }
+    }
+
+    /// Wakes the background thread so that it refreshes the statistics
+    /// soon, rather than waiting out the refresh interval.
+    pub fn schedule_refresh(&self) {
+        // The thread only exits once the datastore is dropped, at which
+        // point there's nothing left to refresh anyway
+        let _ = self.signals.lock().unwrap().send(());
+    }
+}
+
+fn record_sample(state: &RwLock<State>, result: Result<GraphStatistics>) {
+    let mut state = state.write().unwrap();
+
+    match result {
+        Ok(statistics) => {
+            state.statistics = Some(Arc::new(statistics));
+            state.last_error = None;
+        }
+        Err(err) => state.last_error = Some(err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sample_statistics, StatisticsOptions, StatisticsService};
+    use memory::MemoryDatastore;
+    use models::{EdgeDirection, EdgeKey, SpecificVertexQuery, Type, VertexQueryExt};
+    use serde_json::Value as JsonValue;
+    use std::sync::Arc;
+    use traits::{Datastore, Transaction};
+
+    fn create_graph(datastore: &MemoryDatastore) {
+        let trans = datastore.transaction().unwrap();
+        let person_t = Type::new("person").unwrap();
+        let city_t = Type::new("city").unwrap();
+        let lives_in_t = Type::new("lives_in").unwrap();
+        let city_ids: Vec<_> = (0..2)
+            .map(|_| trans.create_vertex_from_type(city_t.clone()).unwrap())
+            .collect();
+
+        for i in 0..6 {
+            let person_id = trans.create_vertex_from_type(person_t.clone()).unwrap();
+            let country = if i < 4 {
+                "nz"
+            } else if i == 4 {
+                "au"
+            } else {
+                "us"
+            };
+            trans
+                .set_vertex_properties(
+                    SpecificVertexQuery::single(person_id).property("country"),
+                    &JsonValue::from(country),
+                )
+                .unwrap();
+            trans
+                .create_edge(&EdgeKey::new(person_id, lives_in_t.clone(), city_ids[i % 2]))
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn should_sample_statistics() {
+        let datastore = MemoryDatastore::default();
+        create_graph(&datastore);
+        let trans = datastore.transaction().unwrap();
+        let person_t = Type::new("person").unwrap();
+        let city_t = Type::new("city").unwrap();
+        let lives_in_t = Type::new("lives_in").unwrap();
+
+        let statistics = sample_statistics(&trans, u32::MAX).unwrap();
+        assert!(statistics.is_complete);
+        assert_eq!(statistics.sampled_vertices, 8);
+        assert_eq!(statistics.estimate_vertex_count(Some(&person_t)), 6);
+        assert_eq!(statistics.estimate_vertex_count(Some(&city_t)), 2);
+        assert_eq!(statistics.estimate_vertex_count(None), 8);
+        assert_eq!(statistics.edge_types[&lives_in_t].estimated_count, 6);
+        assert_eq!(
+            statistics.estimate_degree(Some(&person_t), Some(&lives_in_t), EdgeDirection::Outbound),
+            1.0
+        );
+        assert_eq!(
+            statistics.estimate_degree(Some(&city_t), None, EdgeDirection::Inbound),
+            3.0
+        );
+        assert_eq!(statistics.estimate_degree(None, None, EdgeDirection::Both), 1.5);
+
+        // "au" and "us" were only seen once, so they share what's left
+        // after the common values
+        let selectivity = |value: &str| {
+            statistics
+                .estimate_vertex_property_selectivity(&person_t, "country", &JsonValue::from(value))
+                .unwrap()
+        };
+        assert_eq!(selectivity("nz"), 4.0 / 6.0);
+        assert!((selectivity("au") - 1.0 / 6.0).abs() < 1e-9);
+        assert!((selectivity("fr") - 1.0 / 6.0).abs() < 1e-9);
+        assert_eq!(
+            statistics.estimate_vertex_property_selectivity(&city_t, "country", &JsonValue::from("nz")),
+            Some(0.0)
+        );
+
+        let statistics = sample_statistics(&trans, 4).unwrap();
+        assert!(!statistics.is_complete);
+        assert_eq!(statistics.sampled_vertices, 4);
+        assert_eq!(statistics.vertex_count, 8);
+        let estimated: u64 = statistics
+            .vertex_types
+            .values()
+            .map(|stats| stats.estimated_count)
+            .sum();
+        assert_eq!(estimated, 8);
+    }
+
+    #[test]
+    fn should_refresh_statistics() {
+        let datastore = Arc::new(MemoryDatastore::default());
+        let service = StatisticsService::start(&datastore, StatisticsOptions::default().sample_size(100));
+        create_graph(&datastore);
+
+        let statistics = service.refresh().unwrap();
+        assert_eq!(statistics.sampled_vertices, 8);
+        assert_eq!(service.get().unwrap().sampled_vertices, 8);
+        assert_eq!(service.last_error(), None);
+
+        drop(datastore);
+        assert!(service.refresh().is_err());
+    }
+}
@@ -0,0 +1,184 @@
+//! Exports the graph as integer-indexed edge lists, for feeding graph neural
+//! network training pipelines.
+//!
+//! Two arrays are written, both in NumPy's `.npy` format, so they can be
+//! loaded with `numpy.load`:
+//!
+//! * The edge index, a `(2, E)` array of `int64`s. The first row holds the
+//!   indexes of the edges' outbound vertices, and the second row the
+//!   indexes of their inbound vertices, as used by e.g. PyTorch Geometric.
+//! * The id mapping, a `(V,)` array of 36 byte strings, which holds the
+//!   hyphenated id of each vertex at its index.
+//!
+//! Vertices are numbered in order of their ids, and edges are sorted, so
+//! exporting the same graph always gives the same arrays.
+
+use errors::Result;
+use export::for_each_page;
+use models::VertexQueryExt;
+use std::collections::HashMap;
+use std::io::Write;
+use traits::Transaction;
+use uuid::Uuid;
+
+// The length of a hyphenated uuid.
+const UUID_LEN: usize = 36;
+
+/// Exports the edge index and id mapping of the graph. Returns the number
+/// of vertices and the number of edges written. Edges to vertices that
+/// don't exist are skipped.
+///
+/// Every vertex id and edge is kept in memory until the arrays are written,
+/// since `.npy` files start with the shape of their array.
+///
+/// # Arguments
+/// * `trans` - The transaction to read from.
+/// * `edge_index` - Where to write the edge index.
+/// * `id_mapping` - Where to write the id mapping.
+pub fn export_edge_index<T, E, I>(trans: &T, mut edge_index: E, mut id_mapping: I) -> Result<(u64, u64)>
+where
+    T: Transaction,
+    E: Write,
+    I: Write,
+{
+    let mut ids = Vec::new();
+
+    for_each_page(trans, |q| {
+        ids.extend(trans.get_vertices(q)?.into_iter().map(|vertex| vertex.id));
+        Ok(())
+    })?;
+
+    // Pages are in id order, but the pages are re-read, so a vertex created
+    // in between could be out of place
+    ids.sort();
+    ids.dedup();
+    let indexes: HashMap<Uuid, i64> = ids.iter().enumerate().map(|(i, id)| (*id, i as i64)).collect();
+    let mut edges = Vec::new();
+
+    for_each_page(trans, |q| {
+        for edge in trans.get_edges(q.outbound(u32::MAX))? {
+            if let (Some(&outbound), Some(&inbound)) =
+                (indexes.get(&edge.key.outbound_id), indexes.get(&edge.key.inbound_id))
+            {
+                edges.push((outbound, edge.key.t, inbound));
+            }
+        }
+
+        Ok(())
+    })?;
+
+    edges.sort();
+    edges.dedup();
+
+    write_npy_header(&mut id_mapping, &format!("|S{}", UUID_LEN), &[ids.len()])?;
+
+    for id in &ids {
+        id_mapping.write_all(id.to_hyphenated().to_string().as_bytes())?;
+    }
+
+    write_npy_header(&mut edge_index, "<i8", &[2, edges.len()])?;
+
+    for &(outbound, _, _) in &edges {
+        edge_index.write_all(&outbound.to_le_bytes())?;
+    }
+
+    for &(_, _, inbound) in &edges {
+        edge_index.write_all(&inbound.to_le_bytes())?;
+    }
+
+    id_mapping.flush()?;
+    edge_index.flush()?;
+    Ok((ids.len() as u64, edges.len() as u64))
+}
+
+// Writes the header of a version 1.0 `.npy` file holding a C-ordered array.
+fn write_npy_header<W: Write>(writer: &mut W, descr: &str, shape: &[usize]) -> Result<()> {
+    let shape = match shape.len() {
+        1 => format!("({},)", shape[0]),
+        _ => format!(
+            "({})",
+            shape.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(", ")
+        ),
+    };
+
+    let mut header = format!("{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}", descr, shape);
+
+    // The magic string, version and header length take up 10 bytes, and the
+    // data has to start on a multiple of 64 bytes. The header ends with a
+    // newline.
+    let len = 10 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - len % 64) % 64));
+    header.push('\n');
+
+    writer.write_all(b"\x93NUMPY\x01\x00")?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::export_edge_index;
+    use memory::MemoryDatastore;
+    use models::{BulkInsertItem, EdgeKey, Type};
+    use traits::{Datastore, Transaction};
+    use uuid::Uuid;
+
+    // Splits a `.npy` file into its header and data.
+    fn parse_npy(bytes: &[u8]) -> (String, &[u8]) {
+        assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+        let len = bytes[8] as usize + ((bytes[9] as usize) << 8);
+        assert_eq!((10 + len) % 64, 0);
+        let header = String::from_utf8(bytes[10..10 + len].to_vec()).unwrap();
+        assert!(header.ends_with('\n'));
+        (header.trim_end().to_string(), &bytes[10 + len..])
+    }
+
+    #[test]
+    fn should_export_edge_index() {
+        let datastore = MemoryDatastore::default();
+        let trans = datastore.transaction().unwrap();
+        let t = Type::new("follows").unwrap();
+        let mut ids: Vec<Uuid> = (0..3)
+            .map(|_| trans.create_vertex_from_type(t.clone()).unwrap())
+            .collect();
+        ids.sort();
+
+        for &(outbound, inbound) in &[(2, 0), (0, 1), (1, 2), (0, 2)] {
+            trans
+                .create_edge(&EdgeKey::new(ids[outbound], t.clone(), ids[inbound]))
+                .unwrap();
+        }
+
+        // The memory datastore doesn't check that an edge's vertices exist
+        let dangling_key = EdgeKey::new(ids[1], t.clone(), Uuid::default());
+        datastore
+            .bulk_insert(vec![BulkInsertItem::Edge(dangling_key)].into_iter())
+            .unwrap();
+
+        let mut edge_index = Vec::new();
+        let mut id_mapping = Vec::new();
+        let counts = export_edge_index(&trans, &mut edge_index, &mut id_mapping).unwrap();
+        assert_eq!(counts, (3, 4));
+
+        let (header, data) = parse_npy(&id_mapping);
+        assert_eq!(header, "{'descr': '|S36', 'fortran_order': False, 'shape': (3,), }");
+        let mapped: Vec<Uuid> = data
+            .chunks(36)
+            .map(|chunk| Uuid::parse_str(::std::str::from_utf8(chunk).unwrap()).unwrap())
+            .collect();
+        assert_eq!(mapped, ids);
+
+        let (header, data) = parse_npy(&edge_index);
+        assert_eq!(header, "{'descr': '<i8', 'fortran_order': False, 'shape': (2, 4), }");
+        let values: Vec<i64> = data
+            .chunks(8)
+            .map(|chunk| {
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(chunk);
+                i64::from_le_bytes(bytes)
+            })
+            .collect();
+        assert_eq!(values, vec![0, 0, 1, 2, 1, 2, 2, 0]);
+    }
+}
@@ -1,6 +1,6 @@
 use super::super::{
-    BulkInsertItem, Datastore, EdgeKey, EdgeQueryExt, SpecificEdgeQuery, SpecificVertexQuery, Transaction, Type,
-    Vertex, VertexQueryExt,
+    BulkInsertItem, Datastore, EdgeKey, EdgeQueryExt, Operation, OperationOutput, SpecificEdgeQuery,
+    SpecificVertexQuery, Transaction, Type, Vertex, VertexQueryExt,
 };
 use chrono::offset::Utc;
 use chrono::Timelike;
@@ -115,3 +115,67 @@ pub fn should_bulk_insert_an_invalid_edge<D: Datastore>(datastore: &mut D) {
     let items = vec![BulkInsertItem::Edge(EdgeKey::new(v2.id, edge_t.clone(), v1.id))];
     assert!(datastore.bulk_insert(items.into_iter()).is_ok());
 }
+
+pub fn should_execute_a_batch<D: Datastore>(datastore: &mut D) {
+    let vertex_t = Type::new("test_vertex_type").unwrap();
+    let outbound_v = Vertex::new(vertex_t.clone());
+    let inbound_v = Vertex::new(vertex_t.clone());
+    let edge_t = Type::new("test_edge_type").unwrap();
+    let key = EdgeKey::new(outbound_v.id, edge_t.clone(), inbound_v.id);
+    let property_q = SpecificVertexQuery::single(outbound_v.id).property("name");
+
+    let trans = datastore.transaction().unwrap();
+    let outputs = trans
+        .execute_batch(vec![
+            Operation::CreateVertex(outbound_v.clone()),
+            Operation::CreateVertex(inbound_v.clone()),
+            Operation::CreateVertex(inbound_v.clone()),
+            Operation::CreateEdge(key.clone()),
+            Operation::SetVertexProperties(property_q.clone(), JsonValue::Bool(true)),
+            Operation::GetEdges(SpecificVertexQuery::single(outbound_v.id).outbound(10).into()),
+            Operation::GetVertexProperties(property_q.clone()),
+            Operation::DeleteVertexProperties(property_q.clone()),
+            Operation::GetVertexProperties(property_q),
+        ])
+        .unwrap();
+
+    assert_eq!(outputs.len(), 9);
+
+    let created: Vec<bool> = outputs[..4]
+        .iter()
+        .map(|output| match output {
+            OperationOutput::Created(created) => *created,
+            output => panic!("Unexpected output: {:?}", output),
+        })
+        .collect();
+    assert_eq!(created, vec![true, true, false, true]);
+
+    match outputs[5] {
+        OperationOutput::Edges(ref edges) => {
+            assert_eq!(edges.len(), 1);
+            assert_eq!(edges[0].key, key);
+        }
+        ref output => panic!("Unexpected output: {:?}", output),
+    }
+
+    match outputs[6] {
+        OperationOutput::VertexProperties(ref properties) => {
+            assert_eq!(properties.len(), 1);
+            assert_eq!(properties[0].value, JsonValue::Bool(true));
+        }
+        ref output => panic!("Unexpected output: {:?}", output),
+    }
+
+    match outputs[8] {
+        OperationOutput::VertexProperties(ref properties) => assert!(properties.is_empty()),
+        ref output => panic!("Unexpected output: {:?}", output),
+    }
+
+    assert!(trans.execute_batch(Vec::new()).unwrap().is_empty());
+}
+
+pub fn should_not_report_write_pressure_when_idle<D: Datastore>(datastore: &mut D) {
+    let pressure = datastore.get_write_pressure().unwrap();
+    assert!(!pressure.is_stalled());
+    assert_eq!(pressure.delayed_write_rate, None);
+}
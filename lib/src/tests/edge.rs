@@ -1,6 +1,6 @@
 use super::super::{
-    Datastore, EdgeDirection, EdgeKey, EdgeQueryExt, SpecificEdgeQuery, SpecificVertexQuery, Transaction,
-    VertexQueryExt,
+    Datastore, EdgeDirection, EdgeKey, EdgeQueryExt, Error, ErrorKind, SpecificEdgeQuery, SpecificVertexQuery,
+    Transaction, VertexQueryExt,
 };
 use super::util::{create_edge_from, create_edges, create_time_range_queryable_edges};
 use chrono::offset::Utc;
@@ -109,6 +109,124 @@ pub fn should_not_create_an_invalid_edge<D: Datastore>(datastore: &mut D) {
     assert_eq!(result.unwrap(), false);
 }
 
+pub fn should_touch_an_edge<D: Datastore>(datastore: &mut D) {
+    let trans = datastore.transaction().unwrap();
+    let vertex_t = models::Type::new("test_vertex_type").unwrap();
+    let outbound_v = models::Vertex::new(vertex_t.clone());
+    let inbound_v = models::Vertex::new(vertex_t);
+    trans.create_vertex(&outbound_v).unwrap();
+    trans.create_vertex(&inbound_v).unwrap();
+    let edge_t = models::Type::new("test_edge_type").unwrap();
+    let key = models::EdgeKey::new(outbound_v.id, edge_t.clone(), inbound_v.id);
+
+    assert!(trans.touch_edge(&key).unwrap());
+    assert!(!trans.touch_edge(&key).unwrap());
+    let e = trans.get_edges(SpecificEdgeQuery::single(key.clone())).unwrap();
+    assert_eq!(e.len(), 1);
+    assert_eq!(e[0].key, key);
+
+    let missing_key = models::EdgeKey::new(outbound_v.id, edge_t, Uuid::default());
+
+    match trans.touch_edge(&missing_key) {
+        Err(Error(ErrorKind::NotFound(_), _)) => (),
+        result => panic!("Unexpected result: {:?}", result),
+    }
+
+    let e = trans.get_edges(SpecificEdgeQuery::single(missing_key)).unwrap();
+    assert!(e.is_empty());
+}
+
+pub fn should_check_whether_an_edge_exists<D: Datastore>(datastore: &mut D) {
+    let trans = datastore.transaction().unwrap();
+    let vertex_t = models::Type::new("test_vertex_type").unwrap();
+    let outbound_v = models::Vertex::new(vertex_t.clone());
+    let inbound_v = models::Vertex::new(vertex_t);
+    trans.create_vertex(&outbound_v).unwrap();
+    trans.create_vertex(&inbound_v).unwrap();
+    let edge_t = models::Type::new("test_edge_type").unwrap();
+    let key = models::EdgeKey::new(outbound_v.id, edge_t.clone(), inbound_v.id);
+    assert!(!trans.edge_exists(&key).unwrap());
+
+    trans.create_edge(&key).unwrap();
+    assert!(trans.edge_exists(&key).unwrap());
+    let reversed_key = models::EdgeKey::new(inbound_v.id, edge_t, outbound_v.id);
+    assert!(!trans.edge_exists(&reversed_key).unwrap());
+
+    trans.delete_edges(SpecificEdgeQuery::single(key.clone())).unwrap();
+    assert!(!trans.edge_exists(&key).unwrap());
+}
+
+pub fn should_get_edges_with_metrics<D: Datastore>(datastore: &mut D) {
+    let (outbound_id, _) = create_edges(datastore);
+    let trans = datastore.transaction().unwrap();
+
+    let (edges, metrics) = trans
+        .get_edges_with_metrics(SpecificVertexQuery::single(outbound_id).outbound(2))
+        .unwrap();
+    assert_eq!(edges.len(), 2);
+    assert_eq!(metrics.items_returned, 2);
+    assert!(metrics.truncated);
+    assert!(metrics.keys_scanned >= 2);
+
+    let (edges, metrics) = trans
+        .get_edges_with_metrics(SpecificVertexQuery::single(outbound_id).outbound(5))
+        .unwrap();
+    assert_eq!(edges.len(), 5);
+    assert_eq!(metrics.items_returned, 5);
+    assert!(!metrics.truncated);
+}
+
+pub fn should_get_edges_in_pages<D: Datastore>(datastore: &mut D) {
+    let (outbound_id, _) = create_edges(datastore);
+    let trans = datastore.transaction().unwrap();
+
+    // Piped queries are truncated, but can't be resumed
+    let page = trans
+        .get_edges_page(SpecificVertexQuery::single(outbound_id).outbound(5), 2)
+        .unwrap();
+    assert_eq!(page.edges.len(), 2);
+    assert!(page.truncated);
+    assert!(page.cursor.is_none());
+
+    let page = trans
+        .get_edges_page(SpecificVertexQuery::single(outbound_id).outbound(5), 5)
+        .unwrap();
+    assert_eq!(page.edges.len(), 5);
+    assert!(!page.truncated);
+
+    let keys: Vec<EdgeKey> = page.edges.into_iter().map(|edge| edge.key).collect();
+    let page = trans.get_edges_page(SpecificEdgeQuery::new(keys.clone()), 4).unwrap();
+    assert_eq!(page.edges.len(), 4);
+    assert!(page.truncated);
+    assert_eq!(page.cursor, Some(SpecificEdgeQuery::new(keys[4..].to_vec()).into()));
+}
+
+pub fn should_filter_existing_edges<D: Datastore>(datastore: &mut D) {
+    let trans = datastore.transaction().unwrap();
+    let vertex_t = models::Type::new("test_vertex_type").unwrap();
+    let edge_t = models::Type::new("test_edge_type").unwrap();
+    let mut keys = Vec::new();
+
+    for _ in 0..4 {
+        let outbound_v = models::Vertex::new(vertex_t.clone());
+        let inbound_v = models::Vertex::new(vertex_t.clone());
+        trans.create_vertex(&outbound_v).unwrap();
+        trans.create_vertex(&inbound_v).unwrap();
+        keys.push(models::EdgeKey::new(outbound_v.id, edge_t.clone(), inbound_v.id));
+    }
+
+    trans.create_edge(&keys[1]).unwrap();
+    trans.create_edge(&keys[2]).unwrap();
+    // Duplicates are reported for each occurrence
+    keys.push(keys[1].clone());
+
+    assert_eq!(
+        trans.filter_existing_edges(keys).unwrap(),
+        vec![false, true, true, false, true]
+    );
+    assert!(trans.filter_existing_edges(Vec::new()).unwrap().is_empty());
+}
+
 pub fn should_delete_a_valid_edge<D: Datastore>(datastore: &mut D) {
     let trans = datastore.transaction().unwrap();
     let vertex_t = models::Type::new("test_edge_type").unwrap();
@@ -125,6 +243,26 @@ pub fn should_delete_a_valid_edge<D: Datastore>(datastore: &mut D) {
     assert_eq!(e.len(), 0);
 }
 
+pub fn should_delete_edges_in_batches<D: Datastore>(datastore: &mut D) {
+    let (outbound_id, _) = create_edges(datastore);
+    let trans = datastore.transaction().unwrap();
+    let q = SpecificVertexQuery::single(outbound_id).outbound(10);
+    let mut reported = Vec::new();
+    let count = trans
+        .delete_edges_in_batches(q.clone(), 3, |progress| reported.push(progress))
+        .unwrap();
+    assert_eq!(count, 5);
+    assert_eq!(
+        reported,
+        vec![
+            models::BulkDeleteProgress::new(3, 5),
+            models::BulkDeleteProgress::new(5, 5),
+        ]
+    );
+    assert_eq!(trans.get_edges(q).unwrap().len(), 0);
+    assert_eq!(trans.get_vertex_count().unwrap(), 6);
+}
+
 pub fn should_not_delete_an_invalid_edge<D: Datastore>(datastore: &mut D) {
     let trans = datastore.transaction().unwrap();
     let vertex_t = models::Type::new("test_edge_type").unwrap();
@@ -177,6 +315,141 @@ pub fn should_get_an_inbound_edge_count<D: Datastore>(datastore: &mut D) {
     assert_eq!(count, 1);
 }
 
+pub fn should_get_an_edge_count_in_range<D: Datastore>(datastore: &mut D) {
+    let (outbound_id, start_time, end_time, inbound_ids) = create_time_range_queryable_edges(datastore);
+    let trans = datastore.transaction().unwrap();
+    let t = models::Type::new("test_edge_type").unwrap();
+    let count = trans
+        .get_edge_count_in_range(
+            outbound_id,
+            Some(&t),
+            Some(start_time),
+            Some(end_time),
+            EdgeDirection::Outbound,
+        )
+        .unwrap();
+    assert_eq!(count, 5);
+    let count = trans
+        .get_edge_count_in_range(outbound_id, None, Some(start_time), None, EdgeDirection::Outbound)
+        .unwrap();
+    assert_eq!(count, 10);
+    let count = trans
+        .get_edge_count_in_range(outbound_id, None, None, Some(end_time), EdgeDirection::Outbound)
+        .unwrap();
+    assert_eq!(count, 10);
+    let count = trans
+        .get_edge_count_in_range(inbound_ids[0], None, Some(start_time), None, EdgeDirection::Inbound)
+        .unwrap();
+    assert_eq!(count, 1);
+    let count = trans
+        .get_edge_count_in_range(inbound_ids[0], None, None, Some(start_time), EdgeDirection::Inbound)
+        .unwrap();
+    assert_eq!(count, 0);
+}
+
+pub fn should_get_edges_in_both_directions<D: Datastore>(datastore: &mut D) {
+    let trans = datastore.transaction().unwrap();
+    let vertex_t = models::Type::new("test_vertex_type").unwrap();
+    let edge_t = models::Type::new("test_edge_type").unwrap();
+    let v = models::Vertex::new(vertex_t.clone());
+    let outbound_v = models::Vertex::new(vertex_t.clone());
+    let inbound_v = models::Vertex::new(vertex_t);
+    trans.create_vertex(&v).unwrap();
+    trans.create_vertex(&outbound_v).unwrap();
+    trans.create_vertex(&inbound_v).unwrap();
+
+    let keys = vec![
+        EdgeKey::new(outbound_v.id, edge_t.clone(), v.id),
+        EdgeKey::new(v.id, edge_t.clone(), inbound_v.id),
+        EdgeKey::new(v.id, edge_t.clone(), v.id),
+    ];
+
+    for key in &keys {
+        trans.create_edge(key).unwrap();
+    }
+
+    let edges = trans.get_edges(SpecificVertexQuery::single(v.id).both(10)).unwrap();
+    let edge_keys: HashSet<EdgeKey> = edges.into_iter().map(|edge| edge.key).collect();
+    assert_eq!(edge_keys, keys.iter().cloned().collect());
+
+    let vertices = trans
+        .get_vertices(SpecificVertexQuery::single(v.id).both(10).both(10))
+        .unwrap();
+    let vertex_ids: HashSet<Uuid> = vertices.iter().map(|vertex| vertex.id).collect();
+    assert_eq!(vertices.len(), 3);
+    assert_eq!(
+        vertex_ids,
+        vec![v.id, outbound_v.id, inbound_v.id].into_iter().collect()
+    );
+
+    let count = trans.get_edge_count(v.id, None, EdgeDirection::Both).unwrap();
+    assert_eq!(count, 3);
+    let count = trans
+        .get_edge_count_in_range(v.id, Some(&edge_t), None, None, EdgeDirection::Both)
+        .unwrap();
+    assert_eq!(count, 3);
+    let count = trans.get_edge_count(outbound_v.id, None, EdgeDirection::Both).unwrap();
+    assert_eq!(count, 1);
+}
+
+pub fn should_get_common_neighbors<D: Datastore>(datastore: &mut D) {
+    let trans = datastore.transaction().unwrap();
+    let vertex_t = models::Type::new("test_vertex_type").unwrap();
+    let edge_t = models::Type::new("test_edge_type").unwrap();
+    let other_edge_t = models::Type::new("other_edge_type").unwrap();
+    let first_id = trans.create_vertex_from_type(vertex_t.clone()).unwrap();
+    let second_id = trans.create_vertex_from_type(vertex_t.clone()).unwrap();
+    let lonely_id = trans.create_vertex_from_type(vertex_t.clone()).unwrap();
+    let neighbor_ids: Vec<Uuid> = (0..5)
+        .map(|_| trans.create_vertex_from_type(vertex_t.clone()).unwrap())
+        .collect();
+
+    for &neighbor_id in &neighbor_ids[..4] {
+        trans
+            .create_edge(&EdgeKey::new(first_id, edge_t.clone(), neighbor_id))
+            .unwrap();
+    }
+
+    for &neighbor_id in &neighbor_ids[1..] {
+        trans
+            .create_edge(&EdgeKey::new(second_id, edge_t.clone(), neighbor_id))
+            .unwrap();
+    }
+
+    // Edges of other types aren't followed
+    trans
+        .create_edge(&EdgeKey::new(first_id, other_edge_t, neighbor_ids[4]))
+        .unwrap();
+
+    let mut expected_ids = neighbor_ids[1..4].to_vec();
+    expected_ids.sort();
+    let common_ids = trans
+        .get_common_neighbors(first_id, second_id, &edge_t, EdgeDirection::Outbound, 10)
+        .unwrap();
+    assert_eq!(common_ids, expected_ids);
+    let common_ids = trans
+        .get_common_neighbors(first_id, second_id, &edge_t, EdgeDirection::Outbound, 2)
+        .unwrap();
+    assert_eq!(common_ids, expected_ids[..2].to_vec());
+
+    let mut expected_ids = vec![first_id, second_id];
+    expected_ids.sort();
+    let common_ids = trans
+        .get_common_neighbors(neighbor_ids[1], neighbor_ids[2], &edge_t, EdgeDirection::Inbound, 10)
+        .unwrap();
+    assert_eq!(common_ids, expected_ids);
+
+    let common_ids = trans
+        .get_common_neighbors(neighbor_ids[1], neighbor_ids[4], &edge_t, EdgeDirection::Both, 10)
+        .unwrap();
+    assert_eq!(common_ids, vec![second_id]);
+
+    let common_ids = trans
+        .get_common_neighbors(first_id, lonely_id, &edge_t, EdgeDirection::Outbound, 10)
+        .unwrap();
+    assert!(common_ids.is_empty());
+}
+
 pub fn should_get_an_edge_range<D: Datastore>(datastore: &mut D) {
     let (outbound_id, start_time, end_time, _) = create_time_range_queryable_edges(datastore);
     let trans = datastore.transaction().unwrap();
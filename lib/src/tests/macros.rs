@@ -10,17 +10,24 @@ macro_rules! define_test {
     };
 }
 
-/// Use this macro to enable the entire standard test suite.
-#[macro_export]
-macro_rules! full_test_impl {
+/// Use this macro to enable the standard tests for bulk inserts.
+#[macro_export(local_inner_macros)]
+macro_rules! bulk_insert_test_impl {
     ($code:expr) => {
-        // Bulk insert
         define_test!(should_bulk_insert, $code);
         define_test!(should_bulk_insert_a_redundant_vertex, $code);
         define_test!(should_bulk_insert_an_invalid_edge, $code);
+        define_test!(should_execute_a_batch, $code);
+        define_test!(should_not_report_write_pressure_when_idle, $code);
+    };
+}
 
-        // Vertices
+/// Use this macro to enable the standard tests for vertices.
+#[macro_export(local_inner_macros)]
+macro_rules! vertex_test_impl {
+    ($code:expr) => {
         define_test!(should_create_vertex_from_type, $code);
+        define_test!(should_create_vertex_with_id, $code);
         define_test!(should_get_range_vertices, $code);
         define_test!(should_get_no_vertices_with_zero_limit, $code);
         define_test!(should_get_range_vertices_out_of_range, $code);
@@ -29,21 +36,39 @@ macro_rules! full_test_impl {
         define_test!(should_get_single_vertex_nonexisting, $code);
         define_test!(should_get_vertices, $code);
         define_test!(should_get_vertices_piped, $code);
+        define_test!(should_get_vertices_with_metrics, $code);
+        define_test!(should_get_vertices_in_pages, $code);
         define_test!(should_get_a_vertex_count, $code);
+        define_test!(should_filter_existing_vertices, $code);
         define_test!(should_delete_a_valid_vertex, $code);
+        define_test!(should_delete_vertices_in_batches, $code);
         define_test!(should_not_delete_an_invalid_vertex, $code);
+    };
+}
 
-        // Edges
+/// Use this macro to enable the standard tests for edges.
+#[macro_export(local_inner_macros)]
+macro_rules! edge_test_impl {
+    ($code:expr) => {
         define_test!(should_get_a_valid_edge, $code);
         define_test!(should_not_get_an_invalid_edge, $code);
         define_test!(should_create_a_valid_edge, $code);
         define_test!(should_not_create_an_invalid_edge, $code);
+        define_test!(should_touch_an_edge, $code);
+        define_test!(should_check_whether_an_edge_exists, $code);
+        define_test!(should_get_edges_with_metrics, $code);
+        define_test!(should_get_edges_in_pages, $code);
+        define_test!(should_filter_existing_edges, $code);
         define_test!(should_delete_a_valid_edge, $code);
+        define_test!(should_delete_edges_in_batches, $code);
         define_test!(should_not_delete_an_invalid_edge, $code);
         define_test!(should_get_an_edge_count, $code);
         define_test!(should_get_an_edge_count_with_no_type, $code);
         define_test!(should_get_an_edge_count_for_an_invalid_edge, $code);
         define_test!(should_get_an_inbound_edge_count, $code);
+        define_test!(should_get_an_edge_count_in_range, $code);
+        define_test!(should_get_edges_in_both_directions, $code);
+        define_test!(should_get_common_neighbors, $code);
         define_test!(should_get_an_edge_range, $code);
         define_test!(should_get_edges_with_no_type, $code);
         define_test!(should_get_no_edges_for_an_invalid_range, $code);
@@ -52,13 +77,33 @@ macro_rules! full_test_impl {
         define_test!(should_get_edges_with_no_time, $code);
         define_test!(should_get_no_edges_for_reversed_time, $code);
         define_test!(should_get_edges, $code);
+    };
+}
 
-        // Properties
+/// Use this macro to enable the standard tests for vertex and edge properties.
+#[macro_export(local_inner_macros)]
+macro_rules! property_test_impl {
+    ($code:expr) => {
         define_test!(should_handle_vertex_properties, $code);
         define_test!(should_not_set_invalid_vertex_properties, $code);
         define_test!(should_not_delete_invalid_vertex_properties, $code);
         define_test!(should_handle_edge_properties, $code);
         define_test!(should_not_set_invalid_edge_properties, $code);
         define_test!(should_not_delete_invalid_edge_properties, $code);
+        define_test!(should_get_raw_properties, $code);
+        define_test!(should_get_named_properties, $code);
+        define_test!(should_get_all_properties, $code);
+        define_test!(should_get_vertex_properties_with_prefix, $code);
+    };
+}
+
+/// Use this macro to enable the entire standard test suite.
+#[macro_export(local_inner_macros)]
+macro_rules! full_test_impl {
+    ($code:expr) => {
+        bulk_insert_test_impl!($code);
+        vertex_test_impl!($code);
+        edge_test_impl!($code);
+        property_test_impl!($code);
     };
 }
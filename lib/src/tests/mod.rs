@@ -3,6 +3,24 @@
 //! These are exported so that datastore implementations outside of the
 //! `indradb` crate can reuse them. Generally you can use the convenience macro
 //! `full_test_impl`.
+//!
+//! To check that a third-party datastore conforms to the expected semantics,
+//! enable the `test-suite` feature and pass an expression that creates a new,
+//! empty datastore; each test gets its own:
+//!
+//! ```ignore
+//! #[macro_use]
+//! extern crate indradb;
+//!
+//! #[cfg(test)]
+//! mod tests {
+//!     full_test_impl!(MyDatastore::new());
+//! }
+//! ```
+//!
+//! Datastores that only support part of the API can enable the tests for
+//! each part separately, via `bulk_insert_test_impl`, `vertex_test_impl`,
+//! `edge_test_impl` and `property_test_impl`.
 
 mod bulk_insert;
 mod edge;
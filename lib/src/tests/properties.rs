@@ -1,6 +1,8 @@
 use super::super::{
-    Datastore, EdgeKey, EdgeQueryExt, SpecificEdgeQuery, SpecificVertexQuery, Transaction, Type, Vertex, VertexQueryExt,
+    Datastore, EdgeKey, EdgeQueryExt, NamedProperty, NamedVertexProperty, SpecificEdgeQuery, SpecificVertexQuery,
+    Transaction, Type, Vertex, VertexQueryExt,
 };
+use serde_json;
 use serde_json::Value as JsonValue;
 use util::generate_random_secret;
 use uuid::Uuid;
@@ -122,3 +124,189 @@ pub fn should_not_delete_invalid_edge_properties<D: Datastore>(datastore: &mut D
         .delete_edge_properties(SpecificEdgeQuery::single(key).property("bleh"))
         .unwrap();
 }
+
+pub fn should_get_raw_properties<D: Datastore>(datastore: &mut D) {
+    let trans = datastore.transaction().unwrap();
+    let t = Type::new("test_edge_type").unwrap();
+    let outbound_v = Vertex::new(t.clone());
+    let inbound_v = Vertex::new(t.clone());
+    trans.create_vertex(&outbound_v).unwrap();
+    trans.create_vertex(&inbound_v).unwrap();
+    let key = EdgeKey::new(outbound_v.id, t, inbound_v.id);
+    trans.create_edge(&key).unwrap();
+
+    let value: JsonValue = serde_json::from_str(r#"{"foo": [1, 2, 3]}"#).unwrap();
+    let vertex_q = SpecificVertexQuery::single(outbound_v.id).property("raw");
+    trans.set_vertex_properties(vertex_q.clone(), &value).unwrap();
+    let edge_q = SpecificEdgeQuery::single(key.clone()).property("raw");
+    trans.set_edge_properties(edge_q.clone(), &value).unwrap();
+
+    let result = trans.get_vertex_properties_raw(vertex_q).unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0].id, outbound_v.id);
+    assert_eq!(serde_json::from_slice::<JsonValue>(&result[0].value).unwrap(), value);
+    let raw = trans.get_vertex_property_raw(outbound_v.id, "raw").unwrap().unwrap();
+    assert_eq!(serde_json::from_slice::<JsonValue>(&raw).unwrap(), value);
+    assert_eq!(trans.get_vertex_property_raw(inbound_v.id, "raw").unwrap(), None);
+
+    let result = trans.get_edge_properties_raw(edge_q).unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0].key, key);
+    assert_eq!(serde_json::from_slice::<JsonValue>(&result[0].value).unwrap(), value);
+    let raw = trans.get_edge_property_raw(&key, "raw").unwrap().unwrap();
+    assert_eq!(serde_json::from_slice::<JsonValue>(&raw).unwrap(), value);
+}
+
+pub fn should_get_named_properties<D: Datastore>(datastore: &mut D) {
+    let trans = datastore.transaction().unwrap();
+    let t = Type::new("test_edge_type").unwrap();
+    let outbound_v = Vertex::new(t.clone());
+    let inbound_v = Vertex::new(t.clone());
+    trans.create_vertex(&outbound_v).unwrap();
+    trans.create_vertex(&inbound_v).unwrap();
+    let key = EdgeKey::new(outbound_v.id, t, inbound_v.id);
+    trans.create_edge(&key).unwrap();
+
+    let vertex_q = SpecificVertexQuery::new(vec![outbound_v.id, inbound_v.id]);
+    trans
+        .set_vertex_properties(vertex_q.clone().property("foo"), &JsonValue::Bool(true))
+        .unwrap();
+    trans
+        .set_vertex_properties(
+            SpecificVertexQuery::single(outbound_v.id).property("bar"),
+            &JsonValue::Bool(false),
+        )
+        .unwrap();
+    trans
+        .set_vertex_properties(vertex_q.clone().property("baz"), &JsonValue::Null)
+        .unwrap();
+    let edge_q = SpecificEdgeQuery::single(key.clone());
+    trans
+        .set_edge_properties(edge_q.clone().property("foo"), &JsonValue::Bool(true))
+        .unwrap();
+
+    let result = trans.get_named_vertex_properties(vertex_q, &["foo", "bar"]).unwrap();
+    assert_eq!(result.len(), 2);
+    assert_eq!(result[0].vertex, outbound_v);
+    assert_eq!(
+        result[0].props,
+        vec![
+            NamedProperty::new("foo".to_string(), JsonValue::Bool(true)),
+            NamedProperty::new("bar".to_string(), JsonValue::Bool(false)),
+        ]
+    );
+    assert_eq!(result[1].vertex, inbound_v);
+    assert_eq!(
+        result[1].props,
+        vec![NamedProperty::new("foo".to_string(), JsonValue::Bool(true))]
+    );
+
+    let result = trans.get_named_edge_properties(edge_q, &["foo", "bar"]).unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0].edge.key, key);
+    assert_eq!(
+        result[0].props,
+        vec![NamedProperty::new("foo".to_string(), JsonValue::Bool(true))]
+    );
+}
+
+pub fn should_get_all_properties<D: Datastore>(datastore: &mut D) {
+    let trans = datastore.transaction().unwrap();
+    let t = Type::new("test_edge_type").unwrap();
+    let outbound_v = Vertex::new(t.clone());
+    let inbound_v = Vertex::new(t.clone());
+    trans.create_vertex(&outbound_v).unwrap();
+    trans.create_vertex(&inbound_v).unwrap();
+    let key = EdgeKey::new(outbound_v.id, t, inbound_v.id);
+    trans.create_edge(&key).unwrap();
+
+    let vertex_q = SpecificVertexQuery::single(outbound_v.id);
+    trans
+        .set_vertex_properties(vertex_q.clone().property("a"), &JsonValue::Bool(true))
+        .unwrap();
+    trans
+        .set_vertex_properties(vertex_q.clone().property("b"), &JsonValue::Bool(false))
+        .unwrap();
+    let edge_q = SpecificEdgeQuery::single(key.clone());
+    trans
+        .set_edge_properties(edge_q.clone().property("c"), &JsonValue::Null)
+        .unwrap();
+
+    let result = trans
+        .get_all_vertex_properties(SpecificVertexQuery::new(vec![outbound_v.id, inbound_v.id]))
+        .unwrap();
+    assert_eq!(result.len(), 2);
+    assert_eq!(result[0].vertex, outbound_v);
+    assert_eq!(
+        result[0].props,
+        vec![
+            NamedProperty::new("a".to_string(), JsonValue::Bool(true)),
+            NamedProperty::new("b".to_string(), JsonValue::Bool(false)),
+        ]
+    );
+    assert_eq!(result[1].vertex, inbound_v);
+    assert_eq!(result[1].props.len(), 0);
+
+    let result = trans.get_all_edge_properties(edge_q).unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0].edge.key, key);
+    assert_eq!(
+        result[0].props,
+        vec![NamedProperty::new("c".to_string(), JsonValue::Null)]
+    );
+}
+
+pub fn should_get_vertex_properties_with_prefix<D: Datastore>(datastore: &mut D) {
+    let trans = datastore.transaction().unwrap();
+    let t = Type::new("test_vertex_type").unwrap();
+    let first_v = Vertex::new(t.clone());
+    let second_v = Vertex::new(t);
+    trans.create_vertex(&first_v).unwrap();
+    trans.create_vertex(&second_v).unwrap();
+
+    let first_q = SpecificVertexQuery::single(first_v.id);
+    trans
+        .set_vertex_properties(first_q.clone().property("geo.lng"), &JsonValue::Bool(false))
+        .unwrap();
+    trans
+        .set_vertex_properties(first_q.clone().property("geo.lat"), &JsonValue::Bool(true))
+        .unwrap();
+    trans
+        .set_vertex_properties(first_q.clone().property("name"), &JsonValue::Null)
+        .unwrap();
+    trans
+        .set_vertex_properties(
+            SpecificVertexQuery::single(second_v.id).property("geo.lat"),
+            &JsonValue::Null,
+        )
+        .unwrap();
+
+    let result = trans
+        .get_vertex_properties_with_prefix(first_q.clone(), "geo.")
+        .unwrap();
+    assert_eq!(
+        result,
+        vec![
+            NamedVertexProperty::new(first_v.id, "geo.lat".to_string(), JsonValue::Bool(true)),
+            NamedVertexProperty::new(first_v.id, "geo.lng".to_string(), JsonValue::Bool(false)),
+        ]
+    );
+
+    let result = trans.get_vertex_properties_with_prefix(first_q, "").unwrap();
+    assert_eq!(result.len(), 3);
+
+    let result = trans
+        .get_vertex_properties_with_prefix(SpecificVertexQuery::new(vec![first_v.id, second_v.id]), "geo.lat")
+        .unwrap();
+    assert_eq!(result.len(), 2);
+    assert!(result.contains(&NamedVertexProperty::new(
+        second_v.id,
+        "geo.lat".to_string(),
+        JsonValue::Null
+    )));
+
+    let result = trans
+        .get_vertex_properties_with_prefix(SpecificVertexQuery::single(second_v.id), "name")
+        .unwrap();
+    assert_eq!(result.len(), 0);
+}
@@ -1,4 +1,6 @@
-use super::super::{Datastore, EdgeQueryExt, RangeVertexQuery, SpecificVertexQuery, Transaction, VertexQueryExt};
+use super::super::{
+    Datastore, EdgeQueryExt, Error, ErrorKind, RangeVertexQuery, SpecificVertexQuery, Transaction, VertexQueryExt,
+};
 use super::util::{create_edge_from, create_edges};
 use models;
 use std::collections::HashSet;
@@ -11,6 +13,31 @@ pub fn should_create_vertex_from_type<D: Datastore>(datastore: &mut D) {
     trans.create_vertex_from_type(t).unwrap();
 }
 
+pub fn should_create_vertex_with_id<D: Datastore>(datastore: &mut D) {
+    let trans = datastore.transaction().unwrap();
+    let t = models::Type::new("test_vertex_type").unwrap();
+    let id = trans.create_vertex_from_type(t.clone()).unwrap();
+    assert!(!trans.create_vertex_with_id(id, t.clone()).unwrap());
+
+    match trans.create_vertex_with_id(id, models::Type::new("other_vertex_type").unwrap()) {
+        Err(Error(ErrorKind::AlreadyExists(existing_id, existing_t), _)) => {
+            assert_eq!(existing_id, id);
+            assert_eq!(existing_t, t);
+        }
+        result => panic!("Unexpected result: {:?}", result),
+    }
+
+    let vertices = trans.get_vertices(SpecificVertexQuery::single(id)).unwrap();
+    assert_eq!(vertices, vec![models::Vertex::with_id(id, t.clone())]);
+
+    let new_id = models::Vertex::new(t.clone()).id;
+    assert!(trans.create_vertex_with_id(new_id, t).unwrap());
+    assert_eq!(
+        trans.get_vertices(SpecificVertexQuery::single(new_id)).unwrap().len(),
+        1
+    );
+}
+
 pub fn should_get_range_vertices<D: Datastore>(datastore: &mut D) {
     let trans = datastore.transaction().unwrap();
     let mut inserted_ids = create_vertices(&trans);
@@ -154,6 +181,98 @@ pub fn should_get_vertices_piped<D: Datastore>(datastore: &mut D) {
     assert_eq!(range[0], v);
 }
 
+pub fn should_get_vertices_with_metrics<D: Datastore>(datastore: &mut D) {
+    let (outbound_id, _) = create_edges(datastore);
+    let trans = datastore.transaction().unwrap();
+    let q = SpecificVertexQuery::single(outbound_id).outbound(u32::MAX);
+
+    let (vertices, metrics) = trans.get_vertices_with_metrics(q.clone().inbound(3)).unwrap();
+    assert_eq!(vertices.len(), 3);
+    assert_eq!(metrics.items_returned, 3);
+    assert!(metrics.truncated);
+    assert!(metrics.keys_scanned >= 3);
+
+    let (vertices, metrics) = trans.get_vertices_with_metrics(q.inbound(5)).unwrap();
+    assert_eq!(vertices.len(), 5);
+    assert_eq!(metrics.items_returned, 5);
+    assert!(!metrics.truncated);
+
+    let (vertices, metrics) = trans
+        .get_vertices_with_metrics(SpecificVertexQuery::new(vec![outbound_id, Uuid::default()]))
+        .unwrap();
+    assert_eq!(vertices.len(), 1);
+    assert_eq!(metrics.items_returned, 1);
+    assert!(!metrics.truncated);
+}
+
+pub fn should_get_vertices_in_pages<D: Datastore>(datastore: &mut D) {
+    let trans = datastore.transaction().unwrap();
+    let t = models::Type::new("test_paged_vertex_type").unwrap();
+
+    for _ in 0..5 {
+        trans.create_vertex_from_type(t.clone()).unwrap();
+    }
+
+    let mut q: models::VertexQuery = RangeVertexQuery::new(u32::MAX).t(t.clone()).into();
+    let mut found = HashSet::new();
+    let mut page_count = 0;
+
+    loop {
+        let page = trans.get_vertices_page(q, 2).unwrap();
+        assert!(page.vertices.len() <= 2);
+        found.extend(page.vertices.into_iter().map(|vertex| vertex.id));
+        page_count += 1;
+
+        match page.cursor {
+            Some(cursor) => {
+                assert!(page.truncated);
+                q = cursor;
+            }
+            None => {
+                assert!(!page.truncated);
+                break;
+            }
+        }
+    }
+
+    let expected: HashSet<Uuid> = trans
+        .get_vertices(RangeVertexQuery::new(u32::MAX).t(t))
+        .unwrap()
+        .into_iter()
+        .map(|vertex| vertex.id)
+        .collect();
+    assert_eq!(found, expected);
+    assert_eq!(page_count, 3);
+
+    let ids: Vec<Uuid> = expected.into_iter().collect();
+    let page = trans
+        .get_vertices_page(SpecificVertexQuery::new(ids.clone()), 3)
+        .unwrap();
+    assert_eq!(page.vertices.len(), 3);
+    assert!(page.truncated);
+    assert_eq!(page.cursor, Some(SpecificVertexQuery::new(ids[3..].to_vec()).into()));
+
+    assert!(trans.get_vertices_page(RangeVertexQuery::new(1), 0).is_err());
+}
+
+pub fn should_filter_existing_vertices<D: Datastore>(datastore: &mut D) {
+    let trans = datastore.transaction().unwrap();
+    let t = models::Type::new("test_vertex_type").unwrap();
+    let v1 = models::Vertex::new(t.clone());
+    let v2 = models::Vertex::new(t);
+    trans.create_vertex(&v1).unwrap();
+    trans.create_vertex(&v2).unwrap();
+    trans.delete_vertices(SpecificVertexQuery::single(v2.id)).unwrap();
+
+    assert_eq!(
+        trans
+            .filter_existing_vertices(vec![v1.id, Uuid::default(), v2.id, v1.id])
+            .unwrap(),
+        vec![true, false, false, true]
+    );
+    assert!(trans.filter_existing_vertices(Vec::new()).unwrap().is_empty());
+}
+
 pub fn should_delete_a_valid_vertex<D: Datastore>(datastore: &mut D) {
     let (outbound_id, _) = create_edges(datastore);
     let trans = datastore.transaction().unwrap();
@@ -168,6 +287,27 @@ pub fn should_delete_a_valid_vertex<D: Datastore>(datastore: &mut D) {
     assert_eq!(count, 0);
 }
 
+pub fn should_delete_vertices_in_batches<D: Datastore>(datastore: &mut D) {
+    let (_, inbound_ids) = create_edges(datastore);
+    let trans = datastore.transaction().unwrap();
+    let q = SpecificVertexQuery::new(inbound_ids.to_vec());
+    let mut reported = Vec::new();
+    let count = trans
+        .delete_vertices_in_batches(q.clone(), 2, |progress| reported.push(progress))
+        .unwrap();
+    assert_eq!(count, 5);
+    assert_eq!(
+        reported,
+        vec![
+            models::BulkDeleteProgress::new(2, 5),
+            models::BulkDeleteProgress::new(4, 5),
+            models::BulkDeleteProgress::new(5, 5),
+        ]
+    );
+    assert_eq!(trans.get_vertices(q.clone()).unwrap().len(), 0);
+    assert!(trans.delete_vertices_in_batches(q, 0, |_| ()).is_err());
+}
+
 pub fn should_not_delete_an_invalid_vertex<D: Datastore>(datastore: &mut D) {
     let trans = datastore.transaction().unwrap();
     trans
@@ -1,8 +1,14 @@
-use errors::Result;
+use chrono::offset::Utc;
+use chrono::DateTime;
+use errors::{Error, ErrorKind, Result, ValidationError};
 use models;
 use models::{EdgeQueryExt, VertexQueryExt};
+use serde_json;
 use serde_json::value::Value as JsonValue;
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::Receiver;
 use std::vec::Vec;
+use util::{intersect_sorted, next_uuid};
 use uuid::Uuid;
 
 /// Specifies a datastore implementation.
@@ -47,6 +53,143 @@ pub trait Datastore {
 
         Ok(())
     }
+
+    /// Subscribes to changes to the datastore, as events sent once the
+    /// write that caused them has been applied. Subscribers are removed
+    /// once their receiver is dropped.
+    ///
+    /// # Arguments
+    /// * `filter`: The events to receive.
+    ///
+    /// # Errors
+    /// Returns an error if the datastore doesn't support subscriptions,
+    /// which is the default.
+    fn subscribe(&self, _filter: models::EventFilter) -> Result<Receiver<models::Event>> {
+        Err("Subscriptions are not supported by this datastore".into())
+    }
+
+    /// Gets the response recorded for an idempotency key, or `None` if
+    /// there isn't one or it has expired. Servers use these to replay the
+    /// response to a request that a client retries, rather than applying
+    /// its writes again.
+    ///
+    /// # Arguments
+    /// * `key`: The idempotency key.
+    ///
+    /// # Errors
+    /// Returns an error if the datastore doesn't support idempotency keys,
+    /// which is the default.
+    fn get_idempotency_record(&self, _key: &str) -> Result<Option<Vec<u8>>> {
+        Err("Idempotency keys are not supported by this datastore".into())
+    }
+
+    /// Records the response to a request made with an idempotency key,
+    /// replacing any existing record for the key. The record is treated as
+    /// missing once it expires, and is eventually purged.
+    ///
+    /// # Arguments
+    /// * `key`: The idempotency key.
+    /// * `response`: The encoded response, which must not be empty.
+    /// * `expires_at`: When the record expires.
+    ///
+    /// # Errors
+    /// Returns an error if the datastore doesn't support idempotency keys,
+    /// which is the default.
+    fn set_idempotency_record(&self, _key: &str, _response: &[u8], _expires_at: DateTime<Utc>) -> Result<()> {
+        Err("Idempotency keys are not supported by this datastore".into())
+    }
+
+    /// Gets how far the datastore's background work has fallen behind its
+    /// writes, so that writers can back off before writes are stalled.
+    /// Datastores that never stall writes report no pressure, which is the
+    /// default.
+    fn get_write_pressure(&self) -> Result<models::WritePressure> {
+        Ok(models::WritePressure::default())
+    }
+
+    /// Catches a datastore that follows another one, e.g. a read replica,
+    /// up with the writes that have been committed to it, so that
+    /// transactions created afterwards see them. Datastores that don't
+    /// follow another one are always caught up, which is the default.
+    fn catch_up(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+fn check_batch_size(batch_size: u32) -> Result<()> {
+    if batch_size == 0 {
+        Err(ValidationError::from("Batch size must be greater than zero").into())
+    } else {
+        Ok(())
+    }
+}
+
+// The error returned when an edge can't be touched because one of its
+// vertices is missing.
+pub(crate) fn missing_edge_vertices(key: &models::EdgeKey) -> Error {
+    ErrorKind::NotFound(format!("vertex {} or {}", key.outbound_id, key.inbound_id)).into()
+}
+
+fn check_max_results(max_results: u32) -> Result<()> {
+    if max_results == 0 {
+        Err(ValidationError::from("Maximum result size must be greater than zero").into())
+    } else {
+        Ok(())
+    }
+}
+
+// Raises a query's limit by one, so that whether the limit cut the results
+// short can be told by whether the extra result comes back. Returns the
+// original limit.
+fn probe_limit(limit: &mut u32) -> Option<u32> {
+    let original = *limit;
+    *limit = limit.saturating_add(1);
+    Some(original)
+}
+
+// Drops the extra result fetched by `probe_limit`, if it came back.
+fn with_metrics<T>(mut items: Vec<T>, limit: Option<u32>, keys_scanned: Option<u64>) -> (Vec<T>, models::QueryMetrics) {
+    let keys_scanned = keys_scanned.unwrap_or(items.len() as u64);
+    let truncated = match limit {
+        Some(limit) if items.len() > limit as usize => {
+            items.truncate(limit as usize);
+            true
+        }
+        _ => false,
+    };
+
+    let metrics = models::QueryMetrics {
+        keys_scanned,
+        items_returned: items.len() as u64,
+        truncated,
+    };
+
+    (items, metrics)
+}
+
+// Gets the ids of the vertices across edges of a type from a vertex, in
+// ascending order without duplicates.
+fn sorted_neighbor_ids<T: Transaction + ?Sized>(
+    trans: &T,
+    id: Uuid,
+    t: &models::Type,
+    direction: models::EdgeDirection,
+) -> Result<Vec<Uuid>> {
+    let mut neighbor_ids = Vec::new();
+
+    if direction != models::EdgeDirection::Inbound {
+        let q = models::SpecificVertexQuery::single(id).outbound(u32::MAX).t(t.clone());
+        neighbor_ids.extend(trans.get_edges(q)?.into_iter().map(|edge| edge.key.inbound_id));
+    }
+
+    if direction != models::EdgeDirection::Outbound {
+        let q = models::SpecificVertexQuery::single(id).inbound(u32::MAX).t(t.clone());
+        neighbor_ids.extend(trans.get_edges(q)?.into_iter().map(|edge| edge.key.outbound_id));
+    }
+
+    neighbor_ids.sort();
+    neighbor_ids.dedup();
+    Ok(neighbor_ids)
 }
 
 /// Specifies a transaction implementation, which are returned by datastores.
@@ -76,24 +219,201 @@ pub trait Transaction {
         let v = models::Vertex::new(t);
 
         if !self.create_vertex(&v)? {
-            Err("UUID already taken".into())
+            Err(ErrorKind::Conflict(format!("vertex {}", v.id)).into())
         } else {
             Ok(v.id)
         }
     }
 
+    /// Creates a new vertex with a given id, e.g. one generated by the
+    /// client ahead of time. Unlike `create_vertex`, this distinguishes an
+    /// id that's already taken by a vertex of a different type, which
+    /// usually means that ids are colliding, from a retry that created the
+    /// same vertex. Returns whether the vertex was created - if this is
+    /// false, the vertex already exists with the same type, and is left
+    /// as-is.
+    ///
+    /// # Arguments
+    /// * `id`: The id of the vertex to create.
+    /// * `t`: The type of the vertex to create.
+    ///
+    /// # Errors
+    /// Returns an `AlreadyExists` error if a vertex with the id already
+    /// exists with a different type.
+    fn create_vertex_with_id(&self, id: Uuid, t: models::Type) -> Result<bool> {
+        let vertex = models::Vertex::with_id(id, t);
+
+        if self.create_vertex(&vertex)? {
+            return Ok(true);
+        }
+
+        match self.get_vertices(models::SpecificVertexQuery::single(id))?.pop() {
+            Some(existing) => {
+                if existing.t == vertex.t {
+                    Ok(false)
+                } else {
+                    Err(ErrorKind::AlreadyExists(id, existing.t).into())
+                }
+            }
+            // The vertex was deleted in the meantime
+            None => self.create_vertex(&vertex),
+        }
+    }
+
+    /// Creates a vertex if it doesn't exist, or otherwise sets its type, as
+    /// a single atomic operation. Returns the vertex's previous type, or
+    /// `None` if it was created.
+    ///
+    /// # Arguments
+    /// * `vertex`: The vertex to create or update.
+    ///
+    /// # Errors
+    /// Returns an error if the datastore doesn't support upserting vertices,
+    /// which is the default.
+    fn upsert_vertex(&self, _vertex: &models::Vertex) -> Result<Option<models::Type>> {
+        Err("Upserting vertices is not supported by this datastore".into())
+    }
+
     /// Gets a range of vertices specified by a query.
     ///
     /// # Arguments
     /// * `q` - The query to run.
     fn get_vertices<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<Vec<models::Vertex>>;
 
+    /// Gets a range of vertices specified by a query, along with metadata
+    /// about how the query was executed, e.g. so that clients can tell when
+    /// the results were capped by the limit and page through the rest.
+    ///
+    /// To tell whether the results were truncated, the query is run with a
+    /// limit of one more than requested, and the extra result is dropped.
+    ///
+    /// # Arguments
+    /// * `q` - The query to run.
+    fn get_vertices_with_metrics<Q: Into<models::VertexQuery>>(
+        &self,
+        q: Q,
+    ) -> Result<(Vec<models::Vertex>, models::QueryMetrics)> {
+        let mut q = q.into();
+        let limit = match q {
+            models::VertexQuery::Range(ref mut q) => probe_limit(&mut q.limit),
+            models::VertexQuery::Specific(_) => None,
+            models::VertexQuery::Pipe(ref mut q) => probe_limit(&mut q.limit),
+        };
+
+        let start_keys_scanned = self.get_keys_scanned();
+        let vertices = self.get_vertices(q)?;
+        let keys_scanned = start_keys_scanned.and_then(|start| Some(self.get_keys_scanned()? - start));
+        Ok(with_metrics(vertices, limit, keys_scanned))
+    }
+
+    /// Gets a range of vertices specified by a query, returning at most
+    /// `max_results` of them. Unlike lowering the query's limit, this says
+    /// whether any results were left out, and if possible, returns a cursor
+    /// query to get the rest of them with, so that large results can be
+    /// fetched in pages of bounded size.
+    ///
+    /// # Arguments
+    /// * `q` - The query to run.
+    /// * `max_results` - The maximum number of vertices to return.
+    ///
+    /// # Errors
+    /// Returns a `ValidationError` if `max_results` is zero.
+    fn get_vertices_page<Q: Into<models::VertexQuery>>(&self, q: Q, max_results: u32) -> Result<models::VertexPage> {
+        check_max_results(max_results)?;
+
+        let (vertices, truncated, cursor) = match q.into() {
+            models::VertexQuery::Range(ref q) if q.limit > max_results => {
+                let (vertices, metrics) = self.get_vertices_with_metrics(models::RangeVertexQuery {
+                    limit: max_results,
+                    ..q.clone()
+                })?;
+
+                // If the last vertex has the greatest possible id, there's
+                // nothing left to resume from
+                match vertices.last().map(|vertex| next_uuid(vertex.id)) {
+                    Some(Ok(next_id)) if metrics.truncated => {
+                        let cursor = models::RangeVertexQuery {
+                            limit: q.limit - max_results,
+                            t: q.t.clone(),
+                            start_id: Some(next_id),
+                        };
+                        (vertices, true, Some(cursor.into()))
+                    }
+                    _ => (vertices, false, None),
+                }
+            }
+            models::VertexQuery::Specific(ref q) if q.ids.len() > max_results as usize => {
+                let mut ids = q.ids.clone();
+                let rest = ids.split_off(max_results as usize);
+                let vertices = self.get_vertices(models::SpecificVertexQuery::new(ids))?;
+                (vertices, true, Some(models::SpecificVertexQuery::new(rest).into()))
+            }
+            models::VertexQuery::Pipe(ref q) if q.limit > max_results => {
+                let (vertices, metrics) = self.get_vertices_with_metrics(models::PipeVertexQuery {
+                    limit: max_results,
+                    ..q.clone()
+                })?;
+                (vertices, metrics.truncated, None)
+            }
+            q => (self.get_vertices(q)?, false, None),
+        };
+
+        Ok(models::VertexPage {
+            vertices,
+            truncated,
+            cursor,
+        })
+    }
+
+    /// Checks which of a set of vertices exist, e.g. to validate the
+    /// endpoints of a batch of imported edges. Returns whether each vertex
+    /// exists, in the same order as the ids. By default, the vertices are
+    /// fetched with a single specific vertex query.
+    ///
+    /// # Arguments
+    /// * `ids`: The ids of the vertices to check for.
+    fn filter_existing_vertices(&self, ids: Vec<Uuid>) -> Result<Vec<bool>> {
+        let found: HashSet<Uuid> = self
+            .get_vertices(models::SpecificVertexQuery::new(ids.clone()))?
+            .into_iter()
+            .map(|vertex| vertex.id)
+            .collect();
+        Ok(ids.iter().map(|id| found.contains(id)).collect())
+    }
+
     /// Deletes existing vertices specified by a query.
     ///
     /// # Arguments
     /// * `q` - The query to run.
     fn delete_vertices<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<()>;
 
+    /// Deletes the vertices specified by a query in batches, so that no
+    /// single delete has to remove an unbounded number of vertices and their
+    /// edges. The query is run once up front, and `progress` is called after
+    /// each batch. Returns the number of vertices deleted.
+    ///
+    /// # Arguments
+    /// * `q` - The query to run.
+    /// * `batch_size` - The maximum number of vertices to delete at a time.
+    /// * `progress` - Called with the progress after each batch.
+    fn delete_vertices_in_batches<Q, F>(&self, q: Q, batch_size: u32, mut progress: F) -> Result<u64>
+    where
+        Q: Into<models::VertexQuery>,
+        F: FnMut(models::BulkDeleteProgress),
+    {
+        check_batch_size(batch_size)?;
+        let ids: Vec<Uuid> = self.get_vertices(q)?.into_iter().map(|vertex| vertex.id).collect();
+        let mut deleted = models::BulkDeleteProgress::new(0, ids.len() as u64);
+
+        for chunk in ids.chunks(batch_size as usize) {
+            self.delete_vertices(models::SpecificVertexQuery::new(chunk.to_vec()))?;
+            deleted.deleted_count += chunk.len() as u64;
+            progress(deleted);
+        }
+
+        Ok(deleted.deleted_count)
+    }
+
     /// Gets the number of vertices in the datastore..
     fn get_vertex_count(&self) -> Result<u64>;
 
@@ -106,18 +426,238 @@ pub trait Transaction {
     /// * `key`: The edge to create.
     fn create_edge(&self, key: &models::EdgeKey) -> Result<bool>;
 
+    /// Creates a new edge with a given update datetime, rather than the
+    /// current one, e.g. to import historical data while keeping edge
+    /// ranges in time order. If the edge already exists, its update datetime
+    /// is replaced, even if the given one is older. Returns whether the edge
+    /// was successfully created - if this is false, it's because one of the
+    /// specified vertices is missing.
+    ///
+    /// # Arguments
+    /// * `key`: The edge to create.
+    /// * `update_datetime`: The edge's update datetime.
+    ///
+    /// # Errors
+    /// Returns an error if the datastore doesn't support backdating edges,
+    /// which is the default.
+    fn create_edge_with_datetime(&self, _key: &models::EdgeKey, _update_datetime: DateTime<Utc>) -> Result<bool> {
+        Err("Backdating edges is not supported by this datastore".into())
+    }
+
+    /// Creates an edge if it doesn't exist, or otherwise refreshes its
+    /// update datetime, e.g. to record an interaction between two vertices.
+    /// Returns whether the edge was created.
+    ///
+    /// The default implementation checks for the edge and then creates it,
+    /// so a concurrent writer can make it misreport whether the edge was
+    /// created; datastores override it to do both atomically.
+    ///
+    /// # Arguments
+    /// * `key`: The edge to create or refresh.
+    ///
+    /// # Errors
+    /// Returns a `NotFound` error if one of the edge's vertices is missing.
+    fn touch_edge(&self, key: &models::EdgeKey) -> Result<bool> {
+        let exists = self.edge_exists(key)?;
+
+        if self.create_edge(key)? {
+            Ok(!exists)
+        } else {
+            Err(missing_edge_vertices(key))
+        }
+    }
+
+    /// Creates a copy of an edge when only one of its vertices is stored in
+    /// this datastore, e.g. for an edge between two shards of a
+    /// `ShardedDatastore`. Only `vertex_id`, which should be one of the
+    /// edge's vertices, is checked for existence; the caller is responsible
+    /// for checking the other. If the edge already exists, this will update
+    /// it with a new update datetime. Returns whether the edge was
+    /// successfully created - if this is false, it's because the vertex is
+    /// missing.
+    ///
+    /// # Arguments
+    /// * `key`: The edge to create.
+    /// * `vertex_id`: The ID of the edge's vertex that's stored in this
+    ///   datastore.
+    ///
+    /// # Errors
+    /// Returns an error if the datastore doesn't support mirrored edges,
+    /// which is the default.
+    fn create_mirrored_edge(&self, _key: &models::EdgeKey, _vertex_id: Uuid) -> Result<bool> {
+        Err("Mirrored edges are not supported by this datastore".into())
+    }
+
+    /// Creates a copy of an edge like `create_mirrored_edge`, but with a
+    /// given update datetime, like `create_edge_with_datetime`.
+    ///
+    /// # Arguments
+    /// * `key`: The edge to create.
+    /// * `vertex_id`: The ID of the edge's vertex that's stored in this
+    ///   datastore.
+    /// * `update_datetime`: The edge's update datetime.
+    ///
+    /// # Errors
+    /// Returns an error if the datastore doesn't support mirrored edges,
+    /// which is the default.
+    fn create_mirrored_edge_with_datetime(
+        &self,
+        _key: &models::EdgeKey,
+        _vertex_id: Uuid,
+        _update_datetime: DateTime<Utc>,
+    ) -> Result<bool> {
+        Err("Mirrored edges are not supported by this datastore".into())
+    }
+
     /// Gets a range of edges specified by a query.
     ///
     /// # Arguments
     /// * `q` - The query to run.
     fn get_edges<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<Vec<models::Edge>>;
 
+    /// Gets a range of edges specified by a query, along with metadata about
+    /// how the query was executed. See `get_vertices_with_metrics`.
+    ///
+    /// # Arguments
+    /// * `q` - The query to run.
+    fn get_edges_with_metrics<Q: Into<models::EdgeQuery>>(
+        &self,
+        q: Q,
+    ) -> Result<(Vec<models::Edge>, models::QueryMetrics)> {
+        let mut q = q.into();
+        let limit = match q {
+            models::EdgeQuery::Specific(_) => None,
+            models::EdgeQuery::Pipe(ref mut q) => probe_limit(&mut q.limit),
+        };
+
+        let start_keys_scanned = self.get_keys_scanned();
+        let edges = self.get_edges(q)?;
+        let keys_scanned = start_keys_scanned.and_then(|start| Some(self.get_keys_scanned()? - start));
+        Ok(with_metrics(edges, limit, keys_scanned))
+    }
+
+    /// Gets a range of edges specified by a query, returning at most
+    /// `max_results` of them. See `get_vertices_page`.
+    ///
+    /// # Arguments
+    /// * `q` - The query to run.
+    /// * `max_results` - The maximum number of edges to return.
+    ///
+    /// # Errors
+    /// Returns a `ValidationError` if `max_results` is zero.
+    fn get_edges_page<Q: Into<models::EdgeQuery>>(&self, q: Q, max_results: u32) -> Result<models::EdgePage> {
+        check_max_results(max_results)?;
+
+        let (edges, truncated, cursor) = match q.into() {
+            models::EdgeQuery::Specific(ref q) if q.keys.len() > max_results as usize => {
+                let mut keys = q.keys.clone();
+                let rest = keys.split_off(max_results as usize);
+                let edges = self.get_edges(models::SpecificEdgeQuery::new(keys))?;
+                (edges, true, Some(models::SpecificEdgeQuery::new(rest).into()))
+            }
+            models::EdgeQuery::Pipe(ref q) if q.limit > max_results => {
+                let (edges, metrics) = self.get_edges_with_metrics(models::PipeEdgeQuery {
+                    limit: max_results,
+                    ..q.clone()
+                })?;
+                (edges, metrics.truncated, None)
+            }
+            q => (self.get_edges(q)?, false, None),
+        };
+
+        Ok(models::EdgePage {
+            edges,
+            truncated,
+            cursor,
+        })
+    }
+
+    /// Checks whether an edge exists, e.g. to skip edges that have already
+    /// been ingested. Datastores override this to answer without reading
+    /// the edge, so that checking for missing edges is cheap.
+    ///
+    /// # Arguments
+    /// * `key`: The edge to check for.
+    fn edge_exists(&self, key: &models::EdgeKey) -> Result<bool> {
+        Ok(!self
+            .get_edges(models::SpecificEdgeQuery::single(key.clone()))?
+            .is_empty())
+    }
+
+    /// Checks which of a set of edges exist, e.g. to dedupe a batch of
+    /// candidate edges during ingestion. Returns whether each edge exists,
+    /// in the same order as the keys. Datastores override this to look the
+    /// edges up in a single round trip; by default, each one is checked
+    /// with `edge_exists`.
+    ///
+    /// # Arguments
+    /// * `keys`: The edges to check for.
+    fn filter_existing_edges(&self, keys: Vec<models::EdgeKey>) -> Result<Vec<bool>> {
+        keys.iter().map(|key| self.edge_exists(key)).collect()
+    }
+
     /// Deletes a set of edges specified by a query.
     ///
     /// # Arguments
     /// * `q` - The query to run.
     fn delete_edges<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<()>;
 
+    /// Deletes the edges specified by a query, but only those whose update
+    /// datetime is at or before `since`. Each edge's update datetime is
+    /// checked as it's deleted, so an edge refreshed after the query was run,
+    /// e.g. by `touch_edge`, is kept. Returns the number of edges deleted.
+    ///
+    /// # Arguments
+    /// * `q` - The query to run.
+    /// * `since` - The latest update datetime of edges to delete.
+    ///
+    /// # Errors
+    /// Returns an error if the datastore doesn't support conditional deletes,
+    /// which is the default.
+    fn delete_edges_unmodified_since<Q: Into<models::EdgeQuery>>(&self, _q: Q, _since: DateTime<Utc>) -> Result<u64> {
+        Err("Conditional deletes are not supported by this datastore".into())
+    }
+
+    /// Deletes copies of edges created by `create_mirrored_edge`. Unlike
+    /// `delete_edges`, this deletes edges whose outbound vertex isn't stored
+    /// in this datastore.
+    ///
+    /// # Arguments
+    /// * `keys` - The edges to delete.
+    ///
+    /// # Errors
+    /// Returns an error if the datastore doesn't support mirrored edges,
+    /// which is the default.
+    fn delete_mirrored_edges(&self, _keys: Vec<models::EdgeKey>) -> Result<()> {
+        Err("Mirrored edges are not supported by this datastore".into())
+    }
+
+    /// Deletes the edges specified by a query in batches. The query is run
+    /// once up front, and `progress` is called after each batch. Returns the
+    /// number of edges deleted.
+    ///
+    /// # Arguments
+    /// * `q` - The query to run.
+    /// * `batch_size` - The maximum number of edges to delete at a time.
+    /// * `progress` - Called with the progress after each batch.
+    fn delete_edges_in_batches<Q, F>(&self, q: Q, batch_size: u32, mut progress: F) -> Result<u64>
+    where
+        Q: Into<models::EdgeQuery>,
+        F: FnMut(models::BulkDeleteProgress),
+    {
+        check_batch_size(batch_size)?;
+        let keys: Vec<models::EdgeKey> = self.get_edges(q)?.into_iter().map(|edge| edge.key).collect();
+        let mut deleted = models::BulkDeleteProgress::new(0, keys.len() as u64);
+
+        for chunk in keys.chunks(batch_size as usize) {
+            self.delete_edges(models::SpecificEdgeQuery::new(chunk.to_vec()))?;
+            deleted.deleted_count += chunk.len() as u64;
+            progress(deleted);
+        }
+
+        Ok(deleted.deleted_count)
+    }
+
     /// Gets the number of edges associated with a vertex.
     ///
     /// # Arguments
@@ -126,6 +666,73 @@ pub trait Transaction {
     /// * `direction`: The direction of edges to get.
     fn get_edge_count(&self, id: Uuid, t: Option<&models::Type>, direction: models::EdgeDirection) -> Result<u64>;
 
+    /// Gets the number of edges associated with a vertex whose update
+    /// datetimes are within a range, e.g. for activity metrics.
+    ///
+    /// # Arguments
+    /// * `id` - The id of the vertex.
+    /// * `t` - Only get the count for a specified edge type.
+    /// * `low` - The oldest update datetime to count, inclusive.
+    /// * `high` - The newest update datetime to count, inclusive.
+    /// * `direction`: The direction of edges to get.
+    fn get_edge_count_in_range(
+        &self,
+        id: Uuid,
+        t: Option<&models::Type>,
+        low: Option<DateTime<Utc>>,
+        high: Option<DateTime<Utc>>,
+        direction: models::EdgeDirection,
+    ) -> Result<u64> {
+        let q = models::SpecificVertexQuery::single(id);
+
+        let mut q = match direction {
+            models::EdgeDirection::Outbound => q.outbound(u32::MAX),
+            models::EdgeDirection::Inbound => q.inbound(u32::MAX),
+            models::EdgeDirection::Both => q.both(u32::MAX),
+        };
+
+        if let Some(t) = t {
+            q = q.t(t.clone());
+        }
+
+        if let Some(low) = low {
+            q = q.low(low);
+        }
+
+        if let Some(high) = high {
+            q = q.high(high);
+        }
+
+        Ok(self.get_edges(q)?.len() as u64)
+    }
+
+    /// Gets the vertices that are neighbors of both of two vertices across
+    /// edges of a type, e.g. the mutual connections of two users. Neighbors
+    /// are returned in ascending order of their ids.
+    ///
+    /// # Arguments
+    /// * `first_id` - The id of the first vertex.
+    /// * `second_id` - The id of the second vertex.
+    /// * `t` - The type of edges to follow.
+    /// * `direction`: The direction of edges to follow from each vertex.
+    /// * `limit` - The maximum number of neighbors to return.
+    fn get_common_neighbors(
+        &self,
+        first_id: Uuid,
+        second_id: Uuid,
+        t: &models::Type,
+        direction: models::EdgeDirection,
+        limit: u32,
+    ) -> Result<Vec<Uuid>> {
+        let first_neighbor_ids = sorted_neighbor_ids(self, first_id, t, direction)?;
+        let second_neighbor_ids = sorted_neighbor_ids(self, second_id, t, direction)?;
+        intersect_sorted(
+            first_neighbor_ids.into_iter().map(Ok),
+            second_neighbor_ids.into_iter().map(Ok),
+            limit,
+        )
+    }
+
     /// Gets vertex properties.
     ///
     /// # Arguments
@@ -148,6 +755,114 @@ pub trait Transaction {
     /// * `name` - The property name.
     fn delete_vertex_properties(&self, q: models::VertexPropertyQuery) -> Result<()>;
 
+    /// Gets vertex properties as JSON-encoded bytes, rather than
+    /// deserializing them. This is useful for callers that pass property
+    /// values through without inspecting them. The default implementation
+    /// serializes the results of `get_vertex_properties`; datastores that
+    /// store properties as JSON should override it to skip the round-trip.
+    ///
+    /// # Arguments
+    /// * `q` - The query to run.
+    fn get_vertex_properties_raw(&self, q: models::VertexPropertyQuery) -> Result<Vec<models::RawVertexProperty>> {
+        self.get_vertex_properties(q)?
+            .into_iter()
+            .map(|property| {
+                Ok(models::RawVertexProperty::new(
+                    property.id,
+                    serde_json::to_vec(&property.value)?,
+                ))
+            })
+            .collect()
+    }
+
+    /// Gets vertices along with a selection of their properties. Only the
+    /// named properties are fetched; vertices that have none of them are
+    /// returned with no properties.
+    ///
+    /// # Arguments
+    /// * `q` - The query to run.
+    /// * `names` - The names of the properties to fetch.
+    fn get_named_vertex_properties<Q: Into<models::VertexQuery>>(
+        &self,
+        q: Q,
+        names: &[&str],
+    ) -> Result<Vec<models::VertexProperties>> {
+        let vertices = self.get_vertices(q)?;
+        let mut ids: Vec<Uuid> = vertices.iter().map(|vertex| vertex.id).collect();
+        ids.sort();
+        ids.dedup();
+        let mut props_by_id: HashMap<Uuid, Vec<models::NamedProperty>> = HashMap::new();
+
+        for name in names {
+            let q = models::SpecificVertexQuery::new(ids.clone()).property(*name);
+
+            for property in self.get_vertex_properties(q)? {
+                props_by_id
+                    .entry(property.id)
+                    .or_default()
+                    .push(models::NamedProperty::new(name.to_string(), property.value));
+            }
+        }
+
+        Ok(vertices
+            .into_iter()
+            .map(|vertex| {
+                let props = props_by_id.get(&vertex.id).cloned().unwrap_or_default();
+                models::VertexProperties::new(vertex, props)
+            })
+            .collect())
+    }
+
+    /// Gets vertices along with all of their properties.
+    ///
+    /// # Arguments
+    /// * `q` - The query to run.
+    fn get_all_vertex_properties<Q: Into<models::VertexQuery>>(&self, q: Q) -> Result<Vec<models::VertexProperties>>;
+
+    /// Gets the properties of vertices whose names start with a prefix, as a
+    /// flat list ordered by vertex, then by name. This is useful for
+    /// extracting one attribute across all vertices: page through them with
+    /// a `RangeVertexQuery`, starting each page after the last id seen.
+    ///
+    /// # Arguments
+    /// * `q` - The query to run.
+    /// * `prefix` - The prefix that property names must start with. An empty
+    ///   prefix matches every property.
+    fn get_vertex_properties_with_prefix<Q: Into<models::VertexQuery>>(
+        &self,
+        q: Q,
+        prefix: &str,
+    ) -> Result<Vec<models::NamedVertexProperty>> {
+        let mut results = Vec::new();
+
+        for vertex_props in self.get_all_vertex_properties(q)? {
+            let id = vertex_props.vertex.id;
+            let mut props: Vec<models::NamedProperty> = vertex_props
+                .props
+                .into_iter()
+                .filter(|prop| prop.name.starts_with(prefix))
+                .collect();
+            props.sort_by(|first, second| first.name.cmp(&second.name));
+            results.extend(
+                props
+                    .into_iter()
+                    .map(|prop| models::NamedVertexProperty::new(id, prop.name, prop.value)),
+            );
+        }
+
+        Ok(results)
+    }
+
+    /// Gets a single vertex property as JSON-encoded bytes.
+    ///
+    /// # Arguments
+    /// * `id` - The id of the vertex.
+    /// * `name` - The property name.
+    fn get_vertex_property_raw(&self, id: Uuid, name: &str) -> Result<Option<Vec<u8>>> {
+        let q = models::SpecificVertexQuery::single(id).property(name);
+        Ok(self.get_vertex_properties_raw(q)?.pop().map(|property| property.value))
+    }
+
     /// Gets edge properties.
     ///
     /// # Arguments
@@ -169,4 +884,147 @@ pub trait Transaction {
     /// * `q` - The query to run.
     /// * `name` - The property name.
     fn delete_edge_properties(&self, q: models::EdgePropertyQuery) -> Result<()>;
+
+    /// Gets edge properties as JSON-encoded bytes, rather than deserializing
+    /// them. See `get_vertex_properties_raw`.
+    ///
+    /// # Arguments
+    /// * `q` - The query to run.
+    fn get_edge_properties_raw(&self, q: models::EdgePropertyQuery) -> Result<Vec<models::RawEdgeProperty>> {
+        self.get_edge_properties(q)?
+            .into_iter()
+            .map(|property| {
+                Ok(models::RawEdgeProperty::new(
+                    property.key,
+                    serde_json::to_vec(&property.value)?,
+                ))
+            })
+            .collect()
+    }
+
+    /// Gets edges along with a selection of their properties. Only the named
+    /// properties are fetched; edges that have none of them are returned
+    /// with no properties.
+    ///
+    /// # Arguments
+    /// * `q` - The query to run.
+    /// * `names` - The names of the properties to fetch.
+    fn get_named_edge_properties<Q: Into<models::EdgeQuery>>(
+        &self,
+        q: Q,
+        names: &[&str],
+    ) -> Result<Vec<models::EdgeProperties>> {
+        let edges = self.get_edges(q)?;
+        let mut keys: Vec<models::EdgeKey> = edges.iter().map(|edge| edge.key.clone()).collect();
+        keys.sort();
+        keys.dedup();
+        let mut props_by_key: HashMap<models::EdgeKey, Vec<models::NamedProperty>> = HashMap::new();
+
+        for name in names {
+            let q = models::SpecificEdgeQuery::new(keys.clone()).property(*name);
+
+            for property in self.get_edge_properties(q)? {
+                props_by_key
+                    .entry(property.key)
+                    .or_default()
+                    .push(models::NamedProperty::new(name.to_string(), property.value));
+            }
+        }
+
+        Ok(edges
+            .into_iter()
+            .map(|edge| {
+                let props = props_by_key.get(&edge.key).cloned().unwrap_or_default();
+                models::EdgeProperties::new(edge, props)
+            })
+            .collect())
+    }
+
+    /// Gets edges along with all of their properties.
+    ///
+    /// # Arguments
+    /// * `q` - The query to run.
+    fn get_all_edge_properties<Q: Into<models::EdgeQuery>>(&self, q: Q) -> Result<Vec<models::EdgeProperties>>;
+
+    /// Gets a single edge property as JSON-encoded bytes.
+    ///
+    /// # Arguments
+    /// * `key` - The key to the edge.
+    /// * `name` - The property name.
+    fn get_edge_property_raw(&self, key: &models::EdgeKey, name: &str) -> Result<Option<Vec<u8>>> {
+        let q = models::SpecificEdgeQuery::single(key.clone()).property(name);
+        Ok(self.get_edge_properties_raw(q)?.pop().map(|property| property.value))
+    }
+
+    /// Gets the number of keys this transaction has read from the datastore
+    /// so far, which `get_vertices_with_metrics` and `get_edges_with_metrics`
+    /// use to report how many keys each query read. Statements run
+    /// concurrently on the same transaction are counted together. Datastores
+    /// that don't count the keys they read return `None`, which is the
+    /// default.
+    fn get_keys_scanned(&self) -> Option<u64> {
+        None
+    }
+
+    /// Runs a batch of operations in order, returning the output of each.
+    /// Execution stops at the first operation that fails, whose error is
+    /// returned; the operations before it are not rolled back unless the
+    /// implementation rolls back the whole transaction on error.
+    ///
+    /// Implementations that talk to a remote server should override this,
+    /// so that the whole batch is sent in one request.
+    ///
+    /// # Arguments
+    /// * `operations` - The operations to run.
+    fn execute_batch(&self, operations: Vec<models::Operation>) -> Result<Vec<models::OperationOutput>> {
+        let mut outputs = Vec::with_capacity(operations.len());
+
+        for operation in operations {
+            let output = match operation {
+                models::Operation::CreateVertex(vertex) => {
+                    models::OperationOutput::Created(self.create_vertex(&vertex)?)
+                }
+                models::Operation::CreateVertexFromType(t) => {
+                    models::OperationOutput::Id(self.create_vertex_from_type(t)?)
+                }
+                models::Operation::GetVertices(q) => models::OperationOutput::Vertices(self.get_vertices(q)?),
+                models::Operation::DeleteVertices(q) => {
+                    self.delete_vertices(q)?;
+                    models::OperationOutput::Done
+                }
+                models::Operation::CreateEdge(key) => models::OperationOutput::Created(self.create_edge(&key)?),
+                models::Operation::GetEdges(q) => models::OperationOutput::Edges(self.get_edges(q)?),
+                models::Operation::DeleteEdges(q) => {
+                    self.delete_edges(q)?;
+                    models::OperationOutput::Done
+                }
+                models::Operation::GetVertexProperties(q) => {
+                    models::OperationOutput::VertexProperties(self.get_vertex_properties(q)?)
+                }
+                models::Operation::SetVertexProperties(q, value) => {
+                    self.set_vertex_properties(q, &value)?;
+                    models::OperationOutput::Done
+                }
+                models::Operation::DeleteVertexProperties(q) => {
+                    self.delete_vertex_properties(q)?;
+                    models::OperationOutput::Done
+                }
+                models::Operation::GetEdgeProperties(q) => {
+                    models::OperationOutput::EdgeProperties(self.get_edge_properties(q)?)
+                }
+                models::Operation::SetEdgeProperties(q, value) => {
+                    self.set_edge_properties(q, &value)?;
+                    models::OperationOutput::Done
+                }
+                models::Operation::DeleteEdgeProperties(q) => {
+                    self.delete_edge_properties(q)?;
+                    models::OperationOutput::Done
+                }
+            };
+
+            outputs.push(output);
+        }
+
+        Ok(outputs)
+    }
 }
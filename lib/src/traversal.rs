@@ -0,0 +1,1113 @@
+//! Traversals that follow edges repeatedly, evaluated against a transaction
+//! so that only their results have to be sent to clients.
+
+use chrono::offset::Utc;
+use chrono::{DateTime, Duration};
+use errors::{Result, ValidationError};
+use models;
+use models::{EdgeDirection, EdgeQueryExt, VertexQueryExt};
+use serde_json::Value as JsonValue;
+use std::cmp::{Ordering, Reverse};
+use std::collections::hash_map::Entry;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use traits::Transaction;
+use uuid::Uuid;
+
+// How many edges' properties are read at a time when ranking neighbors.
+const RANK_CHUNK_SIZE: usize = 1000;
+
+/// Gets the vertices that can be reached from a vertex by following edges of
+/// a type, e.g. all of the organizations that ultimately own an
+/// organization. Cycles are followed only once, so every vertex is returned
+/// at most once.
+///
+/// Returns the ids of the vertices along with the number of edges followed
+/// to first reach them, in breadth-first order. The starting vertex isn't
+/// included, even if it's part of a cycle.
+///
+/// # Arguments
+/// * `trans` - The transaction to read from.
+/// * `id` - The id of the vertex to start from.
+/// * `t` - The type of edges to follow.
+/// * `direction` - Whether to follow edges from their outbound vertices to
+///   their inbound vertices, the other way around, or both.
+/// * `max_depth` - The maximum number of edges to follow.
+/// * `limit` - The maximum number of vertices to return.
+pub fn transitive_closure<T: Transaction>(
+    trans: &T,
+    id: Uuid,
+    t: &models::Type,
+    direction: EdgeDirection,
+    max_depth: u32,
+    limit: u32,
+) -> Result<Vec<(Uuid, u32)>> {
+    let mut reached = Vec::new();
+    let mut seen = HashSet::new();
+    seen.insert(id);
+    let mut frontier = vec![id];
+
+    for depth in 1..=max_depth {
+        if frontier.is_empty() || reached.len() >= limit as usize {
+            break;
+        }
+
+        let mut next_frontier = Vec::new();
+
+        for neighbor_id in neighbors(trans, frontier, Some(t), direction)? {
+            if seen.insert(neighbor_id) {
+                reached.push((neighbor_id, depth));
+                next_frontier.push(neighbor_id);
+
+                if reached.len() >= limit as usize {
+                    break;
+                }
+            }
+        }
+
+        frontier = next_frontier;
+    }
+
+    Ok(reached)
+}
+
+/// Enumerates the simple paths between two vertices, i.e. the paths that
+/// don't visit any vertex more than once, e.g. to trace the lineage of a
+/// dataset. Paths follow edges from their outbound vertices to their inbound
+/// vertices, and are passed to `f` as they're found.
+///
+/// Returns the number of paths found. There are no paths from a vertex to
+/// itself.
+///
+/// # Arguments
+/// * `trans` - The transaction to read from.
+/// * `from` - The id of the vertex the paths start at.
+/// * `to` - The id of the vertex the paths end at.
+/// * `types` - The types of edges to follow. If empty, edges of any type
+///   are followed.
+/// * `max_depth` - The maximum number of edges in a path.
+/// * `limit` - The maximum number of paths to find.
+/// * `f` - Called with the edges of each path, in order.
+pub fn simple_paths<T, F>(
+    trans: &T,
+    from: Uuid,
+    to: Uuid,
+    types: &[models::Type],
+    max_depth: u32,
+    limit: u32,
+    f: F,
+) -> Result<u32>
+where
+    T: Transaction,
+    F: FnMut(Vec<models::EdgeKey>) -> Result<()>,
+{
+    if from == to || max_depth == 0 || limit == 0 {
+        return Ok(0);
+    }
+
+    // How many edges each vertex that can reach `to` within `max_depth`
+    // edges is from it, so that the search can skip every other vertex
+    let mut distances = HashMap::new();
+    distances.insert(to, 0);
+    let mut frontier = vec![to];
+
+    for depth in 1..max_depth {
+        let mut next_frontier = Vec::new();
+
+        for id in frontier {
+            for (_, neighbor_id) in adjacent_edges(trans, id, types, EdgeDirection::Inbound)? {
+                if let Entry::Vacant(entry) = distances.entry(neighbor_id) {
+                    entry.insert(depth);
+                    next_frontier.push(neighbor_id);
+                }
+            }
+        }
+
+        frontier = next_frontier;
+    }
+
+    let mut search = PathSearch {
+        trans,
+        to,
+        types,
+        max_depth,
+        limit,
+        distances,
+        found: 0,
+        f,
+    };
+
+    let mut on_path = HashSet::new();
+    on_path.insert(from);
+    search.extend(from, &mut Vec::new(), &mut on_path)?;
+    Ok(search.found)
+}
+
+/// Gets the vertices that can be reached from a vertex over time-respecting
+/// paths, i.e. paths where each edge's update datetime is no earlier than
+/// the previous one's, e.g. to trace who could have been infected through a
+/// series of contacts, or who could have heard a piece of news.
+///
+/// Returns the ids of the vertices along with the earliest datetime they
+/// can be reached at, which is the update datetime of the last edge of the
+/// path. Vertices are returned in order of that datetime. The starting
+/// vertex isn't included.
+///
+/// # Arguments
+/// * `trans` - The transaction to read from.
+/// * `id` - The id of the vertex to start from.
+/// * `types` - The types of edges to follow. If empty, edges of any type
+///   are followed.
+/// * `direction` - Whether to follow edges from their outbound vertices to
+///   their inbound vertices, the other way around, or both.
+/// * `start` - The earliest update datetime the first edge of a path can
+///   have.
+/// * `limit` - The maximum number of vertices to return.
+pub fn time_respecting_reachable<T: Transaction>(
+    trans: &T,
+    id: Uuid,
+    types: &[models::Type],
+    direction: EdgeDirection,
+    start: DateTime<Utc>,
+    limit: u32,
+) -> Result<Vec<(Uuid, DateTime<Utc>)>> {
+    let mut reached = Vec::new();
+    let mut settled = HashSet::new();
+
+    // Vertices to visit, earliest first
+    let mut queue = BinaryHeap::new();
+    queue.push(Reverse((start, id)));
+
+    while let Some(Reverse((datetime, id))) = queue.pop() {
+        if !settled.insert(id) {
+            continue;
+        }
+
+        if settled.len() > 1 {
+            reached.push((id, datetime));
+
+            if reached.len() >= limit as usize {
+                break;
+            }
+        }
+
+        let mut edges = Vec::new();
+
+        if direction != EdgeDirection::Inbound {
+            edges.extend(adjacent_edges(trans, id, types, EdgeDirection::Outbound)?);
+        }
+
+        if direction != EdgeDirection::Outbound {
+            edges.extend(adjacent_edges(trans, id, types, EdgeDirection::Inbound)?);
+        }
+
+        for (edge, neighbor_id) in edges {
+            if edge.created_datetime >= datetime && !settled.contains(&neighbor_id) {
+                queue.push(Reverse((edge.created_datetime, neighbor_id)));
+            }
+        }
+    }
+
+    Ok(reached)
+}
+
+/// Gets the weight of an edge that decays exponentially with its age, i.e.
+/// `0.5 ^ (age / half_life)`. Edges from the future have a weight of 1.
+///
+/// # Arguments
+/// * `datetime` - The update datetime of the edge.
+/// * `now` - The datetime to measure the edge's age at.
+/// * `half_life` - How long it takes for the weight to halve.
+pub fn decayed_weight(datetime: DateTime<Utc>, now: DateTime<Utc>, half_life: Duration) -> f64 {
+    let age = now.signed_duration_since(datetime).num_milliseconds().max(0) as f64;
+    0.5f64.powf(age / half_life.num_milliseconds() as f64)
+}
+
+/// Scores vertices by the decayed weights of the edges they're on, e.g. to
+/// rank recommendations by how many recent interactions point to them.
+///
+/// Each edge returned by the query adds its `decayed_weight` to the score of
+/// the vertex at one of its ends. Returns the vertex ids along with their
+/// scores, highest first.
+///
+/// # Arguments
+/// * `trans` - The transaction to read from.
+/// * `q` - The query for the edges to score by.
+/// * `direction` - Which end of the edges to score. `Both` scores both.
+/// * `now` - The datetime to measure edge ages at.
+/// * `half_life` - How long it takes for an edge's weight to halve.
+/// * `window` - If set, edges older than this are skipped.
+///
+/// # Errors
+/// Returns a `ValidationError` if `half_life` isn't positive.
+pub fn decayed_scores<T, Q>(
+    trans: &T,
+    q: Q,
+    direction: EdgeDirection,
+    now: DateTime<Utc>,
+    half_life: Duration,
+    window: Option<Duration>,
+) -> Result<Vec<(Uuid, f64)>>
+where
+    T: Transaction,
+    Q: Into<models::EdgeQuery>,
+{
+    if half_life.num_milliseconds() <= 0 {
+        return Err(ValidationError::from("The half-life must be positive").into());
+    }
+
+    let mut scores: HashMap<Uuid, f64> = HashMap::new();
+
+    for edge in trans.get_edges(q)? {
+        if let Some(window) = window {
+            if now.signed_duration_since(edge.created_datetime) > window {
+                continue;
+            }
+        }
+
+        let weight = decayed_weight(edge.created_datetime, now, half_life);
+
+        if direction != EdgeDirection::Inbound {
+            *scores.entry(edge.key.inbound_id).or_insert(0.0) += weight;
+        }
+
+        if direction != EdgeDirection::Outbound {
+            *scores.entry(edge.key.outbound_id).or_insert(0.0) += weight;
+        }
+    }
+
+    let mut scores: Vec<(Uuid, f64)> = scores.into_iter().collect();
+    scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then(a.0.cmp(&b.0)));
+    Ok(scores)
+}
+
+/// The property to rank a vertex's neighbors by. See `top_neighbors`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RankBy {
+    /// A property of the edges to the neighbors, e.g. a connection strength.
+    EdgeProperty(String),
+
+    /// A property of the neighbors themselves, e.g. a popularity.
+    VertexProperty(String),
+}
+
+/// Gets the edges from a vertex to the `k` neighbors with the highest values
+/// of a numeric property. Edges whose property is missing or isn't a number
+/// are skipped. Only `k` edges are kept in memory at a time, so this is
+/// suitable for vertices with many edges.
+///
+/// Returns the edges along with the property values, highest first.
+///
+/// # Arguments
+/// * `trans` - The transaction to read from.
+/// * `id` - The id of the vertex.
+/// * `t` - If set, only edges of this type are considered.
+/// * `direction` - Whether to consider the outbound edges, the inbound
+///   edges or both.
+/// * `rank_by` - The property to rank by.
+/// * `k` - The maximum number of edges to return.
+pub fn top_neighbors<T: Transaction>(
+    trans: &T,
+    id: Uuid,
+    t: Option<&models::Type>,
+    direction: EdgeDirection,
+    rank_by: &RankBy,
+    k: u32,
+) -> Result<Vec<(models::EdgeKey, f64)>> {
+    let types: Vec<models::Type> = t.into_iter().cloned().collect();
+    let mut edges = Vec::new();
+
+    if direction != EdgeDirection::Inbound {
+        edges.extend(adjacent_edges(trans, id, &types, EdgeDirection::Outbound)?);
+    }
+
+    if direction != EdgeDirection::Outbound {
+        edges.extend(adjacent_edges(trans, id, &types, EdgeDirection::Inbound)?);
+    }
+
+    // Self-loops are returned in both directions
+    if direction == EdgeDirection::Both {
+        edges.sort_by(|a, b| a.0.key.cmp(&b.0.key));
+        edges.dedup_by(|a, b| a.0.key == b.0.key);
+    }
+
+    // The best edges seen so far, with the worst on top
+    let mut heap = BinaryHeap::new();
+
+    for chunk in edges.chunks(RANK_CHUNK_SIZE) {
+        let ranked: Vec<(models::EdgeKey, JsonValue)> = match *rank_by {
+            RankBy::EdgeProperty(ref name) => {
+                let keys = chunk.iter().map(|(edge, _)| edge.key.clone()).collect();
+                trans
+                    .get_edge_properties(models::SpecificEdgeQuery::new(keys).property(name.as_str()))?
+                    .into_iter()
+                    .map(|property| (property.key, property.value))
+                    .collect()
+            }
+            RankBy::VertexProperty(ref name) => {
+                let ids = chunk.iter().map(|&(_, neighbor_id)| neighbor_id).collect();
+                let values: HashMap<Uuid, JsonValue> = trans
+                    .get_vertex_properties(models::SpecificVertexQuery::new(ids).property(name.as_str()))?
+                    .into_iter()
+                    .map(|property| (property.id, property.value))
+                    .collect();
+                chunk
+                    .iter()
+                    .filter_map(|&(ref edge, neighbor_id)| {
+                        values.get(&neighbor_id).map(|value| (edge.key.clone(), value.clone()))
+                    })
+                    .collect()
+            }
+        };
+
+        for (key, value) in ranked {
+            if let Some(score) = value.as_f64() {
+                heap.push(Reverse(Ranked { score, key }));
+
+                if heap.len() > k as usize {
+                    heap.pop();
+                }
+            }
+        }
+    }
+
+    Ok(heap
+        .into_sorted_vec()
+        .into_iter()
+        .map(|Reverse(ranked)| (ranked.key, ranked.score))
+        .collect())
+}
+
+// An edge ranked by a score. Scores come from JSON numbers, so they're never
+// NaN, and can be totally ordered.
+#[derive(PartialEq)]
+struct Ranked {
+    score: f64,
+    key: models::EdgeKey,
+}
+
+impl Eq for Ranked {}
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Ranked {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score
+            .partial_cmp(&other.score)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| other.key.cmp(&self.key))
+    }
+}
+
+/// How candidate edges are scored by the neighbors their vertices share.
+/// See `score_links`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LinkScore {
+    /// The number of shared neighbors, divided by the number of neighbors
+    /// of either vertex.
+    Jaccard,
+
+    /// The sum of `1 / ln(degree)` over the shared neighbors, so that
+    /// neighbors with fewer edges count for more. A shared neighbor's degree
+    /// is its number of edges of the type in the opposite direction, i.e.
+    /// how many vertices reach it the same way.
+    AdamicAdar,
+}
+
+/// Scores the candidate edges between a vertex and each of a set of other
+/// vertices by the neighbors they share, e.g. to rank who a user is most
+/// likely to follow. Scoring against the transaction means only the scores
+/// have to be sent to clients, rather than every candidate's edges.
+///
+/// Returns the scores in the same order as the candidates. Candidates that
+/// don't share any neighbors with the vertex score zero.
+///
+/// # Arguments
+/// * `trans` - The transaction to read from.
+/// * `id` - The id of the vertex.
+/// * `candidate_ids` - The ids of the vertices to score edges to.
+/// * `t` - The type of edges to follow to neighbors.
+/// * `direction` - The direction of edges to follow from each vertex.
+/// * `score` - How to score the candidates.
+pub fn score_links<T: Transaction>(
+    trans: &T,
+    id: Uuid,
+    candidate_ids: &[Uuid],
+    t: &models::Type,
+    direction: EdgeDirection,
+    score: LinkScore,
+) -> Result<Vec<f64>> {
+    let neighbor_count = match score {
+        LinkScore::Jaccard => distinct_neighbor_count(trans, id, t, direction)?,
+        LinkScore::AdamicAdar => 0,
+    };
+
+    // Shared neighbors' degrees are looked up once, since popular neighbors
+    // are likely to be shared with many of the candidates
+    let mut degrees = HashMap::new();
+    let mut scores = Vec::with_capacity(candidate_ids.len());
+
+    for &candidate_id in candidate_ids {
+        let common_ids = trans.get_common_neighbors(id, candidate_id, t, direction, u32::MAX)?;
+
+        let candidate_score = match score {
+            LinkScore::Jaccard => {
+                if common_ids.is_empty() {
+                    0.0
+                } else {
+                    let common_count = common_ids.len() as u64;
+                    let candidate_neighbor_count = distinct_neighbor_count(trans, candidate_id, t, direction)?;
+
+                    // Edges may change between reads, so the counts aren't
+                    // trusted to be consistent with each other
+                    let union_count = (neighbor_count + candidate_neighbor_count)
+                        .saturating_sub(common_count)
+                        .max(common_count);
+                    common_count as f64 / union_count as f64
+                }
+            }
+            LinkScore::AdamicAdar => {
+                let mut candidate_score = 0.0;
+
+                for common_id in common_ids {
+                    let degree = match degrees.entry(common_id) {
+                        Entry::Occupied(entry) => *entry.get(),
+                        Entry::Vacant(entry) => {
+                            let degree = distinct_neighbor_count(trans, common_id, t, reversed(direction))?;
+                            *entry.insert(degree)
+                        }
+                    };
+
+                    // A neighbor shared with the vertex itself may have only
+                    // one edge, and `ln(1)` is zero
+                    if degree > 1 {
+                        candidate_score += 1.0 / (degree as f64).ln();
+                    }
+                }
+
+                candidate_score
+            }
+        };
+
+        scores.push(candidate_score);
+    }
+
+    Ok(scores)
+}
+
+/// The result of checking whether a subgraph is bipartite. See
+/// `two_coloring`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TwoColoring {
+    /// The subgraph is bipartite. Every vertex is mapped to a color,
+    /// `false` or `true`, such that every edge joins vertices of different
+    /// colors. The first vertex reached in each connected component is
+    /// colored `false`.
+    Bipartite(HashMap<Uuid, bool>),
+
+    /// The subgraph isn't bipartite. This edge joins two vertices that have
+    /// to be the same color, i.e. it closes a cycle of odd length.
+    NotBipartite(models::EdgeKey),
+}
+
+/// Checks whether a subgraph is bipartite, and if it is, colors its vertices
+/// with two colors so that no edge joins vertices of the same color, e.g. to
+/// check that a graph of users and items has no user-user or item-item
+/// edges. The subgraph is made up of the vertices connected to `ids` by
+/// edges of `types`, in either direction. Each connected component is loaded
+/// in full, a breadth-first level at a time.
+///
+/// # Arguments
+/// * `trans` - The transaction to read from.
+/// * `ids` - The ids of vertices in the subgraph. Every vertex connected to
+///   one of these is included.
+/// * `types` - The types of edges in the subgraph. If empty, edges of every
+///   type are included.
+pub fn two_coloring<T: Transaction>(trans: &T, ids: Vec<Uuid>, types: &[models::Type]) -> Result<TwoColoring> {
+    let mut colors = HashMap::new();
+
+    for id in ids {
+        if colors.contains_key(&id) {
+            continue;
+        }
+
+        colors.insert(id, false);
+        let mut frontier = vec![id];
+
+        while !frontier.is_empty() {
+            let mut next_frontier = Vec::new();
+
+            for &direction in &[EdgeDirection::Outbound, EdgeDirection::Inbound] {
+                for (edge, neighbor_id) in frontier_edges(trans, frontier.clone(), types, direction)? {
+                    let owner_id = match direction {
+                        EdgeDirection::Inbound => edge.key.inbound_id,
+                        _ => edge.key.outbound_id,
+                    };
+                    let color = colors[&owner_id];
+
+                    match colors.entry(neighbor_id) {
+                        Entry::Occupied(entry) => {
+                            if *entry.get() == color {
+                                return Ok(TwoColoring::NotBipartite(edge.key));
+                            }
+                        }
+                        Entry::Vacant(entry) => {
+                            entry.insert(!color);
+                            next_frontier.push(neighbor_id);
+                        }
+                    }
+                }
+            }
+
+            frontier = next_frontier;
+        }
+    }
+
+    Ok(TwoColoring::Bipartite(colors))
+}
+
+struct PathSearch<'a, T: Transaction + 'a, F> {
+    trans: &'a T,
+    to: Uuid,
+    types: &'a [models::Type],
+    max_depth: u32,
+    limit: u32,
+    distances: HashMap<Uuid, u32>,
+    found: u32,
+    f: F,
+}
+
+impl<'a, T, F> PathSearch<'a, T, F>
+where
+    T: Transaction + 'a,
+    F: FnMut(Vec<models::EdgeKey>) -> Result<()>,
+{
+    // Extends a path that ends at `id`, returning whether `limit` paths have
+    // been found.
+    fn extend(&mut self, id: Uuid, path: &mut Vec<models::EdgeKey>, on_path: &mut HashSet<Uuid>) -> Result<bool> {
+        let remaining = self.max_depth - path.len() as u32 - 1;
+
+        for (edge, neighbor_id) in adjacent_edges(self.trans, id, self.types, EdgeDirection::Outbound)? {
+            if on_path.contains(&neighbor_id) {
+                continue;
+            }
+
+            match self.distances.get(&neighbor_id) {
+                Some(&distance) if distance <= remaining => (),
+                _ => continue,
+            }
+
+            path.push(edge.key);
+
+            if neighbor_id == self.to {
+                (self.f)(path.clone())?;
+                self.found += 1;
+
+                if self.found >= self.limit {
+                    return Ok(true);
+                }
+            } else {
+                on_path.insert(neighbor_id);
+
+                if self.extend(neighbor_id, path, on_path)? {
+                    return Ok(true);
+                }
+
+                on_path.remove(&neighbor_id);
+            }
+
+            path.pop();
+        }
+
+        Ok(false)
+    }
+}
+
+// Gets the number of distinct vertices across edges of a type from a
+// vertex. In one direction there's at most one edge of a type per neighbor,
+// so edges can be counted, but in both a neighbor may be at the other end of
+// two of them.
+fn distinct_neighbor_count<T: Transaction>(
+    trans: &T,
+    id: Uuid,
+    t: &models::Type,
+    direction: EdgeDirection,
+) -> Result<u64> {
+    match direction {
+        EdgeDirection::Both => {
+            let neighbor_ids: HashSet<Uuid> = neighbors(trans, vec![id], Some(t), direction)?.into_iter().collect();
+            Ok(neighbor_ids.len() as u64)
+        }
+        _ => trans.get_edge_count(id, Some(t), direction),
+    }
+}
+
+fn reversed(direction: EdgeDirection) -> EdgeDirection {
+    match direction {
+        EdgeDirection::Outbound => EdgeDirection::Inbound,
+        EdgeDirection::Inbound => EdgeDirection::Outbound,
+        EdgeDirection::Both => EdgeDirection::Both,
+    }
+}
+
+// Gets the edges of a vertex in one direction, along with the ids of the
+// vertices at their other ends.
+fn adjacent_edges<T: Transaction>(
+    trans: &T,
+    id: Uuid,
+    types: &[models::Type],
+    direction: EdgeDirection,
+) -> Result<Vec<(models::Edge, Uuid)>> {
+    frontier_edges(trans, vec![id], types, direction)
+}
+
+// Gets the edges of a set of vertices in one direction, along with the ids
+// of the vertices at their other ends.
+fn frontier_edges<T: Transaction>(
+    trans: &T,
+    ids: Vec<Uuid>,
+    types: &[models::Type],
+    direction: EdgeDirection,
+) -> Result<Vec<(models::Edge, Uuid)>> {
+    let q = match direction {
+        EdgeDirection::Inbound => models::SpecificVertexQuery::new(ids).inbound(u32::MAX),
+        _ => models::SpecificVertexQuery::new(ids).outbound(u32::MAX),
+    };
+
+    // A single type can be filtered by the datastore
+    let q = match types.len() {
+        1 => q.t(types[0].clone()),
+        _ => q,
+    };
+
+    Ok(trans
+        .get_edges(q)?
+        .into_iter()
+        .filter(|edge| types.is_empty() || types.contains(&edge.key.t))
+        .map(|edge| {
+            let neighbor_id = match direction {
+                EdgeDirection::Inbound => edge.key.outbound_id,
+                _ => edge.key.inbound_id,
+            };
+
+            (edge, neighbor_id)
+        })
+        .collect())
+}
+
+// Gets the ids of the vertices across edges from a set of vertices, in the
+// given direction. Edges to vertices that don't exist are skipped. Vertices
+// may be returned more than once.
+fn neighbors<T: Transaction>(
+    trans: &T,
+    ids: Vec<Uuid>,
+    t: Option<&models::Type>,
+    direction: EdgeDirection,
+) -> Result<Vec<Uuid>> {
+    let mut neighbor_ids = Vec::new();
+
+    if direction != EdgeDirection::Inbound {
+        let q = models::SpecificVertexQuery::new(ids.clone()).outbound(u32::MAX);
+
+        let q = match t {
+            Some(t) => q.t(t.clone()),
+            None => q,
+        };
+
+        neighbor_ids.extend(
+            trans
+                .get_vertices(q.inbound(u32::MAX))?
+                .into_iter()
+                .map(|vertex| vertex.id),
+        );
+    }
+
+    if direction != EdgeDirection::Outbound {
+        let q = models::SpecificVertexQuery::new(ids).inbound(u32::MAX);
+
+        let q = match t {
+            Some(t) => q.t(t.clone()),
+            None => q,
+        };
+
+        neighbor_ids.extend(
+            trans
+                .get_vertices(q.outbound(u32::MAX))?
+                .into_iter()
+                .map(|vertex| vertex.id),
+        );
+    }
+
+    Ok(neighbor_ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        decayed_scores, decayed_weight, score_links, simple_paths, time_respecting_reachable, top_neighbors,
+        transitive_closure, two_coloring, LinkScore, RankBy, TwoColoring,
+    };
+    use chrono::offset::Utc;
+    use chrono::{Duration, TimeZone};
+    use memory::MemoryDatastore;
+    use models::{EdgeDirection, EdgeKey, Type};
+    use models::{EdgeQueryExt, SpecificEdgeQuery};
+    use models::{SpecificVertexQuery, VertexQueryExt};
+    use serde_json::Value as JsonValue;
+    use traits::{Datastore, Transaction};
+
+    #[test]
+    fn should_get_transitive_closure() {
+        let datastore = MemoryDatastore::default();
+        let trans = datastore.transaction().unwrap();
+        let org_t = Type::new("org").unwrap();
+        let owned_by_t = Type::new("owned_by").unwrap();
+        let other_t = Type::new("other").unwrap();
+        let ids: Vec<_> = (0..5)
+            .map(|_| trans.create_vertex_from_type(org_t.clone()).unwrap())
+            .collect();
+
+        // 0 -> 1 -> 2 -> 0 is a cycle, and 2 -> 3 leads out of it
+        for &(outbound, inbound) in &[(0, 1), (1, 2), (2, 0), (2, 3)] {
+            trans
+                .create_edge(&EdgeKey::new(ids[outbound], owned_by_t.clone(), ids[inbound]))
+                .unwrap();
+        }
+
+        trans.create_edge(&EdgeKey::new(ids[3], other_t, ids[4])).unwrap();
+
+        let closure = |id, direction, max_depth, limit| {
+            transitive_closure(&trans, id, &owned_by_t, direction, max_depth, limit).unwrap()
+        };
+
+        assert_eq!(
+            closure(ids[0], EdgeDirection::Outbound, 10, 10),
+            vec![(ids[1], 1), (ids[2], 2), (ids[3], 3)]
+        );
+        assert_eq!(
+            closure(ids[0], EdgeDirection::Outbound, 2, 10),
+            vec![(ids[1], 1), (ids[2], 2)]
+        );
+        assert_eq!(closure(ids[0], EdgeDirection::Outbound, 10, 1), vec![(ids[1], 1)]);
+        assert_eq!(
+            closure(ids[3], EdgeDirection::Inbound, 10, 10),
+            vec![(ids[2], 1), (ids[1], 2), (ids[0], 3)]
+        );
+        assert_eq!(closure(ids[4], EdgeDirection::Both, 10, 10), vec![]);
+
+        let mut both = closure(ids[3], EdgeDirection::Both, 1, 10);
+        both.extend(closure(ids[1], EdgeDirection::Both, 1, 10));
+        both.sort();
+        let mut expected = vec![(ids[2], 1), (ids[0], 1), (ids[2], 1)];
+        expected.sort();
+        assert_eq!(both, expected);
+    }
+
+    #[test]
+    fn should_get_simple_paths() {
+        let datastore = MemoryDatastore::default();
+        let trans = datastore.transaction().unwrap();
+        let t = Type::new("derived_from").unwrap();
+        let other_t = Type::new("other").unwrap();
+        let ids: Vec<_> = (0..5)
+            .map(|_| trans.create_vertex_from_type(t.clone()).unwrap())
+            .collect();
+
+        // Two paths from 0 to 3: 0 -> 1 -> 3 and 0 -> 2 -> 1 -> 3, plus a
+        // cycle through 1 and 2, and a path of another type
+        for &(outbound, inbound) in &[(0, 1), (0, 2), (2, 1), (1, 2), (1, 3)] {
+            trans
+                .create_edge(&EdgeKey::new(ids[outbound], t.clone(), ids[inbound]))
+                .unwrap();
+        }
+
+        for &(outbound, inbound) in &[(0, 4), (4, 3)] {
+            trans
+                .create_edge(&EdgeKey::new(ids[outbound], other_t.clone(), ids[inbound]))
+                .unwrap();
+        }
+
+        let paths = |types: &[Type], max_depth, limit| {
+            let mut paths = Vec::new();
+            let count = simple_paths(&trans, ids[0], ids[3], types, max_depth, limit, |path| {
+                paths.push(
+                    path.into_iter()
+                        .map(|key| ids.iter().position(|&id| id == key.inbound_id).unwrap())
+                        .collect::<Vec<_>>(),
+                );
+                Ok(())
+            })
+            .unwrap();
+            assert_eq!(count as usize, paths.len());
+            paths.sort();
+            paths
+        };
+
+        let types = vec![t.clone()];
+        let other_types = vec![other_t.clone()];
+        assert_eq!(paths(&types, 10, 10), vec![vec![1, 3], vec![2, 1, 3]]);
+        assert_eq!(paths(&types, 2, 10), vec![vec![1, 3]]);
+        assert_eq!(paths(&types, 10, 1).len(), 1);
+        assert_eq!(paths(&types, 1, 10), Vec::<Vec<usize>>::new());
+        assert_eq!(paths(&other_types, 10, 10), vec![vec![4, 3]]);
+        assert_eq!(
+            paths(&[t.clone(), other_t], 10, 10),
+            vec![vec![1, 3], vec![2, 1, 3], vec![4, 3]]
+        );
+        assert_eq!(paths(&[], 10, 10).len(), 3);
+        assert_eq!(
+            simple_paths(&trans, ids[0], ids[0], &[], 10, 10, |_| Ok(())).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn should_get_time_respecting_reachable() {
+        let datastore = MemoryDatastore::default();
+        let trans = datastore.transaction().unwrap();
+        let t = Type::new("met").unwrap();
+        let ids: Vec<_> = (0..5)
+            .map(|_| trans.create_vertex_from_type(t.clone()).unwrap())
+            .collect();
+        let start = Utc.timestamp_opt(1_577_836_800, 0).unwrap();
+
+        // 0 met 1 on day 2, who met 2 on day 3 and 3 on day 1. 4 met 0 on
+        // day 5.
+        for &(outbound, inbound, day) in &[(0, 1, 2), (1, 2, 3), (1, 3, 1), (4, 0, 5)] {
+            let key = EdgeKey::new(ids[outbound], t.clone(), ids[inbound]);
+            trans
+                .create_edge_with_datetime(&key, start + Duration::days(day))
+                .unwrap();
+        }
+
+        let day = |day| start + Duration::days(day);
+        let reachable =
+            |direction, start, limit| time_respecting_reachable(&trans, ids[0], &[], direction, start, limit).unwrap();
+
+        assert_eq!(
+            reachable(EdgeDirection::Outbound, start, 10),
+            vec![(ids[1], day(2)), (ids[2], day(3))]
+        );
+        assert_eq!(reachable(EdgeDirection::Outbound, day(3), 10), vec![]);
+        assert_eq!(reachable(EdgeDirection::Outbound, start, 1), vec![(ids[1], day(2))]);
+        assert_eq!(
+            reachable(EdgeDirection::Both, start, 10),
+            vec![(ids[1], day(2)), (ids[2], day(3)), (ids[4], day(5))]
+        );
+        assert_eq!(reachable(EdgeDirection::Inbound, start, 10), vec![(ids[4], day(5))]);
+    }
+
+    #[test]
+    fn should_get_decayed_scores() {
+        let now = Utc.timestamp_opt(1_577_836_800, 0).unwrap();
+        let half_life = Duration::days(2);
+        assert_eq!(decayed_weight(now, now, half_life), 1.0);
+        assert_eq!(decayed_weight(now - Duration::days(4), now, half_life), 0.25);
+        assert_eq!(decayed_weight(now + Duration::days(4), now, half_life), 1.0);
+
+        let datastore = MemoryDatastore::default();
+        let trans = datastore.transaction().unwrap();
+        let t = Type::new("viewed").unwrap();
+        let user_id = trans.create_vertex_from_type(t.clone()).unwrap();
+        let ids: Vec<_> = (0..3)
+            .map(|_| trans.create_vertex_from_type(t.clone()).unwrap())
+            .collect();
+
+        for &(i, days) in &[(0, 2), (1, 0), (2, 6)] {
+            let key = EdgeKey::new(user_id, t.clone(), ids[i]);
+            trans
+                .create_edge_with_datetime(&key, now - Duration::days(days))
+                .unwrap();
+        }
+
+        let q = || SpecificVertexQuery::single(user_id).outbound(10);
+        let scores = decayed_scores(&trans, q(), EdgeDirection::Outbound, now, half_life, None).unwrap();
+        assert_eq!(scores, vec![(ids[1], 1.0), (ids[0], 0.5), (ids[2], 0.125)]);
+        let scores = decayed_scores(
+            &trans,
+            q(),
+            EdgeDirection::Inbound,
+            now,
+            half_life,
+            Some(Duration::days(3)),
+        )
+        .unwrap();
+        assert_eq!(scores, vec![(user_id, 1.5)]);
+        assert!(decayed_scores(&trans, q(), EdgeDirection::Both, now, Duration::zero(), None).is_err());
+    }
+
+    #[test]
+    fn should_get_top_neighbors() {
+        let datastore = MemoryDatastore::default();
+        let trans = datastore.transaction().unwrap();
+        let t = Type::new("knows").unwrap();
+        let other_t = Type::new("other").unwrap();
+        let id = trans.create_vertex_from_type(t.clone()).unwrap();
+        let ids: Vec<_> = (0..5)
+            .map(|_| trans.create_vertex_from_type(t.clone()).unwrap())
+            .collect();
+        let mut keys = Vec::new();
+
+        for (i, &neighbor_id) in ids.iter().enumerate() {
+            let key = if i == 4 {
+                EdgeKey::new(neighbor_id, t.clone(), id)
+            } else {
+                EdgeKey::new(id, t.clone(), neighbor_id)
+            };
+
+            trans.create_edge(&key).unwrap();
+            keys.push(key);
+        }
+
+        let other_key = EdgeKey::new(id, other_t, ids[0]);
+        trans.create_edge(&other_key).unwrap();
+
+        // Edge strengths, with one that isn't a number
+        for &(i, ref value) in &[
+            (0, JsonValue::from(0.5)),
+            (1, JsonValue::from(3)),
+            (2, JsonValue::from("strong")),
+            (4, JsonValue::from(10)),
+        ] {
+            let q = SpecificEdgeQuery::single(keys[i].clone()).property("strength");
+            trans.set_edge_properties(q, value).unwrap();
+        }
+
+        trans
+            .set_edge_properties(
+                SpecificEdgeQuery::single(other_key.clone()).property("strength"),
+                &JsonValue::from(5),
+            )
+            .unwrap();
+
+        // Neighbor popularities
+        for (i, &neighbor_id) in ids.iter().enumerate() {
+            let q = SpecificVertexQuery::single(neighbor_id).property("popularity");
+            trans.set_vertex_properties(q, &JsonValue::from(i)).unwrap();
+        }
+
+        let strength = RankBy::EdgeProperty("strength".to_string());
+        let popularity = RankBy::VertexProperty("popularity".to_string());
+        let top = |t, direction, rank_by, k| top_neighbors(&trans, id, t, direction, rank_by, k).unwrap();
+
+        assert_eq!(
+            top(Some(&t), EdgeDirection::Both, &strength, 10),
+            vec![(keys[4].clone(), 10.0), (keys[1].clone(), 3.0), (keys[0].clone(), 0.5)]
+        );
+        assert_eq!(
+            top(None, EdgeDirection::Outbound, &strength, 2),
+            vec![(other_key, 5.0), (keys[1].clone(), 3.0)]
+        );
+        assert_eq!(
+            top(Some(&t), EdgeDirection::Outbound, &popularity, 2),
+            vec![(keys[3].clone(), 3.0), (keys[2].clone(), 2.0)]
+        );
+        assert_eq!(top(Some(&t), EdgeDirection::Both, &popularity, 0), vec![]);
+    }
+
+    #[test]
+    fn should_score_links() {
+        let datastore = MemoryDatastore::default();
+        let trans = datastore.transaction().unwrap();
+        let t = Type::new("follows").unwrap();
+        let other_t = Type::new("other").unwrap();
+        let ids: Vec<_> = (0..8)
+            .map(|_| trans.create_vertex_from_type(t.clone()).unwrap())
+            .collect();
+        let (source_id, a_id, b_id, c_id, d_id, x_id, y_id, z_id) =
+            (ids[0], ids[1], ids[2], ids[3], ids[4], ids[5], ids[6], ids[7]);
+
+        for &(outbound_id, inbound_id) in &[
+            (source_id, a_id),
+            (source_id, b_id),
+            (source_id, c_id),
+            (x_id, b_id),
+            (x_id, c_id),
+            (x_id, d_id),
+            (y_id, a_id),
+            (z_id, b_id),
+        ] {
+            trans
+                .create_edge(&EdgeKey::new(outbound_id, t.clone(), inbound_id))
+                .unwrap();
+        }
+
+        // Edges of other types aren't followed
+        trans.create_edge(&EdgeKey::new(source_id, other_t, d_id)).unwrap();
+
+        let candidate_ids = vec![x_id, y_id, z_id, d_id];
+        let score = |id, direction, score| score_links(&trans, id, &candidate_ids, &t, direction, score).unwrap();
+
+        assert_eq!(
+            score(source_id, EdgeDirection::Outbound, LinkScore::Jaccard),
+            vec![2.0 / 4.0, 1.0 / 3.0, 1.0 / 3.0, 0.0]
+        );
+
+        // `a` has two followers, `b` has three and `c` has two
+        assert_eq!(
+            score(source_id, EdgeDirection::Outbound, LinkScore::AdamicAdar),
+            vec![1.0 / 3f64.ln() + 1.0 / 2f64.ln(), 1.0 / 2f64.ln(), 1.0 / 3f64.ln(), 0.0]
+        );
+
+        // `b` and `d` are both followed by `x`, but `d` isn't followed by
+        // `source`
+        assert_eq!(
+            score(b_id, EdgeDirection::Inbound, LinkScore::Jaccard),
+            vec![0.0, 0.0, 0.0, 1.0 / 3.0]
+        );
+    }
+
+    #[test]
+    fn should_get_two_coloring() {
+        let datastore = MemoryDatastore::default();
+        let trans = datastore.transaction().unwrap();
+        let user_t = Type::new("user").unwrap();
+        let item_t = Type::new("item").unwrap();
+        let purchased_t = Type::new("purchased").unwrap();
+        let follows_t = Type::new("follows").unwrap();
+        let user_ids: Vec<_> = (0..3)
+            .map(|_| trans.create_vertex_from_type(user_t.clone()).unwrap())
+            .collect();
+        let item_ids: Vec<_> = (0..3)
+            .map(|_| trans.create_vertex_from_type(item_t.clone()).unwrap())
+            .collect();
+
+        // The last user and item are only connected to each other
+        for &(user_index, item_index) in &[(0, 0), (0, 1), (1, 1), (2, 2)] {
+            let key = EdgeKey::new(user_ids[user_index], purchased_t.clone(), item_ids[item_index]);
+            trans.create_edge(&key).unwrap();
+        }
+
+        // Edges of other types aren't in the subgraph
+        let follows_key = EdgeKey::new(user_ids[0], follows_t, user_ids[1]);
+        trans.create_edge(&follows_key).unwrap();
+
+        let types = vec![purchased_t.clone()];
+        let coloring = two_coloring(&trans, vec![item_ids[1], user_ids[2]], &types).unwrap();
+        let colors = match coloring {
+            TwoColoring::Bipartite(colors) => colors,
+            TwoColoring::NotBipartite(key) => panic!("unexpected odd cycle at {:?}", key),
+        };
+        assert_eq!(colors.len(), 6);
+        assert!(user_ids[..2].iter().all(|id| colors[id]));
+        assert!(item_ids[..2].iter().all(|id| !colors[id]));
+        assert!(!colors[&user_ids[2]]);
+        assert!(colors[&item_ids[2]]);
+
+        // Following is an edge between users, which closes an odd cycle
+        let coloring = two_coloring(&trans, vec![user_ids[0]], &[]).unwrap();
+        let cycle_keys = [
+            follows_key,
+            EdgeKey::new(user_ids[0], purchased_t.clone(), item_ids[1]),
+            EdgeKey::new(user_ids[1], purchased_t, item_ids[1]),
+        ];
+
+        match coloring {
+            TwoColoring::NotBipartite(key) => assert!(cycle_keys.contains(&key)),
+            TwoColoring::Bipartite(_) => panic!("expected an odd cycle"),
+        }
+    }
+}
@@ -1,9 +1,10 @@
 //! Utility functions.
 
-use chrono::offset::Utc;
+use chrono::offset::{TimeZone, Utc};
 use chrono::DateTime;
-use errors::ValidationResult;
+use errors::{Result, ValidationResult};
 use rand::{OsRng, Rng};
+use std::cmp::Ordering;
 use std::env;
 use uuid::v1::Context;
 use uuid::Uuid;
@@ -89,10 +90,87 @@ pub fn nanos_since_epoch(datetime: &DateTime<Utc>) -> u64 {
     timestamp * 1_000_000_000 + nanoseconds
 }
 
+/// Gets the number of nanoseconds since unix epoch for a given datetime, as
+/// a signed integer. Returns `None` for datetimes outside the years 1677 to
+/// 2262, which can't be represented.
+///
+/// # Arguments
+/// * `datetime` - The datetime to convert.
+pub fn signed_nanos_since_epoch(datetime: &DateTime<Utc>) -> Option<i64> {
+    datetime
+        .timestamp()
+        .checked_mul(1_000_000_000)
+        .and_then(|nanos| nanos.checked_add(i64::from(datetime.timestamp_subsec_nanos())))
+}
+
+/// Gets the datetime a given number of nanoseconds after unix epoch. This is
+/// the inverse of `signed_nanos_since_epoch`.
+///
+/// # Arguments
+/// * `nanos` - The number of nanoseconds, which may be negative.
+pub fn datetime_from_signed_nanos(nanos: i64) -> DateTime<Utc> {
+    let mut secs = nanos / 1_000_000_000;
+    let mut subsec_nanos = nanos % 1_000_000_000;
+
+    if subsec_nanos < 0 {
+        secs -= 1;
+        subsec_nanos += 1_000_000_000;
+    }
+
+    Utc.timestamp_opt(secs, subsec_nanos as u32)
+        .single()
+        .expect("Expected every i64 of nanoseconds to be a valid datetime")
+}
+
+// Gets the ids that are in both of two sequences of ids, each of which is in
+// ascending order without duplicates. Stops reading once `limit` ids have
+// been found, so the sequences can be streamed from scans.
+pub(crate) fn intersect_sorted<I, J>(first: I, second: J, limit: u32) -> Result<Vec<Uuid>>
+where
+    I: IntoIterator<Item = Result<Uuid>>,
+    J: IntoIterator<Item = Result<Uuid>>,
+{
+    let mut first = first.into_iter();
+    let mut second = second.into_iter();
+    let mut first_id = next_sorted_id(&mut first)?;
+    let mut second_id = next_sorted_id(&mut second)?;
+    let mut common = Vec::new();
+
+    while common.len() < limit as usize {
+        let (current_first_id, current_second_id) = match (first_id, second_id) {
+            (Some(first_id), Some(second_id)) => (first_id, second_id),
+            _ => break,
+        };
+
+        match current_first_id.cmp(&current_second_id) {
+            Ordering::Less => first_id = next_sorted_id(&mut first)?,
+            Ordering::Greater => second_id = next_sorted_id(&mut second)?,
+            Ordering::Equal => {
+                common.push(current_first_id);
+                first_id = next_sorted_id(&mut first)?;
+                second_id = next_sorted_id(&mut second)?;
+            }
+        }
+    }
+
+    Ok(common)
+}
+
+fn next_sorted_id<I: Iterator<Item = Result<Uuid>>>(iter: &mut I) -> Result<Option<Uuid>> {
+    match iter.next() {
+        Some(Ok(id)) => Ok(Some(id)),
+        Some(Err(err)) => Err(err),
+        None => Ok(None),
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{generate_random_secret, generate_temporary_path, generate_uuid_v1, nanos_since_epoch, next_uuid};
-    use chrono::{DateTime, NaiveDateTime, Utc};
+    use super::{
+        datetime_from_signed_nanos, generate_random_secret, generate_temporary_path, generate_uuid_v1,
+        intersect_sorted, nanos_since_epoch, next_uuid, signed_nanos_since_epoch,
+    };
+    use chrono::{DateTime, Duration, NaiveDateTime, Utc};
     use core::str::FromStr;
     use regex::Regex;
     use uuid::Uuid;
@@ -137,4 +215,32 @@ mod tests {
         let datetime = DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(61, 62), Utc);
         assert_eq!(nanos_since_epoch(&datetime), 61000000062);
     }
+
+    #[test]
+    fn should_round_trip_signed_nanos_since_epoch() {
+        let now = Utc::now();
+        let nanos = signed_nanos_since_epoch(&now).unwrap();
+        assert_eq!(datetime_from_signed_nanos(nanos), now);
+
+        let past = now - Duration::days(365 * 60);
+        let nanos = signed_nanos_since_epoch(&past).unwrap();
+        assert!(nanos < 0);
+        assert_eq!(datetime_from_signed_nanos(nanos), past);
+    }
+
+    #[test]
+    fn should_intersect_sorted_ids() {
+        let mut ids: Vec<Uuid> = (0..6).map(|_| generate_uuid_v1()).collect();
+        ids.sort();
+        let first = [ids[0], ids[1], ids[3], ids[4], ids[5]];
+        let second = [ids[1], ids[2], ids[4], ids[5]];
+        let common = intersect_sorted(first.iter().cloned().map(Ok), second.iter().cloned().map(Ok), 10).unwrap();
+        assert_eq!(common, vec![ids[1], ids[4], ids[5]]);
+
+        let common = intersect_sorted(first.iter().cloned().map(Ok), second.iter().cloned().map(Ok), 2).unwrap();
+        assert_eq!(common, vec![ids[1], ids[4]]);
+
+        let common = intersect_sorted(first.iter().cloned().map(Ok), Vec::new(), 10).unwrap();
+        assert!(common.is_empty());
+    }
 }
@@ -0,0 +1,284 @@
+//! Generates synthetic graphs and measures how quickly a datastore works
+//! with them, so that backends and tuning options can be compared
+//! reproducibly.
+
+use errors::Result;
+use models;
+use models::{EdgeQueryExt, VertexQueryExt};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::time::{Duration, Instant};
+use traits::{Datastore, Transaction};
+use util::next_uuid;
+
+// How many vertices are read at a time while scanning.
+const SCAN_PAGE_SIZE: u32 = 1000;
+
+/// How many outbound edges each generated vertex gets.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DegreeDistribution {
+    /// Every vertex gets the same number of edges.
+    Constant(u32),
+
+    /// Degrees are picked uniformly between `min` and `max`, inclusive.
+    Uniform { min: u32, max: u32 },
+
+    /// Degrees follow a power law, as in many real-world graphs: most
+    /// vertices have about `min` edges, and a few have up to `max`. Larger
+    /// exponents make high degrees rarer; the exponent must be above 1.
+    PowerLaw { exponent: f64, min: u32, max: u32 },
+}
+
+impl DegreeDistribution {
+    fn sample<R: Rng>(&self, rng: &mut R) -> u32 {
+        match *self {
+            DegreeDistribution::Constant(degree) => degree,
+            DegreeDistribution::Uniform { min, max } => rng.gen_range(min, max + 1),
+            DegreeDistribution::PowerLaw { exponent, min, max } => {
+                // Inverse transform sampling of a Pareto distribution
+                let u: f64 = rng.gen();
+                let degree = f64::from(min.max(1)) * (1.0 - u).powf(-1.0 / (exponent - 1.0));
+
+                if degree >= f64::from(max) {
+                    max
+                } else {
+                    (degree as u32).max(min)
+                }
+            }
+        }
+    }
+}
+
+/// Describes a synthetic graph and the workload to run against it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Workload {
+    /// How many vertices to create.
+    pub vertex_count: u32,
+
+    /// How many outbound edges each vertex gets. Edges point at vertices
+    /// picked uniformly at random.
+    pub degree: DegreeDistribution,
+
+    /// How many point reads and traversals to run.
+    pub read_count: u32,
+
+    /// Seeds the random number generator, so that the same workload
+    /// generates the same graph and reads.
+    pub seed: u64,
+}
+
+impl Workload {
+    /// Creates a new workload.
+    ///
+    /// # Arguments
+    /// * `vertex_count` - How many vertices to create.
+    /// * `degree` - How many outbound edges each vertex gets.
+    pub fn new(vertex_count: u32, degree: DegreeDistribution) -> Self {
+        Self {
+            vertex_count,
+            degree,
+            read_count: 1000,
+            seed: 0,
+        }
+    }
+
+    /// Sets how many point reads and traversals to run.
+    ///
+    /// # Arguments
+    /// * `read_count` - The number of reads.
+    pub fn read_count(self, read_count: u32) -> Self {
+        Self { read_count, ..self }
+    }
+
+    /// Sets the random seed.
+    ///
+    /// # Arguments
+    /// * `seed` - The seed.
+    pub fn seed(self, seed: u64) -> Self {
+        Self { seed, ..self }
+    }
+
+    /// Generates the graph, inserts it into a datastore, and measures reads
+    /// against it. The datastore should be empty.
+    ///
+    /// # Arguments
+    /// * `datastore` - The datastore to run the workload against.
+    pub fn run<D: Datastore>(&self, datastore: &D) -> Result<WorkloadReport> {
+        let mut rng = self.rng();
+        let trans = datastore.transaction()?;
+        let t = models::Type::new("workload").unwrap();
+        let mut measurements = Vec::new();
+
+        let start = Instant::now();
+        let mut ids = Vec::with_capacity(self.vertex_count as usize);
+
+        for _ in 0..self.vertex_count {
+            ids.push(trans.create_vertex_from_type(t.clone())?);
+        }
+
+        measurements.push(Measurement::new("insert vertices", u64::from(self.vertex_count), start));
+
+        if !ids.is_empty() {
+            let start = Instant::now();
+            let mut edge_count = 0;
+
+            for &outbound_id in &ids {
+                for _ in 0..self.degree.sample(&mut rng) {
+                    let inbound_id = *rng.choose(&ids).unwrap();
+                    trans.create_edge(&models::EdgeKey::new(outbound_id, t.clone(), inbound_id))?;
+                    edge_count += 1;
+                }
+            }
+
+            measurements.push(Measurement::new("insert edges", edge_count, start));
+
+            let start = Instant::now();
+
+            for _ in 0..self.read_count {
+                let id = *rng.choose(&ids).unwrap();
+                trans.get_vertices(models::SpecificVertexQuery::single(id))?;
+            }
+
+            measurements.push(Measurement::new("point reads", u64::from(self.read_count), start));
+
+            let start = Instant::now();
+
+            for _ in 0..self.read_count {
+                let id = *rng.choose(&ids).unwrap();
+                let q = models::SpecificVertexQuery::single(id)
+                    .outbound(u32::MAX)
+                    .inbound(u32::MAX)
+                    .outbound(u32::MAX);
+                trans.get_edges(q)?;
+            }
+
+            measurements.push(Measurement::new("2-hop traversals", u64::from(self.read_count), start));
+        }
+
+        let start = Instant::now();
+        let scanned_count = scan(&trans)?;
+        measurements.push(Measurement::new("scan", scanned_count, start));
+
+        Ok(WorkloadReport { measurements })
+    }
+
+    fn rng(&self) -> StdRng {
+        let mut seed = [0; 32];
+
+        for (i, byte) in seed.iter_mut().enumerate().take(8) {
+            *byte = (self.seed >> (i * 8)) as u8;
+        }
+
+        StdRng::from_seed(seed)
+    }
+}
+
+fn scan<T: Transaction>(trans: &T) -> Result<u64> {
+    let mut count = 0;
+    let mut q = models::RangeVertexQuery::new(SCAN_PAGE_SIZE);
+
+    loop {
+        let vertices = trans.get_vertices(q.clone())?;
+        count += vertices.len() as u64;
+
+        if vertices.len() < SCAN_PAGE_SIZE as usize {
+            return Ok(count);
+        }
+
+        match next_uuid(vertices.last().unwrap().id) {
+            Ok(next_id) => q = q.start_id(next_id),
+            Err(_) => return Ok(count),
+        }
+    }
+}
+
+/// How long one part of a workload took.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Measurement {
+    /// What was measured.
+    pub name: &'static str,
+
+    /// How many operations were run, or items read for scans.
+    pub count: u64,
+
+    /// How long the operations took.
+    pub elapsed: Duration,
+}
+
+impl Measurement {
+    fn new(name: &'static str, count: u64, start: Instant) -> Self {
+        Self {
+            name,
+            count,
+            elapsed: start.elapsed(),
+        }
+    }
+
+    /// Gets the number of operations per second.
+    pub fn per_second(&self) -> f64 {
+        let secs = self.elapsed.as_secs() as f64 + f64::from(self.elapsed.subsec_nanos()) / 1e9;
+
+        if secs > 0.0 {
+            self.count as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+/// The measurements from running a workload.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorkloadReport {
+    /// The measurements, in the order they were taken.
+    pub measurements: Vec<Measurement>,
+}
+
+impl WorkloadReport {
+    /// Gets a measurement by name.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the measurement.
+    pub fn get(&self, name: &str) -> Option<&Measurement> {
+        self.measurements.iter().find(|measurement| measurement.name == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DegreeDistribution, Workload};
+    use memory::MemoryDatastore;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn should_sample_degrees() {
+        let mut rng = StdRng::from_seed([0; 32]);
+        let distribution = DegreeDistribution::PowerLaw {
+            exponent: 2.5,
+            min: 1,
+            max: 50,
+        };
+
+        for _ in 0..1000 {
+            let degree = distribution.sample(&mut rng);
+            assert!((1..=50).contains(&degree));
+        }
+
+        let distribution = DegreeDistribution::Uniform { min: 2, max: 4 };
+
+        for _ in 0..1000 {
+            let degree = distribution.sample(&mut rng);
+            assert!((2..=4).contains(&degree));
+        }
+    }
+
+    #[test]
+    fn should_run_workloads() {
+        let workload = Workload::new(100, DegreeDistribution::Constant(3)).read_count(10);
+        let report = workload.run(&MemoryDatastore::default()).unwrap();
+        assert_eq!(report.get("insert vertices").unwrap().count, 100);
+        assert_eq!(report.get("insert edges").unwrap().count, 300);
+        assert_eq!(report.get("point reads").unwrap().count, 10);
+        assert_eq!(report.get("scan").unwrap().count, 100);
+    }
+}